@@ -0,0 +1,333 @@
+//! Collects doc comments of configuration types, used when printing default configuration.
+//!
+//! All structs and enums deriving [`serde::Deserialize`] under `src/` are scanned. For every
+//! field, its serialized key, first paragraph of its doc comment and innermost field type name
+//! are recorded.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use syn::{
+    Attribute, Expr, ExprLit, Fields, GenericArgument, Item, Lit, Meta, PathArguments, Type,
+};
+
+/// Documented field of a configuration type.
+struct FieldDoc {
+    /// Serialized key.
+    key: String,
+    /// First paragraph of doc comment.
+    doc: Vec<String>,
+    /// Innermost type name, with wrappers such as `Option` and `Vec` removed.
+    ty: String,
+    /// Field type is a map, keyed by arbitrary strings.
+    map: bool,
+    /// Field is flattened into enclosing type.
+    flatten: bool,
+}
+
+/// Documented configuration type.
+struct TypeDoc {
+    /// Type is an enum.
+    is_enum: bool,
+    /// Documented fields.
+    fields: Vec<FieldDoc>,
+}
+
+/// Types wrapping their first generic argument.
+const WRAPPERS: &[&str] = &[
+    "Option", "Box", "Arc", "Vec", "VecDeque", "BTreeSet", "HashSet", "NonEmpty",
+];
+
+/// Types wrapping their last generic argument as map values.
+const MAPS: &[&str] = &["HashMap", "BTreeMap", "IndexMap"];
+
+fn main() {
+    println!("cargo:rerun-if-changed=src");
+    let mut files = Vec::new();
+    collect_files(Path::new("src"), &mut files);
+    files.sort();
+    let mut types: BTreeMap<String, Option<TypeDoc>> = BTreeMap::new();
+    for path in files {
+        let Ok(source) = fs::read_to_string(&path) else {
+            continue;
+        };
+        let Ok(file) = syn::parse_file(&source) else {
+            continue;
+        };
+        collect_items(&file.items, &mut types);
+    }
+    let out = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR is not set"));
+    fs::write(out.join("config_docs.rs"), render(&types)).expect("unable to write config docs");
+}
+
+/// Recursively find all Rust source files.
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+}
+
+/// Collect documented types from a list of items, including inline modules.
+///
+/// Types with the same name defined more than once are ambiguous, and are not documented.
+fn collect_items(items: &[Item], types: &mut BTreeMap<String, Option<TypeDoc>>) {
+    for item in items {
+        let (name, doc) = match item {
+            Item::Struct(item) if derives_deserialize(&item.attrs) => {
+                let rename_all = serde_value(&item.attrs, "rename_all");
+                let doc = TypeDoc {
+                    is_enum: false,
+                    fields: collect_fields(&item.fields, rename_all.as_deref()),
+                };
+                (item.ident.to_string(), doc)
+            }
+            Item::Enum(item) if derives_deserialize(&item.attrs) => {
+                let mut fields = Vec::new();
+                if let Some(tag) = serde_value(&item.attrs, "tag") {
+                    fields.push(FieldDoc {
+                        key: tag,
+                        doc: doc_paragraph(&item.attrs),
+                        ty: String::new(),
+                        map: false,
+                        flatten: false,
+                    });
+                }
+                let rename_all = serde_value(&item.attrs, "rename_all_fields");
+                for variant in &item.variants {
+                    for field in collect_fields(&variant.fields, rename_all.as_deref()) {
+                        if !fields.iter().any(|known| known.key == field.key) {
+                            fields.push(field);
+                        }
+                    }
+                }
+                (
+                    item.ident.to_string(),
+                    TypeDoc {
+                        is_enum: true,
+                        fields,
+                    },
+                )
+            }
+            Item::Mod(item) => {
+                if let Some((_, items)) = &item.content {
+                    collect_items(items, types);
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        types
+            .entry(name)
+            .and_modify(|known| *known = None)
+            .or_insert(Some(doc));
+    }
+}
+
+/// Collect documented named fields.
+fn collect_fields(fields: &Fields, rename_all: Option<&str>) -> Vec<FieldDoc> {
+    let Fields::Named(fields) = fields else {
+        return Vec::new();
+    };
+    fields
+        .named
+        .iter()
+        .filter(|field| !has_serde_flag(&field.attrs, &["skip", "skip_deserializing"]))
+        .filter_map(|field| {
+            let ident = field.ident.as_ref()?.to_string();
+            let ident = ident.trim_start_matches("r#");
+            let key = serde_value(&field.attrs, "rename")
+                .unwrap_or_else(|| rename_field(ident, rename_all));
+            let (ty, map) = inner_type(&field.ty);
+            Some(FieldDoc {
+                key,
+                doc: doc_paragraph(&field.attrs),
+                ty,
+                map,
+                flatten: has_serde_flag(&field.attrs, &["flatten"]),
+            })
+        })
+        .collect()
+}
+
+/// Check if item derives `Deserialize`.
+fn derives_deserialize(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("derive"))
+        .any(|attr| {
+            let mut found = false;
+            let _ = attr.parse_nested_meta(|meta| {
+                found |= meta
+                    .path
+                    .segments
+                    .last()
+                    .is_some_and(|seg| seg.ident == "Deserialize");
+                Ok(())
+            });
+            found
+        })
+}
+
+/// Iterate over nested items of all `#[serde(...)]` attributes.
+fn serde_metas(attrs: &[Attribute]) -> impl Iterator<Item = Meta> + '_ {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("serde"))
+        .filter_map(|attr| {
+            attr.parse_args_with(
+                syn::punctuated::Punctuated::<Meta, syn::Token![,]>::parse_terminated,
+            )
+            .ok()
+        })
+        .flatten()
+}
+
+/// Get string value of serde attribute, such as `rename`.
+///
+/// For split values, deserialization name is returned.
+fn serde_value(attrs: &[Attribute], key: &str) -> Option<String> {
+    serde_metas(attrs).find_map(|meta| match meta {
+        Meta::NameValue(nv) if nv.path.is_ident(key) => expr_str(&nv.value),
+        Meta::List(list) if list.path.is_ident(key) => {
+            let mut found = None;
+            let _ = list.parse_nested_meta(|inner| {
+                if inner.path.is_ident("deserialize") {
+                    found = expr_str(&inner.value()?.parse()?);
+                }
+                Ok(())
+            });
+            found
+        }
+        _ => None,
+    })
+}
+
+/// Check if serde attributes contain any of flags.
+fn has_serde_flag(attrs: &[Attribute], flags: &[&str]) -> bool {
+    serde_metas(attrs).any(
+        |meta| matches!(meta, Meta::Path(path) if flags.iter().any(|flag| path.is_ident(flag))),
+    )
+}
+
+/// Get value of string literal expression.
+fn expr_str(expr: &Expr) -> Option<String> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(lit), ..
+        }) => Some(lit.value()),
+        _ => None,
+    }
+}
+
+/// Get first paragraph of doc comment, with intra-doc link brackets removed.
+fn doc_paragraph(attrs: &[Attribute]) -> Vec<String> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(nv) => expr_str(&nv.value),
+            _ => None,
+        })
+        .map(|line| line.trim().replace("[`", "`").replace("`]", "`"))
+        .take_while(|line| !line.is_empty())
+        .collect()
+}
+
+/// Apply serde `rename_all` convention to snake case field name.
+fn rename_field(name: &str, rename_all: Option<&str>) -> String {
+    let words = name.split('_').filter(|word| !word.is_empty());
+    let capitalize = |word: &str| {
+        let mut chars = word.chars();
+        chars.next().map_or_else(String::new, |first| {
+            first.to_uppercase().chain(chars).collect()
+        })
+    };
+    match rename_all {
+        Some("lowercase") => name.to_lowercase(),
+        Some("UPPERCASE") => name.to_uppercase(),
+        Some("PascalCase") => words.map(capitalize).collect(),
+        Some("camelCase") => words
+            .enumerate()
+            .map(|(idx, word)| match idx {
+                0 => word.to_string(),
+                _ => capitalize(word),
+            })
+            .collect(),
+        Some("SCREAMING_SNAKE_CASE") => name.to_uppercase(),
+        Some("kebab-case") => name.replace('_', "-"),
+        Some("SCREAMING-KEBAB-CASE") => name.replace('_', "-").to_uppercase(),
+        _ => name.to_string(),
+    }
+}
+
+/// Get innermost type name, and whether type is a map.
+fn inner_type(ty: &Type) -> (String, bool) {
+    let Type::Path(path) = ty else {
+        return (String::new(), false);
+    };
+    let Some(seg) = path.path.segments.last() else {
+        return (String::new(), false);
+    };
+    let name = seg.ident.to_string();
+    let args: Vec<&Type> = match &seg.arguments {
+        PathArguments::AngleBracketed(args) => args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    if WRAPPERS.contains(&name.as_str()) {
+        if let Some(inner) = args.first() {
+            return inner_type(inner);
+        }
+    }
+    if MAPS.contains(&name.as_str()) {
+        if let Some(inner) = args.get(1) {
+            return (inner_type(inner).0, true);
+        }
+    }
+    (name, false)
+}
+
+/// Render collected docs as Rust source.
+fn render(types: &BTreeMap<String, Option<TypeDoc>>) -> String {
+    let mut out = String::from("// Generated by build script, do not edit.\n\n");
+    out.push_str("static CONFIG_DOCS: &[(&str, TypeDoc)] = &[\n");
+    for (name, doc) in types {
+        let Some(doc) = doc else {
+            continue;
+        };
+        let _ = writeln!(
+            out,
+            "    ({name:?}, TypeDoc {{ is_enum: {}, fields: &[",
+            doc.is_enum
+        );
+        for field in &doc.fields {
+            let _ = writeln!(
+                out,
+                "        FieldDoc {{ key: {:?}, doc: &{:?}, ty: {:?}, map: {}, flatten: {} }},",
+                field.key, field.doc, field.ty, field.map, field.flatten
+            );
+        }
+        out.push_str("    ] }),\n");
+    }
+    out.push_str("];\n");
+    out
+}
@@ -2,7 +2,15 @@ use std::{net::SocketAddr, time::Duration};
 
 use config::{Config, File};
 use serde::{Deserialize, Serialize};
-use uxum::{prelude::*, GetResponseSchemas, ResponseSchema};
+use uxum::{
+    prelude::*,
+    reexport::{
+        axum::Extension,
+        tower::util::{BoxCloneServiceLayer, MapRequestLayer},
+    },
+    GetResponseSchemas, HandlerLayerPosition, ReadinessCheckSpec, ResponseHooks, ResponseSchema,
+    Validate, Validated, ValidationError, ValidationErrors,
+};
 
 /// Root container for app configuration.
 #[derive(Deserialize)]
@@ -47,6 +55,16 @@ async fn run(mut config: ServiceConfig) -> Result<(), HandleError> {
     //
     // Also enable the auth subsystem.
     let mut app_builder = AppBuilder::from_config(&config.app).with_basic_auth();
+    // Audit all calls to handlers requiring some permissions, after authentication succeeds.
+    app_builder.with_handler_layer(HandlerLayerPosition::AfterAuth, |ctx| {
+        let (name, permissions) = (ctx.name, ctx.permissions);
+        (!permissions.is_empty()).then(|| {
+            BoxCloneServiceLayer::new(MapRequestLayer::new(move |req| {
+                tracing::info!(handler = name, ?permissions, "audit: handler called");
+                req
+            }))
+        })
+    });
     // Some hard-coded parameters for built-in API documentation.
     app_builder.configure_api_doc(|api_doc| {
         api_doc
@@ -57,12 +75,32 @@ async fn run(mut config: ServiceConfig) -> Result<(), HandleError> {
             .with_contact_email("example@example.com")
             .with_tag("tag1", Some("Some tag"), Some("http://example.com/tag1"))
             .with_tag("tag2", Some("Some other tag"), None::<&str>)
+            // Document configured rate limits and timeouts.
+            .with_config_annotations(true)
+            .with_request_id_header(true)
     });
     // Initialize required states.
     let tracing_client = app_builder
         .http_client_or_default("tracing")
         .await
         .expect("No tracing HTTP client");
+    // Report inner service availability in readiness probe, without affecting its status.
+    let inner_client = tracing_client.clone();
+    app_builder.with_readiness_check(
+        ReadinessCheckSpec::new("inner_service").optional(),
+        move |_| {
+            let client = inner_client.clone();
+            async move {
+                client
+                    .get("http://127.0.0.1:8081/healthz/live")
+                    .send()
+                    .await
+                    .and_then(|resp| Ok(resp.error_for_status()?))
+                    .map(drop)
+                    .map_err(|err| err.to_string())
+            }
+        },
+    );
     app_builder
         .with_state(distributed_tracing::TracingState::from(tracing_client))
         .with_state(counter_state::CounterState::default())
@@ -70,9 +108,7 @@ async fn run(mut config: ServiceConfig) -> Result<(), HandleError> {
     // Build main application router.
     let app = app_builder.build().expect("Unable to build app");
     // Start the service.
-    handle
-        .run(config.server, app, Some(Duration::from_secs(5)))
-        .await
+    handle.run(config.server, app, None).await
 }
 
 /// Sleep for some time and return response.
@@ -108,16 +144,31 @@ pub enum ComputeOp {
 }
 
 /// Request body.
-#[derive(Deserialize, JsonSchema)]
+#[derive(Deserialize, JsonSchema, Validate)]
+#[validate(custom = "check_divisor")]
 pub struct ComputeRequest {
     /// First argument.
+    #[validate(range(min = -1_000_000, max = 1_000_000))]
     arg1: i64,
     /// Second argument.
+    #[validate(range(min = -1_000_000, max = 1_000_000))]
     arg2: i64,
     #[serde(default)]
     op: ComputeOp,
 }
 
+/// Reject division by zero.
+fn check_divisor(req: &ComputeRequest) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+    if matches!(req.op, ComputeOp::Divide) && req.arg2 == 0 {
+        errors.add_field(
+            "arg2",
+            ValidationError::new("compute.division_by_zero", "divisor must not be zero"),
+        );
+    }
+    errors.into_result()
+}
+
 /// Result of computation.
 #[derive(JsonSchema, Serialize)]
 pub struct ComputeResponse {
@@ -128,16 +179,55 @@ pub struct ComputeResponse {
 ///
 /// Gets an operator and two operands as input. Returns result of operation.
 /// This is an example of using automatically (de)serialized JSON as
-/// input and output of a method.
-#[handler(method = "POST", tags = ["calc"])]
-async fn compute(req: Json<ComputeRequest>) -> Json<ComputeResponse> {
+/// input and output of a method. Input is validated before handler is called.
+/// Result is recorded after response is sent, using a post-response hook.
+#[handler(
+    method = "POST",
+    tags = ["calc"],
+    since = "0.2.0",
+    changes(version = "0.3.0", note = "added `op` field"),
+    changes(version = "0.4.0", note = "results are rounded"),
+    extra_params(
+        query(name = "dry_run", schema = bool, description = "Validate request only"),
+        header(name = "X-Tenant-Id", required = true, description = "Tenant identifier")
+    )
+)]
+async fn compute(
+    Extension(hooks): Extension<ResponseHooks>,
+    Validated(req): Validated<Json<ComputeRequest>>,
+) -> Json<ComputeResponse> {
+    let resp = evaluate(&req);
+    let result = resp.result;
+    hooks.schedule(move |ctx| async move {
+        tracing::info!(tenant = ctx.tenant(), result, "computation recorded");
+        Ok::<_, std::convert::Infallible>(())
+    });
+    Json(resp)
+}
+
+/// Perform simple arithmetic, using QUERY method.
+///
+/// This is an example of a handler with non-standard HTTP method. Such handlers
+/// are listed in `x-uxum-custom-methods` extension of OpenAPI specification.
+#[handler(
+    name = "compute_query",
+    path = "/compute",
+    method = "QUERY",
+    tags = ["calc"]
+)]
+async fn compute_query(Validated(req): Validated<Json<ComputeRequest>>) -> Json<ComputeResponse> {
+    Json(evaluate(&req))
+}
+
+/// Evaluate arithmetic operation.
+fn evaluate(req: &ComputeRequest) -> ComputeResponse {
     let result = match req.op {
         ComputeOp::Add => req.arg1 + req.arg2,
         ComputeOp::Subtract => req.arg1 - req.arg2,
         ComputeOp::Multiply => req.arg1 * req.arg2,
         ComputeOp::Divide => req.arg1 / req.arg2,
     };
-    Json(ComputeResponse { result })
+    ComputeResponse { result }
 }
 
 /// Return error sometimes.
@@ -361,7 +451,7 @@ mod hello {
     }
 
     /// Greet someone using a name from a query string.
-    #[handler]
+    #[handler(query_params(name(example = "Valentina", required = false)))]
     async fn name_from_qs(state: State<HelloState>, q: Query<QueryName>) -> String {
         state.log_name(&q.name);
         format!("Hello {}!", q.name)
@@ -1,29 +1,22 @@
 use std::net::SocketAddr;
 
-use config::{Config, File};
-use serde::Deserialize;
-use uxum::prelude::*;
-
-/// Root container for app configuration
-#[derive(Deserialize)]
-struct ServiceConfig {
-    /// Application configuration
-    #[serde(flatten)]
-    app: AppConfig,
-    /// Server configuration
-    server: ServerBuilder,
-}
+use uxum::{prelude::*, Profile, ServiceConfig};
 
 /// Application entry point
 #[tokio::main]
 async fn main() {
-    // Load configuration from file
-    let mut config: ServiceConfig = Config::builder()
-        .add_source(File::with_name("examples/inner_service/config.yaml"))
+    // Print commented default configuration when run with `--print-default-config`
+    if uxum::print_default_config_if_requested(Profile::Dev)
+        .expect("Unable to print default configuration")
+    {
+        return;
+    }
+    // Load configuration from built-in defaults, overridden by file if it exists
+    let mut config = ServiceConfig::builder()
+        .with_defaults_profile(Profile::Dev)
+        .with_optional_file("examples/inner_service/config.yaml")
         .build()
-        .expect("Unable to load configuration")
-        .try_deserialize()
-        .expect("Error deserializing configuration");
+        .expect("Unable to load configuration");
     // Add some hard-coded values to [`AppConfig`]
     let app_cfg = config
         .app
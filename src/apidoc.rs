@@ -1,27 +1,47 @@
 //! Code to generate OpenAPI schema and provide an UI for API discovery and documentation
 //! (RapiDoc).
 
-use std::collections::{BTreeMap, HashMap};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::PathBuf,
+    sync::Arc,
+};
 
-use askama::Template;
+use askama::{Html, MarkupDisplay, Template};
 use axum::{
+    body::Bytes,
     extract::State,
     http::header,
-    response::IntoResponse,
+    response::{self as axum_response, IntoResponse, Response},
     routing::{self, Router},
     Extension,
 };
-use http::Method;
+use base64::{engine::general_purpose::STANDARD as B64, Engine};
+use forwarded_header_value::{ForwardedHeaderValue, Protocol};
+use http::{uri::Authority, HeaderMap, HeaderValue, Method, StatusCode, Uri};
 use okapi::{
     map, openapi3,
-    schemars::gen::{SchemaGenerator, SchemaSettings},
+    schemars::{
+        gen::{SchemaGenerator, SchemaSettings},
+        schema::{InstanceType, SchemaObject},
+    },
     Map,
 };
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tracing::{debug, debug_span};
+use yaml_rust2::{Yaml, YamlEmitter};
 
-use crate::builder::app::HandlerExt;
+use crate::{
+    builder::app::HandlerExt,
+    changelog::Changelog,
+    config::handler_config_keys,
+    layers::{client_ip::ClientIp, request_id::X_REQUEST_ID, trailing_slash::TrailingSlash},
+    payload::{fnv1a, StaticPayload},
+    static_dir::etag_matches,
+};
 
 /// Error type used in API doc objects.
 #[derive(Debug, Error)]
@@ -29,9 +49,309 @@ pub enum ApiDocError {
     /// OpenAPI specification JSON rendering errors.
     #[error(transparent)]
     RenderSpec(#[from] serde_json::Error),
-    /// Unsupported method for OpenAPI specification.
-    #[error("Method {0} not supported in OpenAPI specification")]
-    UnsupportedMethod(Method),
+    /// OpenAPI specification YAML rendering errors.
+    #[error("Unable to render OpenAPI specification as YAML: {0}")]
+    RenderSpecYaml(String),
+    /// Custom API documentation page template could not be loaded.
+    #[error("Unable to load API doc template {path}: {source}")]
+    Template {
+        /// Template file path.
+        path: PathBuf,
+        /// Source error.
+        source: std::io::Error,
+    },
+    /// Inline logo has invalid data or content type.
+    #[error("Invalid API doc logo: {0}")]
+    InvalidLogo(String),
+}
+
+/// Logo displayed in API documentation UI.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ApiDocLogo {
+    /// URL of an external logo image.
+    Url(String),
+    /// Inline logo image, served from `logo` path under API documentation UI path.
+    Inline {
+        /// Content type of the image.
+        content_type: String,
+        /// Base64-encoded image data.
+        data: String,
+    },
+}
+
+/// Branding of API documentation UI.
+///
+/// Unset options leave UI page unchanged.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct ApiDocBranding {
+    /// Logo displayed in page header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    logo: Option<ApiDocLogo>,
+    /// Primary color, passed to RapiDoc as `primary-color` attribute.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    primary_color: Option<String>,
+    /// Background color, passed to RapiDoc as `bg-color` attribute.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bg_color: Option<String>,
+    /// Custom page title.
+    ///
+    /// Default is app title followed by ":: API documentation".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    page_title: Option<String>,
+    /// HTML snippet displayed in page footer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    footer: Option<String>,
+    /// Include footer HTML as is.
+    ///
+    /// Footer is HTML-escaped if this is not set.
+    #[serde(default)]
+    trusted_footer: bool,
+    /// Path to a file replacing built-in page template, loaded at startup.
+    ///
+    /// Only variable substitution is supported in custom templates. Recognized placeholders are
+    /// `{{ title }}`, `{{ page_title }}`, `{{ spec_path }}`, `{{ js_path }}`,
+    /// `{{ changelog_path }}`, `{{ logo_url }}`, `{{ footer }}` and `{{ rapidoc_attributes }}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    template: Option<PathBuf>,
+}
+
+impl ApiDocBranding {
+    /// Use logo image from external URL.
+    #[must_use]
+    pub fn with_logo_url(mut self, url: impl ToString) -> Self {
+        self.logo = Some(ApiDocLogo::Url(url.to_string()));
+        self
+    }
+
+    /// Use inline logo image, served by the application.
+    #[must_use]
+    pub fn with_inline_logo(mut self, content_type: impl ToString, data: impl AsRef<[u8]>) -> Self {
+        self.logo = Some(ApiDocLogo::Inline {
+            content_type: content_type.to_string(),
+            data: B64.encode(data),
+        });
+        self
+    }
+
+    /// Set primary color.
+    #[must_use]
+    pub fn with_primary_color(mut self, color: impl ToString) -> Self {
+        self.primary_color = Some(color.to_string());
+        self
+    }
+
+    /// Set background color.
+    #[must_use]
+    pub fn with_bg_color(mut self, color: impl ToString) -> Self {
+        self.bg_color = Some(color.to_string());
+        self
+    }
+
+    /// Set custom page title.
+    #[must_use]
+    pub fn with_page_title(mut self, title: impl ToString) -> Self {
+        self.page_title = Some(title.to_string());
+        self
+    }
+
+    /// Set page footer HTML snippet.
+    ///
+    /// Untrusted footer is HTML-escaped.
+    #[must_use]
+    pub fn with_footer(mut self, html: impl ToString, trusted: bool) -> Self {
+        self.footer = Some(html.to_string());
+        self.trusted_footer = trusted;
+        self
+    }
+
+    /// Replace built-in page template with a file.
+    #[must_use]
+    pub fn with_template(mut self, path: impl Into<PathBuf>) -> Self {
+        self.template = Some(path.into());
+        self
+    }
+
+    /// Load custom page template, if set.
+    fn load_template(&self) -> Result<Option<Arc<str>>, ApiDocError> {
+        self.template
+            .as_ref()
+            .map(|path| {
+                fs::read_to_string(path)
+                    .map(Arc::from)
+                    .map_err(|source| ApiDocError::Template {
+                        path: path.clone(),
+                        source,
+                    })
+            })
+            .transpose()
+    }
+
+    /// Decode inline logo, if set.
+    fn load_logo(&self) -> Result<Option<StaticPayload>, ApiDocError> {
+        let Some(ApiDocLogo::Inline { content_type, data }) = &self.logo else {
+            return Ok(None);
+        };
+        let content_type = HeaderValue::from_str(content_type)
+            .map_err(|_| ApiDocError::InvalidLogo(format!("content type {content_type}")))?;
+        let data = B64
+            .decode(data)
+            .map_err(|err| ApiDocError::InvalidLogo(err.to_string()))?;
+        Ok(Some(StaticPayload::new(content_type, data)))
+    }
+}
+
+/// Visibility of a handler in API documentation.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ApiVisibility {
+    /// Included in both internal and public specifications.
+    Public,
+    /// Included only in internal specification.
+    #[default]
+    Internal,
+}
+
+/// API documentation UI.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ApiDocUi {
+    /// RapiDoc, bundled with the crate.
+    #[default]
+    Rapidoc,
+    /// Swagger UI, loaded from CDN.
+    SwaggerUi,
+    /// Redoc, loaded from CDN.
+    Redoc,
+    /// No UI, only specification is served.
+    None,
+}
+
+/// Public variant of API documentation.
+///
+/// Contains only handlers marked with `visibility = "public"`. Component schemas, tags and
+/// security schemes not referenced by any public operation are pruned. API changelog and batch
+/// endpoint are not included.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct PublicApiDocConfig {
+    /// URL path for public API documentation UI (RapiDoc).
+    #[serde(default = "PublicApiDocConfig::default_apidoc_path")]
+    apidoc_path: String,
+    /// URL path for public OpenAPI spec.
+    #[serde(default = "PublicApiDocConfig::default_spec_path")]
+    spec_path: String,
+    /// URL path for public OpenAPI spec in YAML format.
+    #[serde(default = "PublicApiDocConfig::default_yaml_spec_path")]
+    yaml_spec_path: String,
+    /// Static list of servers included in public spec.
+    ///
+    /// Falls back to servers of internal spec if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    servers: Vec<openapi3::Server>,
+    /// Base path appended to derived server URL in public spec.
+    ///
+    /// Falls back to base path of internal spec if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    base_path: Option<String>,
+    /// Require OpenID Connect session for public documentation too.
+    ///
+    /// Only used if OpenID Connect login protects API documentation.
+    #[serde(default)]
+    protect: bool,
+}
+
+impl Default for PublicApiDocConfig {
+    fn default() -> Self {
+        Self {
+            apidoc_path: Self::default_apidoc_path(),
+            spec_path: Self::default_spec_path(),
+            yaml_spec_path: Self::default_yaml_spec_path(),
+            servers: Vec::new(),
+            base_path: None,
+            protect: false,
+        }
+    }
+}
+
+impl PublicApiDocConfig {
+    /// Default value for [`Self::apidoc_path`].
+    #[must_use]
+    #[inline]
+    fn default_apidoc_path() -> String {
+        "/apidoc/public".into()
+    }
+
+    /// Default value for [`Self::spec_path`].
+    #[must_use]
+    #[inline]
+    fn default_spec_path() -> String {
+        "/openapi.public.json".into()
+    }
+
+    /// Default value for [`Self::yaml_spec_path`].
+    #[must_use]
+    #[inline]
+    fn default_yaml_spec_path() -> String {
+        "/openapi.public.yaml".into()
+    }
+
+    /// Set URL path for public API documentation UI (RapiDoc).
+    #[must_use]
+    pub fn with_apidoc_path(mut self, path: impl ToString) -> Self {
+        self.apidoc_path = path.to_string();
+        self
+    }
+
+    /// Set URL path for public OpenAPI specification.
+    #[must_use]
+    pub fn with_spec_path(mut self, path: impl ToString) -> Self {
+        self.spec_path = path.to_string();
+        self
+    }
+
+    /// Set URL path for public OpenAPI specification in YAML format.
+    #[must_use]
+    pub fn with_yaml_spec_path(mut self, path: impl ToString) -> Self {
+        self.yaml_spec_path = path.to_string();
+        self
+    }
+
+    /// Add static server entry to public OpenAPI spec.
+    #[must_use]
+    pub fn with_server(mut self, url: impl ToString, description: Option<impl ToString>) -> Self {
+        self.servers.push(openapi3::Server {
+            url: url.to_string(),
+            description: description.map(|d| d.to_string()),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Set base path appended to derived server URL in public spec.
+    #[must_use]
+    pub fn with_base_path(mut self, path: impl ToString) -> Self {
+        self.base_path = Some(path.to_string());
+        self
+    }
+
+    /// Require OpenID Connect session for public documentation.
+    #[must_use]
+    pub fn with_protect(mut self, protect: bool) -> Self {
+        self.protect = protect;
+        self
+    }
+
+    /// Whether public documentation requires OpenID Connect session.
+    #[must_use]
+    #[inline]
+    pub fn is_protected(&self) -> bool {
+        self.protect
+    }
 }
 
 /// Builder for API documentation spec and UI.
@@ -39,12 +359,15 @@ pub enum ApiDocError {
 #[non_exhaustive]
 #[template(path = "rapidoc.html.j2", ext = "html")]
 pub struct ApiDocBuilder {
-    /// URL path for API documentation UI (RapiDoc).
+    /// URL path for API documentation UI.
     #[serde(default = "ApiDocBuilder::default_apidoc_path")]
     apidoc_path: String,
     /// URL path for generated OpenAPI spec.
     #[serde(default = "ApiDocBuilder::default_spec_path")]
     spec_path: String,
+    /// URL path for generated OpenAPI spec in YAML format.
+    #[serde(default = "ApiDocBuilder::default_yaml_spec_path")]
+    yaml_spec_path: String,
     /// URL path for embedded RapiDoc JavaScript source.
     #[serde(default = "ApiDocBuilder::default_js_path")]
     js_path: String,
@@ -72,20 +395,108 @@ pub struct ApiDocBuilder {
     /// OpenAPI spec tag metadata.
     #[serde(default)]
     tags: Vec<openapi3::Tag>,
-    /// Whether to install RapiDoc UI endpoints.
+    /// Static list of servers included in OpenAPI spec.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    servers: Vec<openapi3::Server>,
+    /// Derive server entry of OpenAPI spec from each request.
+    ///
+    /// Only used if no static servers are configured. Public base URL is built from request
+    /// `Host` header, or from `Forwarded`, `X-Forwarded-Proto` and `X-Forwarded-Host` headers if
+    /// request came from a trusted proxy, see [`ClientIpConfig`](crate::ClientIpConfig).
+    #[serde(default)]
+    derive_servers: bool,
+    /// Base path appended to derived server URL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    base_path: Option<String>,
+    /// Parameters consumed by layers, documented for all handlers with a given tag.
+    ///
+    /// Parameters declared on handler level take precedence.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    tag_parameters: HashMap<String, Vec<openapi3::Parameter>>,
+    /// Whether to install API documentation UI endpoints.
+    ///
+    /// If disabled, [`Self::ui`] is ignored.
     #[serde(default = "crate::util::default_true")]
     enable_ui: bool,
+    /// API documentation UI.
+    #[serde(default)]
+    ui: ApiDocUi,
     /// Inline the subschemas or use references.
     ///
     /// See [`SchemaSettings::inline_subschemas`].
     #[serde(default)]
     inline_subschemas: bool,
+    /// Annotate operations with rate limits and timeouts from handler configuration.
+    ///
+    /// Adds `x-rate-limit` and `x-timeout` extensions, and appends a summary to operation
+    /// description.
+    #[serde(default)]
+    config_annotations: bool,
+    /// List configuration keys addressing each operation's handler.
+    ///
+    /// Adds `x-uxum-config-keys` extension, exposing handler names. Never enabled for public
+    /// specification.
+    #[serde(default)]
+    config_keys: bool,
+    /// Document `x-request-id` header on all responses.
+    ///
+    /// The header is always set by request ID layer.
+    #[serde(default)]
+    request_id_header: bool,
     /// Attributes passed to RapiDoc component.
     #[serde(default = "ApiDocBuilder::default_rapidoc_attributes")]
     rapidoc_attributes: HashMap<String, String>,
+    /// Configuration options passed to Swagger UI.
+    ///
+    /// Keys are written in `snake_case`, and converted to `camelCase` used by Swagger UI.
+    #[serde(default = "ApiDocBuilder::default_swagger_ui_attributes")]
+    swagger_ui_attributes: HashMap<String, serde_json::Value>,
+    /// Attributes passed to Redoc component.
+    #[serde(default)]
+    redoc_attributes: HashMap<String, String>,
+    /// Branding of API documentation UI.
+    #[serde(default)]
+    branding: ApiDocBranding,
+    /// Public variant of API documentation.
+    ///
+    /// Disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    public: Option<PublicApiDocConfig>,
+    /// Visibility of handlers included in specification, all if not set.
+    #[serde(skip)]
+    visibility: Option<ApiVisibility>,
     /// List of handlers that have been disabled in configuration.
     #[serde(skip)]
     disabled_handlers: Vec<String>,
+    /// URL path of batch endpoint, if enabled.
+    #[serde(skip)]
+    batch_path: Option<String>,
+    /// Policy for trailing slashes, used to canonicalize handler paths.
+    #[serde(skip)]
+    trailing_slash: TrailingSlash,
+    /// Additional accepted request content types, keyed by handler name.
+    #[serde(skip)]
+    extra_request_types: HashMap<String, Vec<String>>,
+    /// Caching policy descriptions, keyed by handler name.
+    #[serde(skip)]
+    cache_policies: HashMap<String, serde_json::Value>,
+    /// Request body size limits, keyed by handler name.
+    #[serde(skip)]
+    body_limits: HashMap<String, u64>,
+    /// Annotations derived from handler configuration, keyed by handler name.
+    #[serde(skip)]
+    handler_annotations: HashMap<String, Vec<ConfigAnnotation>>,
+}
+
+/// Operation annotation derived from handler configuration.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ConfigAnnotation {
+    /// Name of specification extension.
+    pub(crate) extension: &'static str,
+    /// Value of specification extension.
+    pub(crate) value: serde_json::Value,
+    /// Human-readable summary, appended to operation description.
+    pub(crate) summary: String,
 }
 
 impl Default for ApiDocBuilder {
@@ -93,6 +504,7 @@ impl Default for ApiDocBuilder {
         Self {
             apidoc_path: Self::default_apidoc_path(),
             spec_path: Self::default_spec_path(),
+            yaml_spec_path: Self::default_yaml_spec_path(),
             js_path: Self::default_js_path(),
             app_name: None,
             app_version: None,
@@ -102,10 +514,29 @@ impl Default for ApiDocBuilder {
             contact_url: None,
             contact_email: None,
             tags: vec![],
+            servers: vec![],
+            derive_servers: false,
+            base_path: None,
+            tag_parameters: HashMap::new(),
             enable_ui: true,
+            ui: ApiDocUi::default(),
             inline_subschemas: false,
+            config_annotations: false,
+            config_keys: false,
+            request_id_header: false,
             rapidoc_attributes: Self::default_rapidoc_attributes(),
+            swagger_ui_attributes: Self::default_swagger_ui_attributes(),
+            redoc_attributes: HashMap::new(),
+            branding: ApiDocBranding::default(),
+            public: None,
+            visibility: None,
             disabled_handlers: Vec::new(),
+            batch_path: None,
+            trailing_slash: TrailingSlash::default(),
+            extra_request_types: HashMap::new(),
+            cache_policies: HashMap::new(),
+            body_limits: HashMap::new(),
+            handler_annotations: HashMap::new(),
         }
     }
 }
@@ -128,6 +559,13 @@ impl ApiDocBuilder {
         "/openapi.json".into()
     }
 
+    /// Default value for [`Self::yaml_spec_path`].
+    #[must_use]
+    #[inline]
+    fn default_yaml_spec_path() -> String {
+        "/openapi.yaml".into()
+    }
+
     /// Default value for [`Self::js_path`].
     #[must_use]
     #[inline]
@@ -150,6 +588,16 @@ impl ApiDocBuilder {
         }
     }
 
+    /// Default value for [`Self::swagger_ui_attributes`].
+    #[must_use]
+    #[inline]
+    fn default_swagger_ui_attributes() -> HashMap<String, serde_json::Value> {
+        maplit::hashmap! {
+            "deep_linking".into() => true.into(),
+            "display_request_duration".into() => true.into(),
+        }
+    }
+
     /// Get app title as a string slice.
     #[must_use]
     pub fn app_title(&self) -> &str {
@@ -159,7 +607,104 @@ impl ApiDocBuilder {
             .unwrap_or("Service")
     }
 
-    /// Set URL path for API documentation UI (RapiDoc).
+    /// Get title of API documentation page.
+    #[must_use]
+    pub fn page_title(&self) -> String {
+        match &self.branding.page_title {
+            Some(title) => title.clone(),
+            None => format!("{} :: API documentation", self.app_title()),
+        }
+    }
+
+    /// Get URL path for inline logo image.
+    #[must_use]
+    pub fn logo_path(&self) -> String {
+        format!("{}/logo", self.apidoc_path.trim_end_matches('/'))
+    }
+
+    /// Get URL of logo image, if set.
+    #[must_use]
+    fn logo_url(&self) -> Option<String> {
+        self.branding.logo.as_ref().map(|logo| match logo {
+            ApiDocLogo::Url(url) => url.clone(),
+            ApiDocLogo::Inline { .. } => self.logo_path(),
+        })
+    }
+
+    /// Get footer HTML, escaped unless trusted.
+    #[must_use]
+    fn footer_html(&self) -> Option<String> {
+        self.branding
+            .footer
+            .as_ref()
+            .map(|footer| match self.branding.trusted_footer {
+                true => footer.clone(),
+                false => MarkupDisplay::new_unsafe(footer, Html).to_string(),
+            })
+    }
+
+    /// Attributes passed to RapiDoc component, with branding overrides applied.
+    #[must_use]
+    fn rapidoc_attrs(&self) -> Vec<(&str, &str)> {
+        let branding = [
+            ("primary-color", self.branding.primary_color.as_deref()),
+            ("bg-color", self.branding.bg_color.as_deref()),
+        ];
+        let overridden = |key: &str| {
+            branding
+                .iter()
+                .any(|(name, val)| val.is_some() && *name == key)
+        };
+        self.rapidoc_attributes
+            .iter()
+            .map(|(key, val)| (key.as_str(), val.as_str()))
+            .filter(|(key, _)| !overridden(key))
+            .chain(
+                branding
+                    .iter()
+                    .filter_map(|(key, val)| val.map(|val| (*key, val))),
+            )
+            .collect()
+    }
+
+    /// Render custom page template, substituting known placeholders.
+    ///
+    /// Unknown placeholders are left as is.
+    #[must_use]
+    fn render_custom(&self, template: &str) -> String {
+        let escape = |val: &str| MarkupDisplay::new_unsafe(val, Html).to_string();
+        let attributes = self
+            .rapidoc_attrs()
+            .into_iter()
+            .map(|(key, val)| format!("{}=\"{}\"", escape(key), escape(val)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            let Some(len) = rest[start..].find("}}") else {
+                break;
+            };
+            let value = match rest[start + 2..start + len].trim() {
+                "title" => escape(self.app_title()),
+                "page_title" => escape(&self.page_title()),
+                "spec_path" => escape(&self.spec_path),
+                "js_path" => escape(&self.js_path),
+                "changelog_path" => escape(&self.changelog_path()),
+                "logo_url" => escape(&self.logo_url().unwrap_or_default()),
+                "footer" => self.footer_html().unwrap_or_default(),
+                "rapidoc_attributes" => attributes.clone(),
+                _ => rest[start..start + len + 2].to_string(),
+            };
+            out.push_str(&rest[..start]);
+            out.push_str(&value);
+            rest = &rest[start + len + 2..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// Set URL path for API documentation UI.
     #[must_use]
     pub fn with_apidoc_path(mut self, path: impl ToString) -> Self {
         self.apidoc_path = path.to_string();
@@ -250,13 +795,68 @@ impl ApiDocBuilder {
         self
     }
 
-    /// Disable RapiDoc UI.
+    /// Add static server entry to OpenAPI spec.
+    ///
+    /// Static servers take precedence over derived ones.
+    #[must_use]
+    pub fn with_server(mut self, url: impl ToString, description: Option<impl ToString>) -> Self {
+        self.servers.push(openapi3::Server {
+            url: url.to_string(),
+            description: description.map(|d| d.to_string()),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Derive server entry of OpenAPI spec from each request, if no static servers are set.
+    #[must_use]
+    pub fn with_derive_servers(mut self, derive: bool) -> Self {
+        self.derive_servers = derive;
+        self
+    }
+
+    /// Set base path appended to derived server URL.
+    #[must_use]
+    pub fn with_base_path(mut self, path: impl ToString) -> Self {
+        self.base_path = Some(path.to_string());
+        self
+    }
+
+    /// Document a parameter for all handlers with a given tag.
+    ///
+    /// Useful for parameters consumed by layers, rather than by handler functions.
+    #[must_use]
+    pub fn with_tag_parameter(mut self, tag: impl ToString, param: openapi3::Parameter) -> Self {
+        self.tag_parameters
+            .entry(tag.to_string())
+            .or_default()
+            .push(param);
+        self
+    }
+
+    /// Disable API documentation UI.
     #[must_use]
     pub fn without_ui(mut self) -> Self {
         self.enable_ui = false;
         self
     }
 
+    /// Set API documentation UI.
+    #[must_use]
+    pub fn with_ui(mut self, ui: ApiDocUi) -> Self {
+        self.ui = ui;
+        self
+    }
+
+    /// Get API documentation UI in effect.
+    #[must_use]
+    pub fn ui(&self) -> ApiDocUi {
+        match self.enable_ui {
+            true => self.ui,
+            false => ApiDocUi::None,
+        }
+    }
+
     /// Discourage use of references in generated OpenAPI specification.
     #[must_use]
     pub fn with_inline_subschemas(mut self) -> Self {
@@ -264,6 +864,27 @@ impl ApiDocBuilder {
         self
     }
 
+    /// Annotate operations with rate limits and timeouts from handler configuration.
+    #[must_use]
+    pub fn with_config_annotations(mut self, enable: bool) -> Self {
+        self.config_annotations = enable;
+        self
+    }
+
+    /// List configuration keys addressing each operation's handler.
+    #[must_use]
+    pub fn with_config_keys(mut self, enable: bool) -> Self {
+        self.config_keys = enable;
+        self
+    }
+
+    /// Document `x-request-id` header on all responses.
+    #[must_use]
+    pub fn with_request_id_header(mut self, enable: bool) -> Self {
+        self.request_id_header = enable;
+        self
+    }
+
     /// Set single RapiDoc attribute.
     #[must_use]
     pub fn with_rapidoc_attribute<T, U>(mut self, key: T, value: U) -> Self
@@ -289,6 +910,66 @@ impl ApiDocBuilder {
         self
     }
 
+    /// Set single Swagger UI configuration option.
+    #[must_use]
+    pub fn with_swagger_ui_attribute(
+        mut self,
+        key: impl ToString,
+        value: impl Into<serde_json::Value>,
+    ) -> Self {
+        self.swagger_ui_attributes
+            .insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Set single Redoc attribute.
+    #[must_use]
+    pub fn with_redoc_attribute<T, U>(mut self, key: T, value: U) -> Self
+    where
+        T: ToString,
+        U: ToString,
+    {
+        self.redoc_attributes
+            .insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Swagger UI configuration object, serialized for embedding into a script tag.
+    #[must_use]
+    fn swagger_ui_config(&self) -> String {
+        let mut config: serde_json::Map<String, serde_json::Value> = self
+            .swagger_ui_attributes
+            .iter()
+            .map(|(key, val)| (snake_to_camel(key), val.clone()))
+            .collect();
+        config.insert("url".into(), self.spec_path.clone().into());
+        config.insert("dom_id".into(), "#swagger-ui".into());
+        serde_json::Value::Object(config)
+            .to_string()
+            .replace("</", "<\\/")
+    }
+
+    /// Set branding of API documentation UI.
+    #[must_use]
+    pub fn with_branding(mut self, branding: ApiDocBranding) -> Self {
+        self.branding = branding;
+        self
+    }
+
+    /// Enable public variant of API documentation.
+    #[must_use]
+    pub fn with_public(mut self, public: PublicApiDocConfig) -> Self {
+        self.public = Some(public);
+        self
+    }
+
+    /// Public variant of API documentation configuration, if enabled.
+    #[must_use]
+    #[inline]
+    pub fn public(&self) -> Option<&PublicApiDocConfig> {
+        self.public.as_ref()
+    }
+
     /// Set fallback app name and version.
     ///
     /// This gets called from [`crate::AppBuilder`].
@@ -310,16 +991,87 @@ impl ApiDocBuilder {
         self.disabled_handlers = handlers.into_iter().collect();
     }
 
-    /// Create schema generator for custom types.
-    #[must_use]
-    fn build_generator(&self) -> SchemaGenerator {
-        SchemaSettings::openapi3()
-            .with(|s| {
-                s.inline_subschemas = self.inline_subschemas;
+    /// Set additional accepted request content types, keyed by handler name.
+    ///
+    /// These are added to request body specification of each handler, without schema.
+    pub fn set_extra_request_types(
+        &mut self,
+        types: impl IntoIterator<Item = (String, Vec<String>)>,
+    ) {
+        self.extra_request_types = types.into_iter().collect();
+    }
+
+    /// Set caching policy descriptions, keyed by handler name.
+    ///
+    /// These are added as `x-uxum-cache-policy` extension to each handler operation.
+    pub fn set_cache_policies(
+        &mut self,
+        policies: impl IntoIterator<Item = (String, serde_json::Value)>,
+    ) {
+        self.cache_policies = policies.into_iter().collect();
+    }
+
+    /// Set request body size limits in bytes, keyed by handler name.
+    ///
+    /// These are added as `x-uxum-max-body-size` extension to each handler operation.
+    pub fn set_body_limits(&mut self, limits: impl IntoIterator<Item = (String, u64)>) {
+        self.body_limits = limits.into_iter().collect();
+    }
+
+    /// Whether operations are annotated with limits from handler configuration.
+    #[must_use]
+    pub fn has_config_annotations(&self) -> bool {
+        self.config_annotations
+    }
+
+    /// Set annotations derived from handler configuration, keyed by handler name.
+    ///
+    /// Handlers without annotations are left untouched.
+    pub(crate) fn set_handler_annotations(
+        &mut self,
+        annotations: impl IntoIterator<Item = (String, Vec<ConfigAnnotation>)>,
+    ) {
+        self.handler_annotations = annotations.into_iter().collect();
+    }
+
+    /// Set URL path of batch endpoint, to include it in specification.
+    pub fn set_batch_path(&mut self, path: Option<impl ToString>) {
+        self.batch_path = path.map(|val| val.to_string());
+    }
+
+    /// Set policy for trailing slashes.
+    ///
+    /// With non-strict policy, only canonical handler paths are included in specification.
+    pub fn set_trailing_slash(&mut self, policy: TrailingSlash) {
+        self.trailing_slash = policy;
+    }
+
+    /// Create schema generator for custom types.
+    #[must_use]
+    pub(crate) fn build_generator(&self) -> SchemaGenerator {
+        SchemaSettings::openapi3()
+            .with(|s| {
+                s.inline_subschemas = self.inline_subschemas;
             })
             .into_generator()
     }
 
+    /// Get URL path for API changelog page.
+    #[must_use]
+    pub fn changelog_path(&self) -> String {
+        format!("{}/changelog", self.apidoc_path.trim_end_matches('/'))
+    }
+
+    /// Aggregate API changelog from metadata of all registered handlers.
+    #[must_use]
+    pub fn build_changelog(&self) -> Changelog {
+        Changelog::from_handlers(
+            self.app_title(),
+            inventory::iter::<&dyn HandlerExt>.into_iter().copied(),
+            &self.disabled_handlers,
+        )
+    }
+
     /// Check if builder has any data for [`openapi3::Contact`].
     #[must_use]
     fn has_contact_data(&self) -> bool {
@@ -338,24 +1090,108 @@ impl ApiDocBuilder {
         auth: BTreeMap<String, openapi3::SecurityScheme>,
     ) -> Result<Router, ApiDocError> {
         let _span = debug_span!("build_apidoc").entered();
-        let spec = self.render_spec(auth)?;
-        let mut rtr: Router = Router::new().route(
-            &self.spec_path,
-            routing::get(get_spec).layer(Extension(spec)),
-        );
-        if self.enable_ui {
+        let changelog_path = self.changelog_path();
+        let changelog_json_path = format!("{changelog_path}.json");
+        let mut rtr = self
+            .build_spec_router(auth)?
+            .merge(self.build_changelog().build_router(
+                (self.ui() != ApiDocUi::None).then_some(changelog_path.as_str()),
+                &changelog_json_path,
+            ));
+        if self.ui() == ApiDocUi::Rapidoc {
             let js_map_path = format!("{}.map", &self.js_path);
+            rtr = rtr
+                .route(&self.js_path, routing::get(get_rapidoc_js))
+                .route(&js_map_path, routing::get(get_rapidoc_js_map));
+        }
+        debug!("built API doc router");
+        Ok(rtr)
+    }
+
+    /// Build Axum router for public variant of API documentation, if enabled.
+    ///
+    /// RapiDoc JavaScript source is shared with internal documentation.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if OpenAPI specification object could not be generated for some reason or
+    /// there was some error during serialization.
+    pub fn build_public_router(
+        &self,
+        auth: BTreeMap<String, openapi3::SecurityScheme>,
+    ) -> Result<Option<Router>, ApiDocError> {
+        let Some(public) = self.public_variant() else {
+            return Ok(None);
+        };
+        let _span = debug_span!("build_public_apidoc").entered();
+        let rtr = public.build_spec_router(auth)?;
+        debug!("built public API doc router");
+        Ok(Some(rtr))
+    }
+
+    /// Get builder for public variant of API documentation, if enabled.
+    #[must_use]
+    fn public_variant(&self) -> Option<Self> {
+        let public = self.public.as_ref()?;
+        let mut variant = self.clone();
+        variant.apidoc_path.clone_from(&public.apidoc_path);
+        variant.spec_path.clone_from(&public.spec_path);
+        variant.yaml_spec_path.clone_from(&public.yaml_spec_path);
+        if !public.servers.is_empty() {
+            variant.servers.clone_from(&public.servers);
+        }
+        if public.base_path.is_some() {
+            variant.base_path.clone_from(&public.base_path);
+        }
+        variant.batch_path = None;
+        variant.config_keys = false;
+        variant.public = None;
+        variant.visibility = Some(ApiVisibility::Public);
+        Some(variant)
+    }
+
+    /// Build router serving OpenAPI specification and API documentation UI page.
+    fn build_spec_router(
+        &self,
+        auth: BTreeMap<String, openapi3::SecurityScheme>,
+    ) -> Result<Router, ApiDocError> {
+        let spec = self.render_spec(auth)?;
+        let spec = SpecState {
+            json: StaticPayload::new(HeaderValue::from_static(SPEC_CONTENT_TYPE), spec.json),
+            yaml: StaticPayload::new(HeaderValue::from_static(SPEC_YAML_CONTENT_TYPE), spec.yaml),
+            derive: (self.derive_servers && self.servers.is_empty()).then(|| {
+                Arc::new(ServerDerivation {
+                    base_path: self
+                        .base_path
+                        .as_deref()
+                        .map(|path| path.trim_end_matches('/').to_string())
+                        .unwrap_or_default(),
+                })
+            }),
+        };
+        let mut rtr: Router = Router::new()
+            .route(&self.spec_path, routing::get(get_spec))
+            .route(&self.yaml_spec_path, routing::get(get_spec_yaml))
+            .layer(Extension(spec));
+        if self.ui() != ApiDocUi::None {
             let index_path = format!("{}/index.html", &self.apidoc_path);
+            let page = ApiDocPage {
+                api_doc: self.clone(),
+                template: self.branding.load_template()?,
+            };
             rtr = rtr.merge(
                 Router::new()
-                    .route(&self.apidoc_path, routing::get(get_rapidoc_index))
-                    .route(&index_path, routing::get(get_rapidoc_index))
-                    .route(&self.js_path, routing::get(get_rapidoc_js))
-                    .route(&js_map_path, routing::get(get_rapidoc_js_map))
-                    .with_state(self.clone()),
+                    .route(&self.apidoc_path, routing::get(get_ui_index))
+                    .route(&index_path, routing::get(get_ui_index))
+                    .with_state(page),
             );
+            if let Some(logo) = self.branding.load_logo()? {
+                rtr = rtr.route(
+                    &self.logo_path(),
+                    routing::get(get_logo).layer(Extension(Arc::new(logo))),
+                );
+            }
         }
-        debug!("built API doc router");
         Ok(rtr)
     }
 
@@ -372,7 +1208,7 @@ impl ApiDocBuilder {
         let mut grouped: BTreeMap<&str, Vec<&dyn HandlerExt>> = BTreeMap::new();
         for handler in inventory::iter::<&dyn HandlerExt> {
             grouped
-                .entry(handler.spec_path())
+                .entry(self.trailing_slash.canonical(handler.spec_path()))
                 .and_modify(|handlers| handlers.push(*handler))
                 .or_insert_with(|| vec![*handler]);
         }
@@ -386,24 +1222,109 @@ impl ApiDocBuilder {
                 if self.disabled_handlers.contains(&handler.name().to_string()) {
                     continue;
                 }
-                let spec = handler.openapi_spec(&mut gen);
-                match handler.method() {
-                    Method::GET => path_item.get = Some(spec),
-                    Method::PUT => path_item.put = Some(spec),
-                    Method::POST => path_item.post = Some(spec),
-                    Method::DELETE => path_item.delete = Some(spec),
-                    Method::OPTIONS => path_item.options = Some(spec),
-                    Method::HEAD => path_item.head = Some(spec),
-                    Method::PATCH => path_item.patch = Some(spec),
-                    Method::TRACE => path_item.trace = Some(spec),
-                    other => return Err(ApiDocError::UnsupportedMethod(other)),
-                };
+                if self
+                    .visibility
+                    .is_some_and(|visibility| visibility != handler.visibility())
+                {
+                    continue;
+                }
+                let mut spec = handler.openapi_spec(&mut gen);
+                if handler.no_auth() {
+                    spec.security = Some(Vec::new());
+                }
+                if self.request_id_header {
+                    add_response_header(
+                        &mut spec.responses,
+                        X_REQUEST_ID,
+                        request_id_header(),
+                        &[],
+                    );
+                }
+                for tag in &spec.tags {
+                    if let Some(params) = self.tag_parameters.get(tag) {
+                        merge_parameters(&mut spec.parameters, params.iter().cloned());
+                    }
+                }
+                if let (Some(types), Some(openapi3::RefOr::Object(body))) = (
+                    self.extra_request_types.get(handler.name()),
+                    spec.request_body.as_mut(),
+                ) {
+                    for content_type in types {
+                        body.content
+                            .entry(content_type.clone())
+                            .or_insert_with(openapi3::MediaType::default);
+                    }
+                }
+                if let Some(since) = handler.since() {
+                    spec.extensions.insert("x-since".into(), since.into());
+                }
+                if !handler.changes().is_empty() {
+                    spec.extensions.insert(
+                        "x-changes".into(),
+                        handler
+                            .changes()
+                            .iter()
+                            .map(|change| {
+                                serde_json::json!({
+                                    "version": change.version(),
+                                    "note": change.note(),
+                                })
+                            })
+                            .collect(),
+                    );
+                }
+                if let Some(policy) = self.cache_policies.get(handler.name()) {
+                    spec.extensions
+                        .insert("x-uxum-cache-policy".into(), policy.clone());
+                }
+                if let Some(limit) = self.body_limits.get(handler.name()) {
+                    spec.extensions
+                        .insert("x-uxum-max-body-size".into(), (*limit).into());
+                }
+                for annotation in self
+                    .handler_annotations
+                    .get(handler.name())
+                    .into_iter()
+                    .flatten()
+                {
+                    spec.extensions
+                        .insert(annotation.extension.into(), annotation.value.clone());
+                    spec.description = Some(match spec.description.take() {
+                        Some(descr) => format!("{descr}\n\n{}", annotation.summary),
+                        None => annotation.summary.clone(),
+                    });
+                }
+                for (idx, method) in handler.methods().into_iter().enumerate() {
+                    let mut spec = spec.clone();
+                    // Operation IDs must be unique, so only the primary method keeps the original.
+                    if idx > 0 {
+                        if let Some(op_id) = spec.operation_id.as_mut() {
+                            *op_id = format!("{op_id}_{}", method.as_str().to_lowercase());
+                        }
+                    }
+                    if self.config_keys {
+                        spec.extensions.insert(
+                            "x-uxum-config-keys".into(),
+                            handler_config_keys(handler.name(), &method, handler.path()).into(),
+                        );
+                    }
+                    insert_operation(&mut path_item, method, spec)?;
+                }
                 path_has_handlers = true;
             }
             if path_has_handlers {
                 paths.insert(path.to_owned(), path_item);
             }
         }
+        if let Some(batch_path) = &self.batch_path {
+            paths.insert(
+                batch_path.clone(),
+                openapi3::PathItem {
+                    post: Some(crate::batch::openapi_operation(&mut gen)),
+                    ..Default::default()
+                },
+            );
+        }
         let contact = if self.has_contact_data() {
             Some(openapi3::Contact {
                 name: self.contact_name.clone(),
@@ -415,7 +1336,7 @@ impl ApiDocBuilder {
             None
         };
         let security = auth.keys().cloned().map(|k| map! {k => vec![]}).collect();
-        Ok(openapi3::OpenApi {
+        let mut spec = openapi3::OpenApi {
             openapi: Self::OPENAPI_VERSION.into(),
             info: openapi3::Info {
                 title: self.app_title().to_owned(),
@@ -426,7 +1347,7 @@ impl ApiDocBuilder {
                 version: self.app_version.clone().unwrap_or("0.0.0".into()),
                 extensions: Map::default(),
             },
-            servers: vec![], // FIXME: read from configuration.
+            servers: self.servers.clone(),
             paths,
             components: Some(openapi3::Components {
                 schemas: gen
@@ -441,7 +1362,11 @@ impl ApiDocBuilder {
             tags: self.tags.clone(),
             external_docs: None,
             extensions: Map::default(),
-        })
+        };
+        if self.visibility.is_some() {
+            prune_spec(&mut spec);
+        }
+        Ok(spec)
     }
 
     /// Build and serialize OpenAPI specification.
@@ -454,42 +1379,1329 @@ impl ApiDocBuilder {
         &self,
         auth: BTreeMap<String, openapi3::SecurityScheme>,
     ) -> Result<OpenApiSpec, ApiDocError> {
-        serde_json::to_vec_pretty(&self.build_spec(auth)?)
-            .map(OpenApiSpec)
-            .map_err(Into::into)
+        let spec = self.build_spec(auth)?;
+        let json = serde_json::to_vec_pretty(&spec)?;
+        let mut yaml = String::new();
+        let mut emitter = YamlEmitter::new(&mut yaml);
+        emitter.multiline_strings(true);
+        emitter
+            .dump(&json_to_yaml(&serde_json::to_value(&spec)?))
+            .map_err(|err| ApiDocError::RenderSpecYaml(err.to_string()))?;
+        yaml.push('\n');
+        Ok(OpenApiSpec {
+            json: json.into(),
+            yaml: yaml.into(),
+        })
+    }
+}
+
+/// Content type of OpenAPI specification.
+const SPEC_CONTENT_TYPE: &str = "application/swagger+json";
+
+/// Content type of OpenAPI specification in YAML format.
+const SPEC_YAML_CONTENT_TYPE: &str = "application/yaml";
+
+/// Path item extension listing operations with non-standard HTTP methods.
+const CUSTOM_METHODS_EXTENSION: &str = "x-uxum-custom-methods";
+
+/// Add operation to path item under specified HTTP method.
+///
+/// # Errors
+///
+/// Returns `Err` if operation with non-standard method could not be serialized.
+fn insert_operation(
+    path_item: &mut openapi3::PathItem,
+    method: Method,
+    spec: openapi3::Operation,
+) -> Result<(), ApiDocError> {
+    match method {
+        Method::GET => path_item.get = Some(spec),
+        Method::PUT => path_item.put = Some(spec),
+        Method::POST => path_item.post = Some(spec),
+        Method::DELETE => path_item.delete = Some(spec),
+        Method::OPTIONS => path_item.options = Some(spec),
+        Method::HEAD => path_item.head = Some(spec),
+        Method::PATCH => path_item.patch = Some(spec),
+        Method::TRACE => path_item.trace = Some(spec),
+        other => {
+            // OpenAPI 3.0 has no place for non-standard methods.
+            let methods = path_item
+                .extensions
+                .entry(CUSTOM_METHODS_EXTENSION.into())
+                .or_insert_with(|| serde_json::json!({}));
+            if let Some(methods) = methods.as_object_mut() {
+                methods.insert(other.to_string(), serde_json::to_value(spec)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prefix of references to component schemas.
+const SCHEMA_REF_PREFIX: &str = "#/components/schemas/";
+
+/// Iterate over all operations of a path item.
+fn operations(item: &openapi3::PathItem) -> impl Iterator<Item = &openapi3::Operation> {
+    [
+        &item.get,
+        &item.put,
+        &item.post,
+        &item.delete,
+        &item.options,
+        &item.head,
+        &item.patch,
+        &item.trace,
+    ]
+    .into_iter()
+    .flatten()
+}
+
+/// Collect names of component schemas referenced from a JSON value.
+fn collect_schema_refs(value: &serde_json::Value, refs: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Object(obj) => {
+            for (key, val) in obj {
+                match (key.as_str(), val) {
+                    ("$ref", serde_json::Value::String(reference)) => {
+                        if let Some(name) = reference.strip_prefix(SCHEMA_REF_PREFIX) {
+                            refs.push(name.to_string());
+                        }
+                    }
+                    _ => collect_schema_refs(val, refs),
+                }
+            }
+        }
+        serde_json::Value::Array(arr) => {
+            for val in arr {
+                collect_schema_refs(val, refs);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Remove component schemas, tags and security schemes not referenced by any operation.
+///
+/// Schemas are retained if reachable from operations, directly or through other schemas.
+/// Security schemes are retained if required by at least one operation, either explicitly or via
+/// top-level security requirements.
+fn prune_spec(spec: &mut openapi3::OpenApi) {
+    let mut pending = Vec::new();
+    let mut used_tags = BTreeSet::new();
+    let mut used_schemes = BTreeSet::new();
+    for op in spec.paths.values().flat_map(operations) {
+        used_tags.extend(op.tags.iter().cloned());
+        for req in op.security.as_ref().unwrap_or(&spec.security) {
+            used_schemes.extend(req.keys().cloned());
+        }
+        if let Ok(value) = serde_json::to_value(op) {
+            collect_schema_refs(&value, &mut pending);
+        }
+    }
+    spec.tags.retain(|tag| used_tags.contains(&tag.name));
+    spec.security
+        .retain(|req| req.keys().all(|name| used_schemes.contains(name)));
+    let Some(components) = spec.components.as_mut() else {
+        return;
+    };
+    components
+        .security_schemes
+        .retain(|name, _| used_schemes.contains(name));
+    let mut reachable = BTreeSet::new();
+    while let Some(name) = pending.pop() {
+        if !reachable.insert(name.clone()) {
+            continue;
+        }
+        if let Some(value) = components
+            .schemas
+            .get(&name)
+            .and_then(|schema| serde_json::to_value(schema).ok())
+        {
+            collect_schema_refs(&value, &mut pending);
+        }
+    }
+    components
+        .schemas
+        .retain(|name, _| reachable.contains(name));
+}
+
+/// Append parameters to operation parameter list, skipping already present ones.
+///
+/// Parameters are matched by name and location, header names are matched case-insensitively.
+/// Used in [`crate::handler`] macro expansion.
+#[doc(hidden)]
+pub fn merge_parameters(
+    params: &mut Vec<openapi3::RefOr<openapi3::Parameter>>,
+    extra: impl IntoIterator<Item = openapi3::Parameter>,
+) {
+    for param in extra {
+        let duplicate = params.iter().any(|existing| match existing {
+            openapi3::RefOr::Object(existing) => {
+                existing.location == param.location
+                    && match param.location.as_str() {
+                        "header" => existing.name.eq_ignore_ascii_case(&param.name),
+                        _ => existing.name == param.name,
+                    }
+            }
+            openapi3::RefOr::Ref(_) => false,
+        });
+        match duplicate {
+            true => debug!(
+                name = param.name,
+                location = param.location,
+                "skipping duplicate parameter"
+            ),
+            false => params.push(param.into()),
+        }
+    }
+}
+
+/// Documentation of `x-request-id` response header.
+#[must_use]
+fn request_id_header() -> openapi3::Header {
+    openapi3::Header {
+        description: Some("Unique request identifier, taken from request or generated.".into()),
+        required: true,
+        deprecated: false,
+        allow_empty_value: false,
+        value: openapi3::ParameterValue::Schema {
+            style: None,
+            explode: None,
+            allow_reserved: false,
+            schema: SchemaObject {
+                instance_type: Some(InstanceType::String.into()),
+                ..Default::default()
+            },
+            example: None,
+            examples: None,
+        },
+        extensions: Map::default(),
+    }
+}
+
+/// Add header to responses with given status codes, or to all responses if none are given.
+///
+/// Headers already present are kept intact, names are matched case-insensitively.
+/// Used in [`crate::handler`] macro expansion.
+#[doc(hidden)]
+pub fn add_response_header(
+    responses: &mut openapi3::Responses,
+    name: &str,
+    header: openapi3::Header,
+    statuses: &[u16],
+) {
+    for (status, resp) in &mut responses.responses {
+        if !statuses.is_empty() && !statuses.iter().any(|code| code.to_string() == *status) {
+            continue;
+        }
+        let openapi3::RefOr::Object(resp) = resp else {
+            continue;
+        };
+        if resp
+            .headers
+            .keys()
+            .any(|key| key.eq_ignore_ascii_case(name))
+        {
+            continue;
+        }
+        resp.headers.insert(name.into(), header.clone().into());
     }
 }
 
-/// Newtype for pre-rendered OpenAPI specification.
+/// Pre-rendered OpenAPI specification.
 #[derive(Clone)]
-#[repr(transparent)]
-pub struct OpenApiSpec(Vec<u8>);
-
-/// Handler to serve OpenAPI specification as JSON.
-async fn get_spec(spec: Extension<OpenApiSpec>) -> impl IntoResponse {
-    (
-        [(header::CONTENT_TYPE, "application/swagger+json")],
-        spec.0 .0,
+pub struct OpenApiSpec {
+    /// Specification in JSON format.
+    json: Bytes,
+    /// Specification in YAML format.
+    yaml: Bytes,
+}
+
+/// Convert `snake_case` identifier to `camelCase`.
+fn snake_to_camel(key: &str) -> String {
+    let mut parts = key.split('_');
+    let mut out = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            out.extend(first.to_uppercase());
+            out.push_str(chars.as_str());
+        }
+    }
+    out
+}
+
+/// Convert JSON value to YAML document node.
+fn json_to_yaml(value: &serde_json::Value) -> Yaml {
+    match value {
+        serde_json::Value::Null => Yaml::Null,
+        serde_json::Value::Bool(val) => Yaml::Boolean(*val),
+        serde_json::Value::Number(num) => match num.as_i64() {
+            Some(val) => Yaml::Integer(val),
+            None => Yaml::Real(num.to_string()),
+        },
+        serde_json::Value::String(val) => Yaml::String(val.clone()),
+        serde_json::Value::Array(items) => Yaml::Array(items.iter().map(json_to_yaml).collect()),
+        serde_json::Value::Object(obj) => Yaml::Hash(
+            obj.iter()
+                .map(|(key, val)| (Yaml::String(key.clone()), json_to_yaml(val)))
+                .collect(),
+        ),
+    }
+}
+
+/// RapiDoc code as minified javascript.
+static RAPIDOC_JS: Lazy<StaticPayload> = Lazy::new(|| {
+    StaticPayload::from_static(
+        "application/javascript",
+        include_bytes!("../static/rapidoc-min.js"),
+    )
+    .with_gzip(Bytes::from_static(include_bytes!(
+        "../static/rapidoc-min.js.gz"
+    )))
+});
+
+/// RapiDoc javascript map file.
+static RAPIDOC_JS_MAP: Lazy<StaticPayload> = Lazy::new(|| {
+    StaticPayload::from_static(
+        "application/json",
+        include_bytes!("../static/rapidoc-min.js.map"),
     )
+    .with_gzip(Bytes::from_static(include_bytes!(
+        "../static/rapidoc-min.js.map.gz"
+    )))
+});
+
+/// Base URL of Swagger UI assets.
+const SWAGGER_UI_ASSETS_URL: &str = "https://unpkg.com/swagger-ui-dist@5.17.14";
+
+/// Base URL of Redoc assets.
+const REDOC_ASSETS_URL: &str = "https://cdn.redoc.ly/redoc/v2.1.5/bundles";
+
+/// Swagger UI page.
+#[derive(Template)]
+#[template(path = "swagger_ui.html.j2", ext = "html")]
+struct SwaggerUiPage<'a> {
+    /// API documentation builder.
+    api_doc: &'a ApiDocBuilder,
+    /// Base URL of Swagger UI assets.
+    assets_url: &'static str,
+    /// Swagger UI configuration object.
+    config: String,
+}
+
+/// Redoc page.
+#[derive(Template)]
+#[template(path = "redoc.html.j2", ext = "html")]
+struct RedocPage<'a> {
+    /// API documentation builder.
+    api_doc: &'a ApiDocBuilder,
+    /// Base URL of Redoc assets.
+    assets_url: &'static str,
+}
+
+/// State of API documentation UI page handler.
+#[derive(Clone)]
+struct ApiDocPage {
+    /// API documentation builder, used as built-in template.
+    api_doc: ApiDocBuilder,
+    /// Custom page template, if set.
+    template: Option<Arc<str>>,
+}
+
+/// Shared state of OpenAPI specification handler.
+#[derive(Clone)]
+struct SpecState {
+    /// Pre-rendered specification in JSON format.
+    json: StaticPayload,
+    /// Pre-rendered specification in YAML format.
+    yaml: StaticPayload,
+    /// Server derivation settings, if enabled.
+    derive: Option<Arc<ServerDerivation>>,
+}
+
+/// Settings used to derive server entry from request.
+struct ServerDerivation {
+    /// Base path, without trailing slash.
+    base_path: String,
+}
+
+impl ServerDerivation {
+    /// Derive public base URL from request.
+    ///
+    /// Forwarded scheme and host are only used if peer is a trusted proxy. Otherwise, the host
+    /// used to access the server directly is taken from request.
+    fn base_url(
+        &self,
+        client_ip: Option<ClientIp>,
+        uri: &Uri,
+        headers: &HeaderMap,
+    ) -> Option<String> {
+        let (fwd_proto, fwd_host) = match client_ip.is_some_and(|ip| ip.is_proxied()) {
+            true => forwarded_origin(headers),
+            false => (None, None),
+        };
+        let scheme = fwd_proto
+            .or_else(|| uri.scheme_str().map(str::to_ascii_lowercase))
+            .unwrap_or_else(|| "http".into());
+        if scheme != "http" && scheme != "https" {
+            return None;
+        }
+        let host = fwd_host
+            .or_else(|| {
+                headers
+                    .get(header::HOST)
+                    .and_then(|hv| hv.to_str().ok())
+                    .map(str::to_string)
+            })
+            .or_else(|| uri.authority().map(ToString::to_string))?;
+        // Reject anything which is not a plain host and optional port.
+        let authority: Authority = host.parse().ok()?;
+        if authority.as_str().contains('@') {
+            return None;
+        }
+        Some(format!("{scheme}://{authority}{}", self.base_path))
+    }
+}
+
+/// Get scheme and host from forwarded headers.
+///
+/// Standard `Forwarded` header takes precedence over `X-Forwarded-*` headers. First (client-facing)
+/// values are used.
+fn forwarded_origin(headers: &HeaderMap) -> (Option<String>, Option<String>) {
+    let stanza = headers
+        .get(header::FORWARDED)
+        .and_then(|hv| hv.to_str().ok())
+        .and_then(|hstr| ForwardedHeaderValue::from_forwarded(hstr).ok())
+        .map(|fhv| fhv.into_remotest());
+    let first = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|hv| hv.to_str().ok())
+            .and_then(|hstr| hstr.split(',').next())
+            .map(|val| val.trim().to_string())
+            .filter(|val| !val.is_empty())
+    };
+    let proto = stanza
+        .as_ref()
+        .and_then(|fs| fs.forwarded_proto)
+        .map(|proto| match proto {
+            Protocol::Http => "http".to_string(),
+            Protocol::Https => "https".to_string(),
+        })
+        .or_else(|| first("x-forwarded-proto").map(|val| val.to_ascii_lowercase()));
+    let host = stanza
+        .and_then(|fs| fs.forwarded_host)
+        .or_else(|| first("x-forwarded-host"));
+    (proto, host)
+}
+
+/// Insert server list into pre-rendered specification.
+///
+/// Pre-rendered specification never contains `servers` key when derivation is enabled, so it is
+/// inserted right after the opening brace of top-level object.
+fn patch_servers(spec: &[u8], url: &str) -> Vec<u8> {
+    let entry = format!("\n  \"servers\": {},", serde_json::json!([{ "url": url }]));
+    let mut out = Vec::with_capacity(spec.len() + entry.len());
+    match spec.split_first() {
+        Some((b'{', rest)) => {
+            out.push(b'{');
+            out.extend_from_slice(entry.as_bytes());
+            out.extend_from_slice(rest);
+        }
+        _ => out.extend_from_slice(spec),
+    }
+    out
+}
+
+/// Insert server list into pre-rendered YAML specification.
+///
+/// Server list is inserted right after the document start marker.
+fn patch_servers_yaml(spec: &[u8], url: &str) -> Vec<u8> {
+    // JSON string is also a valid double-quoted YAML scalar.
+    let entry = format!("servers:\n  - url: {}\n", serde_json::Value::from(url));
+    let (head, rest) = match spec.strip_prefix(b"---\n") {
+        Some(rest) => (&b"---\n"[..], rest),
+        None => (&b""[..], spec),
+    };
+    [head, entry.as_bytes(), rest].concat()
 }
 
-/// Handler to serve RapiDoc UI page.
-async fn get_rapidoc_index(api_doc: State<ApiDocBuilder>) -> impl IntoResponse {
-    api_doc.0.into_response()
+/// Check whether client explicitly asks for YAML.
+fn accepts_yaml(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|hv| hv.to_str().ok())
+        .flat_map(|hv| hv.split(','))
+        .filter_map(|range| range.split(';').next())
+        .any(|mime| {
+            matches!(
+                mime.trim().to_ascii_lowercase().as_str(),
+                "application/yaml" | "application/x-yaml" | "text/yaml"
+            )
+        })
+}
+
+/// Handler to serve OpenAPI specification as JSON, or as YAML if requested by client.
+async fn get_spec(
+    Extension(state): Extension<SpecState>,
+    client_ip: Option<Extension<ClientIp>>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    let yaml = accepts_yaml(&headers);
+    let mut resp = spec_response(&state, yaml, client_ip, &uri, &headers);
+    resp.headers_mut()
+        .append(header::VARY, HeaderValue::from_static("accept"));
+    resp
+}
+
+/// Handler to serve OpenAPI specification as YAML.
+async fn get_spec_yaml(
+    Extension(state): Extension<SpecState>,
+    client_ip: Option<Extension<ClientIp>>,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Response {
+    spec_response(&state, true, client_ip, &uri, &headers)
+}
+
+/// Build OpenAPI specification response in JSON or YAML format.
+fn spec_response(
+    state: &SpecState,
+    yaml: bool,
+    client_ip: Option<Extension<ClientIp>>,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Response {
+    let (payload, content_type) = match yaml {
+        true => (&state.yaml, SPEC_YAML_CONTENT_TYPE),
+        false => (&state.json, SPEC_CONTENT_TYPE),
+    };
+    let server = state
+        .derive
+        .as_ref()
+        .and_then(|derive| derive.base_url(client_ip.map(|Extension(ip)| ip), uri, headers));
+    let Some(url) = server else {
+        return payload.response(headers);
+    };
+    let hash = fnv1a(payload.hash(), url.as_bytes());
+    let etag = HeaderValue::from_str(&format!("\"{hash:016x}\"")).ok();
+    if let Some(etag) = &etag {
+        let matched = headers
+            .get(header::IF_NONE_MATCH)
+            .and_then(|hv| hv.to_str().ok())
+            .is_some_and(|inm| etag_matches(inm, etag));
+        if matched {
+            return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag.clone())]).into_response();
+        }
+    }
+    let body = match yaml {
+        true => patch_servers_yaml(payload.raw(), &url),
+        false => patch_servers(payload.raw(), &url),
+    };
+    let mut resp = ([(header::CONTENT_TYPE, content_type)], body).into_response();
+    if let Some(etag) = etag {
+        resp.headers_mut().insert(header::ETAG, etag);
+    }
+    resp
+}
+
+/// Handler to serve API documentation UI page.
+async fn get_ui_index(page: State<ApiDocPage>) -> Response {
+    let api_doc = &page.api_doc;
+    if let Some(template) = &page.template {
+        return axum_response::Html(api_doc.render_custom(template)).into_response();
+    }
+    match api_doc.ui {
+        ApiDocUi::SwaggerUi => SwaggerUiPage {
+            api_doc,
+            assets_url: SWAGGER_UI_ASSETS_URL,
+            config: api_doc.swagger_ui_config(),
+        }
+        .into_response(),
+        ApiDocUi::Redoc => RedocPage {
+            api_doc,
+            assets_url: REDOC_ASSETS_URL,
+        }
+        .into_response(),
+        ApiDocUi::Rapidoc | ApiDocUi::None => api_doc.clone().into_response(),
+    }
+}
+
+/// Handler to serve inline logo image.
+async fn get_logo(Extension(logo): Extension<Arc<StaticPayload>>, headers: HeaderMap) -> Response {
+    logo.response(&headers)
 }
 
 /// Handler to serve RapiDoc code as minified javascript.
-async fn get_rapidoc_js() -> impl IntoResponse {
-    (
-        [(header::CONTENT_TYPE, mime::APPLICATION_JAVASCRIPT.as_ref())],
-        include_bytes!("../static/rapidoc-min.js").as_slice(),
-    )
+async fn get_rapidoc_js(headers: HeaderMap) -> Response {
+    RAPIDOC_JS.response(&headers)
 }
 
 /// Handler to serve RapiDoc javascript map file.
-async fn get_rapidoc_js_map() -> impl IntoResponse {
-    (
-        [(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())],
-        include_bytes!("../static/rapidoc-min.js.map").as_slice(),
-    )
+async fn get_rapidoc_js_map(headers: HeaderMap) -> Response {
+    RAPIDOC_JS_MAP.response(&headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::{
+        body::Body,
+        extract::{ConnectInfo, Query},
+        Json,
+    };
+    use http::Request;
+    use okapi::schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::layers::client_ip::ClientIpConfig;
+
+    /// Nested schema, reachable only through public one.
+    #[derive(Deserialize, JsonSchema, Serialize)]
+    struct PublicInner {
+        value: i32,
+    }
+
+    /// Public schema.
+    #[derive(Deserialize, JsonSchema, Serialize)]
+    struct PublicItem {
+        inner: PublicInner,
+    }
+
+    /// Nested schema, reachable only through internal one.
+    #[derive(Deserialize, JsonSchema, Serialize)]
+    struct InternalSecret {
+        token: String,
+    }
+
+    /// Internal schema.
+    #[derive(Deserialize, JsonSchema, Serialize)]
+    struct InternalItem {
+        secret: InternalSecret,
+    }
+
+    #[crate::handler(
+        path = "/visibility/public",
+        visibility = "public",
+        no_auth,
+        tags = ["open"]
+    )]
+    async fn visibility_public(Json(item): Json<PublicItem>) -> Json<PublicItem> {
+        Json(item)
+    }
+
+    #[crate::handler(path = "/visibility/internal", tags = ["private"])]
+    async fn visibility_internal(Json(item): Json<InternalItem>) -> Json<InternalItem> {
+        Json(item)
+    }
+
+    /// Query string with documented fields.
+    #[derive(Deserialize, JsonSchema)]
+    struct SearchQuery {
+        /// Search term.
+        term: String,
+        /// Maximum number of results.
+        limit: Option<u32>,
+        #[serde(default)]
+        fuzzy: bool,
+    }
+
+    #[crate::handler(
+        path = "/query/search",
+        query_params(
+            term(example = "uxum"),
+            limit(description = "Page size", example = 20, deprecated),
+            fuzzy(required = true),
+        )
+    )]
+    async fn query_search(Query(query): Query<SearchQuery>) -> String {
+        format!("{} {:?} {}", query.term, query.limit, query.fuzzy)
+    }
+
+    #[crate::handler(
+        path = "/headers/limited",
+        response_headers(
+            retry_after(description = "Seconds to wait", schema = "integer", status = [200]),
+            x_unavailable(status = [503]),
+            x_request_id(name = "X-Request-ID", description = "Custom request ID"),
+        )
+    )]
+    async fn headers_limited() -> String {
+        String::new()
+    }
+
+    fn auth() -> BTreeMap<String, openapi3::SecurityScheme> {
+        maplit::btreemap! {
+            "basic".into() => openapi3::SecurityScheme {
+                description: None,
+                data: openapi3::SecuritySchemeData::Http {
+                    scheme: "basic".into(),
+                    bearer_format: None,
+                },
+                extensions: Map::default(),
+            },
+        }
+    }
+
+    fn visibility_builder() -> ApiDocBuilder {
+        ApiDocBuilder::default()
+            .with_tag("open", None::<String>, None::<String>)
+            .with_tag("private", None::<String>, None::<String>)
+            .with_public(
+                PublicApiDocConfig::default()
+                    .with_server("https://api.example.com", None::<String>),
+            )
+    }
+
+    fn param(name: &str, location: &str, required: bool) -> openapi3::Parameter {
+        openapi3::Parameter {
+            name: name.into(),
+            location: location.into(),
+            description: None,
+            required,
+            deprecated: false,
+            allow_empty_value: false,
+            value: openapi3::ParameterValue::Schema {
+                style: None,
+                explode: None,
+                allow_reserved: false,
+                schema: Default::default(),
+                example: None,
+                examples: None,
+            },
+            extensions: Map::default(),
+        }
+    }
+
+    fn names(params: &[openapi3::RefOr<openapi3::Parameter>]) -> Vec<String> {
+        params
+            .iter()
+            .map(|param| match param {
+                openapi3::RefOr::Object(param) => format!("{}:{}", param.location, param.name),
+                openapi3::RefOr::Ref(re) => re.reference.clone(),
+            })
+            .collect()
+    }
+
+    /// Query parameters documented in handler attributes override extractor schema.
+    #[test]
+    fn documented_query_params() {
+        let spec = ApiDocBuilder::default().build_spec(auth()).unwrap();
+        let op = spec.paths["/query/search"].get.clone().unwrap();
+        let params: HashMap<_, _> = op
+            .parameters
+            .into_iter()
+            .filter_map(|param| match param {
+                openapi3::RefOr::Object(param) => Some((param.name.clone(), param)),
+                openapi3::RefOr::Ref(_) => None,
+            })
+            .collect();
+        let example = |name: &str| match &params[name].value {
+            openapi3::ParameterValue::Schema { example, .. } => example.clone(),
+            openapi3::ParameterValue::Content { .. } => None,
+        };
+
+        let term = &params["term"];
+        assert!(term.required);
+        assert_eq!(term.description.as_deref(), Some("Search term."));
+        assert_eq!(example("term"), Some(serde_json::json!("uxum")));
+
+        let limit = &params["limit"];
+        assert!(!limit.required);
+        assert!(limit.deprecated);
+        assert_eq!(limit.description.as_deref(), Some("Page size"));
+        assert_eq!(example("limit"), Some(serde_json::json!(20)));
+
+        assert!(params["fuzzy"].required);
+        assert_eq!(example("fuzzy"), None);
+    }
+
+    /// Response headers are documented from handler attributes and builder options.
+    #[test]
+    fn response_headers() {
+        let headers = |builder: ApiDocBuilder| {
+            let spec = builder.build_spec(auth()).unwrap();
+            let op = spec.paths["/headers/limited"].get.clone().unwrap();
+            let openapi3::RefOr::Object(resp) = op.responses.responses["200"].clone() else {
+                panic!("response is a reference");
+            };
+            resp.headers
+                .into_iter()
+                .map(|(name, header)| match header {
+                    openapi3::RefOr::Object(header) => (name, header),
+                    openapi3::RefOr::Ref(_) => panic!("header is a reference"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let declared = headers(ApiDocBuilder::default());
+        let names: Vec<_> = declared.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["Retry-After", "X-Request-ID"]);
+        let retry_after = &declared[0].1;
+        assert_eq!(retry_after.description.as_deref(), Some("Seconds to wait"));
+        let openapi3::ParameterValue::Schema { schema, .. } = &retry_after.value else {
+            panic!("header has no schema");
+        };
+        assert_eq!(schema.instance_type, Some(InstanceType::Integer.into()));
+
+        // Declared header takes precedence over generic request ID documentation.
+        let documented = headers(ApiDocBuilder::default().with_request_id_header(true));
+        assert_eq!(documented.len(), 2);
+        assert_eq!(
+            documented[1].1.description.as_deref(),
+            Some("Custom request ID")
+        );
+
+        let spec = ApiDocBuilder::default()
+            .with_request_id_header(true)
+            .build_spec(auth())
+            .unwrap();
+        let op = spec.paths["/query/search"].get.clone().unwrap();
+        let openapi3::RefOr::Object(resp) = &op.responses.responses["200"] else {
+            panic!("response is a reference");
+        };
+        assert!(resp.headers.contains_key(X_REQUEST_ID));
+    }
+
+    /// Extra parameters are appended in order, already present ones are kept intact.
+    #[test]
+    fn merged_parameters() {
+        let mut params = vec![
+            param("id", "path", true).into(),
+            param("dry_run", "query", true).into(),
+        ];
+        merge_parameters(
+            &mut params,
+            [
+                param("dry_run", "query", false),
+                param("id", "query", false),
+                param("X-Tenant-Id", "header", true),
+            ],
+        );
+        merge_parameters(
+            &mut params,
+            [
+                param("x-tenant-id", "header", false),
+                param("session", "cookie", false),
+            ],
+        );
+        assert_eq!(
+            names(&params),
+            [
+                "path:id",
+                "query:dry_run",
+                "query:id",
+                "header:X-Tenant-Id",
+                "cookie:session"
+            ]
+        );
+        let openapi3::RefOr::Object(dry_run) = &params[1] else {
+            unreachable!()
+        };
+        assert!(dry_run.required);
+    }
+
+    /// Tag-level parameters are configurable.
+    #[test]
+    fn tag_parameters() {
+        let builder: ApiDocBuilder = serde_json::from_value(serde_json::json!({
+            "tag_parameters": {
+                "tenant": [{
+                    "name": "X-Tenant-Id",
+                    "in": "header",
+                    "required": true,
+                    "schema": {"type": "string"},
+                }],
+            },
+        }))
+        .unwrap();
+        let params = &builder.tag_parameters["tenant"];
+        assert_eq!(params.len(), 1);
+        assert_eq!(params[0].location, "header");
+        assert_eq!(
+            builder,
+            ApiDocBuilder::default().with_tag_parameter("tenant", params[0].clone())
+        );
+    }
+
+    /// Fetch spec from peer address with headers, returning `ETag` and derived server URLs.
+    async fn fetch_servers(
+        builder: &ApiDocBuilder,
+        peer: &str,
+        headers: &[(&str, &str)],
+    ) -> (String, Vec<String>) {
+        let mut req = Request::get("/openapi.json");
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let mut req = req.body(Body::empty()).unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+        let req = ClientIpConfig::default()
+            .with_trusted_proxy("10.0.0.0/8".parse().unwrap())
+            .resolve_incoming(req);
+        let resp = builder
+            .build_router(BTreeMap::new())
+            .unwrap()
+            .oneshot(req)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let etag = resp.headers()[header::ETAG].to_str().unwrap().to_string();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let spec: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let servers = spec["servers"].as_array().cloned().unwrap_or_default();
+        (
+            etag,
+            servers
+                .iter()
+                .map(|srv| srv["url"].as_str().unwrap().to_string())
+                .collect(),
+        )
+    }
+
+    /// Server entry is derived from request, trusting forwarded headers only from proxies.
+    #[tokio::test]
+    async fn derived_servers() {
+        let builder = ApiDocBuilder::default()
+            .with_derive_servers(true)
+            .with_base_path("/api/");
+        let forwarded = [
+            ("host", "10.1.2.3:8080"),
+            ("x-forwarded-proto", "https"),
+            ("x-forwarded-host", "api.example.com, internal.lan"),
+        ];
+
+        // Direct access.
+        let (direct_etag, servers) =
+            fetch_servers(&builder, "192.0.2.1:5000", &[("host", "svc.local:8080")]).await;
+        assert_eq!(servers, ["http://svc.local:8080/api"]);
+
+        // Forwarded headers from trusted proxy.
+        let (proxy_etag, servers) = fetch_servers(&builder, "10.0.0.1:5000", &forwarded).await;
+        assert_eq!(servers, ["https://api.example.com/api"]);
+        assert_ne!(direct_etag, proxy_etag);
+        let (_, servers) = fetch_servers(
+            &builder,
+            "10.0.0.1:5000",
+            &[
+                ("host", "10.1.2.3:8080"),
+                (
+                    "forwarded",
+                    "for=192.0.2.1;proto=https;host=docs.example.com",
+                ),
+            ],
+        )
+        .await;
+        assert_eq!(servers, ["https://docs.example.com/api"]);
+
+        // Untrusted proxy falls back to the address used to access the server.
+        let (_, servers) = fetch_servers(&builder, "192.0.2.1:5000", &forwarded).await;
+        assert_eq!(servers, ["http://10.1.2.3:8080/api"]);
+
+        // Invalid host is not used.
+        let (etag, servers) =
+            fetch_servers(&builder, "192.0.2.1:5000", &[("host", "user@evil")]).await;
+        assert!(servers.is_empty());
+        assert_ne!(etag, direct_etag);
+
+        // Static configuration wins.
+        let builder = builder.with_server("https://static.example.com", None::<String>);
+        let (_, servers) = fetch_servers(&builder, "10.0.0.1:5000", &forwarded).await;
+        assert_eq!(servers, ["https://static.example.com"]);
+    }
+
+    /// Conditional requests are answered with `304 Not Modified` per derived server.
+    #[tokio::test]
+    async fn derived_etag() {
+        let builder = ApiDocBuilder::default().with_derive_servers(true);
+        let (etag, _) = fetch_servers(&builder, "192.0.2.1:5000", &[("host", "svc.local")]).await;
+        let rtr = builder.build_router(BTreeMap::new()).unwrap();
+        for (host, status) in [
+            ("svc.local", StatusCode::NOT_MODIFIED),
+            ("other.local", StatusCode::OK),
+        ] {
+            let req = Request::get("/openapi.json")
+                .header("host", host)
+                .header("if-none-match", &etag)
+                .body(Body::empty())
+                .unwrap();
+            let resp = rtr.clone().oneshot(req).await.unwrap();
+            assert_eq!(resp.status(), status);
+        }
+    }
+
+    /// Spec is served as YAML on its own path, and on JSON path if requested.
+    #[tokio::test]
+    async fn yaml_spec() {
+        use yaml_rust2::YamlLoader;
+
+        let rtr = ApiDocBuilder::default()
+            .with_derive_servers(true)
+            .build_router(BTreeMap::new())
+            .unwrap();
+        let fetch = |path: &'static str, accept: &'static str| {
+            let rtr = rtr.clone();
+            async move {
+                let req = Request::get(path)
+                    .header("host", "svc.local")
+                    .header(header::ACCEPT, accept)
+                    .body(Body::empty())
+                    .unwrap();
+                let resp = rtr.oneshot(req).await.unwrap();
+                assert_eq!(resp.status(), StatusCode::OK);
+                let headers = resp.headers().clone();
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (headers, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        let (headers, json) = fetch("/openapi.json", "application/json").await;
+        assert_eq!(headers[header::CONTENT_TYPE], SPEC_CONTENT_TYPE);
+        assert_eq!(headers[header::VARY], "accept");
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        for (path, accept) in [
+            ("/openapi.yaml", "*/*"),
+            ("/openapi.json", "text/html, application/yaml;q=0.9"),
+        ] {
+            let (headers, yaml) = fetch(path, accept).await;
+            assert_eq!(headers[header::CONTENT_TYPE], SPEC_YAML_CONTENT_TYPE);
+            let doc = &YamlLoader::load_from_str(&yaml).unwrap()[0];
+            assert_eq!(doc["openapi"].as_str(), json["openapi"].as_str());
+            assert_eq!(
+                doc["info"]["title"].as_str(),
+                json["info"]["title"].as_str()
+            );
+            assert_eq!(doc["servers"][0]["url"].as_str(), Some("http://svc.local"));
+        }
+    }
+
+    /// Handlers with configured limits are annotated, others are left untouched.
+    #[test]
+    fn config_annotations() {
+        use std::time::Duration;
+
+        use crate::layers::{rate::HandlerRateLimitConfig, timeout::HandlerTimeoutConfig};
+
+        let rate: HandlerRateLimitConfig =
+            serde_json::from_value(serde_json::json!({"rps": 10, "key": "peer_ip"})).unwrap();
+        let timeout = HandlerTimeoutConfig {
+            default_timeout: Some(Duration::from_secs(5)),
+            max_timeout: Some(Duration::from_secs(30)),
+            ..Default::default()
+        };
+        let mut builder = ApiDocBuilder::default().with_config_annotations(true);
+        builder.set_handler_annotations([(
+            "visibility_internal".to_string(),
+            vec![rate.annotation(), timeout.annotation().unwrap()],
+        )]);
+        let spec = builder.build_spec(auth()).unwrap();
+        let operation = |path: &str| {
+            let item = serde_json::to_value(&spec.paths[path]).unwrap();
+            item.as_object().unwrap().values().next().unwrap().clone()
+        };
+
+        let annotated = operation("/visibility/internal");
+        assert_eq!(annotated["x-rate-limit"]["rps"], 10);
+        assert_eq!(annotated["x-rate-limit"]["key"], "peer_ip");
+        assert_eq!(annotated["x-timeout"]["default"], 5.0);
+        assert_eq!(annotated["x-timeout"]["max"], 30.0);
+        let descr = annotated["description"].as_str().unwrap();
+        assert!(descr.contains("Rate limited to 10 requests per second per client IP address"));
+        assert!(descr.contains("Requests time out after 5s."));
+        assert!(descr.contains("`X-Timeout` header, of at most 30s."));
+
+        let untouched = operation("/visibility/public");
+        assert!(untouched.get("x-rate-limit").is_none());
+        assert!(untouched.get("x-timeout").is_none());
+    }
+
+    /// Configuration keys are listed only if enabled.
+    #[test]
+    fn config_keys() {
+        let operation = |builder: ApiDocBuilder| {
+            let spec = builder.build_spec(auth()).unwrap();
+            let item = serde_json::to_value(&spec.paths["/visibility/internal"]).unwrap();
+            item.as_object().unwrap().values().next().unwrap().clone()
+        };
+        assert!(operation(ApiDocBuilder::default())
+            .get("x-uxum-config-keys")
+            .is_none());
+        let keys = operation(ApiDocBuilder::default().with_config_keys(true));
+        assert!(keys["x-uxum-config-keys"]
+            .as_array()
+            .unwrap()
+            .contains(&"visibility_internal".into()));
+    }
+
+    /// Public specification contains only public operations, and never references schemas,
+    /// tags or security schemes used only by internal ones.
+    #[tokio::test]
+    async fn public_spec() {
+        let builder = visibility_builder();
+        let internal = builder.build_spec(auth()).unwrap();
+        assert!(internal.paths.contains_key("/visibility/internal"));
+        assert!(internal.paths.contains_key("/visibility/public"));
+        let schemas = &internal.components.as_ref().unwrap().schemas;
+        assert!(schemas.contains_key("InternalSecret"));
+        assert!(schemas.contains_key("PublicInner"));
+        assert_eq!(internal.tags.len(), 2);
+
+        let rtr = builder.build_public_router(auth()).unwrap().unwrap();
+        let resp = rtr
+            .clone()
+            .oneshot(
+                Request::get("/openapi.public.json")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let raw = String::from_utf8(body.to_vec()).unwrap();
+        assert!(!raw.contains("Internal"));
+        assert!(!raw.contains("/visibility/internal"));
+        let public: openapi3::OpenApi = serde_json::from_str(&raw).unwrap();
+        assert!(public.paths.contains_key("/visibility/public"));
+        let components = public.components.unwrap();
+        assert!(components.schemas.contains_key("PublicItem"));
+        assert!(components.schemas.contains_key("PublicInner"));
+        assert!(components.security_schemes.is_empty());
+        assert!(public.security.is_empty());
+        assert_eq!(
+            public
+                .tags
+                .iter()
+                .map(|tag| tag.name.as_str())
+                .collect::<Vec<_>>(),
+            ["open"]
+        );
+        assert_eq!(public.servers[0].url, "https://api.example.com");
+
+        // Public UI points to public spec, without link to internal changelog.
+        let resp = rtr
+            .oneshot(Request::get("/apidoc/public").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page = String::from_utf8(body.to_vec()).unwrap();
+        assert!(page.contains("spec-url=\"/openapi.public.json\""));
+        assert!(!page.contains("changelog"));
+
+        // Public variant is disabled by default.
+        assert!(ApiDocBuilder::default()
+            .build_public_router(auth())
+            .unwrap()
+            .is_none());
+    }
+
+    /// Schemas are pruned transitively, security schemes are kept if used by any operation.
+    #[test]
+    fn prune_unreferenced() {
+        let mut spec: openapi3::OpenApi = serde_json::from_value(serde_json::json!({
+            "openapi": "3.0.3",
+            "info": {"title": "test", "version": "1.0.0"},
+            "paths": {
+                "/a": {"get": {
+                    "tags": ["a"],
+                    "security": [],
+                    "responses": {"200": {
+                        "description": "ok",
+                        "content": {"application/json": {
+                            "schema": {"$ref": "#/components/schemas/A"},
+                        }},
+                    }},
+                }},
+                "/b": {"get": {"security": [{"token": []}], "responses": {}}},
+                "/c": {"get": {"responses": {}}},
+            },
+            "components": {
+                "schemas": {
+                    "A": {"type": "array", "items": {"$ref": "#/components/schemas/B"}},
+                    "B": {"type": "object"},
+                    "C": {"$ref": "#/components/schemas/B"},
+                },
+                "securitySchemes": {
+                    "basic": {"type": "http", "scheme": "basic"},
+                    "token": {"type": "http", "scheme": "bearer"},
+                    "unused": {"type": "http", "scheme": "digest"},
+                },
+            },
+            "security": [{"basic": []}],
+            "tags": [{"name": "a"}, {"name": "b"}],
+        }))
+        .unwrap();
+        prune_spec(&mut spec);
+        let components = spec.components.unwrap();
+        assert_eq!(components.schemas.keys().collect::<Vec<_>>(), ["A", "B"]);
+        assert_eq!(
+            components.security_schemes.keys().collect::<Vec<_>>(),
+            ["basic", "token"]
+        );
+        assert_eq!(spec.security.len(), 1);
+        assert_eq!(spec.tags.len(), 1);
+    }
+
+    /// Branding options are substituted into built-in template, defaults render no extras.
+    #[test]
+    fn branding_template() {
+        let page = ApiDocBuilder::default()
+            .with_app_title("Acme")
+            .render()
+            .unwrap();
+        assert!(page.contains("<title>Acme :: API documentation</title>"));
+        assert!(page.contains(r#"theme="dark""#));
+        assert!(!page.contains("slot=\"logo\""));
+        assert!(!page.contains("slot=\"footer\""));
+
+        let builder = ApiDocBuilder::default()
+            .with_app_title("Acme")
+            .with_rapidoc_attribute("primary-color", "#000000")
+            .with_branding(
+                ApiDocBranding::default()
+                    .with_inline_logo("image/png", b"\x89PNG")
+                    .with_primary_color("#ff6600")
+                    .with_bg_color("#fafafa")
+                    .with_page_title("Acme Developer Portal")
+                    .with_footer("<b>Confidential</b>", false),
+            );
+        let page = builder.render().unwrap();
+        assert!(page.contains("<title>Acme Developer Portal</title>"));
+        assert!(page.contains(r##"primary-color="#ff6600""##));
+        assert!(!page.contains(r##"primary-color="#000000""##));
+        assert!(page.contains(r##"bg-color="#fafafa""##));
+        assert!(page.contains(r#"<img slot="logo" src="/apidoc/logo""#));
+        assert!(page.contains("&lt;b&gt;Confidential&lt;/b&gt;"));
+
+        let builder = builder.with_branding(
+            ApiDocBranding::default()
+                .with_logo_url("https://example.com/logo.svg")
+                .with_footer("<b>Confidential</b>", true),
+        );
+        let page = builder.render().unwrap();
+        assert!(page.contains(r#"src="https://example.com/logo.svg""#));
+        assert!(page.contains(r#"<div slot="footer"><b>Confidential</b></div>"#));
+    }
+
+    async fn fetch(rtr: Router, path: &str) -> (String, Bytes) {
+        let resp = rtr
+            .oneshot(Request::get(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let content_type = resp.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (content_type, body)
+    }
+
+    /// Selected UI contributes its own routes, and no UI routes exist if disabled.
+    #[tokio::test]
+    async fn ui_variants() {
+        let status = |rtr: &Router, path: &'static str| {
+            let rtr = rtr.clone();
+            async move {
+                rtr.oneshot(Request::get(path).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap()
+                    .status()
+            }
+        };
+
+        let rtr = ApiDocBuilder::default()
+            .with_ui(ApiDocUi::SwaggerUi)
+            .with_swagger_ui_attribute("doc_expansion", "none")
+            .build_router(BTreeMap::new())
+            .unwrap();
+        let (content_type, body) = fetch(rtr.clone(), "/apidoc").await;
+        assert!(content_type.starts_with("text/html"));
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.contains(SWAGGER_UI_ASSETS_URL));
+        assert!(body.contains(r#""url":"/openapi.json""#));
+        assert!(body.contains(r#""docExpansion":"none""#));
+        assert!(body.contains(r#""deepLinking":true"#));
+        assert_eq!(status(&rtr, "/rapidoc-min.js").await, StatusCode::NOT_FOUND);
+
+        let rtr = ApiDocBuilder::default()
+            .with_ui(ApiDocUi::Redoc)
+            .with_redoc_attribute("hide-download-button", "true")
+            .build_router(BTreeMap::new())
+            .unwrap();
+        let (_, body) = fetch(rtr, "/apidoc/index.html").await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.contains(r#"spec-url="/openapi.json""#));
+        assert!(body.contains(r#"hide-download-button="true""#));
+
+        for builder in [
+            ApiDocBuilder::default().with_ui(ApiDocUi::None),
+            ApiDocBuilder::default()
+                .with_ui(ApiDocUi::Redoc)
+                .without_ui(),
+        ] {
+            assert_eq!(builder.ui(), ApiDocUi::None);
+            let rtr = builder.build_router(BTreeMap::new()).unwrap();
+            assert_eq!(status(&rtr, "/apidoc").await, StatusCode::NOT_FOUND);
+            assert_eq!(status(&rtr, "/rapidoc-min.js").await, StatusCode::NOT_FOUND);
+            assert_eq!(status(&rtr, "/openapi.json").await, StatusCode::OK);
+        }
+
+        let config: ApiDocBuilder = serde_json::from_value(serde_json::json!({
+            "ui": "swagger_ui",
+        }))
+        .unwrap();
+        assert_eq!(config.ui(), ApiDocUi::SwaggerUi);
+    }
+
+    /// Inline logo is served from its own endpoint, custom template is loaded at startup.
+    #[tokio::test]
+    async fn branding_endpoints() {
+        let path = std::env::temp_dir().join(format!("uxum-apidoc-{}.html", std::process::id()));
+        fs::write(
+            &path,
+            "<h1>{{ page_title }}</h1><img src=\"{{logo_url}}\">{{ unknown }}",
+        )
+        .unwrap();
+        let branding = ApiDocBranding::default()
+            .with_inline_logo("image/png", b"\x89PNG")
+            .with_page_title("<Portal>");
+        let builder = ApiDocBuilder::default().with_branding(branding.clone().with_template(&path));
+        let rtr = builder.build_router(BTreeMap::new()).unwrap();
+        let (content_type, body) = fetch(rtr.clone(), "/apidoc/logo").await;
+        assert_eq!(content_type, "image/png");
+        assert_eq!(&body[..], b"\x89PNG");
+        let (content_type, body) = fetch(rtr, "/apidoc").await;
+        assert!(content_type.starts_with("text/html"));
+        assert_eq!(
+            body,
+            "<h1>&lt;Portal&gt;</h1><img src=\"/apidoc/logo\">{{ unknown }}"
+        );
+        fs::remove_file(&path).unwrap();
+
+        let builder = ApiDocBuilder::default().with_branding(branding.with_template(&path));
+        let Err(err) = builder.build_router(BTreeMap::new()) else {
+            panic!("missing template not detected");
+        };
+        assert!(matches!(err, ApiDocError::Template { .. }));
+        assert!(err.to_string().contains(&path.display().to_string()));
+    }
 }
@@ -0,0 +1,869 @@
+//! AAA - API keys, managed at runtime.
+//!
+//! Keys have the form `<prefix>.<secret>`. Only the prefix and an Argon2 hash of the whole key
+//! are persisted, the plaintext key is returned to the operator exactly once, when minted.
+
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+    Argon2,
+};
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    error_handling::HandleErrorLayer,
+    extract::{Path, State},
+    http::{HeaderValue, Request, Response, StatusCode},
+    response::IntoResponse,
+    routing, Json, Router,
+};
+use dashmap::DashMap;
+use okapi::{openapi3, Map};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tower::ServiceBuilder;
+use tracing::{debug_span, warn};
+
+use crate::{
+    auth::{
+        config::{AuthConfig, HashedPassword, UserPassword},
+        errors::{AuthError, AuthSetupError},
+        extractor::AuthExtractor,
+        layer::AuthLayer,
+        provider::{AuthProvider, ConfigAuthProvider},
+        user::{UserId, CURRENT_USER_ID},
+    },
+    builder::app::error_handler,
+    errors::{codes, ErrorCode},
+    kv::{KeyValueStore, KvError},
+};
+
+/// Length of public key prefix, used as key identifier.
+const PREFIX_LEN: usize = 8;
+
+/// Length of secret part of the key.
+const SECRET_LEN: usize = 32;
+
+/// Namespace of API key records in key-value store.
+const STORE_PREFIX: &str = "auth/api_key/";
+
+/// Error type used in API key management.
+#[derive(Clone, Debug, Error)]
+#[non_exhaustive]
+pub enum ApiKeyError {
+    /// API keys are not configured.
+    #[error("API keys are not configured")]
+    NotConfigured,
+    /// No key with provided prefix.
+    #[error("API key not found: {0}")]
+    NotFound(String),
+    /// Unable to hash new key.
+    #[error("Unable to hash API key: {0}")]
+    Hash(String),
+    /// Key-value store error.
+    #[error(transparent)]
+    Store(#[from] KvError),
+}
+
+impl ApiKeyError {
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::NotConfigured => codes::API_KEY_NOT_CONFIGURED,
+            Self::NotFound(_) => codes::API_KEY_NOT_FOUND,
+            Self::Hash(_) | Self::Store(_) => codes::API_KEY_INTERNAL,
+        }
+    }
+}
+
+impl IntoResponse for ApiKeyError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            Self::NotConfigured => StatusCode::SERVICE_UNAVAILABLE,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Hash(_) | Self::Store(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let code = self.code();
+        let problem = code
+            .problem(status)
+            .with_type("tag:uxum.github.io,2024:api-key")
+            .with_title(self.to_string());
+        (code, problem).into_response()
+    }
+}
+
+/// API key configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct ApiKeyConfig {
+    /// Request header containing API key.
+    #[serde(default = "ApiKeyConfig::default_header")]
+    pub header: String,
+    /// URL path of API key management endpoints.
+    #[serde(default = "ApiKeyConfig::default_path")]
+    pub path: String,
+    /// Minimum interval between persisting last-used time of a key.
+    #[serde(
+        default = "ApiKeyConfig::default_last_used_interval",
+        with = "humantime_serde"
+    )]
+    pub last_used_interval: Duration,
+}
+
+impl Default for ApiKeyConfig {
+    fn default() -> Self {
+        Self {
+            header: Self::default_header(),
+            path: Self::default_path(),
+            last_used_interval: Self::default_last_used_interval(),
+        }
+    }
+}
+
+impl ApiKeyConfig {
+    /// Permission required to use API key management endpoints.
+    pub const PERMISSION: &'static str = "api_keys";
+
+    /// Default value for [`Self::header`].
+    #[must_use]
+    #[inline]
+    fn default_header() -> String {
+        "X-Api-Key".into()
+    }
+
+    /// Default value for [`Self::path`].
+    #[must_use]
+    #[inline]
+    fn default_path() -> String {
+        "/auth/keys".into()
+    }
+
+    /// Default value for [`Self::last_used_interval`].
+    #[must_use]
+    #[inline]
+    fn default_last_used_interval() -> Duration {
+        Duration::from_secs(60)
+    }
+}
+
+/// Public metadata of an API key.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct ApiKeyInfo {
+    /// Public key prefix, identifying the key.
+    pub prefix: String,
+    /// User the key authenticates as.
+    pub user: String,
+    /// Roles granted to the key.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub roles: BTreeSet<String>,
+    /// Free-form description.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Time of key creation.
+    #[serde(with = "humantime_serde")]
+    pub created: SystemTime,
+    /// Time of last successful authentication using this key.
+    #[serde(default, with = "humantime_serde")]
+    pub last_used: Option<SystemTime>,
+}
+
+/// API key record, as persisted in key-value store.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct StoredKey {
+    /// Public metadata.
+    #[serde(flatten)]
+    info: ApiKeyInfo,
+    /// Hash of the full key.
+    hash: HashedPassword,
+}
+
+/// Cached API key record.
+#[derive(Clone, Debug)]
+struct CachedKey {
+    /// Stored record.
+    stored: StoredKey,
+    /// Last-used time as persisted in store.
+    persisted_last_used: Option<SystemTime>,
+}
+
+/// Plaintext API key, extracted from request.
+///
+/// Never printed in logs.
+#[derive(Clone)]
+pub struct ApiKeyToken(String);
+
+impl fmt::Debug for ApiKeyToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ApiKeyToken(<redacted>)")
+    }
+}
+
+impl ApiKeyToken {
+    /// Public prefix part of the key.
+    fn prefix(&self) -> Option<&str> {
+        self.0.split_once('.').map(|(prefix, _)| prefix)
+    }
+}
+
+/// User authenticated by API key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApiKeyUser {
+    /// User ID.
+    id: UserId,
+    /// Prefix of used key.
+    prefix: String,
+    /// Roles granted to used key.
+    roles: BTreeSet<String>,
+}
+
+impl ApiKeyUser {
+    /// Get user ID.
+    #[must_use]
+    pub fn id(&self) -> &UserId {
+        &self.id
+    }
+
+    /// Get prefix of used key.
+    #[must_use]
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Get roles granted to used key.
+    #[must_use]
+    pub fn roles(&self) -> &BTreeSet<String> {
+        &self.roles
+    }
+}
+
+/// API key registry, shared by extractor, provider and management endpoints.
+#[derive(Clone, Debug)]
+pub struct ApiKeys(Arc<ApiKeysInner>);
+
+/// Inner container for [`ApiKeys`].
+#[derive(Debug)]
+struct ApiKeysInner {
+    /// API key configuration.
+    config: ApiKeyConfig,
+    /// Persistent storage.
+    store: Arc<dyn KeyValueStore>,
+    /// Prefix index of all known keys.
+    index: DashMap<String, CachedKey>,
+}
+
+impl ApiKeys {
+    /// Create new registry, loading existing keys from store.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if stored keys cannot be loaded.
+    pub fn new(config: ApiKeyConfig, store: Arc<dyn KeyValueStore>) -> Result<Self, ApiKeyError> {
+        let index = DashMap::new();
+        for (_, raw) in store.scan_prefix(STORE_PREFIX)? {
+            let stored: StoredKey = serde_json::from_slice(&raw).map_err(KvError::from)?;
+            index.insert(
+                stored.info.prefix.clone(),
+                CachedKey {
+                    persisted_last_used: stored.info.last_used,
+                    stored,
+                },
+            );
+        }
+        Ok(Self(Arc::new(ApiKeysInner {
+            config,
+            store,
+            index,
+        })))
+    }
+
+    /// Mint new API key.
+    ///
+    /// Returns plaintext key, along with its metadata. Plaintext key is not retained.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if key cannot be hashed or persisted.
+    pub fn create(
+        &self,
+        user: impl ToString,
+        roles: BTreeSet<String>,
+        description: Option<String>,
+    ) -> Result<(String, ApiKeyInfo), ApiKeyError> {
+        let mut rng = rand::thread_rng();
+        let prefix = loop {
+            let prefix: String = (&mut rng)
+                .sample_iter(Alphanumeric)
+                .take(PREFIX_LEN)
+                .map(char::from)
+                .collect();
+            if !self.0.index.contains_key(&prefix) {
+                break prefix;
+            }
+        };
+        let secret: String = (&mut rng)
+            .sample_iter(Alphanumeric)
+            .take(SECRET_LEN)
+            .map(char::from)
+            .collect();
+        let key = format!("{prefix}.{secret}");
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+            .hash_password(key.as_bytes(), &salt)
+            .map_err(|err| ApiKeyError::Hash(err.to_string()))?
+            .serialize()
+            .try_into()
+            .map_err(|err: AuthSetupError| ApiKeyError::Hash(err.to_string()))?;
+        let stored = StoredKey {
+            info: ApiKeyInfo {
+                prefix: prefix.clone(),
+                user: user.to_string(),
+                roles,
+                description,
+                created: SystemTime::now(),
+                last_used: None,
+            },
+            hash,
+        };
+        self.persist(&stored)?;
+        let info = stored.info.clone();
+        self.0.index.insert(
+            prefix,
+            CachedKey {
+                stored,
+                persisted_last_used: None,
+            },
+        );
+        Ok((key, info))
+    }
+
+    /// List metadata of all keys, ordered by prefix.
+    #[must_use]
+    pub fn list(&self) -> Vec<ApiKeyInfo> {
+        let mut keys: Vec<_> = self
+            .0
+            .index
+            .iter()
+            .map(|entry| entry.stored.info.clone())
+            .collect();
+        keys.sort_by(|a, b| a.prefix.cmp(&b.prefix));
+        keys
+    }
+
+    /// Revoke API key.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if key does not exist, or cannot be deleted from store.
+    pub fn revoke(&self, prefix: &str) -> Result<ApiKeyInfo, ApiKeyError> {
+        self.0.store.delete(&format!("{STORE_PREFIX}{prefix}"))?;
+        self.0
+            .index
+            .remove(prefix)
+            .map(|(_, key)| key.stored.info)
+            .ok_or_else(|| ApiKeyError::NotFound(prefix.to_string()))
+    }
+
+    /// Find user of a key by its prefix, without verifying the key.
+    fn user_of(&self, token: &ApiKeyToken) -> Option<ApiKeyUser> {
+        let prefix = token.prefix()?;
+        let entry = self.0.index.get(prefix)?;
+        let info = &entry.stored.info;
+        crypto::util::fixed_time_eq(info.prefix.as_bytes(), prefix.as_bytes()).then(|| ApiKeyUser {
+            id: info.user.as_str().into(),
+            prefix: info.prefix.clone(),
+            roles: info.roles.clone(),
+        })
+    }
+
+    /// Verify full key, recording its use.
+    fn verify(&self, user: &ApiKeyUser, token: &ApiKeyToken) -> Result<(), AuthError> {
+        if token.prefix() != Some(user.prefix.as_str()) {
+            return Err(AuthError::InvalidAuthPayload);
+        }
+        let stored = match self.0.index.get(&user.prefix) {
+            Some(entry) if *entry.stored.info.user == **user.id => entry.stored.clone(),
+            _ => return Err(AuthError::UserNotFound),
+        };
+        if UserPassword::Hashed(stored.hash) != token.0.as_str() {
+            return Err(AuthError::AuthFailed);
+        }
+        self.touch(&user.prefix);
+        Ok(())
+    }
+
+    /// Update last-used time of a key, persisting it at most once per configured interval.
+    fn touch(&self, prefix: &str) {
+        let now = SystemTime::now();
+        let to_persist = match self.0.index.get_mut(prefix) {
+            Some(mut entry) => {
+                entry.stored.info.last_used = Some(now);
+                let due = entry.persisted_last_used.map_or(true, |last| {
+                    now.duration_since(last).unwrap_or_default() >= self.0.config.last_used_interval
+                });
+                if due {
+                    entry.persisted_last_used = Some(now);
+                }
+                due.then(|| entry.stored.clone())
+            }
+            None => None,
+        };
+        if let Some(stored) = to_persist {
+            if let Err(err) = self.persist(&stored) {
+                warn!(prefix, %err, "unable to persist API key last-used time");
+            }
+        }
+    }
+
+    /// Write key record to store.
+    fn persist(&self, stored: &StoredKey) -> Result<(), KvError> {
+        self.0.store.put(
+            &format!("{STORE_PREFIX}{}", stored.info.prefix),
+            serde_json::to_vec(stored)?.into(),
+        )
+    }
+
+    /// Build Axum router containing API key management endpoints.
+    pub(crate) fn build_router<AuthProv, AuthExt>(
+        &self,
+        auth_provider: AuthProv,
+        auth_extractor: AuthExt,
+    ) -> Router
+    where
+        AuthProv: AuthProvider + Sync + 'static,
+        AuthExt: AuthExtractor + Sync + 'static,
+        AuthExt::User: Borrow<AuthProv::User>,
+        AuthExt::AuthTokens: Borrow<AuthProv::AuthTokens>,
+    {
+        let _span = debug_span!("build_api_key_router").entered();
+        let path = &self.0.config.path;
+        Router::new()
+            .route(path, routing::get(list_keys).post(create_key))
+            .route(
+                &format!("{}/:prefix", path.trim_end_matches('/')),
+                routing::delete(revoke_key),
+            )
+            .layer(
+                ServiceBuilder::new()
+                    .layer(HandleErrorLayer::new(error_handler))
+                    .layer(AuthLayer::new(
+                        &[ApiKeyConfig::PERMISSION],
+                        auth_provider,
+                        auth_extractor,
+                    )),
+            )
+            .with_state(self.clone())
+    }
+}
+
+/// Body of key creation request.
+#[derive(Clone, Debug, Deserialize)]
+struct CreateRequest {
+    /// User the key authenticates as.
+    user: String,
+    /// Roles granted to the key.
+    #[serde(default)]
+    roles: BTreeSet<String>,
+    /// Free-form description.
+    #[serde(default)]
+    description: Option<String>,
+}
+
+/// Body of key creation response.
+#[derive(Clone, Debug, Serialize)]
+struct CreateResponse {
+    /// Plaintext key, shown only once.
+    key: String,
+    /// Key metadata.
+    #[serde(flatten)]
+    info: ApiKeyInfo,
+}
+
+/// Get ID of authenticated operator of management endpoint.
+fn current_operator() -> Option<UserId> {
+    CURRENT_USER_ID.try_with(Clone::clone).ok().flatten()
+}
+
+/// List metadata of all keys.
+async fn list_keys(State(keys): State<ApiKeys>) -> Json<Vec<ApiKeyInfo>> {
+    Json(keys.list())
+}
+
+/// Mint new key.
+async fn create_key(
+    State(keys): State<ApiKeys>,
+    Json(req): Json<CreateRequest>,
+) -> Result<impl IntoResponse, ApiKeyError> {
+    let (key, info) = keys.create(req.user, req.roles, req.description)?;
+    let operator = current_operator();
+    warn!(
+        operator = operator.as_deref().map(String::as_str),
+        prefix = info.prefix,
+        user = info.user,
+        roles = ?info.roles,
+        "API key created"
+    );
+    Ok((StatusCode::CREATED, Json(CreateResponse { key, info })))
+}
+
+/// Revoke key.
+async fn revoke_key(
+    State(keys): State<ApiKeys>,
+    Path(prefix): Path<String>,
+) -> Result<StatusCode, ApiKeyError> {
+    let info = keys.revoke(&prefix)?;
+    let operator = current_operator();
+    warn!(
+        operator = operator.as_deref().map(String::as_str),
+        prefix = info.prefix,
+        user = info.user,
+        "API key revoked"
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Authentication extractor (front-end) for runtime-managed API keys.
+#[derive(Clone, Debug)]
+pub struct ApiKeyAuthExtractor {
+    /// API key registry.
+    keys: ApiKeys,
+}
+
+impl From<ApiKeys> for ApiKeyAuthExtractor {
+    fn from(value: ApiKeys) -> Self {
+        Self { keys: value }
+    }
+}
+
+impl AuthExtractor for ApiKeyAuthExtractor {
+    type User = ApiKeyUser;
+    type AuthTokens = ApiKeyToken;
+
+    fn extract_auth(
+        &self,
+        req: &Request<Body>,
+    ) -> Result<(Self::User, Self::AuthTokens), AuthError> {
+        let token = match req.headers().get(self.keys.0.config.header.as_str()) {
+            Some(header) => match header.to_str() {
+                Ok(key) => ApiKeyToken(key.trim().to_string()),
+                Err(_) => return Err(AuthError::InvalidAuthPayload),
+            },
+            None => return Err(AuthError::NoAuthProvided),
+        };
+        match self.keys.user_of(&token) {
+            Some(user) => Ok((user, token)),
+            None => Err(AuthError::UserNotFound),
+        }
+    }
+
+    fn error_response(&self, err: AuthError) -> Response<Body> {
+        let status = match err {
+            AuthError::NoAuthProvided | AuthError::UserNotFound | AuthError::AuthFailed => {
+                StatusCode::UNAUTHORIZED
+            }
+            AuthError::NoPermission(_) => StatusCode::FORBIDDEN,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        let mut resp = err.problem_response(status);
+        if status == StatusCode::UNAUTHORIZED {
+            if let Ok(val) = HeaderValue::from_str(&self.keys.0.config.header) {
+                resp.headers_mut()
+                    .insert(axum::http::header::WWW_AUTHENTICATE, val);
+            }
+        }
+        resp
+    }
+
+    fn security_schemes(&self) -> BTreeMap<String, openapi3::SecurityScheme> {
+        maplit::btreemap! {
+            "api-key".into() => openapi3::SecurityScheme {
+                description: Some("API key".into()),
+                data: openapi3::SecuritySchemeData::ApiKey {
+                    name: self.keys.0.config.header.clone(),
+                    location: "header".into(),
+                },
+                extensions: Map::default(),
+            },
+        }
+    }
+
+    fn request_headers(&self) -> Vec<String> {
+        vec![self.keys.0.config.header.clone()]
+    }
+}
+
+/// Authentication provider (back-end) for runtime-managed API keys.
+///
+/// Roles granted to keys are looked up in role database stored in app configuration.
+#[derive(Clone, Debug)]
+pub struct ApiKeyAuthProvider {
+    /// API key registry.
+    keys: ApiKeys,
+    /// Provider used for role lookup.
+    roles: ConfigAuthProvider,
+}
+
+impl ApiKeyAuthProvider {
+    /// Create new provider.
+    #[must_use]
+    pub fn new(keys: ApiKeys, config: AuthConfig) -> Self {
+        Self {
+            keys,
+            roles: config.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for ApiKeyAuthProvider {
+    type User = ApiKeyUser;
+    type AuthTokens = ApiKeyToken;
+
+    async fn authenticate(
+        &self,
+        user: &Self::User,
+        tokens: &Self::AuthTokens,
+    ) -> Result<(), AuthError> {
+        self.keys.verify(user, tokens)
+    }
+
+    async fn authorize(
+        &self,
+        user: &Self::User,
+        _tokens: &Self::AuthTokens,
+        permission: &'static str,
+    ) -> Result<(), AuthError> {
+        match self.roles.roles_allow(&user.roles, permission) {
+            true => Ok(()),
+            false => Err(AuthError::NoPermission(permission)),
+        }
+    }
+
+    async fn roles(&self, user: &Self::User, _tokens: &Self::AuthTokens) -> BTreeSet<String> {
+        user.roles.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use axum::routing::get;
+    use tower::ServiceExt;
+    use tracing_subscriber::fmt::MakeWriter;
+
+    use super::*;
+    use crate::{auth::config::RoleConfig, kv::MemoryStore};
+
+    /// Log writer collecting output in memory.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Captured {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    fn auth_config() -> AuthConfig {
+        let mut cfg = AuthConfig::default();
+        cfg.roles.insert(
+            "reader".into(),
+            RoleConfig {
+                permissions: ["read".to_string()].into(),
+                super_user: false,
+            },
+        );
+        cfg.roles.insert(
+            "admin".into(),
+            RoleConfig {
+                permissions: BTreeSet::new(),
+                super_user: true,
+            },
+        );
+        cfg
+    }
+
+    fn app(keys: &ApiKeys) -> Router {
+        let prov = ApiKeyAuthProvider::new(keys.clone(), auth_config());
+        let ext = ApiKeyAuthExtractor::from(keys.clone());
+        Router::new()
+            .route("/read", get(|| async { "ok" }))
+            .layer(
+                ServiceBuilder::new()
+                    .layer(HandleErrorLayer::new(error_handler))
+                    .layer(AuthLayer::new(&["read"], prov.clone(), ext.clone())),
+            )
+            .merge(keys.build_router(prov, ext))
+    }
+
+    async fn call(app: &Router, method: &str, uri: &str, key: &str, body: &str) -> Response<Body> {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("X-Api-Key", key)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        app.clone().oneshot(req).await.unwrap()
+    }
+
+    /// Mint, use, list and revoke keys; plaintext is never logged or stored.
+    #[tokio::test]
+    async fn lifecycle() {
+        let logs = Captured::default();
+        let _guard = tracing::subscriber::set_default(
+            tracing_subscriber::fmt()
+                .with_writer(logs.clone())
+                .with_max_level(tracing::Level::TRACE)
+                .finish(),
+        );
+        let store = MemoryStore::new();
+        let keys = ApiKeys::new(ApiKeyConfig::default(), Arc::new(store.clone())).unwrap();
+        let (admin_key, _) = keys
+            .create("ops", ["admin".to_string()].into(), None)
+            .unwrap();
+        let app = app(&keys);
+
+        // Mint key through management endpoint.
+        let resp = call(
+            &app,
+            "POST",
+            "/auth/keys",
+            &admin_key,
+            r#"{"user": "svc", "roles": ["reader"]}"#,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let created: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let key = created["key"].as_str().unwrap().to_string();
+        let prefix = created["prefix"].as_str().unwrap().to_string();
+        assert!(key.starts_with(&format!("{prefix}.")));
+
+        // Use it.
+        assert_eq!(
+            call(&app, "GET", "/read", &key, "").await.status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            call(&app, "GET", "/read", &format!("{prefix}.wrong"), "")
+                .await
+                .status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            call(&app, "GET", "/auth/keys", &key, "").await.status(),
+            StatusCode::FORBIDDEN
+        );
+
+        // List shows metadata, including last-used time.
+        let resp = call(&app, "GET", "/auth/keys", &admin_key, "").await;
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let list: Vec<ApiKeyInfo> = serde_json::from_slice(&body).unwrap();
+        let info = list.iter().find(|info| info.prefix == prefix).unwrap();
+        assert_eq!(info.user, "svc");
+        assert!(info.last_used.is_some());
+        assert!(!String::from_utf8_lossy(&body).contains(&key));
+
+        // Keys survive reload from store.
+        let reloaded = ApiKeys::new(ApiKeyConfig::default(), Arc::new(store.clone())).unwrap();
+        assert_eq!(reloaded.list().len(), 2);
+
+        // Revoke it.
+        assert_eq!(
+            call(
+                &app,
+                "DELETE",
+                &format!("/auth/keys/{prefix}"),
+                &admin_key,
+                ""
+            )
+            .await
+            .status(),
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            call(&app, "GET", "/read", &key, "").await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            call(
+                &app,
+                "DELETE",
+                &format!("/auth/keys/{prefix}"),
+                &admin_key,
+                ""
+            )
+            .await
+            .status(),
+            StatusCode::NOT_FOUND
+        );
+
+        // Plaintext keys never reach logs or store.
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("API key created"));
+        for (_, val) in store.scan_prefix("").unwrap() {
+            let val = String::from_utf8_lossy(&val);
+            assert!(!val.contains(&key) && !val.contains(&admin_key));
+        }
+        assert!(!logs.contains(&key) && !logs.contains(&admin_key));
+    }
+
+    /// Last-used time is persisted at most once per interval.
+    #[tokio::test]
+    async fn debounced_last_used() {
+        let store = MemoryStore::new();
+        let keys = ApiKeys::new(ApiKeyConfig::default(), Arc::new(store.clone())).unwrap();
+        let (key, info) = keys.create("svc", BTreeSet::new(), None).unwrap();
+        let token = ApiKeyToken(key);
+        let user = keys.user_of(&token).unwrap();
+        assert_eq!(user.id().as_str(), "svc");
+        keys.verify(&user, &token).unwrap();
+        let stored = |store: &MemoryStore| -> StoredKey {
+            let raw = store
+                .get(&format!("{STORE_PREFIX}{}", info.prefix))
+                .unwrap()
+                .unwrap();
+            serde_json::from_slice(&raw).unwrap()
+        };
+        let first = stored(&store).info.last_used.unwrap();
+        keys.verify(&user, &token).unwrap();
+        assert_eq!(stored(&store).info.last_used, Some(first));
+        assert!(keys.list()[0].last_used.unwrap() > first);
+        let other = ApiKeyUser {
+            id: "other".into(),
+            ..user
+        };
+        assert_eq!(
+            keys.verify(&other, &token).unwrap_err().to_string(),
+            "Authentication failed"
+        );
+    }
+}
@@ -0,0 +1,244 @@
+//! AAA - static bearer tokens.
+//!
+//! Opaque tokens are passed in `Authorization: Bearer <token>` header, and are mapped to users
+//! using token dictionary stored in app configuration.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    http::{
+        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+        HeaderValue, Request, Response, StatusCode,
+    },
+};
+use okapi::{openapi3, Map};
+
+use crate::auth::{
+    config::{AuthConfig, BearerTokenConfig},
+    errors::AuthError,
+    extractor::AuthExtractor,
+    provider::{AuthProvider, ConfigAuthProvider},
+    user::UserId,
+};
+
+/// Marker for successfully verified static bearer token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BearerAuthToken;
+
+/// Authentication extractor (front-end) for static bearer tokens.
+///
+/// Token is verified against token dictionary, and is resolved to its user name.
+#[derive(Clone, Debug)]
+pub struct BearerAuthExtractor {
+    /// Token dictionary.
+    tokens: Arc<[BearerTokenConfig]>,
+}
+
+impl BearerAuthExtractor {
+    /// Name of authentication scheme.
+    const SCHEME: &'static str = "Bearer";
+
+    /// Create new extractor from configuration.
+    #[must_use]
+    pub fn new(config: &AuthConfig) -> Self {
+        Self {
+            tokens: config.bearer_tokens.clone().into(),
+        }
+    }
+}
+
+impl AuthExtractor for BearerAuthExtractor {
+    type User = UserId;
+    type AuthTokens = BearerAuthToken;
+
+    fn extract_auth(
+        &self,
+        req: &Request<Body>,
+    ) -> Result<(Self::User, Self::AuthTokens), AuthError> {
+        let header = req
+            .headers()
+            .get(AUTHORIZATION)
+            .ok_or(AuthError::NoAuthProvided)?
+            .to_str()
+            .map_err(|_| AuthError::InvalidAuthHeader)?;
+        let token = match header.split_once(' ') {
+            Some((scheme, token)) if scheme.eq_ignore_ascii_case(Self::SCHEME) => token.trim(),
+            Some((scheme, _)) => return Err(AuthError::UnknownAuthScheme(scheme.to_string())),
+            None => return Err(AuthError::InvalidAuthHeader),
+        };
+        if token.is_empty() {
+            return Err(AuthError::InvalidAuthPayload);
+        }
+        self.tokens
+            .iter()
+            .find(|cfg| cfg.token == token)
+            .map(|cfg| (cfg.user.as_str().into(), BearerAuthToken))
+            .ok_or(AuthError::AuthFailed)
+    }
+
+    fn error_response(&self, err: AuthError) -> Response<Body> {
+        let status = match err {
+            AuthError::NoAuthProvided | AuthError::UserNotFound | AuthError::AuthFailed => {
+                StatusCode::UNAUTHORIZED
+            }
+            AuthError::NoPermission(_) => StatusCode::FORBIDDEN,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        let mut resp = err.problem_response(status);
+        if status == StatusCode::UNAUTHORIZED {
+            resp.headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static(Self::SCHEME));
+        }
+        resp
+    }
+
+    fn security_schemes(&self) -> BTreeMap<String, openapi3::SecurityScheme> {
+        maplit::btreemap! {
+            "bearer".into() => openapi3::SecurityScheme {
+                description: Some("Static bearer token".into()),
+                data: openapi3::SecuritySchemeData::Http {
+                    scheme: "bearer".into(),
+                    bearer_format: None,
+                },
+                extensions: Map::default(),
+            },
+        }
+    }
+
+    fn request_headers(&self) -> Vec<String> {
+        vec![AUTHORIZATION.to_string()]
+    }
+}
+
+/// Authentication provider (back-end) for static bearer tokens.
+///
+/// Token users are looked up in users and roles stored in app configuration.
+#[derive(Clone, Debug)]
+pub struct BearerAuthProvider {
+    /// Provider used for user lookup and authorization.
+    inner: ConfigAuthProvider,
+}
+
+impl From<ConfigAuthProvider> for BearerAuthProvider {
+    fn from(value: ConfigAuthProvider) -> Self {
+        Self { inner: value }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for BearerAuthProvider {
+    type User = UserId;
+    type AuthTokens = BearerAuthToken;
+
+    async fn authenticate(
+        &self,
+        user: &Self::User,
+        _tokens: &Self::AuthTokens,
+    ) -> Result<(), AuthError> {
+        // Token itself was already verified by extractor.
+        match self.inner.has_user(user) {
+            true => Ok(()),
+            false => Err(AuthError::UserNotFound),
+        }
+    }
+
+    async fn authorize(
+        &self,
+        user: &Self::User,
+        _tokens: &Self::AuthTokens,
+        permission: &'static str,
+    ) -> Result<(), AuthError> {
+        self.inner.user_allows(user, permission)
+    }
+
+    async fn roles(&self, user: &Self::User, _tokens: &Self::AuthTokens) -> BTreeSet<String> {
+        self.inner.user_roles(user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::{
+        password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+        Argon2,
+    };
+    use axum::{routing::get, Extension, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{auth::layer::AuthLayer, builder::app::error_handler};
+
+    /// Plaintext and hashed tokens are resolved to their users.
+    #[tokio::test]
+    async fn bearer_tokens() {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+            .hash_password(b"hashed-token", &salt)
+            .unwrap()
+            .to_string();
+        let auth: AuthConfig = serde_json::from_value(serde_json::json!({
+            "users": {
+                "partner": {"password": "unused", "roles": ["reader"]},
+                "other": {"password": "unused"},
+            },
+            "roles": {
+                "reader": {"permissions": ["read"]},
+            },
+            "bearer_tokens": [
+                {"token": "plain-token", "user": "partner"},
+                {"token_hash": hash, "user": "other"},
+                {"token": "orphan-token", "user": "missing"},
+            ],
+        }))
+        .unwrap();
+        let extractor = BearerAuthExtractor::new(&auth);
+        assert_eq!(
+            extractor.security_schemes()["bearer"].data,
+            openapi3::SecuritySchemeData::Http {
+                scheme: "bearer".into(),
+                bearer_format: None,
+            }
+        );
+        let app = Router::new().route(
+            "/read",
+            get(|Extension(user): Extension<UserId>| async move { user.to_string() })
+                .layer(AuthLayer::new(
+                    &["read"],
+                    BearerAuthProvider::from(ConfigAuthProvider::from(auth)),
+                    extractor,
+                ))
+                .handle_error(error_handler),
+        );
+        let call = |auth: Option<&'static str>| {
+            let mut req = Request::get("/read");
+            if let Some(auth) = auth {
+                req = req.header(AUTHORIZATION, auth);
+            }
+            app.clone().oneshot(req.body(Body::empty()).unwrap())
+        };
+
+        let resp = call(Some("Bearer plain-token")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"partner");
+
+        let forbidden = call(Some("bearer hashed-token")).await.unwrap();
+        assert_eq!(forbidden.status(), StatusCode::FORBIDDEN);
+
+        for auth in [None, Some("Bearer wrong"), Some("Bearer orphan-token")] {
+            let resp = call(auth).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(resp.headers()[WWW_AUTHENTICATE], "Bearer");
+        }
+        let resp = call(Some("Basic dXNlcjpwd2Q=")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
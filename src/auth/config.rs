@@ -2,13 +2,20 @@
 
 use std::{
     collections::{BTreeMap, BTreeSet},
-    ops::{Deref, DerefMut},
+    fmt,
+    ops::Deref,
+    sync::Arc,
 };
 
-use argon2::{Argon2, PasswordVerifier};
-use password_hash::PasswordHashString;
+use argon2::{Algorithm, Argon2, Params, Version};
+use password_hash::{PasswordHash, PasswordHashString};
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    auth::{api_key::ApiKeyConfig, errors::AuthSetupError, token::ServiceTokenConfig},
+    secret::SecretValue,
+};
+
 /// User configuration.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[non_exhaustive]
@@ -25,8 +32,10 @@ pub struct UserConfig {
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum UserPassword {
     /// Cleartext password value.
+    ///
+    /// May be read from environment variable or file, see [`SecretValue`].
     #[serde(rename = "password")]
-    Plaintext(String),
+    Plaintext(SecretValue),
     /// Securely hashed password value.
     ///
     /// This accepts strings in PHC format, as defined in [the specification][1].
@@ -45,11 +54,47 @@ pub enum UserPassword {
 impl PartialEq<&str> for UserPassword {
     fn eq(&self, other: &&str) -> bool {
         match self {
-            Self::Plaintext(pwd) => crypto::util::fixed_time_eq(pwd.as_bytes(), other.as_bytes()),
-            // FIXME: generalize hash verification.
-            Self::Hashed(pwd) => Argon2::default()
-                .verify_password(other.as_bytes(), &pwd.password_hash())
-                .is_ok(),
+            Self::Plaintext(pwd) => {
+                crypto::util::fixed_time_eq(pwd.expose().as_bytes(), other.as_bytes())
+            }
+            Self::Hashed(pwd) => pwd.verify(other),
+        }
+    }
+}
+
+/// Static bearer token configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct BearerTokenConfig {
+    /// Token value.
+    #[serde(flatten)]
+    pub token: BearerToken,
+    /// Name of user this token authenticates as.
+    pub user: String,
+}
+
+/// Various ways of storing static bearer token.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum BearerToken {
+    /// Cleartext token value.
+    ///
+    /// May be read from environment variable or file, see [`SecretValue`].
+    #[serde(rename = "token")]
+    Plaintext(SecretValue),
+    /// Securely hashed token value, in PHC format.
+    ///
+    /// Each hashed token is verified separately, so prefer these only for a handful of tokens.
+    #[serde(rename = "token_hash")]
+    Hashed(HashedPassword),
+}
+
+impl PartialEq<&str> for BearerToken {
+    fn eq(&self, other: &&str) -> bool {
+        match self {
+            Self::Plaintext(token) => {
+                crypto::util::fixed_time_eq(token.expose().as_bytes(), other.as_bytes())
+            }
+            Self::Hashed(token) => token.verify(other),
         }
     }
 }
@@ -76,6 +121,19 @@ pub struct AuthConfig {
     /// Role dictionary.
     #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     pub roles: BTreeMap<String, RoleConfig>,
+    /// Runtime-managed API key configuration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_keys: Option<ApiKeyConfig>,
+    /// Service-to-service token configuration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service_token: Option<ServiceTokenConfig>,
+    /// Static bearer token dictionary.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bearer_tokens: Vec<BearerTokenConfig>,
+    /// OpenID Connect login configuration.
+    #[cfg(feature = "oidc")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub oidc: Option<crate::auth::OidcConfig>,
 }
 
 impl AuthConfig {
@@ -85,14 +143,95 @@ impl AuthConfig {
     }
 }
 
-/// Newtype for hashed passwords.
-#[derive(Clone, Debug, PartialEq, Eq)]
-#[repr(transparent)]
-pub struct HashedPassword(PasswordHashString);
+/// Hashed password.
+///
+/// PHC string is parsed once, when the hash is loaded, so that verification only needs to
+/// compute the hash itself.
+#[derive(Clone)]
+pub struct HashedPassword {
+    /// Original PHC string.
+    phc: PasswordHashString,
+    /// Pre-parsed verifier.
+    verifier: Arc<Argon2Verifier>,
+}
+
+/// Argon2 hash, ready for verification.
+struct Argon2Verifier {
+    /// Hasher with parameters taken from PHC string.
+    argon2: Argon2<'static>,
+    /// Decoded salt.
+    salt: Vec<u8>,
+    /// Expected hash output.
+    hash: Vec<u8>,
+}
 
-impl From<PasswordHashString> for HashedPassword {
-    fn from(item: PasswordHashString) -> Self {
-        Self(item)
+impl Argon2Verifier {
+    /// Parse PHC string.
+    fn new(phc: &PasswordHash<'_>) -> Result<Self, AuthSetupError> {
+        let unsupported =
+            |err: &dyn fmt::Display| AuthSetupError::UnsupportedPasswordHash(err.to_string());
+        let algorithm = Algorithm::try_from(phc.algorithm).map_err(|err| unsupported(&err))?;
+        let version = phc
+            .version
+            .map(Version::try_from)
+            .transpose()
+            .map_err(|err| unsupported(&err))?
+            .unwrap_or_default();
+        let params = Params::try_from(phc).map_err(|err| unsupported(&err))?;
+        let (Some(salt), Some(hash)) = (phc.salt, phc.hash) else {
+            return Err(AuthSetupError::InvalidPasswordHash(
+                "missing salt or hash output".into(),
+            ));
+        };
+        let mut salt_buf = [0; 64];
+        let salt = salt
+            .decode_b64(&mut salt_buf)
+            .map_err(|err| AuthSetupError::InvalidPasswordHash(err.to_string()))?
+            .to_vec();
+        Ok(Self {
+            argon2: Argon2::new(algorithm, version, params),
+            salt,
+            hash: hash.as_bytes().to_vec(),
+        })
+    }
+
+    /// Check plaintext value against this hash.
+    fn verify(&self, value: &str) -> bool {
+        let mut output = vec![0; self.hash.len()];
+        self.argon2
+            .hash_password_into(value.as_bytes(), &self.salt, &mut output)
+            .is_ok()
+            && crypto::util::fixed_time_eq(&output, &self.hash)
+    }
+}
+
+impl HashedPassword {
+    /// Check plaintext value against this hash.
+    #[must_use]
+    pub fn verify(&self, value: &str) -> bool {
+        self.verifier.verify(value)
+    }
+}
+
+impl TryFrom<PasswordHashString> for HashedPassword {
+    type Error = AuthSetupError;
+
+    fn try_from(item: PasswordHashString) -> Result<Self, Self::Error> {
+        let verifier = Argon2Verifier::new(&item.password_hash())?;
+        Ok(Self {
+            phc: item,
+            verifier: Arc::new(verifier),
+        })
+    }
+}
+
+impl std::str::FromStr for HashedPassword {
+    type Err = AuthSetupError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PasswordHashString::new(s)
+            .map_err(|err| AuthSetupError::InvalidPasswordHash(err.to_string()))?
+            .try_into()
     }
 }
 
@@ -100,19 +239,25 @@ impl Deref for HashedPassword {
     type Target = PasswordHashString;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.phc
     }
 }
 
-impl DerefMut for HashedPassword {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+impl fmt::Debug for HashedPassword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("HashedPassword").field(&self.phc).finish()
     }
 }
 
-mod serde_impls {
-    use std::fmt;
+impl PartialEq for HashedPassword {
+    fn eq(&self, other: &Self) -> bool {
+        self.phc == other.phc
+    }
+}
 
+impl Eq for HashedPassword {}
+
+mod serde_impls {
     use serde::{de, Deserializer, Serializer};
 
     use super::*;
@@ -149,9 +294,73 @@ mod serde_impls {
         where
             E: de::Error,
         {
-            PasswordHashString::new(v)
-                .map(Into::into)
-                .map_err(|err| E::custom(format!("unable to parse PHC format: {}", err)))
+            v.parse().map_err(E::custom)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::{
+        password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+        Argon2,
+    };
+
+    use crate::{config::AppConfig, AppBuilder};
+
+    /// Password hashes are parsed when configuration is loaded.
+    #[test]
+    fn password_hashes() {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+            .hash_password(b"secret", &salt)
+            .unwrap()
+            .to_string();
+        let config = |hash: &str| {
+            serde_json::from_value::<AppConfig>(serde_json::json!({
+                "auth": {"users": {"user": {"password_hash": hash}}},
+            }))
+        };
+
+        let app_cfg = config(&hash).unwrap();
+        let _builder = AppBuilder::from_config(&app_cfg).with_basic_auth();
+        let password = &app_cfg.auth.user("user").unwrap().password;
+        assert!(*password == "secret");
+        assert!(*password != "wrong");
+
+        for (hash, expected) in [
+            ("not-a-phc-string", "Invalid password hash"),
+            (
+                "$pbkdf2-sha256$i=1000$c29tZXNhbHQ$aGFzaGhhc2hoYXNo",
+                "Unsupported password hash",
+            ),
+            (
+                "$argon2id$v=19$m=1,t=2,p=1$c29tZXNhbHQ$aGFzaGhhc2hoYXNo",
+                "Unsupported password hash",
+            ),
+        ] {
+            let err = config(hash).unwrap_err().to_string();
+            assert!(err.contains(expected), "{hash}: {err}");
         }
     }
+
+    /// Plaintext passwords can be read from environment, and are redacted when serialized.
+    #[test]
+    fn password_from_env() {
+        std::env::set_var("UXUM_TEST_USER_PASSWORD", "pwd");
+        let config: super::AuthConfig = serde_json::from_value(serde_json::json!({
+            "users": {"alice": {"password": {"env": "UXUM_TEST_USER_PASSWORD"}}},
+        }))
+        .unwrap();
+        assert!(config.users["alice"].password == "pwd");
+        assert_eq!(
+            serde_json::to_value(&config).unwrap()["users"]["alice"]["password"],
+            serde_json::json!({"env": "UXUM_TEST_USER_PASSWORD"})
+        );
+        let err = serde_json::from_value::<super::AuthConfig>(serde_json::json!({
+            "users": {"bob": {"password": {"env": "UXUM_TEST_USER_MISSING"}}},
+        }))
+        .unwrap_err();
+        assert!(err.to_string().contains("UXUM_TEST_USER_MISSING"), "{err}");
+    }
 }
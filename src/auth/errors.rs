@@ -1,7 +1,14 @@
 //! AAA - errors.
 
+use axum::{
+    body::Body,
+    http::{Response, StatusCode},
+    response::IntoResponse,
+};
 use thiserror::Error;
 
+use crate::errors::{codes, ErrorCode};
+
 /// Error type used in authentication and authorization layer.
 #[derive(Clone, Debug, Error)]
 #[non_exhaustive]
@@ -36,7 +43,55 @@ pub enum AuthError {
     /// response to not divulge sensitive information.
     #[error("Authentication failed")]
     AuthFailed,
+    /// Session is expired.
+    #[error("Session expired")]
+    SessionExpired,
     /// User does not have permission.
     #[error("User does not have permission: {0}")]
     NoPermission(&'static str),
 }
+
+impl AuthError {
+    /// Stable error code.
+    ///
+    /// [`AuthError::UserNotFound`] and [`AuthError::AuthFailed`] share the same code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::NoAuthProvided => codes::AUTH_MISSING,
+            Self::InvalidAuthHeader
+            | Self::UnknownAuthScheme(_)
+            | Self::PayloadDecode(_)
+            | Self::NonPrintablePayload(_)
+            | Self::InvalidAuthPayload => codes::AUTH_MALFORMED,
+            Self::UserNotFound | Self::AuthFailed => codes::AUTH_INVALID,
+            Self::SessionExpired => codes::AUTH_SESSION_EXPIRED,
+            Self::NoPermission(_) => codes::AUTH_FORBIDDEN,
+        }
+    }
+
+    /// Build problem details response with provided HTTP status.
+    ///
+    /// Used by authentication extractors to generate error responses.
+    #[must_use]
+    pub fn problem_response(&self, status: StatusCode) -> Response<Body> {
+        let code = self.code();
+        let problem = code
+            .problem(status)
+            .with_type("tag:uxum.github.io,2024:auth")
+            .with_title(self.to_string());
+        (code, problem).into_response()
+    }
+}
+
+/// Error type used when setting up authentication from configuration.
+#[derive(Clone, Debug, Error, PartialEq)]
+#[non_exhaustive]
+pub enum AuthSetupError {
+    /// Password hash is not a valid PHC string.
+    #[error("Invalid password hash: {0}")]
+    InvalidPasswordHash(String),
+    /// Password hash uses unsupported algorithm or parameters.
+    #[error("Unsupported password hash: {0}")]
+    UnsupportedPasswordHash(String),
+}
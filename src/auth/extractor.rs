@@ -14,7 +14,10 @@ use base64::{engine::general_purpose::STANDARD as B64, Engine};
 use okapi::{openapi3, Map};
 use tracing::error;
 
-use crate::auth::{errors::AuthError, user::UserId};
+use crate::{
+    auth::{errors::AuthError, user::UserId},
+    errors::codes,
+};
 
 /// Authentication extractor (front-end) trait.
 pub trait AuthExtractor: Clone + Send {
@@ -24,7 +27,7 @@ pub trait AuthExtractor: Clone + Send {
     /// On successful authentication it is injected into request as an extension.
     type User: Clone + Send + Sync + 'static;
     /// Authentication data type.
-    type AuthTokens;
+    type AuthTokens: Send + Sync + 'static;
 
     /// Extract user ID and authentication data from request.
     ///
@@ -47,6 +50,14 @@ pub trait AuthExtractor: Clone + Send {
     fn security_schemes(&self) -> BTreeMap<String, openapi3::SecurityScheme> {
         BTreeMap::new()
     }
+
+    /// Get names of request headers used to extract authentication data.
+    ///
+    /// Used to compose `Vary` response header.
+    #[must_use]
+    fn request_headers(&self) -> Vec<String> {
+        Vec::new()
+    }
 }
 
 /// Authentication extractor (front-end) which does nothing.
@@ -64,14 +75,10 @@ impl AuthExtractor for NoOpAuthExtractor {
         Ok(((), ()))
     }
 
-    #[must_use]
     fn error_response(&self, err: AuthError) -> Response<Body> {
         // This shuld never get executed for a NoOp extractor
         error!("tried to generate auth error response for NoOpAuthExtractor");
-        problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
-            .with_type("tag:uxum.github.io,2024:auth")
-            .with_title(err.to_string())
-            .into_response()
+        err.problem_response(StatusCode::INTERNAL_SERVER_ERROR)
     }
 }
 
@@ -106,7 +113,6 @@ impl AuthExtractor for BasicAuthExtractor {
         }
     }
 
-    #[must_use]
     fn error_response(&self, err: AuthError) -> Response<Body> {
         let status = match err {
             AuthError::NoAuthProvided | AuthError::UserNotFound | AuthError::AuthFailed => {
@@ -115,19 +121,18 @@ impl AuthExtractor for BasicAuthExtractor {
             AuthError::NoPermission(_) => StatusCode::FORBIDDEN,
             _ => StatusCode::BAD_REQUEST,
         };
-        let mut resp = problemdetails::new(status)
-            .with_type("tag:uxum.github.io,2024:auth")
-            .with_title(err.to_string())
-            .into_response();
+        let mut resp = err.problem_response(status);
         if status == StatusCode::UNAUTHORIZED {
             let header_value = match HeaderValue::from_str(&self.www_auth) {
                 Ok(val) => val,
                 Err(err) => {
-                    return problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
+                    let code = codes::AUTH_MISCONFIGURED;
+                    let problem = code
+                        .problem(StatusCode::INTERNAL_SERVER_ERROR)
                         .with_type("tag:uxum.github.io,2024:auth")
                         .with_title("Invalid HTTP Basic realm value")
-                        .with_detail(err.to_string())
-                        .into_response()
+                        .with_detail(err.to_string());
+                    return (code, problem).into_response();
                 }
             };
             let _ = resp.headers_mut().insert(WWW_AUTHENTICATE, header_value);
@@ -135,7 +140,6 @@ impl AuthExtractor for BasicAuthExtractor {
         resp
     }
 
-    #[must_use]
     fn security_schemes(&self) -> BTreeMap<String, openapi3::SecurityScheme> {
         maplit::btreemap! {
             "basic".into() => openapi3::SecurityScheme {
@@ -148,6 +152,10 @@ impl AuthExtractor for BasicAuthExtractor {
             },
         }
     }
+
+    fn request_headers(&self) -> Vec<String> {
+        vec![AUTHORIZATION.to_string()]
+    }
 }
 
 impl BasicAuthExtractor {
@@ -240,7 +248,6 @@ impl AuthExtractor for HeaderAuthExtractor {
         Ok((user, tokens))
     }
 
-    #[must_use]
     fn error_response(&self, err: AuthError) -> Response<Body> {
         let status = match err {
             AuthError::NoAuthProvided
@@ -249,13 +256,9 @@ impl AuthExtractor for HeaderAuthExtractor {
             | AuthError::NoPermission(_) => StatusCode::FORBIDDEN,
             _ => StatusCode::BAD_REQUEST,
         };
-        problemdetails::new(status)
-            .with_type("tag:uxum.github.io,2024:auth")
-            .with_title(err.to_string())
-            .into_response()
+        err.problem_response(status)
     }
 
-    #[must_use]
     fn security_schemes(&self) -> BTreeMap<String, openapi3::SecurityScheme> {
         maplit::btreemap! {
             "api-name".into() => openapi3::SecurityScheme {
@@ -276,6 +279,10 @@ impl AuthExtractor for HeaderAuthExtractor {
             },
         }
     }
+
+    fn request_headers(&self) -> Vec<String> {
+        vec![self.user_header.to_string(), self.tokens_header.to_string()]
+    }
 }
 
 impl HeaderAuthExtractor {
@@ -289,3 +296,145 @@ impl HeaderAuthExtractor {
         self.tokens_header = Cow::Owned(name.as_ref().into());
     }
 }
+
+/// Authentication extractor (front-end) that tries two extractors in order.
+///
+/// Second extractor is only used if first one finds no credentials in request, or finds
+/// credentials of another authentication scheme. Invalid credentials are reported right away.
+/// More extractors can be chained by nesting.
+///
+/// Error responses are formatted by the first extractor.
+#[derive(Clone, Debug)]
+pub struct StackedAuthExtractor<First, Second> {
+    /// Extractor tried first.
+    first: First,
+    /// Extractor tried when first one finds no credentials.
+    second: Second,
+}
+
+impl<First, Second> StackedAuthExtractor<First, Second> {
+    /// Create new stacked extractor.
+    #[must_use]
+    pub fn new(first: First, second: Second) -> Self {
+        Self { first, second }
+    }
+}
+
+impl<First, Second> AuthExtractor for StackedAuthExtractor<First, Second>
+where
+    First: AuthExtractor,
+    Second: AuthExtractor<User = First::User, AuthTokens = First::AuthTokens>,
+{
+    type User = First::User;
+    type AuthTokens = First::AuthTokens;
+
+    fn extract_auth(
+        &self,
+        req: &Request<Body>,
+    ) -> Result<(Self::User, Self::AuthTokens), AuthError> {
+        match self.first.extract_auth(req) {
+            Err(AuthError::NoAuthProvided | AuthError::UnknownAuthScheme(_)) => {
+                self.second.extract_auth(req)
+            }
+            res => res,
+        }
+    }
+
+    fn error_response(&self, err: AuthError) -> Response<Body> {
+        self.first.error_response(err)
+    }
+
+    fn security_schemes(&self) -> BTreeMap<String, openapi3::SecurityScheme> {
+        let mut schemes = self.second.security_schemes();
+        schemes.extend(self.first.security_schemes());
+        schemes
+    }
+
+    fn request_headers(&self) -> Vec<String> {
+        let mut headers = self.first.request_headers();
+        for header in self.second.request_headers() {
+            if !headers.iter().any(|h| h.eq_ignore_ascii_case(&header)) {
+                headers.push(header);
+            }
+        }
+        headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stacked() -> StackedAuthExtractor<BasicAuthExtractor, HeaderAuthExtractor> {
+        StackedAuthExtractor::new(
+            BasicAuthExtractor::default(),
+            HeaderAuthExtractor::default(),
+        )
+    }
+
+    fn request(headers: &[(&str, &str)]) -> Request<Body> {
+        let mut req = Request::builder();
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        req.body(Body::empty()).unwrap()
+    }
+
+    /// First extractor with credentials present wins.
+    #[test]
+    fn stacked_order() {
+        let ext = stacked();
+        let basic = format!("Basic {}", B64.encode("human:pwd"));
+        let (user, pwd) = ext
+            .extract_auth(&request(&[
+                ("authorization", &basic),
+                ("x-api-name", "machine"),
+                ("x-api-key", "key"),
+            ]))
+            .unwrap();
+        assert_eq!((user.as_str(), pwd.as_str()), ("human", "pwd"));
+        let (user, key) = ext
+            .extract_auth(&request(&[("x-api-name", "machine"), ("x-api-key", "key")]))
+            .unwrap();
+        assert_eq!((user.as_str(), key.as_str()), ("machine", "key"));
+        assert!(matches!(
+            ext.extract_auth(&request(&[])),
+            Err(AuthError::NoAuthProvided)
+        ));
+        let (user, _) = ext
+            .extract_auth(&request(&[
+                ("authorization", "Bearer xyz"),
+                ("x-api-name", "machine"),
+                ("x-api-key", "key"),
+            ]))
+            .unwrap();
+        assert_eq!(user.as_str(), "machine");
+    }
+
+    /// Invalid credentials are not passed to the next extractor.
+    #[test]
+    fn stacked_invalid_short_circuit() {
+        let ext = stacked();
+        let res = ext.extract_auth(&request(&[
+            ("authorization", "Basic !!!"),
+            ("x-api-name", "machine"),
+            ("x-api-key", "key"),
+        ]));
+        assert!(res.is_err());
+        assert!(!matches!(res, Err(AuthError::NoAuthProvided)));
+    }
+
+    /// Schemes and headers of all extractors are merged.
+    #[test]
+    fn stacked_merge() {
+        let ext = stacked();
+        assert_eq!(
+            ext.security_schemes().keys().collect::<Vec<_>>(),
+            ["api-key", "api-name", "basic"]
+        );
+        assert_eq!(
+            ext.request_headers(),
+            ["authorization", "X-API-Name", "X-API-Key"]
+        );
+    }
+}
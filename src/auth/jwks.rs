@@ -0,0 +1,557 @@
+//! AAA - JSON Web Key Sets.
+//!
+//! Public keys of identity providers are fetched from a JWKS URL. Key set is refreshed in
+//! background, and also on demand when a token signed with an unknown key is seen.
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use aws_lc_rs::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as B64, Engine};
+use parking_lot::{Mutex, RwLock};
+use reqwest_middleware::ClientWithMiddleware;
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::{auth::token::TokenError, http_client::HttpClientConfig, metrics::ClientMetricsState};
+
+/// JSON Web Key Set configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct JwksConfig {
+    /// URL of key set.
+    pub url: Url,
+    /// Interval between background refreshes.
+    #[serde(
+        default = "JwksConfig::default_refresh_interval",
+        with = "humantime_serde"
+    )]
+    pub refresh_interval: Duration,
+    /// Minimum interval between on-demand refreshes, triggered by unknown key IDs.
+    #[serde(
+        default = "JwksConfig::default_min_refresh_interval",
+        with = "humantime_serde"
+    )]
+    pub min_refresh_interval: Duration,
+    /// Name of HTTP client used to fetch key set.
+    ///
+    /// Default client configuration is used if there is no client with this name.
+    #[serde(default = "JwksConfig::default_http_client")]
+    pub http_client: String,
+}
+
+impl JwksConfig {
+    /// Default value for [`Self::refresh_interval`].
+    #[must_use]
+    #[inline]
+    fn default_refresh_interval() -> Duration {
+        Duration::from_secs(300)
+    }
+
+    /// Default value for [`Self::min_refresh_interval`].
+    #[must_use]
+    #[inline]
+    fn default_min_refresh_interval() -> Duration {
+        Duration::from_secs(30)
+    }
+
+    /// Default value for [`Self::http_client`].
+    #[must_use]
+    #[inline]
+    fn default_http_client() -> String {
+        "jwks".into()
+    }
+
+    /// Create new configuration with a key set URL.
+    #[must_use]
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            refresh_interval: Self::default_refresh_interval(),
+            min_refresh_interval: Self::default_min_refresh_interval(),
+            http_client: Self::default_http_client(),
+        }
+    }
+}
+
+/// Key set document.
+#[derive(Debug, Deserialize)]
+struct JwkSet {
+    /// Published keys.
+    ///
+    /// Kept as raw values, so that a single unsupported key does not spoil the whole set.
+    keys: Vec<serde_json::Value>,
+}
+
+/// Single JSON Web Key.
+#[derive(Debug, Deserialize)]
+struct Jwk {
+    /// Key ID.
+    #[serde(default)]
+    kid: String,
+    /// Key type.
+    kty: String,
+    /// Intended use of key.
+    #[serde(default, rename = "use")]
+    usage: Option<String>,
+    /// Elliptic curve name.
+    #[serde(default)]
+    crv: Option<String>,
+    /// RSA modulus.
+    #[serde(default)]
+    n: Option<String>,
+    /// RSA public exponent.
+    #[serde(default)]
+    e: Option<String>,
+    /// Elliptic curve X coordinate, or Ed25519 public key.
+    #[serde(default)]
+    x: Option<String>,
+    /// Elliptic curve Y coordinate.
+    #[serde(default)]
+    y: Option<String>,
+}
+
+/// Public key used for token signature verification.
+#[derive(Debug)]
+pub(crate) enum PublicKey {
+    /// RSA public key components.
+    Rsa {
+        /// Modulus.
+        n: Vec<u8>,
+        /// Public exponent.
+        e: Vec<u8>,
+    },
+    /// NIST P-256 public key, as uncompressed point.
+    P256(Vec<u8>),
+    /// NIST P-384 public key, as uncompressed point.
+    P384(Vec<u8>),
+    /// Ed25519 public key.
+    Ed25519(Vec<u8>),
+}
+
+impl PublicKey {
+    /// Decode public key from JWK.
+    ///
+    /// Returns `None` for unsupported or malformed keys.
+    fn from_jwk(jwk: &Jwk) -> Option<Self> {
+        if jwk.usage.as_deref().is_some_and(|usage| usage != "sig") {
+            return None;
+        }
+        let decode = |value: &Option<String>| B64.decode(value.as_deref()?).ok();
+        let point = || {
+            let mut point = vec![0x04];
+            point.extend(decode(&jwk.x)?);
+            point.extend(decode(&jwk.y)?);
+            Some(point)
+        };
+        match (jwk.kty.as_str(), jwk.crv.as_deref()) {
+            ("RSA", _) => Some(Self::Rsa {
+                n: decode(&jwk.n)?,
+                e: decode(&jwk.e)?,
+            }),
+            ("EC", Some("P-256")) => point().map(Self::P256),
+            ("EC", Some("P-384")) => point().map(Self::P384),
+            ("OKP", Some("Ed25519")) => decode(&jwk.x).map(Self::Ed25519),
+            _ => None,
+        }
+    }
+
+    /// Verify token signature.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if algorithm does not match key type, or signature is invalid.
+    pub(crate) fn verify(&self, alg: &str, message: &[u8], sig: &[u8]) -> Result<(), TokenError> {
+        let verified = match (self, alg) {
+            (Self::Rsa { n, e }, alg) => {
+                let params = match alg {
+                    "RS256" => &signature::RSA_PKCS1_2048_8192_SHA256,
+                    "RS384" => &signature::RSA_PKCS1_2048_8192_SHA384,
+                    "RS512" => &signature::RSA_PKCS1_2048_8192_SHA512,
+                    "PS256" => &signature::RSA_PSS_2048_8192_SHA256,
+                    "PS384" => &signature::RSA_PSS_2048_8192_SHA384,
+                    "PS512" => &signature::RSA_PSS_2048_8192_SHA512,
+                    _ => return Err(TokenError::InvalidSignature),
+                };
+                RsaPublicKeyComponents { n, e }.verify(params, message, sig)
+            }
+            (Self::P256(point), "ES256") => {
+                UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point)
+                    .verify(message, sig)
+            }
+            (Self::P384(point), "ES384") => {
+                UnparsedPublicKey::new(&signature::ECDSA_P384_SHA384_FIXED, point)
+                    .verify(message, sig)
+            }
+            (Self::Ed25519(key), "EdDSA") => {
+                UnparsedPublicKey::new(&signature::ED25519, key).verify(message, sig)
+            }
+            _ => return Err(TokenError::InvalidSignature),
+        };
+        verified.map_err(|_| TokenError::InvalidSignature)
+    }
+}
+
+/// Cache of public keys fetched from JWKS URL.
+#[derive(Clone, Debug)]
+pub(crate) struct JwksCache(Arc<JwksCacheInner>);
+
+/// Inner struct for [`JwksCache`].
+#[derive(Debug)]
+struct JwksCacheInner {
+    /// Key set configuration.
+    config: JwksConfig,
+    /// HTTP client configuration.
+    client_config: HttpClientConfig,
+    /// HTTP client metrics.
+    client_metrics: Option<ClientMetricsState>,
+    /// HTTP client, built on first use.
+    client: OnceCell<ClientWithMiddleware>,
+    /// Last known good keys, by key ID.
+    keys: RwLock<HashMap<String, Arc<PublicKey>>>,
+    /// Time of last fetch attempt.
+    last_fetch: Mutex<Option<Instant>>,
+}
+
+impl JwksCache {
+    /// Create new empty cache.
+    #[must_use]
+    pub(crate) fn new(
+        config: JwksConfig,
+        client_config: HttpClientConfig,
+        client_metrics: Option<ClientMetricsState>,
+    ) -> Self {
+        Self(Arc::new(JwksCacheInner {
+            config,
+            client_config,
+            client_metrics,
+            client: OnceCell::new(),
+            keys: RwLock::new(HashMap::new()),
+            last_fetch: Mutex::new(None),
+        }))
+    }
+
+    /// Find key by its ID.
+    ///
+    /// Unknown key ID triggers a rate-limited refresh in background.
+    #[must_use]
+    pub(crate) fn key(&self, kid: &str) -> Option<Arc<PublicKey>> {
+        let key = self.0.keys.read().get(kid).cloned();
+        if key.is_none() {
+            self.request_refresh();
+        }
+        key
+    }
+
+    /// Start refresh in background, unless one was attempted recently.
+    fn request_refresh(&self) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        {
+            let mut last_fetch = self.0.last_fetch.lock();
+            if last_fetch.is_some_and(|last| last.elapsed() < self.0.config.min_refresh_interval) {
+                return;
+            }
+            *last_fetch = Some(Instant::now());
+        }
+        debug!(url = %self.0.config.url, "unknown key ID, refreshing JWKS");
+        let cache = self.clone();
+        runtime.spawn(async move { cache.refresh().await });
+    }
+
+    /// Fetch key set, replacing cached keys.
+    ///
+    /// Last known good keys are kept if fetch fails.
+    pub(crate) async fn refresh(&self) {
+        *self.0.last_fetch.lock() = Some(Instant::now());
+        match self.fetch().await {
+            Ok(keys) => {
+                info!(url = %self.0.config.url, keys = keys.len(), "fetched JWKS");
+                *self.0.keys.write() = keys;
+            }
+            Err(error) => {
+                warn!(url = %self.0.config.url, %error, "unable to refresh JWKS, keeping last known keys");
+            }
+        }
+    }
+
+    /// Refresh key set periodically.
+    pub(crate) async fn run(self) {
+        loop {
+            self.refresh().await;
+            tokio::time::sleep(self.0.config.refresh_interval).await;
+        }
+    }
+
+    /// Fetch and decode key set.
+    async fn fetch(&self) -> Result<HashMap<String, Arc<PublicKey>>, TokenError> {
+        let fetch_error = |err: &dyn std::fmt::Display| TokenError::KeySetFetch(err.to_string());
+        let client = self
+            .0
+            .client
+            .get_or_try_init(|| {
+                self.0
+                    .client_config
+                    .to_client(self.0.client_metrics.clone())
+            })
+            .await
+            .map_err(|err| fetch_error(&err))?;
+        let key_set: JwkSet = client
+            .get(self.0.config.url.clone())
+            .send()
+            .await
+            .map_err(|err| fetch_error(&err))?
+            .error_for_status()
+            .map_err(|err| fetch_error(&err))?
+            .json()
+            .await
+            .map_err(|err| fetch_error(&err))?;
+        Ok(key_set
+            .keys
+            .into_iter()
+            .filter_map(|raw| {
+                let jwk: Jwk = serde_json::from_value(raw).ok()?;
+                let key = PublicKey::from_jwk(&jwk);
+                if key.is_none() {
+                    debug!(kid = jwk.kid, kty = jwk.kty, "skipping unsupported JWK");
+                }
+                Some((jwk.kid, Arc::new(key?)))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use aws_lc_rs::{
+        rand::SystemRandom,
+        rsa::KeySize,
+        signature::{
+            EcdsaKeyPair, KeyPair, RsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING, RSA_PKCS1_SHA256,
+        },
+    };
+    use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+
+    use super::*;
+    use crate::auth::token::{unix_now, Audience, ServiceTokenConfig, TokenIssuer, TokenValidator};
+
+    /// Key set served by test identity provider.
+    #[derive(Clone, Default)]
+    struct Provider {
+        /// Published keys.
+        keys: Arc<Mutex<Vec<serde_json::Value>>>,
+        /// Respond with an error instead.
+        failing: Arc<AtomicBool>,
+    }
+
+    async fn serve_jwks(
+        State(provider): State<Provider>,
+    ) -> Result<Json<serde_json::Value>, StatusCode> {
+        match provider.failing.load(Ordering::Relaxed) {
+            true => Err(StatusCode::INTERNAL_SERVER_ERROR),
+            false => Ok(Json(
+                serde_json::json!({"keys": provider.keys.lock().clone()}),
+            )),
+        }
+    }
+
+    /// Generate ES256 key pair, along with its JWK.
+    fn key_pair(kid: &str) -> (EcdsaKeyPair, serde_json::Value) {
+        let pair = EcdsaKeyPair::generate(&ECDSA_P256_SHA256_FIXED_SIGNING).unwrap();
+        let point = pair.public_key().as_ref();
+        let jwk = serde_json::json!({
+            "kid": kid,
+            "kty": "EC",
+            "crv": "P-256",
+            "use": "sig",
+            "x": B64.encode(&point[1..33]),
+            "y": B64.encode(&point[33..]),
+        });
+        (pair, jwk)
+    }
+
+    /// Sign token using ES256.
+    fn es256(pair: &EcdsaKeyPair, kid: &str) -> String {
+        let header = serde_json::json!({"alg": "ES256", "typ": "JWT", "kid": kid});
+        let claims = serde_json::json!({
+            "iss": "idp",
+            "sub": "partner",
+            "iat": unix_now(),
+            "exp": unix_now() + 60,
+        });
+        let signing_input = format!(
+            "{}.{}",
+            B64.encode(header.to_string()),
+            B64.encode(claims.to_string())
+        );
+        let sig = pair
+            .sign(&SystemRandom::new(), signing_input.as_bytes())
+            .unwrap();
+        format!("{signing_input}.{}", B64.encode(sig.as_ref()))
+    }
+
+    /// Split DER-encoded `RSAPublicKey` into modulus and exponent.
+    fn der_integers(der: &[u8]) -> Vec<&[u8]> {
+        let read = |buf: &[u8]| -> (usize, usize) {
+            match buf[1] {
+                len @ 0..=0x7f => (2, len.into()),
+                prefix => {
+                    let octets = usize::from(prefix & 0x7f);
+                    let len = buf[2..2 + octets]
+                        .iter()
+                        .fold(0, |len, b| (len << 8) | usize::from(*b));
+                    (2 + octets, len)
+                }
+            }
+        };
+        let (offset, _) = read(der);
+        let mut rest = &der[offset..];
+        let mut items = Vec::new();
+        while !rest.is_empty() {
+            let (offset, len) = read(rest);
+            let value = &rest[offset..offset + len];
+            items.push(value.strip_prefix(&[0]).unwrap_or(value));
+            rest = &rest[offset + len..];
+        }
+        items
+    }
+
+    /// Tokens signed using RS256 with an array audience claim are accepted.
+    #[tokio::test]
+    async fn rs256_audience_array() {
+        let pair = RsaKeyPair::generate(KeySize::Rsa2048).unwrap();
+        let der = der_integers(pair.public_key().as_ref());
+        let provider = Provider::default();
+        provider.keys.lock().push(serde_json::json!({
+            "kid": "r1",
+            "kty": "RSA",
+            "use": "sig",
+            "n": B64.encode(der[0]),
+            "e": B64.encode(der[1]),
+        }));
+        let app = Router::new()
+            .route("/jwks", get(serve_jwks))
+            .with_state(provider);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut cfg = ServiceTokenConfig::from_jwks(JwksConfig::new(
+            format!("http://{addr}/jwks").parse().unwrap(),
+        ));
+        cfg.audience = Some("backend".into());
+        let validator = TokenValidator::from(&cfg);
+        validator.jwks().unwrap().refresh().await;
+
+        let sign = |aud: serde_json::Value| {
+            let header = serde_json::json!({"alg": "RS256", "typ": "JWT", "kid": "r1"});
+            let claims = serde_json::json!({
+                "iss": "idp",
+                "sub": "partner",
+                "aud": aud,
+                "exp": unix_now() + 60,
+            });
+            let signing_input = format!(
+                "{}.{}",
+                B64.encode(header.to_string()),
+                B64.encode(claims.to_string())
+            );
+            let mut sig = vec![0; pair.public_modulus_len()];
+            pair.sign(
+                &RSA_PKCS1_SHA256,
+                &SystemRandom::new(),
+                signing_input.as_bytes(),
+                &mut sig,
+            )
+            .unwrap();
+            format!("{signing_input}.{}", B64.encode(sig))
+        };
+        let claims = validator
+            .validate(&sign(serde_json::json!(["gateway", "backend"])))
+            .unwrap();
+        assert_eq!(claims.sub, "partner");
+        assert_eq!(claims.iat, None);
+        assert_eq!(
+            claims.aud,
+            Some(Audience::Multiple(vec!["gateway".into(), "backend".into()]))
+        );
+        assert_eq!(
+            validator.validate(&sign(serde_json::json!(["gateway", "other"]))),
+            Err(TokenError::InvalidAudience)
+        );
+    }
+
+    /// Shared secret tokens are rejected when key set is configured, unless explicitly allowed.
+    #[tokio::test]
+    async fn hs256_with_jwks() {
+        let mut cfg = ServiceTokenConfig::new("s3cr3t");
+        cfg.jwks = Some(JwksConfig::new("http://127.0.0.1:9/jwks".parse().unwrap()));
+        let token = TokenIssuer::new(cfg.clone(), Some("frontend"))
+            .unwrap()
+            .issue(None);
+        assert_eq!(
+            TokenValidator::from(&cfg).validate(&token),
+            Err(TokenError::InvalidSignature)
+        );
+
+        cfg.allow_hs256_with_jwks = true;
+        let claims = TokenValidator::from(&cfg).validate(&token).unwrap();
+        assert_eq!(claims.sub, "frontend");
+    }
+
+    /// Keys are fetched, rotated on demand, and kept when refresh fails.
+    #[tokio::test]
+    async fn rotation() {
+        let provider = Provider::default();
+        let app = Router::new()
+            .route("/jwks", get(serve_jwks))
+            .with_state(provider.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let (k1, jwk1) = key_pair("k1");
+        let (k2, jwk2) = key_pair("k2");
+        provider.keys.lock().push(jwk1);
+        let mut jwks_cfg = JwksConfig::new(format!("http://{addr}/jwks").parse().unwrap());
+        jwks_cfg.min_refresh_interval = Duration::ZERO;
+        let cfg = ServiceTokenConfig::from_jwks(jwks_cfg);
+        let validator = TokenValidator::from(&cfg);
+        let cache = validator.jwks().unwrap().clone();
+        cache.refresh().await;
+
+        let claims = validator.validate(&es256(&k1, "k1")).unwrap();
+        assert_eq!(claims.sub, "partner");
+        let mut forged = es256(&k1, "k1");
+        forged.truncate(forged.len() - 4);
+        forged.push_str("AAAA");
+        assert!(validator.validate(&forged).is_err());
+        assert!(validator.validate(&es256(&k2, "k1")).is_err());
+
+        // Rotation: unknown key triggers refresh.
+        *provider.keys.lock() = vec![jwk2];
+        let token = es256(&k2, "k2");
+        assert_eq!(
+            validator.validate(&token),
+            Err(TokenError::UnknownKey("k2".into()))
+        );
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while validator.validate(&token).is_err() {
+            assert!(Instant::now() < deadline, "key set was not refreshed");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        // Failed refresh keeps last known keys.
+        provider.failing.store(true, Ordering::Relaxed);
+        cache.refresh().await;
+        assert!(validator.validate(&token).is_ok());
+    }
+}
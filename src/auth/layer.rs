@@ -1,27 +1,28 @@
 //! Authentication and authorization [`tower`] layer and service.
 
 use std::{
+    any::Any,
     borrow::Borrow,
-    future::Future,
     marker::PhantomData,
+    mem,
     ops::Deref,
-    pin::Pin,
     sync::Arc,
-    task::{ready, Context, Poll},
+    task::{Context, Poll},
 };
 
 use axum::{
     body::Body,
-    http::{Request, Response, StatusCode},
-    response::IntoResponse,
+    http::{Request, Response},
 };
-use pin_project::pin_project;
+use futures::future::{self, BoxFuture};
 use tower::{BoxError, Layer, Service};
-use tracing::{trace_span, warn};
+use tracing::{trace_span, warn, Instrument};
 
 use crate::auth::{
+    errors::AuthError,
     extractor::{AuthExtractor, NoOpAuthExtractor},
     provider::{AuthProvider, NoOpAuthProvider},
+    user::{CurrentUser, UserId, CURRENT_USER_ID},
 };
 
 /// Authentication and authorization [`tower`] layer.
@@ -103,16 +104,17 @@ pub struct AuthService<S, AuthProv, AuthExt> {
 
 impl<S, AuthProv, AuthExt> Service<Request<Body>> for AuthService<S, AuthProv, AuthExt>
 where
-    S: Service<Request<Body>, Response = Response<Body>>,
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
     S::Error: Into<BoxError>,
-    AuthProv: AuthProvider,
-    AuthExt: AuthExtractor,
+    S::Future: Send + 'static,
+    AuthProv: AuthProvider + 'static,
+    AuthExt: AuthExtractor + 'static,
     AuthExt::User: Borrow<AuthProv::User>,
     AuthExt::AuthTokens: Borrow<AuthProv::AuthTokens>,
 {
     type Response = S::Response;
     type Error = BoxError;
-    type Future = AuthFuture<S::Future>;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         match self.inner.poll_ready(cx) {
@@ -122,77 +124,79 @@ where
     }
 
     fn call(&mut self, mut req: Request<Body>) -> Self::Future {
-        let span = trace_span!("auth").entered();
+        let span = trace_span!("auth");
         // Extract user and/or auth tokens from request.
-        let (user, tokens) = match self.auth_extractor.extract_auth(&req) {
+        let (user, tokens) = match span.in_scope(|| self.auth_extractor.extract_auth(&req)) {
             Ok(pair) => pair,
             Err(error) => {
-                warn!(cause = %error, "auth extraction error");
-                return AuthFuture::Negative {
-                    error_response: Some(self.auth_extractor.error_response(error)),
-                };
+                span.in_scope(|| warn!(cause = %error, "auth extraction error"));
+                return Box::pin(future::ready(Ok(self.auth_extractor.error_response(error))));
             }
         };
-        // Authenticate user.
-        if let Err(error) = self
-            .auth_provider
-            .authenticate(user.borrow(), tokens.borrow())
-        {
-            warn!(cause = %error, "authentication error");
-            return AuthFuture::Negative {
-                error_response: Some(self.auth_extractor.error_response(error)),
-            };
-        }
-        // Authorize request.
-        for perm in self.permissions {
-            if let Err(error) = self.auth_provider.authorize(user.borrow(), perm) {
-                warn!(cause = %error, "authorization error");
-                return AuthFuture::Negative {
-                    error_response: Some(self.auth_extractor.error_response(error)),
-                };
+        let permissions = self.permissions;
+        let auth_provider = self.auth_provider.clone();
+        let auth_extractor = self.auth_extractor.clone();
+        // Use the service that was driven to readiness, leaving a fresh clone in its place.
+        let clone = self.inner.clone();
+        let mut inner = mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let checked: Result<(), AuthError> = async {
+                // Authenticate user.
+                auth_provider
+                    .authenticate(user.borrow(), tokens.borrow())
+                    .await
+                    .inspect_err(|error| warn!(cause = %error, "authentication error"))?;
+                // Authorize request.
+                for perm in permissions {
+                    auth_provider
+                        .authorize(user.borrow(), tokens.borrow(), perm)
+                        .await
+                        .inspect_err(|error| warn!(cause = %error, "authorization error"))?;
+                }
+                Ok(())
             }
-        }
-        // Add user ID as an extension into request.
-        req.extensions_mut().insert(user);
-        drop(span);
-        AuthFuture::Positive {
-            inner: self.inner.call(req),
-        }
+            .instrument(span)
+            .await;
+            if let Err(error) = checked {
+                return Ok(auth_extractor.error_response(error));
+            }
+            let roles = auth_provider.roles(user.borrow(), tokens.borrow()).await;
+            // Record user ID for use in outgoing requests.
+            let user_id = user_id_of(&user);
+            // Add user ID, caller identity and user object as extensions into request.
+            if let Some(user_id) = &user_id {
+                req.extensions_mut().insert(user_id.clone());
+            }
+            req.extensions_mut().insert(CurrentUser {
+                id: user_id.clone(),
+                roles,
+                permissions,
+            });
+            req.extensions_mut().insert(user);
+            let mut resp = CURRENT_USER_ID
+                .scope(user_id.clone(), inner.call(req))
+                .await
+                .map_err(Into::into)?;
+            // Authenticated user ID is added to response extensions for use in outer layers.
+            if let Some(user_id) = user_id {
+                resp.extensions_mut().insert(user_id);
+            }
+            Ok(resp)
+        })
     }
 }
 
-/// Authentication and authorization [`tower`] service future.
-#[pin_project(project = ProjectedOutcome)]
-pub enum AuthFuture<F> {
-    /// Happy path, calling inner service.
-    Positive {
-        /// Inner future.
-        #[pin]
-        inner: F,
-    },
-    /// Authentication error or failure.
-    Negative {
-        /// Preformatted negative HTTP response.
-        error_response: Option<Response<Body>>,
-    },
-}
-
-impl<F, E> Future for AuthFuture<F>
-where
-    F: Future<Output = Result<Response<Body>, E>>,
-    E: Into<BoxError>,
-{
-    type Output = Result<Response<Body>, BoxError>;
-
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        match self.project() {
-            ProjectedOutcome::Positive { inner } => {
-                let resp = ready!(inner.poll(cx).map_err(Into::into))?;
-                Poll::Ready(Ok(resp))
-            }
-            ProjectedOutcome::Negative { error_response } => Poll::Ready(Ok(error_response
-                .take()
-                .unwrap_or_else(|| StatusCode::INTERNAL_SERVER_ERROR.into_response()))),
-        }
+/// Get user ID from extracted user object, if it is of a known type.
+fn user_id_of(user: &dyn Any) -> Option<UserId> {
+    if let Some(user_id) = user.downcast_ref::<UserId>() {
+        return Some(user_id.clone());
+    }
+    if let Some(key_user) = user.downcast_ref::<crate::auth::ApiKeyUser>() {
+        return Some(key_user.id().clone());
+    }
+    #[cfg(feature = "oidc")]
+    if let Some(session) = user.downcast_ref::<crate::auth::SessionUser>() {
+        return Some(session.id().clone());
     }
+    None
 }
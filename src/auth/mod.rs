@@ -1,17 +1,43 @@
 //! Authentication and authorization system.
 
+mod api_key;
+mod bearer;
 mod config;
 mod errors;
 mod extractor;
+mod jwks;
 mod layer;
+#[cfg(feature = "oidc")]
+mod oidc;
 mod provider;
+mod token;
 mod user;
 
+#[cfg(feature = "oidc")]
+pub(crate) use self::oidc::OidcState;
+#[cfg(feature = "oidc")]
+pub use self::oidc::{
+    OidcConfig, OidcError, SessionAuthExtractor, SessionAuthProvider, SessionUser,
+};
 pub use self::{
-    config::{AuthConfig, RoleConfig, UserConfig, UserPassword},
-    errors::AuthError,
-    extractor::{AuthExtractor, BasicAuthExtractor, HeaderAuthExtractor, NoOpAuthExtractor},
+    api_key::{
+        ApiKeyAuthExtractor, ApiKeyAuthProvider, ApiKeyConfig, ApiKeyError, ApiKeyInfo,
+        ApiKeyToken, ApiKeyUser, ApiKeys,
+    },
+    bearer::{BearerAuthExtractor, BearerAuthProvider, BearerAuthToken},
+    config::{AuthConfig, BearerToken, BearerTokenConfig, RoleConfig, UserConfig, UserPassword},
+    errors::{AuthError, AuthSetupError},
+    extractor::{
+        AuthExtractor, BasicAuthExtractor, HeaderAuthExtractor, NoOpAuthExtractor,
+        StackedAuthExtractor,
+    },
+    jwks::JwksConfig,
     layer::AuthLayer,
     provider::{AuthProvider, ConfigAuthProvider, NoOpAuthProvider},
-    user::UserId,
+    token::{
+        ActorClaim, Audience, ClaimMappingConfig, ServiceClaims, ServiceTokenAuthExtractor,
+        ServiceTokenAuthProvider, ServiceTokenConfig, TokenError, TokenGrants, TokenIssuer,
+        TokenValidator,
+    },
+    user::{CurrentUser, UserId, CURRENT_USER_ID},
 };
@@ -0,0 +1,1179 @@
+//! AAA - OpenID Connect login for browser users.
+//!
+//! Implements relying party side of authorization code flow with PKCE. After successful login
+//! the user gets a signed session cookie, carrying user ID and roles mapped from ID token
+//! claims. Session cookie is then validated by [`SessionAuthExtractor`], and roles are checked
+//! against built-in role database by [`SessionAuthProvider`].
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Deref,
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{
+        header::{COOKIE, LOCATION, SET_COOKIE},
+        HeaderMap, HeaderValue, Request, Response, StatusCode,
+    },
+    response::IntoResponse,
+    routing, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as B64, Engine};
+use crypto::{digest::Digest, sha2::Sha256, util::fixed_time_eq};
+use okapi::{openapi3, Map};
+use rand::RngCore;
+use reqwest_middleware::ClientWithMiddleware;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use tokio::sync::OnceCell;
+use tracing::{debug_span, info, warn};
+use url::Url;
+
+use crate::{
+    auth::{
+        config::RoleConfig,
+        errors::AuthError,
+        extractor::AuthExtractor,
+        provider::AuthProvider,
+        token::{sign, unix_now},
+        user::UserId,
+    },
+    errors::{codes, ErrorCode},
+    http_client::{HttpClientConfig, HttpClientError},
+    metrics::ClientMetricsState,
+    secret::SecretValue,
+};
+
+/// Error type used in OpenID Connect login flow.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum OidcError {
+    /// Invalid OpenID Connect configuration.
+    #[error("Invalid OpenID Connect configuration: {0}")]
+    Config(&'static str),
+    /// Error building HTTP client.
+    #[error(transparent)]
+    HttpClient(#[from] HttpClientError),
+    /// Request to OpenID provider failed.
+    #[error("OpenID provider request failed: {0}")]
+    Provider(String),
+    /// Login state cookie is missing, invalid or expired.
+    #[error("Login state is missing or expired")]
+    MissingState,
+    /// Login state returned by OpenID provider does not match the one in cookie.
+    #[error("Login state mismatch")]
+    StateMismatch,
+    /// OpenID provider returned an error.
+    #[error("Authorization failed: {0}")]
+    Authorization(String),
+    /// ID token is malformed or does not pass validation.
+    #[error("Invalid ID token: {0}")]
+    InvalidIdToken(&'static str),
+}
+
+impl OidcError {
+    /// HTTP status code for used for this error.
+    fn http_status(&self) -> StatusCode {
+        match self {
+            Self::Config(_) | Self::HttpClient(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Provider(_) => StatusCode::BAD_GATEWAY,
+            Self::MissingState | Self::StateMismatch | Self::Authorization(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            Self::InvalidIdToken(_) => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Config(_) | Self::HttpClient(_) => codes::OIDC_MISCONFIGURED,
+            Self::Provider(_) => codes::OIDC_PROVIDER,
+            Self::MissingState | Self::StateMismatch => codes::OIDC_INVALID_STATE,
+            Self::Authorization(_) => codes::OIDC_AUTHORIZATION,
+            Self::InvalidIdToken(_) => codes::OIDC_INVALID_TOKEN,
+        }
+    }
+}
+
+impl From<reqwest_middleware::Error> for OidcError {
+    fn from(value: reqwest_middleware::Error) -> Self {
+        Self::Provider(value.to_string())
+    }
+}
+
+impl From<reqwest::Error> for OidcError {
+    fn from(value: reqwest::Error) -> Self {
+        Self::Provider(value.to_string())
+    }
+}
+
+impl IntoResponse for OidcError {
+    fn into_response(self) -> Response<Body> {
+        let code = self.code();
+        let problem = code
+            .problem(self.http_status())
+            .with_type("tag:uxum.github.io,2024:oidc")
+            .with_title(self.to_string());
+        (code, problem).into_response()
+    }
+}
+
+/// OpenID Connect login configuration.
+///
+/// Compiled in with `oidc` crate feature, and enabled at runtime by adding
+/// [`crate::AuthConfig::oidc`] section. Adds login and callback endpoints, and protects API
+/// documentation and management endpoints with session cookie authentication. Regular API
+/// handlers keep using authentication scheme set up in [`crate::AppBuilder`].
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct OidcConfig {
+    /// OpenID provider discovery document URL.
+    ///
+    /// Usually this is `<issuer>/.well-known/openid-configuration`.
+    pub discovery_url: Url,
+    /// Client ID, registered with OpenID provider.
+    pub client_id: String,
+    /// Client secret, if this is a confidential client.
+    ///
+    /// May be read from environment variable or file, see [`SecretValue`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<SecretValue>,
+    /// Absolute URL of callback endpoint, registered with OpenID provider.
+    ///
+    /// Callback endpoint is mounted on the path part of this URL.
+    pub redirect_url: Url,
+    /// URL path of login endpoint.
+    #[serde(default = "OidcConfig::default_login_path")]
+    pub login_path: String,
+    /// Requested scopes.
+    #[serde(default = "OidcConfig::default_scopes")]
+    pub scopes: Vec<String>,
+    /// Name of HTTP client used to talk to OpenID provider.
+    ///
+    /// Default client configuration is used if there is no client with this name.
+    #[serde(default = "OidcConfig::default_http_client")]
+    pub http_client: String,
+    /// ID token claim used as user ID.
+    #[serde(default = "OidcConfig::default_user_claim")]
+    pub user_claim: String,
+    /// ID token claim containing user roles.
+    ///
+    /// Nested claims can be addressed using dots, as in `realm_access.roles`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roles_claim: Option<String>,
+    /// Mapping from role claim values to role names.
+    ///
+    /// If empty, role claim values are used as role names as is. Otherwise unmapped values are
+    /// ignored.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub role_mapping: BTreeMap<String, String>,
+    /// Roles granted to all logged in users.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub default_roles: BTreeSet<String>,
+    /// Secret key used to sign session and login state cookies.
+    ///
+    /// May be read from environment variable or file, see [`SecretValue`].
+    pub session_secret: SecretValue,
+    /// Session cookie name.
+    #[serde(default = "OidcConfig::default_session_cookie")]
+    pub session_cookie: String,
+    /// Session lifetime.
+    #[serde(default = "OidcConfig::default_session_ttl", with = "humantime_serde")]
+    pub session_ttl: Duration,
+    /// Maximum time between start of login and callback from OpenID provider.
+    #[serde(default = "OidcConfig::default_login_ttl", with = "humantime_serde")]
+    pub login_ttl: Duration,
+    /// Allowed clock skew when validating ID tokens.
+    #[serde(default = "OidcConfig::default_leeway", with = "humantime_serde")]
+    pub leeway: Duration,
+    /// Add `Secure` attribute to cookies.
+    #[serde(default = "crate::util::default_true")]
+    pub secure_cookies: bool,
+    /// Where to redirect after login, if not requested otherwise.
+    #[serde(default = "OidcConfig::default_post_login_redirect")]
+    pub post_login_redirect: String,
+    /// Require session authentication for API documentation.
+    #[serde(default = "crate::util::default_true")]
+    pub protect_api_doc: bool,
+    /// Require session authentication for management endpoints.
+    #[serde(default = "crate::util::default_true")]
+    pub protect_management: bool,
+}
+
+impl OidcConfig {
+    /// Default value for [`Self::login_path`].
+    #[must_use]
+    #[inline]
+    fn default_login_path() -> String {
+        "/auth/oidc/login".into()
+    }
+
+    /// Default value for [`Self::scopes`].
+    #[must_use]
+    #[inline]
+    fn default_scopes() -> Vec<String> {
+        vec!["openid".into()]
+    }
+
+    /// Default value for [`Self::http_client`].
+    #[must_use]
+    #[inline]
+    fn default_http_client() -> String {
+        "oidc".into()
+    }
+
+    /// Default value for [`Self::user_claim`].
+    #[must_use]
+    #[inline]
+    fn default_user_claim() -> String {
+        "sub".into()
+    }
+
+    /// Default value for [`Self::session_cookie`].
+    #[must_use]
+    #[inline]
+    fn default_session_cookie() -> String {
+        "uxum_session".into()
+    }
+
+    /// Default value for [`Self::session_ttl`].
+    #[must_use]
+    #[inline]
+    fn default_session_ttl() -> Duration {
+        Duration::from_secs(8 * 3600)
+    }
+
+    /// Default value for [`Self::login_ttl`].
+    #[must_use]
+    #[inline]
+    fn default_login_ttl() -> Duration {
+        Duration::from_secs(600)
+    }
+
+    /// Default value for [`Self::leeway`].
+    #[must_use]
+    #[inline]
+    fn default_leeway() -> Duration {
+        Duration::from_secs(30)
+    }
+
+    /// Default value for [`Self::post_login_redirect`].
+    #[must_use]
+    #[inline]
+    fn default_post_login_redirect() -> String {
+        "/".into()
+    }
+
+    /// Create new configuration.
+    #[must_use]
+    pub fn new(
+        discovery_url: Url,
+        client_id: impl ToString,
+        redirect_url: Url,
+        session_secret: impl Into<SecretValue>,
+    ) -> Self {
+        Self {
+            discovery_url,
+            client_id: client_id.to_string(),
+            client_secret: None,
+            redirect_url,
+            login_path: Self::default_login_path(),
+            scopes: Self::default_scopes(),
+            http_client: Self::default_http_client(),
+            user_claim: Self::default_user_claim(),
+            roles_claim: None,
+            role_mapping: BTreeMap::new(),
+            default_roles: BTreeSet::new(),
+            session_secret: session_secret.into(),
+            session_cookie: Self::default_session_cookie(),
+            session_ttl: Self::default_session_ttl(),
+            login_ttl: Self::default_login_ttl(),
+            leeway: Self::default_leeway(),
+            secure_cookies: true,
+            post_login_redirect: Self::default_post_login_redirect(),
+            protect_api_doc: true,
+            protect_management: true,
+        }
+    }
+
+    /// Set client secret.
+    #[must_use]
+    pub fn with_client_secret(mut self, secret: impl Into<SecretValue>) -> Self {
+        self.client_secret = Some(secret.into());
+        self
+    }
+
+    /// Set ID token claim containing user roles.
+    #[must_use]
+    pub fn with_roles_claim(mut self, claim: impl ToString) -> Self {
+        self.roles_claim = Some(claim.to_string());
+        self
+    }
+
+    /// Map role claim value to role name.
+    #[must_use]
+    pub fn with_role_mapping(mut self, value: impl ToString, role: impl ToString) -> Self {
+        self.role_mapping
+            .insert(value.to_string(), role.to_string());
+        self
+    }
+
+    /// Set session lifetime.
+    #[must_use]
+    pub fn with_session_ttl(mut self, ttl: Duration) -> Self {
+        self.session_ttl = ttl;
+        self
+    }
+
+    /// Add or remove `Secure` attribute on cookies.
+    #[must_use]
+    pub fn with_secure_cookies(mut self, secure: bool) -> Self {
+        self.secure_cookies = secure;
+        self
+    }
+
+    /// Map claim values to role names.
+    fn map_roles(&self, claims: &Value) -> BTreeSet<String> {
+        let mut roles = self.default_roles.clone();
+        let Some(value) = self
+            .roles_claim
+            .as_deref()
+            .and_then(|path| claim(claims, path))
+        else {
+            return roles;
+        };
+        let values: Vec<&str> = match value {
+            Value::String(val) => vec![val.as_str()],
+            Value::Array(vals) => vals.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        for val in values {
+            if self.role_mapping.is_empty() {
+                roles.insert(val.to_owned());
+            } else if let Some(role) = self.role_mapping.get(val) {
+                roles.insert(role.clone());
+            }
+        }
+        roles
+    }
+}
+
+/// Look up claim by dotted path.
+fn claim<'a>(claims: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(claims, |value, key| value.as_object()?.get(key))
+}
+
+/// Generate random URL-safe string.
+fn random_token() -> String {
+    let mut buf = [0; 32];
+    rand::thread_rng().fill_bytes(&mut buf);
+    B64.encode(buf)
+}
+
+/// PKCE code challenge for a verifier, using `S256` method.
+fn code_challenge(verifier: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input_str(verifier);
+    let mut digest = [0; 32];
+    hasher.result(&mut digest);
+    B64.encode(digest)
+}
+
+/// Find cookie value in request headers.
+fn get_cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|val| val.to_str().ok())
+        .flat_map(|val| val.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find_map(|(key, val)| (key == name).then_some(val))
+}
+
+/// Signer and validator of cookie values.
+#[derive(Clone, Debug)]
+struct CookieSealer {
+    /// Signing key.
+    key: Arc<[u8]>,
+}
+
+impl CookieSealer {
+    /// Serialize and sign value.
+    ///
+    /// Purpose string is included in signature, so values can not be reused between cookies.
+    fn seal(&self, purpose: &str, value: &impl Serialize) -> String {
+        let payload = B64.encode(serde_json::to_vec(value).unwrap_or_default());
+        let signature = sign(&self.key, format!("{purpose}.{payload}").as_bytes());
+        format!("{payload}.{}", B64.encode(signature))
+    }
+
+    /// Validate signature and deserialize value.
+    fn open<T: DeserializeOwned>(&self, purpose: &str, sealed: &str) -> Option<T> {
+        let (payload, signature) = sealed.split_once('.')?;
+        let expected = sign(&self.key, format!("{purpose}.{payload}").as_bytes());
+        if !fixed_time_eq(&B64.decode(signature).ok()?, &expected) {
+            return None;
+        }
+        serde_json::from_slice(&B64.decode(payload).ok()?).ok()
+    }
+}
+
+/// Login state, stored in a short-lived cookie between login and callback.
+#[derive(Debug, Deserialize, Serialize)]
+struct LoginState {
+    /// Anti-CSRF state value.
+    state: String,
+    /// PKCE code verifier.
+    verifier: String,
+    /// ID token replay protection value.
+    nonce: String,
+    /// Where to redirect after login.
+    return_to: String,
+    /// Expiration time, in seconds since UNIX epoch.
+    exp: u64,
+}
+
+/// Session data, stored in session cookie.
+#[derive(Debug, Deserialize, Serialize)]
+struct SessionData {
+    /// User ID.
+    sub: String,
+    /// Granted roles.
+    roles: BTreeSet<String>,
+    /// Expiration time, in seconds since UNIX epoch.
+    exp: u64,
+}
+
+/// Cookie purpose for login state.
+const LOGIN_PURPOSE: &str = "login";
+
+/// Cookie purpose for session.
+const SESSION_PURPOSE: &str = "session";
+
+/// Authenticated session user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionUser {
+    /// User ID.
+    id: UserId,
+    /// Granted roles.
+    roles: BTreeSet<String>,
+}
+
+impl SessionUser {
+    /// Get user ID.
+    #[must_use]
+    pub fn id(&self) -> &UserId {
+        &self.id
+    }
+
+    /// Get granted roles.
+    #[must_use]
+    pub fn roles(&self) -> &BTreeSet<String> {
+        &self.roles
+    }
+}
+
+/// Authentication extractor (front-end) for session cookies.
+///
+/// Unauthenticated requests are redirected to login endpoint.
+#[derive(Clone, Debug)]
+pub struct SessionAuthExtractor {
+    /// Session cookie name.
+    cookie: Arc<str>,
+    /// Login endpoint path.
+    login_path: Arc<str>,
+    /// Cookie validator.
+    sealer: CookieSealer,
+}
+
+impl AuthExtractor for SessionAuthExtractor {
+    type User = SessionUser;
+    type AuthTokens = ();
+
+    fn extract_auth(
+        &self,
+        req: &Request<Body>,
+    ) -> Result<(Self::User, Self::AuthTokens), AuthError> {
+        let cookie = get_cookie(req.headers(), &self.cookie).ok_or(AuthError::NoAuthProvided)?;
+        let session: SessionData = self
+            .sealer
+            .open(SESSION_PURPOSE, cookie)
+            .ok_or(AuthError::AuthFailed)?;
+        if session.exp <= unix_now() {
+            return Err(AuthError::SessionExpired);
+        }
+        let user = SessionUser {
+            id: session.sub.into(),
+            roles: session.roles,
+        };
+        Ok((user, ()))
+    }
+
+    fn error_response(&self, err: AuthError) -> Response<Body> {
+        match err {
+            AuthError::NoPermission(_) => err.problem_response(StatusCode::FORBIDDEN),
+            _ => match HeaderValue::from_str(&self.login_path) {
+                Ok(location) => (StatusCode::SEE_OTHER, [(LOCATION, location)]).into_response(),
+                Err(_) => StatusCode::UNAUTHORIZED.into_response(),
+            },
+        }
+    }
+
+    fn security_schemes(&self) -> BTreeMap<String, openapi3::SecurityScheme> {
+        maplit::btreemap! {
+            "session".into() => openapi3::SecurityScheme {
+                description: Some("Session cookie, obtained via OpenID Connect login".into()),
+                data: openapi3::SecuritySchemeData::ApiKey {
+                    name: self.cookie.to_string(),
+                    location: "cookie".into(),
+                },
+                extensions: Map::default(),
+            },
+        }
+    }
+
+    fn request_headers(&self) -> Vec<String> {
+        vec![COOKIE.to_string()]
+    }
+}
+
+/// Authentication provider (back-end) for session users.
+///
+/// Session is validated by [`SessionAuthExtractor`], so this only checks permissions of granted
+/// roles using built-in role database.
+#[derive(Clone, Debug)]
+pub struct SessionAuthProvider {
+    /// Role dictionary.
+    roles: Arc<BTreeMap<String, RoleConfig>>,
+}
+
+#[async_trait]
+impl AuthProvider for SessionAuthProvider {
+    type User = SessionUser;
+    type AuthTokens = ();
+
+    async fn authenticate(
+        &self,
+        _user: &Self::User,
+        _tokens: &Self::AuthTokens,
+    ) -> Result<(), AuthError> {
+        Ok(())
+    }
+
+    async fn authorize(
+        &self,
+        user: &Self::User,
+        _tokens: &Self::AuthTokens,
+        permission: &'static str,
+    ) -> Result<(), AuthError> {
+        let permitted = user
+            .roles
+            .iter()
+            .filter_map(|role| self.roles.get(role))
+            .any(|role| role.super_user || role.permissions.contains(permission));
+        match permitted {
+            true => Ok(()),
+            false => Err(AuthError::NoPermission(permission)),
+        }
+    }
+
+    async fn roles(&self, user: &Self::User, _tokens: &Self::AuthTokens) -> BTreeSet<String> {
+        user.roles.clone()
+    }
+}
+
+/// Relevant parts of OpenID provider discovery document.
+#[derive(Clone, Debug, Deserialize)]
+struct Discovery {
+    /// Issuer identifier.
+    issuer: String,
+    /// Authorization endpoint URL.
+    authorization_endpoint: Url,
+    /// Token endpoint URL.
+    token_endpoint: Url,
+}
+
+/// Token endpoint response.
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    /// ID token.
+    id_token: String,
+}
+
+/// Callback query parameters.
+#[derive(Debug, Deserialize)]
+struct CallbackParams {
+    /// Authorization code.
+    code: Option<String>,
+    /// Anti-CSRF state value.
+    state: Option<String>,
+    /// Error code.
+    error: Option<String>,
+    /// Error description.
+    error_description: Option<String>,
+}
+
+/// Login query parameters.
+#[derive(Debug, Deserialize)]
+struct LoginParams {
+    /// Where to redirect after login.
+    return_to: Option<String>,
+}
+
+/// Shared state of OpenID Connect login flow.
+#[derive(Clone, Debug)]
+pub(crate) struct OidcState(Arc<OidcStateInner>);
+
+impl Deref for OidcState {
+    type Target = OidcStateInner;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Inner state of OpenID Connect login flow.
+#[derive(Debug)]
+pub(crate) struct OidcStateInner {
+    /// Login configuration.
+    config: OidcConfig,
+    /// HTTP client configuration.
+    client_config: HttpClientConfig,
+    /// HTTP client metrics.
+    client_metrics: Option<ClientMetricsState>,
+    /// HTTP client, created on first use.
+    client: OnceCell<ClientWithMiddleware>,
+    /// Discovery document, fetched on first use.
+    discovery: OnceCell<Discovery>,
+    /// Cookie signer.
+    sealer: CookieSealer,
+    /// Role dictionary.
+    roles: Arc<BTreeMap<String, RoleConfig>>,
+}
+
+impl OidcState {
+    /// Create new login flow state.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if configuration is invalid.
+    pub(crate) fn new(
+        config: OidcConfig,
+        client_config: HttpClientConfig,
+        client_metrics: Option<ClientMetricsState>,
+        roles: &BTreeMap<String, RoleConfig>,
+    ) -> Result<Self, OidcError> {
+        if config.session_secret.expose().is_empty() {
+            return Err(OidcError::Config("session secret is empty"));
+        }
+        if !config.login_path.starts_with('/') {
+            return Err(OidcError::Config("login path must start with a slash"));
+        }
+        if config.redirect_url.cannot_be_a_base() {
+            return Err(OidcError::Config("redirect URL must be absolute"));
+        }
+        let sealer = CookieSealer {
+            key: config.session_secret.expose().as_bytes().into(),
+        };
+        Ok(Self(Arc::new(OidcStateInner {
+            config,
+            client_config,
+            client_metrics,
+            client: OnceCell::new(),
+            discovery: OnceCell::new(),
+            sealer,
+            roles: Arc::new(roles.clone()),
+        })))
+    }
+
+    /// Build Axum router containing login and callback endpoints.
+    pub(crate) fn build_router(&self) -> Router {
+        let _span = debug_span!("build_oidc").entered();
+        Router::new()
+            .route(&self.config.login_path, routing::get(login))
+            .route(self.config.redirect_url.path(), routing::get(callback))
+            .with_state(self.clone())
+    }
+
+    /// Whether API documentation requires session authentication.
+    pub(crate) fn protects_api_doc(&self) -> bool {
+        self.config.protect_api_doc
+    }
+
+    /// Whether management endpoints require session authentication.
+    pub(crate) fn protects_management(&self) -> bool {
+        self.config.protect_management
+    }
+
+    /// Get session authentication extractor.
+    pub(crate) fn auth_extractor(&self) -> SessionAuthExtractor {
+        SessionAuthExtractor {
+            cookie: self.config.session_cookie.as_str().into(),
+            login_path: self.config.login_path.as_str().into(),
+            sealer: self.sealer.clone(),
+        }
+    }
+
+    /// Get session authentication provider.
+    pub(crate) fn auth_provider(&self) -> SessionAuthProvider {
+        SessionAuthProvider {
+            roles: self.roles.clone(),
+        }
+    }
+
+    /// Get HTTP client, creating it on first use.
+    async fn client(&self) -> Result<&ClientWithMiddleware, OidcError> {
+        self.client
+            .get_or_try_init(|| async {
+                let client = self
+                    .client_config
+                    .to_client(self.client_metrics.clone())
+                    .await?;
+                Ok::<_, OidcError>(client)
+            })
+            .await
+    }
+
+    /// Get discovery document, fetching it on first use.
+    async fn discovery(&self) -> Result<&Discovery, OidcError> {
+        self.discovery
+            .get_or_try_init(|| async {
+                let discovery = self
+                    .client()
+                    .await?
+                    .get(self.config.discovery_url.clone())
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<Discovery>()
+                    .await?;
+                info!(
+                    issuer = discovery.issuer,
+                    "fetched OpenID provider configuration"
+                );
+                Ok::<_, OidcError>(discovery)
+            })
+            .await
+    }
+
+    /// Format cookie header value.
+    fn cookie(&self, name: &str, value: &str, path: &str, max_age: u64) -> HeaderValue {
+        let secure = if self.config.secure_cookies {
+            "; Secure"
+        } else {
+            ""
+        };
+        let cookie = format!(
+            "{name}={value}; Path={path}; Max-Age={max_age}; HttpOnly; SameSite=Lax{secure}"
+        );
+        HeaderValue::from_str(&cookie).unwrap_or_else(|_| HeaderValue::from_static(""))
+    }
+
+    /// Exchange authorization code for validated ID token claims.
+    async fn exchange(&self, code: &str, login: &LoginState) -> Result<Value, OidcError> {
+        let discovery = self.discovery().await?;
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_url.as_str()),
+            ("client_id", &self.config.client_id),
+            ("code_verifier", &login.verifier),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret.expose()));
+        }
+        let resp = self
+            .client()
+            .await?
+            .post(discovery.token_endpoint.clone())
+            .form(&form)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(OidcError::Provider(format!(
+                "token endpoint returned {}",
+                resp.status()
+            )));
+        }
+        let token: TokenResponse = resp.json().await?;
+        self.validate_id_token(&token.id_token, discovery, &login.nonce)
+    }
+
+    /// Decode ID token and validate its claims.
+    ///
+    /// ID token is received directly from token endpoint, so its signature is not checked, and
+    /// authenticity is ensured by transport security instead.
+    fn validate_id_token(
+        &self,
+        token: &str,
+        discovery: &Discovery,
+        nonce: &str,
+    ) -> Result<Value, OidcError> {
+        let payload = token
+            .split('.')
+            .nth(1)
+            .ok_or(OidcError::InvalidIdToken("malformed token"))?;
+        let claims: Value = B64
+            .decode(payload)
+            .ok()
+            .and_then(|raw| serde_json::from_slice(&raw).ok())
+            .ok_or(OidcError::InvalidIdToken("malformed token"))?;
+        if claims.get("iss").and_then(Value::as_str) != Some(discovery.issuer.as_str()) {
+            return Err(OidcError::InvalidIdToken("issuer mismatch"));
+        }
+        let audience_ok = match claims.get("aud") {
+            Some(Value::String(aud)) => *aud == self.config.client_id,
+            Some(Value::Array(auds)) => auds
+                .iter()
+                .any(|aud| aud.as_str() == Some(&self.config.client_id)),
+            _ => false,
+        };
+        if !audience_ok {
+            return Err(OidcError::InvalidIdToken("audience mismatch"));
+        }
+        let exp = claims
+            .get("exp")
+            .and_then(Value::as_u64)
+            .ok_or(OidcError::InvalidIdToken("no expiration time"))?;
+        if exp + self.config.leeway.as_secs() <= unix_now() {
+            return Err(OidcError::InvalidIdToken("token is expired"));
+        }
+        if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+            return Err(OidcError::InvalidIdToken("nonce mismatch"));
+        }
+        Ok(claims)
+    }
+
+    /// Sanitize post-login redirect target, allowing only local paths.
+    fn return_to(&self, requested: Option<String>) -> String {
+        requested
+            .filter(|path| path.starts_with('/') && !path.starts_with("//") && !path.contains('\\'))
+            .unwrap_or_else(|| self.config.post_login_redirect.clone())
+    }
+}
+
+/// Start login, redirecting user to OpenID provider.
+async fn login(
+    State(oidc): State<OidcState>,
+    Query(params): Query<LoginParams>,
+) -> Result<Response<Body>, OidcError> {
+    let discovery = oidc.discovery().await?;
+    let login = LoginState {
+        state: random_token(),
+        verifier: random_token(),
+        nonce: random_token(),
+        return_to: oidc.return_to(params.return_to),
+        exp: unix_now() + oidc.config.login_ttl.as_secs(),
+    };
+    let mut url = discovery.authorization_endpoint.clone();
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &oidc.config.client_id)
+        .append_pair("redirect_uri", oidc.config.redirect_url.as_str())
+        .append_pair("scope", &oidc.config.scopes.join(" "))
+        .append_pair("state", &login.state)
+        .append_pair("nonce", &login.nonce)
+        .append_pair("code_challenge", &code_challenge(&login.verifier))
+        .append_pair("code_challenge_method", "S256");
+    let location =
+        HeaderValue::from_str(url.as_str()).map_err(|_| OidcError::Config("invalid URL"))?;
+    let cookie = oidc.cookie(
+        &login_cookie_name(&oidc.config.session_cookie),
+        &oidc.sealer.seal(LOGIN_PURPOSE, &login),
+        oidc.config.redirect_url.path(),
+        oidc.config.login_ttl.as_secs(),
+    );
+    Ok((
+        StatusCode::SEE_OTHER,
+        [(LOCATION, location), (SET_COOKIE, cookie)],
+    )
+        .into_response())
+}
+
+/// Complete login, establishing a session.
+async fn callback(
+    State(oidc): State<OidcState>,
+    Query(params): Query<CallbackParams>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, OidcError> {
+    let login: LoginState = get_cookie(&headers, &login_cookie_name(&oidc.config.session_cookie))
+        .and_then(|cookie| oidc.sealer.open(LOGIN_PURPOSE, cookie))
+        .filter(|login: &LoginState| login.exp > unix_now())
+        .ok_or(OidcError::MissingState)?;
+    if let Some(error) = params.error {
+        return Err(OidcError::Authorization(
+            params.error_description.unwrap_or(error),
+        ));
+    }
+    let state = params.state.unwrap_or_default();
+    if !fixed_time_eq(state.as_bytes(), login.state.as_bytes()) {
+        warn!("login state mismatch");
+        return Err(OidcError::StateMismatch);
+    }
+    let code = params
+        .code
+        .ok_or(OidcError::Authorization("no authorization code".into()))?;
+    let claims = oidc.exchange(&code, &login).await?;
+    let sub = claim(&claims, &oidc.config.user_claim)
+        .and_then(Value::as_str)
+        .ok_or(OidcError::InvalidIdToken("no user claim"))?;
+    let session = SessionData {
+        sub: sub.to_owned(),
+        roles: oidc.config.map_roles(&claims),
+        exp: unix_now() + oidc.config.session_ttl.as_secs(),
+    };
+    info!(user = session.sub, roles = ?session.roles, "user logged in");
+    let session_cookie = oidc.cookie(
+        &oidc.config.session_cookie,
+        &oidc.sealer.seal(SESSION_PURPOSE, &session),
+        "/",
+        oidc.config.session_ttl.as_secs(),
+    );
+    let clear_cookie = oidc.cookie(
+        &login_cookie_name(&oidc.config.session_cookie),
+        "",
+        oidc.config.redirect_url.path(),
+        0,
+    );
+    let location =
+        HeaderValue::from_str(&login.return_to).map_err(|_| OidcError::Config("invalid URL"))?;
+    let mut resp = (StatusCode::SEE_OTHER, [(LOCATION, location)]).into_response();
+    resp.headers_mut().append(SET_COOKIE, session_cookie);
+    resp.headers_mut().append(SET_COOKIE, clear_cookie);
+    Ok(resp)
+}
+
+/// Name of login state cookie.
+fn login_cookie_name(session_cookie: &str) -> String {
+    format!("{session_cookie}_login")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::{error_handling::HandleErrorLayer, extract::Form, Extension, Json};
+    use parking_lot::Mutex;
+    use tokio::net::TcpListener;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    use super::*;
+    use crate::{auth::AuthLayer, builder::app::error_handler};
+
+    /// Values passed by relying party in authorization request.
+    #[derive(Clone, Debug, Default)]
+    struct Authorization {
+        challenge: String,
+        nonce: String,
+    }
+
+    /// Start mock OpenID provider, returning its base URL.
+    async fn mock_provider(auth: Arc<Mutex<Authorization>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let issuer = base.clone();
+        let discovery = serde_json::json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{base}/authorize"),
+            "token_endpoint": format!("{base}/token"),
+        });
+        let token = move |Form(form): Form<HashMap<String, String>>| async move {
+            let auth = auth.lock().clone();
+            if form.get("code").map(String::as_str) != Some("good-code")
+                || form.get("client_secret").map(String::as_str) != Some("s3cret")
+                || code_challenge(&form["code_verifier"]) != auth.challenge
+            {
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            let claims = serde_json::json!({
+                "iss": issuer,
+                "aud": ["docs", "other"],
+                "sub": "alice",
+                "exp": unix_now() + 300,
+                "nonce": auth.nonce,
+                "realm_access": {"roles": ["idp-admin", "idp-unmapped"]},
+            });
+            let id_token = format!(
+                "{}.{}.",
+                B64.encode(r#"{"alg":"none"}"#),
+                B64.encode(claims.to_string())
+            );
+            Ok(Json(serde_json::json!({
+                "access_token": "opaque",
+                "token_type": "Bearer",
+                "id_token": id_token,
+            })))
+        };
+        let app = Router::new()
+            .route(
+                "/.well-known/openid-configuration",
+                routing::get(move || async move { Json(discovery) }),
+            )
+            .route("/token", routing::post(token));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        base
+    }
+
+    /// Build login flow state and application router with a protected endpoint.
+    async fn setup() -> (OidcState, Router, Arc<Mutex<Authorization>>) {
+        let auth = Arc::new(Mutex::new(Authorization::default()));
+        let base = mock_provider(auth.clone()).await;
+        let config = OidcConfig::new(
+            format!("{base}/.well-known/openid-configuration")
+                .parse()
+                .unwrap(),
+            "docs",
+            "https://app.test/auth/oidc/callback".parse().unwrap(),
+            "session-secret",
+        )
+        .with_client_secret("s3cret")
+        .with_roles_claim("realm_access.roles")
+        .with_role_mapping("idp-admin", "admin");
+        let roles = BTreeMap::from([(
+            "admin".to_owned(),
+            RoleConfig {
+                permissions: BTreeSet::from(["maintenance".to_owned()]),
+                super_user: false,
+            },
+        )]);
+        let oidc = OidcState::new(config, HttpClientConfig::default(), None, &roles).unwrap();
+        let protected = Router::new()
+            .route(
+                "/protected",
+                routing::get(|Extension(user): Extension<SessionUser>| async move {
+                    format!("{} {:?}", user.id().as_str(), user.roles())
+                }),
+            )
+            .layer(
+                ServiceBuilder::new()
+                    .layer(HandleErrorLayer::new(error_handler))
+                    .layer(AuthLayer::new(
+                        &["maintenance"],
+                        oidc.auth_provider(),
+                        oidc.auth_extractor(),
+                    )),
+            );
+        let app = oidc.build_router().merge(protected);
+        (oidc, app, auth)
+    }
+
+    async fn get(app: &Router, uri: &str, cookie: Option<&str>) -> Response<Body> {
+        let mut req = Request::get(uri);
+        if let Some(cookie) = cookie {
+            req = req.header(COOKIE, cookie);
+        }
+        app.clone()
+            .oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    fn location(resp: &Response<Body>) -> Url {
+        let location = resp.headers()[LOCATION].to_str().unwrap();
+        Url::parse("https://app.test")
+            .unwrap()
+            .join(location)
+            .unwrap()
+    }
+
+    fn cookies(resp: &Response<Body>) -> Vec<String> {
+        resp.headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .map(|val| val.to_str().unwrap().split(';').next().unwrap().to_owned())
+            .collect()
+    }
+
+    /// Start login, returning state value and login cookie.
+    async fn start_login(app: &Router, auth: &Mutex<Authorization>) -> (String, String) {
+        let resp = get(app, "/auth/oidc/login?return_to=/rapidoc", None).await;
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let params: HashMap<_, _> = location(&resp).query_pairs().into_owned().collect();
+        assert_eq!(params["code_challenge_method"], "S256");
+        assert_eq!(
+            params["redirect_uri"],
+            "https://app.test/auth/oidc/callback"
+        );
+        *auth.lock() = Authorization {
+            challenge: params["code_challenge"].clone(),
+            nonce: params["nonce"].clone(),
+        };
+        (params["state"].clone(), cookies(&resp).remove(0))
+    }
+
+    /// Full login flow, followed by access to protected endpoint.
+    #[tokio::test]
+    async fn happy_path() {
+        let (_, app, auth) = setup().await;
+        let resp = get(&app, "/protected", None).await;
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(location(&resp).path(), "/auth/oidc/login");
+
+        let (state, login_cookie) = start_login(&app, &auth).await;
+        let uri = format!("/auth/oidc/callback?code=good-code&state={state}");
+        let resp = get(&app, &uri, Some(&login_cookie)).await;
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(location(&resp).path(), "/rapidoc");
+        let session_cookie = cookies(&resp).remove(0);
+        assert!(session_cookie.starts_with("uxum_session="));
+
+        let resp = get(&app, "/protected", Some(&session_cookie)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, r#"alice {"admin"}"#);
+    }
+
+    /// Callback with foreign state or without login cookie is rejected.
+    #[tokio::test]
+    async fn state_mismatch() {
+        let (_, app, auth) = setup().await;
+        let (_, login_cookie) = start_login(&app, &auth).await;
+        let uri = "/auth/oidc/callback?code=good-code&state=forged";
+        let resp = get(&app, uri, Some(&login_cookie)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let resp = get(&app, uri, None).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let tampered = login_cookie.replace('.', "x.");
+        let resp = get(&app, uri, Some(&tampered)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    /// Expired, tampered and unprivileged sessions are not accepted.
+    #[tokio::test]
+    async fn expired_session() {
+        let (oidc, app, _) = setup().await;
+        let session = |exp, role: &str| {
+            let data = SessionData {
+                sub: "bob".into(),
+                roles: BTreeSet::from([role.to_owned()]),
+                exp,
+            };
+            format!("uxum_session={}", oidc.sealer.seal(SESSION_PURPOSE, &data))
+        };
+        let resp = get(&app, "/protected", Some(&session(unix_now() - 1, "admin"))).await;
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let valid = session(unix_now() + 60, "admin");
+        let resp = get(&app, "/protected", Some(&valid)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = get(
+            &app,
+            "/protected",
+            Some(&valid.replace("uxum_session=e", "uxum_session=f")),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        let resp = get(&app, "/protected", Some(&session(unix_now() + 60, "guest"))).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    }
+}
@@ -1,18 +1,66 @@
 //! AAA - providers.
 
-use std::sync::Arc;
+use std::{collections::BTreeSet, sync::Arc};
+
+use async_trait::async_trait;
 
 use crate::auth::{config::AuthConfig, errors::AuthError, user::UserId};
 
 /// Authentication provider (back-end) trait.
-pub trait AuthProvider: Clone + Send {
+///
+/// Both checks are asynchronous, so providers are free to query external back-ends, such as
+/// LDAP or a database.
+///
+/// # Example
+///
+/// ```
+/// use std::{collections::HashMap, sync::Arc};
+///
+/// use tokio::sync::RwLock;
+/// use uxum::{async_trait, AuthError, AuthProvider, UserId};
+///
+/// #[derive(Clone, Default)]
+/// struct DbAuthProvider {
+///     /// User passwords and permissions, keyed by user name.
+///     users: Arc<RwLock<HashMap<String, (String, Vec<&'static str>)>>>,
+/// }
+///
+/// #[async_trait]
+/// impl AuthProvider for DbAuthProvider {
+///     type User = UserId;
+///     type AuthTokens = String;
+///
+///     async fn authenticate(&self, user: &UserId, tokens: &String) -> Result<(), AuthError> {
+///         match self.users.read().await.get(user.as_str()) {
+///             Some((password, _)) if password == tokens => Ok(()),
+///             Some(_) => Err(AuthError::AuthFailed),
+///             None => Err(AuthError::UserNotFound),
+///         }
+///     }
+///
+///     async fn authorize(
+///         &self,
+///         user: &UserId,
+///         _tokens: &String,
+///         permission: &'static str,
+///     ) -> Result<(), AuthError> {
+///         match self.users.read().await.get(user.as_str()) {
+///             Some((_, perms)) if perms.contains(&permission) => Ok(()),
+///             Some(_) => Err(AuthError::NoPermission(permission)),
+///             None => Err(AuthError::UserNotFound),
+///         }
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait AuthProvider: Clone + Send + Sync {
     /// User ID type.
     ///
     /// Acquired from auth extractor (front-end) for authentication and authorization.
     /// On successful authentication it is injected into request as an extension.
     type User: Clone + Send + Sync + 'static;
     /// Authentication data type.
-    type AuthTokens;
+    type AuthTokens: Send + Sync;
 
     /// Authenticate the request.
     ///
@@ -21,27 +69,46 @@ pub trait AuthProvider: Clone + Send {
     /// # Errors
     ///
     /// Returns `Err` if user authentication failed, or on other error condition.
-    fn authenticate(&self, user: &Self::User, tokens: &Self::AuthTokens) -> Result<(), AuthError>;
+    async fn authenticate(
+        &self,
+        user: &Self::User,
+        tokens: &Self::AuthTokens,
+    ) -> Result<(), AuthError>;
 
     /// Authorize the request.
     ///
-    /// Checks if the user has specific permission.
+    /// Checks if the user has specific permission. Auth tokens are passed along, for providers
+    /// that derive permissions from them.
     ///
     /// # Errors
     ///
     /// Returns `Err` if permission check is unsuccessful, or on other error condition.
-    fn authorize(&self, user: &Self::User, permission: &'static str) -> Result<(), AuthError>;
+    async fn authorize(
+        &self,
+        user: &Self::User,
+        tokens: &Self::AuthTokens,
+        permission: &'static str,
+    ) -> Result<(), AuthError>;
+
+    /// Get roles granted to authenticated user.
+    ///
+    /// Roles are added to request extensions as part of [`crate::CurrentUser`], for use by
+    /// handlers and layers such as rate limiting. Default implementation reports no roles.
+    async fn roles(&self, _user: &Self::User, _tokens: &Self::AuthTokens) -> BTreeSet<String> {
+        BTreeSet::new()
+    }
 }
 
 /// Authentication provider (back-end) which does nothing.
 #[derive(Clone, Debug, Default)]
 pub struct NoOpAuthProvider;
 
+#[async_trait]
 impl AuthProvider for NoOpAuthProvider {
     type User = ();
     type AuthTokens = ();
 
-    fn authenticate(
+    async fn authenticate(
         &self,
         _user: &Self::User,
         _tokens: &Self::AuthTokens,
@@ -49,7 +116,12 @@ impl AuthProvider for NoOpAuthProvider {
         Ok(())
     }
 
-    fn authorize(&self, _user: &Self::User, _permission: &'static str) -> Result<(), AuthError> {
+    async fn authorize(
+        &self,
+        _user: &Self::User,
+        _tokens: &Self::AuthTokens,
+        _permission: &'static str,
+    ) -> Result<(), AuthError> {
         Ok(())
     }
 }
@@ -63,11 +135,16 @@ pub struct ConfigAuthProvider {
     config: Arc<AuthConfig>,
 }
 
+#[async_trait]
 impl AuthProvider for ConfigAuthProvider {
     type User = UserId;
     type AuthTokens = String;
 
-    fn authenticate(&self, user: &Self::User, tokens: &Self::AuthTokens) -> Result<(), AuthError> {
+    async fn authenticate(
+        &self,
+        user: &Self::User,
+        tokens: &Self::AuthTokens,
+    ) -> Result<(), AuthError> {
         match self.config.user(user) {
             Some(user_cfg) => {
                 if user_cfg.password == tokens.as_str() {
@@ -80,22 +157,62 @@ impl AuthProvider for ConfigAuthProvider {
         }
     }
 
-    fn authorize(&self, user: &Self::User, permission: &'static str) -> Result<(), AuthError> {
+    async fn authorize(
+        &self,
+        user: &Self::User,
+        _tokens: &Self::AuthTokens,
+        permission: &'static str,
+    ) -> Result<(), AuthError> {
+        self.user_allows(user, permission)
+    }
+
+    async fn roles(&self, user: &Self::User, _tokens: &Self::AuthTokens) -> BTreeSet<String> {
+        self.user_roles(user)
+    }
+}
+
+impl ConfigAuthProvider {
+    /// Check whether user exists in configuration, and has specific permission.
+    pub(crate) fn user_allows(
+        &self,
+        user: &str,
+        permission: &'static str,
+    ) -> Result<(), AuthError> {
         // TODO: combine with authentication to avoid double lookup
         match self.config.user(user) {
-            Some(user_cfg) => {
-                for role in &user_cfg.roles {
-                    if let Some(role_cfg) = self.config.roles.get(role) {
-                        if role_cfg.super_user || role_cfg.permissions.contains(permission) {
-                            return Ok(());
-                        }
-                    }
-                }
-                Err(AuthError::NoPermission(permission))
-            }
+            Some(user_cfg) => match self.roles_allow(&user_cfg.roles, permission) {
+                true => Ok(()),
+                false => Err(AuthError::NoPermission(permission)),
+            },
             None => Err(AuthError::UserNotFound),
         }
     }
+
+    /// Get roles granted to user in configuration.
+    pub(crate) fn user_roles(&self, user: &str) -> BTreeSet<String> {
+        self.config
+            .user(user)
+            .map(|user_cfg| user_cfg.roles.clone())
+            .unwrap_or_default()
+    }
+
+    /// Check whether user exists in configuration.
+    pub(crate) fn has_user(&self, user: &str) -> bool {
+        self.config.user(user).is_some()
+    }
+
+    /// Check whether any of the roles grants a permission.
+    pub(crate) fn roles_allow<'a>(
+        &self,
+        roles: impl IntoIterator<Item = &'a String>,
+        permission: &str,
+    ) -> bool {
+        roles.into_iter().any(|role| {
+            self.config.roles.get(role).is_some_and(|role_cfg| {
+                role_cfg.super_user || role_cfg.permissions.contains(permission)
+            })
+        })
+    }
 }
 
 impl From<AuthConfig> for ConfigAuthProvider {
@@ -0,0 +1,1069 @@
+//! AAA - short-lived service-to-service tokens.
+//!
+//! Tokens are compact JWTs signed with HMAC-SHA256 (`HS256`). Outbound HTTP clients get them
+//! from [`TokenIssuer`], and receiving services validate them using
+//! [`ServiceTokenAuthExtractor`]. Tokens issued by an external identity provider can also be
+//! validated using public keys from its JSON Web Key Set.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    http::{
+        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+        HeaderValue, Request, Response, StatusCode,
+    },
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as B64, Engine};
+use crypto::{hmac::Hmac, mac::Mac, sha2::Sha256};
+use dashmap::DashMap;
+use okapi::{openapi3, Map};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+
+use crate::{
+    auth::{
+        errors::AuthError,
+        extractor::AuthExtractor,
+        jwks::{JwksCache, JwksConfig},
+        provider::{AuthProvider, ConfigAuthProvider},
+        user::UserId,
+    },
+    http_client::HttpClientConfig,
+    metrics::ClientMetricsState,
+    secret::SecretValue,
+};
+
+/// Error type used in service token subsystem.
+#[derive(Clone, Debug, Error, PartialEq)]
+#[non_exhaustive]
+pub enum TokenError {
+    /// Service tokens are not configured.
+    #[error("Service tokens are not configured")]
+    NotConfigured,
+    /// Token issuer name is not set, and no application name is available.
+    #[error("Service token issuer name is not set")]
+    NoIssuer,
+    /// Token is malformed.
+    #[error("Malformed service token")]
+    Malformed,
+    /// Token signature or algorithm is invalid.
+    #[error("Invalid service token signature")]
+    InvalidSignature,
+    /// Shared secret is empty.
+    #[error("Service token secret is empty")]
+    EmptySecret,
+    /// Shared secret is required, but not set.
+    #[error("Service token secret is not set")]
+    MissingSecret,
+    /// Token is expired.
+    #[error("Service token is expired")]
+    Expired,
+    /// Token is not valid yet.
+    #[error("Service token is not valid yet")]
+    NotYetValid,
+    /// Token issuer is not in the list of allowed issuers.
+    #[error("Service token issuer is not allowed: {0}")]
+    InvalidIssuer(String),
+    /// Token is issued for another audience.
+    #[error("Service token audience mismatch")]
+    InvalidAudience,
+    /// Token is signed with a key missing from key set.
+    #[error("Unknown service token key: {0}")]
+    UnknownKey(String),
+    /// Unable to fetch key set.
+    #[error("Unable to fetch key set: {0}")]
+    KeySetFetch(String),
+}
+
+/// Service token configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct ServiceTokenConfig {
+    /// Issuer name, asserting identity of the calling service.
+    ///
+    /// Defaults to application name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    /// Shared HMAC secret used for signing and validation.
+    ///
+    /// May be read from environment variable or file, see [`SecretValue`]. Must not be empty.
+    /// Required for issuing tokens, and for validating `HS256` tokens. May be omitted if only
+    /// tokens signed by an identity provider are validated using [`Self::jwks`].
+    #[serde(
+        default,
+        deserialize_with = "non_empty_secret",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub secret: Option<SecretValue>,
+    /// Token lifetime.
+    #[serde(default = "ServiceTokenConfig::default_ttl", with = "humantime_serde")]
+    pub ttl: Duration,
+    /// Issue new token when cached one is this close to expiry.
+    #[serde(
+        default = "ServiceTokenConfig::default_refresh_before",
+        with = "humantime_serde"
+    )]
+    pub refresh_before: Duration,
+    /// Allowed clock skew when validating tokens.
+    #[serde(
+        default = "ServiceTokenConfig::default_leeway",
+        with = "humantime_serde"
+    )]
+    pub leeway: Duration,
+    /// Audience claim to issue, and to require when validating.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audience: Option<String>,
+    /// Issuers accepted when validating tokens.
+    ///
+    /// Tokens from any issuer are accepted if empty.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub allowed_issuers: BTreeSet<String>,
+    /// Include authenticated end-user as token subject, and calling service as an actor.
+    #[serde(default)]
+    pub propagate_user: bool,
+    /// Names of HTTP clients which attach service tokens to outgoing requests.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub clients: BTreeSet<String>,
+    /// Key set used to validate tokens signed by an identity provider.
+    ///
+    /// Only asymmetric algorithms are accepted if set, unless [`Self::allow_hs256_with_jwks`] is
+    /// enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jwks: Option<JwksConfig>,
+    /// Still accept `HS256` tokens signed with shared secret when key set is configured.
+    #[serde(default)]
+    pub allow_hs256_with_jwks: bool,
+    /// Derive roles and permissions from token claims.
+    ///
+    /// If set, token subjects do not need to exist in user database.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claim_mapping: Option<ClaimMappingConfig>,
+}
+
+impl ServiceTokenConfig {
+    /// Default value for [`Self::ttl`].
+    #[must_use]
+    #[inline]
+    fn default_ttl() -> Duration {
+        Duration::from_secs(300)
+    }
+
+    /// Default value for [`Self::refresh_before`].
+    #[must_use]
+    #[inline]
+    fn default_refresh_before() -> Duration {
+        Duration::from_secs(30)
+    }
+
+    /// Default value for [`Self::leeway`].
+    #[must_use]
+    #[inline]
+    fn default_leeway() -> Duration {
+        Duration::from_secs(30)
+    }
+
+    /// Create new configuration with a shared secret.
+    #[must_use]
+    pub fn new(secret: impl ToString) -> Self {
+        Self {
+            issuer: None,
+            secret: Some(SecretValue::new(secret)),
+            ttl: Self::default_ttl(),
+            refresh_before: Self::default_refresh_before(),
+            leeway: Self::default_leeway(),
+            audience: None,
+            allowed_issuers: BTreeSet::new(),
+            propagate_user: false,
+            clients: BTreeSet::new(),
+            jwks: None,
+            allow_hs256_with_jwks: false,
+            claim_mapping: None,
+        }
+    }
+
+    /// Create new configuration validating tokens using key set only, without a shared secret.
+    #[must_use]
+    pub fn from_jwks(jwks: JwksConfig) -> Self {
+        Self {
+            secret: None,
+            jwks: Some(jwks),
+            ..Self::new("")
+        }
+    }
+
+    /// Check whether configuration is consistent.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if neither shared secret nor key set are configured, or if `HS256` tokens
+    /// are allowed along with key set, but no shared secret is set.
+    pub fn validate(&self) -> Result<(), TokenError> {
+        match (&self.secret, &self.jwks) {
+            (Some(secret), _) if secret.expose().is_empty() => Err(TokenError::EmptySecret),
+            (None, None) => Err(TokenError::MissingSecret),
+            (None, Some(_)) if self.allow_hs256_with_jwks => Err(TokenError::MissingSecret),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Deserialize optional shared secret, rejecting empty values.
+fn non_empty_secret<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<SecretValue>, D::Error> {
+    let secret = SecretValue::deserialize(deserializer)?;
+    if secret.expose().is_empty() {
+        return Err(de::Error::custom(TokenError::EmptySecret));
+    }
+    Ok(Some(secret))
+}
+
+/// Mapping of token claims to roles and permissions.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct ClaimMappingConfig {
+    /// Claim containing role names, as a dot-separated path (e.g. `realm_access.roles`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roles_claim: Option<String>,
+    /// Claim containing permission names, as a dot-separated path (e.g. `scope`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions_claim: Option<String>,
+    /// Translation of token role names to roles defined in auth configuration.
+    ///
+    /// Token roles missing from this table are ignored, unless [`Self::pass_through_roles`] is
+    /// enabled.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub role_mapping: BTreeMap<String, String>,
+    /// Use token role names missing from [`Self::role_mapping`] as is.
+    #[serde(default)]
+    pub pass_through_roles: bool,
+    /// Roles granted to every token holder.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub default_roles: BTreeSet<String>,
+}
+
+impl ClaimMappingConfig {
+    /// Resolve roles and permissions from raw token claims.
+    #[must_use]
+    fn grants(&self, claims: &serde_json::Value) -> TokenGrants {
+        let roles = claim_names(claims, self.roles_claim.as_deref())
+            .into_iter()
+            .filter_map(|role| match self.role_mapping.get(&role) {
+                Some(mapped) => Some(mapped.clone()),
+                None => self.pass_through_roles.then_some(role),
+            })
+            .chain(self.default_roles.iter().cloned())
+            .collect();
+        let permissions = claim_names(claims, self.permissions_claim.as_deref())
+            .into_iter()
+            .collect();
+        TokenGrants { roles, permissions }
+    }
+}
+
+/// Get names from a claim, which is either an array of strings, or a space-separated string.
+fn claim_names(claims: &serde_json::Value, path: Option<&str>) -> Vec<String> {
+    let claim = path.and_then(|path| {
+        path.split('.')
+            .try_fold(claims, |value, key| value.as_object()?.get(key))
+    });
+    match claim {
+        Some(serde_json::Value::Array(items)) => items
+            .iter()
+            .filter_map(|item| item.as_str())
+            .map(str::to_string)
+            .collect(),
+        Some(serde_json::Value::String(items)) => {
+            items.split_whitespace().map(str::to_string).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Roles and permissions derived from token claims.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TokenGrants {
+    /// Granted roles, as defined in auth configuration.
+    pub roles: BTreeSet<String>,
+    /// Directly granted permissions.
+    pub permissions: BTreeSet<String>,
+}
+
+/// Actor claim, as defined in RFC 8693.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct ActorClaim {
+    /// Acting service.
+    pub sub: String,
+}
+
+/// Audience claim: either a single audience, or an array of them (RFC 7519, section 4.1.3).
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+#[non_exhaustive]
+pub enum Audience {
+    /// Single audience.
+    Single(String),
+    /// Multiple audiences.
+    Multiple(Vec<String>),
+}
+
+impl Audience {
+    /// Check whether token is intended for this audience.
+    #[must_use]
+    pub fn contains(&self, audience: &str) -> bool {
+        match self {
+            Self::Single(aud) => aud == audience,
+            Self::Multiple(auds) => auds.iter().any(|aud| aud == audience),
+        }
+    }
+}
+
+/// Claims of a service token.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct ServiceClaims {
+    /// Issuing service.
+    pub iss: String,
+    /// Subject: either issuing service, or propagated end-user.
+    pub sub: String,
+    /// Intended audience.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<Audience>,
+    /// Issue timestamp, in seconds since UNIX epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat: Option<u64>,
+    /// Timestamp before which token must not be accepted, in seconds since UNIX epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<u64>,
+    /// Expiration timestamp, in seconds since UNIX epoch.
+    pub exp: u64,
+    /// Acting service, present if end-user was propagated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<ActorClaim>,
+    /// Roles and permissions derived from claims, if claim mapping is configured.
+    #[serde(skip)]
+    pub grants: Option<TokenGrants>,
+}
+
+/// Fixed JOSE header for issued tokens.
+const JOSE_HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+/// Current time, in seconds since UNIX epoch.
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_secs())
+        .unwrap_or_default()
+}
+
+/// Calculate HMAC-SHA256 signature.
+pub(crate) fn sign(secret: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::new(Sha256::new(), secret);
+    mac.input(data);
+    mac.result().code().to_vec()
+}
+
+/// Issuer of service tokens.
+///
+/// Issued tokens are cached per subject until they are close to expiry.
+#[derive(Debug)]
+pub struct TokenIssuer {
+    /// Token configuration.
+    config: ServiceTokenConfig,
+    /// Issuer name.
+    issuer: String,
+    /// Cached tokens, along with their expiration timestamps.
+    cache: DashMap<Option<String>, (HeaderValue, u64)>,
+}
+
+impl PartialEq for TokenIssuer {
+    fn eq(&self, other: &Self) -> bool {
+        self.config == other.config && self.issuer == other.issuer
+    }
+}
+
+impl TokenIssuer {
+    /// Create new token issuer.
+    ///
+    /// `app_name` is used as an issuer name if it was not set in configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if issuer name is not available, or shared secret is not set or empty.
+    pub fn new(config: ServiceTokenConfig, app_name: Option<&str>) -> Result<Self, TokenError> {
+        match &config.secret {
+            None => return Err(TokenError::MissingSecret),
+            Some(secret) if secret.expose().is_empty() => return Err(TokenError::EmptySecret),
+            Some(_) => {}
+        }
+        let issuer = config
+            .issuer
+            .as_deref()
+            .or(app_name)
+            .ok_or(TokenError::NoIssuer)?
+            .to_string();
+        Ok(Self {
+            config,
+            issuer,
+            cache: DashMap::new(),
+        })
+    }
+
+    /// Whether authenticated end-user is propagated in issued tokens.
+    #[must_use]
+    pub fn propagates_user(&self) -> bool {
+        self.config.propagate_user
+    }
+
+    /// Get `Authorization` header value with a valid token, issuing a new one if needed.
+    #[must_use]
+    pub fn authorization(&self, user: Option<&str>) -> HeaderValue {
+        let user = user.filter(|_| self.config.propagate_user);
+        let now = unix_now();
+        let refresh = self.config.refresh_before.as_secs();
+        let key = user.map(str::to_string);
+        if let Some(cached) = self.cache.get(&key) {
+            if cached.1 > now + refresh {
+                return cached.0.clone();
+            }
+        }
+        let (token, exp) = self.issue_at(user, now);
+        // SAFETY: base64 alphabet is always a valid header value.
+        let value = HeaderValue::from_str(&format!("Bearer {token}")).unwrap();
+        self.cache.insert(key, (value.clone(), exp));
+        value
+    }
+
+    /// Issue new token.
+    #[must_use]
+    pub fn issue(&self, user: Option<&str>) -> String {
+        self.issue_at(user.filter(|_| self.config.propagate_user), unix_now())
+            .0
+    }
+
+    /// Issue new token at a specific point in time.
+    fn issue_at(&self, user: Option<&str>, now: u64) -> (String, u64) {
+        let exp = now + self.config.ttl.as_secs();
+        let claims = ServiceClaims {
+            iss: self.issuer.clone(),
+            sub: user.unwrap_or(&self.issuer).to_string(),
+            aud: self.config.audience.clone().map(Audience::Single),
+            iat: Some(now),
+            nbf: None,
+            exp,
+            act: user.map(|_| ActorClaim {
+                sub: self.issuer.clone(),
+            }),
+            grants: None,
+        };
+        // SAFETY: claims always serialize successfully.
+        let payload = serde_json::to_vec(&claims).unwrap();
+        let signing_input = format!("{}.{}", B64.encode(JOSE_HEADER), B64.encode(payload));
+        let secret = self
+            .config
+            .secret
+            .as_ref()
+            .map(SecretValue::expose)
+            .unwrap_or_default();
+        let signature = sign(secret.as_bytes(), signing_input.as_bytes());
+        (format!("{signing_input}.{}", B64.encode(signature)), exp)
+    }
+}
+
+/// Validator of service tokens.
+#[derive(Clone, Debug)]
+pub struct TokenValidator {
+    /// Shared HMAC secret, if `HS256` tokens are accepted.
+    secret: Option<Arc<[u8]>>,
+    /// Required audience.
+    audience: Option<String>,
+    /// Accepted issuers, any if empty.
+    allowed_issuers: Arc<BTreeSet<String>>,
+    /// Allowed clock skew, in seconds.
+    leeway: u64,
+    /// Public keys of identity provider.
+    jwks: Option<JwksCache>,
+    /// Accept `HS256` tokens even if key set is configured.
+    allow_hs256_with_jwks: bool,
+}
+
+impl From<&ServiceTokenConfig> for TokenValidator {
+    fn from(value: &ServiceTokenConfig) -> Self {
+        Self {
+            secret: value
+                .secret
+                .as_ref()
+                .map(|secret| secret.expose().as_bytes().into()),
+            audience: value.audience.clone(),
+            allowed_issuers: Arc::new(value.allowed_issuers.clone()),
+            leeway: value.leeway.as_secs(),
+            jwks: value
+                .jwks
+                .clone()
+                .map(|jcfg| JwksCache::new(jcfg, HttpClientConfig::default(), None)),
+            allow_hs256_with_jwks: value.allow_hs256_with_jwks,
+        }
+    }
+}
+
+impl TokenValidator {
+    /// Get key set cache, if configured.
+    #[must_use]
+    pub(crate) fn jwks(&self) -> Option<&JwksCache> {
+        self.jwks.as_ref()
+    }
+
+    /// Validate token and return its claims.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if token is malformed, has invalid signature, is expired or not yet valid,
+    /// or comes from an issuer which is not allowed.
+    pub fn validate(&self, token: &str) -> Result<ServiceClaims, TokenError> {
+        self.validate_raw(token).map(|(claims, _)| claims)
+    }
+
+    /// Validate token and return its claims, along with raw claims payload.
+    fn validate_raw(&self, token: &str) -> Result<(ServiceClaims, serde_json::Value), TokenError> {
+        let (signing_input, signature) = token.rsplit_once('.').ok_or(TokenError::Malformed)?;
+        let (header, payload) = signing_input.split_once('.').ok_or(TokenError::Malformed)?;
+        let header: BTreeMap<String, serde_json::Value> =
+            serde_json::from_slice(&B64.decode(header).map_err(|_| TokenError::Malformed)?)
+                .map_err(|_| TokenError::Malformed)?;
+        let signature = B64.decode(signature).map_err(|_| TokenError::Malformed)?;
+        match (header.get("alg").and_then(|alg| alg.as_str()), &self.jwks) {
+            (Some("HS256"), jwks) => {
+                if jwks.is_some() && !self.allow_hs256_with_jwks {
+                    return Err(TokenError::InvalidSignature);
+                }
+                let secret = self.secret.as_ref().ok_or(TokenError::InvalidSignature)?;
+                let expected = sign(secret, signing_input.as_bytes());
+                if !crypto::util::fixed_time_eq(&signature, &expected) {
+                    return Err(TokenError::InvalidSignature);
+                }
+            }
+            (Some(alg), Some(jwks)) => {
+                let kid = header
+                    .get("kid")
+                    .and_then(|kid| kid.as_str())
+                    .unwrap_or_default();
+                jwks.key(kid)
+                    .ok_or_else(|| TokenError::UnknownKey(kid.into()))?
+                    .verify(alg, signing_input.as_bytes(), &signature)?;
+            }
+            _ => return Err(TokenError::InvalidSignature),
+        }
+        let raw: serde_json::Value =
+            serde_json::from_slice(&B64.decode(payload).map_err(|_| TokenError::Malformed)?)
+                .map_err(|_| TokenError::Malformed)?;
+        let claims: ServiceClaims =
+            serde_json::from_value(raw.clone()).map_err(|_| TokenError::Malformed)?;
+        let now = unix_now();
+        if claims.exp + self.leeway < now {
+            return Err(TokenError::Expired);
+        }
+        if claims.nbf.is_some_and(|nbf| nbf > now + self.leeway) {
+            return Err(TokenError::NotYetValid);
+        }
+        if !self.allowed_issuers.is_empty() && !self.allowed_issuers.contains(&claims.iss) {
+            return Err(TokenError::InvalidIssuer(claims.iss));
+        }
+        if let Some(audience) = &self.audience {
+            if !claims
+                .aud
+                .as_ref()
+                .is_some_and(|aud| aud.contains(audience))
+            {
+                return Err(TokenError::InvalidAudience);
+            }
+        }
+        Ok((claims, raw))
+    }
+}
+
+/// Authentication extractor (front-end) for service tokens.
+#[derive(Clone, Debug)]
+pub struct ServiceTokenAuthExtractor {
+    /// Token validator.
+    validator: TokenValidator,
+    /// Mapping of token claims to roles and permissions.
+    claim_mapping: Option<Arc<ClaimMappingConfig>>,
+}
+
+impl ServiceTokenAuthExtractor {
+    /// Name of authentication scheme.
+    const SCHEME: &'static str = "Bearer";
+
+    /// Create new extractor from configuration.
+    #[must_use]
+    pub fn new(config: &ServiceTokenConfig) -> Self {
+        Self {
+            validator: config.into(),
+            claim_mapping: config.claim_mapping.clone().map(Arc::new),
+        }
+    }
+
+    /// Use specific HTTP client to fetch key set.
+    #[must_use]
+    pub(crate) fn with_jwks_client(
+        mut self,
+        client_config: HttpClientConfig,
+        client_metrics: ClientMetricsState,
+        jwks_config: JwksConfig,
+    ) -> Self {
+        self.validator.jwks = Some(JwksCache::new(
+            jwks_config,
+            client_config,
+            Some(client_metrics),
+        ));
+        self
+    }
+
+    /// Get key set cache, if configured.
+    #[must_use]
+    pub(crate) fn jwks(&self) -> Option<&JwksCache> {
+        self.validator.jwks()
+    }
+}
+
+impl AuthExtractor for ServiceTokenAuthExtractor {
+    type User = UserId;
+    type AuthTokens = ServiceClaims;
+
+    fn extract_auth(
+        &self,
+        req: &Request<Body>,
+    ) -> Result<(Self::User, Self::AuthTokens), AuthError> {
+        let header = req
+            .headers()
+            .get(AUTHORIZATION)
+            .ok_or(AuthError::NoAuthProvided)?
+            .to_str()
+            .map_err(|_| AuthError::InvalidAuthHeader)?;
+        let token = match header.split_once(' ') {
+            Some((scheme, token)) if scheme.eq_ignore_ascii_case(Self::SCHEME) => token.trim(),
+            Some((scheme, _)) => return Err(AuthError::UnknownAuthScheme(scheme.to_string())),
+            None => return Err(AuthError::InvalidAuthHeader),
+        };
+        let (mut claims, raw) = self
+            .validator
+            .validate_raw(token)
+            .map_err(|err| match err {
+                TokenError::Malformed => AuthError::InvalidAuthPayload,
+                _ => AuthError::AuthFailed,
+            })?;
+        claims.grants = self.claim_mapping.as_ref().map(|cm| cm.grants(&raw));
+        Ok((claims.sub.as_str().into(), claims))
+    }
+
+    fn error_response(&self, err: AuthError) -> Response<Body> {
+        let status = match err {
+            AuthError::NoAuthProvided | AuthError::UserNotFound | AuthError::AuthFailed => {
+                StatusCode::UNAUTHORIZED
+            }
+            AuthError::NoPermission(_) => StatusCode::FORBIDDEN,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        let mut resp = err.problem_response(status);
+        if status == StatusCode::UNAUTHORIZED {
+            resp.headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static(Self::SCHEME));
+        }
+        resp
+    }
+
+    fn security_schemes(&self) -> BTreeMap<String, openapi3::SecurityScheme> {
+        maplit::btreemap! {
+            "service-token".into() => openapi3::SecurityScheme {
+                description: Some("Service-to-service token".into()),
+                data: openapi3::SecuritySchemeData::Http {
+                    scheme: "bearer".into(),
+                    bearer_format: Some("JWT".into()),
+                },
+                extensions: Map::default(),
+            },
+        }
+    }
+
+    fn request_headers(&self) -> Vec<String> {
+        vec![AUTHORIZATION.to_string()]
+    }
+}
+
+/// Authentication provider (back-end) for service tokens.
+///
+/// Token subject is looked up in users and roles stored in app configuration, unless roles
+/// and permissions were derived from token claims.
+#[derive(Clone, Debug)]
+pub struct ServiceTokenAuthProvider {
+    /// Provider used for user lookup and authorization.
+    inner: ConfigAuthProvider,
+}
+
+impl From<ConfigAuthProvider> for ServiceTokenAuthProvider {
+    fn from(value: ConfigAuthProvider) -> Self {
+        Self { inner: value }
+    }
+}
+
+#[async_trait]
+impl AuthProvider for ServiceTokenAuthProvider {
+    type User = UserId;
+    type AuthTokens = ServiceClaims;
+
+    async fn authenticate(
+        &self,
+        user: &Self::User,
+        tokens: &Self::AuthTokens,
+    ) -> Result<(), AuthError> {
+        // Token itself was already validated by extractor.
+        match tokens.grants.is_some() || self.inner.has_user(user) {
+            true => Ok(()),
+            false => Err(AuthError::UserNotFound),
+        }
+    }
+
+    async fn authorize(
+        &self,
+        user: &Self::User,
+        tokens: &Self::AuthTokens,
+        permission: &'static str,
+    ) -> Result<(), AuthError> {
+        match &tokens.grants {
+            Some(grants) => match grants.permissions.contains(permission)
+                || self.inner.roles_allow(&grants.roles, permission)
+            {
+                true => Ok(()),
+                false => Err(AuthError::NoPermission(permission)),
+            },
+            None => self.inner.user_allows(user, permission),
+        }
+    }
+
+    async fn roles(&self, user: &Self::User, tokens: &Self::AuthTokens) -> BTreeSet<String> {
+        match &tokens.grants {
+            Some(grants) => grants.roles.iter().cloned().collect(),
+            None => self.inner.user_roles(user),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{routing::get, Extension, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        auth::{config::AuthConfig, layer::AuthLayer, user::CURRENT_USER_ID},
+        builder::app::error_handler,
+        http_client::HttpClientConfig,
+    };
+
+    fn config() -> ServiceTokenConfig {
+        let mut cfg = ServiceTokenConfig::new("s3cr3t");
+        cfg.audience = Some("backend".into());
+        cfg
+    }
+
+    /// Issued token is validated, tampered and foreign tokens are rejected.
+    #[test]
+    fn issue_validate() {
+        let issuer = TokenIssuer::new(config(), Some("frontend")).unwrap();
+        let validator = TokenValidator::from(&config());
+        let token = issuer.issue(Some("alice"));
+        let claims = validator.validate(&token).unwrap();
+        assert_eq!(claims.sub, "frontend");
+        assert_eq!(claims.act, None);
+        assert_eq!(claims.aud, Some(Audience::Single("backend".into())));
+
+        let mut tampered = token.clone();
+        tampered.insert(token.find('.').unwrap() + 2, 'x');
+        assert!(validator.validate(&tampered).is_err());
+
+        let foreign = TokenIssuer::new(ServiceTokenConfig::new("other"), Some("frontend"))
+            .unwrap()
+            .issue(None);
+        assert_eq!(
+            validator.validate(&foreign),
+            Err(TokenError::InvalidSignature)
+        );
+
+        let (expired, _) = issuer.issue_at(None, unix_now() - 3600);
+        assert_eq!(validator.validate(&expired), Err(TokenError::Expired));
+    }
+
+    /// Tokens are cached per subject.
+    #[test]
+    fn cache() {
+        let mut cfg = config();
+        cfg.propagate_user = true;
+        let issuer = TokenIssuer::new(cfg, Some("frontend")).unwrap();
+        let alice = issuer.authorization(Some("alice"));
+        assert_eq!(issuer.authorization(Some("alice")), alice);
+        assert_ne!(issuer.authorization(Some("bob")), alice);
+        assert_ne!(issuer.authorization(None), alice);
+    }
+
+    /// Full chain: mint, send, validate, authorize.
+    #[tokio::test]
+    async fn service_to_service() {
+        // Receiving service.
+        let auth: AuthConfig = serde_json::from_value(serde_json::json!({
+            "users": {
+                "frontend": {"password": "unused", "roles": ["service"]},
+                "alice": {"password": "unused", "roles": ["reader"]},
+            },
+            "roles": {
+                "service": {"permissions": ["ping"]},
+                "reader": {"permissions": ["read"]},
+            },
+        }))
+        .unwrap();
+        let provider = ServiceTokenAuthProvider::from(ConfigAuthProvider::from(auth));
+        let extractor = ServiceTokenAuthExtractor::new(&config());
+        let whoami = |perms: &'static [&'static str]| {
+            get(|Extension(user): Extension<UserId>| async move { user.to_string() })
+                .layer(AuthLayer::new(perms, provider.clone(), extractor.clone()))
+                .handle_error(error_handler)
+        };
+        let app = Router::new()
+            .route("/ping", whoami(&["ping"]))
+            .route("/read", whoami(&["read"]));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        // Calling service.
+        let call = |propagate_user: bool, path: &'static str| async move {
+            let mut cfg = config();
+            cfg.propagate_user = propagate_user;
+            let issuer = TokenIssuer::new(cfg, Some("frontend")).unwrap();
+            let mut client_cfg = HttpClientConfig::default();
+            client_cfg.with_token_issuer(Arc::new(issuer));
+            let client = client_cfg.to_client(None).await.unwrap();
+            let resp = CURRENT_USER_ID
+                .scope(
+                    Some("alice".into()),
+                    client.get(format!("http://{addr}{path}")).send(),
+                )
+                .await
+                .unwrap();
+            (resp.status(), resp.text().await.unwrap())
+        };
+        assert_eq!(
+            call(false, "/ping").await,
+            (StatusCode::OK, "frontend".into())
+        );
+        assert_eq!(call(false, "/read").await.0, StatusCode::FORBIDDEN);
+        assert_eq!(call(true, "/read").await, (StatusCode::OK, "alice".into()));
+        assert_eq!(call(true, "/ping").await.0, StatusCode::FORBIDDEN);
+
+        // Request without token.
+        let resp = reqwest::get(format!("http://{addr}/ping")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    /// Sign arbitrary claims using HS256.
+    fn hs256(secret: &str, claims: serde_json::Value) -> String {
+        let payload = serde_json::to_vec(&claims).unwrap();
+        let signing_input = format!("{}.{}", B64.encode(JOSE_HEADER), B64.encode(payload));
+        let signature = sign(secret.as_bytes(), signing_input.as_bytes());
+        format!("{signing_input}.{}", B64.encode(signature))
+    }
+
+    /// Not-before timestamp, issuer allow list and empty secrets are checked.
+    #[test]
+    fn nbf_issuer_secret() {
+        let mut cfg = config();
+        cfg.leeway = Duration::from_secs(30);
+        let validator = TokenValidator::from(&cfg);
+        let token = |iss: &str, nbf: u64| {
+            hs256(
+                "s3cr3t",
+                serde_json::json!({
+                    "iss": iss,
+                    "sub": iss,
+                    "aud": "backend",
+                    "iat": unix_now(),
+                    "nbf": nbf,
+                    "exp": unix_now() + 600,
+                }),
+            )
+        };
+        assert!(validator.validate(&token("idp", unix_now() + 10)).is_ok());
+        assert_eq!(
+            validator.validate(&token("idp", unix_now() + 300)),
+            Err(TokenError::NotYetValid)
+        );
+
+        cfg.allowed_issuers = ["frontend".to_string()].into();
+        let validator = TokenValidator::from(&cfg);
+        assert!(validator.validate(&token("frontend", unix_now())).is_ok());
+        assert_eq!(
+            validator.validate(&token("idp", unix_now())),
+            Err(TokenError::InvalidIssuer("idp".into()))
+        );
+
+        let err = serde_json::from_value::<ServiceTokenConfig>(serde_json::json!({"secret": ""}))
+            .unwrap_err();
+        assert!(err.to_string().contains("secret is empty"), "{err}");
+        assert_eq!(
+            TokenIssuer::new(ServiceTokenConfig::new(""), Some("frontend")).unwrap_err(),
+            TokenError::EmptySecret
+        );
+    }
+
+    /// Shared secret is only required for issuing tokens and validating `HS256` tokens.
+    #[test]
+    fn optional_secret() {
+        let cfg: ServiceTokenConfig =
+            serde_json::from_value(serde_json::json!({"jwks": {"url": "http://idp/jwks"}}))
+                .unwrap();
+        assert_eq!(cfg.secret, None);
+        assert_eq!(cfg.validate(), Ok(()));
+        assert_eq!(
+            TokenIssuer::new(cfg.clone(), Some("frontend")).unwrap_err(),
+            TokenError::MissingSecret
+        );
+        let token = TokenIssuer::new(config(), Some("frontend"))
+            .unwrap()
+            .issue(None);
+        assert_eq!(
+            TokenValidator::from(&cfg).validate(&token),
+            Err(TokenError::InvalidSignature)
+        );
+
+        let mut hs256_cfg = cfg.clone();
+        hs256_cfg.allow_hs256_with_jwks = true;
+        assert_eq!(hs256_cfg.validate(), Err(TokenError::MissingSecret));
+
+        let cfg: ServiceTokenConfig = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert_eq!(cfg.validate(), Err(TokenError::MissingSecret));
+    }
+
+    /// Token role names are used as is only if explicitly allowed.
+    #[test]
+    fn role_pass_through() {
+        let claims = serde_json::json!({"roles": ["idp-writer", "admin"]});
+        let mut mapping: ClaimMappingConfig = serde_json::from_value(serde_json::json!({
+            "roles_claim": "roles",
+            "default_roles": ["guest"],
+        }))
+        .unwrap();
+        assert_eq!(mapping.grants(&claims).roles, ["guest".to_string()].into());
+
+        mapping.pass_through_roles = true;
+        mapping.role_mapping = [("idp-writer".to_string(), "writer".to_string())].into();
+        assert_eq!(
+            mapping.grants(&claims).roles,
+            [
+                "admin".to_string(),
+                "guest".to_string(),
+                "writer".to_string()
+            ]
+            .into()
+        );
+    }
+
+    /// Roles and permissions are derived from claims, without a user database entry.
+    #[tokio::test]
+    async fn claim_mapping() {
+        let auth: AuthConfig = serde_json::from_value(serde_json::json!({
+            "roles": {
+                "writer": {"permissions": ["write"]},
+                "admin": {"super_user": true},
+            },
+        }))
+        .unwrap();
+        let mut cfg = config();
+        cfg.claim_mapping = Some(
+            serde_json::from_value(serde_json::json!({
+                "roles_claim": "realm_access.roles",
+                "permissions_claim": "scope",
+                "role_mapping": {"idp-writer": "writer", "idp-reader": "reader"},
+            }))
+            .unwrap(),
+        );
+        let extractor = ServiceTokenAuthExtractor::new(&cfg);
+        let provider = ServiceTokenAuthProvider::from(ConfigAuthProvider::from(auth));
+        let app = ["read", "write", "delete"]
+            .into_iter()
+            .fold(Router::new(), |app, perm| {
+                let perms: &'static [&'static str] = Box::leak(Box::new([perm]));
+                app.route(
+                    &format!("/{perm}"),
+                    get(|Extension(user): Extension<UserId>| async move { user.to_string() })
+                        .layer(AuthLayer::new(perms, provider.clone(), extractor.clone()))
+                        .handle_error(error_handler),
+                )
+            });
+        let token = hs256(
+            "s3cr3t",
+            serde_json::json!({
+                "iss": "idp",
+                "sub": "partner",
+                "aud": "backend",
+                "iat": unix_now(),
+                "exp": unix_now() + 60,
+                "realm_access": {"roles": ["idp-writer", "admin"]},
+                "scope": "read profile",
+            }),
+        );
+        let call = |path: &'static str| {
+            let req = Request::get(path)
+                .header(AUTHORIZATION, format!("Bearer {token}"))
+                .body(Body::empty())
+                .unwrap();
+            app.clone().oneshot(req)
+        };
+        let (claims, raw) = TokenValidator::from(&cfg).validate_raw(&token).unwrap();
+        assert_eq!(claims.grants, None);
+        assert_eq!(
+            cfg.claim_mapping.as_ref().unwrap().grants(&raw),
+            TokenGrants {
+                roles: ["writer".to_string()].into(),
+                permissions: ["profile".to_string(), "read".to_string()].into(),
+            }
+        );
+        for (path, status) in [
+            ("/read", StatusCode::OK),
+            ("/write", StatusCode::OK),
+            // Unmapped role is ignored.
+            ("/delete", StatusCode::FORBIDDEN),
+        ] {
+            assert_eq!(call(path).await.unwrap().status(), status, "{path}");
+        }
+        let resp = call("/read").await.unwrap();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"partner");
+
+        // Without claim mapping, subject must exist in user database.
+        let app = Router::new().route(
+            "/read",
+            get(|| async {})
+                .layer(AuthLayer::new(
+                    &["read"],
+                    provider.clone(),
+                    ServiceTokenAuthExtractor::new(&config()),
+                ))
+                .handle_error(error_handler),
+        );
+        let req = Request::get("/read")
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+}
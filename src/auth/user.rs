@@ -2,9 +2,19 @@
 
 use std::{
     borrow::{Borrow, BorrowMut},
+    collections::BTreeSet,
+    convert::Infallible,
     ops::{Deref, DerefMut},
 };
 
+use async_trait::async_trait;
+use axum::{extract::FromRequestParts, http::request::Parts};
+
+tokio::task_local! {
+    /// Authenticated user of currently executing request, if any.
+    pub static CURRENT_USER_ID: Option<UserId>;
+}
+
 /// User ID.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 #[repr(transparent)]
@@ -47,3 +57,135 @@ impl BorrowMut<str> for UserId {
         &mut self.0
     }
 }
+
+/// Caller of current request.
+///
+/// Added to request extensions by [`crate::AuthLayer`], and can be used as an extractor in
+/// handlers. Requests which did not pass through authentication, such as those to `no_auth`
+/// handlers, get an anonymous value.
+///
+/// # Example
+///
+/// ```
+/// use uxum::prelude::*;
+///
+/// #[handler]
+/// async fn whoami(user: CurrentUser) -> String {
+///     match user.id {
+///         Some(id) => format!("{}: {:?}", id.as_str(), user.roles),
+///         None => "anonymous".into(),
+///     }
+/// }
+/// # fn main() {}
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CurrentUser {
+    /// User ID, if known.
+    pub id: Option<UserId>,
+    /// Roles granted to user, as reported by [`crate::AuthProvider::roles`].
+    pub roles: BTreeSet<String>,
+    /// Permissions required by handler, all of which were granted to user.
+    pub permissions: &'static [&'static str],
+}
+
+impl CurrentUser {
+    /// Check whether user is anonymous.
+    #[must_use]
+    pub fn is_anonymous(&self) -> bool {
+        self.id.is_none()
+    }
+
+    /// Check whether a role is granted.
+    #[must_use]
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.contains(role)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CurrentUser
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Self>().cloned().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header::AUTHORIZATION, Request},
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        auth::{
+            AuthConfig, AuthLayer, BasicAuthExtractor, ConfigAuthProvider, NoOpAuthExtractor,
+            NoOpAuthProvider,
+        },
+        builder::app::error_handler,
+    };
+
+    async fn whoami(user: CurrentUser) -> String {
+        format!(
+            "{}:{}:{}",
+            user.id.as_deref().map_or("-", String::as_str),
+            user.roles.into_iter().collect::<Vec<_>>().join(","),
+            user.permissions.join(","),
+        )
+    }
+
+    /// Caller identity is available to handlers, and is anonymous without authentication.
+    #[tokio::test]
+    async fn current_user() {
+        let auth: AuthConfig = serde_json::from_value(serde_json::json!({
+            "users": {"alice": {"password": "pwd", "roles": ["reader"]}},
+            "roles": {"reader": {"permissions": ["read"]}},
+        }))
+        .unwrap();
+        let app = Router::new()
+            .route(
+                "/basic",
+                get(whoami)
+                    .layer(AuthLayer::new(
+                        &["read"],
+                        ConfigAuthProvider::from(auth),
+                        BasicAuthExtractor::default(),
+                    ))
+                    .handle_error(error_handler),
+            )
+            .route(
+                "/noop",
+                get(whoami)
+                    .layer(AuthLayer::new(&[], NoOpAuthProvider, NoOpAuthExtractor))
+                    .handle_error(error_handler),
+            )
+            .route("/public", get(whoami));
+        let call = |path: &str| {
+            let req = Request::get(path)
+                // alice:pwd
+                .header(AUTHORIZATION, "Basic YWxpY2U6cHdk")
+                .body(Body::empty())
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let resp = app.oneshot(req).await.unwrap();
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+        assert_eq!(call("/basic").await, "alice:reader:read");
+        assert_eq!(call("/noop").await, "-::");
+        assert_eq!(call("/public").await, "-::");
+    }
+}
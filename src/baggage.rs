@@ -0,0 +1,298 @@
+//! Access to OpenTelemetry baggage carried by current request.
+//!
+//! Baggage is extracted from `baggage` header of incoming requests, and injected into requests
+//! made by HTTP clients built with [`crate::AppBuilder::http_client`]. Number of entries and total
+//! size of baggage accepted from callers is limited by [`BaggageConfig`].
+//!
+//! Baggage propagation is only enabled when tracing is configured.
+
+use opentelemetry::{
+    baggage::{BaggageExt, KeyValueMetadata},
+    propagation::{text_map_propagator::FieldIter, Extractor, Injector, TextMapPropagator},
+    Context, KeyValue,
+};
+use opentelemetry_sdk::propagation::BaggagePropagator;
+use serde::{Deserialize, Serialize};
+use tracing::{warn, Span};
+use tracing_opentelemetry::{OpenTelemetrySpanExt, OtelData};
+use tracing_subscriber::{registry::LookupSpan, Registry};
+
+/// Prefix of span attributes created from baggage entries.
+const ATTRIBUTE_PREFIX: &str = "baggage.";
+
+/// Get value of baggage entry for current request.
+#[must_use]
+pub fn get(key: &str) -> Option<String> {
+    Span::current()
+        .context()
+        .baggage()
+        .get(key)
+        .map(|value| value.as_str().into_owned())
+}
+
+/// Get all baggage entries for current request, sorted by key.
+#[must_use]
+pub fn entries() -> Vec<(String, String)> {
+    let mut entries: Vec<_> = Span::current()
+        .context()
+        .baggage()
+        .iter()
+        .map(|(key, (value, _))| (key.to_string(), value.as_str().into_owned()))
+        .collect();
+    entries.sort_unstable();
+    entries
+}
+
+/// Baggage propagation configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct BaggageConfig {
+    /// Propagate baggage.
+    #[serde(default = "crate::util::default_true")]
+    enabled: bool,
+    /// Maximum number of entries accepted from incoming requests.
+    #[serde(default = "BaggageConfig::default_max_entries")]
+    max_entries: usize,
+    /// Maximum total size of keys and values accepted from incoming requests, in bytes.
+    #[serde(default = "BaggageConfig::default_max_bytes")]
+    max_bytes: usize,
+    /// Baggage entries to record as attributes of server span.
+    ///
+    /// Attribute names are prefixed with `baggage.`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    span_attributes: Vec<String>,
+}
+
+impl Default for BaggageConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_entries: Self::default_max_entries(),
+            max_bytes: Self::default_max_bytes(),
+            span_attributes: Vec::new(),
+        }
+    }
+}
+
+impl BaggageConfig {
+    /// Default value for [`Self::max_entries`].
+    #[must_use]
+    #[inline]
+    fn default_max_entries() -> usize {
+        16
+    }
+
+    /// Default value for [`Self::max_bytes`].
+    #[must_use]
+    #[inline]
+    fn default_max_bytes() -> usize {
+        1024
+    }
+
+    /// Enable or disable baggage propagation.
+    #[must_use]
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// Set limits on baggage accepted from incoming requests.
+    #[must_use]
+    pub fn with_limits(mut self, max_entries: usize, max_bytes: usize) -> Self {
+        self.max_entries = max_entries;
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Set baggage entries to record as attributes of server span.
+    #[must_use]
+    pub fn with_span_attributes<I, S>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.span_attributes = keys.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Baggage entries to record as attributes of server span.
+    #[must_use]
+    pub(crate) fn span_attributes(&self) -> &[String] {
+        &self.span_attributes
+    }
+
+    /// Build propagator, if baggage propagation is enabled.
+    #[must_use]
+    pub(crate) fn propagator(&self) -> Option<LimitedBaggagePropagator> {
+        self.enabled.then(|| LimitedBaggagePropagator {
+            inner: BaggagePropagator::new(),
+            max_entries: self.max_entries,
+            max_bytes: self.max_bytes,
+        })
+    }
+}
+
+/// Baggage propagator enforcing limits on extracted entries.
+#[derive(Debug)]
+pub(crate) struct LimitedBaggagePropagator {
+    /// Standard W3C baggage propagator.
+    inner: BaggagePropagator,
+    /// Maximum number of extracted entries.
+    max_entries: usize,
+    /// Maximum total size of extracted keys and values.
+    max_bytes: usize,
+}
+
+impl TextMapPropagator for LimitedBaggagePropagator {
+    fn inject_context(&self, cx: &Context, injector: &mut dyn Injector) {
+        self.inner.inject_context(cx, injector);
+    }
+
+    fn extract_with_context(&self, cx: &Context, extractor: &dyn Extractor) -> Context {
+        let cx = self.inner.extract_with_context(cx, extractor);
+        let baggage = cx.baggage();
+        let mut entries: Vec<_> = baggage.iter().collect();
+        entries.sort_unstable_by_key(|(key, _)| *key);
+        let mut kept = Vec::with_capacity(entries.len().min(self.max_entries));
+        let mut bytes = 0;
+        for (key, (value, metadata)) in entries {
+            let value = value.as_str();
+            let size = key.as_str().len() + value.len();
+            if kept.len() >= self.max_entries || bytes + size > self.max_bytes {
+                continue;
+            }
+            bytes += size;
+            kept.push(KeyValueMetadata::new(
+                key.clone(),
+                value.into_owned(),
+                metadata.clone(),
+            ));
+        }
+        let dropped = baggage.len() - kept.len();
+        if dropped == 0 {
+            return cx;
+        }
+        warn!(
+            dropped,
+            max_entries = self.max_entries,
+            max_bytes = self.max_bytes,
+            "dropped excess incoming baggage entries"
+        );
+        cx.with_cleared_baggage().with_baggage(kept)
+    }
+
+    fn fields(&self) -> FieldIter<'_> {
+        self.inner.fields()
+    }
+}
+
+/// Record allowlisted baggage entries as attributes of a span.
+pub(crate) fn record_attributes(span: &Span, keys: &[String]) {
+    if keys.is_empty() {
+        return;
+    }
+    let cx = span.context();
+    let attrs: Vec<_> = keys
+        .iter()
+        .filter_map(|key| {
+            cx.baggage().get(key.as_str()).map(|value| {
+                KeyValue::new(
+                    format!("{ATTRIBUTE_PREFIX}{key}"),
+                    value.as_str().into_owned(),
+                )
+            })
+        })
+        .collect();
+    if attrs.is_empty() {
+        return;
+    }
+    span.with_subscriber(|(id, dispatch)| {
+        let Some(span) = dispatch
+            .downcast_ref::<Registry>()
+            .and_then(|registry| registry.span(id))
+        else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(data) = extensions.get_mut::<OtelData>() {
+            data.builder
+                .attributes
+                .get_or_insert_with(Vec::new)
+                .extend(attrs);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::TracerProvider;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    fn extract(config: &BaggageConfig, header: &str) -> Vec<(String, String)> {
+        let headers = HashMap::from([("baggage".to_string(), header.to_string())]);
+        let cx = config.propagator().unwrap().extract(&headers);
+        let mut entries: Vec<_> = cx
+            .baggage()
+            .iter()
+            .map(|(key, (value, _))| (key.to_string(), value.to_string()))
+            .collect();
+        entries.sort_unstable();
+        entries
+    }
+
+    /// Excess entries are dropped according to configured limits.
+    #[test]
+    fn limits() {
+        let config = BaggageConfig::default().with_limits(2, 1024);
+        assert_eq!(
+            extract(&config, "c=3,a=1,b=2"),
+            [("a".into(), "1".into()), ("b".into(), "2".into())]
+        );
+        let config = BaggageConfig::default().with_limits(16, 8);
+        assert_eq!(
+            extract(&config, "tenant=acme,x=1,long=abcdefgh"),
+            [("x".into(), "1".into())]
+        );
+        assert!(BaggageConfig::default()
+            .with_enabled(false)
+            .propagator()
+            .is_none());
+    }
+
+    /// Allowlisted entries are recorded as span attributes.
+    #[test]
+    fn span_attributes() {
+        let provider = TracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let span = tracing::info_span!("request");
+        span.set_parent(
+            Context::new().with_baggage([KeyValue::new("tenant", "acme"), KeyValue::new("x", "1")]),
+        );
+        record_attributes(&span, &["tenant".into(), "missing".into()]);
+        let attrs = span
+            .with_subscriber(|(id, dispatch)| {
+                let registry = dispatch.downcast_ref::<Registry>()?;
+                let span = registry.span(id)?;
+                let ext = span.extensions();
+                ext.get::<OtelData>()?.builder.attributes.clone()
+            })
+            .flatten()
+            .unwrap();
+        let attrs: Vec<_> = attrs
+            .into_iter()
+            .filter(|kv| kv.key.as_str().starts_with(ATTRIBUTE_PREFIX))
+            .collect();
+        assert_eq!(attrs, [KeyValue::new("baggage.tenant", "acme")]);
+        let _entered = span.enter();
+        assert_eq!(get("x").as_deref(), Some("1"));
+        assert_eq!(entries().len(), 2);
+    }
+}
@@ -0,0 +1,612 @@
+//! Batch request endpoint.
+//!
+//! Allows clients to execute several API calls in a single HTTP request. Each sub-request is
+//! dispatched to the in-process router, passing through the full layer stack.
+
+use std::{collections::BTreeMap, net::SocketAddr, time::Duration};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Extension, State},
+    http::{
+        header::{self, HeaderName},
+        HeaderMap, HeaderValue, Method, Request, Response, StatusCode, Uri,
+    },
+    response::IntoResponse,
+    routing::{self, Router},
+    Json,
+};
+use futures::{stream, StreamExt};
+use okapi::{openapi3, schemars::gen::SchemaGenerator};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tower::ServiceExt;
+use tracing::{debug_span, info_span, Instrument};
+
+use crate::{
+    errors::{codes, ErrorCode},
+    layers::client_ip::ClientIp,
+    memory::{self, buffer_body, BufferError},
+};
+
+/// Error type returned by batch endpoint.
+#[derive(Clone, Debug, Error)]
+#[non_exhaustive]
+pub enum BatchError {
+    /// Too many sub-requests in a batch.
+    #[error("Batch contains {size} sub-requests, maximum is {max}")]
+    TooLarge {
+        /// Number of sub-requests.
+        size: usize,
+        /// Configured maximum.
+        max: usize,
+    },
+    /// Batch endpoint was called from within a batch.
+    #[error("Nested batch requests are not allowed")]
+    Recursive,
+    /// Invalid sub-request method.
+    #[error("Invalid sub-request method: {0}")]
+    InvalidMethod(String),
+    /// Invalid sub-request path.
+    #[error("Invalid sub-request path: {0}")]
+    InvalidPath(String),
+    /// Invalid sub-request header.
+    #[error("Invalid sub-request header: {0}")]
+    InvalidHeader(String),
+    /// Sub-request timed out.
+    #[error("Sub-request timed out")]
+    Timeout,
+}
+
+impl BatchError {
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::TooLarge { .. } => codes::BATCH_TOO_LARGE,
+            Self::Recursive => codes::BATCH_RECURSIVE,
+            Self::InvalidMethod(_) | Self::InvalidPath(_) | Self::InvalidHeader(_) => {
+                codes::BATCH_INVALID
+            }
+            Self::Timeout => codes::BATCH_TIMEOUT,
+        }
+    }
+}
+
+impl IntoResponse for BatchError {
+    fn into_response(self) -> Response<Body> {
+        let status = match self {
+            Self::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        let code = self.code();
+        let problem = code
+            .problem(status)
+            .with_type("tag:uxum.github.io,2024:batch")
+            .with_title(self.to_string());
+        (code, problem).into_response()
+    }
+}
+
+/// Marker extension for requests dispatched from a batch.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BatchedRequest;
+
+/// Batch endpoint configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct BatchConfig {
+    /// URL path for batch endpoint.
+    #[serde(default = "BatchConfig::default_path")]
+    path: String,
+    /// Maximum number of sub-requests in a single batch.
+    #[serde(default = "BatchConfig::default_max_size")]
+    max_size: usize,
+    /// Maximum number of sub-requests executed concurrently.
+    #[serde(default = "BatchConfig::default_concurrency")]
+    concurrency: usize,
+    /// Timeout for a single sub-request.
+    #[serde(default = "BatchConfig::default_timeout", with = "humantime_serde")]
+    timeout: Duration,
+    /// Maximum size of a single sub-response body, in bytes.
+    #[serde(default = "BatchConfig::default_max_body_size")]
+    max_body_size: usize,
+    /// Headers copied from outer request into each sub-request.
+    ///
+    /// These cannot be overridden by sub-requests, so that authentication is always performed
+    /// using outer request credentials.
+    #[serde(default = "BatchConfig::default_forward_headers")]
+    forward_headers: Vec<String>,
+    /// Headers copied from sub-responses into batch response.
+    #[serde(default = "BatchConfig::default_response_headers")]
+    response_headers: Vec<String>,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            path: Self::default_path(),
+            max_size: Self::default_max_size(),
+            concurrency: Self::default_concurrency(),
+            timeout: Self::default_timeout(),
+            max_body_size: Self::default_max_body_size(),
+            forward_headers: Self::default_forward_headers(),
+            response_headers: Self::default_response_headers(),
+        }
+    }
+}
+
+impl BatchConfig {
+    /// Default value for [`Self::path`].
+    #[must_use]
+    #[inline]
+    fn default_path() -> String {
+        "/batch".into()
+    }
+
+    /// Default value for [`Self::max_size`].
+    #[must_use]
+    #[inline]
+    fn default_max_size() -> usize {
+        16
+    }
+
+    /// Default value for [`Self::concurrency`].
+    #[must_use]
+    #[inline]
+    fn default_concurrency() -> usize {
+        4
+    }
+
+    /// Default value for [`Self::timeout`].
+    #[must_use]
+    #[inline]
+    fn default_timeout() -> Duration {
+        Duration::from_secs(30)
+    }
+
+    /// Default value for [`Self::max_body_size`].
+    #[must_use]
+    #[inline]
+    fn default_max_body_size() -> usize {
+        1024 * 1024
+    }
+
+    /// Default value for [`Self::forward_headers`].
+    #[must_use]
+    #[inline]
+    fn default_forward_headers() -> Vec<String> {
+        vec![
+            "authorization".into(),
+            "cookie".into(),
+            "accept-language".into(),
+        ]
+    }
+
+    /// Default value for [`Self::response_headers`].
+    #[must_use]
+    #[inline]
+    fn default_response_headers() -> Vec<String> {
+        vec![
+            "content-type".into(),
+            "etag".into(),
+            "location".into(),
+            "retry-after".into(),
+        ]
+    }
+
+    /// URL path for batch endpoint.
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Set URL path for batch endpoint.
+    #[must_use]
+    pub fn with_path(mut self, path: impl ToString) -> Self {
+        self.path = path.to_string();
+        self
+    }
+
+    /// Set maximum number of sub-requests in a single batch.
+    #[must_use]
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Set maximum number of sub-requests executed concurrently.
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Set timeout for a single sub-request.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Build router containing batch endpoint.
+    ///
+    /// Sub-requests are dispatched to `app`.
+    pub(crate) fn build_router(&self, app: Router) -> Router {
+        let _span = debug_span!("build_batch").entered();
+        let header_names = |names: &[String]| {
+            names
+                .iter()
+                .filter_map(|name| HeaderName::try_from(name.as_str()).ok())
+                .collect()
+        };
+        let state = BatchState {
+            app,
+            path: self.path.clone(),
+            max_size: self.max_size,
+            concurrency: self.concurrency.max(1),
+            timeout: self.timeout,
+            max_body_size: self.max_body_size,
+            forward_headers: header_names(&self.forward_headers),
+            response_headers: header_names(&self.response_headers),
+        };
+        Router::new()
+            .route(&self.path, routing::post(batch))
+            .with_state(state)
+    }
+}
+
+/// Shared state of batch endpoint.
+#[derive(Clone)]
+struct BatchState {
+    /// Router to dispatch sub-requests to.
+    app: Router,
+    /// URL path for batch endpoint.
+    path: String,
+    /// Maximum number of sub-requests in a single batch.
+    max_size: usize,
+    /// Maximum number of sub-requests executed concurrently.
+    concurrency: usize,
+    /// Timeout for a single sub-request.
+    timeout: Duration,
+    /// Maximum size of a single sub-response body.
+    max_body_size: usize,
+    /// Headers copied from outer request into each sub-request.
+    forward_headers: Vec<HeaderName>,
+    /// Headers copied from sub-responses into batch response.
+    response_headers: Vec<HeaderName>,
+}
+
+/// Single sub-request in a batch.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[non_exhaustive]
+pub struct BatchItem {
+    /// HTTP method.
+    pub method: String,
+    /// URL path, including optional query string.
+    pub path: String,
+    /// HTTP request headers.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// JSON request body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<serde_json::Value>,
+}
+
+/// Single sub-response in a batch.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+#[non_exhaustive]
+pub struct BatchItemResponse {
+    /// HTTP status code.
+    pub status: u16,
+    /// Selected HTTP response headers.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Response body.
+    ///
+    /// JSON bodies are embedded as-is, other bodies are embedded as strings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<serde_json::Value>,
+}
+
+/// Batch endpoint handler.
+async fn batch(
+    State(state): State<BatchState>,
+    batched: Option<Extension<BatchedRequest>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    client_ip: Option<Extension<ClientIp>>,
+    headers: HeaderMap,
+    Json(items): Json<Vec<BatchItem>>,
+) -> Result<Json<Vec<BatchItemResponse>>, BatchError> {
+    if batched.is_some() {
+        return Err(BatchError::Recursive);
+    }
+    if items.len() > state.max_size {
+        return Err(BatchError::TooLarge {
+            size: items.len(),
+            max: state.max_size,
+        });
+    }
+    let state = &state;
+    let headers = &headers;
+    let client_ip = client_ip.map(|Extension(ip)| ip);
+    let responses = stream::iter(items.into_iter().enumerate())
+        .map(|(index, item)| {
+            let span = info_span!("batch_item", index, method = item.method, path = item.path);
+            async move {
+                let resp = match state.sub_request(item, headers, connect_info, client_ip) {
+                    Ok(req) => state.dispatch(req).await,
+                    Err(err) => err.into_response(),
+                };
+                state.sub_response(resp).await
+            }
+            .instrument(span)
+        })
+        .buffered(state.concurrency)
+        .collect()
+        .await;
+    Ok(Json(responses))
+}
+
+impl BatchState {
+    /// Build sub-request from batch item.
+    fn sub_request(
+        &self,
+        item: BatchItem,
+        outer_headers: &HeaderMap,
+        connect_info: Option<ConnectInfo<SocketAddr>>,
+        client_ip: Option<ClientIp>,
+    ) -> Result<Request<Body>, BatchError> {
+        let method = Method::from_bytes(item.method.to_ascii_uppercase().as_bytes())
+            .map_err(|_| BatchError::InvalidMethod(item.method.clone()))?;
+        let uri: Uri = item
+            .path
+            .parse()
+            .map_err(|_| BatchError::InvalidPath(item.path.clone()))?;
+        if uri.scheme().is_some() || !uri.path().starts_with('/') {
+            return Err(BatchError::InvalidPath(item.path));
+        }
+        if uri.path().trim_end_matches('/') == self.path.trim_end_matches('/') {
+            return Err(BatchError::Recursive);
+        }
+        let body = match item.body {
+            Some(ref value) => Body::from(value.to_string()),
+            None => Body::empty(),
+        };
+        let mut req = Request::new(body);
+        *req.method_mut() = method;
+        *req.uri_mut() = uri;
+        let req_headers = req.headers_mut();
+        for (key, value) in &item.headers {
+            let name = HeaderName::try_from(key.as_str())
+                .map_err(|_| BatchError::InvalidHeader(key.clone()))?;
+            if self.forward_headers.contains(&name) {
+                continue;
+            }
+            let value = HeaderValue::try_from(value.as_str())
+                .map_err(|_| BatchError::InvalidHeader(key.clone()))?;
+            req_headers.append(name, value);
+        }
+        for name in &self.forward_headers {
+            for value in outer_headers.get_all(name) {
+                req_headers.append(name.clone(), value.clone());
+            }
+        }
+        if item.body.is_some() && !req_headers.contains_key(header::CONTENT_TYPE) {
+            req_headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+        }
+        let ext = req.extensions_mut();
+        ext.insert(BatchedRequest);
+        if let Some(connect_info) = connect_info {
+            ext.insert(connect_info);
+        }
+        // Client address was already resolved from outer request headers.
+        if let Some(client_ip) = client_ip {
+            ext.insert(client_ip);
+        }
+        Ok(req)
+    }
+
+    /// Execute sub-request against application router.
+    async fn dispatch(&self, req: Request<Body>) -> Response<Body> {
+        match tokio::time::timeout(self.timeout, self.app.clone().oneshot(req)).await {
+            Ok(Ok(resp)) => resp,
+            Ok(Err(never)) => match never {},
+            Err(_) => BatchError::Timeout.into_response(),
+        }
+    }
+
+    /// Convert sub-response into batch response item.
+    async fn sub_response(&self, resp: Response<Body>) -> BatchItemResponse {
+        let (parts, body) = resp.into_parts();
+        let headers = self
+            .response_headers
+            .iter()
+            .filter_map(|name| {
+                let value = parts.headers.get(name)?.to_str().ok()?;
+                Some((name.as_str().to_owned(), value.to_owned()))
+            })
+            .collect();
+        let is_json = parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|val| val.to_str().ok())
+            .is_some_and(|val| val.contains("json"));
+        let body = match buffer_body(memory::global(), "batch", body, self.max_body_size).await {
+            Ok((bytes, _)) if bytes.is_empty() => None,
+            Ok((bytes, _)) if is_json => serde_json::from_slice(&bytes)
+                .ok()
+                .or_else(|| Some(String::from_utf8_lossy(&bytes).into())),
+            Ok((bytes, _)) => Some(String::from_utf8_lossy(&bytes).into()),
+            Err(err) => {
+                let (status, message) = match err {
+                    BufferError::Memory(err) => (StatusCode::SERVICE_UNAVAILABLE, err.to_string()),
+                    BufferError::TooLarge(limit) => (
+                        StatusCode::BAD_GATEWAY,
+                        format!("Response body exceeds size limit of {limit} bytes"),
+                    ),
+                    BufferError::Read(err) => (StatusCode::BAD_GATEWAY, err),
+                };
+                return BatchItemResponse {
+                    status: status.as_u16(),
+                    headers: BTreeMap::new(),
+                    body: Some(message.into()),
+                };
+            }
+        };
+        BatchItemResponse {
+            status: parts.status.as_u16(),
+            headers,
+            body,
+        }
+    }
+}
+
+/// Generate OpenAPI operation for batch endpoint.
+pub(crate) fn openapi_operation(gen: &mut SchemaGenerator) -> openapi3::Operation {
+    let json_content = |schema| {
+        okapi::map! {
+            "application/json".into() => openapi3::MediaType {
+                schema: Some(schema),
+                ..Default::default()
+            },
+        }
+    };
+    let problem = |description: &str| {
+        openapi3::RefOr::Object(openapi3::Response {
+            description: description.into(),
+            ..Default::default()
+        })
+    };
+    openapi3::Operation {
+        operation_id: Some("batch".into()),
+        summary: Some("Execute multiple requests".into()),
+        description: Some(
+            "Executes multiple API requests in a single call. Sub-requests are authenticated \
+             using credentials of the outer request. Responses are returned in the same order \
+             as requests."
+                .into(),
+        ),
+        request_body: Some(openapi3::RefOr::Object(openapi3::RequestBody {
+            content: json_content(gen.subschema_for::<Vec<BatchItem>>().into_object()),
+            required: true,
+            ..Default::default()
+        })),
+        responses: openapi3::Responses {
+            responses: okapi::map! {
+                "200".into() => openapi3::RefOr::Object(openapi3::Response {
+                    description: "Sub-responses, in request order".into(),
+                    content: json_content(
+                        gen.subschema_for::<Vec<BatchItemResponse>>().into_object(),
+                    ),
+                    ..Default::default()
+                }),
+                "400".into() => problem("Invalid or nested batch request"),
+                "413".into() => problem("Too many sub-requests"),
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{extract::Path, http::HeaderMap};
+
+    use super::*;
+
+    fn app() -> Router {
+        let inner = Router::new()
+            .route(
+                "/hello/:name",
+                routing::get(|Path(name): Path<String>| async move { format!("Hello {name}") }),
+            )
+            .route(
+                "/echo",
+                routing::post(|Json(value): Json<serde_json::Value>| async move { Json(value) }),
+            )
+            .route(
+                "/whoami",
+                routing::get(|headers: HeaderMap| async move {
+                    headers
+                        .get(header::AUTHORIZATION)
+                        .map(|val| val.to_str().unwrap().to_owned())
+                        .unwrap_or_default()
+                }),
+            )
+            .route(
+                "/slow",
+                routing::get(|| async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    "late"
+                }),
+            );
+        let cfg = BatchConfig::default()
+            .with_max_size(5)
+            .with_timeout(Duration::from_millis(100));
+        let batch = cfg.build_router(inner.clone());
+        inner.merge(batch)
+    }
+
+    async fn call(body: serde_json::Value) -> (StatusCode, serde_json::Value) {
+        let req = Request::post("/batch")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::AUTHORIZATION, "Bearer outer")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let resp = app().oneshot(req).await.unwrap();
+        let status = resp.status();
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    /// Sub-requests are executed, responses are in request order.
+    #[tokio::test]
+    async fn ordered_responses() {
+        let (status, body) = call(serde_json::json!([
+            {"method": "GET", "path": "/hello/world"},
+            {"method": "post", "path": "/echo", "body": {"a": 1}},
+            {"method": "GET", "path": "/whoami", "headers": {"authorization": "Bearer inner"}},
+            {"method": "GET", "path": "/slow"},
+            {"method": "GET", "path": "/missing"},
+        ]))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let statuses: Vec<_> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["status"].as_u64().unwrap())
+            .collect();
+        assert_eq!(statuses, [200, 200, 200, 504, 404]);
+        assert_eq!(body[0]["body"], "Hello world");
+        assert_eq!(body[1]["body"], serde_json::json!({"a": 1}));
+        assert_eq!(body[1]["headers"]["content-type"], "application/json");
+        assert_eq!(body[2]["body"], "Bearer outer");
+    }
+
+    /// Nested batches and oversized batches are rejected.
+    #[tokio::test]
+    async fn rejections() {
+        let (status, body) = call(serde_json::json!([
+            {"method": "POST", "path": "/batch", "body": []},
+            {"method": "BAD METHOD", "path": "/hello/x"},
+        ]))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["status"], 400);
+        assert_eq!(body[1]["status"], 400);
+
+        let item = serde_json::json!({"method": "GET", "path": "/hello/x"});
+        let (status, _) = call(serde_json::Value::Array(vec![item; 6])).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -0,0 +1,30 @@
+//! Convert binary access log files to newline-delimited JSON.
+//!
+//! Usage: `uxum-logcat [FILE]...`. Reads standard input if no files are specified.
+
+use std::{
+    env, fs,
+    io::{self, BufReader, BufWriter},
+    process::ExitCode,
+};
+
+fn main() -> ExitCode {
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let paths: Vec<_> = env::args_os().skip(1).collect();
+    let result = if paths.is_empty() {
+        uxum::access_log_to_ndjson(BufReader::new(io::stdin().lock()), &mut out).map(|_| ())
+    } else {
+        paths.iter().try_for_each(|path| {
+            let file = fs::File::open(path)?;
+            uxum::access_log_to_ndjson(BufReader::new(file), &mut out).map(|_| ())
+        })
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("uxum-logcat: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,50 +1,115 @@
 use std::{
-    any::Any,
+    any::{Any, TypeId},
     borrow::Borrow,
-    collections::{BTreeMap, HashSet},
+    collections::{BTreeMap, HashMap},
     convert::Infallible,
+    future::Future,
+    mem,
+    path::PathBuf,
+    sync::Arc,
+    time::SystemTime,
 };
 
+#[cfg(feature = "oidc")]
+use axum::error_handling::HandleErrorLayer;
 use axum::{
-    body::Body,
+    body::{Body, Bytes},
+    handler::Handler,
     http::{
         header::{self, HeaderValue},
-        StatusCode,
+        Method, StatusCode,
     },
     response::IntoResponse,
     routing::{MethodRouter, Router},
     BoxError,
 };
 use hyper::{Request, Response};
-use okapi::{openapi3, schemars::gen::SchemaGenerator};
+use mime::Mime;
+use okapi::{
+    openapi3,
+    schemars::gen::{SchemaGenerator, SchemaSettings},
+};
 use thiserror::Error;
 use tower::{builder::ServiceBuilder, util::BoxCloneService, ServiceExt};
 use tower_http::{
     catch_panic::CatchPanicLayer,
+    compression::CompressionBody,
+    cors::CorsLayer,
     request_id::MakeRequestUuid,
     set_header::SetResponseHeaderLayer,
     trace::{DefaultOnRequest, DefaultOnResponse, TraceLayer},
     LatencyUnit, ServiceBuilderExt,
 };
-use tracing::{debug, debug_span, info, info_span, warn};
+use tracing::{debug, debug_span, error, info, info_span, warn};
 
+#[cfg(feature = "oidc")]
+use crate::{apidoc::PublicApiDocConfig, auth::OidcState};
 use crate::{
-    apidoc::{ApiDocBuilder, ApiDocError},
+    apidoc::{ApiDocBuilder, ApiDocError, ApiVisibility},
     auth::{
-        AuthExtractor, AuthLayer, AuthProvider, BasicAuthExtractor, ConfigAuthProvider,
-        HeaderAuthExtractor, NoOpAuthExtractor, NoOpAuthProvider,
+        ApiKeyAuthExtractor, ApiKeyAuthProvider, ApiKeyError, ApiKeys, AuthExtractor, AuthLayer,
+        AuthProvider, BasicAuthExtractor, BearerAuthExtractor, BearerAuthProvider,
+        ConfigAuthProvider, HeaderAuthExtractor, NoOpAuthExtractor, NoOpAuthProvider,
+        ServiceTokenAuthExtractor, ServiceTokenAuthProvider, TokenError, TokenIssuer,
+    },
+    batch::BatchConfig,
+    builder::{
+        fallback::FallbackService,
+        layer::{
+            self, HandlerLayer, HandlerLayerContext, HandlerLayerFactory, HandlerLayerPosition,
+        },
+        routing::{self, RouteShadowing},
     },
-    config::AppConfig,
+    changelog::ApiChange,
+    config::{AppConfig, ConfigError},
+    errors::codes,
+    hooks,
     http_client::{HttpClientConfig, HttpClientError},
+    i18n::LocalizationError,
+    kv::{KeyValueStore, MemoryStore},
     layers::{
-        ext::HandlerName, rate::RateLimitError, request_id::RecordRequestIdLayer,
+        access_log::AccessLogLayer,
+        body_limit::BodyLimitLayer,
+        cache::HandlerSemantics,
+        cb::CircuitBreakerError,
+        compression::NoCompression,
+        contract::ResponseSchemas,
+        cors::{CorsConfig, CorsError, PreflightLayer},
+        decompression::{RequestDecompressionLayer, DEFAULT_MAX_DECOMPRESSED_SIZE},
+        deprecation::{DeprecationConfig, DeprecationError, DeprecationTracker},
+        error_context::ErrorContextLayer,
+        ext::{Deadline, HandlerName},
+        fair::FairQueueError,
+        identity::SuppressIdentity,
+        ip_filter::{IpFilterError, IpFilterLayer},
+        localize::LocalizeLayer,
+        rate::{HandlerRateLimitConfig, RateLimitError},
+        recent_errors::{RecentErrors, RecentErrorsLayer},
+        request_id::RecordRequestIdLayer,
         timeout::TimeoutError,
+        toggle::HandlerToggles,
+        trailing_slash::TrailingSlash,
+        transform::{RequestTransformer, TransformError, TransformLayer},
+    },
+    logging::{
+        access::{AccessLogError, AccessLogSink},
+        span::CustomMakeSpan,
+    },
+    memory::MemoryError,
+    metrics::{ClientMetricsState, MetricsBuilder, MetricsError, MetricsState},
+    normalize::NormalizeRules,
+    persist::StatePersistenceError,
+    probes::{ReadinessCheck, ReadinessCheckSpec},
+    queue::{Job, JobHandler, JobQueue},
+    startup::{
+        StartupError, StartupGraph, StartupNode, StartupNodeKind, StartupSpec, StartupTimeline,
     },
-    logging::span::CustomMakeSpan,
-    metrics::{MetricsBuilder, MetricsError, MetricsState},
     state,
+    static_dir::{StaticDirConfig, StaticDirError, StaticOptions},
     tracing::TracingError,
     util::ResponseExtension,
+    validate::SkipValidation,
+    warmup::WarmupRunner,
 };
 
 /// Error type used in app builder.
@@ -54,21 +119,77 @@ pub enum AppBuilderError {
     /// API doc error.
     #[error(transparent)]
     ApiDoc(#[from] ApiDocError),
+    /// Configuration error.
+    #[error(transparent)]
+    Config(#[from] ConfigError),
     /// Metrics error.
     #[error(transparent)]
     Metrics(#[from] MetricsError),
     /// Tracing error.
     #[error(transparent)]
     Tracing(#[from] TracingError),
-    /// Duplicate handler name.
-    #[error("Duplicate handler name: {0}")]
-    DuplicateHandlerName(&'static str),
+    /// Duplicate handler name, registered by different handlers.
+    #[error("Duplicate handler name {name}, registered in {first} and {second}")]
+    DuplicateHandlerName {
+        /// Handler name.
+        name: &'static str,
+        /// Module of first registration.
+        first: &'static str,
+        /// Module of second registration.
+        second: &'static str,
+    },
+    /// Different handlers registered for the same method and path.
+    #[error("Conflicting handlers for {method} {path}: {first} and {second}")]
+    ConflictingRoute {
+        /// HTTP method.
+        method: Method,
+        /// URL path.
+        path: &'static str,
+        /// Name of first handler.
+        first: &'static str,
+        /// Name of second handler.
+        second: &'static str,
+    },
+    /// Route shadowing detected, with strict routing enabled.
+    #[error("Route shadowing detected: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    RouteShadowing(Vec<RouteShadowing>),
+    /// Access log error.
+    #[error(transparent)]
+    AccessLog(#[from] AccessLogError),
     /// HTTP client error.
     #[error("HTTP client error: {0}")]
     HttpClient(#[from] HttpClientError),
+    /// Static asset directory error.
+    #[error(transparent)]
+    StaticDir(#[from] StaticDirError),
     /// HTTP client is absent from configuration.
     #[error("HTTP client is absent from configuration: {0}")]
     HttpClientAbsent(String),
+    /// Service token error.
+    #[error(transparent)]
+    ServiceToken(#[from] TokenError),
+    /// OpenID Connect login error.
+    #[cfg(feature = "oidc")]
+    #[error(transparent)]
+    Oidc(#[from] crate::auth::OidcError),
+    /// Startup dependency error.
+    #[error(transparent)]
+    Startup(#[from] StartupError),
+    /// API key error.
+    #[error(transparent)]
+    ApiKey(#[from] ApiKeyError),
+    /// Error message catalog error.
+    #[error(transparent)]
+    Localization(#[from] LocalizationError),
+    /// Invalid CORS configuration of a handler.
+    #[error("Invalid CORS configuration of handler {0}: {1}")]
+    Cors(String, CorsError),
+    /// Resilience state persistence error.
+    #[error(transparent)]
+    StatePersistence(#[from] StatePersistenceError),
+    /// Invalid redirect URL of fallback handler.
+    #[error("Invalid fallback redirect URL: {0}")]
+    InvalidFallbackRedirect(String),
 }
 
 /// Builder for application routes.
@@ -85,8 +206,35 @@ pub struct AppBuilder<AuthProv = NoOpAuthProvider, AuthExt = NoOpAuthExtractor>
     config: AppConfig,
     /// Metrics container object.
     metrics: Option<MetricsState>,
+    /// Issuer of service-to-service tokens, shared by HTTP clients.
+    token_issuer: Option<Arc<TokenIssuer>>,
+    /// Lazy states, background tasks and warmup hooks, initialized in dependency order.
+    startup_nodes: Vec<StartupNode>,
+    /// Request body transformers, keyed by handler name.
+    request_transformers: HashMap<String, Vec<RequestTransformer>>,
+    /// Deprecated handler usage tracker, for periodic summary logs.
+    deprecation_tracker: Option<DeprecationTracker>,
+    /// Runtime handler state flags, managed via probe API.
+    handler_toggles: HandlerToggles,
+    /// Filter for registered handlers.
+    handler_filter: Option<HandlerFilter>,
+    /// Custom per-handler layer factories, in registration order.
+    handler_layers: Vec<HandlerLayerFactory>,
+    /// Runtime-managed API keys, if API key authentication is enabled.
+    api_keys: Option<ApiKeys>,
+    /// Key-value store used to persist resilience state and queued jobs across restarts.
+    state_store: Option<Arc<dyn KeyValueStore>>,
+    /// Job handlers, keyed by job type.
+    job_handlers: HashMap<String, JobHandler>,
+    /// Custom readiness checks.
+    readiness_checks: Vec<ReadinessCheck>,
+    /// Custom handler for requests not matching any route.
+    fallback: Option<FallbackService>,
 }
 
+/// Predicate used to exclude some of the registered handlers from the application.
+pub type HandlerFilter = fn(&dyn HandlerExt) -> bool;
+
 impl From<AppConfig> for AppBuilder {
     fn from(value: AppConfig) -> Self {
         Self {
@@ -94,6 +242,18 @@ impl From<AppConfig> for AppBuilder {
             auth_extractor: NoOpAuthExtractor,
             config: value,
             metrics: None,
+            token_issuer: None,
+            startup_nodes: Vec::new(),
+            request_transformers: HashMap::new(),
+            deprecation_tracker: None,
+            handler_toggles: HandlerToggles::default(),
+            handler_filter: None,
+            handler_layers: Vec::new(),
+            api_keys: None,
+            state_store: None,
+            job_handlers: HashMap::new(),
+            readiness_checks: Vec::new(),
+            fallback: None,
         }
     }
 }
@@ -105,6 +265,18 @@ impl Default for AppBuilder<NoOpAuthProvider, NoOpAuthExtractor> {
             auth_extractor: NoOpAuthExtractor,
             config: AppConfig::default(),
             metrics: None,
+            token_issuer: None,
+            startup_nodes: Vec::new(),
+            request_transformers: HashMap::new(),
+            deprecation_tracker: None,
+            handler_toggles: HandlerToggles::default(),
+            handler_filter: None,
+            handler_layers: Vec::new(),
+            api_keys: None,
+            state_store: None,
+            job_handlers: HashMap::new(),
+            readiness_checks: Vec::new(),
+            fallback: None,
         }
     }
 }
@@ -138,6 +310,18 @@ where
             auth_extractor: BasicAuthExtractor::default(),
             config: self.config,
             metrics: self.metrics,
+            token_issuer: self.token_issuer,
+            startup_nodes: self.startup_nodes,
+            request_transformers: self.request_transformers,
+            deprecation_tracker: self.deprecation_tracker,
+            handler_toggles: self.handler_toggles,
+            handler_filter: self.handler_filter,
+            handler_layers: self.handler_layers,
+            api_keys: self.api_keys,
+            state_store: self.state_store,
+            job_handlers: self.job_handlers,
+            readiness_checks: self.readiness_checks,
+            fallback: self.fallback,
         }
     }
 
@@ -149,9 +333,132 @@ where
             auth_extractor: HeaderAuthExtractor::default(),
             config: self.config,
             metrics: self.metrics,
+            token_issuer: self.token_issuer,
+            startup_nodes: self.startup_nodes,
+            request_transformers: self.request_transformers,
+            deprecation_tracker: self.deprecation_tracker,
+            handler_toggles: self.handler_toggles,
+            handler_filter: self.handler_filter,
+            handler_layers: self.handler_layers,
+            api_keys: self.api_keys,
+            state_store: self.state_store,
+            job_handlers: self.job_handlers,
+            readiness_checks: self.readiness_checks,
+            fallback: self.fallback,
+        }
+    }
+
+    /// Enable static bearer token authentication using built-in user and role databases.
+    #[must_use]
+    pub fn with_bearer_auth(self) -> AppBuilder<BearerAuthProvider, BearerAuthExtractor> {
+        AppBuilder {
+            auth_provider: ConfigAuthProvider::from(self.config.auth.clone()).into(),
+            auth_extractor: BearerAuthExtractor::new(&self.config.auth),
+            config: self.config,
+            metrics: self.metrics,
+            token_issuer: self.token_issuer,
+            startup_nodes: self.startup_nodes,
+            request_transformers: self.request_transformers,
+            deprecation_tracker: self.deprecation_tracker,
+            handler_toggles: self.handler_toggles,
+            handler_filter: self.handler_filter,
+            handler_layers: self.handler_layers,
+            api_keys: self.api_keys,
+            state_store: self.state_store,
+            job_handlers: self.job_handlers,
+            readiness_checks: self.readiness_checks,
+            fallback: self.fallback,
         }
     }
 
+    /// Enable authentication using service-to-service tokens.
+    ///
+    /// Token subjects are looked up in built-in user and role databases. If JWKS is configured,
+    /// its key set is refreshed by a background task.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if service tokens are not configured or configuration is inconsistent, or on
+    /// metrics initialization error.
+    pub fn with_service_token_auth(
+        mut self,
+    ) -> Result<AppBuilder<ServiceTokenAuthProvider, ServiceTokenAuthExtractor>, AppBuilderError>
+    {
+        let token_cfg = self
+            .config
+            .auth
+            .service_token
+            .clone()
+            .ok_or(TokenError::NotConfigured)?;
+        token_cfg.validate()?;
+        let mut auth_extractor = ServiceTokenAuthExtractor::new(&token_cfg);
+        if let Some(jwks_cfg) = token_cfg.jwks {
+            let (client_cfg, client_metrics) =
+                self.http_client_config(&jwks_cfg.http_client, true)?;
+            auth_extractor = auth_extractor.with_jwks_client(client_cfg, client_metrics, jwks_cfg);
+            if let Some(jwks) = auth_extractor.jwks().cloned() {
+                self.with_task("jwks_refresh", move || jwks.run());
+            }
+        }
+        Ok(AppBuilder {
+            auth_provider: ConfigAuthProvider::from(self.config.auth.clone()).into(),
+            auth_extractor,
+            config: self.config,
+            metrics: self.metrics,
+            token_issuer: self.token_issuer,
+            startup_nodes: self.startup_nodes,
+            request_transformers: self.request_transformers,
+            deprecation_tracker: self.deprecation_tracker,
+            handler_toggles: self.handler_toggles,
+            handler_filter: self.handler_filter,
+            handler_layers: self.handler_layers,
+            api_keys: self.api_keys,
+            state_store: self.state_store,
+            job_handlers: self.job_handlers,
+            readiness_checks: self.readiness_checks,
+            fallback: self.fallback,
+        })
+    }
+
+    /// Enable authentication using runtime-managed API keys.
+    ///
+    /// Only hashes of keys are persisted in provided store. Roles granted to keys are looked up
+    /// in built-in role database.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if API keys are not configured, or stored keys cannot be loaded.
+    pub fn with_api_key_auth(
+        self,
+        store: Arc<dyn KeyValueStore>,
+    ) -> Result<AppBuilder<ApiKeyAuthProvider, ApiKeyAuthExtractor>, AppBuilderError> {
+        let key_cfg = self
+            .config
+            .auth
+            .api_keys
+            .clone()
+            .ok_or(ApiKeyError::NotConfigured)?;
+        let keys = ApiKeys::new(key_cfg, store)?;
+        Ok(AppBuilder {
+            auth_provider: ApiKeyAuthProvider::new(keys.clone(), self.config.auth.clone()),
+            auth_extractor: keys.clone().into(),
+            config: self.config,
+            metrics: self.metrics,
+            token_issuer: self.token_issuer,
+            startup_nodes: self.startup_nodes,
+            request_transformers: self.request_transformers,
+            deprecation_tracker: self.deprecation_tracker,
+            handler_toggles: self.handler_toggles,
+            handler_filter: self.handler_filter,
+            handler_layers: self.handler_layers,
+            api_keys: Some(keys),
+            state_store: self.state_store,
+            job_handlers: self.job_handlers,
+            readiness_checks: self.readiness_checks,
+            fallback: self.fallback,
+        })
+    }
+
     /// Set custom authentication extractor (front-end).
     #[must_use]
     pub fn with_auth_extractor<E: AuthExtractor>(
@@ -163,6 +470,18 @@ where
             auth_extractor,
             config: self.config,
             metrics: self.metrics,
+            token_issuer: self.token_issuer,
+            startup_nodes: self.startup_nodes,
+            request_transformers: self.request_transformers,
+            deprecation_tracker: self.deprecation_tracker,
+            handler_toggles: self.handler_toggles,
+            handler_filter: self.handler_filter,
+            handler_layers: self.handler_layers,
+            api_keys: self.api_keys,
+            state_store: self.state_store,
+            job_handlers: self.job_handlers,
+            readiness_checks: self.readiness_checks,
+            fallback: self.fallback,
         }
     }
 
@@ -174,6 +493,18 @@ where
             auth_extractor: self.auth_extractor,
             config: self.config,
             metrics: self.metrics,
+            token_issuer: self.token_issuer,
+            startup_nodes: self.startup_nodes,
+            request_transformers: self.request_transformers,
+            deprecation_tracker: self.deprecation_tracker,
+            handler_toggles: self.handler_toggles,
+            handler_filter: self.handler_filter,
+            handler_layers: self.handler_layers,
+            api_keys: self.api_keys,
+            state_store: self.state_store,
+            job_handlers: self.job_handlers,
+            readiness_checks: self.readiness_checks,
+            fallback: self.fallback,
         }
     }
 
@@ -198,6 +529,34 @@ where
         self
     }
 
+    /// Mount static asset directory.
+    ///
+    /// Files from `fs_path` are served under `url_prefix`. Static directories are not included in
+    /// OpenAPI specification.
+    ///
+    /// Alternatively, you can include static directories in [`AppConfig::static_dirs`] section.
+    pub fn with_static_dir(
+        &mut self,
+        url_prefix: impl ToString,
+        fs_path: impl Into<PathBuf>,
+        options: StaticOptions,
+    ) -> &mut Self {
+        self.config
+            .static_dirs
+            .push(StaticDirConfig::new(url_prefix, fs_path, options));
+        self
+    }
+
+    /// Set policy for request paths with trailing slashes.
+    ///
+    /// Alternatively, you can set it in [`RoutingConfig::trailing_slash`] configuration field.
+    ///
+    /// [`RoutingConfig::trailing_slash`]: crate::RoutingConfig::trailing_slash
+    pub fn with_trailing_slash(&mut self, policy: TrailingSlash) -> &mut Self {
+        self.config.routing.trailing_slash = policy;
+        self
+    }
+
     /// Add state to be used in handlers using [`axum::extract::State`].
     pub fn with_state<S>(&mut self, state: S) -> &mut Self
     where
@@ -208,6 +567,232 @@ where
         self
     }
 
+    /// Add state to be initialized asynchronously at startup.
+    ///
+    /// State is initialized in background after building the application, once all of its
+    /// dependencies declared with [`StartupSpec::depends_on`] are initialized. Readiness probe
+    /// does not report the service as ready until all startup nodes are initialized. If
+    /// initialization fails, dependent nodes are skipped, and the service never becomes ready.
+    ///
+    /// See [`AppConfig::startup`] for parallelism and timeout settings.
+    pub fn with_lazy_state<S, F, Fut>(&mut self, spec: impl Into<StartupSpec>, init: F) -> &mut Self
+    where
+        S: Clone + Send + 'static,
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<S, BoxError>> + Send + 'static,
+    {
+        self.startup_nodes.push(StartupNode::new(
+            spec.into(),
+            StartupNodeKind::State,
+            move || async move {
+                state::put(init().await?);
+                Ok(())
+            },
+        ));
+        self
+    }
+
+    /// Add supervised background task.
+    ///
+    /// Task is spawned after all of its dependencies are initialized. Nodes depending on the task
+    /// are initialized right after it is spawned. Task exit or panic is logged.
+    pub fn with_task<F, Fut>(&mut self, spec: impl Into<StartupSpec>, task: F) -> &mut Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let spec = spec.into();
+        let name = spec.name().to_owned();
+        self.startup_nodes.push(StartupNode::new(
+            spec,
+            StartupNodeKind::Task,
+            move || async move {
+                let handle = tokio::spawn(task());
+                tokio::spawn(async move {
+                    match handle.await {
+                        Ok(()) => warn!(task = name, "background task exited"),
+                        Err(err) => error!(task = name, error = %err, "background task failed"),
+                    }
+                });
+                Ok(())
+            },
+        ));
+        self
+    }
+
+    /// Add warmup hook.
+    ///
+    /// Warmup hooks are run in background after building the application, before readiness
+    /// probe reports the service as ready. Use this to initialize lazily created resources, like
+    /// connection pools or compiled schemas. Hooks take part in startup dependency ordering, and
+    /// can depend on lazy states and background tasks, or vice versa.
+    ///
+    /// See also [`AppConfig::warmup`] for configuring synthetic warmup requests.
+    pub fn with_warmup<F, Fut>(&mut self, spec: impl Into<StartupSpec>, hook: F) -> &mut Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.startup_nodes.push(StartupNode::new(
+            spec.into(),
+            StartupNodeKind::Warmup,
+            move || async move {
+                hook().await;
+                Ok(())
+            },
+        ));
+        self
+    }
+
+    /// Add custom readiness check.
+    ///
+    /// Checks are run concurrently every time readiness probe is requested, each bounded by
+    /// [`crate::ProbeConfig`] check timeout, which is also passed to the check as a [`Deadline`].
+    /// Failure of a mandatory check makes probe respond with 503, while failure of an optional
+    /// one (see [`ReadinessCheckSpec::optional`]) is only reported in response body.
+    ///
+    /// ```
+    /// # use uxum::{AppBuilder, ReadinessCheckSpec};
+    /// # let mut builder = AppBuilder::default();
+    /// builder.with_readiness_check("db", |_| async { Ok(()) });
+    /// builder.with_readiness_check(ReadinessCheckSpec::new("cache").optional(), |_| async {
+    ///     Err("not connected".to_string())
+    /// });
+    /// ```
+    pub fn with_readiness_check<F, Fut>(
+        &mut self,
+        spec: impl Into<ReadinessCheckSpec>,
+        check: F,
+    ) -> &mut Self
+    where
+        F: Fn(Deadline) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        self.readiness_checks
+            .push(ReadinessCheck::new(spec.into(), check));
+        self
+    }
+
+    /// Set custom handler for requests not matching any route.
+    ///
+    /// Handler is wrapped in global layers, so its responses are traced and counted in metrics.
+    /// Overrides [`crate::FallbackConfig`] from configuration.
+    ///
+    /// ```
+    /// # use axum::http::StatusCode;
+    /// # use uxum::AppBuilder;
+    /// # let mut builder = AppBuilder::default();
+    /// builder.with_fallback(|| async { (StatusCode::NOT_FOUND, "nothing here") });
+    /// ```
+    pub fn with_fallback<H, T>(&mut self, handler: H) -> &mut Self
+    where
+        H: Handler<T, ()>,
+        T: 'static,
+    {
+        self.fallback = Some(BoxCloneService::new(handler.with_state(())));
+        self
+    }
+
+    /// Add request body transformer for a handler.
+    ///
+    /// Transformer is applied to requests whose `Content-Type` matches `content_type` pattern
+    /// (like `application/xml` or `text/*`), before handler extractors run. It must return new
+    /// request body, along with its content type. Transformed requests carry
+    /// [`crate::Transformed`] extension.
+    ///
+    /// Transformation errors are returned to client as 400 Bad Request.
+    pub fn with_request_transformer<F>(
+        &mut self,
+        handler_name: impl ToString,
+        content_type: impl ToString,
+        transformer: F,
+    ) -> &mut Self
+    where
+        F: Fn(Bytes) -> Result<(Bytes, Mime), TransformError> + Send + Sync + 'static,
+    {
+        self.request_transformers
+            .entry(handler_name.to_string())
+            .or_default()
+            .push(RequestTransformer::new(content_type, transformer));
+        self
+    }
+
+    /// Set filter for handlers registered with [`crate::handler`] macro.
+    ///
+    /// Handlers for which `filter` returns `false` are not added to the application, nor to its
+    /// OpenAPI specification. Use this to exclude handlers brought in by linked library crates.
+    pub fn with_handler_filter(&mut self, filter: HandlerFilter) -> &mut Self {
+        self.handler_filter = Some(filter);
+        self
+    }
+
+    /// Add custom per-handler layer.
+    ///
+    /// `factory` is called once for every registered handler, and may return a layer to insert
+    /// at `position` in the handler's layer stack, or `None` to skip the handler. Layers at the
+    /// same position are applied in registration order, first registered being the outermost.
+    ///
+    /// ```
+    /// use uxum::{
+    ///     reexport::tower::{util::BoxCloneServiceLayer, util::MapRequestLayer},
+    ///     AppBuilder, HandlerLayerPosition,
+    /// };
+    ///
+    /// let mut app_builder = AppBuilder::new();
+    /// app_builder.with_handler_layer(HandlerLayerPosition::AfterAuth, |ctx| {
+    ///     // Only audit handlers requiring some permissions.
+    ///     let name = ctx.name;
+    ///     (!ctx.permissions.is_empty()).then(|| {
+    ///         BoxCloneServiceLayer::new(MapRequestLayer::new(move |req| {
+    ///             uxum::reexport::tracing::info!(handler = name, "audit");
+    ///             req
+    ///         }))
+    ///     })
+    /// });
+    /// ```
+    pub fn with_handler_layer<F>(&mut self, position: HandlerLayerPosition, factory: F) -> &mut Self
+    where
+        F: Fn(&HandlerLayerContext<'_>) -> Option<HandlerLayer> + Send + Sync + 'static,
+    {
+        self.handler_layers
+            .push(HandlerLayerFactory::new(position, factory));
+        self
+    }
+
+    /// Set key-value store used to persist circuit breaker and rate limiter state, as well as
+    /// queued jobs.
+    ///
+    /// Used by state persistence if it is configured without a local state file. Job queue falls
+    /// back to an in-memory store if this is not set.
+    pub fn with_state_store(&mut self, store: Arc<dyn KeyValueStore>) -> &mut Self {
+        self.state_store = Some(store);
+        self
+    }
+
+    /// Register handler for jobs of a specific type.
+    ///
+    /// Handlers are started only if job queue is configured in [`AppConfig::queue`]. Jobs are
+    /// delivered at least once, so handlers must be idempotent. See [`crate::queue`] for details.
+    ///
+    /// ```
+    /// # use uxum::{AppBuilder, queue::Job};
+    /// # let mut builder = AppBuilder::default();
+    /// builder.with_job_handler("send_email", |job: Job| async move {
+    ///     let _address = job.payload.as_str().ok_or("address expected")?;
+    ///     Ok::<_, &str>(())
+    /// });
+    /// ```
+    pub fn with_job_handler<F, Fut, E>(&mut self, job_type: impl ToString, handler: F) -> &mut Self
+    where
+        F: Fn(Job) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<BoxError>,
+    {
+        self.job_handlers
+            .insert(job_type.to_string(), JobHandler::new(handler));
+        self
+    }
+
     /// Set used metrics builder.
     ///
     /// The builder must be configured prior to passing it to this method. This enables gathering
@@ -279,30 +864,268 @@ where
         // Build metrics subsystem.
         let metrics_state = self.metrics()?.clone();
         if self.config.metrics.is_enabled() {
-            rtr = rtr.merge(metrics_state.build_router());
+            rtr = rtr.merge(
+                metrics_state.build_router(self.auth_provider.clone(), self.auth_extractor.clone()),
+            );
+        }
+
+        // Add OpenID Connect login flow.
+        #[cfg(feature = "oidc")]
+        let oidc = match &self.config.auth.oidc {
+            Some(oidc_cfg) => {
+                let name = &oidc_cfg.http_client;
+                let mut client_cfg = self
+                    .config
+                    .http_clients
+                    .get(name)
+                    .cloned()
+                    .unwrap_or_default();
+                if let Some(app_name) = &self.config.app_name {
+                    client_cfg.with_app_name(app_name);
+                }
+                if let Some(app_version) = &self.config.app_version {
+                    client_cfg.with_app_version(app_version);
+                }
+                if let Some(egress) = &self.config.egress {
+                    client_cfg.with_default_egress(egress);
+                }
+                let oidc = OidcState::new(
+                    oidc_cfg.clone(),
+                    client_cfg,
+                    Some(metrics_state.client_metrics(name)),
+                    &self.config.auth.roles,
+                )?;
+                rtr = rtr.merge(oidc.build_router());
+                Some(oidc)
+            }
+            None => None,
+        };
+
+        // Management endpoints use session authentication if OpenID Connect login is enabled.
+        macro_rules! management_router {
+            (|$prov:ident, $ext:ident| $body:expr) => {{
+                #[cfg(feature = "oidc")]
+                let router = match oidc.as_ref().filter(|oidc| oidc.protects_management()) {
+                    Some(oidc) => {
+                        let ($prov, $ext) = (oidc.auth_provider(), oidc.auth_extractor());
+                        $body
+                    }
+                    None => {
+                        let ($prov, $ext) =
+                            (self.auth_provider.clone(), self.auth_extractor.clone());
+                        $body
+                    }
+                };
+                #[cfg(not(feature = "oidc"))]
+                let router = {
+                    let ($prov, $ext) = (self.auth_provider.clone(), self.auth_extractor.clone());
+                    $body
+                };
+                router
+            }};
         }
 
         // Add probes and management mode API.
-        rtr = rtr.merge(
-            self.config
-                .probes
-                .build_router(self.auth_provider.clone(), self.auth_extractor.clone()),
+        let probe_state = self
+            .config
+            .probes
+            .build_state(&self.config.retry_advice)
+            .with_checks(std::mem::take(&mut self.readiness_checks))
+            .with_handler_toggles(self.handler_toggles.clone());
+        probe_state.register();
+        rtr = rtr.merge(management_router!(|prov, ext| self
+            .config
+            .probes
+            .build_router(probe_state.clone(), prov, ext)));
+
+        // Add logging control endpoint.
+        if let Some(control) = &self.config.logging.control {
+            rtr = rtr.merge(management_router!(
+                |prov, ext| control.build_router(prov, ext)
+            ));
+        }
+
+        // Add API key management endpoints.
+        if let Some(keys) = &self.api_keys {
+            rtr = rtr.merge(management_router!(|prov, ext| keys.build_router(prov, ext)));
+        }
+
+        // Add trace sampling control endpoint.
+        if let Some(control) = self
+            .config
+            .tracing
+            .as_ref()
+            .and_then(|tracing| tracing.sampling_control())
+        {
+            rtr = rtr.merge(management_router!(
+                |prov, ext| control.build_router(prov, ext)
+            ));
+        }
+
+        // Add optional subsystem status endpoint.
+        rtr = rtr.merge(management_router!(|prov, ext| self
+            .config
+            .otel
+            .build_router(prov, ext)));
+
+        // Validate startup dependencies.
+        let warmup_config = self.config.warmup.clone().unwrap_or_default();
+        let mut startup_nodes = mem::take(&mut self.startup_nodes);
+        for node in &mut startup_nodes {
+            if node.kind() == StartupNodeKind::Warmup {
+                node.set_timeout(warmup_config.target_timeout());
+            }
+        }
+        let startup_graph = StartupGraph::new(startup_nodes)?;
+        let startup_timeline = StartupTimeline::default();
+        rtr = rtr.merge(self.config.startup.build_router(startup_timeline.clone()));
+
+        // Add profiling endpoints.
+        #[cfg(feature = "profiling")]
+        if let Some(profiling) = &self.config.profiling {
+            rtr = rtr.merge(management_router!(|prov, ext| profiling.build_router(
+                &self.config.retry_advice,
+                prov,
+                ext
+            )));
+        }
+
+        let trailing_slash = self.config.routing.trailing_slash;
+        let grouped = group_handlers(
+            inventory::iter::<&dyn HandlerExt>.into_iter().copied(),
+            self.handler_filter,
+            trailing_slash,
+        )?;
+
+        // Rewrite path-keyed handler configuration.
+        let handler_routes: Vec<_> = grouped
+            .values()
+            .flatten()
+            .flat_map(|handler| {
+                handler
+                    .methods()
+                    .into_iter()
+                    .map(|method| (handler.name(), method, handler.path()))
+            })
+            .collect();
+        self.config.resolve_handler_keys(&handler_routes)?;
+
+        // Validate CORS configuration.
+        for (name, handler_cfg) in &self.config.handlers {
+            let Some(cors) = &handler_cfg.cors else {
+                continue;
+            };
+            match cors.make_layer() {
+                Ok(_) => {}
+                Err(err @ CorsError::CredentialedWildcard(_)) => {
+                    return Err(AppBuilderError::Cors(name.clone(), err));
+                }
+                Err(err) if self.config.strict_cors => {
+                    return Err(AppBuilderError::Cors(name.clone(), err));
+                }
+                Err(err) => {
+                    warn!(handler = name, error = %err, "CORS disabled for handler");
+                }
+            }
+        }
+
+        // Open access log.
+        let access_log = match &self.config.logging.access {
+            Some(access) => Some(access.build_sink(handler_routes.iter().map(|(name, ..)| *name))?),
+            None => None,
+        };
+
+        // Keep recent error responses of all handlers.
+        let recent_errors = self
+            .config
+            .errors
+            .recent_errors()
+            .build(handler_routes.iter().map(|(name, ..)| *name));
+        if let Some(recent) = &recent_errors {
+            rtr = rtr.merge(management_router!(
+                |prov, ext| recent.build_router(prov, ext)
+            ));
+        }
+
+        // Set global budget for buffered bodies.
+        self.config.memory.apply(
+            Some(metrics_state.memory_rejections()),
+            &self.config.retry_advice,
         );
 
-        // A set to ensure uniqueness of handler names.
-        let mut handler_names = HashSet::new();
-        let mut grouped: BTreeMap<&str, Vec<&dyn HandlerExt>> = BTreeMap::new();
-        for handler in inventory::iter::<&dyn HandlerExt> {
-            let name = handler.name();
-            let _record_span = debug_span!("iter_handler", name).entered();
-            if !handler_names.insert(name) {
-                return Err(AppBuilderError::DuplicateHandlerName(name));
+        // Load error message catalog.
+        let localize = LocalizeLayer::new(
+            match self.config.errors.localization() {
+                Some(localization) => Some(localization.load()?),
+                None => None,
+            },
+            metrics_state.missing_translations(),
+        );
+
+        // Restore resilience state saved on previous shutdown.
+        if let Some(persist_cfg) = &self.config.state_persistence {
+            let persistence = persist_cfg.build(
+                self.state_store.clone(),
+                Some(metrics_state.state_persistence()),
+            )?;
+            persistence.register();
+            persistence.spawn_restore();
+        }
+
+        // Set up runner for post-response hooks.
+        self.config
+            .response_hooks
+            .build(Some(metrics_state.response_hooks()))
+            .register();
+
+        // Start job queue workers.
+        if let Some(queue_cfg) = &self.config.queue {
+            let store = self.state_store.clone().unwrap_or_else(|| {
+                warn!("no state store configured, queued jobs will be lost on restart");
+                Arc::new(MemoryStore::new())
+            });
+            let queue = JobQueue::new(
+                queue_cfg.clone(),
+                store,
+                mem::take(&mut self.job_handlers),
+                Some(metrics_state.job_queue()),
+            );
+            match tokio::runtime::Handle::try_current() {
+                Ok(_) => {
+                    queue.spawn_workers();
+                }
+                Err(_) => warn!("no async runtime available, job queue workers not started"),
+            }
+            queue.register();
+            rtr = rtr.merge(management_router!(|prov, ext| queue.build_router(prov, ext)));
+        }
+
+        // Detect overlapping routes.
+        let routes: Vec<_> = grouped
+            .iter()
+            .map(|(path, handlers)| (*path, handlers.iter().map(|hdl| hdl.name()).collect()))
+            .collect();
+        let shadowing = routing::find_shadowing(&routes, &self.config.routing);
+        if self.config.routing.dump_routes {
+            routing::dump_routes(&routes, &shadowing);
+        }
+        if !shadowing.is_empty() {
+            if self.config.routing.strict_routing {
+                return Err(AppBuilderError::RouteShadowing(shadowing));
+            }
+            routing::report_shadowing(&shadowing);
+        }
+
+        // Start periodic summary of deprecated handler usage.
+        if let Some(report) = &self.config.deprecation_report {
+            match tokio::runtime::Handle::try_current() {
+                Ok(runtime) => {
+                    let tracker = DeprecationTracker::default();
+                    runtime.spawn(tracker.clone().report(report.clone()));
+                    self.deprecation_tracker = Some(tracker);
+                }
+                Err(_) => warn!("no async runtime available, skipping deprecation report"),
             }
-            grouped
-                .entry(handler.path())
-                .and_modify(|handlers| handlers.push(*handler))
-                .or_insert_with(|| vec![*handler]);
-            debug!("handler recorded");
         }
 
         // Register handlers.
@@ -312,7 +1135,24 @@ where
             }
         }
 
+        // Mount static asset directories.
+        for static_dir in &self.config.static_dirs {
+            let _span = info_span!("mount_static_dir", prefix = static_dir.prefix).entered();
+            static_dir
+                .check_conflicts(handler_routes.iter().map(|(name, _, path)| (*name, *path)))?;
+            rtr = rtr.nest_service(&static_dir.normalized_prefix(), static_dir.build_service()?);
+            info!("static directory mounted");
+        }
+
         // Add RapiDoc and/or OpenAPI specification generator if enabled.
+        let body_limits: Vec<_> = inventory::iter::<&dyn HandlerExt>
+            .into_iter()
+            .filter_map(|handler| {
+                self.config
+                    .max_body_size(handler.name())
+                    .map(|limit| (handler.name().to_owned(), limit))
+            })
+            .collect();
         if let Some(ref mut api_doc) = self.config.api_doc {
             let disabled = self
                 .config
@@ -320,21 +1160,193 @@ where
                 .iter()
                 .filter(|(_, v)| v.disabled)
                 .map(|(k, _)| k.clone());
-            api_doc.set_disabled_handlers(disabled);
+            let filtered = self.handler_filter.into_iter().flat_map(|filter| {
+                inventory::iter::<&dyn HandlerExt>
+                    .into_iter()
+                    .filter(move |handler| !filter(**handler))
+                    .map(|handler| handler.name().to_owned())
+            });
+            api_doc.set_disabled_handlers(disabled.chain(filtered));
+            api_doc.set_extra_request_types(self.request_transformers.iter().map(
+                |(name, transformers)| {
+                    (
+                        name.clone(),
+                        transformers
+                            .iter()
+                            .map(|tr| tr.content_type().to_owned())
+                            .collect(),
+                    )
+                },
+            ));
+            if self.config.cache_policy.has_spec_extension() {
+                let auth_headers = self.auth_extractor.request_headers();
+                api_doc.set_cache_policies(inventory::iter::<&dyn HandlerExt>.into_iter().map(
+                    |handler| {
+                        let method = handler.method();
+                        let semantics = handler_semantics(*handler, &method, &auth_headers);
+                        (
+                            handler.name().to_owned(),
+                            self.config.cache_policy.describe(&semantics),
+                        )
+                    },
+                ));
+            }
+            api_doc.set_body_limits(body_limits);
+            if api_doc.has_config_annotations() {
+                api_doc.set_handler_annotations(self.config.handlers.iter().filter_map(
+                    |(name, cfg)| {
+                        let annotations: Vec<_> = cfg
+                            .rate_limit
+                            .iter()
+                            .map(HandlerRateLimitConfig::annotation)
+                            .chain(cfg.timeout.annotation())
+                            .collect();
+                        (!annotations.is_empty()).then(|| (name.clone(), annotations))
+                    },
+                ));
+            }
+            api_doc.set_batch_path(self.config.batch.as_ref().map(BatchConfig::path));
+            api_doc.set_trailing_slash(trailing_slash);
             api_doc.set_app_defaults(
                 self.config.app_name.as_deref(),
                 self.config.app_version.as_deref(),
             );
             let auth = self.auth_extractor.security_schemes();
-            rtr = rtr.merge(api_doc.build_router(auth)?);
+            let api_doc_rtr = api_doc.build_router(auth.clone())?;
+            let public_rtr = api_doc.build_public_router(auth)?;
+            #[cfg(feature = "oidc")]
+            let (api_doc_rtr, public_rtr) =
+                match oidc.as_ref().filter(|oidc| oidc.protects_api_doc()) {
+                    Some(oidc) => {
+                        let protect = |rtr: Router| {
+                            rtr.layer(
+                                ServiceBuilder::new()
+                                    .layer(HandleErrorLayer::new(error_handler))
+                                    .layer(AuthLayer::new(
+                                        &[],
+                                        oidc.auth_provider(),
+                                        oidc.auth_extractor(),
+                                    )),
+                            )
+                        };
+                        let protect_public = api_doc
+                            .public()
+                            .is_some_and(PublicApiDocConfig::is_protected);
+                        (
+                            protect(api_doc_rtr),
+                            public_rtr.map(|rtr| match protect_public {
+                                true => protect(rtr),
+                                false => rtr,
+                            }),
+                        )
+                    }
+                    None => (api_doc_rtr, public_rtr),
+                };
+            rtr = rtr.merge(api_doc_rtr);
+            if let Some(public_rtr) = public_rtr {
+                rtr = rtr.merge(public_rtr);
+            }
+        }
+
+        // Install fallback before batch endpoint, so that sub-requests use it too.
+        let fallback = match self.fallback.take() {
+            Some(fallback) => fallback,
+            None => self
+                .config
+                .routing
+                .fallback
+                .make_service()
+                .map_err(AppBuilderError::InvalidFallbackRedirect)?,
+        };
+        rtr = rtr.fallback_service(fallback);
+
+        // Add batch endpoint, dispatching to fully wrapped application router.
+        if let Some(batch) = &self.config.batch {
+            let app = self.wrap_global_layers(
+                rtr.clone(),
+                metrics_state.clone(),
+                access_log.clone(),
+                recent_errors.clone(),
+                localize.clone(),
+            );
+            rtr = rtr.merge(batch.build_router(app));
         }
 
         // Wrap router in global layers.
-        let final_rtr = self.wrap_global_layers(rtr, metrics_state);
+        let redirects = metrics_state.trailing_slash_redirects();
+        let final_rtr =
+            self.wrap_global_layers(rtr, metrics_state, access_log, recent_errors, localize);
+
+        // Apply trailing slash policy before routing. Redirects bypass request metrics.
+        let final_rtr = trailing_slash.wrap_router(
+            final_rtr,
+            self.config
+                .static_dirs
+                .iter()
+                .map(StaticDirConfig::normalized_prefix),
+            Some(redirects),
+        );
+
+        // Run startup nodes and warmup in background, holding readiness until they are finished.
+        let get_paths = handler_routes
+            .iter()
+            .filter(|(name, method, path)| {
+                method == Method::GET
+                    && !path.contains([':', '*'])
+                    && !self
+                        .config
+                        .handlers
+                        .get(*name)
+                        .is_some_and(|cfg| cfg.disabled)
+            })
+            .map(|(_, _, path)| *path);
+        let warmup = WarmupRunner::new(warmup_config, get_paths);
+        if !startup_graph.is_empty() || !warmup.is_empty() {
+            match tokio::runtime::Handle::try_current() {
+                Ok(runtime) => {
+                    probe_state.begin_warmup();
+                    let startup_config = self.config.startup.clone();
+                    let app = final_rtr.clone();
+                    runtime.spawn(async move {
+                        if !startup_graph.run(&startup_config, startup_timeline).await {
+                            error!("startup failed, service will not become ready");
+                            return;
+                        }
+                        warmup.run(app, probe_state).await;
+                    });
+                }
+                Err(_) => warn!("no async runtime available, skipping startup and warmup"),
+            }
+        }
         info!("finished building application");
         Ok(final_rtr)
     }
 
+    /// Get service token issuer for use in named HTTP client.
+    ///
+    /// Creates a new shared issuer on first call. Returns [`None`] if service tokens are not
+    /// configured, or not enabled for this client.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if token issuer could not be initialized.
+    fn client_token_issuer(
+        &mut self,
+        name: &str,
+    ) -> Result<Option<Arc<TokenIssuer>>, AppBuilderError> {
+        let Some(token_cfg) = &self.config.auth.service_token else {
+            return Ok(None);
+        };
+        if !token_cfg.clients.contains(name) {
+            return Ok(None);
+        }
+        if self.token_issuer.is_none() {
+            let issuer = TokenIssuer::new(token_cfg.clone(), self.config.app_name.as_deref())?;
+            self.token_issuer = Some(Arc::new(issuer));
+        }
+        Ok(self.token_issuer.clone())
+    }
+
     /// Build and return configured [`reqwest`] HTTP client with distributed tracing support.
     ///
     /// # Errors
@@ -344,21 +1356,8 @@ where
         &mut self,
         name: impl AsRef<str>,
     ) -> Result<reqwest_middleware::ClientWithMiddleware, AppBuilderError> {
-        let metrics = self.metrics()?.client_metrics(name);
-        match self.config.http_clients.get_mut(metrics.name()) {
-            Some(cfg) => {
-                if let Some(app_name) = &self.config.app_name {
-                    cfg.with_app_name(app_name);
-                }
-                if let Some(app_version) = &self.config.app_version {
-                    cfg.with_app_version(app_version);
-                }
-                cfg.to_client(Some(metrics)).await.map_err(Into::into)
-            }
-            None => Err(AppBuilderError::HttpClientAbsent(
-                metrics.name().to_string(),
-            )),
-        }
+        let (cfg, metrics) = self.http_client_config(name, false)?;
+        cfg.to_client(Some(metrics)).await.map_err(Into::into)
     }
 
     /// Same as [`Self::http_client`], but returns default client if there is no configuration
@@ -371,27 +1370,65 @@ where
         &mut self,
         name: impl AsRef<str>,
     ) -> Result<reqwest_middleware::ClientWithMiddleware, AppBuilderError> {
+        let (cfg, metrics) = self.http_client_config(name, true)?;
+        cfg.to_client(Some(metrics)).await.map_err(Into::into)
+    }
+
+    /// Get complete HTTP client configuration, along with client metrics.
+    ///
+    /// Client is built separately, which allows building it later in an async context.
+    fn http_client_config(
+        &mut self,
+        name: impl AsRef<str>,
+        or_default: bool,
+    ) -> Result<(HttpClientConfig, ClientMetricsState), AppBuilderError> {
         let metrics = self.metrics()?.client_metrics(name);
-        match self.http_client(metrics.name()).await {
-            Ok(client) => Ok(client),
-            Err(AppBuilderError::HttpClientAbsent(_)) => {
-                let mut cfg = HttpClientConfig::default();
-                if let Some(app_name) = &self.config.app_name {
-                    cfg.with_app_name(app_name);
-                }
-                if let Some(app_version) = &self.config.app_version {
-                    cfg.with_app_version(app_version);
-                }
-                cfg.to_client(Some(metrics)).await.map_err(Into::into)
+        let token_issuer = self.client_token_issuer(metrics.name())?;
+        let mut cfg = match self.config.http_clients.get(metrics.name()) {
+            Some(cfg) => cfg.clone(),
+            None if or_default => HttpClientConfig::default(),
+            None => {
+                return Err(AppBuilderError::HttpClientAbsent(
+                    metrics.name().to_string(),
+                ))
             }
-            Err(err) => Err(err),
+        };
+        if let Some(token_issuer) = token_issuer {
+            cfg.with_token_issuer(token_issuer);
+        }
+        if let Some(app_name) = &self.config.app_name {
+            cfg.with_app_name(app_name);
+        }
+        if let Some(app_version) = &self.config.app_version {
+            cfg.with_app_version(app_version);
         }
+        if let Some(egress) = &self.config.egress {
+            cfg.with_default_egress(egress);
+        }
+        Ok((cfg, metrics))
     }
 
     /// Wrap router in global [`tower`] layers.
-    fn wrap_global_layers(&self, rtr: Router, metrics: MetricsState) -> Router {
+    fn wrap_global_layers(
+        &self,
+        rtr: Router,
+        metrics: MetricsState,
+        access_log: Option<AccessLogSink>,
+        recent_errors: Option<RecentErrors>,
+        localize: LocalizeLayer,
+    ) -> Router {
+        let client_ip = Arc::new(self.config.client_ip.clone());
+        let request_id = Arc::new(self.config.request_id.clone());
+        let baggage_attributes: Arc<[String]> = self
+            .config
+            .tracing
+            .as_ref()
+            .map(|tracing| tracing.baggage().span_attributes().into())
+            .unwrap_or_default();
         // [`tower`] layers that are executed for any request.
         let global_layers = ServiceBuilder::new()
+            .map_request(move |req: Request<Body>| client_ip.resolve_incoming(req))
+            .map_request(move |req: Request<Body>| request_id.filter_incoming(req))
             .set_x_request_id(MakeRequestUuid)
             .layer(RecordRequestIdLayer::new())
             .sensitive_headers([header::AUTHORIZATION])
@@ -408,14 +1445,38 @@ where
                     ),
             )
             .layer(metrics)
-            .map_request(crate::logging::span::register_request)
+            // Placed inside metrics layer, so that on-wire body size is recorded.
+            .option_layer(self.config.compression.make_layer().map(|layer| {
+                ServiceBuilder::new()
+                    .map_response(|resp: Response<CompressionBody<Body>>| resp.map(Body::new))
+                    .layer(layer)
+            }))
+            .option_layer(self.config.accept_compressed_requests.then(|| {
+                RequestDecompressionLayer::new(
+                    self.config
+                        .max_decompressed_body_size
+                        .map_or(DEFAULT_MAX_DECOMPRESSED_SIZE, |size| size.as_u64()),
+                )
+            }))
+            .map_request(move |req| {
+                crate::logging::span::register_request(req, &baggage_attributes)
+            })
             .propagate_x_request_id()
             .layer(SetResponseHeaderLayer::if_not_present(
                 header::SERVER,
                 self.server_header(),
             ))
+            .option_layer(self.config.response_identity.make_layer(
+                self.config.app_name.as_deref(),
+                self.config.app_version.as_deref(),
+            ))
+            .option_layer(access_log.map(AccessLogLayer::new))
+            .option_layer(recent_errors.map(RecentErrorsLayer::new))
+            .layer(ErrorContextLayer::new(
+                self.config.errors.includes_trace_id(),
+            ))
+            .layer(localize)
             .layer(CatchPanicLayer::custom(panic_handler));
-        // TODO: DefaultBodyLimit (configurable).
         rtr.layer(global_layers)
     }
 
@@ -431,6 +1492,9 @@ where
         let _register_span = info_span!("register_path", path).entered();
         let mut path_has_handlers = false;
         let mut method_rtr = MethodRouter::new();
+        let mut allowed = Vec::new();
+        let mut custom = Vec::new();
+        let mut preflight = PreflightLayer::default();
         for handler in handlers {
             let name = handler.name();
             let _span = info_span!("register_handler", name, method = ?handler.method()).entered();
@@ -440,60 +1504,295 @@ where
                     continue;
                 }
             }
-            method_rtr = self.register_handler(method_rtr, handler);
+            let service = self.handler_service(handler);
+            if let Some(cors_layer) = self.cors_layer(handler) {
+                preflight.add(
+                    &handler.methods(),
+                    &cors_layer,
+                    self.ip_filter_layer(handler),
+                );
+            }
+            for method in handler.methods() {
+                let implied = (method == Method::GET).then_some(Method::HEAD);
+                for allow in implied.into_iter().chain([method.clone()]) {
+                    if !allowed.contains(&allow) {
+                        allowed.push(allow);
+                    }
+                }
+                method_rtr = register_method(method_rtr, method, service.clone(), &mut custom);
+            }
             path_has_handlers = true;
             info!("handler registered");
         }
+        if !custom.is_empty() {
+            method_rtr = method_rtr.fallback_service(custom_methods_service(&allowed, custom));
+        }
+        // Preflight requests are answered before any handler layers, including authentication,
+        // but after IP filtering.
+        if !preflight.is_empty() {
+            method_rtr = method_rtr.layer(preflight);
+        }
         path_has_handlers.then_some(method_rtr)
     }
 
-    /// Register a handler in [`MethodRouter`].
-    fn register_handler(
-        &self,
-        method_rtr: MethodRouter<(), BoxError>,
-        handler: &dyn HandlerExt,
-    ) -> MethodRouter<(), BoxError> {
-        let service = self.handler_service(handler);
-        match handler.method() {
-            http::Method::GET => method_rtr.get_service(service),
-            http::Method::HEAD => method_rtr.head_service(service),
-            http::Method::POST => method_rtr.post_service(service),
-            http::Method::PUT => method_rtr.put_service(service),
-            http::Method::DELETE => method_rtr.delete_service(service),
-            http::Method::OPTIONS => method_rtr.options_service(service),
-            http::Method::TRACE => method_rtr.trace_service(service),
-            http::Method::PATCH => method_rtr.patch_service(service),
-            other => panic!("Unsupported HTTP method: {other}"),
-        }
+    /// Get effective CORS configuration of a handler.
+    ///
+    /// Invalid CORS configuration is reported when validating configuration in `build`, and
+    /// disables CORS for a handler.
+    #[must_use]
+    fn cors_config(&self, name: &str) -> Option<&CorsConfig> {
+        self.config
+            .handlers
+            .get(name)
+            .and_then(|cfg| cfg.cors.as_ref())
+            .filter(|cors| cors.make_layer().is_ok())
+    }
+
+    /// Get service responding to requests not matching any route.
+    ///
+    /// Invalid fallback redirect URL is reported in `build`.
+    #[must_use]
+    fn fallback_service(&self) -> Option<FallbackService> {
+        self.fallback
+            .clone()
+            .or_else(|| self.config.routing.fallback.make_service().ok())
+    }
+
+    /// Build CORS layer of a handler, if configured.
+    #[must_use]
+    fn cors_layer(&self, handler: &dyn HandlerExt) -> Option<CorsLayer> {
+        // TODO: default catch-all CORS config?
+        self.cors_config(handler.name())
+            .and_then(|cors| cors.make_layer().ok())
+            .map(|layer| layer.allow_methods(handler.methods()))
+    }
+
+    /// Build IP filtering layer of a handler, if configured.
+    #[must_use]
+    fn ip_filter_layer(&self, handler: &dyn HandlerExt) -> Option<IpFilterLayer> {
+        let name = handler.name();
+        self.config
+            .handlers
+            .get(name)
+            .and_then(|cfg| cfg.ip_filter.as_ref())
+            .or(self.config.ip_filter.as_ref())
+            .map(|icfg| {
+                icfg.make_layer(
+                    name,
+                    self.metrics
+                        .as_ref()
+                        .map(MetricsState::ip_filter_rejections),
+                )
+                .with_fallback(self.fallback_service())
+            })
     }
 
     /// Convert a [`HandlerExt`] structure into a [`tower`] layered service.
     #[must_use]
-    fn handler_service(
-        &self,
-        handler: &dyn HandlerExt,
-    ) -> BoxCloneService<Request<Body>, Response<Body>, BoxError> {
+    fn handler_service(&self, handler: &dyn HandlerExt) -> HandlerService {
         let name = handler.name();
         let _span = info_span!("handler_service", name, method = ?handler.method()).entered();
         let service_cfg = self.config.handlers.get(name);
-        // TODO: default catch-all CORS config?
-        let cors_layer =
-            service_cfg.and_then(|cfg| match cfg.cors.as_ref().map(|c| c.make_layer()) {
-                None => None,
-                Some(Ok(layer)) => Some(layer.allow_methods(handler.method())),
-                Some(Err(err)) => {
-                    warn!(error = %err, "Unable to build CORS layer");
-                    None
+        if let (Some(metrics), Some(mcfg)) = (
+            self.metrics.as_ref(),
+            service_cfg.and_then(|cfg| cfg.metrics.as_ref()),
+        ) {
+            metrics.register_handler(name, mcfg);
+        }
+        let cors_layer = self.cors_layer(handler);
+        let transform_layer = self.request_transformers.get(name).map(|transformers| {
+            TransformLayer::new(
+                transformers.clone(),
+                name,
+                self.metrics
+                    .as_ref()
+                    .map(MetricsState::requests_transformed),
+            )
+        });
+        let ip_filter_layer = self.ip_filter_layer(handler);
+        let method = handler.method();
+        let auth_headers = self.auth_extractor.request_headers();
+        let cache_layer = self.config.cache_policy.make_layer(&handler_semantics(
+            handler,
+            &method,
+            &auth_headers,
+        ));
+        let deprecation_layer = service_cfg
+            .and_then(|cfg| cfg.deprecation.clone())
+            .or_else(|| handler.deprecated().then(DeprecationConfig::default))
+            .map(|mut dcfg| {
+                dcfg.since = dcfg.since.or_else(|| handler.deprecated_since());
+                dcfg.make_layer(
+                    name,
+                    self.metrics.as_ref().map(MetricsState::deprecated_requests),
+                    self.deprecation_tracker.clone(),
+                )
+            });
+        let timeout_layer = service_cfg
+            .map(|cfg| cfg.timeout.clone())
+            .unwrap_or_default()
+            .make_layer()
+            .map(|layer| {
+                layer.with_cancellation_metrics(
+                    name,
+                    self.metrics.as_ref().map(MetricsState::cancelled_requests),
+                )
+            });
+        let dependency_layer = self.config.dependency_timing.as_ref().map(|dcfg| {
+            dcfg.make_layer(
+                name,
+                self.metrics.as_ref().map(MetricsState::dependency_duration),
+            )
+        });
+        let response_cache_layer = service_cfg
+            .and_then(|cfg| cfg.cache.as_ref())
+            .filter(|_| {
+                let cacheable = handler
+                    .methods()
+                    .iter()
+                    .any(|method| matches!(*method, Method::GET | Method::HEAD));
+                if !cacheable {
+                    warn!(
+                        handler = name,
+                        "response cache only applies to GET and HEAD handlers, skipping"
+                    );
                 }
+                cacheable
+            })
+            .map(|ccfg| {
+                let key_headers = match handler.no_auth() {
+                    true => &[][..],
+                    false => &auth_headers[..],
+                };
+                ccfg.make_layer(name, key_headers)
             });
+        let response_validation_layer = self.config.response_validation.make_layer(
+            name,
+            service_cfg.and_then(|cfg| cfg.response_validation),
+            || {
+                // Use the same generator settings as API documentation.
+                let mut gen = self.config.api_doc.as_ref().map_or_else(
+                    || SchemaSettings::openapi3().into_generator(),
+                    ApiDocBuilder::build_generator,
+                );
+                let spec = handler.openapi_spec(&mut gen);
+                ResponseSchemas::compile(&spec, gen)
+            },
+            self.metrics.as_ref().map(MetricsState::response_validation),
+        );
+        let cpu_guard_layer = handler.cpu_guard().then(|| {
+            self.config
+                .cpu_guard
+                .make_layer(name, self.metrics.as_ref().map(MetricsState::cpu_stalls))
+        });
+        let layer_ctx = HandlerLayerContext {
+            name,
+            path: handler.path(),
+            method: &method,
+            permissions: handler.permissions(),
+            no_auth: handler.no_auth(),
+            config: service_cfg,
+            metrics: self.metrics.as_ref(),
+        };
+        let custom_layers =
+            |position| layer::make_layers(&self.handler_layers, position, &layer_ctx);
+        let fair_queue_layer = service_cfg
+            .and_then(|cfg| cfg.fair_queue.as_ref())
+            .map(|fcfg| {
+                fcfg.make_layer(
+                    name,
+                    self.metrics.as_ref().map(MetricsState::fair_queue),
+                    &self.config.retry_advice,
+                )
+            });
+        let circuit_breaker_layer = service_cfg
+            .and_then(|cfg| cfg.circuit_breaker.as_ref())
+            .map(|ccfg| {
+                ccfg.make_layer(
+                    name,
+                    self.metrics.as_ref().map(MetricsState::circuit_breaker),
+                    &self.config.retry_advice,
+                )
+            });
+        let toggle_layer = self.handler_toggles.register(
+            name,
+            handler.path(),
+            handler.methods(),
+            self.cors_config(name).cloned(),
+            &self.config.retry_advice,
+        );
+        let before_auth_layers = custom_layers(HandlerLayerPosition::BeforeAuth);
+        let after_auth_layers = custom_layers(HandlerLayerPosition::AfterAuth);
+        let before_handler_layers = custom_layers(HandlerLayerPosition::BeforeHandler);
+        // Layers closest to the handler are boxed separately, as each optional layer doubles
+        // the size of resulting service type.
+        let inner = ServiceBuilder::new()
+            .boxed_clone()
+            // Request body size limit, before anything reads the body.
+            .option_layer(self.config.max_body_size(name).map(BodyLimitLayer::new))
+            // Circuit breaker, outside timeout so that timeouts count as failures.
+            .option_layer(circuit_breaker_layer)
+            // Timeout layer.
+            .option_layer(timeout_layer)
+            // Fair queuing layer, inside timeout so that queue wait counts towards deadline.
+            .option_layer(fair_queue_layer)
+            // Request body transformation layer.
+            .option_layer(transform_layer)
+            // Handler-level input normalization rules, used by extractors.
+            .option_layer(handler.normalize_strings().map(axum::Extension))
+            // Handler-level opt-out of input validation, used by extractors.
+            .option_layer(
+                handler
+                    .no_validate()
+                    .then_some(axum::Extension(SkipValidation)),
+            )
+            // Custom layers, placed right before the handler.
+            .option_layer(before_handler_layers)
+            // Response contract validation, checks handler output only.
+            .option_layer(response_validation_layer)
+            // CPU stall detection, measures handler itself.
+            .option_layer(cpu_guard_layer)
+            .service(handler.service().map_err(|err| err.into()));
         ServiceBuilder::new()
             .boxed_clone()
             .layer(ResponseExtension(HandlerName::new(name)))
+            // Post-response hooks, run after the whole response is sent.
+            .layer(self.config.response_hooks.make_layer(name, hooks::runner()))
+            // Runtime handler toggle.
+            .layer(toggle_layer)
+            // Downstream dependency latency attribution.
+            .option_layer(dependency_layer)
+            // Caching headers policy layer.
+            .option_layer(cache_layer)
+            // Service identity header suppression.
+            .option_layer(
+                service_cfg
+                    .is_some_and(|cfg| cfg.suppress_identity)
+                    .then_some(ResponseExtension(SuppressIdentity)),
+            )
+            // Response compression opt-out.
+            .option_layer(
+                service_cfg
+                    .is_some_and(|cfg| cfg.compression == Some(false))
+                    .then_some(ResponseExtension(NoCompression)),
+            )
+            // CORS layer, before authentication so that rejections are readable by browsers.
+            .option_layer(cors_layer)
+            // IP filtering layer.
+            .option_layer(ip_filter_layer)
+            // Custom layers, placed before authentication.
+            .option_layer(before_auth_layers)
             // Authentication layer.
             .option_layer(match handler.no_auth() {
                 true => None,
                 false => Some(self.auth_layer(handler.permissions())),
             })
+            // Custom layers, placed after authentication.
+            .option_layer(after_auth_layers)
+            // Deprecation layer.
+            .option_layer(deprecation_layer)
+            // Response cache layer.
+            .option_layer(response_cache_layer)
             // Buffer layer.
             .option_layer(
                 service_cfg.and_then(|cfg| cfg.buffer.as_ref())
@@ -502,13 +1801,18 @@ where
             // Rate limiting layer.
             .option_layer(
                 service_cfg.and_then(|cfg| cfg.rate_limit.as_ref())
-                    .map(|rcfg| rcfg.make_layer()),
+                    .map(|rcfg| {
+                        let mut layer = rcfg.make_layer().with_retry_advice(&self.config.retry_advice);
+                        if let Some(metrics) = &self.metrics {
+                            layer = layer.with_rejections(name, metrics.rate_limit_rejections());
+                        }
+                        match self.config.state_persistence.is_some() {
+                            true => layer.with_persistence(name),
+                            false => layer,
+                        }
+                    }),
             )
-            // CORS layer.
-            .option_layer(cors_layer)
-            // Timeout layer.
-            .option_layer(service_cfg.map(|cfg| cfg.timeout.clone()).unwrap_or_default().make_layer())
-            .service(handler.service().map_err(|err| err.into()))
+            .service(inner)
     }
 
     /// Generate a value to be used in HTTP Server header.
@@ -560,6 +1864,141 @@ impl<AuthProv> AppBuilder<AuthProv, HeaderAuthExtractor> {
     }
 }
 
+/// Register a handler service in [`MethodRouter`] for a single method.
+///
+/// Services for methods not supported by [`MethodRouter`] are added to `custom` instead.
+fn register_method(
+    method_rtr: MethodRouter<(), BoxError>,
+    method: Method,
+    service: HandlerService,
+    custom: &mut Vec<(Method, HandlerService)>,
+) -> MethodRouter<(), BoxError> {
+    match method {
+        http::Method::GET => method_rtr.get_service(service),
+        http::Method::HEAD => method_rtr.head_service(service),
+        http::Method::POST => method_rtr.post_service(service),
+        http::Method::PUT => method_rtr.put_service(service),
+        http::Method::DELETE => method_rtr.delete_service(service),
+        http::Method::OPTIONS => method_rtr.options_service(service),
+        http::Method::TRACE => method_rtr.trace_service(service),
+        http::Method::PATCH => method_rtr.patch_service(service),
+        other => {
+            custom.push((other, service));
+            method_rtr
+        }
+    }
+}
+
+/// Layered handler service.
+type HandlerService = BoxCloneService<Request<Body>, Response<Body>, BoxError>;
+
+/// Build [`MethodRouter`] fallback service to dispatch non-standard HTTP methods.
+///
+/// Responds with `405 Method Not Allowed` if no handler is registered for request method.
+fn custom_methods_service(
+    allowed: &[Method],
+    handlers: Vec<(Method, HandlerService)>,
+) -> impl tower::Service<
+    Request<Body>,
+    Response = Response<Body>,
+    Error = BoxError,
+    Future = impl Future<Output = Result<Response<Body>, BoxError>> + Send,
+> + Clone
+       + Send {
+    let allow = allowed
+        .iter()
+        .map(Method::as_str)
+        .collect::<Vec<_>>()
+        .join(",");
+    // SAFETY: method tokens and commas are valid header value characters.
+    let allow = HeaderValue::from_str(&allow).unwrap();
+    tower::service_fn(move |req: Request<Body>| {
+        let handler = handlers
+            .iter()
+            .find(|(method, _)| method == req.method())
+            .map(|(_, service)| service.clone());
+        let allow = allow.clone();
+        async move {
+            let mut resp = match handler {
+                Some(service) => service.oneshot(req).await?,
+                None => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+            };
+            resp.headers_mut().insert(header::ALLOW, allow);
+            Ok(resp)
+        }
+    })
+}
+
+/// Collect handler properties used to select caching policy rules.
+fn handler_semantics<'a>(
+    handler: &'a dyn HandlerExt,
+    method: &'a Method,
+    auth_headers: &'a [String],
+) -> HandlerSemantics<'a> {
+    HandlerSemantics {
+        name: handler.name(),
+        path: handler.path(),
+        method,
+        auth: !handler.no_auth(),
+        auth_headers,
+    }
+}
+
+/// Group handlers by URL path.
+///
+/// Identical registrations of the same handler, as happens when a crate is linked more than once,
+/// are deduplicated.
+///
+/// # Errors
+///
+/// Returns `Err` if different handlers share a name, or a method and path.
+fn group_handlers<'a>(
+    handlers: impl IntoIterator<Item = &'a dyn HandlerExt>,
+    filter: Option<HandlerFilter>,
+    trailing_slash: TrailingSlash,
+) -> Result<BTreeMap<&'static str, Vec<&'a dyn HandlerExt>>, AppBuilderError> {
+    let mut by_name: HashMap<&str, &dyn HandlerExt> = HashMap::new();
+    let mut by_route: HashMap<(Method, &str), &str> = HashMap::new();
+    let mut grouped: BTreeMap<&str, Vec<&dyn HandlerExt>> = BTreeMap::new();
+    for handler in handlers {
+        let name = handler.name();
+        let _record_span = debug_span!("iter_handler", name, module = handler.module()).entered();
+        if filter.is_some_and(|filter| !filter(handler)) {
+            debug!("handler excluded by filter");
+            continue;
+        }
+        if let Some(prev) = by_name.get(name) {
+            if prev.path() == handler.path()
+                && prev.methods() == handler.methods()
+                && prev.handler_type_id() == handler.handler_type_id()
+            {
+                debug!("duplicate handler registration ignored");
+                continue;
+            }
+            return Err(AppBuilderError::DuplicateHandlerName {
+                name,
+                first: prev.module(),
+                second: handler.module(),
+            });
+        }
+        let path = trailing_slash.canonical(handler.path());
+        for method in handler.methods() {
+            if let Some(prev) = by_route.insert((method.clone(), path), name) {
+                return Err(AppBuilderError::ConflictingRoute {
+                    method,
+                    path,
+                    first: prev,
+                    second: name,
+                });
+            }
+        }
+        by_name.insert(name, handler);
+        grouped.entry(path).or_default().push(handler);
+        debug!("handler recorded");
+    }
+    Ok(grouped)
+}
+
 // FIXME: write proper handler.
 pub(crate) async fn error_handler(err: BoxError) -> Response<Body> {
     // TODO: generalize, remove all the downcasts.
@@ -569,13 +2008,32 @@ pub(crate) async fn error_handler(err: BoxError) -> Response<Body> {
     if let Some(timeo_err) = err.downcast_ref::<TimeoutError>().cloned() {
         return timeo_err.into_response();
     }
-    problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
+    if let Some(ip_err) = err.downcast_ref::<IpFilterError>().cloned() {
+        return ip_err.into_response();
+    }
+    if let Some(tr_err) = err.downcast_ref::<TransformError>().cloned() {
+        return tr_err.into_response();
+    }
+    if let Some(dep_err) = err.downcast_ref::<DeprecationError>().cloned() {
+        return dep_err.into_response();
+    }
+    if let Some(fq_err) = err.downcast_ref::<FairQueueError>().cloned() {
+        return fq_err.into_response();
+    }
+    if let Some(cb_err) = err.downcast_ref::<CircuitBreakerError>().cloned() {
+        return cb_err.into_response();
+    }
+    if let Some(mem_err) = err.downcast_ref::<MemoryError>().cloned() {
+        return mem_err.into_response();
+    }
+    let problem = codes::INTERNAL_ERROR
+        .problem(StatusCode::INTERNAL_SERVER_ERROR)
         .with_type("tag:uxum.github.io,2024:error")
-        .with_title(err.to_string())
-        .into_response()
+        .with_title(err.to_string());
+    (codes::INTERNAL_ERROR, problem).into_response()
 }
 
-fn panic_handler(err: Box<dyn Any + Send + 'static>) -> Response<Body> {
+pub(crate) fn panic_handler(err: Box<dyn Any + Send + 'static>) -> Response<Body> {
     let details = if let Some(s) = err.downcast_ref::<String>() {
         s.clone()
     } else if let Some(s) = err.downcast_ref::<&str>() {
@@ -583,11 +2041,12 @@ fn panic_handler(err: Box<dyn Any + Send + 'static>) -> Response<Body> {
     } else {
         "Unknown panic format".to_string()
     };
-    problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
+    let problem = codes::PANIC
+        .problem(StatusCode::INTERNAL_SERVER_ERROR)
         .with_type("tag:uxum.github.io,2024:panic")
         .with_title("Encountered panic in handler")
-        .with_detail(details)
-        .into_response()
+        .with_detail(details);
+    (codes::PANIC, problem).into_response()
 }
 
 /// Application API method handler object trait.
@@ -606,11 +2065,63 @@ pub trait HandlerExt: Sync {
     /// Get URL path to run this handler, reformatted for OpenAPI specification.
     fn spec_path(&self) -> &'static str;
     /// Get HTTP method to run this handler.
+    ///
+    /// For handlers registered for several methods, this is the primary one.
     fn method(&self) -> http::Method;
+    /// Get all HTTP methods to run this handler.
+    ///
+    /// First element is the same as returned by [`Self::method`].
+    fn methods(&self) -> Vec<http::Method> {
+        vec![self.method()]
+    }
+    /// Get path of the module where handler was declared.
+    fn module(&self) -> &'static str;
+    /// Get type ID of handler metadata object.
+    ///
+    /// Used to tell repeated registrations of the same handler from different handlers.
+    fn handler_type_id(&self) -> TypeId;
     /// Get required permissions, if any.
     fn permissions(&self) -> &'static [&'static str];
     /// Skip authentication for this handler.
     fn no_auth(&self) -> bool;
+    /// Whether handler is marked as deprecated.
+    ///
+    /// Deprecated handlers emit `Deprecation` header in their responses.
+    fn deprecated(&self) -> bool {
+        false
+    }
+    /// Date since which handler is deprecated, if declared.
+    ///
+    /// Used as `Deprecation` header value, unless overridden in configuration.
+    fn deprecated_since(&self) -> Option<SystemTime> {
+        None
+    }
+    /// API version in which handler was introduced, if declared.
+    fn since(&self) -> Option<&'static str> {
+        None
+    }
+    /// Visibility of handler in API documentation.
+    ///
+    /// Only public handlers are included in public variant of OpenAPI specification.
+    fn visibility(&self) -> ApiVisibility {
+        ApiVisibility::Internal
+    }
+    /// Changes to handler behavior, per API version.
+    fn changes(&self) -> &'static [ApiChange] {
+        &[]
+    }
+    /// Default normalization rules for strings in normalized request bodies.
+    fn normalize_strings(&self) -> Option<NormalizeRules> {
+        None
+    }
+    /// Whether to watch handler for CPU-bound work which does not yield to async runtime.
+    fn cpu_guard(&self) -> bool {
+        false
+    }
+    /// Whether to skip business rule validation of validated request bodies.
+    fn no_validate(&self) -> bool {
+        false
+    }
     /// Return handler function packaged as a [`tower`] service.
     fn service(&self) -> BoxCloneService<Request<Body>, Response<Body>, Infallible>;
     /// Generate OpenAPI specification object for handler.
@@ -622,3 +2133,529 @@ pub trait HandlerExt: Sync {
 // This happens magically before `main()` is run.
 // For more info see documentation on [`inventory`] crate.
 inventory::collect!(&'static dyn HandlerExt);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test handler metadata, with explicitly set type ID.
+    struct Meta<T> {
+        name: &'static str,
+        path: &'static str,
+        module: &'static str,
+        methods: &'static [&'static str],
+        permissions: &'static [&'static str],
+        _type: std::marker::PhantomData<fn() -> T>,
+    }
+
+    const fn meta<T>(name: &'static str, path: &'static str, module: &'static str) -> Meta<T> {
+        Meta {
+            name,
+            path,
+            module,
+            methods: &["GET"],
+            permissions: &[],
+            _type: std::marker::PhantomData,
+        }
+    }
+
+    impl<T: 'static> HandlerExt for Meta<T> {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn path(&self) -> &'static str {
+            self.path
+        }
+
+        fn spec_path(&self) -> &'static str {
+            self.path
+        }
+
+        fn method(&self) -> http::Method {
+            self.methods()[0].clone()
+        }
+
+        fn methods(&self) -> Vec<http::Method> {
+            self.methods
+                .iter()
+                .map(|method| Method::from_bytes(method.as_bytes()).unwrap())
+                .collect()
+        }
+
+        fn module(&self) -> &'static str {
+            self.module
+        }
+
+        fn handler_type_id(&self) -> TypeId {
+            TypeId::of::<T>()
+        }
+
+        fn permissions(&self) -> &'static [&'static str] {
+            self.permissions
+        }
+
+        fn no_auth(&self) -> bool {
+            self.permissions.is_empty()
+        }
+
+        fn service(&self) -> BoxCloneService<Request<Body>, Response<Body>, Infallible> {
+            BoxCloneService::new(tower::service_fn(|_req| async {
+                Ok(Response::new(Body::empty()))
+            }))
+        }
+
+        fn openapi_spec(&self, _gen: &mut SchemaGenerator) -> openapi3::Operation {
+            openapi3::Operation::default()
+        }
+    }
+
+    static FIRST: Meta<u8> = meta("hello", "/hello", "lib_a::api");
+    static FIRST_AGAIN: Meta<u8> = meta("hello", "/hello", "lib_a::api");
+    static SAME_NAME: Meta<u16> = meta("hello", "/hello/v2", "lib_b::api");
+    static SAME_ROUTE: Meta<u32> = meta("greet", "/hello", "lib_b::api");
+    static OTHER: Meta<u64> = meta("other", "/other", "lib_b::api");
+    static SLASHED: Meta<i16> = meta("slashed", "/other/", "lib_b::api");
+    static SECURED: Meta<i8> = Meta {
+        permissions: &["secret"],
+        ..meta("secured", "/secured", "lib_a::api")
+    };
+    static QUERY: Meta<i32> = Meta {
+        methods: &["QUERY"],
+        ..meta("query", "/hello", "lib_a::api")
+    };
+    static MULTI: Meta<i64> = Meta {
+        methods: &["GET", "POST"],
+        ..meta("multi", "/other", "lib_a::api")
+    };
+    static MULTI_POST: Meta<u128> = Meta {
+        methods: &["POST"],
+        ..meta("multi_post", "/other", "lib_b::api")
+    };
+
+    /// Identical registrations are deduplicated.
+    #[test]
+    fn benign_duplicate() {
+        let grouped = group_handlers(
+            [&FIRST as &dyn HandlerExt, &FIRST_AGAIN, &OTHER],
+            None,
+            TrailingSlash::Strict,
+        )
+        .unwrap();
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(grouped["/hello"].len(), 1);
+    }
+
+    /// Different handlers with the same name or route are rejected.
+    #[test]
+    fn conflicts() {
+        let Err(err) = group_handlers(
+            [&FIRST as &dyn HandlerExt, &SAME_NAME],
+            None,
+            TrailingSlash::Strict,
+        ) else {
+            panic!("conflict not detected");
+        };
+        assert_eq!(
+            err.to_string(),
+            "Duplicate handler name hello, registered in lib_a::api and lib_b::api"
+        );
+        let Err(err) = group_handlers(
+            [&FIRST as &dyn HandlerExt, &SAME_ROUTE],
+            None,
+            TrailingSlash::Strict,
+        ) else {
+            panic!("conflict not detected");
+        };
+        assert_eq!(
+            err.to_string(),
+            "Conflicting handlers for GET /hello: hello and greet"
+        );
+    }
+
+    /// Each method of a multi-method handler takes part in conflict detection.
+    #[test]
+    fn multi_method_conflicts() {
+        let Err(err) = group_handlers(
+            [&MULTI as &dyn HandlerExt, &MULTI_POST],
+            None,
+            TrailingSlash::Strict,
+        ) else {
+            panic!("conflict not detected");
+        };
+        assert_eq!(
+            err.to_string(),
+            "Conflicting handlers for POST /other: multi and multi_post"
+        );
+        let grouped = group_handlers(
+            [&MULTI as &dyn HandlerExt, &MULTI, &FIRST],
+            None,
+            TrailingSlash::Strict,
+        )
+        .unwrap();
+        assert_eq!(grouped["/other"].len(), 1);
+    }
+
+    /// Filtered out handlers do not take part in conflict detection.
+    #[test]
+    fn filter() {
+        let grouped = group_handlers(
+            [&FIRST as &dyn HandlerExt, &SAME_NAME, &OTHER],
+            Some(|handler| !handler.module().starts_with("lib_a")),
+            TrailingSlash::Strict,
+        )
+        .unwrap();
+        assert_eq!(
+            grouped.keys().copied().collect::<Vec<_>>(),
+            ["/hello/v2", "/other"]
+        );
+    }
+
+    /// Handlers are grouped by canonical path with non-strict trailing slash policy.
+    #[test]
+    fn trailing_slash() {
+        let grouped = group_handlers(
+            [&SLASHED as &dyn HandlerExt, &FIRST],
+            None,
+            TrailingSlash::Merge,
+        )
+        .unwrap();
+        assert_eq!(
+            grouped.keys().copied().collect::<Vec<_>>(),
+            ["/hello", "/other"]
+        );
+        let handlers = [&SLASHED as &dyn HandlerExt, &OTHER];
+        assert_eq!(
+            group_handlers(handlers, None, TrailingSlash::Strict)
+                .unwrap()
+                .len(),
+            2
+        );
+        let Err(err) = group_handlers(handlers, None, TrailingSlash::Redirect) else {
+            panic!("conflict not detected");
+        };
+        assert_eq!(
+            err.to_string(),
+            "Conflicting handlers for GET /other: slashed and other"
+        );
+    }
+
+    /// Custom layers are built per handler, and placed around authentication layer in
+    /// registration order.
+    #[tokio::test]
+    async fn custom_layers() {
+        use parking_lot::Mutex;
+        use tower::util::{BoxCloneServiceLayer, MapRequestLayer};
+
+        let built = Arc::new(Mutex::new(Vec::new()));
+        let called = Arc::new(Mutex::new(Vec::new()));
+        let mut app_builder = AppBuilder::new().with_basic_auth();
+        for (position, tag) in [
+            (HandlerLayerPosition::AfterAuth, "after"),
+            (HandlerLayerPosition::BeforeAuth, "before1"),
+            (HandlerLayerPosition::BeforeHandler, "inner"),
+            (HandlerLayerPosition::BeforeAuth, "before2"),
+        ] {
+            let (built, called) = (built.clone(), called.clone());
+            app_builder.with_handler_layer(position, move |ctx| {
+                built.lock().push(format!("{}:{tag}", ctx.name));
+                let called = called.clone();
+                Some(BoxCloneServiceLayer::new(MapRequestLayer::new(
+                    move |req: Request<Body>| {
+                        called.lock().push(tag);
+                        req
+                    },
+                )))
+            });
+        }
+
+        let service = app_builder.handler_service(&OTHER);
+        assert_eq!(
+            *built.lock(),
+            [
+                "other:before1",
+                "other:before2",
+                "other:after",
+                "other:inner"
+            ]
+        );
+        service.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(*called.lock(), ["before1", "before2", "after", "inner"]);
+
+        built.lock().clear();
+        called.lock().clear();
+        let service = app_builder.handler_service(&SECURED);
+        assert_eq!(built.lock().len(), 4);
+        assert!(built
+            .lock()
+            .iter()
+            .all(|entry| entry.starts_with("secured:")));
+        let resp = service.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(*called.lock(), ["before1", "before2"]);
+    }
+
+    /// Custom fallback handler is wrapped in global layers.
+    #[tokio::test]
+    async fn custom_fallback() {
+        let mut app_builder = AppBuilder::new();
+        app_builder.with_fallback(|| async { (StatusCode::IM_A_TEAPOT, "teapot") });
+        let metrics = app_builder.metrics().unwrap().clone();
+        let rtr = app_builder.build().unwrap();
+        let resp = rtr
+            .oneshot(Request::get("/nothing").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::IM_A_TEAPOT);
+        assert!(resp.headers().contains_key("x-request-id"));
+        let counted = metrics
+            .registry
+            .gather()
+            .iter()
+            .filter(|fam| fam.get_name() == "http_server_requests_total")
+            .flat_map(|fam| fam.get_metric())
+            .any(|m| {
+                m.get_label()
+                    .iter()
+                    .any(|l| l.get_name() == "http_response_status_code" && l.get_value() == "418")
+            });
+        assert!(counted);
+    }
+
+    /// Non-standard methods are dispatched alongside standard ones.
+    #[tokio::test]
+    async fn custom_methods() {
+        let app_builder = AppBuilder::new();
+        let method_rtr = app_builder
+            .register_path("/hello", vec![&FIRST as &dyn HandlerExt, &QUERY])
+            .unwrap()
+            .handle_error(|_: BoxError| async { StatusCode::INTERNAL_SERVER_ERROR });
+        let rtr = Router::new().route("/hello", method_rtr);
+        for (method, status) in [
+            ("GET", StatusCode::OK),
+            ("QUERY", StatusCode::OK),
+            ("PROPFIND", StatusCode::METHOD_NOT_ALLOWED),
+            ("POST", StatusCode::METHOD_NOT_ALLOWED),
+        ] {
+            let req = Request::builder()
+                .method(method)
+                .uri("/hello")
+                .body(Body::empty())
+                .unwrap();
+            let resp = rtr.clone().oneshot(req).await.unwrap();
+            assert_eq!(resp.status(), status, "{method}");
+            if status == StatusCode::METHOD_NOT_ALLOWED {
+                assert_eq!(resp.headers()[header::ALLOW], "HEAD,GET,QUERY", "{method}");
+            }
+        }
+    }
+
+    /// Handler using caller identity.
+    #[crate::handler(path = "/whoami", method = "POST")]
+    async fn whoami(user: crate::CurrentUser) -> String {
+        user.id.map(|id| id.to_string()).unwrap_or_default()
+    }
+
+    /// Caller identity extractor is not mistaken for request body.
+    #[test]
+    fn current_user_extractor() {
+        let spec = ApiDocBuilder::default()
+            .build_spec(BTreeMap::new())
+            .unwrap();
+        let op = spec.paths["/whoami"].post.clone().unwrap();
+        assert!(op.request_body.is_none());
+    }
+
+    /// Deprecated handler with declaration date.
+    #[crate::handler(
+        path = "/deprecated/dated",
+        deprecated(since = "2024-06-01", note = "Use `/hello` instead.")
+    )]
+    async fn deprecated_dated() -> String {
+        String::new()
+    }
+
+    /// Deprecation date and note from handler attribute end up in headers and specification.
+    #[tokio::test]
+    async fn deprecated_since() {
+        let handler = inventory::iter::<&dyn HandlerExt>
+            .into_iter()
+            .copied()
+            .find(|handler| handler.name() == "deprecated_dated")
+            .unwrap();
+        assert!(handler.deprecated());
+        let method_rtr = AppBuilder::new()
+            .register_path(handler.path(), vec![handler])
+            .unwrap()
+            .handle_error(|_: BoxError| async { StatusCode::INTERNAL_SERVER_ERROR });
+        let rtr = Router::new().route(handler.path(), method_rtr);
+        let req = Request::get(handler.path()).body(Body::empty()).unwrap();
+        let resp = rtr.oneshot(req).await.unwrap();
+        assert_eq!(
+            resp.headers()["deprecation"],
+            "Sat, 01 Jun 2024 00:00:00 GMT"
+        );
+
+        let spec = ApiDocBuilder::default()
+            .build_spec(BTreeMap::new())
+            .unwrap();
+        let op = spec.paths["/deprecated/dated"].get.clone().unwrap();
+        assert!(op.deprecated);
+        assert_eq!(
+            op.description.as_deref(),
+            Some("**Deprecated:** Use `/hello` instead.")
+        );
+        assert_eq!(op.extensions["x-deprecated-since"], "2024-06-01");
+    }
+
+    /// Multi-method handler is registered for each of its methods.
+    #[tokio::test]
+    async fn multiple_methods() {
+        let app_builder = AppBuilder::new();
+        let method_rtr = app_builder
+            .register_path("/other", vec![&MULTI as &dyn HandlerExt])
+            .unwrap()
+            .handle_error(|_: BoxError| async { StatusCode::INTERNAL_SERVER_ERROR });
+        let rtr = Router::new().route("/other", method_rtr);
+        for (method, status) in [
+            (Method::GET, StatusCode::OK),
+            (Method::HEAD, StatusCode::OK),
+            (Method::POST, StatusCode::OK),
+            (Method::PUT, StatusCode::METHOD_NOT_ALLOWED),
+        ] {
+            let req = Request::builder()
+                .method(method.clone())
+                .uri("/other")
+                .body(Body::empty())
+                .unwrap();
+            let resp = rtr.clone().oneshot(req).await.unwrap();
+            assert_eq!(resp.status(), status, "{method}");
+        }
+    }
+
+    /// CORS preflight is answered at path level before authentication, and effective CORS policy
+    /// is listed by handler introspection endpoint.
+    #[tokio::test]
+    async fn cors_preflight() {
+        let app_cfg: AppConfig = serde_json::from_value(serde_json::json!({
+            "handlers": {
+                "secured": {
+                    "cors": {"origins": ["https://app.example.com"], "headers": ["authorization"]},
+                },
+            },
+        }))
+        .unwrap();
+        let app_builder = AppBuilder::from_config(&app_cfg).with_basic_auth();
+        let method_rtr = app_builder
+            .register_path("/secured", vec![&SECURED as &dyn HandlerExt])
+            .unwrap()
+            .handle_error(|_: BoxError| async { StatusCode::INTERNAL_SERVER_ERROR });
+        let rtr = Router::new().route("/secured", method_rtr);
+        let call = |method: Method, origin: &'static str, requested: Option<&'static str>| {
+            let mut req = Request::builder()
+                .method(method)
+                .uri("/secured")
+                .header(header::ORIGIN, origin);
+            if let Some(requested) = requested {
+                req = req.header(header::ACCESS_CONTROL_REQUEST_METHOD, requested);
+            }
+            rtr.clone().oneshot(req.body(Body::empty()).unwrap())
+        };
+
+        let resp = call(Method::OPTIONS, "https://app.example.com", Some("GET"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let headers = resp.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET");
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_HEADERS],
+            "authorization"
+        );
+
+        let resp = call(Method::OPTIONS, "https://evil.org", Some("GET"))
+            .await
+            .unwrap();
+        assert!(!resp
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+
+        // No handler for requested method.
+        let resp = call(Method::OPTIONS, "https://app.example.com", Some("POST"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        // Authentication failures are readable by allowed origins.
+        let resp = call(Method::GET, "https://app.example.com", None)
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            resp.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+
+        let handlers = serde_json::to_value(app_builder.handler_toggles.list()).unwrap();
+        assert_eq!(
+            handlers[0]["cors"]["origins"],
+            serde_json::json!(["https://app.example.com"])
+        );
+    }
+
+    /// CORS preflight requests from denied addresses are rejected by handler IP filter.
+    #[tokio::test]
+    async fn cors_preflight_ip_filter() {
+        use std::net::SocketAddr;
+
+        use axum::extract::ConnectInfo;
+
+        use crate::layers::client_ip::ClientIpConfig;
+
+        let app_cfg: AppConfig = serde_json::from_value(serde_json::json!({
+            "handlers": {
+                "secured": {
+                    "cors": {"origins": ["https://app.example.com"]},
+                    "ip_filter": {"deny": ["203.0.113.0/24"]},
+                },
+            },
+        }))
+        .unwrap();
+        let app_builder = AppBuilder::from_config(&app_cfg).with_basic_auth();
+        let method_rtr = app_builder
+            .register_path("/secured", vec![&SECURED as &dyn HandlerExt])
+            .unwrap()
+            .handle_error(error_handler);
+        let rtr = Router::new().route("/secured", method_rtr);
+        let preflight = |peer: [u8; 4]| {
+            let mut req = Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/secured")
+                .header(header::ORIGIN, "https://app.example.com")
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                .body(Body::empty())
+                .unwrap();
+            req.extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from((peer, 12345))));
+            rtr.clone()
+                .oneshot(ClientIpConfig::default().resolve_incoming(req))
+        };
+
+        let resp = preflight([198, 51, 100, 1]).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+
+        let resp = preflight([203, 0, 113, 5]).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        assert!(!resp
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+}
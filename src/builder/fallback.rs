@@ -0,0 +1,126 @@
+//! Responses to requests not matching any route.
+
+use std::convert::Infallible;
+
+use axum::{
+    body::Body,
+    http::{header::LOCATION, HeaderValue, Request, Response, StatusCode},
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use tower::{service_fn, util::BoxCloneService};
+
+use crate::errors::codes;
+
+/// Type-erased fallback service.
+pub(crate) type FallbackService = BoxCloneService<Request<Body>, Response<Body>, Infallible>;
+
+/// Response to requests not matching any route.
+///
+/// Can be replaced with a custom handler, see [`crate::AppBuilder::with_fallback`].
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+#[non_exhaustive]
+pub enum FallbackConfig {
+    /// 404 Not Found with `application/problem+json` body.
+    #[default]
+    Problem,
+    /// 404 Not Found with empty body.
+    Empty,
+    /// Redirect to another URL.
+    Redirect {
+        /// Redirect target.
+        url: String,
+        /// Use 308 Permanent Redirect instead of 307 Temporary Redirect.
+        #[serde(default)]
+        permanent: bool,
+    },
+}
+
+impl FallbackConfig {
+    /// Build fallback service.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if redirect URL is not a valid header value.
+    pub(crate) fn make_service(&self) -> Result<FallbackService, String> {
+        Ok(match self {
+            Self::Problem => {
+                BoxCloneService::new(service_fn(|_: Request<Body>| async { Ok(not_found()) }))
+            }
+            Self::Empty => BoxCloneService::new(service_fn(|_: Request<Body>| async {
+                Ok(StatusCode::NOT_FOUND.into_response())
+            })),
+            Self::Redirect { url, permanent } => {
+                let location = HeaderValue::from_str(url).map_err(|_| url.clone())?;
+                let status = match permanent {
+                    true => StatusCode::PERMANENT_REDIRECT,
+                    false => StatusCode::TEMPORARY_REDIRECT,
+                };
+                BoxCloneService::new(service_fn(move |_: Request<Body>| {
+                    let location = location.clone();
+                    async move { Ok((status, [(LOCATION, location)]).into_response()) }
+                }))
+            }
+        })
+    }
+}
+
+/// Problem response for requests not matching any route.
+#[must_use]
+pub(crate) fn not_found() -> Response<Body> {
+    let code = codes::NOT_FOUND;
+    let problem = code
+        .problem(StatusCode::NOT_FOUND)
+        .with_type("tag:uxum.github.io,2024:not-found")
+        .with_title("No handler found for request path");
+    (code, problem).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn call(config: &FallbackConfig) -> Response<Body> {
+        config
+            .make_service()
+            .unwrap()
+            .oneshot(Request::get("/nothing").body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    /// Each fallback kind produces its own response.
+    #[tokio::test]
+    async fn kinds() {
+        let resp = call(&FallbackConfig::Problem).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(resp.extensions().get(), Some(&codes::NOT_FOUND));
+
+        let resp = call(&FallbackConfig::Empty).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(bytes.is_empty());
+
+        let config: FallbackConfig =
+            serde_json::from_value(serde_json::json!({"type": "redirect", "url": "/app/"}))
+                .unwrap();
+        let resp = call(&config).await;
+        assert_eq!(resp.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(resp.headers()[LOCATION], "/app/");
+    }
+
+    /// Invalid redirect URL is rejected.
+    #[test]
+    fn invalid_redirect() {
+        let config = FallbackConfig::Redirect {
+            url: "/bad\nurl".into(),
+            permanent: false,
+        };
+        assert!(config.make_service().is_err());
+    }
+}
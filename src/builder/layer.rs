@@ -0,0 +1,133 @@
+//! Custom per-handler [`tower`] layers.
+
+use std::{fmt, sync::Arc};
+
+use axum::{body::Body, BoxError};
+use hyper::{Request, Response};
+use serde::{Deserialize, Serialize};
+use tower::{
+    util::{BoxCloneService, BoxCloneServiceLayer},
+    Layer, Service, ServiceExt,
+};
+
+use crate::{config::HandlerConfig, metrics::MetricsState};
+
+/// Type-erased per-handler service, as seen by custom handler layers.
+pub type HandlerService = BoxCloneService<Request<Body>, Response<Body>, BoxError>;
+
+/// Type-erased custom handler layer.
+///
+/// Use [`BoxCloneServiceLayer::new`] to create one from any compatible [`tower::Layer`].
+pub type HandlerLayer =
+    BoxCloneServiceLayer<HandlerService, Request<Body>, Response<Body>, BoxError>;
+
+/// Position of a custom layer in per-handler layer stack.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Hash, Serialize)]
+#[non_exhaustive]
+#[serde(rename_all = "snake_case")]
+pub enum HandlerLayerPosition {
+    /// Before authentication, right after IP filtering.
+    ///
+    /// Layer sees all requests, including ones later rejected by authentication.
+    BeforeAuth,
+    /// After authentication, before deprecation, buffering and rate limiting layers.
+    ///
+    /// Layer only sees authenticated and authorized requests.
+    #[default]
+    AfterAuth,
+    /// Innermost position, right before the handler itself.
+    BeforeHandler,
+}
+
+/// Handler metadata passed to custom layer factories.
+#[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
+pub struct HandlerLayerContext<'a> {
+    /// Handler name.
+    pub name: &'static str,
+    /// URL path of the handler.
+    pub path: &'static str,
+    /// HTTP method of the handler.
+    pub method: &'a http::Method,
+    /// Permissions required to call the handler.
+    pub permissions: &'static [&'static str],
+    /// Whether authentication is disabled for the handler.
+    pub no_auth: bool,
+    /// Effective handler configuration, if any.
+    pub config: Option<&'a HandlerConfig>,
+    /// Metrics container, if metrics are initialized.
+    pub metrics: Option<&'a MetricsState>,
+}
+
+/// Function creating a custom layer for a handler.
+type FactoryFn = dyn Fn(&HandlerLayerContext<'_>) -> Option<HandlerLayer> + Send + Sync;
+
+/// Registered custom layer factory.
+#[derive(Clone)]
+pub(crate) struct HandlerLayerFactory {
+    /// Position of created layers in per-handler layer stack.
+    position: HandlerLayerPosition,
+    /// Factory function.
+    func: Arc<FactoryFn>,
+}
+
+impl fmt::Debug for HandlerLayerFactory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HandlerLayerFactory")
+            .field("position", &self.position)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HandlerLayerFactory {
+    /// Create new custom layer factory.
+    pub(crate) fn new<F>(position: HandlerLayerPosition, func: F) -> Self
+    where
+        F: Fn(&HandlerLayerContext<'_>) -> Option<HandlerLayer> + Send + Sync + 'static,
+    {
+        Self {
+            position,
+            func: Arc::new(func),
+        }
+    }
+}
+
+/// Build custom layers for a handler at specific position.
+///
+/// Returns `None` if no factory produced a layer.
+#[must_use]
+pub(crate) fn make_layers(
+    factories: &[HandlerLayerFactory],
+    position: HandlerLayerPosition,
+    ctx: &HandlerLayerContext<'_>,
+) -> Option<CustomLayers> {
+    let layers: Vec<_> = factories
+        .iter()
+        .filter(|factory| factory.position == position)
+        .filter_map(|factory| (factory.func)(ctx))
+        .collect();
+    (!layers.is_empty()).then_some(CustomLayers(layers))
+}
+
+/// Stack of custom layers, applied in registration order.
+///
+/// First registered layer is the outermost one.
+#[derive(Clone)]
+pub(crate) struct CustomLayers(Vec<HandlerLayer>);
+
+impl<S> Layer<S> for CustomLayers
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    type Service = HandlerService;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        let inner = BoxCloneService::new(inner.map_err(Into::into));
+        self.0
+            .iter()
+            .rev()
+            .fold(inner, |service, layer| layer.layer(service))
+    }
+}
@@ -0,0 +1,255 @@
+//! Listen addresses and servers bound to several sockets.
+
+use std::{fmt, io, net::SocketAddr, path::Path};
+
+use axum_server::{
+    accept::{Accept, DefaultAcceptor},
+    service::{MakeService, SendService},
+    Handle, Server,
+};
+use futures::future::{try_join, try_join_all};
+use hyper::{body::Incoming, Request};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpStream, UnixListener},
+};
+
+use crate::builder::unix::{SocketFile, UnixServer};
+
+/// One or more host/address and port pairs to listen on.
+///
+/// Addresses starting with `unix:` denote paths to Unix domain sockets, like
+/// `unix:/run/app.sock`. Deserializes from either a single string, or a list of strings.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(from = "ListenRepr", into = "ListenRepr")]
+pub struct ListenAddrs(Vec<String>);
+
+/// Serialized form of [`ListenAddrs`].
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum ListenRepr {
+    /// Single address.
+    One(String),
+    /// List of addresses.
+    Many(Vec<String>),
+}
+
+impl From<ListenRepr> for ListenAddrs {
+    fn from(value: ListenRepr) -> Self {
+        match value {
+            ListenRepr::One(addr) => Self(vec![addr]),
+            ListenRepr::Many(addrs) => Self(addrs),
+        }
+    }
+}
+
+impl From<ListenAddrs> for ListenRepr {
+    fn from(mut value: ListenAddrs) -> Self {
+        match value.0.len() {
+            1 => Self::One(value.0.remove(0)),
+            _ => Self::Many(value.0),
+        }
+    }
+}
+
+impl From<String> for ListenAddrs {
+    fn from(value: String) -> Self {
+        Self(vec![value])
+    }
+}
+
+impl From<&str> for ListenAddrs {
+    fn from(value: &str) -> Self {
+        Self(vec![value.into()])
+    }
+}
+
+impl<T: Into<String>> From<Vec<T>> for ListenAddrs {
+    fn from(value: Vec<T>) -> Self {
+        Self(value.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<String>, const N: usize> From<[T; N]> for ListenAddrs {
+    fn from(value: [T; N]) -> Self {
+        Self(value.into_iter().map(Into::into).collect())
+    }
+}
+
+impl PartialEq<str> for ListenAddrs {
+    fn eq(&self, other: &str) -> bool {
+        matches!(self.0.as_slice(), [addr] if addr == other)
+    }
+}
+
+impl PartialEq<&str> for ListenAddrs {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl fmt::Display for ListenAddrs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.join(", "))
+    }
+}
+
+impl<'a> IntoIterator for &'a ListenAddrs {
+    type Item = &'a String;
+    type IntoIter = std::slice::Iter<'a, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl ListenAddrs {
+    /// Iterate over configured addresses.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+
+    /// Number of configured addresses.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Check if no addresses are configured.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Bound listening socket.
+pub(crate) enum Listener {
+    /// TCP listener, along with its local address.
+    Tcp(SocketAddr, std::net::TcpListener),
+    /// Unix domain socket listener.
+    Unix(UnixListener, SocketFile),
+}
+
+/// HTTP server listening on one or more sockets.
+///
+/// All listeners serve the same application, and are controlled by the same [`Handle`].
+pub struct MultiServer<A = DefaultAcceptor> {
+    /// Local addresses of TCP listeners, in the same order as servers.
+    addrs: Vec<SocketAddr>,
+    /// TCP servers, one per listener.
+    servers: Vec<Server<A>>,
+    /// Unix domain socket servers.
+    unix: Vec<UnixServer>,
+}
+
+impl<A> fmt::Debug for MultiServer<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultiServer")
+            .field("addrs", &self.addrs)
+            .field("unix", &self.unix)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A> MultiServer<A> {
+    /// Create server from TCP servers along with their local addresses, and Unix socket servers.
+    pub(crate) fn new(tcp: Vec<(SocketAddr, Server<A>)>, unix: Vec<UnixServer>) -> Self {
+        let (addrs, servers) = tcp.into_iter().unzip();
+        Self {
+            addrs,
+            servers,
+            unix,
+        }
+    }
+
+    /// Local addresses of TCP listeners.
+    #[must_use]
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.addrs
+    }
+
+    /// Paths of Unix domain socket listeners.
+    pub fn unix_paths(&self) -> impl Iterator<Item = &Path> {
+        self.unix.iter().map(UnixServer::path)
+    }
+
+    /// Provide a handle for server control, shared by all listeners.
+    ///
+    /// Note that [`Handle::listening`] resolves to the address of whichever listener starts first.
+    #[must_use]
+    pub fn handle(self, handle: Handle) -> Self {
+        Self {
+            addrs: self.addrs,
+            servers: self
+                .servers
+                .into_iter()
+                .map(|server| server.handle(handle.clone()))
+                .collect(),
+            unix: self
+                .unix
+                .into_iter()
+                .map(|server| server.handle(handle.clone()))
+                .collect(),
+        }
+    }
+
+    /// Split into individual TCP servers along with their local addresses, and Unix socket
+    /// servers.
+    #[must_use]
+    pub fn into_servers(self) -> (Vec<(SocketAddr, Server<A>)>, Vec<UnixServer>) {
+        (
+            self.addrs.into_iter().zip(self.servers).collect(),
+            self.unix,
+        )
+    }
+
+    /// Serve provided [`MakeService`] on all listeners concurrently.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` as soon as one of the listeners fails. Other listeners are stopped in that
+    /// case.
+    pub async fn serve<M>(self, make_service: M) -> io::Result<()>
+    where
+        M: MakeService<SocketAddr, Request<Incoming>> + Clone,
+        A: Accept<TcpStream, M::Service> + Clone + Send + Sync + 'static,
+        A::Stream: AsyncRead + AsyncWrite + Unpin + Send,
+        A::Service: SendService<Request<Incoming>> + Send,
+        A::Future: Send,
+        M::Service: SendService<Request<Incoming>>,
+    {
+        let tcp = try_join_all(
+            self.servers
+                .into_iter()
+                .map(|server| server.serve(make_service.clone())),
+        );
+        let unix = try_join_all(
+            self.unix
+                .into_iter()
+                .map(|server| server.serve(make_service.clone())),
+        );
+        try_join(tcp, unix).await.map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listen_addrs_serde() {
+        let one: ListenAddrs = serde_json::from_str(r#""127.0.0.1:8080""#).unwrap();
+        assert_eq!(one, "127.0.0.1:8080");
+        assert_eq!(serde_json::to_string(&one).unwrap(), r#""127.0.0.1:8080""#);
+
+        let many: ListenAddrs = serde_json::from_str(r#"["0.0.0.0:8080", "[::]:8080"]"#).unwrap();
+        assert_eq!(many, ListenAddrs::from(["0.0.0.0:8080", "[::]:8080"]));
+        assert_ne!(many, "0.0.0.0:8080");
+        assert_eq!(many.to_string(), "0.0.0.0:8080, [::]:8080");
+        assert_eq!(
+            serde_json::to_string(&many).unwrap(),
+            r#"["0.0.0.0:8080","[::]:8080"]"#
+        );
+    }
+}
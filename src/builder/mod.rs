@@ -1,4 +1,10 @@
 //! Main builders.
 
 pub(crate) mod app;
+pub(crate) mod fallback;
+pub(crate) mod layer;
+pub(crate) mod listen;
+pub(crate) mod routing;
 pub(crate) mod server;
+pub(crate) mod tls_reload;
+pub(crate) mod unix;
@@ -0,0 +1,342 @@
+//! Static analysis of handler paths.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::fallback::FallbackConfig;
+use crate::layers::trailing_slash::TrailingSlash;
+
+/// Routing analysis configuration.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct RoutingConfig {
+    /// Fail to build the application if route shadowing is detected.
+    ///
+    /// Shadowing is only reported as warnings by default.
+    #[serde(default)]
+    pub strict_routing: bool,
+    /// Intentional overlaps, not reported as shadowing.
+    ///
+    /// Each entry is a pair of handler names or URL paths, in any order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_overlaps: Vec<[String; 2]>,
+    /// Log all registered routes at build time, annotating shadowed ones.
+    #[serde(default)]
+    pub dump_routes: bool,
+    /// Policy for request paths with trailing slashes.
+    ///
+    /// Applies to handlers, management endpoints and fallback, but not to static directories.
+    #[serde(default)]
+    pub trailing_slash: TrailingSlash,
+    /// Response to requests not matching any route.
+    ///
+    /// Ignored if custom fallback handler is set with [`crate::AppBuilder::with_fallback`].
+    #[serde(default)]
+    pub fallback: FallbackConfig,
+}
+
+impl RoutingConfig {
+    /// Fail to build the application if route shadowing is detected.
+    #[must_use]
+    pub fn with_strict_routing(mut self, strict: bool) -> Self {
+        self.strict_routing = strict;
+        self
+    }
+
+    /// Allow intentional overlap between two handlers or URL paths.
+    #[must_use]
+    pub fn with_allowed_overlap(mut self, first: impl ToString, second: impl ToString) -> Self {
+        self.allowed_overlaps
+            .push([first.to_string(), second.to_string()]);
+        self
+    }
+
+    /// Log all registered routes at build time.
+    #[must_use]
+    pub fn with_dump_routes(mut self, dump: bool) -> Self {
+        self.dump_routes = dump;
+        self
+    }
+
+    /// Set policy for request paths with trailing slashes.
+    #[must_use]
+    pub fn with_trailing_slash(mut self, policy: TrailingSlash) -> Self {
+        self.trailing_slash = policy;
+        self
+    }
+
+    /// Set response to requests not matching any route.
+    #[must_use]
+    pub fn with_fallback(mut self, fallback: FallbackConfig) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Check if overlap between two routes is allowed.
+    fn is_allowed(&self, first: &Route<'_>, second: &Route<'_>) -> bool {
+        self.allowed_overlaps.iter().any(|[a, b]| {
+            (first.matches(a) && second.matches(b)) || (first.matches(b) && second.matches(a))
+        })
+    }
+}
+
+/// Pair of overlapping routes, where one of them is always preferred by the router.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RouteShadowing {
+    /// URL path preferred by the router for overlapping requests.
+    pub preferred_path: &'static str,
+    /// Handlers registered for preferred path.
+    pub preferred_handlers: Vec<&'static str>,
+    /// URL path not reachable for overlapping requests.
+    pub shadowed_path: &'static str,
+    /// Handlers registered for shadowed path.
+    pub shadowed_handlers: Vec<&'static str>,
+}
+
+impl fmt::Display for RouteShadowing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}) is shadowed by {} ({})",
+            self.shadowed_path,
+            self.shadowed_handlers.join(", "),
+            self.preferred_path,
+            self.preferred_handlers.join(", "),
+        )
+    }
+}
+
+/// Kind of URL path segment, ordered by router preference.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Segment<'a> {
+    /// Literal segment.
+    Static(&'a str),
+    /// Single segment parameter, like `:id`.
+    Param,
+    /// Parameter capturing the rest of the path, like `*rest`.
+    Wildcard,
+}
+
+impl<'a> Segment<'a> {
+    /// Parse URL path segment.
+    fn parse(segment: &'a str) -> Self {
+        if segment.starts_with(':') {
+            Self::Param
+        } else if segment.starts_with('*') {
+            Self::Wildcard
+        } else {
+            Self::Static(segment)
+        }
+    }
+
+    /// Router preference rank, lower is preferred.
+    fn rank(self) -> u8 {
+        match self {
+            Self::Static(_) => 0,
+            Self::Param => 1,
+            Self::Wildcard => 2,
+        }
+    }
+}
+
+/// Registered URL path with its handlers.
+struct Route<'a> {
+    /// URL path.
+    path: &'static str,
+    /// Handler names.
+    handlers: &'a [&'static str],
+    /// Parsed path segments.
+    segments: Vec<Segment<'static>>,
+}
+
+impl<'a> Route<'a> {
+    /// Parse URL path.
+    fn new(path: &'static str, handlers: &'a [&'static str]) -> Self {
+        Self {
+            path,
+            handlers,
+            segments: path
+                .strip_prefix('/')
+                .unwrap_or(path)
+                .split('/')
+                .map(Segment::parse)
+                .collect(),
+        }
+    }
+
+    /// Check if allowlist entry refers to this route.
+    fn matches(&self, entry: &str) -> bool {
+        self.path == entry || self.handlers.contains(&entry)
+    }
+
+    /// Check if some request path can be matched by both routes.
+    fn overlaps(&self, other: &Self) -> bool {
+        let len = self.segments.len().max(other.segments.len());
+        for idx in 0..len {
+            match (self.segments.get(idx), other.segments.get(idx)) {
+                (Some(Segment::Wildcard), Some(_)) | (Some(_), Some(Segment::Wildcard)) => {
+                    return true;
+                }
+                (Some(Segment::Static(a)), Some(Segment::Static(b))) if a != b => return false,
+                (Some(_), Some(_)) => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// Check whether this route is preferred by the router over an overlapping one.
+    ///
+    /// Router prefers static segments over parameters, and parameters over wildcards, at the
+    /// first position where segment kinds differ. Returns [`None`] if both routes have the same
+    /// shape.
+    fn is_preferred_over(&self, other: &Self) -> Option<bool> {
+        self.segments
+            .iter()
+            .zip(&other.segments)
+            .map(|(a, b)| a.rank().cmp(&b.rank()))
+            .find(|ord| ord.is_ne())
+            .map(std::cmp::Ordering::is_lt)
+    }
+}
+
+/// Find pairs of routes where requests to one route can never reach the other.
+///
+/// `routes` contains URL paths with names of handlers registered for them.
+pub(crate) fn find_shadowing(
+    routes: &[(&'static str, Vec<&'static str>)],
+    config: &RoutingConfig,
+) -> Vec<RouteShadowing> {
+    let parsed: Vec<_> = routes
+        .iter()
+        .map(|(path, handlers)| Route::new(path, handlers))
+        .collect();
+    let mut found = Vec::new();
+    for (idx, first) in parsed.iter().enumerate() {
+        for second in &parsed[idx + 1..] {
+            if !first.overlaps(second) || config.is_allowed(first, second) {
+                continue;
+            }
+            let (preferred, shadowed) = match first.is_preferred_over(second) {
+                Some(true) => (first, second),
+                Some(false) => (second, first),
+                None => continue,
+            };
+            found.push(RouteShadowing {
+                preferred_path: preferred.path,
+                preferred_handlers: preferred.handlers.to_vec(),
+                shadowed_path: shadowed.path,
+                shadowed_handlers: shadowed.handlers.to_vec(),
+            });
+        }
+    }
+    found
+}
+
+/// Report detected shadowing as warnings.
+pub(crate) fn report_shadowing(shadowing: &[RouteShadowing]) {
+    for item in shadowing {
+        warn!(
+            preferred_path = item.preferred_path,
+            preferred_handlers = ?item.preferred_handlers,
+            shadowed_path = item.shadowed_path,
+            shadowed_handlers = ?item.shadowed_handlers,
+            "route shadowing detected: overlapping requests are routed to {}",
+            item.preferred_path,
+        );
+    }
+}
+
+/// Log all registered routes, annotating shadowed ones.
+pub(crate) fn dump_routes(
+    routes: &[(&'static str, Vec<&'static str>)],
+    shadowing: &[RouteShadowing],
+) {
+    for (path, handlers) in routes {
+        let shadowed_by: Vec<_> = shadowing
+            .iter()
+            .filter(|item| item.shadowed_path == *path)
+            .map(|item| item.preferred_path)
+            .collect();
+        let shadows: Vec<_> = shadowing
+            .iter()
+            .filter(|item| item.preferred_path == *path)
+            .map(|item| item.shadowed_path)
+            .collect();
+        info!(path, ?handlers, ?shadowed_by, ?shadows, "route");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(paths: &[&'static str], config: &RoutingConfig) -> Vec<(&'static str, &'static str)> {
+        let routes: Vec<_> = paths.iter().map(|path| (*path, vec![*path])).collect();
+        find_shadowing(&routes, config)
+            .into_iter()
+            .map(|item| (item.preferred_path, item.shadowed_path))
+            .collect()
+    }
+
+    /// Static segments shadow parameters at the same depth.
+    #[test]
+    fn static_vs_param() {
+        let config = RoutingConfig::default();
+        assert_eq!(
+            check(&["/users/:id", "/users/export"], &config),
+            [("/users/export", "/users/:id")]
+        );
+        assert!(check(&["/users/:id", "/users/:id/posts", "/users"], &config).is_empty());
+        assert!(check(&["/users/export", "/users/import"], &config).is_empty());
+    }
+
+    /// Parameters shadow wildcards.
+    #[test]
+    fn param_vs_wildcard() {
+        let config = RoutingConfig::default();
+        assert_eq!(
+            check(&["/files/*path", "/files/:name"], &config),
+            [("/files/:name", "/files/*path")]
+        );
+        assert!(check(&["/files/*path", "/other/:name"], &config).is_empty());
+    }
+
+    /// Overlaps are detected deeper than the first differing segment.
+    #[test]
+    fn nested_prefix() {
+        let config = RoutingConfig::default();
+        assert_eq!(
+            check(&["/api/*rest", "/api/v1/users/:id"], &config),
+            [("/api/v1/users/:id", "/api/*rest")]
+        );
+        assert_eq!(
+            check(&["/api/:version/users", "/api/v1/:resource"], &config),
+            [("/api/v1/:resource", "/api/:version/users")]
+        );
+        assert!(check(&["/api/:version/users", "/api/v1/:resource/:id"], &config).is_empty());
+    }
+
+    /// Allowlisted overlaps are not reported.
+    #[test]
+    fn allowlist() {
+        let config = RoutingConfig::default().with_allowed_overlap("/users/export", "/users/:id");
+        assert!(check(&["/users/:id", "/users/export"], &config).is_empty());
+        let routes = [
+            ("/users/:id", vec!["get_user"]),
+            ("/users/export", vec!["export_users"]),
+            ("/files/*path", vec!["get_file"]),
+            ("/files/:name", vec!["get_named_file"]),
+        ];
+        let config = RoutingConfig::default().with_allowed_overlap("get_user", "export_users");
+        let found = find_shadowing(&routes, &config);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].to_string(),
+            "/files/*path (get_file) is shadowed by /files/:name (get_named_file)"
+        );
+    }
+}
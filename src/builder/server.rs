@@ -1,7 +1,9 @@
 use std::{
+    fmt, io,
     net::{SocketAddr, TcpListener},
     num::{NonZeroU32, NonZeroUsize},
-    path::Path,
+    path::{Path, PathBuf},
+    sync::Arc,
     time::Duration,
 };
 
@@ -9,7 +11,14 @@ use axum_server::{
     tls_rustls::{RustlsAcceptor, RustlsConfig},
     Handle,
 };
-use hyper_util::server::conn::auto::Builder;
+use futures::{Stream, StreamExt};
+use hyper_util::{rt::TokioExecutor, server::conn::auto::Builder};
+use rustls::{
+    crypto::aws_lc_rs,
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    RootCertStore, ServerConfig, SupportedProtocolVersion,
+};
 use serde::{Deserialize, Serialize};
 use socket2::SockRef;
 use thiserror::Error;
@@ -17,11 +26,17 @@ use tokio::{
     net::{lookup_host, TcpSocket, ToSocketAddrs},
     task::JoinHandle,
 };
-use tracing::{debug, debug_span, error, info, Instrument};
+use tracing::{debug, debug_span, error, info, warn, Instrument};
 
 use crate::{
+    builder::{
+        listen::{ListenAddrs, Listener, MultiServer},
+        tls_reload::{self, cert_not_after},
+        unix::{self, UnixServer, UnixSocketConfig, UNIX_PREFIX},
+    },
     errors::IoError,
-    signal::{SignalError, SignalStream},
+    probes::Lifecycle,
+    signal::{Signal, SignalError, SignalStream},
 };
 
 /// Error type returned by server builder.
@@ -43,6 +58,18 @@ pub enum ServerBuilderError {
     /// Unable to listen on socket.
     #[error("Unable to listen on socket {0}: {1}")]
     Listen(SocketAddr, IoError),
+    /// Unable to set up listener for configured address.
+    #[error("Unable to set up listener on {0}: {1}")]
+    Listener(String, Box<ServerBuilderError>),
+    /// No listen addresses were configured.
+    #[error("No listen addresses were configured")]
+    NoListenAddrs,
+    /// Unable to bind Unix domain socket.
+    #[error("Unable to bind Unix socket {}: {1}", .0.display())]
+    BindUnix(PathBuf, IoError),
+    /// TLS was configured for a Unix domain socket.
+    #[error("TLS is not supported on Unix sockets")]
+    TlsOnUnixSocket,
     /// Unable to perform conversion into [`std`] listener.
     #[error("Unable to perform conversion into std listener: {0}")]
     ConvertListener(IoError),
@@ -82,6 +109,18 @@ pub enum ServerBuilderError {
     /// No TLS configuration was provided.
     #[error("No TLS configuration was provided")]
     NoTlsConfig,
+    /// Unable to read certificate, key or CA file.
+    #[error("Unable to read TLS file {}: {1}", .0.display())]
+    TlsFile(PathBuf, IoError),
+    /// Certificate, key or CA file has invalid contents.
+    #[error("Invalid TLS file {}: {1}", .0.display())]
+    TlsFileContents(PathBuf, String),
+    /// Client certificates are required, but no client CA is configured.
+    #[error("Client certificates are required, but no client CA is configured")]
+    NoClientCa,
+    /// Minimum TLS protocol version is higher than maximum.
+    #[error("Invalid TLS protocol version range: {0} to {1}")]
+    TlsVersionRange(TlsVersion, TlsVersion),
 }
 
 /// Builder for HTTP server
@@ -89,8 +128,10 @@ pub enum ServerBuilderError {
 #[non_exhaustive]
 pub struct ServerBuilder {
     /// Host/address and port to listen on.
+    ///
+    /// Either a single address, or a list of addresses. Socket options apply to all of them.
     #[serde(default = "ServerBuilder::default_listen")]
-    pub listen: String,
+    pub listen: ListenAddrs,
     /// Sleep on accept errors.
     ///
     /// Default is false.
@@ -108,9 +149,24 @@ pub struct ServerBuilder {
     /// Configuration specific to HTTP/2 protocol.
     #[serde(default)]
     pub http2: Http2Config,
+    /// Unix domain socket configuration, used for `unix:` listen addresses.
+    #[serde(default)]
+    pub unix: UnixSocketConfig,
     /// TLS configuration.
     #[serde(default)]
     pub tls: Option<TlsConfig>,
+    /// Grace period for long-lived streaming connections on graceful shutdown.
+    ///
+    /// Registered streams are notified when shutdown starts, and terminated if still active after
+    /// this period.
+    #[serde(
+        default = "ServerBuilder::default_stream_drain_timeout",
+        with = "humantime_serde"
+    )]
+    pub stream_drain_timeout: Duration,
+    /// Graceful shutdown configuration.
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
 }
 
 impl Default for ServerBuilder {
@@ -122,7 +178,10 @@ impl Default for ServerBuilder {
             tcp: TcpConfig::default(),
             http1: Http1Config::default(),
             http2: Http2Config::default(),
+            unix: UnixSocketConfig::default(),
             tls: None,
+            stream_drain_timeout: Self::default_stream_drain_timeout(),
+            shutdown: ShutdownConfig::default(),
         }
     }
 }
@@ -131,10 +190,17 @@ impl ServerBuilder {
     /// Default value for [`Self::listen`].
     #[must_use]
     #[inline]
-    fn default_listen() -> String {
+    fn default_listen() -> ListenAddrs {
         "localhost:8080".into()
     }
 
+    /// Default value for [`Self::stream_drain_timeout`].
+    #[must_use]
+    #[inline]
+    fn default_stream_drain_timeout() -> Duration {
+        Duration::from_secs(2)
+    }
+
     /// Check if server configuration includes parameters required for setting up TLS.
     #[must_use]
     #[inline]
@@ -148,23 +214,36 @@ impl ServerBuilder {
         Self::default()
     }
 
-    /// Build TCP network server.
+    /// Build TCP network server, listening on all configured addresses.
     ///
     /// # Errors
     ///
     /// Returns `Err` if builder encounters an error while setting up a listening socket.
-    pub async fn build(self) -> Result<axum_server::Server, ServerBuilderError> {
+    pub async fn build(self) -> Result<MultiServer, ServerBuilderError> {
         let span = debug_span!("build_server");
         async move {
-            let listener = self.create_listener(&self.listen).await?;
-            let mut server = axum_server::from_tcp(listener);
+            let (mut servers, mut unix_servers) = (Vec::new(), Vec::new());
+            for listener in self.create_listeners(&self.listen).await? {
+                match listener {
+                    Listener::Tcp(addr, listener) => {
+                        let mut server = axum_server::from_tcp(listener);
 
-            let builder = server.http_builder();
-            self.configure_http1(builder);
-            self.configure_http2(builder);
+                        let builder = server.http_builder();
+                        self.configure_http1(builder);
+                        self.configure_http2(builder);
+                        servers.push((addr, server));
+                    }
+                    Listener::Unix(listener, file) => {
+                        let mut builder = Builder::new(TokioExecutor::new());
+                        self.configure_http1(&mut builder);
+                        self.configure_http2(&mut builder);
+                        unix_servers.push(UnixServer::new(listener, file, builder));
+                    }
+                }
+            }
 
-            info!("finished building plain server");
-            Ok(server)
+            info!(listen = %self.listen, "finished building plain server");
+            Ok(MultiServer::new(servers, unix_servers))
         }
         .instrument(span)
         .await
@@ -176,27 +255,90 @@ impl ServerBuilder {
     ///
     /// Returns `Err` if builder encounters an error while setting up a listening socket
     /// or configuring TLS parameters.
-    pub async fn build_tls(
-        self,
-    ) -> Result<axum_server::Server<RustlsAcceptor>, ServerBuilderError> {
+    pub async fn build_tls(self) -> Result<MultiServer<RustlsAcceptor>, ServerBuilderError> {
         let span = debug_span!("build_tls_server");
         async move {
             let tls_config = self.tls.as_ref().ok_or(ServerBuilderError::NoTlsConfig)?;
-            let listener = self.create_listener(&tls_config.listen).await?;
+            if let Some(addr) = tls_config
+                .listen
+                .iter()
+                .find(|addr| addr.starts_with(UNIX_PREFIX))
+            {
+                return Err(ServerBuilderError::Listener(
+                    addr.into(),
+                    Box::new(ServerBuilderError::TlsOnUnixSocket),
+                ));
+            }
+            let listeners = self.create_listeners(&tls_config.listen).await?;
             let rustls_config = tls_config.rustls_config().await?;
-            let mut server = axum_server::from_tcp_rustls(listener, rustls_config);
+            tls_reload::register(tls_config.clone(), rustls_config.clone());
+            let mut servers = Vec::with_capacity(listeners.len());
+            for listener in listeners {
+                let Listener::Tcp(addr, listener) = listener else {
+                    continue;
+                };
+                let mut server = axum_server::from_tcp_rustls(listener, rustls_config.clone());
 
-            let builder = server.http_builder();
-            self.configure_http1(builder);
-            self.configure_http2(builder);
+                let builder = server.http_builder();
+                self.configure_http1(builder);
+                self.configure_http2(builder);
+                servers.push((addr, server));
+            }
 
-            info!("finished building TLS server");
-            Ok(server)
+            info!(listen = %tls_config.listen, "finished building TLS server");
+            Ok(MultiServer::new(servers, Vec::new()))
         }
         .instrument(span)
         .await
     }
 
+    /// Create and configure TCP and Unix socket listeners for all addresses.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if no addresses are provided, or when unable to set up any of the listeners.
+    /// Error identifies the address which failed.
+    pub(crate) async fn create_listeners(
+        &self,
+        listen: &ListenAddrs,
+    ) -> Result<Vec<Listener>, ServerBuilderError> {
+        if listen.is_empty() {
+            return Err(ServerBuilderError::NoListenAddrs);
+        }
+        let mut listeners = Vec::with_capacity(listen.len());
+        for addr_conf in listen.iter() {
+            let listener_error =
+                |err| ServerBuilderError::Listener(addr_conf.into(), Box::new(err));
+            if let Some(path) = addr_conf.strip_prefix(UNIX_PREFIX) {
+                let tcp_defaults = TcpConfig {
+                    backlog: self.tcp.backlog,
+                    ..TcpConfig::default()
+                };
+                if self.ip != IpConfig::default() || self.tcp != tcp_defaults {
+                    debug!("ignoring TCP and IP socket options for {addr_conf}");
+                }
+                let backlog = i32::try_from(self.tcp.backlog.get()).unwrap_or(i32::MAX);
+                let (listener, file) = self
+                    .unix
+                    .bind(Path::new(path), backlog)
+                    .map_err(listener_error)?;
+                debug!("created listener for {addr_conf}");
+                listeners.push(Listener::Unix(listener, file));
+                continue;
+            }
+            let listener = self
+                .create_listener(addr_conf)
+                .await
+                .map_err(listener_error)?;
+            let addr = listener
+                .local_addr()
+                .map_err(|err| listener_error(ServerBuilderError::ConvertListener(err.into())))?;
+            debug!(%addr, "created listener for {addr_conf}");
+            listeners.push(Listener::Tcp(addr, listener));
+        }
+        Ok(listeners)
+    }
+
     /// Create and configure TCP listener.
     ///
     /// # Errors
@@ -298,28 +440,149 @@ impl ServerBuilder {
         handle: Handle,
     ) -> Result<JoinHandle<()>, ServerBuilderError> {
         let span = debug_span!("signal_handler");
-        let mut sig = SignalStream::new()?;
+        let signals = futures::stream::unfold(SignalStream::new()?, |mut sig| async move {
+            Some((sig.next().await, sig))
+        });
         Ok(tokio::spawn(
-            async move {
-                loop {
-                    match sig.next().await {
-                        Ok(sig) if sig.is_shutdown() => {
-                            info!("received {}, shutting down server", sig.name());
-                            // FIXME: configure duration.
-                            handle.graceful_shutdown(Some(Duration::from_secs(5)));
-                            break;
-                        }
-                        Ok(sig) => {
-                            debug!("don't know what to do with signal {}, ignoring", sig.name());
-                        }
-                        Err(err) => {
-                            error!("error in signal handler: {err}");
+            handle_signals(
+                signals,
+                handle,
+                self.shutdown.clone(),
+                self.stream_drain_timeout,
+            )
+            .instrument(span),
+        ))
+    }
+}
+
+/// Abort the server, dropping all connections.
+fn abort_server(handle: &Handle) {
+    crate::drain::terminate_all();
+    // Dropped connections cancel their in-flight requests.
+    handle.shutdown();
+    unix::shutdown_all();
+}
+
+/// React to received signals.
+///
+/// First shutdown signal switches readiness probe to draining, and starts graceful shutdown after
+/// [`ShutdownConfig::drain_window`]. If configured, second one aborts the server immediately.
+async fn handle_signals<S>(
+    signals: S,
+    handle: Handle,
+    shutdown: ShutdownConfig,
+    stream_drain_timeout: Duration,
+) where
+    S: Stream<Item = Result<Signal, SignalError>>,
+{
+    tokio::pin!(signals);
+    let mut shutting_down = false;
+    while let Some(res) = signals.next().await {
+        match res {
+            Ok(sig) if sig.is_shutdown() && shutting_down => {
+                warn!(
+                    "received {} during graceful shutdown, aborting server",
+                    sig.name()
+                );
+                abort_server(&handle);
+                break;
+            }
+            Ok(sig) if sig.is_shutdown() => {
+                info!(
+                    grace_period = ?shutdown.grace_period,
+                    drain_window = ?shutdown.drain_window,
+                    "received {}, shutting down server",
+                    sig.name()
+                );
+                crate::probes::set_lifecycle(Lifecycle::Draining);
+                if !shutdown.drain_window.is_zero() {
+                    let window = tokio::time::sleep(shutdown.drain_window);
+                    tokio::pin!(window);
+                    loop {
+                        tokio::select! {
+                            () = &mut window => break,
+                            Some(res) = signals.next() => match res {
+                                Ok(sig) if sig.is_shutdown()
+                                    && shutdown.force_abort_on_second_signal =>
+                                {
+                                    warn!(
+                                        "received {} during drain window, aborting server",
+                                        sig.name()
+                                    );
+                                    abort_server(&handle);
+                                    return;
+                                }
+                                Ok(sig) => {
+                                    debug!("ignoring signal {} during drain window", sig.name());
+                                }
+                                Err(err) => error!("error in signal handler: {err}"),
+                            },
                         }
                     }
                 }
+                crate::drain::start_drain(stream_drain_timeout);
+                handle.graceful_shutdown(Some(shutdown.grace_period));
+                unix::graceful_shutdown_all(Some(shutdown.grace_period));
+                if !shutdown.force_abort_on_second_signal {
+                    break;
+                }
+                shutting_down = true;
             }
-            .instrument(span),
-        ))
+            Ok(Signal::HangUp) => {
+                info!("received SIGHUP, reloading message catalogs and TLS certificates");
+                crate::i18n::reload();
+                // Errors are logged by reload function itself.
+                let _ = tls_reload::reload_all().await;
+            }
+            Ok(sig) => {
+                debug!("don't know what to do with signal {}, ignoring", sig.name());
+            }
+            Err(err) => {
+                error!("error in signal handler: {err}");
+            }
+        }
+    }
+}
+
+/// Graceful shutdown configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct ShutdownConfig {
+    /// Time to wait for in-progress requests to finish after shutdown is requested.
+    ///
+    /// Remaining connections are closed after this period.
+    #[serde(
+        default = "ShutdownConfig::default_grace_period",
+        with = "humantime_serde"
+    )]
+    pub grace_period: Duration,
+    /// Time between readiness probe starting to fail and graceful shutdown start.
+    ///
+    /// Gives load balancers time to notice that service is draining, and stop sending new
+    /// requests to it.
+    #[serde(default, with = "humantime_serde")]
+    pub drain_window: Duration,
+    /// Abort server immediately when another shutdown signal is received during grace period.
+    #[serde(default = "crate::util::default_true")]
+    pub force_abort_on_second_signal: bool,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            grace_period: Self::default_grace_period(),
+            drain_window: Duration::ZERO,
+            force_abort_on_second_signal: true,
+        }
+    }
+}
+
+impl ShutdownConfig {
+    /// Default value for [`Self::grace_period`].
+    #[must_use]
+    #[inline]
+    fn default_grace_period() -> Duration {
+        Duration::from_secs(5)
     }
 }
 
@@ -508,41 +771,269 @@ pub struct Http2KeepaliveConfig {
     pub timeout: Option<Duration>,
 }
 
+/// TLS protocol version.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[non_exhaustive]
+pub enum TlsVersion {
+    /// TLS 1.2.
+    #[serde(rename = "1.2")]
+    Tls12,
+    /// TLS 1.3.
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+impl fmt::Display for TlsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Tls12 => "TLS 1.2",
+            Self::Tls13 => "TLS 1.3",
+        })
+    }
+}
+
+impl TlsVersion {
+    /// Get corresponding RusTLS protocol version.
+    #[must_use]
+    fn rustls_version(self) -> &'static SupportedProtocolVersion {
+        match self {
+            Self::Tls12 => &rustls::version::TLS12,
+            Self::Tls13 => &rustls::version::TLS13,
+        }
+    }
+}
+
 /// TLS configuration.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[non_exhaustive]
 pub struct TlsConfig {
     /// Host/address and port to listen on when using TLS.
+    ///
+    /// Either a single address, or a list of addresses.
     #[serde(default = "TlsConfig::default_listen")]
-    pub listen: String,
+    pub listen: ListenAddrs,
     /// Path to certificate or certificate chain in PEM format.
     #[serde(alias = "cert", alias = "chain")]
     certificate: Box<Path>,
     /// Path to private key file in PEM format.
     #[serde(alias = "key")]
     private_key: Box<Path>,
+    /// Path to CA certificates in PEM format, used to verify client certificates.
+    ///
+    /// Client certificates are not requested if not set.
+    #[serde(default, alias = "ca", skip_serializing_if = "Option::is_none")]
+    client_ca: Option<Box<Path>>,
+    /// Reject connections without a valid client certificate.
+    ///
+    /// Requires [`Self::client_ca`]. If not set, client certificates are verified only if
+    /// presented.
+    #[serde(default)]
+    require_client_cert: bool,
+    /// Minimum supported TLS protocol version.
+    ///
+    /// Default is TLS 1.2.
+    #[serde(default = "TlsConfig::default_min_version")]
+    min_version: TlsVersion,
+    /// Maximum supported TLS protocol version.
+    ///
+    /// Default is TLS 1.3.
+    #[serde(default = "TlsConfig::default_max_version")]
+    max_version: TlsVersion,
+    /// Interval for checking certificate, key and CA files for changes.
+    ///
+    /// Changed files are loaded into running server without dropping existing connections.
+    /// Files are not watched if not set, but can still be reloaded on SIGHUP or by calling
+    /// [`crate::Handle::reload_tls`].
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "humantime_serde"
+    )]
+    reload_interval: Option<Duration>,
 }
 
 impl TlsConfig {
+    /// Create new TLS configuration using certificate chain and private key files.
+    #[must_use]
+    pub fn new(certificate: impl AsRef<Path>, private_key: impl AsRef<Path>) -> Self {
+        Self {
+            listen: Self::default_listen(),
+            certificate: certificate.as_ref().into(),
+            private_key: private_key.as_ref().into(),
+            client_ca: None,
+            require_client_cert: false,
+            min_version: Self::default_min_version(),
+            max_version: Self::default_max_version(),
+            reload_interval: None,
+        }
+    }
+
     /// Default value for [`Self::listen`].
     #[must_use]
     #[inline]
-    fn default_listen() -> String {
+    fn default_listen() -> ListenAddrs {
         "localhost:8443".into()
     }
 
+    /// Default value for [`Self::min_version`].
+    #[must_use]
+    #[inline]
+    fn default_min_version() -> TlsVersion {
+        TlsVersion::Tls12
+    }
+
+    /// Default value for [`Self::max_version`].
+    #[must_use]
+    #[inline]
+    fn default_max_version() -> TlsVersion {
+        TlsVersion::Tls13
+    }
+
+    /// Set host/address and port, or a list of them, to listen on.
+    #[must_use]
+    pub fn with_listen(mut self, listen: impl Into<ListenAddrs>) -> Self {
+        self.listen = listen.into();
+        self
+    }
+
+    /// Verify client certificates using CA certificates from a file.
+    #[must_use]
+    pub fn with_client_ca(mut self, path: impl AsRef<Path>) -> Self {
+        self.client_ca = Some(path.as_ref().into());
+        self
+    }
+
+    /// Reject connections without a valid client certificate.
+    #[must_use]
+    pub fn with_required_client_cert(mut self, require: bool) -> Self {
+        self.require_client_cert = require;
+        self
+    }
+
+    /// Set range of supported TLS protocol versions.
+    #[must_use]
+    pub fn with_versions(mut self, min: TlsVersion, max: TlsVersion) -> Self {
+        self.min_version = min;
+        self.max_version = max;
+        self
+    }
+
+    /// Periodically check certificate, key and CA files for changes, and reload them.
+    #[must_use]
+    pub fn with_reload_interval(mut self, interval: Duration) -> Self {
+        self.reload_interval = Some(interval);
+        self
+    }
+
+    /// Interval for checking TLS files for changes.
+    pub(crate) fn reload_interval(&self) -> Option<Duration> {
+        self.reload_interval
+    }
+
+    /// Paths to all files used in TLS configuration.
+    pub(crate) fn files(&self) -> impl Iterator<Item = &Path> {
+        [
+            Some(&self.certificate),
+            Some(&self.private_key),
+            self.client_ca.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(AsRef::as_ref)
+    }
+
     /// Generate configuration object for RusTLS.
     ///
     /// # Errors
     ///
-    /// Returns `Err` if provided TLS configuration is invalid.
+    /// Returns `Err` if provided TLS configuration is invalid, or some of referenced files could
+    /// not be loaded.
     pub async fn rustls_config(&self) -> Result<RustlsConfig, ServerBuilderError> {
-        RustlsConfig::from_pem_chain_file(&self.certificate, &self.private_key)
-            .await
-            .map_err(|err| ServerBuilderError::TlsConfig(err.into()))
+        let (config, _) = self.server_config().await?;
+        Ok(RustlsConfig::from_config(config))
+    }
+
+    /// Load RusTLS server configuration.
+    ///
+    /// Also returns expiration date of server certificate, if it could be parsed.
+    pub(crate) async fn server_config(
+        &self,
+    ) -> Result<(Arc<ServerConfig>, Option<String>), ServerBuilderError> {
+        if self.min_version > self.max_version {
+            return Err(ServerBuilderError::TlsVersionRange(
+                self.min_version,
+                self.max_version,
+            ));
+        }
+        let versions: Vec<_> = [TlsVersion::Tls12, TlsVersion::Tls13]
+            .into_iter()
+            .filter(|ver| (self.min_version..=self.max_version).contains(ver))
+            .map(TlsVersion::rustls_version)
+            .collect();
+        let certs = load_certs(&self.certificate).await?;
+        let not_after = certs.first().and_then(|cert| cert_not_after(cert));
+        let key = load_key(&self.private_key).await?;
+        let provider = Arc::new(aws_lc_rs::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_protocol_versions(&versions)
+            .map_err(tls_error)?;
+        let builder = match &self.client_ca {
+            Some(path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in load_certs(path).await? {
+                    roots.add(cert).map_err(|err| {
+                        ServerBuilderError::TlsFileContents(path.to_path_buf(), err.to_string())
+                    })?;
+                }
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+                let verifier = match self.require_client_cert {
+                    true => verifier,
+                    false => verifier.allow_unauthenticated(),
+                };
+                builder.with_client_cert_verifier(verifier.build().map_err(tls_error)?)
+            }
+            None if self.require_client_cert => return Err(ServerBuilderError::NoClientCa),
+            None => builder.with_no_client_auth(),
+        };
+        let mut config = builder.with_single_cert(certs, key).map_err(tls_error)?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok((Arc::new(config), not_after))
     }
 }
 
+/// Wrap RusTLS error.
+fn tls_error(err: impl std::error::Error + Send + Sync + 'static) -> ServerBuilderError {
+    ServerBuilderError::TlsConfig(io::Error::other(err).into())
+}
+
+/// Read contents of a TLS-related file.
+async fn read_tls_file(path: &Path) -> Result<Vec<u8>, ServerBuilderError> {
+    tokio::fs::read(path)
+        .await
+        .map_err(|err| ServerBuilderError::TlsFile(path.to_path_buf(), err.into()))
+}
+
+/// Load all certificates from a PEM file.
+async fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, ServerBuilderError> {
+    let certs = CertificateDer::pem_slice_iter(&read_tls_file(path).await?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| ServerBuilderError::TlsFileContents(path.to_path_buf(), err.to_string()))?;
+    if certs.is_empty() {
+        return Err(ServerBuilderError::TlsFileContents(
+            path.to_path_buf(),
+            "no certificates found".into(),
+        ));
+    }
+    Ok(certs)
+}
+
+/// Load private key from a PEM file.
+async fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, ServerBuilderError> {
+    PrivateKeyDer::from_pem_slice(&read_tls_file(path).await?)
+        .map_err(|err| ServerBuilderError::TlsFileContents(path.to_path_buf(), err.to_string()))
+}
+
 /// Turn DNS name or address into a socket.
 ///
 /// # Errors
@@ -609,3 +1100,312 @@ fn sock_create(addr: &SocketAddr) -> Result<TcpSocket, ServerBuilderError> {
 
     Ok(socket)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_file(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("examples/advanced_server")
+            .join(name)
+    }
+
+    /// Start server with a single handler, which responds after a delay.
+    ///
+    /// Returned notifier is signalled when handler starts processing a request.
+    async fn slow_server(
+        delay: Duration,
+    ) -> (
+        Handle,
+        SocketAddr,
+        JoinHandle<Result<(), io::Error>>,
+        Arc<tokio::sync::Notify>,
+    ) {
+        let started = Arc::new(tokio::sync::Notify::new());
+        let notify = Arc::clone(&started);
+        let app = axum::Router::new().route(
+            "/sleep",
+            axum::routing::get(move || {
+                notify.notify_one();
+                async move {
+                    tokio::time::sleep(delay).await;
+                    "done"
+                }
+            }),
+        );
+        let handle = Handle::new();
+        let server = ServerBuilder {
+            listen: "127.0.0.1:0".into(),
+            ..ServerBuilder::default()
+        }
+        .build()
+        .await
+        .unwrap()
+        .handle(handle.clone());
+        let task = tokio::spawn(server.serve(app.into_make_service()));
+        let addr = handle.listening().await.unwrap();
+        (handle, addr, task, started)
+    }
+
+    fn example_config() -> TlsConfig {
+        TlsConfig::new(example_file("tls.crt"), example_file("tls.key"))
+    }
+
+    #[tokio::test]
+    async fn tls_basic() {
+        example_config().rustls_config().await.unwrap();
+        example_config()
+            .with_versions(TlsVersion::Tls13, TlsVersion::Tls13)
+            .rustls_config()
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn tls_client_auth() {
+        example_config()
+            .with_client_ca(example_file("tls.crt"))
+            .with_required_client_cert(true)
+            .rustls_config()
+            .await
+            .unwrap();
+        let err = example_config()
+            .with_required_client_cert(true)
+            .rustls_config()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ServerBuilderError::NoClientCa));
+    }
+
+    #[tokio::test]
+    async fn tls_errors() {
+        let missing = example_file("missing.crt");
+        let err = TlsConfig::new(&missing, example_file("tls.key"))
+            .rustls_config()
+            .await
+            .unwrap_err();
+        assert!(matches!(&err, ServerBuilderError::TlsFile(path, _) if *path == missing));
+        assert!(err.to_string().contains("missing.crt"));
+
+        let err = TlsConfig::new(example_file("config.yaml"), example_file("tls.key"))
+            .rustls_config()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ServerBuilderError::TlsFileContents(..)));
+
+        let err = example_config()
+            .with_versions(TlsVersion::Tls13, TlsVersion::Tls12)
+            .rustls_config()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ServerBuilderError::TlsVersionRange(..)));
+    }
+
+    /// In-progress requests finish during grace period.
+    #[tokio::test]
+    async fn shutdown_graceful() {
+        let (handle, addr, server, started) = slow_server(Duration::from_millis(300)).await;
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        let shutdown = ShutdownConfig {
+            grace_period: Duration::from_secs(5),
+            drain_window: Duration::ZERO,
+            force_abort_on_second_signal: false,
+        };
+        let signals = tokio::spawn(handle_signals(
+            rx,
+            handle,
+            shutdown,
+            Duration::from_millis(100),
+        ));
+        let request = tokio::spawn(reqwest::get(format!("http://{addr}/sleep")));
+        started.notified().await;
+        tx.unbounded_send(Ok(Signal::Terminate)).unwrap();
+        // Signal handler exits after the first signal, since forced abort is disabled.
+        tokio::time::timeout(Duration::from_secs(1), signals)
+            .await
+            .unwrap()
+            .unwrap();
+        let resp = request.await.unwrap().unwrap();
+        assert_eq!(resp.text().await.unwrap(), "done");
+        server.await.unwrap().unwrap();
+        assert!(reqwest::get(format!("http://{addr}/sleep")).await.is_err());
+    }
+
+    /// Second shutdown signal aborts the server without waiting for grace period.
+    #[tokio::test]
+    async fn shutdown_forced() {
+        let (handle, addr, server, started) = slow_server(Duration::from_secs(30)).await;
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        let shutdown = ShutdownConfig {
+            grace_period: Duration::from_secs(30),
+            drain_window: Duration::ZERO,
+            force_abort_on_second_signal: true,
+        };
+        let signals = tokio::spawn(handle_signals(
+            rx,
+            handle,
+            shutdown,
+            Duration::from_millis(100),
+        ));
+        let request = tokio::spawn(reqwest::get(format!("http://{addr}/sleep")));
+        started.notified().await;
+        tx.unbounded_send(Ok(Signal::Terminate)).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!server.is_finished());
+        tx.unbounded_send(Ok(Signal::Interrupt)).unwrap();
+        tokio::time::timeout(Duration::from_secs(2), server)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(request.await.unwrap().is_err());
+        signals.await.unwrap();
+    }
+
+    /// New requests are still served during drain window.
+    #[tokio::test]
+    async fn shutdown_drain_window() {
+        let (handle, addr, server, started) = slow_server(Duration::ZERO).await;
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        let shutdown = ShutdownConfig {
+            grace_period: Duration::from_secs(5),
+            drain_window: Duration::from_millis(500),
+            force_abort_on_second_signal: false,
+        };
+        let signals = tokio::spawn(handle_signals(
+            rx,
+            handle,
+            shutdown,
+            Duration::from_millis(100),
+        ));
+        tx.unbounded_send(Ok(Signal::Terminate)).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let resp = reqwest::get(format!("http://{addr}/sleep")).await.unwrap();
+        started.notified().await;
+        assert_eq!(resp.text().await.unwrap(), "done");
+        assert!(!server.is_finished());
+        tokio::time::timeout(Duration::from_secs(2), server)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        signals.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn multiple_listeners() {
+        let app = axum::Router::new().route("/", axum::routing::get(|| async { "hello" }));
+        let server = ServerBuilder {
+            listen: ["127.0.0.1:0", "127.0.0.1:0"].into(),
+            ..ServerBuilder::default()
+        }
+        .build()
+        .await
+        .unwrap();
+        let addrs = server.local_addrs().to_vec();
+        assert_eq!(addrs.len(), 2);
+        assert_ne!(addrs[0], addrs[1]);
+        let handle = Handle::new();
+        let task = tokio::spawn(server.handle(handle.clone()).serve(app.into_make_service()));
+        handle.listening().await.unwrap();
+        for addr in addrs {
+            let resp = reqwest::get(format!("http://{addr}/")).await.unwrap();
+            assert_eq!(resp.text().await.unwrap(), "hello");
+        }
+        handle.graceful_shutdown(Some(Duration::from_secs(1)));
+        task.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn listener_errors() {
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let taken = taken.local_addr().unwrap().to_string();
+        let err = ServerBuilder {
+            listen: vec!["127.0.0.1:0", taken.as_str()].into(),
+            ..ServerBuilder::default()
+        }
+        .build()
+        .await
+        .unwrap_err();
+        assert!(
+            matches!(&err, ServerBuilderError::Listener(addr, _) if *addr == taken),
+            "{err}"
+        );
+
+        let err = ServerBuilder {
+            listen: Vec::<String>::new().into(),
+            ..ServerBuilder::default()
+        }
+        .build()
+        .await
+        .unwrap_err();
+        assert!(matches!(err, ServerBuilderError::NoListenAddrs));
+    }
+
+    #[tokio::test]
+    async fn unix_listener() {
+        let path = std::env::temp_dir().join(format!("uxum-builder-{}.sock", std::process::id()));
+        let listen = format!("unix:{}", path.display());
+        let server = ServerBuilder {
+            listen: vec!["127.0.0.1:0", listen.as_str()].into(),
+            ..ServerBuilder::default()
+        }
+        .build()
+        .await
+        .unwrap();
+        assert_eq!(server.local_addrs().len(), 1);
+        assert_eq!(server.unix_paths().collect::<Vec<_>>(), [path.as_path()]);
+        assert!(path.exists());
+        // Socket file is removed even if server never started.
+        drop(server);
+        assert!(!path.exists());
+
+        let err = ServerBuilder {
+            tls: Some(example_config().with_listen(listen.as_str())),
+            ..ServerBuilder::default()
+        }
+        .build_tls()
+        .await
+        .unwrap_err();
+        assert!(
+            matches!(&err, ServerBuilderError::Listener(addr, inner)
+                if *addr == listen && matches!(**inner, ServerBuilderError::TlsOnUnixSocket)),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn shutdown_deserialize() {
+        let cfg: ServerBuilder =
+            serde_json::from_str(r#"{"shutdown": {"grace_period": "1m"}}"#).unwrap();
+        assert_eq!(cfg.shutdown.grace_period, Duration::from_secs(60));
+        assert!(cfg.shutdown.force_abort_on_second_signal);
+        assert_eq!(cfg.shutdown.drain_window, Duration::ZERO);
+        assert_eq!(
+            ServerBuilder::default().shutdown.grace_period,
+            Duration::from_secs(5)
+        );
+    }
+
+    #[test]
+    fn tls_deserialize() {
+        let cfg: TlsConfig = serde_json::from_str(
+            r#"{"cert": "a.crt", "key": "a.key", "client_ca": "ca.crt", "min_version": "1.3"}"#,
+        )
+        .unwrap();
+        assert_eq!(cfg.min_version, TlsVersion::Tls13);
+        assert_eq!(cfg.max_version, TlsVersion::Tls13);
+        assert_eq!(cfg.client_ca.as_deref(), Some(Path::new("ca.crt")));
+        assert!(!cfg.require_client_cert);
+        assert_eq!(cfg.reload_interval, None);
+        let cfg: TlsConfig =
+            serde_json::from_str(r#"{"cert": "a.crt", "key": "a.key", "reload_interval": "5m"}"#)
+                .unwrap();
+        assert_eq!(cfg.reload_interval, Some(Duration::from_secs(300)));
+        assert_eq!(
+            cfg.files().collect::<Vec<_>>(),
+            [Path::new("a.crt"), Path::new("a.key")]
+        );
+    }
+}
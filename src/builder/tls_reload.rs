@@ -0,0 +1,251 @@
+//! Reloading of TLS certificates in running servers.
+//!
+//! New configuration is swapped atomically. Established connections keep using configuration
+//! they were accepted with, while new connections use reloaded one.
+
+use std::time::{Duration, SystemTime};
+
+use axum_server::tls_rustls::RustlsConfig;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tokio::{task::JoinHandle, time::MissedTickBehavior};
+use tracing::{debug, error, info};
+
+use crate::builder::server::{ServerBuilderError, TlsConfig};
+
+/// TLS configuration of a running server.
+struct ReloadTarget {
+    /// Server TLS configuration.
+    config: TlsConfig,
+    /// Configuration object shared with the server.
+    rustls: RustlsConfig,
+    /// File watcher task.
+    watcher: Option<JoinHandle<()>>,
+}
+
+impl Drop for ReloadTarget {
+    fn drop(&mut self) {
+        if let Some(watcher) = self.watcher.take() {
+            watcher.abort();
+        }
+    }
+}
+
+/// TLS configurations of running servers.
+static TARGETS: Lazy<Mutex<Vec<ReloadTarget>>> = Lazy::new(Default::default);
+
+/// Register TLS configuration of a server, so that it could be reloaded later.
+///
+/// Replaces previously registered configuration for the same listen address. Starts file watcher
+/// if configured.
+pub(crate) fn register(config: TlsConfig, rustls: RustlsConfig) {
+    let watcher = config
+        .reload_interval()
+        .map(|interval| tokio::spawn(watch(config.clone(), rustls.clone(), interval)));
+    let mut targets = TARGETS.lock();
+    targets.retain(|target| target.config.listen != config.listen);
+    targets.push(ReloadTarget {
+        config,
+        rustls,
+        watcher,
+    });
+}
+
+/// Reload TLS files for all registered servers.
+///
+/// # Errors
+///
+/// Returns `Err` if no servers are registered, or if files for some of the servers could not be
+/// loaded. Servers keep using their previous configuration in the latter case.
+pub(crate) async fn reload_all() -> Result<(), ServerBuilderError> {
+    let targets: Vec<_> = TARGETS
+        .lock()
+        .iter()
+        .map(|target| (target.config.clone(), target.rustls.clone()))
+        .collect();
+    if targets.is_empty() {
+        return Err(ServerBuilderError::NoTlsConfig);
+    }
+    let mut ret = Ok(());
+    for (config, rustls) in targets {
+        if let Err(err) = reload(&config, &rustls).await {
+            ret = ret.and(Err(err));
+        }
+    }
+    ret
+}
+
+/// Reload TLS files for a single server.
+async fn reload(config: &TlsConfig, rustls: &RustlsConfig) -> Result<(), ServerBuilderError> {
+    match config.server_config().await {
+        Ok((server_config, not_after)) => {
+            rustls.reload_from_config(server_config);
+            info!(
+                listen = %config.listen,
+                not_after = not_after.as_deref().unwrap_or("unknown"),
+                "reloaded TLS certificate"
+            );
+            Ok(())
+        }
+        Err(err) => {
+            error!(
+                listen = %config.listen,
+                "unable to reload TLS certificate, keeping previous one: {err}"
+            );
+            Err(err)
+        }
+    }
+}
+
+/// Periodically check TLS files for changes, and reload them.
+async fn watch(config: TlsConfig, rustls: RustlsConfig, interval: Duration) {
+    debug!(listen = %config.listen, ?interval, "watching TLS files");
+    let mut last = modified(&config).await;
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let current = modified(&config).await;
+        if current != last {
+            last = current;
+            // Errors are logged by reload function itself.
+            let _ = reload(&config, &rustls).await;
+        }
+    }
+}
+
+/// Get modification times of all TLS files.
+async fn modified(config: &TlsConfig) -> Vec<Option<SystemTime>> {
+    let mut times = Vec::new();
+    for path in config.files() {
+        let meta = tokio::fs::metadata(path).await;
+        times.push(meta.and_then(|meta| meta.modified()).ok());
+    }
+    times
+}
+
+/// Split DER-encoded value into tag, contents and remaining data.
+fn der_next(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&len, mut data) = data.split_first()?;
+    let len = match len {
+        0..=0x7f => usize::from(len),
+        0x81..=0x84 => {
+            let num = usize::from(len & 0x7f);
+            let bytes = data.get(..num)?;
+            data = &data[num..];
+            bytes
+                .iter()
+                .fold(0, |acc, byte| (acc << 8) | usize::from(*byte))
+        }
+        _ => return None,
+    };
+    Some((tag, data.get(..len)?, &data[len..]))
+}
+
+/// Extract expiration date from DER-encoded X.509 certificate, in RFC 3339 format.
+pub(crate) fn cert_not_after(der: &[u8]) -> Option<String> {
+    let (_, cert, _) = der_next(der)?;
+    let (_, mut tbs, _) = der_next(cert)?;
+    // Skip explicitly tagged version, if present.
+    if tbs.first() == Some(&0xa0) {
+        tbs = der_next(tbs)?.2;
+    }
+    // Skip serial number, signature algorithm and issuer.
+    for _ in 0..3 {
+        tbs = der_next(tbs)?.2;
+    }
+    let (_, validity, _) = der_next(tbs)?;
+    let (_, _, validity) = der_next(validity)?;
+    let (tag, time, _) = der_next(validity)?;
+    let time = std::str::from_utf8(time).ok()?.strip_suffix('Z')?;
+    if !time.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let time = match (tag, time.len()) {
+        // UTCTime, with two-digit year.
+        (0x17, 12) => match &time[..2] {
+            year if year < "50" => format!("20{time}"),
+            _ => format!("19{time}"),
+        },
+        // GeneralizedTime.
+        (0x18, 14) => time.to_string(),
+        _ => return None,
+    };
+    Some(format!(
+        "{}-{}-{}T{}:{}:{}Z",
+        &time[..4],
+        &time[4..6],
+        &time[6..8],
+        &time[8..10],
+        &time[10..12],
+        &time[12..14]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, sync::Arc};
+
+    use rustls::pki_types::{pem::PemObject, CertificateDer};
+
+    use super::*;
+
+    fn example_file(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("examples/advanced_server")
+            .join(name)
+    }
+
+    #[test]
+    fn not_after() {
+        let cert = CertificateDer::from_pem_file(example_file("tls.crt")).unwrap();
+        assert_eq!(
+            cert_not_after(&cert).as_deref(),
+            Some("2034-08-13T05:08:06Z")
+        );
+        assert_eq!(cert_not_after(&cert[..cert.len() / 2]), None);
+        assert_eq!(cert_not_after(b"\x30\x00"), None);
+    }
+
+    #[tokio::test]
+    async fn reload_changed_files() {
+        let dir = std::env::temp_dir().join(format!("uxum-tls-reload-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (cert, key) = (dir.join("tls.crt"), dir.join("tls.key"));
+        fs::copy(example_file("tls.crt"), &cert).unwrap();
+        fs::copy(example_file("tls.key"), &key).unwrap();
+        let config = TlsConfig::new(&cert, &key)
+            .with_listen("reload.test:8443")
+            .with_reload_interval(Duration::from_millis(20));
+        let rustls = config.rustls_config().await.unwrap();
+        register(config, rustls.clone());
+
+        // Explicit reload swaps configuration.
+        let initial = rustls.get_inner();
+        reload_all().await.unwrap();
+        let reloaded = rustls.get_inner();
+        assert!(!Arc::ptr_eq(&initial, &reloaded));
+
+        // Watcher picks up changed files.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        fs::File::options()
+            .write(true)
+            .open(&cert)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let watched = rustls.get_inner();
+        assert!(!Arc::ptr_eq(&reloaded, &watched));
+
+        // Invalid files keep previous configuration.
+        fs::write(&key, "garbage").unwrap();
+        assert!(reload_all().await.is_err());
+        assert!(Arc::ptr_eq(&watched, &rustls.get_inner()));
+
+        TARGETS.lock().clear();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,452 @@
+//! Serving HTTP over Unix domain sockets.
+//!
+//! [`axum_server`] only supports TCP listeners, so Unix socket listeners run their own accept
+//! loop. They take part in graceful shutdown initiated by the signal handler or by
+//! [`crate::Handle`], once attached to a server handle.
+
+use std::{
+    fs, io,
+    net::{Ipv4Addr, SocketAddr},
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use axum_server::{
+    service::{MakeService, SendService},
+    Handle,
+};
+use futures::future::poll_fn;
+use hyper::{body::Incoming, Request};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto::Builder,
+    service::TowerToHyperService,
+};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use socket2::{Domain, SockAddr, Socket, Type};
+use tokio::{net::UnixListener, task::JoinSet};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::builder::server::ServerBuilderError;
+
+/// Prefix of listen addresses denoting Unix domain socket paths.
+pub(crate) const UNIX_PREFIX: &str = "unix:";
+
+/// Peer address reported to services for connections accepted on Unix sockets.
+///
+/// Allows using the same make service, including the ones extracting [`SocketAddr`] as
+/// connection info.
+const UNIX_PEER_ADDR: SocketAddr = SocketAddr::new(std::net::IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+
+/// Unix domain socket configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct UnixSocketConfig {
+    /// File permissions of created socket, as an octal string like `"660"`.
+    ///
+    /// Permissions are determined by process umask if not set.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_mode",
+        serialize_with = "serialize_mode"
+    )]
+    pub mode: Option<u32>,
+    /// Remove stale socket file left by a previous process before binding.
+    ///
+    /// Socket is considered stale if nothing accepts connections on it. Files which are not
+    /// sockets are never removed.
+    #[serde(default = "crate::util::default_true")]
+    pub remove_stale: bool,
+}
+
+impl Default for UnixSocketConfig {
+    fn default() -> Self {
+        Self {
+            mode: None,
+            remove_stale: true,
+        }
+    }
+}
+
+/// Deserialize file mode from an octal string, or from an integer.
+fn deserialize_mode<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Mode {
+        Octal(String),
+        Number(u32),
+    }
+    match Option::<Mode>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Mode::Number(mode)) => Ok(Some(mode)),
+        Some(Mode::Octal(mode)) => u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+            .map(Some)
+            .map_err(|_| serde::de::Error::custom(format!("invalid octal file mode: {mode}"))),
+    }
+}
+
+/// Serialize file mode as an octal string.
+fn serialize_mode<S>(mode: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match mode {
+        Some(mode) => serializer.serialize_str(&format!("{mode:o}")),
+        None => serializer.serialize_none(),
+    }
+}
+
+impl UnixSocketConfig {
+    /// Create Unix socket listener at provided path.
+    ///
+    /// Socket file is removed when returned guard is dropped.
+    pub(crate) fn bind(
+        &self,
+        path: &Path,
+        backlog: i32,
+    ) -> Result<(UnixListener, SocketFile), ServerBuilderError> {
+        let bind_error = |err: io::Error| ServerBuilderError::BindUnix(path.into(), err.into());
+        if self.remove_stale {
+            remove_stale(path).map_err(bind_error)?;
+        }
+        let sock = Socket::new(Domain::UNIX, Type::STREAM, None)
+            .map_err(|err| ServerBuilderError::SocketCreate(err.into()))?;
+        sock.bind(&SockAddr::unix(path).map_err(bind_error)?)
+            .map_err(bind_error)?;
+        // Socket file exists from now on, so it must be removed on errors.
+        let file = SocketFile(path.into());
+        if let Some(mode) = self.mode {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode)).map_err(bind_error)?;
+        }
+        sock.listen(backlog).map_err(bind_error)?;
+        sock.set_nonblocking(true).map_err(bind_error)?;
+        let listener = UnixListener::from_std(sock.into())
+            .map_err(|err| ServerBuilderError::ConvertListener(err.into()))?;
+        Ok((listener, file))
+    }
+}
+
+/// Remove socket file, if nothing is listening on it.
+fn remove_stale(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => {
+            match std::os::unix::net::UnixStream::connect(path) {
+                Ok(_) => Err(io::ErrorKind::AddrInUse.into()),
+                Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+                    debug!(path = %path.display(), "removing stale socket file");
+                    fs::remove_file(path)
+                }
+                Err(err) => Err(err),
+            }
+        }
+        Ok(_) => Err(io::ErrorKind::AlreadyExists.into()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Socket file, removed when dropped.
+#[derive(Debug)]
+pub(crate) struct SocketFile(PathBuf);
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.0) {
+            warn!(path = %self.0.display(), "unable to remove socket file: {err}");
+        }
+    }
+}
+
+/// Shutdown state of a Unix socket server.
+#[derive(Debug, Default)]
+pub(crate) struct UnixShutdown {
+    /// Cancelled when graceful shutdown starts.
+    graceful: CancellationToken,
+    /// Grace period for in-progress requests.
+    grace_period: Mutex<Option<Duration>>,
+    /// Cancelled when server needs to stop immediately.
+    abort: CancellationToken,
+}
+
+impl UnixShutdown {
+    /// Stop accepting new connections, and wait for in-progress requests to finish.
+    ///
+    /// Connections are closed after grace period, if set.
+    pub(crate) fn graceful_shutdown(&self, grace_period: Option<Duration>) {
+        *self.grace_period.lock() = grace_period;
+        self.graceful.cancel();
+    }
+
+    /// Close all connections immediately.
+    pub(crate) fn shutdown(&self) {
+        self.graceful.cancel();
+        self.abort.cancel();
+    }
+}
+
+/// Unix socket servers attached to a server handle.
+static ATTACHED: Lazy<Mutex<Vec<Weak<UnixShutdown>>>> = Lazy::new(Default::default);
+
+/// Gracefully shut down all attached Unix socket servers.
+pub(crate) fn graceful_shutdown_all(grace_period: Option<Duration>) {
+    for server in attached() {
+        server.graceful_shutdown(grace_period);
+    }
+}
+
+/// Immediately shut down all attached Unix socket servers.
+pub(crate) fn shutdown_all() {
+    for server in attached() {
+        server.shutdown();
+    }
+}
+
+/// Get currently running attached servers, forgetting the ones which already exited.
+fn attached() -> Vec<Arc<UnixShutdown>> {
+    let mut servers = ATTACHED.lock();
+    servers.retain(|server| server.strong_count() > 0);
+    servers.iter().filter_map(Weak::upgrade).collect()
+}
+
+/// HTTP server listening on a Unix domain socket.
+///
+/// Socket file is removed when server exits, or when it is dropped without serving.
+pub struct UnixServer {
+    /// Bound listener.
+    listener: UnixListener,
+    /// Socket file.
+    file: SocketFile,
+    /// HTTP connection builder.
+    builder: Builder<TokioExecutor>,
+    /// Shutdown state.
+    shutdown: Arc<UnixShutdown>,
+    /// Whether server is attached to a server handle.
+    attached: bool,
+}
+
+impl std::fmt::Debug for UnixServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UnixServer")
+            .field("path", &self.file.0)
+            .field("attached", &self.attached)
+            .finish_non_exhaustive()
+    }
+}
+
+impl UnixServer {
+    /// Create server from bound listener.
+    pub(crate) fn new(
+        listener: UnixListener,
+        file: SocketFile,
+        builder: Builder<TokioExecutor>,
+    ) -> Self {
+        Self {
+            listener,
+            file,
+            builder,
+            shutdown: Arc::default(),
+            attached: false,
+        }
+    }
+
+    /// Path of socket file.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.file.0
+    }
+
+    /// Attach server to a server handle.
+    ///
+    /// Graceful shutdown and abort initiated by the signal handler, or by [`crate::Handle`], will
+    /// apply to this server as well. Calling shutdown methods on `handle` directly does not
+    /// affect Unix socket servers.
+    #[must_use]
+    pub fn handle(mut self, _handle: Handle) -> Self {
+        self.attached = true;
+        self
+    }
+
+    /// Serve provided [`MakeService`] on the socket.
+    ///
+    /// Services are created with unspecified IPv4 address as a target, so make services
+    /// extracting [`SocketAddr`] as connection info can be used.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if make service fails to become ready.
+    pub async fn serve<M>(self, mut make_service: M) -> io::Result<()>
+    where
+        M: MakeService<SocketAddr, Request<Incoming>>,
+        M::Service: SendService<Request<Incoming>>,
+    {
+        let Self {
+            listener,
+            file,
+            builder,
+            shutdown,
+            attached,
+        } = self;
+        if attached {
+            ATTACHED.lock().push(Arc::downgrade(&shutdown));
+        }
+        info!(path = %file.0.display(), "serving on Unix socket");
+        let builder = Arc::new(builder);
+        let mut connections = JoinSet::new();
+        let accept_loop = async {
+            loop {
+                let stream = tokio::select! {
+                    biased;
+                    () = shutdown.graceful.cancelled() => return Ok(()),
+                    res = listener.accept() => match res {
+                        Ok((stream, _)) => stream,
+                        Err(err) => {
+                            debug!("error accepting Unix socket connection: {err}");
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            continue;
+                        }
+                    },
+                };
+                poll_fn(|cx| make_service.poll_ready(cx))
+                    .await
+                    .map_err(io::Error::other)?;
+                let Ok(service) = make_service.make_service(UNIX_PEER_ADDR).await else {
+                    continue;
+                };
+                let service = TowerToHyperService::new(service.into_service());
+                let builder = Arc::clone(&builder);
+                let shutdown = Arc::clone(&shutdown);
+                connections.spawn(async move {
+                    let conn =
+                        builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+                    tokio::pin!(conn);
+                    tokio::select! {
+                        biased;
+                        () = shutdown.graceful.cancelled() => {
+                            conn.as_mut().graceful_shutdown();
+                            tokio::select! {
+                                biased;
+                                () = shutdown.abort.cancelled() => (),
+                                _ = &mut conn => (),
+                            }
+                        }
+                        _ = &mut conn => (),
+                    }
+                });
+                // Reap finished connections.
+                while connections.try_join_next().is_some() {}
+            }
+        };
+        let res = tokio::select! {
+            biased;
+            () = shutdown.abort.cancelled() => Ok(()),
+            res = accept_loop => res,
+        };
+        // Refuse new connections right away.
+        drop(listener);
+        drop(file);
+        let grace_period = *shutdown.grace_period.lock();
+        let wait_all = async { while connections.join_next().await.is_some() {} };
+        tokio::select! {
+            () = shutdown.abort.cancelled() => (),
+            () = wait_all => (),
+            () = sleep_or_forever(grace_period) => {
+                warn!(remaining = connections.len(), "grace period expired, closing Unix socket connections");
+            }
+        }
+        connections.shutdown().await;
+        res
+    }
+}
+
+/// Sleep for a duration, or forever if not set.
+async fn sleep_or_forever(duration: Option<Duration>) {
+    match duration {
+        Some(duration) => tokio::time::sleep(duration).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::MetadataExt;
+
+    use http_body_util::{BodyExt, Empty};
+    use hyper::body::Bytes;
+    use tokio::net::UnixStream;
+
+    use super::*;
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("uxum-{name}-{}.sock", std::process::id()))
+    }
+
+    /// Send GET request over Unix socket, returning response body.
+    async fn get(path: &Path, uri: &str) -> String {
+        let stream = UnixStream::connect(path).await.unwrap();
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        let req = Request::get(uri).body(Empty::<Bytes>::new()).unwrap();
+        let resp = sender.send_request(req).await.unwrap();
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn mode_serde() {
+        let cfg: UnixSocketConfig = serde_json::from_str(r#"{"mode": "660"}"#).unwrap();
+        assert_eq!(cfg.mode, Some(0o660));
+        assert!(cfg.remove_stale);
+        assert_eq!(
+            serde_json::to_string(&cfg).unwrap(),
+            r#"{"mode":"660","remove_stale":true}"#
+        );
+        assert!(serde_json::from_str::<UnixSocketConfig>(r#"{"mode": "999"}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn serve_unix() {
+        let path = socket_path("serve");
+        // Stale file from a previous run.
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let config = UnixSocketConfig {
+            mode: Some(0o600),
+            ..UnixSocketConfig::default()
+        };
+        let (listener, file) = config.bind(&path, 16).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o600);
+        // Second listener on the same path is refused, since socket is in use.
+        assert!(matches!(
+            config.bind(&path, 16),
+            Err(ServerBuilderError::BindUnix(..))
+        ));
+
+        let server = UnixServer::new(listener, file, Builder::new(TokioExecutor::new()));
+        let shutdown = Arc::clone(&server.shutdown);
+        let app = axum::Router::new().route(
+            "/",
+            axum::routing::get(
+                |axum::extract::ConnectInfo(addr): axum::extract::ConnectInfo<SocketAddr>| async move {
+                    addr.to_string()
+                },
+            ),
+        );
+        let task =
+            tokio::spawn(server.serve(app.into_make_service_with_connect_info::<SocketAddr>()));
+        assert_eq!(get(&path, "/").await, "0.0.0.0:0");
+
+        shutdown.graceful_shutdown(Some(Duration::from_secs(1)));
+        task.await.unwrap().unwrap();
+        assert!(!path.exists());
+    }
+}
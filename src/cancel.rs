@@ -0,0 +1,284 @@
+//! Cooperative cancellation of request handling work.
+//!
+//! Each request passing through the timeout layer gets its own [`CancellationToken`], which is a
+//! child of the global shutdown token. Request token is cancelled when request deadline expires,
+//! or when the client disconnects before the response is ready. Token is available as a request
+//! extension, and as a task-local value inside handler future.
+
+use std::future::Future;
+
+use once_cell::sync::Lazy;
+use opentelemetry::{metrics::Counter, KeyValue};
+use thiserror::Error;
+use tokio::task::futures::TaskLocalFuture;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+/// Global shutdown token, parent of all request tokens.
+static SHUTDOWN: Lazy<CancellationToken> = Lazy::new(CancellationToken::new);
+
+tokio::task_local! {
+    /// Cancellation token of currently executing request.
+    static CURRENT_CANCELLATION: CancellationToken;
+}
+
+/// Error returned when awaited work was cancelled.
+#[derive(Clone, Debug, Error)]
+#[error("Request handling was cancelled")]
+#[non_exhaustive]
+pub struct Cancelled;
+
+/// Reason for request cancellation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CancelCause {
+    /// Request deadline expired.
+    Timeout,
+    /// Client went away before response was ready.
+    Disconnect,
+}
+
+impl CancelCause {
+    /// Label value used in metrics.
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Timeout => "timeout",
+            Self::Disconnect => "disconnect",
+        }
+    }
+}
+
+/// Cancel all in-flight requests, used on immediate shutdown.
+pub(crate) fn cancel_all() {
+    SHUTDOWN.cancel();
+}
+
+/// Get cancellation token of currently executing request.
+///
+/// Returns [`None`] if called outside of request handling task.
+#[must_use]
+pub fn current_cancellation() -> Option<CancellationToken> {
+    CURRENT_CANCELLATION.try_with(Clone::clone).ok()
+}
+
+/// Run future with provided request cancellation token.
+///
+/// Use this to pass request cancellation to spawned tasks, since task-local values are not
+/// inherited by them.
+pub async fn with_cancellation<F: Future>(token: CancellationToken, fut: F) -> F::Output {
+    scope(token, fut).await
+}
+
+/// Scope future with request cancellation token, without awaiting it.
+pub(crate) fn scope<F: Future>(
+    token: CancellationToken,
+    fut: F,
+) -> TaskLocalFuture<CancellationToken, F> {
+    CURRENT_CANCELLATION.scope(token, fut)
+}
+
+/// Await future, aborting it if current request gets cancelled.
+///
+/// Outside of request handling task the future is awaited as is.
+///
+/// # Errors
+///
+/// Returns `Err` if request was cancelled before the future completed.
+pub async fn cancel_aware<F: Future>(fut: F) -> Result<F::Output, Cancelled> {
+    match current_cancellation() {
+        Some(token) => tokio::select! {
+            biased;
+            () = token.cancelled() => Err(Cancelled),
+            out = fut => Ok(out),
+        },
+        None => Ok(fut.await),
+    }
+}
+
+/// Per-request cancellation state, owned by request future.
+///
+/// Cancels the token with [`CancelCause::Disconnect`] when dropped before completion.
+#[derive(Debug)]
+pub struct RequestCancellation {
+    /// Request cancellation token.
+    token: CancellationToken,
+    /// Handler name, used in metrics.
+    handler: &'static str,
+    /// Cancellation counter.
+    counter: Option<Counter<u64>>,
+    /// Whether request is still in progress.
+    armed: bool,
+}
+
+impl RequestCancellation {
+    /// Create new request cancellation state.
+    pub(crate) fn new(handler: &'static str, counter: Option<Counter<u64>>) -> Self {
+        Self {
+            token: SHUTDOWN.child_token(),
+            handler,
+            counter,
+            armed: true,
+        }
+    }
+
+    /// Get request cancellation token.
+    pub(crate) fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    /// Cancel request, recording the cause.
+    pub(crate) fn cancel(&mut self, cause: CancelCause) {
+        if !std::mem::take(&mut self.armed) {
+            return;
+        }
+        debug!(
+            handler = self.handler,
+            cause = cause.as_str(),
+            "request cancelled"
+        );
+        self.token.cancel();
+        if let Some(counter) = &self.counter {
+            counter.add(
+                1,
+                &[
+                    KeyValue::new("handler", self.handler),
+                    KeyValue::new("cause", cause.as_str()),
+                ],
+            );
+        }
+    }
+
+    /// Mark request as completed, so that dropping does not cancel it.
+    pub(crate) fn complete(&mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for RequestCancellation {
+    fn drop(&mut self) {
+        self.cancel(CancelCause::Disconnect);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use axum::{
+        body::Body,
+        http::{Request, Response},
+    };
+    use tokio::{net::TcpListener, sync::oneshot, time::Instant};
+    use tower::{service_fn, Layer, Service, ServiceExt};
+
+    use super::*;
+    use crate::{layers::timeout::TimeoutLayer, HttpClientConfig};
+
+    /// Accept connections but never respond.
+    async fn stalled_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut conns = Vec::new();
+            while let Ok((conn, _)) = listener.accept().await {
+                conns.push(conn);
+            }
+        });
+        format!("http://{addr}/")
+    }
+
+    /// Downstream call made from a spawned task is aborted when handler deadline expires.
+    #[tokio::test]
+    async fn downstream_call_aborted_on_deadline() {
+        let url = stalled_server().await;
+        let client = HttpClientConfig::default()
+            .build_client(reqwest::Client::builder(), None)
+            .unwrap();
+        let (tx, rx) = oneshot::channel();
+        let tx = std::sync::Mutex::new(Some(tx));
+        let handler = service_fn(move |req: Request<Body>| {
+            let token = req
+                .extensions()
+                .get::<CancellationToken>()
+                .cloned()
+                .unwrap();
+            let tx = tx.lock().unwrap().take().unwrap();
+            let call = client.get(&url).send();
+            tokio::spawn(with_cancellation(token, async move {
+                let start = Instant::now();
+                let res = call.await;
+                let _ = tx.send((res, start.elapsed()));
+            }));
+            async {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            }
+        });
+        let config = crate::HandlerTimeoutConfig {
+            default_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let mut svc = TimeoutLayer::from(&config).layer(handler);
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::empty()))
+            .await;
+        assert!(res.unwrap_err().is::<crate::TimeoutError>());
+        let (res, elapsed) = tokio::time::timeout(Duration::from_secs(2), rx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(elapsed < Duration::from_secs(1));
+        match res.unwrap_err() {
+            reqwest_middleware::Error::Middleware(err) => assert!(err.is::<Cancelled>()),
+            err => panic!("unexpected error: {err}"),
+        }
+    }
+
+    /// Dropping request future before completion cancels the token, completion does not.
+    #[tokio::test]
+    async fn disconnect_and_completion() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let handler = service_fn(move |req: Request<Body>| {
+            let _ = tx.send(
+                req.extensions()
+                    .get::<CancellationToken>()
+                    .cloned()
+                    .unwrap(),
+            );
+            let slow = req.headers().contains_key("x-slow");
+            async move {
+                assert!(current_cancellation().is_some());
+                if slow {
+                    std::future::pending::<()>().await;
+                }
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            }
+        });
+        let config = crate::HandlerTimeoutConfig::default();
+        let mut svc = TimeoutLayer::from(&config).layer(handler);
+
+        let req = Request::builder()
+            .header("x-slow", "1")
+            .body(Body::empty())
+            .unwrap();
+        let fut = svc.ready().await.unwrap().call(req);
+        let token = rx.recv().await.unwrap();
+        assert!(!token.is_cancelled());
+        drop(fut);
+        assert!(token.is_cancelled());
+
+        let res = svc
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::empty()))
+            .await;
+        assert!(res.is_ok());
+        assert!(!rx.recv().await.unwrap().is_cancelled());
+        // Outside of request scope futures are awaited as is.
+        assert!(current_cancellation().is_none());
+        assert_eq!(cancel_aware(async { 1 }).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,332 @@
+//! Client-facing API changelog, aggregated from handler metadata.
+
+use std::cmp::Reverse;
+
+use askama::Template;
+use axum::{
+    extract::State,
+    http::Method,
+    response::IntoResponse,
+    routing::{self, Router},
+    Json,
+};
+use serde::Serialize;
+
+use crate::builder::app::HandlerExt;
+
+/// Note used for changelog entries generated from handler `since` attribute.
+const ADDED_NOTE: &str = "added";
+
+/// Single change to handler behavior, as declared in handler metadata.
+///
+/// Versions are validated at compile time when using [`macro@crate::handler`]:
+///
+/// ```compile_fail
+/// #[uxum::handler(changes(version = "1.x", note = "broken version"))]
+/// async fn hello() -> &'static str {
+///     "Hello"
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ApiChange {
+    /// API version in which the change was introduced.
+    version: &'static str,
+    /// Human-readable description of the change.
+    note: &'static str,
+}
+
+impl ApiChange {
+    /// Create new API change record.
+    #[must_use]
+    pub const fn new(version: &'static str, note: &'static str) -> Self {
+        Self { version, note }
+    }
+
+    /// Get API version in which the change was introduced.
+    #[must_use]
+    pub fn version(&self) -> &'static str {
+        self.version
+    }
+
+    /// Get human-readable description of the change.
+    #[must_use]
+    pub fn note(&self) -> &'static str {
+        self.note
+    }
+}
+
+/// Aggregated API changelog.
+#[derive(Clone, Debug, Serialize, Template)]
+#[template(path = "changelog.html.j2", ext = "html")]
+pub struct Changelog {
+    /// App title for use in UI.
+    #[serde(skip)]
+    title: String,
+    /// Changelog entries grouped by version, newest first.
+    versions: Vec<ChangelogVersion>,
+}
+
+/// Changelog entries for a single API version.
+#[derive(Clone, Debug, Serialize)]
+#[non_exhaustive]
+pub struct ChangelogVersion {
+    /// API version.
+    pub version: String,
+    /// Changes introduced in this version.
+    pub entries: Vec<ChangelogEntry>,
+}
+
+/// Single changelog entry.
+#[derive(Clone, Debug, Serialize)]
+#[non_exhaustive]
+pub struct ChangelogEntry {
+    /// Handler name.
+    pub handler: String,
+    /// HTTP method of handler.
+    ///
+    /// Comma-separated if handler is registered for several methods.
+    pub method: String,
+    /// URL path of handler.
+    pub path: String,
+    /// Human-readable description of the change.
+    pub note: String,
+}
+
+impl Changelog {
+    /// Aggregate changelog from handler metadata.
+    ///
+    /// Versions are sorted in descending semantic versioning order. Versions which fail to parse
+    /// are placed last.
+    pub(crate) fn from_handlers<'a>(
+        title: impl ToString,
+        handlers: impl IntoIterator<Item = &'a dyn HandlerExt>,
+        disabled: &[String],
+    ) -> Self {
+        let mut versions: Vec<ChangelogVersion> = Vec::new();
+        for handler in handlers {
+            if disabled.iter().any(|name| name == handler.name()) {
+                continue;
+            }
+            let added = handler.since().map(|ver| ApiChange::new(ver, ADDED_NOTE));
+            for change in added.iter().chain(handler.changes()) {
+                let entry = ChangelogEntry {
+                    handler: handler.name().to_owned(),
+                    method: handler
+                        .methods()
+                        .iter()
+                        .map(Method::as_str)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    path: handler.path().to_owned(),
+                    note: change.note.to_owned(),
+                };
+                match versions
+                    .iter_mut()
+                    .find(|ver| ver.version == change.version)
+                {
+                    Some(ver) => ver.entries.push(entry),
+                    None => versions.push(ChangelogVersion {
+                        version: change.version.to_owned(),
+                        entries: vec![entry],
+                    }),
+                }
+            }
+        }
+        versions.sort_by(|a, b| {
+            let key = |ver: &ChangelogVersion| Reverse(semver::Version::parse(&ver.version).ok());
+            key(a).cmp(&key(b)).then_with(|| a.version.cmp(&b.version))
+        });
+        for ver in &mut versions {
+            ver.entries.sort_by(|a, b| {
+                (&a.handler, &a.method, &a.note).cmp(&(&b.handler, &b.method, &b.note))
+            });
+        }
+        Self {
+            title: title.to_string(),
+            versions,
+        }
+    }
+
+    /// Build router serving changelog as HTML and as JSON.
+    pub(crate) fn build_router(self, html_path: Option<&str>, json_path: &str) -> Router {
+        let mut rtr = Router::new().route(json_path, routing::get(get_changelog_json));
+        if let Some(html_path) = html_path {
+            rtr = rtr.route(html_path, routing::get(get_changelog_html));
+        }
+        rtr.with_state(self)
+    }
+}
+
+/// Handler to serve changelog page.
+async fn get_changelog_html(changelog: State<Changelog>) -> impl IntoResponse {
+    changelog.0.into_response()
+}
+
+/// Handler to serve changelog as JSON.
+async fn get_changelog_json(changelog: State<Changelog>) -> impl IntoResponse {
+    Json(changelog.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{any::TypeId, convert::Infallible};
+
+    use axum::{
+        body::Body,
+        http::{Method, Request, Response, StatusCode},
+    };
+    use okapi::{openapi3, schemars::gen::SchemaGenerator};
+    use tower::{util::BoxCloneService, ServiceExt};
+
+    use super::*;
+
+    /// Test handler metadata.
+    struct Meta {
+        name: &'static str,
+        since: Option<&'static str>,
+        changes: &'static [ApiChange],
+    }
+
+    impl HandlerExt for Meta {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn path(&self) -> &'static str {
+            "/test"
+        }
+
+        fn spec_path(&self) -> &'static str {
+            "/test"
+        }
+
+        fn method(&self) -> Method {
+            Method::GET
+        }
+
+        fn module(&self) -> &'static str {
+            module_path!()
+        }
+
+        fn handler_type_id(&self) -> TypeId {
+            TypeId::of::<Self>()
+        }
+
+        fn permissions(&self) -> &'static [&'static str] {
+            &[]
+        }
+
+        fn no_auth(&self) -> bool {
+            true
+        }
+
+        fn since(&self) -> Option<&'static str> {
+            self.since
+        }
+
+        fn changes(&self) -> &'static [ApiChange] {
+            self.changes
+        }
+
+        fn service(&self) -> BoxCloneService<Request<Body>, Response<Body>, Infallible> {
+            unimplemented!()
+        }
+
+        fn openapi_spec(&self, _gen: &mut SchemaGenerator) -> openapi3::Operation {
+            openapi3::Operation::default()
+        }
+    }
+
+    static USERS: Meta = Meta {
+        name: "users",
+        since: Some("1.4.0"),
+        changes: &[
+            ApiChange::new("1.10.0", "added `status` field"),
+            ApiChange::new("1.6.0", "added paging"),
+        ],
+    };
+    static ORDERS: Meta = Meta {
+        name: "orders",
+        since: Some("1.6.0"),
+        changes: &[ApiChange::new("2.0.0-rc.1", "removed `legacy` field")],
+    };
+    static HIDDEN: Meta = Meta {
+        name: "hidden",
+        since: Some("3.0.0"),
+        changes: &[],
+    };
+
+    /// Entries are grouped by version, versions are sorted semver-descending.
+    #[test]
+    fn aggregation_ordering() {
+        let changelog = Changelog::from_handlers(
+            "Test",
+            [&USERS as &dyn HandlerExt, &ORDERS, &HIDDEN],
+            &["hidden".to_owned()],
+        );
+        let versions: Vec<_> = changelog
+            .versions
+            .iter()
+            .map(|ver| ver.version.as_str())
+            .collect();
+        assert_eq!(versions, ["2.0.0-rc.1", "1.10.0", "1.6.0", "1.4.0"]);
+        let entries: Vec<_> = changelog.versions[2]
+            .entries
+            .iter()
+            .map(|entry| (entry.handler.as_str(), entry.note.as_str()))
+            .collect();
+        assert_eq!(entries, [("orders", "added"), ("users", "added paging")]);
+    }
+
+    /// JSON endpoint follows published schema, HTML page is rendered.
+    #[tokio::test]
+    async fn changelog_endpoints() {
+        let changelog = Changelog::from_handlers("Test", [&USERS as &dyn HandlerExt], &[]);
+        let app = changelog.build_router(Some("/apidoc/changelog"), "/apidoc/changelog.json");
+
+        let resp = app
+            .clone()
+            .oneshot(
+                Request::get("/apidoc/changelog.json")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            json["versions"][0],
+            serde_json::json!({
+                "version": "1.10.0",
+                "entries": [{
+                    "handler": "users",
+                    "method": "GET",
+                    "path": "/test",
+                    "note": "added `status` field",
+                }],
+            })
+        );
+        assert_eq!(json["versions"].as_array().unwrap().len(), 3);
+        assert!(json.get("title").is_none());
+
+        let resp = app
+            .oneshot(
+                Request::get("/apidoc/changelog")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let html = String::from_utf8(body.to_vec()).unwrap();
+        assert!(html.contains("added `status` field"));
+        assert!(html.find("1.10.0").unwrap() < html.find("1.4.0").unwrap());
+    }
+}
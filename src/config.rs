@@ -2,24 +2,67 @@
 
 use std::collections::HashMap;
 
-use serde::{Deserialize, Serialize};
+use bytesize::ByteSize;
+use http::Method;
+use serde::{Deserialize, Serialize, Serializer};
+use thiserror::Error;
+use tracing::warn;
 
 use crate::{
     apidoc::ApiDocBuilder,
     auth::AuthConfig,
-    http_client::HttpClientConfig,
+    batch::BatchConfig,
+    builder::routing::RoutingConfig,
+    errors::ErrorsConfig,
+    hooks::ResponseHooksConfig,
+    http_client::{EgressPolicyConfig, HttpClientConfig},
     layers::{
-        buffer::HandlerBufferConfig, cors::CorsConfig, rate::HandlerRateLimitConfig,
+        buffer::HandlerBufferConfig,
+        cache::CachePolicyConfig,
+        cb::HandlerCircuitBreakerConfig,
+        client_ip::ClientIpConfig,
+        compression::CompressionConfig,
+        contract::{ResponseValidationConfig, ResponseValidationMode},
+        cors::CorsConfig,
+        cpu_guard::CpuGuardConfig,
+        dependency::DependencyTimingConfig,
+        deprecation::{DeprecationConfig, DeprecationReportConfig},
+        fair::HandlerFairQueueConfig,
+        identity::ResponseIdentityConfig,
+        ip_filter::IpFilterConfig,
+        rate::HandlerRateLimitConfig,
+        request_id::RequestIdConfig,
+        response_cache::HandlerCacheConfig,
         timeout::HandlerTimeoutConfig,
     },
     logging::LoggingConfig,
-    metrics::MetricsBuilder,
+    memory::MemoryConfig,
+    metrics::{HandlerMetricsConfig, MetricsBuilder},
+    persist::StatePersistenceConfig,
     probes::ProbeConfig,
+    queue::JobQueueConfig,
+    retry::RetryAdviceConfig,
     runtime::RuntimeConfig,
+    startup::StartupConfig,
+    static_dir::StaticDirConfig,
     telemetry::OpenTelemetryConfig,
     tracing::TracingConfig,
+    warmup::WarmupConfig,
 };
 
+/// Error type used when resolving application configuration.
+#[derive(Clone, Debug, Error, PartialEq)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// Path-keyed handler configuration does not match any registered handler.
+    #[error("Handler configuration key does not match any handler: {0}")]
+    UnknownHandlerKey(String),
+    /// Path-keyed handler configuration matches more than one handler, or more than one
+    /// path-keyed entry matches the same handler.
+    #[error("Ambiguous handler configuration key: {0}")]
+    AmbiguousHandlerKey(String),
+}
+
 /// Top-level application configuration.
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 #[non_exhaustive]
@@ -34,8 +77,26 @@ pub struct AppConfig {
     #[serde(default)]
     pub tracing: Option<TracingConfig>,
     /// Individual handler configuration.
+    ///
+    /// Keyed either by handler name, or by HTTP method and URL path, separated by whitespace
+    /// (`"GET /hello/:name"`). Path parameters can be written as `:param` or `{param}`. If both
+    /// a name-keyed and a path-keyed entry match the same handler, name-keyed entry wins.
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub handlers: HashMap<String, HandlerConfig>,
+    /// Fail application build on any invalid handler CORS configuration.
+    ///
+    /// By default, handlers with malformed origins or header names are served without CORS, and
+    /// a warning is logged. Wildcards combined with credentials are always rejected.
+    #[serde(default)]
+    pub strict_cors: bool,
+    /// Routing analysis configuration.
+    #[serde(default)]
+    pub routing: RoutingConfig,
+    /// Default IP filter configuration.
+    ///
+    /// Applied to handlers which have no IP filter configured on their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip_filter: Option<IpFilterConfig>,
     /// API doc configuration.
     #[serde(default)]
     pub api_doc: Option<ApiDocBuilder>,
@@ -51,9 +112,124 @@ pub struct AppConfig {
     /// Authentication and authorization back-end configuration.
     #[serde(default)]
     pub auth: AuthConfig,
+    /// Retry advice configuration for rejected requests.
+    #[serde(default)]
+    pub retry_advice: RetryAdviceConfig,
+    /// Built-in error response configuration.
+    #[serde(default)]
+    pub errors: ErrorsConfig,
+    /// Batch request endpoint configuration.
+    ///
+    /// Batch endpoint is disabled by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<BatchConfig>,
+    /// Warmup configuration.
+    ///
+    /// Synthetic warmup requests are only issued if this section is present.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warmup: Option<WarmupConfig>,
+    /// Startup dependency ordering configuration.
+    #[serde(default)]
+    pub startup: StartupConfig,
+    /// Profiling endpoints configuration.
+    ///
+    /// Profiling endpoints are only enabled if this section is present.
+    #[cfg(feature = "profiling")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profiling: Option<crate::profiling::ProfilingConfig>,
+    /// Caching headers policy.
+    ///
+    /// Caching headers are not added if no rules are configured.
+    #[serde(default)]
+    pub cache_policy: CachePolicyConfig,
+    /// Service identity headers added to all responses.
+    #[serde(default)]
+    pub response_identity: ResponseIdentityConfig,
+    /// Client IP address resolution.
+    ///
+    /// Resolved address is used by IP filters, client IP rate limits, request ID trust and API
+    /// documentation server derivation.
+    #[serde(default)]
+    pub client_ip: ClientIpConfig,
+    /// Handling of request IDs passed by clients.
+    #[serde(default)]
+    pub request_id: RequestIdConfig,
+    /// Response compression.
+    ///
+    /// Can be disabled for individual handlers, see [`HandlerConfig::compression`].
+    #[serde(default)]
+    pub compression: CompressionConfig,
+    /// Periodic summary of deprecated handler usage.
+    ///
+    /// Summary is not logged if this section is absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecation_report: Option<DeprecationReportConfig>,
+    /// Static asset directories.
+    #[serde(default, alias = "static_files", skip_serializing_if = "Vec::is_empty")]
+    pub static_dirs: Vec<StaticDirConfig>,
+    /// Downstream dependency latency attribution.
+    ///
+    /// Dependency times are not tracked if this section is absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dependency_timing: Option<DependencyTimingConfig>,
+    /// Detection of CPU-bound handlers which do not yield to async runtime.
+    ///
+    /// Only applies to handlers marked with `cpu_guard` attribute.
+    #[serde(default)]
+    pub cpu_guard: CpuGuardConfig,
+    /// Validation of handler responses against declared schemas.
+    ///
+    /// Intended for contract testing environments, disabled by default.
+    #[serde(default)]
+    pub response_validation: ResponseValidationConfig,
+    /// Maximum request body size, like `2 MiB`.
+    ///
+    /// Overridden by [`HandlerConfig::max_body_size`]. If not set, default limit of body
+    /// extractors is used.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_byte_size"
+    )]
+    pub max_body_size: Option<ByteSize>,
+    /// Decode request bodies sent with `Content-Encoding` of `gzip`, `deflate` or `br`.
+    ///
+    /// Disabled by default.
+    #[serde(default)]
+    pub accept_compressed_requests: bool,
+    /// Maximum size of decoded request body, like `16 MiB`.
+    ///
+    /// Protects against decompression bombs. Defaults to 16 MiB.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_byte_size"
+    )]
+    pub max_decompressed_body_size: Option<ByteSize>,
+    /// Post-response hooks scheduled by handlers.
+    #[serde(default)]
+    pub response_hooks: ResponseHooksConfig,
+    /// Persistence of circuit breaker and rate limiter state across restarts.
+    ///
+    /// State is not persisted if this section is absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_persistence: Option<StatePersistenceConfig>,
+    /// Global budget for buffered request and response bodies.
+    #[serde(default)]
+    pub memory: MemoryConfig,
+    /// Embedded job queue.
+    ///
+    /// Registered job handlers are not started if this section is absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue: Option<JobQueueConfig>,
     /// [`reqwest`] HTTP client configuration.
     #[serde(default)]
     pub http_clients: HashMap<String, HttpClientConfig>,
+    /// Default outgoing request policy for HTTP clients.
+    ///
+    /// Used for clients without their own policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub egress: Option<EgressPolicyConfig>,
     /// Short application name.
     #[serde(skip)]
     pub app_name: Option<String>,
@@ -76,6 +252,16 @@ impl AppConfig {
         self
     }
 
+    /// Get effective request body size limit for a handler, in bytes.
+    #[must_use]
+    pub(crate) fn max_body_size(&self, handler: &str) -> Option<u64> {
+        self.handlers
+            .get(handler)
+            .and_then(|cfg| cfg.max_body_size)
+            .or(self.max_body_size)
+            .map(|size| size.as_u64())
+    }
+
     /// Set application version.
     ///
     /// Preferably in semver format. Whitespace is not allowed, as this value is used in Server:
@@ -85,6 +271,97 @@ impl AppConfig {
         self.app_version = Some(app_version.to_string());
         self
     }
+
+    /// Rewrite path-keyed handler configuration entries to be keyed by handler name.
+    ///
+    /// `handlers` is a list of registered handler names, methods and paths.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if some path-keyed entry matches no handler, or is ambiguous.
+    pub(crate) fn resolve_handler_keys(
+        &mut self,
+        handlers: &[(&str, Method, &str)],
+    ) -> Result<(), ConfigError> {
+        let mut by_path: HashMap<String, (String, HandlerConfig)> = HashMap::new();
+        let keys: Vec<String> = self.handlers.keys().cloned().collect();
+        for key in keys {
+            let Some((method, path)) = parse_handler_key(&key) else {
+                continue;
+            };
+            let mut matched = handlers
+                .iter()
+                .filter(|(_, hmethod, hpath)| *hmethod == method && normalize_path(hpath) == path);
+            let name = match (matched.next(), matched.next()) {
+                (None, _) => return Err(ConfigError::UnknownHandlerKey(key)),
+                (Some(_), Some(_)) => return Err(ConfigError::AmbiguousHandlerKey(key)),
+                (Some((name, _, _)), None) => name.to_string(),
+            };
+            // SAFETY: key was taken from the map.
+            let cfg = self.handlers.remove(&key).unwrap();
+            if let Some((other_key, _)) = by_path.insert(name, (key.clone(), cfg)) {
+                return Err(ConfigError::AmbiguousHandlerKey(format!(
+                    "{other_key}, {key}"
+                )));
+            }
+        }
+        for (name, (key, cfg)) in by_path {
+            if self.handlers.contains_key(&name) {
+                warn!(
+                    name,
+                    key, "ignoring path-keyed handler config: name-keyed entry exists"
+                );
+                continue;
+            }
+            self.handlers.insert(name, cfg);
+        }
+        Ok(())
+    }
+}
+
+/// Get all keys which can be used to address handler configuration.
+#[must_use]
+pub(crate) fn handler_config_keys(name: &str, method: &Method, path: &str) -> Vec<String> {
+    vec![name.to_string(), format!("{method} {path}")]
+}
+
+/// Serialize byte size as a plain number of bytes.
+///
+/// Textual form produced by [`ByteSize`] is rounded, and does not survive a round trip.
+fn serialize_byte_size<S: Serializer>(
+    value: &Option<ByteSize>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    value.map(|size| size.as_u64()).serialize(serializer)
+}
+
+/// Try to parse handler configuration key as HTTP method and normalized URL path.
+///
+/// Returns [`None`] if key is not path-based, i.e. it is a handler name.
+fn parse_handler_key(key: &str) -> Option<(Method, String)> {
+    let (method, path) = key.trim().split_once(char::is_whitespace)?;
+    let path = path.trim();
+    if !path.starts_with('/') {
+        return None;
+    }
+    let method = Method::from_bytes(method.to_ascii_uppercase().as_bytes()).ok()?;
+    Some((method, normalize_path(path)))
+}
+
+/// Normalize URL path parameter syntax to `{param}` / `{*param}` form.
+fn normalize_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            if let Some(param) = segment.strip_prefix(':') {
+                format!("{{{param}}}")
+            } else if let Some(param) = segment.strip_prefix('*') {
+                format!("{{*{param}}}")
+            } else {
+                segment.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
 }
 
 /// Configuration of a single handler.
@@ -100,19 +377,167 @@ pub struct HandlerConfig {
     /// Request buffering configuration.
     #[serde(default)]
     pub buffer: Option<HandlerBufferConfig>,
+    /// In-memory response cache configuration.
+    ///
+    /// Only applies to `GET` and `HEAD` requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<HandlerCacheConfig>,
     /// CORS configuration.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub cors: Option<CorsConfig>,
+    /// IP filter configuration.
+    ///
+    /// Overrides [`AppConfig::ip_filter`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip_filter: Option<IpFilterConfig>,
     /// Rate limiter configuration.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub rate_limit: Option<HandlerRateLimitConfig>,
     /// Throttling configuration.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub throttle: Option<u8>,
+    /// Weighted fair queuing between tenants.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fair_queue: Option<HandlerFairQueueConfig>,
+    /// Circuit breaker, rejecting requests while handler keeps failing.
+    ///
+    /// Handler errors and 5xx responses count as failures.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub circuit_breaker: Option<HandlerCircuitBreakerConfig>,
     /// Request timeout configuration.
     #[serde(default, skip_serializing_if = "HandlerTimeoutConfig::is_default")]
     pub timeout: HandlerTimeoutConfig,
     /// Required RBAC permissions.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub permissions: Vec<String>,
+    /// Deprecation configuration.
+    ///
+    /// Handlers marked with `deprecated` attribute use default deprecation configuration if this
+    /// is not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecation: Option<DeprecationConfig>,
+    /// Do not add service identity headers to responses of this handler.
+    ///
+    /// See [`AppConfig::response_identity`].
+    #[serde(default)]
+    pub suppress_identity: bool,
+    /// Set to `false` to disable response compression for this handler.
+    ///
+    /// See [`AppConfig::compression`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<bool>,
+    /// Override response validation mode for this handler.
+    ///
+    /// See [`AppConfig::response_validation`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_validation: Option<ResponseValidationMode>,
+    /// Maximum request body size, like `2 MiB`.
+    ///
+    /// Overrides [`AppConfig::max_body_size`].
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_byte_size"
+    )]
+    pub max_body_size: Option<ByteSize>,
+    /// Metrics configuration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<HandlerMetricsConfig>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handlers() -> Vec<(&'static str, Method, &'static str)> {
+        vec![
+            ("hello", Method::GET, "/hello/:name"),
+            ("hello_post", Method::POST, "/hello/:name"),
+            ("files", Method::GET, "/files/*rest"),
+        ]
+    }
+
+    fn config(keys: &[&str]) -> AppConfig {
+        let mut cfg = AppConfig::default();
+        for (idx, key) in keys.iter().enumerate() {
+            let hcfg = HandlerConfig {
+                permissions: vec![idx.to_string()],
+                ..Default::default()
+            };
+            cfg.handlers.insert(key.to_string(), hcfg);
+        }
+        cfg
+    }
+
+    /// Path-keyed entries are rewritten to names, with parameter syntax normalized.
+    #[test]
+    fn resolve_path_keys() {
+        let mut cfg = config(&[
+            "GET /hello/{name}",
+            "post  /hello/:name",
+            "GET /files/{*rest}",
+        ]);
+        cfg.resolve_handler_keys(&handlers()).unwrap();
+        assert_eq!(cfg.handlers["hello"].permissions, ["0"]);
+        assert_eq!(cfg.handlers["hello_post"].permissions, ["1"]);
+        assert_eq!(cfg.handlers["files"].permissions, ["2"]);
+        assert_eq!(cfg.handlers.len(), 3);
+    }
+
+    /// Name-keyed entry wins over path-keyed one.
+    #[test]
+    fn resolve_name_wins() {
+        let mut cfg = config(&["GET /hello/:name", "hello"]);
+        cfg.resolve_handler_keys(&handlers()).unwrap();
+        assert_eq!(cfg.handlers["hello"].permissions, ["1"]);
+        assert_eq!(cfg.handlers.len(), 1);
+    }
+
+    /// Unknown path or method.
+    #[test]
+    fn resolve_unknown() {
+        let mut cfg = config(&["PUT /hello/:name"]);
+        assert_eq!(
+            cfg.resolve_handler_keys(&handlers()),
+            Err(ConfigError::UnknownHandlerKey("PUT /hello/:name".into()))
+        );
+        let mut cfg = config(&["GET /nope"]);
+        assert!(cfg.resolve_handler_keys(&handlers()).is_err());
+    }
+
+    /// Two path-keyed entries matching the same handler, or duplicate handler routes.
+    #[test]
+    fn resolve_ambiguous() {
+        let mut cfg = config(&["GET /hello/:name", "GET /hello/{name}"]);
+        assert!(matches!(
+            cfg.resolve_handler_keys(&handlers()),
+            Err(ConfigError::AmbiguousHandlerKey(_))
+        ));
+        let mut dup = handlers();
+        dup.push(("hello2", Method::GET, "/hello/{name}"));
+        let mut cfg = config(&["GET /hello/:name"]);
+        assert!(matches!(
+            cfg.resolve_handler_keys(&dup),
+            Err(ConfigError::AmbiguousHandlerKey(_))
+        ));
+    }
+
+    /// Body size limits accept human-friendly sizes, and handler limits override global one.
+    #[test]
+    fn max_body_size() {
+        let cfg: AppConfig = serde_json::from_value(serde_json::json!({
+            "max_body_size": "2 MiB",
+            "handlers": {
+                "upload": {"max_body_size": "10 MB"},
+                "small": {"max_body_size": 1024},
+            },
+        }))
+        .unwrap();
+        assert_eq!(cfg.max_body_size("other"), Some(2 * 1024 * 1024));
+        assert_eq!(cfg.max_body_size("upload"), Some(10_000_000));
+        assert_eq!(cfg.max_body_size("small"), Some(1024));
+        let value = serde_json::to_value(&cfg).unwrap();
+        assert_eq!(value["max_body_size"], 2 * 1024 * 1024);
+        assert_eq!(value["handlers"]["upload"]["max_body_size"], 10_000_000);
+    }
 }
@@ -0,0 +1,384 @@
+//! Drain coordination for long-lived streaming connections.
+//!
+//! Streams like server-sent events can legitimately outlive any reasonable graceful shutdown
+//! timeout. Such streams register themselves with the drain coordinator. When graceful shutdown
+//! starts, registered streams are notified, and given a separate, usually shorter grace period to
+//! finish on their own. Streams still active past that period are terminated.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::response::sse::Event;
+use dashmap::DashMap;
+use futures::{Stream, StreamExt};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+/// Comment sent to server-sent events clients when shutdown starts.
+const SSE_SHUTDOWN_COMMENT: &str = "server shutting down";
+
+/// Global drain coordinator.
+static DRAIN: Lazy<DrainCoordinator> = Lazy::new(DrainCoordinator::new);
+
+/// Type of long-lived connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum StreamKind {
+    /// Server-sent events stream.
+    ServerSentEvents,
+    /// WebSocket connection.
+    WebSocket,
+    /// Server-streaming or bidirectional gRPC call.
+    Grpc,
+    /// Other kind of long-lived response.
+    Other,
+}
+
+/// Long-lived connection, as seen in drain snapshot.
+#[derive(Clone, Debug, Serialize)]
+#[non_exhaustive]
+pub struct StreamInfo {
+    /// Type of connection.
+    pub kind: StreamKind,
+    /// Time since connection was registered.
+    #[serde(with = "humantime_serde")]
+    pub age: Duration,
+}
+
+/// Point-in-time state of long-lived connection draining.
+#[derive(Clone, Debug, Serialize)]
+#[non_exhaustive]
+pub struct DrainSnapshot {
+    /// Whether shutdown notification was already sent.
+    pub draining: bool,
+    /// Currently active long-lived connections, oldest first.
+    pub streams: Vec<StreamInfo>,
+    /// Number of connections terminated after their grace period expired.
+    pub terminated: u64,
+}
+
+/// Shared state of drain coordinator.
+#[derive(Debug)]
+struct DrainState {
+    /// Identifier for next registered stream.
+    next_id: AtomicU64,
+    /// Active streams.
+    streams: DashMap<u64, (StreamKind, Instant)>,
+    /// Cancelled when shutdown starts.
+    notify: CancellationToken,
+    /// Cancelled when grace period for streams expires.
+    terminate: CancellationToken,
+    /// Woken up when a stream gets deregistered.
+    removed: Notify,
+    /// Number of streams terminated after grace period.
+    terminated: AtomicU64,
+}
+
+/// Registry of long-lived connections.
+#[derive(Clone, Debug)]
+pub(crate) struct DrainCoordinator {
+    /// Shared state.
+    state: Arc<DrainState>,
+}
+
+impl DrainCoordinator {
+    /// Create new drain coordinator.
+    pub(crate) fn new() -> Self {
+        Self {
+            state: Arc::new(DrainState {
+                next_id: AtomicU64::new(0),
+                streams: DashMap::new(),
+                notify: CancellationToken::new(),
+                terminate: CancellationToken::new(),
+                removed: Notify::new(),
+                terminated: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Register new long-lived connection.
+    pub(crate) fn register(&self, kind: StreamKind) -> StreamGuard {
+        let id = self.state.next_id.fetch_add(1, Ordering::Relaxed);
+        self.state.streams.insert(id, (kind, Instant::now()));
+        StreamGuard {
+            id,
+            state: self.state.clone(),
+        }
+    }
+
+    /// Wrap stream of server-sent events, so that it takes part in draining.
+    pub(crate) fn sse<S, E>(&self, stream: S) -> impl Stream<Item = Result<Event, E>> + Send
+    where
+        S: Stream<Item = Result<Event, E>> + Send + 'static,
+        E: Send + 'static,
+    {
+        let guard = self.register(StreamKind::ServerSentEvents);
+        futures::stream::unfold(
+            (stream.boxed(), guard, false),
+            |(mut stream, guard, notified)| async move {
+                let item = if notified {
+                    tokio::select! {
+                        biased;
+                        () = guard.terminated() => None,
+                        item = stream.next() => item,
+                    }
+                } else {
+                    tokio::select! {
+                        biased;
+                        () = guard.terminated() => None,
+                        () = guard.notified() => {
+                            let event = Event::default().comment(SSE_SHUTDOWN_COMMENT);
+                            return Some((Ok(event), (stream, guard, true)));
+                        }
+                        item = stream.next() => item,
+                    }
+                };
+                item.map(|item| (item, (stream, guard, notified)))
+            },
+        )
+    }
+
+    /// Notify long-lived connections about shutdown, then terminate the ones still active after
+    /// grace period.
+    pub(crate) async fn drain(&self, grace: Duration) {
+        let state = &self.state;
+        info!(
+            streams = state.streams.len(),
+            ?grace,
+            "draining long-lived connections"
+        );
+        state.notify.cancel();
+        let all_done = async {
+            loop {
+                // Waiter must be created before checking, to not miss wakeups.
+                let removed = state.removed.notified();
+                if state.streams.is_empty() {
+                    break;
+                }
+                removed.await;
+            }
+        };
+        if tokio::time::timeout(grace, all_done).await.is_ok() {
+            debug!("all long-lived connections finished");
+            return;
+        }
+        self.terminate();
+    }
+
+    /// Immediately terminate all long-lived connections.
+    pub(crate) fn terminate(&self) {
+        let state = &self.state;
+        let remaining = state.streams.len() as u64;
+        if remaining > 0 {
+            warn!(remaining, "terminating long-lived connections");
+            state.terminated.fetch_add(remaining, Ordering::Relaxed);
+        }
+        state.notify.cancel();
+        state.terminate.cancel();
+    }
+
+    /// Get current state of long-lived connections.
+    pub(crate) fn snapshot(&self) -> DrainSnapshot {
+        let now = Instant::now();
+        let mut streams: Vec<_> = self
+            .state
+            .streams
+            .iter()
+            .map(|entry| {
+                let (kind, start) = *entry.value();
+                StreamInfo {
+                    kind,
+                    age: now.saturating_duration_since(start),
+                }
+            })
+            .collect();
+        streams.sort_by_key(|info| std::cmp::Reverse(info.age));
+        DrainSnapshot {
+            draining: self.state.notify.is_cancelled(),
+            streams,
+            terminated: self.state.terminated.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Registration of a long-lived connection.
+///
+/// Connection is deregistered when guard is dropped.
+#[derive(Debug)]
+pub struct StreamGuard {
+    /// Stream identifier.
+    id: u64,
+    /// Coordinator state.
+    state: Arc<DrainState>,
+}
+
+impl StreamGuard {
+    /// Wait until server starts shutting down.
+    ///
+    /// Connection should notify its client (send close frame, final event or status), and finish
+    /// as soon as possible.
+    pub async fn notified(&self) {
+        self.state.notify.cancelled().await;
+    }
+
+    /// Wait until grace period for long-lived connections expires.
+    ///
+    /// Connection must be closed immediately.
+    pub async fn terminated(&self) {
+        self.state.terminate.cancelled().await;
+    }
+
+    /// Check whether server is shutting down.
+    #[must_use]
+    pub fn is_draining(&self) -> bool {
+        self.state.notify.is_cancelled()
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.state.streams.remove(&self.id);
+        self.state.removed.notify_waiters();
+    }
+}
+
+/// Register long-lived connection, so that it takes part in graceful shutdown.
+///
+/// Use this for WebSocket connections, streaming gRPC calls or any other long-running responses.
+/// Keep returned guard for as long as the connection is active.
+#[must_use]
+pub fn register_stream(kind: StreamKind) -> StreamGuard {
+    DRAIN.register(kind)
+}
+
+/// Wrap stream of server-sent events, so that it takes part in graceful shutdown.
+///
+/// When shutdown starts, clients receive a final comment event. Stream is closed if it is still
+/// active after grace period for long-lived connections.
+pub fn drainable_sse<S, E>(stream: S) -> impl Stream<Item = Result<Event, E>> + Send
+where
+    S: Stream<Item = Result<Event, E>> + Send + 'static,
+    E: Send + 'static,
+{
+    DRAIN.sse(stream)
+}
+
+/// Get current state of long-lived connections.
+#[must_use]
+pub fn drain_snapshot() -> DrainSnapshot {
+    DRAIN.snapshot()
+}
+
+/// Start draining long-lived connections in background.
+pub(crate) fn start_drain(grace: Duration) {
+    tokio::spawn(DRAIN.drain(grace));
+}
+
+/// Immediately terminate all long-lived connections.
+pub(crate) fn terminate_all() {
+    DRAIN.terminate();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use axum::{body::Body, response::Sse, routing, Router};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// SSE client receives shutdown notification, then stream is closed by the server.
+    #[tokio::test]
+    async fn sse_notified_on_shutdown() {
+        let drain = DrainCoordinator::new();
+        let coordinator = drain.clone();
+        let app = Router::new().route(
+            "/events",
+            routing::get(move || async move {
+                let events = futures::stream::pending::<Result<Event, Infallible>>()
+                    .chain(futures::stream::iter([Ok(Event::default().data("never"))]));
+                Sse::new(coordinator.sse(events))
+            }),
+        );
+        let resp = app
+            .oneshot(
+                axum::http::Request::get("/events")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let snapshot = drain.snapshot();
+        assert!(!snapshot.draining);
+        assert_eq!(snapshot.streams.len(), 1);
+        assert_eq!(snapshot.streams[0].kind, StreamKind::ServerSentEvents);
+
+        let drainer = tokio::spawn({
+            let drain = drain.clone();
+            async move { drain.drain(Duration::from_millis(100)).await }
+        });
+        let body = tokio::time::timeout(Duration::from_secs(2), resp.into_body().collect())
+            .await
+            .unwrap()
+            .unwrap()
+            .to_bytes();
+        assert_eq!(&body[..], b": server shutting down\n\n");
+        drainer.await.unwrap();
+        let snapshot = drain.snapshot();
+        assert!(snapshot.draining);
+        assert!(snapshot.streams.is_empty());
+        assert_eq!(snapshot.terminated, 1);
+    }
+
+    /// Streams get their own grace period, cooperative streams are not terminated.
+    #[tokio::test]
+    async fn stream_grace_period() {
+        let drain = DrainCoordinator::new();
+        let stubborn = drain.register(StreamKind::WebSocket);
+        let polite = drain.register(StreamKind::Grpc);
+        let polite_task = tokio::spawn(async move {
+            polite.notified().await;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(polite);
+        });
+        let stubborn_task = tokio::spawn(async move {
+            stubborn.notified().await;
+            let start = Instant::now();
+            stubborn.terminated().await;
+            start.elapsed()
+        });
+
+        let start = Instant::now();
+        drain.drain(Duration::from_millis(200)).await;
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(200));
+        assert!(elapsed < Duration::from_secs(1));
+        polite_task.await.unwrap();
+        assert!(stubborn_task.await.unwrap() >= Duration::from_millis(150));
+        let snapshot = drain.snapshot();
+        assert!(snapshot.streams.is_empty());
+        assert_eq!(snapshot.terminated, 1);
+
+        // Drain finishes early when all streams are gone.
+        let drain = DrainCoordinator::new();
+        let guard = drain.register(StreamKind::Other);
+        tokio::spawn(async move {
+            guard.notified().await;
+        });
+        let start = Instant::now();
+        drain.drain(Duration::from_secs(5)).await;
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(drain.snapshot().terminated, 0);
+    }
+}
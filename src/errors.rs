@@ -2,6 +2,15 @@
 
 use std::{fmt, io};
 
+use axum::{
+    http::StatusCode,
+    response::{IntoResponseParts, ResponseParts},
+};
+use problemdetails::Problem;
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::{i18n::LocalizationConfig, layers::recent_errors::RecentErrorsConfig};
+
 /// Wrapper for [`std::io::Error`].
 #[derive(Debug)]
 #[repr(transparent)]
@@ -22,3 +31,412 @@ impl fmt::Display for IoError {
         Ok(())
     }
 }
+
+/// Configuration for built-in error responses.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct ErrorsConfig {
+    /// Include current trace and span IDs in problem details error responses.
+    ///
+    /// Also adds `traceparent` response header. When current span is not sampled, only trace ID is
+    /// included, along with `sampled: false` field.
+    #[serde(default)]
+    include_trace_id: bool,
+    /// Localization of error messages, according to `Accept-Language` request header.
+    ///
+    /// Disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    localization: Option<LocalizationConfig>,
+    /// In-memory buffers of recent error responses, exposed via management endpoints.
+    #[serde(default)]
+    recent: RecentErrorsConfig,
+}
+
+impl ErrorsConfig {
+    /// Whether trace context is included in error responses.
+    #[must_use]
+    #[inline]
+    pub fn includes_trace_id(&self) -> bool {
+        self.include_trace_id
+    }
+
+    /// Include or omit trace context in error responses.
+    #[must_use]
+    pub fn with_include_trace_id(mut self, include: bool) -> Self {
+        self.include_trace_id = include;
+        self
+    }
+
+    /// Error message localization configuration, if enabled.
+    #[must_use]
+    #[inline]
+    pub fn localization(&self) -> Option<&LocalizationConfig> {
+        self.localization.as_ref()
+    }
+
+    /// Enable localization of error messages.
+    #[must_use]
+    pub fn with_localization(mut self, localization: LocalizationConfig) -> Self {
+        self.localization = Some(localization);
+        self
+    }
+
+    /// Recent error responses configuration.
+    #[must_use]
+    #[inline]
+    pub fn recent_errors(&self) -> &RecentErrorsConfig {
+        &self.recent
+    }
+
+    /// Set recent error responses configuration.
+    #[must_use]
+    pub fn with_recent_errors(mut self, recent: RecentErrorsConfig) -> Self {
+        self.recent = recent;
+        self
+    }
+}
+
+/// Stable machine-readable error code.
+///
+/// Added as `code` field to all built-in problem details responses. Unlike problem type URIs,
+/// codes distinguish individual error conditions, and never change once released.
+///
+/// When used as a part of a response, code is also attached as a response extension and written
+/// to access log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ErrorCode(&'static str);
+
+impl ErrorCode {
+    /// Create new error code.
+    ///
+    /// Application-defined codes should not clash with [built-in ones](codes).
+    #[must_use]
+    pub const fn new(code: &'static str) -> Self {
+        Self(code)
+    }
+
+    /// Get code as a string.
+    #[must_use]
+    #[inline]
+    pub const fn as_str(self) -> &'static str {
+        self.0
+    }
+
+    /// Create problem details object, with `code` field set.
+    #[must_use]
+    pub fn problem(self, status: StatusCode) -> Problem {
+        problemdetails::new(status).with_value("code", self.0)
+    }
+
+    /// Get registry entry for a built-in error code.
+    #[must_use]
+    pub fn info(self) -> Option<&'static ErrorCodeInfo> {
+        ERROR_CODES.iter().find(|info| info.code == self)
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+
+impl IntoResponseParts for ErrorCode {
+    type Error = std::convert::Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        res.extensions_mut().insert(self);
+        Ok(res)
+    }
+}
+
+/// Registry entry for a built-in error code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct ErrorCodeInfo {
+    /// Error code.
+    pub code: ErrorCode,
+    /// Usual HTTP status code of responses carrying this error code.
+    pub status: u16,
+    /// Meaning of the error code.
+    pub description: &'static str,
+}
+
+/// Define built-in error code constants, registry and its documentation table.
+macro_rules! error_codes {
+    ($($name:ident = $code:literal, $status:literal, $desc:literal;)+) => {
+        /// Built-in error codes.
+        ///
+        /// | Code | Status | Meaning |
+        /// |------|--------|---------|
+        $(#[doc = concat!("| `", $code, "` | ", $status, " | ", $desc, " |")])+
+        pub mod codes {
+            use super::ErrorCode;
+
+            $(
+                #[doc = concat!($desc, ".")]
+                pub const $name: ErrorCode = ErrorCode::new($code);
+            )+
+        }
+
+        /// Registry of all built-in error codes.
+        ///
+        /// See [`codes`] for a table.
+        pub const ERROR_CODES: &[ErrorCodeInfo] = &[$(ErrorCodeInfo {
+            code: codes::$name,
+            status: $status,
+            description: $desc,
+        }),+];
+    };
+}
+
+error_codes! {
+    INTERNAL_ERROR = "internal_error", 500, "Unhandled error in request processing";
+    PANIC = "panic", 500, "Handler panicked";
+    RATE_LIMITED = "rate_limited", 429, "Rate limit reached";
+    RATE_LIMIT_KEY = "rate_limit.key", 400, "Unable to extract rate limiting key";
+    HANDLER_TIMEOUT = "handler_timeout", 504, "Handler timed out";
+    FAIR_QUEUE_FULL = "fair_queue.full", 429, "Too many queued requests for tenant";
+    FAIR_QUEUE_CLOSED = "fair_queue.closed", 503, "Request queue was shut down";
+    CIRCUIT_OPEN = "circuit_breaker.open", 503, "Handler circuit breaker is open";
+    IP_NO_CLIENT_IP = "ip_filter.no_client_ip", 403, "Unable to determine client IP address";
+    IP_DENIED = "ip_filter.denied", 403, "Client IP address is not allowed";
+    TRANSFORM_REJECTED = "transform.rejected", 400, "Transformer rejected request body";
+    TRANSFORM_READ = "transform.read", 400, "Unable to read request body";
+    TRANSFORM_TOO_LARGE = "transform.too_large", 413, "Request body exceeds size limit";
+    BODY_TOO_LARGE = "body.too_large", 413, "Request body exceeds configured size limit";
+    DECOMPRESSED_TOO_LARGE = "body.decompressed_too_large", 413, "Decompressed request body exceeds size limit";
+    VALIDATION_FAILED = "validation.failed", 422, "Request body violates validation rules";
+    CONTRACT_VIOLATION = "contract.violation", 500, "Response does not match declared schema";
+    NOT_FOUND = "not_found", 404, "No handler found for request path";
+    GONE = "gone", 410, "Handler was removed after its sunset date";
+    MEMORY_EXHAUSTED = "memory.exhausted", 503, "Buffered memory budget exhausted";
+    BATCH_TOO_LARGE = "batch.too_large", 413, "Too many sub-requests in a batch";
+    BATCH_RECURSIVE = "batch.recursive", 400, "Nested batch requests are not allowed";
+    BATCH_INVALID = "batch.invalid", 400, "Invalid sub-request method, path or header";
+    BATCH_TIMEOUT = "batch.timeout", 504, "Sub-request timed out";
+    AUTH_MISSING = "auth.missing_credentials", 401, "No authentication data provided";
+    AUTH_MALFORMED = "auth.malformed", 400, "Malformed authentication data";
+    AUTH_INVALID = "auth.invalid_credentials", 401, "Authentication failed";
+    AUTH_SESSION_EXPIRED = "auth.session_expired", 401, "Session expired";
+    AUTH_FORBIDDEN = "auth.forbidden", 403, "User does not have permission";
+    AUTH_MISCONFIGURED = "auth.misconfigured", 500, "Authentication is misconfigured";
+    API_KEY_NOT_CONFIGURED = "api_key.not_configured", 503, "API keys are not configured";
+    API_KEY_NOT_FOUND = "api_key.not_found", 404, "API key not found";
+    API_KEY_INTERNAL = "api_key.internal", 500, "Unable to hash or store API key";
+    OIDC_MISCONFIGURED = "oidc.misconfigured", 500, "OpenID Connect is misconfigured";
+    OIDC_PROVIDER = "oidc.provider", 502, "OpenID provider request failed";
+    OIDC_INVALID_STATE = "oidc.invalid_state", 400, "Login state is missing, expired or mismatched";
+    OIDC_AUTHORIZATION = "oidc.authorization", 400, "OpenID provider returned an error";
+    OIDC_INVALID_TOKEN = "oidc.invalid_token", 401, "Invalid ID token";
+    SAMPLING_INVALID_RATIO = "sampling.invalid_ratio", 400, "Sampling ratio must be between 0 and 1";
+    SAMPLING_NOT_RUNNING = "sampling.not_running", 503, "Tracing pipeline with adjustable sampling is not running";
+    LOGGING_INVALID_SELECTOR = "logging.invalid_selector", 400, "Subscriber must be addressed either by index or by name";
+    LOGGING_UNKNOWN_SUBSCRIBER = "logging.unknown_subscriber", 404, "Unknown logging subscriber";
+    LOGGING_IMMUTABLE = "logging.immutable", 403, "Logging subscriber is immutable";
+    QUEUE_NOT_CONFIGURED = "queue.not_configured", 503, "Job queue is not configured";
+    QUEUE_UNKNOWN_JOB_TYPE = "queue.unknown_job_type", 400, "No handler registered for job type";
+    QUEUE_NOT_FOUND = "queue.not_found", 404, "Job not found";
+    QUEUE_INVALID_PAYLOAD = "queue.invalid_payload", 400, "Unable to encode job payload";
+    QUEUE_INTERNAL = "queue.internal", 500, "Job store error";
+    METRICS_INTERNAL = "metrics.internal", 500, "Metrics exporter error";
+    PROFILING_BUSY = "profiling.busy", 429, "Profiling request in progress or cooling down";
+    PROFILING_NOT_ACTIVE = "profiling.not_active", 503, "Heap profiling is not active";
+    PROFILING_FAILED = "profiling.failed", 500, "Unable to collect profiling data";
+    RECENT_ERRORS_UNKNOWN_HANDLER = "recent_errors.unknown_handler", 404, "Unknown handler";
+    HANDLER_DISABLED = "handler.disabled", 503, "Handler was disabled at runtime";
+    HANDLER_UNKNOWN = "handler.unknown", 404, "Unknown handler";
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use axum::{
+        body::Body,
+        http::Response,
+        response::{IntoResponse, Response as AxumResponse},
+    };
+
+    use super::*;
+    use crate::{
+        auth::AuthError,
+        batch::BatchError,
+        builder::app::{error_handler, panic_handler},
+        kv::KvError,
+        layers::{
+            body_limit::BodyLimitError,
+            cb::CircuitBreakerError,
+            decompression::DecompressionError,
+            deprecation::DeprecationError,
+            fair::FairQueueError,
+            ip_filter::IpFilterError,
+            rate::{RateLimitError, RateLimitKey},
+            recent_errors::RecentErrorsError,
+            timeout::TimeoutError,
+            toggle::HandlerToggleError,
+            transform::TransformError,
+            util::ExtractionError,
+        },
+        logging::control::LoggingControlError,
+        memory::MemoryError,
+        metrics::MetricsError,
+        queue::QueueError,
+        retry::{RetryAdviceConfig, RetrySource},
+        tracing::sampling::SamplingControlError,
+        ApiKeyError,
+    };
+
+    /// Get code from both response body and extension, checking that they match.
+    async fn response_code(resp: Response<Body>) -> (StatusCode, ErrorCode) {
+        let status = resp.status();
+        let code = *resp.extensions().get::<ErrorCode>().unwrap();
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        if !bytes.is_empty() {
+            let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(body["code"], code.as_str());
+        }
+        (status, code)
+    }
+
+    /// Codes are unique, and documentation table lists all of them.
+    #[test]
+    fn registry() {
+        let mut seen = HashSet::new();
+        for info in ERROR_CODES {
+            assert!(seen.insert(info.code), "duplicate code {}", info.code);
+            assert!(StatusCode::from_u16(info.status).is_ok());
+            assert_eq!(info.code.info(), Some(info));
+        }
+        assert_eq!(ErrorCode::new("custom").info(), None);
+    }
+
+    /// Every built-in error response carries a registered code.
+    #[tokio::test]
+    async fn built_in_errors() {
+        let advice = RetryAdviceConfig::default().advise(RetrySource::RateLimit, None);
+        let auth_errors = [
+            AuthError::NoAuthProvided,
+            AuthError::InvalidAuthHeader,
+            AuthError::UnknownAuthScheme("digest".into()),
+            AuthError::InvalidAuthPayload,
+            AuthError::UserNotFound,
+            AuthError::AuthFailed,
+            AuthError::SessionExpired,
+            AuthError::NoPermission("perm"),
+        ];
+        let mut responses: Vec<AxumResponse> = vec![
+            BatchError::TooLarge { size: 10, max: 5 }.into_response(),
+            BatchError::Recursive.into_response(),
+            BatchError::InvalidMethod("X".into()).into_response(),
+            BatchError::InvalidPath("x".into()).into_response(),
+            BatchError::InvalidHeader("x".into()).into_response(),
+            BatchError::Timeout.into_response(),
+            ApiKeyError::NotConfigured.into_response(),
+            ApiKeyError::NotFound("x".into()).into_response(),
+            ApiKeyError::Hash("x".into()).into_response(),
+            ApiKeyError::Store(KvError::Backend("x".into())).into_response(),
+            SamplingControlError::InvalidRatio(2.0).into_response(),
+            SamplingControlError::NotRunning.into_response(),
+            QueueError::NotConfigured.into_response(),
+            QueueError::UnknownJobType("x".into()).into_response(),
+            QueueError::NotFound("x".into()).into_response(),
+            QueueError::Store(KvError::Backend("x".into())).into_response(),
+            LoggingControlError::InvalidSelector.into_response(),
+            LoggingControlError::UnknownSubscriber("x".into()).into_response(),
+            LoggingControlError::Immutable("x".into()).into_response(),
+            RecentErrorsError::UnknownHandler("x".into()).into_response(),
+            HandlerToggleError::Disabled {
+                name: "x".into(),
+                advice,
+            }
+            .into_response(),
+            HandlerToggleError::UnknownHandler("x".into()).into_response(),
+            TransformError::new("x").into_response(),
+            TransformError::Read("x".into()).into_response(),
+            TransformError::TooLarge(1).into_response(),
+            BodyLimitError::TooLarge(1).into_response(),
+            DecompressionError::TooLarge(1).into_response(),
+            crate::ValidationErrors::new().into_response(),
+            crate::layers::contract::contract_violation(
+                StatusCode::OK,
+                crate::ValidationErrors::new(),
+            ),
+            DeprecationError::Gone {
+                handler: "x",
+                successor: None,
+            }
+            .into_response(),
+            FairQueueError::QueueFull { advice }.into_response(),
+            FairQueueError::Closed.into_response(),
+            CircuitBreakerError::Open { advice }.into_response(),
+            IpFilterError::NoClientIp { hide: false }.into_response(),
+            IpFilterError::Denied {
+                ip: [127, 0, 0, 1].into(),
+                hide: false,
+            }
+            .into_response(),
+            TimeoutError::TimedOut.into_response(),
+            RateLimitError::Extraction(ExtractionError).into_response(),
+            RateLimitError::LimitReached {
+                remaining_seconds: 1,
+                advice,
+                state: None,
+                key: RateLimitKey::Global,
+            }
+            .into_response(),
+            MemoryError::Exhausted { layer: "x", advice }.into_response(),
+            MetricsError::Prometheus(prometheus::Error::Msg("x".into())).into_response(),
+            crate::builder::fallback::not_found(),
+            error_handler("unknown".into()).await,
+            panic_handler(Box::new("boom")),
+        ];
+        responses.extend(
+            auth_errors
+                .iter()
+                .map(|err| err.problem_response(StatusCode::UNAUTHORIZED)),
+        );
+        let mut seen = HashSet::new();
+        for resp in responses {
+            let (status, code) = response_code(resp).await;
+            let info = code.info().unwrap_or_else(|| panic!("unknown code {code}"));
+            if !code.as_str().starts_with("auth.") {
+                assert_eq!(status.as_u16(), info.status, "status of {code}");
+            }
+            seen.insert(code);
+        }
+        // Codes used only by optional features or in misconfiguration.
+        let optional = [
+            codes::AUTH_MISCONFIGURED,
+            codes::QUEUE_INVALID_PAYLOAD,
+            codes::OIDC_MISCONFIGURED,
+            codes::OIDC_PROVIDER,
+            codes::OIDC_INVALID_STATE,
+            codes::OIDC_AUTHORIZATION,
+            codes::OIDC_INVALID_TOKEN,
+            codes::PROFILING_BUSY,
+            codes::PROFILING_NOT_ACTIVE,
+            codes::PROFILING_FAILED,
+        ];
+        for info in ERROR_CODES {
+            assert!(
+                seen.contains(&info.code) || optional.contains(&info.code),
+                "code {} is not covered",
+                info.code
+            );
+        }
+
+        // Hidden IP filter rejections look like regular 404, but are still tagged.
+        let resp = IpFilterError::NoClientIp { hide: true }.into_response();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(resp.extensions().get(), Some(&codes::IP_NO_CLIENT_IP));
+    }
+}
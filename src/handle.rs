@@ -14,6 +14,7 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use crate::{
     builder::server::ServerBuilder, config::AppConfig, errors::IoError, notify::ServiceNotifier,
+    probes::Lifecycle,
 };
 
 /// Error type returned by uxum handle.
@@ -68,6 +69,12 @@ pub struct Handle {
     http_task: Option<JoinHandle<Result<(), HandleError>>>,
     /// HTTPS server task.
     https_task: Option<JoinHandle<Result<(), HandleError>>>,
+    /// Grace period for long-lived streaming connections.
+    stream_drain_timeout: Duration,
+    /// Grace period for in-progress requests.
+    grace_period: Duration,
+    /// Time between readiness probe starting to fail and graceful shutdown start.
+    drain_window: Duration,
 }
 
 impl Drop for Handle {
@@ -85,6 +92,9 @@ impl Drop for Handle {
 impl Handle {
     /// Set up background service tasks.
     fn prepare(&mut self, server: &ServerBuilder) -> Result<(), HandleError> {
+        self.stream_drain_timeout = server.stream_drain_timeout;
+        self.grace_period = server.shutdown.grace_period;
+        self.drain_window = server.shutdown.drain_window;
         if self.signal_handler.is_none() {
             self.signal_handler = Some(server.spawn_signal_handler(self.handle.clone())?);
         }
@@ -125,24 +135,45 @@ impl Handle {
 
     /// Start the server in the background.
     ///
+    /// Probes are switched to ready state once server listeners are bound.
+    ///
     /// # Errors
     ///
     /// Returns `Err` if caught an error when initializing server tasks.
     pub async fn start(&mut self, server: ServerBuilder, app: Router) -> Result<(), HandleError> {
         self.prepare(&server)?;
         self.start_servers(server, app).await?;
+        self.set_ready();
         self.notify.on_ready();
         Ok(())
     }
 
+    /// Switch startup and readiness probes to ready state.
+    ///
+    /// This is called automatically when the server starts.
+    pub fn set_ready(&self) {
+        crate::probes::set_lifecycle(Lifecycle::Ready);
+    }
+
+    /// Switch readiness probe to draining state, making it fail.
+    ///
+    /// This is called automatically when shutdown is requested.
+    pub fn set_draining(&self) {
+        crate::probes::set_lifecycle(Lifecycle::Draining);
+    }
+
     /// Immediately shutdown the server.
     ///
     /// # Errors
     ///
     /// Returns `Err` if one of server tasks finished with an error.
     pub async fn shutdown(&mut self) -> Result<(), HandleError> {
+        self.set_draining();
         self.notify.on_shutdown();
+        crate::cancel::cancel_all();
+        crate::drain::terminate_all();
         self.handle.shutdown();
+        crate::builder::unix::shutdown_all();
         if let Some(task) = self.http_task.take() {
             task.await??;
         }
@@ -154,6 +185,15 @@ impl Handle {
 
     /// Gracefully shutdown the server, waiting for in-progress requests to finish.
     ///
+    /// If `graceful` is `None`, grace period from [`ServerBuilder::shutdown`] is used. Readiness
+    /// probe starts failing right away, and shutdown itself starts after the drain window from the
+    /// same configuration.
+    ///
+    /// Long-lived streaming connections are notified, and terminated after their own grace
+    /// period, configured in [`ServerBuilder::stream_drain_timeout`]. Job queue workers stop
+    /// claiming new jobs, and in-flight jobs are given the same grace period to finish. The same
+    /// applies to pending post-response hooks.
+    ///
     /// # Errors
     ///
     /// Returns `Err` if one of server tasks finished with an error.
@@ -161,20 +201,45 @@ impl Handle {
         &mut self,
         graceful: Option<Duration>,
     ) -> Result<(), HandleError> {
+        let graceful = Some(graceful.unwrap_or(self.grace_period));
+        self.set_draining();
+        tokio::time::sleep(self.drain_window).await;
         self.notify.on_shutdown();
+        crate::drain::start_drain(self.stream_drain_timeout);
         self.handle.graceful_shutdown(graceful);
+        crate::builder::unix::graceful_shutdown_all(graceful);
         if let Some(task) = self.http_task.take() {
             task.await??;
         }
         if let Some(task) = self.https_task.take() {
             task.await??;
         }
+        crate::queue::drain(graceful).await;
+        crate::hooks::drain(graceful).await;
+        save_state().await;
         Ok(())
     }
 
+    /// Reload certificates, private keys and client CA certificates of running HTTPS servers.
+    ///
+    /// New configuration is swapped atomically: established connections are not affected, and
+    /// new connections use reloaded certificates. This is also done on SIGHUP.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if no HTTPS server is running, or if some of TLS files could not be loaded.
+    /// Servers keep using their previous configuration in the latter case.
+    pub async fn reload_tls(&self) -> Result<(), HandleError> {
+        Ok(crate::builder::tls_reload::reload_all().await?)
+    }
+
     /// Immediately abort execution of the server.
     pub fn abort(&mut self) {
+        self.set_draining();
         self.notify.on_shutdown();
+        crate::cancel::cancel_all();
+        crate::drain::terminate_all();
+        crate::builder::unix::shutdown_all();
         if let Some(task) = self.http_task.take() {
             task.abort();
         }
@@ -185,7 +250,8 @@ impl Handle {
 
     /// Start the server and block execution until one of the server tasks exits.
     ///
-    /// Will gracefully shutdown remaining server tasks.
+    /// Will gracefully shutdown remaining server tasks. If `graceful` is `None`, grace period from
+    /// [`ServerBuilder::shutdown`] is used.
     ///
     /// # Errors
     ///
@@ -204,15 +270,20 @@ impl Handle {
 
     /// Block execution until one of the server tasks exits.
     ///
-    /// Will gracefully shutdown remaining server tasks.
+    /// Will gracefully shutdown remaining server tasks. If `graceful` is `None`, grace period from
+    /// [`ServerBuilder::shutdown`] is used.
     ///
     /// # Errors
     ///
     /// Returns `Err` if one of server tasks finished with an error.
     pub async fn wait(&mut self, graceful: Option<Duration>) -> Result<(), HandleError> {
+        let graceful = Some(graceful.unwrap_or(self.grace_period));
         let http_fut = self.http_task.take();
         let https_fut = self.https_task.take();
-        match (http_fut, https_fut) {
+        if http_fut.is_none() && https_fut.is_none() {
+            return Err(HandleError::NotRunning);
+        }
+        let ret = match (http_fut, https_fut) {
             (None, None) => Err(HandleError::NotRunning),
             (Some(task), None) => task.await?,
             (None, Some(task)) => task.await?,
@@ -224,7 +295,10 @@ impl Handle {
                     // Gracefully shutdown other tasks and return result of the one which exited
                     // first.
                     Some(ret) => {
+                        self.set_draining();
+                        crate::drain::start_drain(self.stream_drain_timeout);
                         self.handle.graceful_shutdown(graceful);
+                        crate::builder::unix::graceful_shutdown_all(graceful);
                         while let Some(other_ret) = tasks.next().await {
                             let _ = other_ret?;
                         }
@@ -234,10 +308,19 @@ impl Handle {
                     None => Ok(()),
                 }
             }
-        }
+        };
+        crate::queue::drain(graceful).await;
+        crate::hooks::drain(graceful).await;
+        save_state().await;
+        ret
     }
 }
 
+/// Save resilience state after server tasks exit, without blocking async runtime.
+async fn save_state() {
+    let _ = tokio::task::spawn_blocking(crate::persist::save).await;
+}
+
 impl AppConfig {
     /// Initialize logging and tracing subsystems.
     ///
@@ -258,9 +341,7 @@ impl AppConfig {
                 .build();
             let layer = tcfg.build_layer(&tracer);
             registry.with(layer).init();
-            opentelemetry::global::set_text_map_propagator(
-                opentelemetry_sdk::propagation::TraceContextPropagator::default(),
-            );
+            tcfg.propagation().install(tcfg.baggage());
             (Some(tracer), Some(tracer_provider))
         } else {
             registry.init();
@@ -278,6 +359,9 @@ impl AppConfig {
             signal_handler: None,
             http_task: None,
             https_task: None,
+            stream_drain_timeout: ServerBuilder::default().stream_drain_timeout,
+            grace_period: ServerBuilder::default().shutdown.grace_period,
+            drain_window: ServerBuilder::default().shutdown.drain_window,
         })
     }
 }
@@ -0,0 +1,684 @@
+//! Post-response hooks.
+//!
+//! Handlers can schedule non-critical follow-up work, which runs after the response body is
+//! fully sent. Hooks are executed on a shared runner, which bounds their concurrency and
+//! duration, keeps request context of the originating request, and is drained on graceful
+//! shutdown.
+//!
+//! Handlers get a [`ResponseHooks`] instance as a request extension:
+//!
+//! ```ignore
+//! async fn create(Extension(hooks): Extension<ResponseHooks>) -> &'static str {
+//!     hooks.schedule(|ctx| async move {
+//!         send_notification(ctx.user_id()).await
+//!     });
+//!     "created"
+//! }
+//! ```
+
+use std::{
+    fmt,
+    future::Future,
+    num::NonZeroUsize,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    http::{HeaderName, Request, Response},
+};
+use futures::{future::BoxFuture, FutureExt};
+use http_body::{Frame, SizeHint};
+use once_cell::sync::Lazy;
+use opentelemetry::KeyValue;
+use parking_lot::{Mutex, RwLock};
+use pin_project::{pin_project, pinned_drop};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, Semaphore};
+use tower::{BoxError, Layer, Service};
+use tower_http::request_id::RequestId;
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+use crate::{
+    auth::{UserId, CURRENT_USER_ID},
+    layers::request_id::CURRENT_REQUEST_ID,
+    metrics::ResponseHookMetrics,
+};
+
+/// Hook runner used by handlers built by the last [`crate::AppBuilder`].
+static RUNNER: Lazy<RwLock<Option<HookRunner>>> = Lazy::new(Default::default);
+
+/// Get current hook runner, registering one with default configuration if none exists.
+pub(crate) fn runner() -> HookRunner {
+    if let Some(runner) = RUNNER.read().clone() {
+        return runner;
+    }
+    RUNNER
+        .write()
+        .get_or_insert_with(|| ResponseHooksConfig::default().build(None))
+        .clone()
+}
+
+/// Wait for scheduled and running hooks to finish.
+///
+/// Does nothing if no handlers were built.
+pub(crate) async fn drain(timeout: Option<Duration>) {
+    let runner = RUNNER.read().clone();
+    if let Some(runner) = runner {
+        runner.drain(timeout).await;
+    }
+}
+
+/// Post-response hooks configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct ResponseHooksConfig {
+    /// Maximum execution time of a single hook.
+    ///
+    /// Time spent waiting for a free concurrency slot is not included. Default is 30 seconds.
+    #[serde(
+        default = "ResponseHooksConfig::default_timeout",
+        with = "humantime_serde"
+    )]
+    pub timeout: Duration,
+    /// Maximum number of concurrently executing hooks, across all handlers.
+    #[serde(default = "ResponseHooksConfig::default_concurrency")]
+    pub concurrency: NonZeroUsize,
+    /// Request header containing tenant identifier, recorded in [`HookContext`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_header: Option<String>,
+}
+
+impl Default for ResponseHooksConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Self::default_timeout(),
+            concurrency: Self::default_concurrency(),
+            tenant_header: None,
+        }
+    }
+}
+
+impl ResponseHooksConfig {
+    /// Default value for [`Self::timeout`].
+    #[must_use]
+    #[inline]
+    fn default_timeout() -> Duration {
+        Duration::from_secs(30)
+    }
+
+    /// Default value for [`Self::concurrency`].
+    #[must_use]
+    #[inline]
+    fn default_concurrency() -> NonZeroUsize {
+        // SAFETY: value is non-zero.
+        NonZeroUsize::new(64).unwrap()
+    }
+
+    /// Set maximum execution time of a single hook.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set maximum number of concurrently executing hooks.
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: NonZeroUsize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Set request header containing tenant identifier.
+    #[must_use]
+    pub fn with_tenant_header(mut self, name: impl ToString) -> Self {
+        self.tenant_header = Some(name.to_string());
+        self
+    }
+
+    /// Create hook runner.
+    #[must_use]
+    pub(crate) fn build(&self, metrics: Option<ResponseHookMetrics>) -> HookRunner {
+        HookRunner(Arc::new(HookRunnerInner {
+            timeout: self.timeout,
+            slots: Arc::new(Semaphore::new(self.concurrency.get())),
+            pending: AtomicUsize::new(0),
+            idle: Notify::new(),
+            metrics,
+        }))
+    }
+
+    /// Create layer for use in [`tower`] services.
+    #[must_use]
+    pub(crate) fn make_layer(
+        &self,
+        handler: &'static str,
+        runner: HookRunner,
+    ) -> ResponseHooksLayer {
+        ResponseHooksLayer {
+            handler,
+            tenant_header: self
+                .tenant_header
+                .as_deref()
+                .and_then(|name| HeaderName::try_from(name).ok()),
+            runner,
+        }
+    }
+}
+
+/// Snapshot of request context, passed to hooks.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct HookContext {
+    /// Originating handler name.
+    handler: &'static str,
+    /// Request ID, if any.
+    request_id: Option<RequestId>,
+    /// Authenticated user, if any.
+    user_id: Option<UserId>,
+    /// Tenant identifier, if any.
+    tenant: Option<String>,
+}
+
+impl HookContext {
+    /// Name of the handler which scheduled the hook.
+    #[must_use]
+    pub fn handler(&self) -> &'static str {
+        self.handler
+    }
+
+    /// Request ID of the originating request.
+    #[must_use]
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id
+            .as_ref()
+            .and_then(|id| id.header_value().to_str().ok())
+    }
+
+    /// Authenticated user of the originating request.
+    #[must_use]
+    pub fn user_id(&self) -> Option<&UserId> {
+        self.user_id.as_ref()
+    }
+
+    /// Tenant of the originating request.
+    ///
+    /// See [`ResponseHooksConfig::tenant_header`].
+    #[must_use]
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
+}
+
+/// Boxed hook function.
+type Hook = Box<dyn FnOnce(HookContext) -> BoxFuture<'static, Result<(), BoxError>> + Send>;
+
+/// Hooks scheduled by a handler, to be run after the response is sent.
+///
+/// Available to handlers as a request extension. Hooks scheduled after the response is sent are
+/// discarded.
+#[derive(Clone)]
+pub struct ResponseHooks(Arc<Mutex<Option<Vec<Hook>>>>);
+
+impl fmt::Debug for ResponseHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ResponseHooks")
+            .field(&self.0.lock().as_ref().map(Vec::len))
+            .finish()
+    }
+}
+
+impl ResponseHooks {
+    /// Create new open hook list.
+    #[must_use]
+    fn new() -> Self {
+        Self(Arc::new(Mutex::new(Some(Vec::new()))))
+    }
+
+    /// Schedule a hook to run after the response is sent.
+    ///
+    /// Errors and timeouts are logged and counted in metrics, but never affect the response.
+    pub fn schedule<F, Fut, E>(&self, hook: F)
+    where
+        F: FnOnce(HookContext) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<BoxError>,
+    {
+        match self.0.lock().as_mut() {
+            Some(hooks) => hooks.push(Box::new(move |ctx| {
+                hook(ctx).map(|res| res.map_err(Into::into)).boxed()
+            })),
+            None => warn!("response already sent, hook discarded"),
+        }
+    }
+
+    /// Stop accepting new hooks, and return ones already scheduled.
+    fn take(&self) -> Vec<Hook> {
+        self.0.lock().take().unwrap_or_default()
+    }
+}
+
+/// Shared executor for post-response hooks.
+#[derive(Clone)]
+pub(crate) struct HookRunner(Arc<HookRunnerInner>);
+
+/// Inner container for [`HookRunner`].
+struct HookRunnerInner {
+    /// Maximum execution time of a single hook.
+    timeout: Duration,
+    /// Permits for concurrently executing hooks.
+    slots: Arc<Semaphore>,
+    /// Number of scheduled hooks which have not finished yet.
+    pending: AtomicUsize,
+    /// Notified when there are no pending hooks.
+    idle: Notify,
+    /// Hook metrics.
+    metrics: Option<ResponseHookMetrics>,
+}
+
+/// Decrements pending hook counter when dropped, even if hook panics.
+struct PendingGuard(Arc<HookRunnerInner>);
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        if self.0.pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+impl HookRunner {
+    /// Register this runner for use in newly built handlers and during shutdown.
+    pub(crate) fn register(&self) {
+        *RUNNER.write() = Some(self.clone());
+    }
+
+    /// Spawn tasks executing provided hooks.
+    fn spawn(&self, ctx: &HookContext, hooks: Vec<Hook>) {
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            warn!(
+                handler = ctx.handler,
+                request_id = ctx.request_id(),
+                "no async runtime available, response hooks discarded"
+            );
+            return;
+        };
+        let labels = [KeyValue::new("uxum.handler", ctx.handler)];
+        for hook in hooks {
+            if let Some(metrics) = &self.0.metrics {
+                metrics.scheduled.add(1, &labels);
+            }
+            self.0.pending.fetch_add(1, Ordering::AcqRel);
+            let guard = PendingGuard(self.0.clone());
+            let span = info_span!(
+                "response_hook",
+                handler = ctx.handler,
+                request_id = ctx.request_id()
+            );
+            handle.spawn(run_hook(guard, ctx.clone(), hook).instrument(span));
+        }
+    }
+
+    /// Wait for scheduled and running hooks to finish.
+    pub(crate) async fn drain(&self, timeout: Option<Duration>) {
+        let wait = async {
+            loop {
+                let idle = self.0.idle.notified();
+                if self.0.pending.load(Ordering::Acquire) == 0 {
+                    break;
+                }
+                idle.await;
+            }
+        };
+        let drained = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, wait).await.is_ok(),
+            None => {
+                wait.await;
+                true
+            }
+        };
+        match drained {
+            true => info!("response hooks drained"),
+            false => warn!(
+                pending = self.0.pending.load(Ordering::Acquire),
+                "response hooks drain timed out"
+            ),
+        }
+    }
+}
+
+/// Execute a single hook within snapshot of request context.
+async fn run_hook(guard: PendingGuard, ctx: HookContext, hook: Hook) {
+    let inner = guard.0.clone();
+    let _permit = inner.slots.clone().acquire_owned().await;
+    let (handler, request_id) = (ctx.handler, ctx.request_id.clone());
+    let future = CURRENT_REQUEST_ID.scope(
+        ctx.request_id.clone(),
+        CURRENT_USER_ID.scope(ctx.user_id.clone(), hook(ctx)),
+    );
+    let outcome =
+        tokio::time::timeout(inner.timeout, AssertUnwindSafe(future).catch_unwind()).await;
+    let request_id = request_id
+        .as_ref()
+        .and_then(|id| id.header_value().to_str().ok());
+    let counter = inner.metrics.as_ref().map(|metrics| match &outcome {
+        Ok(Ok(Ok(()))) => &metrics.completed,
+        Ok(_) => &metrics.failed,
+        Err(_) => &metrics.timed_out,
+    });
+    match outcome {
+        Ok(Ok(Ok(()))) => debug!("response hook completed"),
+        Ok(Ok(Err(err))) => error!(handler, request_id, %err, "response hook failed"),
+        Ok(Err(_)) => error!(handler, request_id, "response hook panicked"),
+        Err(_) => warn!(handler, request_id, timeout = ?inner.timeout, "response hook timed out"),
+    }
+    if let Some(counter) = counter {
+        counter.add(1, &[KeyValue::new("uxum.handler", handler)]);
+    }
+    drop(guard);
+}
+
+/// Post-response hooks [`tower`] layer.
+#[derive(Clone)]
+pub(crate) struct ResponseHooksLayer {
+    /// Handler name.
+    handler: &'static str,
+    /// Request header containing tenant identifier.
+    tenant_header: Option<HeaderName>,
+    /// Hook runner.
+    runner: HookRunner,
+}
+
+impl<S> Layer<S> for ResponseHooksLayer {
+    type Service = ResponseHooksService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ResponseHooksService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Post-response hooks [`tower`] service.
+#[derive(Clone)]
+pub(crate) struct ResponseHooksService<S> {
+    /// Inner service.
+    inner: S,
+    /// Layer configuration.
+    layer: ResponseHooksLayer,
+}
+
+impl<S, T> Service<Request<T>> for ResponseHooksService<S>
+where
+    S: Service<Request<T>, Response = Response<Body>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: Request<T>) -> Self::Future {
+        let hooks = ResponseHooks::new();
+        let mut ctx = HookContext {
+            handler: self.layer.handler,
+            request_id: req.extensions().get::<RequestId>().cloned(),
+            user_id: None,
+            tenant: self
+                .layer
+                .tenant_header
+                .as_ref()
+                .and_then(|name| req.headers().get(name))
+                .and_then(|val| val.to_str().ok())
+                .map(ToString::to_string),
+        };
+        req.extensions_mut().insert(hooks.clone());
+        let future = self.inner.call(req);
+        let runner = self.layer.runner.clone();
+        Box::pin(async move {
+            let resp = match future.await {
+                Ok(resp) => resp,
+                Err(err) => {
+                    let discarded = hooks.take().len();
+                    if discarded > 0 {
+                        debug!(discarded, "request failed, response hooks discarded");
+                    }
+                    return Err(err.into());
+                }
+            };
+            // Nothing was scheduled, and handler kept no reference to schedule anything later.
+            if Arc::strong_count(&hooks.0) == 1
+                && hooks.0.lock().as_ref().is_some_and(Vec::is_empty)
+            {
+                return Ok(resp);
+            }
+            ctx.user_id = resp.extensions().get::<UserId>().cloned();
+            let (parts, body) = resp.into_parts();
+            let body = HooksBody {
+                inner: body,
+                pending: Some(PendingHooks { hooks, ctx, runner }),
+            };
+            Ok(Response::from_parts(parts, Body::new(body)))
+        })
+    }
+}
+
+/// Hooks waiting for response body to finish.
+struct PendingHooks {
+    /// Scheduled hooks.
+    hooks: ResponseHooks,
+    /// Request context snapshot.
+    ctx: HookContext,
+    /// Hook runner.
+    runner: HookRunner,
+}
+
+impl PendingHooks {
+    /// Hand scheduled hooks over to runner.
+    fn run(self) {
+        let hooks = self.hooks.take();
+        if !hooks.is_empty() {
+            self.runner.spawn(&self.ctx, hooks);
+        }
+    }
+}
+
+/// Response body wrapper, which runs hooks when body is finished or dropped.
+#[pin_project(PinnedDrop)]
+struct HooksBody<B> {
+    /// Inner body.
+    #[pin]
+    inner: B,
+    /// Pending hooks, taken when body is finished or dropped.
+    pending: Option<PendingHooks>,
+}
+
+impl<B> HttpBody for HooksBody<B>
+where
+    B: HttpBody<Data = Bytes>,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        let frame = ready!(this.inner.as_mut().poll_frame(cx));
+        if frame.is_none() || this.inner.is_end_stream() {
+            if let Some(pending) = this.pending.take() {
+                pending.run();
+            }
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<B> PinnedDrop for HooksBody<B> {
+    fn drop(self: Pin<&mut Self>) {
+        if let Some(pending) = self.project().pending.take() {
+            pending.run();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use axum::http::HeaderValue;
+    use http_body_util::BodyExt;
+    use tokio::sync::mpsc;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// Layer around handler which schedules provided hook.
+    fn service<F, Fut>(
+        runner: HookRunner,
+        hook: F,
+    ) -> impl Service<Request<Body>, Response = Response<Body>, Error = BoxError>
+    where
+        F: FnOnce(HookContext) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = Result<(), BoxError>> + Send + 'static,
+    {
+        ResponseHooksConfig::default()
+            .with_tenant_header("x-tenant")
+            .make_layer("hooked", runner)
+            .layer(tower::service_fn(move |req: Request<Body>| {
+                let hook = hook.clone();
+                async move {
+                    req.extensions()
+                        .get::<ResponseHooks>()
+                        .unwrap()
+                        .schedule(hook);
+                    let mut resp = Response::new(Body::from("done"));
+                    resp.extensions_mut().insert(UserId::from("alice"));
+                    Ok::<_, BoxError>(resp)
+                }
+            }))
+    }
+
+    #[tokio::test]
+    async fn hooks_run_after_body() {
+        let runner = ResponseHooksConfig::default().build(None);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let svc = service(runner, move |_ctx| async move {
+            tx.send(()).unwrap();
+            Ok(())
+        });
+        let resp = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        tokio::task::yield_now().await;
+        assert!(rx.try_recv().is_err());
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "done");
+        tokio::time::timeout(Duration::from_secs(1), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn hooks_get_request_context() {
+        let runner = ResponseHooksConfig::default().build(None);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let svc = service(runner, move |ctx| async move {
+            let current_user = CURRENT_USER_ID.with(Clone::clone);
+            let current_id = CURRENT_REQUEST_ID.with(|id| {
+                id.as_ref()
+                    .and_then(|id| id.header_value().to_str().ok())
+                    .map(ToString::to_string)
+            });
+            tx.send((ctx, current_user, current_id)).unwrap();
+            Ok(())
+        });
+        let mut req = Request::new(Body::empty());
+        req.extensions_mut()
+            .insert(RequestId::new(HeaderValue::from_static("req-1")));
+        req.headers_mut()
+            .insert("x-tenant", HeaderValue::from_static("acme"));
+        let resp = svc.oneshot(req).await.unwrap();
+        drop(resp.into_body().collect().await.unwrap());
+        let (ctx, current_user, current_id) = rx.recv().await.unwrap();
+        assert_eq!(ctx.handler(), "hooked");
+        assert_eq!(ctx.request_id(), Some("req-1"));
+        assert_eq!(ctx.user_id().map(|id| id.as_str()), Some("alice"));
+        assert_eq!(ctx.tenant(), Some("acme"));
+        assert_eq!(current_user.as_deref().map(String::as_str), Some("alice"));
+        assert_eq!(current_id.as_deref(), Some("req-1"));
+    }
+
+    #[tokio::test]
+    async fn hooks_do_not_affect_response() {
+        let runner = ResponseHooksConfig::default()
+            .with_timeout(Duration::from_millis(10))
+            .build(None);
+        let svc = service(runner.clone(), |_ctx| async move {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            Err::<(), BoxError>("unreachable".into())
+        });
+        let resp = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "done");
+        tokio::time::timeout(Duration::from_secs(1), runner.drain(None))
+            .await
+            .unwrap();
+        assert_eq!(runner.0.pending.load(Ordering::Acquire), 0);
+    }
+
+    #[tokio::test]
+    async fn drain_waits_for_hooks() {
+        let runner = ResponseHooksConfig::default().build(None);
+        let finished = Arc::new(AtomicBool::new(false));
+        let flag = finished.clone();
+        let svc = service(runner.clone(), move |_ctx| async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            flag.store(true, Ordering::Release);
+            Ok(())
+        });
+        let resp = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        drop(resp);
+        assert!(!finished.load(Ordering::Acquire));
+        runner.drain(Some(Duration::from_secs(5))).await;
+        assert!(finished.load(Ordering::Acquire));
+    }
+
+    #[tokio::test]
+    async fn drain_times_out() {
+        let runner = ResponseHooksConfig::default().build(None);
+        let svc = service(runner.clone(), |_ctx| async move {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            Ok(())
+        });
+        drop(svc.oneshot(Request::new(Body::empty())).await.unwrap());
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            runner.drain(Some(Duration::from_millis(20))),
+        )
+        .await
+        .unwrap();
+        assert_eq!(runner.0.pending.load(Ordering::Acquire), 1);
+    }
+}
@@ -1,10 +1,13 @@
 //! HTTP client - circuit breaker.
 
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
+use parking_lot::Mutex;
 use recloser::{AsyncRecloser, Recloser};
 use serde::{Deserialize, Serialize};
 
+use crate::persist::{BreakerSnapshot, BreakerStatus, PersistentBreaker};
+
 /// HTTP client circuit breaker configuration.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[non_exhaustive]
@@ -102,3 +105,89 @@ impl HttpClientCircuitBreakerConfig {
         )
     }
 }
+
+/// Observed state of a circuit breaker, used for state persistence.
+///
+/// [`recloser`] does not expose its internal state, so breaker is assumed to be open after the
+/// first rejected request, and closed after the first request passing through.
+#[derive(Debug)]
+pub(crate) struct BreakerTracker {
+    /// Time that CB stays open after being tripped.
+    open_wait: Duration,
+    /// Observed state.
+    state: Mutex<TrackerState>,
+}
+
+/// Inner state of [`BreakerTracker`].
+#[derive(Debug)]
+struct TrackerState {
+    /// Observed state.
+    status: BreakerStatus,
+    /// Time of last observed state transition.
+    since: SystemTime,
+    /// Requests are rejected until this moment, if breaker was restored in open state.
+    reject_until: Option<Instant>,
+}
+
+impl BreakerTracker {
+    /// Create new tracker, assuming breaker is closed.
+    #[must_use]
+    pub(crate) fn new(config: &HttpClientCircuitBreakerConfig) -> Self {
+        Self {
+            open_wait: config.open_wait,
+            state: Mutex::new(TrackerState {
+                status: BreakerStatus::Closed,
+                since: SystemTime::now(),
+                reject_until: None,
+            }),
+        }
+    }
+
+    /// Check whether breaker is held open after being restored in open state.
+    #[must_use]
+    pub(crate) fn is_held_open(&self) -> bool {
+        let mut state = self.state.lock();
+        match state.reject_until {
+            Some(until) if until > Instant::now() => true,
+            Some(_) => {
+                state.reject_until = None;
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Record breaker state, as observed by request outcome.
+    pub(crate) fn observe(&self, status: BreakerStatus) {
+        let mut state = self.state.lock();
+        if state.status != status {
+            state.status = status;
+            state.since = SystemTime::now();
+        }
+    }
+}
+
+impl PersistentBreaker for BreakerTracker {
+    fn snapshot(&self) -> BreakerSnapshot {
+        let state = self.state.lock();
+        BreakerSnapshot {
+            status: state.status,
+            since: state.since,
+        }
+    }
+
+    fn restore(&self, snapshot: &BreakerSnapshot) {
+        if snapshot.status != BreakerStatus::Open {
+            return;
+        }
+        let elapsed = SystemTime::now()
+            .duration_since(snapshot.since)
+            .unwrap_or_default();
+        if let Some(remaining) = self.open_wait.checked_sub(elapsed) {
+            let mut state = self.state.lock();
+            state.status = BreakerStatus::Open;
+            state.since = snapshot.since;
+            state.reject_until = Some(Instant::now() + remaining);
+        }
+    }
+}
@@ -1,6 +1,8 @@
 //! HTTP client - configuration.
 
-use std::{collections::BTreeMap, num::NonZeroU32, path::Path, str::FromStr, time::Duration};
+use std::{
+    collections::BTreeMap, num::NonZeroU32, path::Path, str::FromStr, sync::Arc, time::Duration,
+};
 
 use reqwest::{
     header::{HeaderName, HeaderValue},
@@ -11,8 +13,12 @@ use serde::{Deserialize, Serialize};
 use tokio::{fs::OpenOptions, io::AsyncReadExt};
 
 use crate::{
+    auth::TokenIssuer,
     http_client::{
-        cb::HttpClientCircuitBreakerConfig, errors::HttpClientError, middleware::wrap_client,
+        cb::HttpClientCircuitBreakerConfig,
+        egress::{EgressPolicyConfig, EgressResolver},
+        errors::HttpClientError,
+        middleware::wrap_client,
     },
     metrics::ClientMetricsState,
 };
@@ -108,12 +114,26 @@ pub struct HttpClientConfig {
     /// Circuit breaker configuration.
     #[serde(default, alias = "breaker", alias = "circuit_breaker")]
     pub cb: Option<HttpClientCircuitBreakerConfig>,
+    /// Outgoing request policy.
+    ///
+    /// Defaults to [`crate::AppConfig::egress`] if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub egress: Option<EgressPolicyConfig>,
+    /// Name of HTTP header used to pass deadline of current server request downstream.
+    ///
+    /// Deadline is sent in milliseconds since UNIX epoch. Request timeout is bounded by the
+    /// remaining time regardless of this setting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline_header: Option<String>,
     /// Short application name.
     #[serde(skip)]
     app_name: Option<String>,
     /// Application version.
     #[serde(skip)]
     app_version: Option<String>,
+    /// Issuer of service-to-service tokens.
+    #[serde(skip)]
+    token_issuer: Option<Arc<TokenIssuer>>,
 }
 
 impl Default for HttpClientConfig {
@@ -132,8 +152,11 @@ impl Default for HttpClientConfig {
             tcp: HttpClientTcpConfig::default(),
             http2: HttpClientHttp2Config::default(),
             cb: None,
+            egress: None,
+            deadline_header: None,
             app_name: None,
             app_version: None,
+            token_issuer: None,
         }
     }
 }
@@ -190,6 +213,22 @@ impl HttpClientConfig {
         self
     }
 
+    /// Set outgoing request policy, unless already configured for this client.
+    pub fn with_default_egress(&mut self, egress: &EgressPolicyConfig) -> &mut Self {
+        if self.egress.is_none() {
+            self.egress = Some(egress.clone());
+        }
+        self
+    }
+
+    /// Set issuer of service-to-service tokens.
+    ///
+    /// If set, a short-lived token is attached to every outgoing request.
+    pub fn with_token_issuer(&mut self, token_issuer: Arc<TokenIssuer>) -> &mut Self {
+        self.token_issuer = Some(token_issuer);
+        self
+    }
+
     /// Create [`reqwest::ClientBuilder`] from configuration.
     ///
     /// # Errors
@@ -260,13 +299,35 @@ impl HttpClientConfig {
     /// * DNS resolver fails to load its configuration.
     pub fn build_client(
         &self,
-        builder: ClientBuilder,
+        mut builder: ClientBuilder,
         metrics: Option<ClientMetricsState>,
     ) -> Result<ClientWithMiddleware, HttpClientError> {
+        let egress = self.egress.as_ref().map(|egress| {
+            Arc::new(
+                egress.build(
+                    metrics.as_ref().map_or("", ClientMetricsState::name),
+                    metrics
+                        .as_ref()
+                        .map(|metrics| metrics.metrics().egress_violations.clone()),
+                ),
+            )
+        });
+        if let Some(policy) = &egress {
+            builder = builder.redirect(policy.redirect_policy(self.redirect));
+            if policy.checks_addresses() {
+                builder = builder.dns_resolver(Arc::new(EgressResolver(Arc::clone(policy))));
+            }
+        }
         Ok(wrap_client(
             builder.build()?,
             metrics,
-            self.cb.as_ref().map(|cb| cb.make_circuit_breaker()),
+            self.cb.as_ref(),
+            self.token_issuer.clone(),
+            egress,
+            self.request_timeout,
+            self.deadline_header
+                .as_deref()
+                .and_then(|name| HeaderName::from_str(name).ok()),
         ))
     }
 
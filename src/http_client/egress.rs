@@ -0,0 +1,602 @@
+//! HTTP client - egress policy.
+//!
+//! Outgoing requests are checked against an allowlist of URL patterns, and resolved addresses of
+//! destination hosts are checked against denied IP ranges. Checking resolved addresses, rather
+//! than hostnames, catches DNS rebinding. Redirects are checked on every hop.
+//!
+//! Violations fail requests locally, before anything is sent.
+
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+};
+
+use http::Extensions;
+use once_cell::sync::Lazy;
+use opentelemetry::{metrics::Counter, KeyValue};
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect::{Attempt, Policy},
+    Request, Response, Url,
+};
+use reqwest_middleware::{Middleware, Next};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::{
+    http_client::{config::HttpClientRedirectPolicy, errors::HttpClientError},
+    layers::ip_filter::IpNetwork,
+};
+
+/// Rule name used when destination does not match any allowed URL pattern.
+const ALLOWLIST_RULE: &str = "allowlist";
+
+/// Rule name used for built-in private and link-local ranges.
+const PRIVATE_RULE: &str = "private";
+
+/// Loopback, private, shared, link-local and unspecified address ranges.
+static PRIVATE_RANGES: Lazy<Vec<IpNetwork>> = Lazy::new(|| {
+    [
+        "0.0.0.0/8",
+        "10.0.0.0/8",
+        "100.64.0.0/10",
+        "127.0.0.0/8",
+        "169.254.0.0/16",
+        "172.16.0.0/12",
+        "192.168.0.0/16",
+        "::/128",
+        "::1/128",
+        "fc00::/7",
+        "fe80::/10",
+    ]
+    .into_iter()
+    .filter_map(|net| net.parse().ok())
+    .collect()
+});
+
+/// Error returned when parsing URL pattern.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+#[error("Invalid egress URL pattern: {0}")]
+pub struct EgressRuleError(String);
+
+/// Allowed URL pattern.
+///
+/// Written as `[scheme://]host[:port][/path]`. Host may contain `*` wildcards, scheme and port
+/// may be `*` or omitted to allow any. Path is matched as a prefix, on segment boundary.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EgressRule {
+    /// URL scheme, any if not set.
+    scheme: Option<String>,
+    /// Host glob pattern.
+    host: String,
+    /// Port, any if not set.
+    port: Option<u16>,
+    /// Path prefix.
+    path: String,
+}
+
+impl EgressRule {
+    /// Check if URL matches this pattern.
+    #[must_use]
+    pub fn matches(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        self.scheme
+            .as_deref()
+            .map_or(true, |scheme| scheme == url.scheme())
+            && glob_match(&self.host, &host.to_ascii_lowercase())
+            && self
+                .port
+                .map_or(true, |port| Some(port) == url.port_or_known_default())
+            && path_matches(&self.path, url.path())
+    }
+}
+
+impl FromStr for EgressRule {
+    type Err = EgressRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || EgressRuleError(s.to_string());
+        let (scheme, rest) = match s.split_once("://") {
+            Some(("*", rest)) => (None, rest),
+            Some((scheme, rest)) => (Some(scheme.to_ascii_lowercase()), rest),
+            None => (None, s),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(pos) => rest.split_at(pos),
+            None => (rest, ""),
+        };
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(v6) => {
+                let (host, port) = v6.split_once(']').ok_or_else(err)?;
+                (host, port.strip_prefix(':'))
+            }
+            None => match authority.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        if host.is_empty() {
+            return Err(err());
+        }
+        let port = match port {
+            None | Some("*") => None,
+            Some(port) => Some(port.parse().map_err(|_| err())?),
+        };
+        Ok(Self {
+            scheme,
+            host: host.to_ascii_lowercase(),
+            port,
+            path: path.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for EgressRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(scheme) = &self.scheme {
+            write!(f, "{scheme}://")?;
+        }
+        match self.host.contains(':') {
+            true => write!(f, "[{}]", self.host)?,
+            false => f.write_str(&self.host)?,
+        }
+        if let Some(port) = self.port {
+            write!(f, ":{port}")?;
+        }
+        f.write_str(&self.path)
+    }
+}
+
+impl Serialize for EgressRule {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for EgressRule {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Match text against glob pattern, where `*` matches any sequence of characters.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.as_bytes(), text.as_bytes());
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack = None;
+    while ti < text.len() {
+        match pattern.get(pi) {
+            Some(b'*') => {
+                backtrack = Some((pi, ti));
+                pi += 1;
+            }
+            Some(ch) if *ch == text[ti] => {
+                pi += 1;
+                ti += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    pi = star + 1;
+                    ti = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[pi..].iter().all(|ch| *ch == b'*')
+}
+
+/// Check if URL path starts with prefix, on segment boundary.
+fn path_matches(prefix: &str, path: &str) -> bool {
+    match path.strip_prefix(prefix) {
+        Some(rest) => {
+            prefix.is_empty() || prefix.ends_with('/') || rest.is_empty() || rest.starts_with('/')
+        }
+        None => false,
+    }
+}
+
+/// Outgoing request policy configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct EgressPolicyConfig {
+    /// Allowed URL patterns.
+    ///
+    /// All destinations are allowed if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<EgressRule>,
+    /// Deny loopback, private, shared and link-local addresses.
+    ///
+    /// Default is `true`.
+    #[serde(default = "crate::util::default_true")]
+    pub deny_private: bool,
+    /// Additional denied IP ranges.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<IpNetwork>,
+    /// Only log violations, without blocking requests.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+impl Default for EgressPolicyConfig {
+    fn default() -> Self {
+        Self {
+            allow: Vec::new(),
+            deny_private: true,
+            deny: Vec::new(),
+            dry_run: false,
+        }
+    }
+}
+
+impl EgressPolicyConfig {
+    /// Add allowed URL pattern.
+    #[must_use]
+    pub fn with_allow(mut self, rule: EgressRule) -> Self {
+        self.allow.push(rule);
+        self
+    }
+
+    /// Enable or disable denying private and link-local addresses.
+    #[must_use]
+    pub fn with_deny_private(mut self, deny_private: bool) -> Self {
+        self.deny_private = deny_private;
+        self
+    }
+
+    /// Add denied IP range.
+    #[must_use]
+    pub fn with_deny(mut self, net: IpNetwork) -> Self {
+        self.deny.push(net);
+        self
+    }
+
+    /// Enable or disable dry-run mode.
+    #[must_use]
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Build policy for named client.
+    #[must_use]
+    pub(crate) fn build(&self, client: &str, violations: Option<Counter<u64>>) -> EgressPolicy {
+        let mut deny: Vec<_> = self
+            .deny
+            .iter()
+            .map(|net| (*net, net.to_string()))
+            .collect();
+        if self.deny_private {
+            deny.extend(PRIVATE_RANGES.iter().map(|net| (*net, PRIVATE_RULE.into())));
+        }
+        EgressPolicy {
+            client: client.to_string(),
+            allow: self.allow.clone(),
+            deny,
+            dry_run: self.dry_run,
+            violations,
+        }
+    }
+}
+
+/// Egress policy violation.
+#[derive(Debug)]
+struct EgressViolation {
+    /// Attempted destination.
+    destination: String,
+    /// Name of violated rule.
+    rule: String,
+}
+
+/// Egress policy of a single named client.
+#[derive(Debug)]
+pub(crate) struct EgressPolicy {
+    /// Client name.
+    client: String,
+    /// Allowed URL patterns.
+    allow: Vec<EgressRule>,
+    /// Denied IP ranges, with rule names.
+    deny: Vec<(IpNetwork, String)>,
+    /// Only log violations.
+    dry_run: bool,
+    /// Violation counter.
+    violations: Option<Counter<u64>>,
+}
+
+impl EgressPolicy {
+    /// Check destination URL against allowed patterns, and against denied ranges if host is an IP
+    /// address.
+    fn check_url(&self, url: &Url) -> Result<(), EgressViolation> {
+        let destination = || url[..url::Position::AfterPath].to_string();
+        if !self.allow.is_empty() && !self.allow.iter().any(|rule| rule.matches(url)) {
+            return Err(EgressViolation {
+                destination: destination(),
+                rule: ALLOWLIST_RULE.into(),
+            });
+        }
+        let ip = match url.host() {
+            Some(url::Host::Ipv4(ip)) => IpAddr::V4(ip),
+            Some(url::Host::Ipv6(ip)) => IpAddr::V6(ip),
+            _ => return Ok(()),
+        };
+        self.check_ip(ip).map_err(|rule| EgressViolation {
+            destination: destination(),
+            rule,
+        })
+    }
+
+    /// Check destination IP address against denied ranges, returning name of violated rule.
+    fn check_ip(&self, ip: IpAddr) -> Result<(), String> {
+        match self.deny.iter().find(|(net, _)| net.contains(ip)) {
+            Some((_, rule)) => Err(rule.clone()),
+            None => Ok(()),
+        }
+    }
+
+    /// Record violation, and decide whether to block the request.
+    fn enforce(&self, res: Result<(), EgressViolation>) -> Result<(), HttpClientError> {
+        let Err(violation) = res else {
+            return Ok(());
+        };
+        if let Some(counter) = &self.violations {
+            counter.add(
+                1,
+                &[
+                    KeyValue::new("http.client", self.client.clone()),
+                    KeyValue::new("uxum.egress.rule", violation.rule.clone()),
+                ],
+            );
+        }
+        warn!(
+            client = self.client,
+            destination = violation.destination,
+            rule = violation.rule,
+            dry_run = self.dry_run,
+            "egress policy violation"
+        );
+        match self.dry_run {
+            true => Ok(()),
+            false => Err(HttpClientError::EgressDenied {
+                client: self.client.clone(),
+                destination: violation.destination,
+                rule: violation.rule,
+            }),
+        }
+    }
+
+    /// Check whether resolving host names is needed to enforce this policy.
+    #[must_use]
+    pub(crate) fn checks_addresses(&self) -> bool {
+        !self.deny.is_empty()
+    }
+
+    /// Build redirect policy re-checking every hop.
+    #[must_use]
+    pub(crate) fn redirect_policy(self: &Arc<Self>, redirect: HttpClientRedirectPolicy) -> Policy {
+        let HttpClientRedirectPolicy::Limited { redirect_limit } = redirect else {
+            return redirect.into();
+        };
+        let policy = Arc::clone(self);
+        Policy::custom(move |attempt: Attempt<'_>| {
+            if attempt.previous().len() > redirect_limit {
+                return attempt.error(TooManyRedirects);
+            }
+            match policy.enforce(policy.check_url(attempt.url())) {
+                Ok(()) => attempt.follow(),
+                Err(err) => attempt.error(err),
+            }
+        })
+    }
+}
+
+/// Redirect limit exceeded.
+#[derive(Debug, Error)]
+#[error("Too many redirects")]
+struct TooManyRedirects;
+
+/// DNS resolver checking resolved addresses against egress policy.
+pub(crate) struct EgressResolver(pub(crate) Arc<EgressPolicy>);
+
+impl Resolve for EgressResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = Arc::clone(&self.0);
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0)).await?.collect();
+            for addr in &addrs {
+                let res = policy.check_ip(addr.ip()).map_err(|rule| EgressViolation {
+                    destination: format!("{host} ({})", addr.ip()),
+                    rule,
+                });
+                policy.enforce(res)?;
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Middleware checking destination of outgoing requests before sending.
+pub(crate) struct EgressMiddleware(pub(crate) Arc<EgressPolicy>);
+
+#[async_trait::async_trait]
+impl Middleware for EgressMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        self.0
+            .enforce(self.0.check_url(req.url()))
+            .map_err(reqwest_middleware::Error::middleware)?;
+        next.run(req, extensions).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+
+    use axum::{response::Redirect, routing::get, Router};
+
+    use super::*;
+    use crate::http_client::HttpClientConfig;
+
+    fn url(url: &str) -> Url {
+        url.parse().unwrap()
+    }
+
+    /// Find egress error in error source chain.
+    fn egress_rule(err: &reqwest_middleware::Error) -> Option<String> {
+        if let reqwest_middleware::Error::Middleware(err) = err {
+            if let Some(HttpClientError::EgressDenied { rule, .. }) = err.downcast_ref() {
+                return Some(rule.clone());
+            }
+        }
+        let mut source = err.source();
+        while let Some(err) = source {
+            if let Some(HttpClientError::EgressDenied { rule, .. }) = err.downcast_ref() {
+                return Some(rule.clone());
+            }
+            source = err.source();
+        }
+        None
+    }
+
+    /// Serve redirecting endpoints on loopback interface.
+    async fn serve() -> SocketAddr {
+        let app = Router::new()
+            .route(
+                "/public",
+                get(|| async { Redirect::temporary("/internal") }),
+            )
+            .route("/internal", get(|| async { "internal" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    /// URL patterns are parsed and matched by scheme, host glob, port and path prefix.
+    #[test]
+    fn hostname_allow() {
+        let rule: EgressRule = "https://*.example.com/api".parse().unwrap();
+        assert_eq!(rule.to_string(), "https://*.example.com/api");
+        assert!(rule.matches(&url("https://billing.example.com/api/v1?x=1")));
+        assert!(rule.matches(&url("https://a.b.EXAMPLE.com/api")));
+        assert!(!rule.matches(&url("http://billing.example.com/api")));
+        assert!(!rule.matches(&url("https://example.com/api")));
+        assert!(!rule.matches(&url("https://billing.example.com.evil.net/api")));
+        assert!(!rule.matches(&url("https://billing.example.com/apiary")));
+
+        let rule: EgressRule = "*://[::1]:8080".parse().unwrap();
+        assert!(rule.matches(&url("http://[::1]:8080/anything")));
+        assert!(!rule.matches(&url("http://[::1]:8081/anything")));
+        let rule: EgressRule = "internal.svc:443".parse().unwrap();
+        assert!(rule.matches(&url("https://internal.svc/")));
+        assert!(!rule.matches(&url("http://internal.svc/")));
+        assert!("https://:80".parse::<EgressRule>().is_err());
+        assert!("host:port".parse::<EgressRule>().is_err());
+
+        let policy = EgressPolicyConfig::default()
+            .with_allow("https://*.example.com".parse().unwrap())
+            .build("test", None);
+        assert!(policy.check_url(&url("https://api.example.com/x")).is_ok());
+        let violation = policy.check_url(&url("https://evil.net/x?q")).unwrap_err();
+        assert_eq!(violation.destination, "https://evil.net/x");
+        assert_eq!(violation.rule, ALLOWLIST_RULE);
+    }
+
+    /// Resolved addresses are checked, regardless of host name.
+    #[tokio::test]
+    async fn resolved_ip_deny() {
+        let policy = Arc::new(EgressPolicyConfig::default().build("test", None));
+        assert_eq!(
+            policy.check_ip("169.254.169.254".parse().unwrap()),
+            Err(PRIVATE_RULE.into())
+        );
+        assert_eq!(
+            policy.check_ip("::ffff:10.1.2.3".parse().unwrap()),
+            Err(PRIVATE_RULE.into())
+        );
+        assert!(policy.check_ip("93.184.216.34".parse().unwrap()).is_ok());
+
+        let resolver = EgressResolver(Arc::clone(&policy));
+        let err = match resolver.resolve(Name::from_str("localhost").unwrap()).await {
+            Ok(_) => panic!("loopback address not denied"),
+            Err(err) => err,
+        };
+        assert!(matches!(
+            err.downcast_ref::<HttpClientError>(),
+            Some(HttpClientError::EgressDenied { rule, .. }) if rule == PRIVATE_RULE
+        ));
+
+        // Whole client, with host name and IP address destinations.
+        let addr = serve().await;
+        let mut cfg = HttpClientConfig::default();
+        cfg.egress = Some(EgressPolicyConfig::default());
+        let client = cfg.to_client(None).await.unwrap();
+        for url in [
+            format!("http://{addr}/internal"),
+            format!("http://localhost:{}/internal", addr.port()),
+        ] {
+            let err = client.get(url).send().await.unwrap_err();
+            assert_eq!(egress_rule(&err).as_deref(), Some(PRIVATE_RULE));
+        }
+    }
+
+    /// Every redirect hop is checked against allowlist.
+    #[tokio::test]
+    async fn redirect_recheck() {
+        let addr = serve().await;
+        let mut cfg = HttpClientConfig::default();
+        cfg.egress = Some(
+            EgressPolicyConfig::default()
+                .with_deny_private(false)
+                .with_allow(format!("http://{addr}/public").parse().unwrap()),
+        );
+        let client = cfg.to_client(None).await.unwrap();
+        let err = client
+            .get(format!("http://{addr}/public"))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(egress_rule(&err).as_deref(), Some(ALLOWLIST_RULE));
+        let err = client
+            .get(format!("http://{addr}/internal"))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(egress_rule(&err).as_deref(), Some(ALLOWLIST_RULE));
+    }
+
+    /// Violations are not blocked in dry-run mode.
+    #[tokio::test]
+    async fn dry_run() {
+        let addr = serve().await;
+        let mut cfg = HttpClientConfig::default();
+        cfg.egress = Some(
+            EgressPolicyConfig::default()
+                .with_allow("https://*.example.com".parse().unwrap())
+                .with_dry_run(true),
+        );
+        let client = cfg.to_client(None).await.unwrap();
+        for host in [addr.to_string(), format!("localhost:{}", addr.port())] {
+            let resp = client
+                .get(format!("http://{host}/public"))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.text().await.unwrap(), "internal");
+        }
+    }
+}
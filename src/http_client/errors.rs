@@ -14,6 +14,16 @@ pub enum HttpClientError {
     /// Error loading TLS identity.
     #[error("Error loading TLS identity: {0}")]
     IdentityLoad(IoError),
+    /// Outgoing request denied by egress policy.
+    #[error("Request from client {client} to {destination} denied by egress rule {rule}")]
+    EgressDenied {
+        /// Client name.
+        client: String,
+        /// Attempted destination.
+        destination: String,
+        /// Name of violated rule.
+        rule: String,
+    },
 }
 
 impl HttpClientError {
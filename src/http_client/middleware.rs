@@ -1,8 +1,11 @@
 //! HTTP client - middleware setup.
 
-use std::time::Instant;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use http::{Extensions, HeaderValue};
+use http::{header::AUTHORIZATION, Extensions, HeaderName, HeaderValue};
 use hyper::body::Body;
 use opentelemetry::KeyValue;
 use recloser::AsyncRecloser;
@@ -14,11 +17,19 @@ use reqwest_tracing::{
 use tracing::{field::Empty, Span};
 
 use crate::{
+    auth::{TokenIssuer, CURRENT_USER_ID},
+    cancel::cancel_aware,
+    http_client::{
+        cb::{BreakerTracker, HttpClientCircuitBreakerConfig},
+        egress::{EgressMiddleware, EgressPolicy},
+    },
     layers::{
+        dependency::track_dependency,
         request_id::{CURRENT_REQUEST_ID, X_REQUEST_ID},
         timeout::{CURRENT_DEADLINE, X_TIMEOUT},
     },
     metrics::ClientMetricsState,
+    persist::{register_breaker, BreakerStatus},
 };
 
 /// Custom delegate to create OpenTelemetry spans for distributed tracing.
@@ -73,8 +84,87 @@ impl Middleware for HeaderPropagationMiddleware {
     }
 }
 
+/// Middleware to bound chained requests by the deadline of current server request.
+struct DeadlineMiddleware {
+    /// Request timeout configured for the client.
+    request_timeout: Option<Duration>,
+    /// Name of HTTP header used to pass deadline downstream.
+    header: Option<HeaderName>,
+}
+
+#[async_trait::async_trait]
+impl Middleware for DeadlineMiddleware {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        if let Ok(Some(deadline)) = CURRENT_DEADLINE.try_with(Clone::clone) {
+            let left = deadline.time_left().unwrap_or_default();
+            let timeout = [req.timeout().copied(), self.request_timeout]
+                .into_iter()
+                .flatten()
+                .fold(left, Duration::min);
+            *req.timeout_mut() = Some(timeout);
+            if let Some(header) = &self.header {
+                req.headers_mut()
+                    .insert(header.clone(), HeaderValue::from(deadline.to_unix_millis()));
+            }
+        }
+        next.run(req, extensions).await
+    }
+}
+
+/// Middleware to abort in-flight requests when current server request gets cancelled.
+struct CancellationMiddleware;
+
+#[async_trait::async_trait]
+impl Middleware for CancellationMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        cancel_aware(next.run(req, extensions))
+            .await
+            .map_err(Error::middleware)?
+    }
+}
+
+/// Middleware to attach service-to-service tokens.
+struct ServiceTokenMiddleware(Arc<TokenIssuer>);
+
+#[async_trait::async_trait]
+impl Middleware for ServiceTokenMiddleware {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        if !req.headers().contains_key(AUTHORIZATION) {
+            let user = match self.0.propagates_user() {
+                true => CURRENT_USER_ID.try_with(Clone::clone).ok().flatten(),
+                false => None,
+            };
+            req.headers_mut().insert(
+                AUTHORIZATION,
+                self.0.authorization(user.as_deref().map(String::as_str)),
+            );
+        }
+        next.run(req, extensions).await
+    }
+}
+
 /// Circuit breaker middleware.
-struct CircuitBreakerMiddleware(AsyncRecloser);
+struct CircuitBreakerMiddleware {
+    /// Circuit breaker.
+    breaker: AsyncRecloser,
+    /// Observed breaker state, used for state persistence.
+    tracker: Arc<BreakerTracker>,
+}
 
 /// Circuit breaker rejection error.
 #[derive(Clone, Debug, thiserror::Error)]
@@ -89,14 +179,41 @@ impl Middleware for CircuitBreakerMiddleware {
         extensions: &mut Extensions,
         next: Next<'_>,
     ) -> Result<Response> {
-        match self.0.call(next.run(req, extensions)).await {
-            Ok(resp) => Ok(resp),
-            Err(recloser::Error::Rejected) => Err(Error::middleware(CircuitBreakerRejection)),
-            Err(recloser::Error::Inner(err)) => Err(err),
+        if self.tracker.is_held_open() {
+            return Err(Error::middleware(CircuitBreakerRejection));
+        }
+        match self.breaker.call(next.run(req, extensions)).await {
+            Ok(resp) => {
+                self.tracker.observe(BreakerStatus::Closed);
+                Ok(resp)
+            }
+            Err(recloser::Error::Rejected) => {
+                self.tracker.observe(BreakerStatus::Open);
+                Err(Error::middleware(CircuitBreakerRejection))
+            }
+            Err(recloser::Error::Inner(err)) => {
+                self.tracker.observe(BreakerStatus::Closed);
+                Err(err)
+            }
         }
     }
 }
 
+/// Middleware attributing request time to a downstream dependency of current server request.
+struct DependencyTimingMiddleware(String);
+
+#[async_trait::async_trait]
+impl Middleware for DependencyTimingMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        track_dependency(&self.0, next.run(req, extensions)).await
+    }
+}
+
 /// HTTP client metrics middleware.
 struct MetricsMiddleware(ClientMetricsState);
 
@@ -153,16 +270,114 @@ impl Middleware for MetricsMiddleware {
 pub(crate) fn wrap_client(
     client: Client,
     metrics: Option<ClientMetricsState>,
-    cb: Option<AsyncRecloser>,
+    cb: Option<&HttpClientCircuitBreakerConfig>,
+    token_issuer: Option<Arc<TokenIssuer>>,
+    egress: Option<Arc<EgressPolicy>>,
+    request_timeout: Option<Duration>,
+    deadline_header: Option<HeaderName>,
 ) -> ClientWithMiddleware {
-    let mut builder = ClientBuilder::new(client)
+    let mut builder = ClientBuilder::new(client);
+    if let Some(egress) = egress {
+        builder = builder.with(EgressMiddleware(egress));
+    }
+    builder = builder
+        .with(CancellationMiddleware)
         .with(HeaderPropagationMiddleware)
-        .with(TracingMiddleware::<ReqwestSpanBackend>::new());
+        .with(DeadlineMiddleware {
+            request_timeout,
+            header: deadline_header,
+        });
+    if let Some(token_issuer) = token_issuer {
+        builder = builder.with(ServiceTokenMiddleware(token_issuer));
+    }
+    builder = builder.with(TracingMiddleware::<ReqwestSpanBackend>::new());
+    let name = metrics.as_ref().map(|metrics| metrics.name().to_string());
+    if let Some(name) = &name {
+        builder = builder.with(DependencyTimingMiddleware(name.clone()));
+    }
     if let Some(metrics) = metrics {
         builder = builder.with(MetricsMiddleware(metrics));
     }
     if let Some(cb) = cb {
-        builder = builder.with(CircuitBreakerMiddleware(cb));
+        let tracker = Arc::new(BreakerTracker::new(cb));
+        if let Some(name) = name {
+            register_breaker(&name, tracker.clone());
+        }
+        builder = builder.with(CircuitBreakerMiddleware {
+            breaker: cb.make_circuit_breaker(),
+            tracker,
+        });
     }
     builder.build()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::{http::HeaderMap, routing::get, Router};
+
+    use super::*;
+    use crate::{http_client::HttpClientConfig, layers::ext::Deadline};
+
+    /// Serve endpoints on loopback interface.
+    async fn serve() -> SocketAddr {
+        let app = Router::new()
+            .route(
+                "/deadline",
+                get(|headers: HeaderMap| async move {
+                    headers
+                        .get("x-request-deadline")
+                        .and_then(|val| val.to_str().ok())
+                        .unwrap_or_default()
+                        .to_string()
+                }),
+            )
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                    "slow"
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    /// Deadline of current request bounds request timeout and gets passed downstream.
+    #[tokio::test]
+    async fn deadline_propagation() {
+        let addr = serve().await;
+        let mut config = HttpClientConfig::default();
+        config.deadline_header = Some("x-request-deadline".into());
+        let client = config.to_client(None).await.unwrap();
+        let deadline = Deadline::from(Duration::from_millis(300));
+
+        let sent = CURRENT_DEADLINE
+            .scope(Some(deadline), async {
+                client
+                    .get(format!("http://{addr}/deadline"))
+                    .send()
+                    .await
+                    .unwrap()
+                    .text()
+                    .await
+                    .unwrap()
+            })
+            .await;
+        let sent: u64 = sent.parse().unwrap();
+        assert!(sent.abs_diff(deadline.to_unix_millis()) < 50);
+
+        let start = Instant::now();
+        let err = CURRENT_DEADLINE
+            .scope(Some(deadline), async {
+                client.get(format!("http://{addr}/slow")).send().await
+            })
+            .await
+            .unwrap_err();
+        assert!(err.is_timeout());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -4,7 +4,12 @@
 
 mod cb;
 mod config;
+mod egress;
 mod errors;
 mod middleware;
 
-pub use self::{config::HttpClientConfig, errors::HttpClientError};
+pub use self::{
+    config::HttpClientConfig,
+    egress::{EgressPolicyConfig, EgressRule, EgressRuleError},
+    errors::HttpClientError,
+};
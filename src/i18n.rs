@@ -0,0 +1,355 @@
+//! Localization of error messages.
+
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::errors::IoError;
+
+/// Locale of built-in error messages.
+pub(crate) const BUILTIN_LOCALE: &str = "en";
+
+/// Message translations, keyed by message key, then by problem field name, then by locale.
+pub type MessageMap = HashMap<String, HashMap<String, HashMap<String, String>>>;
+
+/// Error type used in message catalog loading.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum LocalizationError {
+    /// Unable to read catalog file.
+    #[error("Unable to read message catalog {0}: {1}")]
+    Read(PathBuf, IoError),
+    /// Unable to parse catalog file.
+    #[error("Unable to parse message catalog {0}: {1}")]
+    Parse(PathBuf, serde_json::Error),
+}
+
+/// Error message localization configuration.
+///
+/// Catalog files are JSON documents, keyed by problem details `type` URI (or by explicit
+/// [`MessageKey`]), then by problem field name, then by locale:
+///
+/// ```json
+/// {
+///   "tag:example.com,2024:not-found": {
+///     "title": {"de": "Nicht gefunden", "fr": "Introuvable"}
+///   }
+/// }
+/// ```
+///
+/// Later catalogs override earlier ones, inline `messages` override all files. Catalogs are
+/// reloaded on SIGHUP.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct LocalizationConfig {
+    /// Locale used when none of the locales requested by client are available.
+    #[serde(default = "LocalizationConfig::default_locale")]
+    default_locale: String,
+    /// Paths to message catalog files.
+    #[serde(default)]
+    catalogs: Vec<PathBuf>,
+    /// Inline message translations.
+    #[serde(default)]
+    messages: MessageMap,
+}
+
+impl Default for LocalizationConfig {
+    fn default() -> Self {
+        Self {
+            default_locale: Self::default_locale(),
+            catalogs: Vec::new(),
+            messages: MessageMap::new(),
+        }
+    }
+}
+
+impl LocalizationConfig {
+    /// Default value for [`Self::default_locale`].
+    #[must_use]
+    #[inline]
+    fn default_locale() -> String {
+        BUILTIN_LOCALE.into()
+    }
+
+    /// Set locale used when none of the requested locales are available.
+    #[must_use]
+    pub fn with_default_locale(mut self, locale: impl ToString) -> Self {
+        self.default_locale = locale.to_string().to_ascii_lowercase();
+        self
+    }
+
+    /// Add message catalog file.
+    #[must_use]
+    pub fn with_catalog(mut self, path: impl Into<PathBuf>) -> Self {
+        self.catalogs.push(path.into());
+        self
+    }
+
+    /// Add inline message translation.
+    #[must_use]
+    pub fn with_message(
+        mut self,
+        key: impl ToString,
+        field: impl ToString,
+        locale: impl ToString,
+        text: impl ToString,
+    ) -> Self {
+        self.messages
+            .entry(key.to_string())
+            .or_default()
+            .entry(field.to_string())
+            .or_default()
+            .insert(locale.to_string().to_ascii_lowercase(), text.to_string());
+        self
+    }
+
+    /// Load message catalog.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if any of catalog files cannot be read or parsed.
+    pub(crate) fn load(&self) -> Result<MessageCatalog, LocalizationError> {
+        let catalog = MessageCatalog {
+            config: self.clone(),
+            messages: Arc::new(RwLock::new(self.load_messages()?)),
+        };
+        register(catalog.clone());
+        Ok(catalog)
+    }
+
+    /// Read and merge all configured message sources.
+    fn load_messages(&self) -> Result<CatalogData, LocalizationError> {
+        let mut data = CatalogData::default();
+        for path in &self.catalogs {
+            let raw =
+                fs::read(path).map_err(|err| LocalizationError::Read(path.clone(), err.into()))?;
+            let messages: MessageMap = serde_json::from_slice(&raw)
+                .map_err(|err| LocalizationError::Parse(path.clone(), err))?;
+            data.merge(messages);
+        }
+        data.merge(self.messages.clone());
+        data.locales.insert(BUILTIN_LOCALE.into());
+        data.locales.insert(self.default_locale.clone());
+        Ok(data)
+    }
+}
+
+/// Merged message translations.
+#[derive(Debug, Default)]
+struct CatalogData {
+    /// Translations.
+    messages: MessageMap,
+    /// All locales present in translations.
+    locales: HashSet<String>,
+}
+
+impl CatalogData {
+    /// Merge translations, overriding existing ones.
+    fn merge(&mut self, messages: MessageMap) {
+        for (key, fields) in messages {
+            let entry = self.messages.entry(key).or_default();
+            for (field, texts) in fields {
+                let field_entry = entry.entry(field).or_default();
+                for (locale, text) in texts {
+                    let locale = locale.to_ascii_lowercase();
+                    self.locales.insert(locale.clone());
+                    field_entry.insert(locale, text);
+                }
+            }
+        }
+    }
+}
+
+/// Catalog of globally installed localization layer.
+static CATALOG: Lazy<RwLock<Option<MessageCatalog>>> = Lazy::new(Default::default);
+
+/// Replace globally registered message catalog.
+fn register(catalog: MessageCatalog) {
+    *CATALOG.write() = Some(catalog);
+}
+
+/// Reload globally registered message catalog, if any.
+///
+/// Keeps old translations if reload fails.
+pub(crate) fn reload() {
+    let catalog = CATALOG.read().clone();
+    if let Some(catalog) = catalog {
+        match catalog.reload() {
+            Ok(()) => info!("message catalog reloaded"),
+            Err(err) => warn!(%err, "unable to reload message catalog"),
+        }
+    }
+}
+
+/// Shared, reloadable message catalog.
+#[derive(Clone, Debug)]
+pub(crate) struct MessageCatalog {
+    /// Catalog configuration.
+    config: LocalizationConfig,
+    /// Loaded translations.
+    messages: Arc<RwLock<CatalogData>>,
+}
+
+impl MessageCatalog {
+    /// Re-read all configured message sources.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if any of catalog files cannot be read or parsed.
+    pub(crate) fn reload(&self) -> Result<(), LocalizationError> {
+        let data = self.config.load_messages()?;
+        *self.messages.write() = data;
+        Ok(())
+    }
+
+    /// Choose locale for a response, given `Accept-Language` header value.
+    #[must_use]
+    pub(crate) fn negotiate(&self, accept_language: Option<&str>) -> String {
+        let data = self.messages.read();
+        for tag in parse_accept_language(accept_language.unwrap_or_default()) {
+            if tag == "*" {
+                break;
+            }
+            if data.locales.contains(&tag) {
+                return tag;
+            }
+            if let Some((primary, _)) = tag.split_once('-') {
+                if data.locales.contains(primary) {
+                    return primary.to_string();
+                }
+            }
+        }
+        self.config.default_locale.clone()
+    }
+
+    /// Get translated field text.
+    #[must_use]
+    pub(crate) fn lookup(&self, key: &str, field: &str, locale: &str) -> Option<String> {
+        self.messages
+            .read()
+            .messages
+            .get(key)?
+            .get(field)?
+            .get(locale)
+            .cloned()
+    }
+}
+
+/// Explicit catalog key for problem details response.
+///
+/// By default, problem details `type` URI is used as a key. Add this as a response extension
+/// (see [`crate::ResponseExtension`]) to use a different key, for example to translate distinct
+/// error variants sharing the same `type`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MessageKey(pub String);
+
+impl MessageKey {
+    /// Create new message key.
+    #[must_use]
+    pub fn new(key: impl ToString) -> Self {
+        Self(key.to_string())
+    }
+}
+
+/// Parse `Accept-Language` header value into a list of lowercase language tags, ordered by
+/// descending quality.
+///
+/// Tags with zero quality are skipped. Order of tags with equal quality is preserved.
+fn parse_accept_language(value: &str) -> Vec<String> {
+    let mut tags: Vec<(String, f32)> = value
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let tag = parts.next()?.trim().to_ascii_lowercase();
+            if tag.is_empty() {
+                return None;
+            }
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (quality > 0.0).then_some((tag, quality))
+        })
+        .collect();
+    tags.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+    tags.into_iter().map(|(tag, _)| tag).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> MessageCatalog {
+        LocalizationConfig::default()
+            .with_message("tag:test,2024:nf", "title", "de", "Nicht gefunden")
+            .with_message("tag:test,2024:nf", "title", "fr", "Introuvable")
+            .with_message("tag:test,2024:nf", "title", "fr-ca", "Pas trouvé")
+            .load()
+            .unwrap()
+    }
+
+    /// Quality values are honored, ties keep header order.
+    #[test]
+    fn parse_quality() {
+        assert_eq!(
+            parse_accept_language("fr;q=0.5, de , en;q=0.9, it;q=0, es;q=0.9"),
+            vec!["de", "en", "es", "fr"]
+        );
+        assert!(parse_accept_language("").is_empty());
+    }
+
+    /// Negotiation order: exact match, primary subtag, default locale.
+    #[test]
+    fn negotiation_order() {
+        let catalog = catalog();
+        assert_eq!(catalog.negotiate(Some("fr-CA, de;q=0.5")), "fr-ca");
+        assert_eq!(catalog.negotiate(Some("fr-BE, de;q=0.5")), "fr");
+        assert_eq!(catalog.negotiate(Some("it, de;q=0.5")), "de");
+        assert_eq!(catalog.negotiate(Some("de;q=0.1, fr;q=0.2")), "fr");
+        assert_eq!(catalog.negotiate(Some("it, *")), "en");
+        assert_eq!(catalog.negotiate(None), "en");
+    }
+
+    /// Catalog files are re-read on reload.
+    #[test]
+    fn catalog_reload() {
+        let path = std::env::temp_dir().join(format!("uxum-i18n-{}.json", std::process::id()));
+        fs::write(&path, r#"{"tag:test,2024:nf": {"title": {"de": "Alt"}}}"#).unwrap();
+        let catalog = LocalizationConfig::default()
+            .with_catalog(&path)
+            .load()
+            .unwrap();
+        assert_eq!(
+            catalog.lookup("tag:test,2024:nf", "title", "de").as_deref(),
+            Some("Alt")
+        );
+        fs::write(
+            &path,
+            r#"{"tag:test,2024:nf": {"title": {"de": "Neu", "fr": "Nouveau"}}}"#,
+        )
+        .unwrap();
+        catalog.reload().unwrap();
+        assert_eq!(
+            catalog.lookup("tag:test,2024:nf", "title", "de").as_deref(),
+            Some("Neu")
+        );
+        assert_eq!(catalog.negotiate(Some("fr")), "fr");
+        fs::write(&path, "not json").unwrap();
+        assert!(catalog.reload().is_err());
+        assert_eq!(
+            catalog.lookup("tag:test,2024:nf", "title", "de").as_deref(),
+            Some("Neu")
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
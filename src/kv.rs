@@ -0,0 +1,100 @@
+//! Key-value storage abstraction for framework state.
+
+use std::{collections::BTreeMap, fmt, sync::Arc};
+
+use bytes::Bytes;
+use parking_lot::RwLock;
+use thiserror::Error;
+
+/// Error type used in key-value stores.
+#[derive(Clone, Debug, Error)]
+#[non_exhaustive]
+pub enum KvError {
+    /// Storage back-end failure.
+    #[error("Key-value store error: {0}")]
+    Backend(String),
+    /// Stored value could not be encoded or decoded.
+    #[error("Key-value store serialization error: {0}")]
+    Serialization(String),
+}
+
+impl From<serde_json::Error> for KvError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Serialization(value.to_string())
+    }
+}
+
+/// Key-value store used to persist framework state.
+///
+/// Keys are UTF-8 strings, namespaced by subsystem using `/` separator. Implementations must be
+/// safe to share between threads.
+pub trait KeyValueStore: fmt::Debug + Send + Sync {
+    /// Get value by key.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` on storage back-end failure.
+    fn get(&self, key: &str) -> Result<Option<Bytes>, KvError>;
+
+    /// Insert or replace value.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` on storage back-end failure.
+    fn put(&self, key: &str, value: Bytes) -> Result<(), KvError>;
+
+    /// Delete value by key.
+    ///
+    /// Returns `true` if value was present.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` on storage back-end failure.
+    fn delete(&self, key: &str) -> Result<bool, KvError>;
+
+    /// Get all key-value pairs with keys starting with `prefix`, ordered by key.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` on storage back-end failure.
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, Bytes)>, KvError>;
+}
+
+/// In-memory key-value store.
+///
+/// Contents are lost on restart.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore(Arc<RwLock<BTreeMap<String, Bytes>>>);
+
+impl MemoryStore {
+    /// Create new empty store.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl KeyValueStore for MemoryStore {
+    fn get(&self, key: &str) -> Result<Option<Bytes>, KvError> {
+        Ok(self.0.read().get(key).cloned())
+    }
+
+    fn put(&self, key: &str, value: Bytes) -> Result<(), KvError> {
+        self.0.write().insert(key.to_string(), value);
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, KvError> {
+        Ok(self.0.write().remove(key).is_some())
+    }
+
+    fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, Bytes)>, KvError> {
+        Ok(self
+            .0
+            .read()
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, val)| (key.clone(), val.clone()))
+            .collect())
+    }
+}
@@ -0,0 +1,139 @@
+//! [`tower`] layer to write access log records.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::{Instant, SystemTime},
+};
+
+use axum::{
+    body::HttpBody,
+    http::{Request, Response},
+};
+use pin_project::pin_project;
+use tower::{Layer, Service};
+
+use crate::{
+    auth::UserId,
+    errors::ErrorCode,
+    layers::ext::HandlerName,
+    logging::access::{user_hash, AccessLogSink, AccessRecord},
+};
+
+/// Name of request ID header.
+const X_REQUEST_ID: &str = "x-request-id";
+
+/// Access log [`tower`] layer.
+#[derive(Clone, Debug)]
+pub(crate) struct AccessLogLayer {
+    /// Access log writer.
+    sink: AccessLogSink,
+}
+
+impl AccessLogLayer {
+    /// Create new access log layer.
+    pub(crate) fn new(sink: AccessLogSink) -> Self {
+        Self { sink }
+    }
+}
+
+impl<S> Layer<S> for AccessLogLayer {
+    type Service = AccessLog<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AccessLog {
+            inner,
+            sink: self.sink.clone(),
+        }
+    }
+}
+
+/// Access log [`tower`] service.
+#[derive(Clone, Debug)]
+pub(crate) struct AccessLog<S> {
+    /// Inner service.
+    inner: S,
+    /// Access log writer.
+    sink: AccessLogSink,
+}
+
+impl<S, T, U> Service<Request<T>> for AccessLog<S>
+where
+    S: Service<Request<T>, Response = Response<U>>,
+    U: HttpBody,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = AccessLogFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<T>) -> Self::Future {
+        let request_id = req
+            .headers()
+            .get(X_REQUEST_ID)
+            .and_then(|val| val.to_str().ok())
+            .map(ToOwned::to_owned);
+        AccessLogFuture {
+            inner: self.inner.call(req),
+            sink: self.sink.clone(),
+            timestamp: SystemTime::now(),
+            start: Instant::now(),
+            request_id,
+        }
+    }
+}
+
+/// Access log [`tower`] service future.
+#[pin_project]
+pub(crate) struct AccessLogFuture<F> {
+    /// Inner future.
+    #[pin]
+    inner: F,
+    /// Access log writer.
+    sink: AccessLogSink,
+    /// Request start time.
+    timestamp: SystemTime,
+    /// Request start time, for duration measurement.
+    start: Instant,
+    /// Request ID.
+    request_id: Option<String>,
+}
+
+impl<F, U, E> Future for AccessLogFuture<F>
+where
+    F: Future<Output = Result<Response<U>, E>>,
+    U: HttpBody,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let resp = ready!(this.inner.poll(cx))?;
+        let timestamp_us = this
+            .timestamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |ts| ts.as_micros() as u64);
+        let extensions = resp.extensions();
+        this.sink.write(&AccessRecord {
+            timestamp_us,
+            handler: extensions
+                .get::<HandlerName>()
+                .map_or("", HandlerName::as_str),
+            status: resp.status().as_u16(),
+            duration_us: this.start.elapsed().as_micros() as u64,
+            bytes: resp.body().size_hint().exact().unwrap_or_default(),
+            user_hash: extensions
+                .get::<UserId>()
+                .map(|user| user_hash(user.as_str())),
+            request_id: this.request_id.take(),
+            code: extensions
+                .get::<ErrorCode>()
+                .map(|code| code.as_str().to_string()),
+        });
+        Poll::Ready(Ok(resp))
+    }
+}
@@ -0,0 +1,259 @@
+//! Request body size limit.
+//!
+//! Requests with `Content-Length` over the limit are rejected right away. Other bodies are counted
+//! while being read, and if the limit is exceeded, whatever the handler produced is replaced with
+//! the same error response. Default body limit of [`axum`] extractors is disabled, so that this
+//! limit is the only one in effect.
+
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{ready, Context, Poll},
+};
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::DefaultBodyLimit,
+    http::{header::CONTENT_LENGTH, Request, Response, StatusCode},
+    response::IntoResponse,
+};
+use futures::future::BoxFuture;
+use http_body::{Frame, SizeHint};
+use pin_project::pin_project;
+use thiserror::Error;
+use tower::{BoxError, Layer, Service};
+use tracing::debug;
+
+use crate::errors::{codes, ErrorCode};
+
+/// Request body size limit error.
+#[derive(Clone, Debug, Error)]
+#[non_exhaustive]
+pub enum BodyLimitError {
+    /// Request body exceeds size limit.
+    #[error("Request body exceeds size limit of {0} bytes")]
+    TooLarge(u64),
+}
+
+impl BodyLimitError {
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::TooLarge(_) => codes::BODY_TOO_LARGE,
+        }
+    }
+}
+
+impl IntoResponse for BodyLimitError {
+    fn into_response(self) -> axum::response::Response {
+        let code = self.code();
+        let mut problem = code
+            .problem(StatusCode::PAYLOAD_TOO_LARGE)
+            .with_type("tag:uxum.github.io,2024:body-limit")
+            .with_title(self.to_string());
+        match self {
+            Self::TooLarge(limit) => problem = problem.with_value("limit", limit),
+        }
+        (code, problem).into_response()
+    }
+}
+
+/// Request body size limit [`tower`] layer.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BodyLimitLayer {
+    /// Maximum request body size, in bytes.
+    limit: u64,
+}
+
+impl BodyLimitLayer {
+    /// Create new request body size limit layer.
+    #[must_use]
+    pub(crate) fn new(limit: u64) -> Self {
+        Self { limit }
+    }
+}
+
+impl<S> Layer<S> for BodyLimitLayer {
+    type Service = BodyLimit<<DefaultBodyLimit as Layer<S>>::Service>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BodyLimit {
+            inner: DefaultBodyLimit::disable().layer(inner),
+            limit: self.limit,
+        }
+    }
+}
+
+/// Request body size limit [`tower`] service.
+#[derive(Clone, Debug)]
+pub(crate) struct BodyLimit<S> {
+    /// Inner service.
+    inner: S,
+    /// Maximum request body size, in bytes.
+    limit: u64,
+}
+
+impl<S> Service<Request<Body>> for BodyLimit<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let limit = self.limit;
+        let declared = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|val| val.to_str().ok())
+            .and_then(|val| val.parse::<u64>().ok());
+        if declared.is_some_and(|len| len > limit) {
+            debug!(limit, declared, "request body is too large");
+            return Box::pin(async move { Ok(BodyLimitError::TooLarge(limit).into_response()) });
+        }
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let req = req.map(|body| {
+            Body::new(LimitedBody {
+                inner: body,
+                remaining: limit,
+                limit,
+                exceeded: exceeded.clone(),
+            })
+        });
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let result = future.await.map_err(Into::into);
+            match exceeded.load(Ordering::Acquire) {
+                true => {
+                    debug!(limit, "request body exceeded size limit while reading");
+                    Ok(BodyLimitError::TooLarge(limit).into_response())
+                }
+                false => result,
+            }
+        })
+    }
+}
+
+/// Request body wrapper, which fails once size limit is exceeded.
+#[pin_project]
+struct LimitedBody<B> {
+    /// Inner body.
+    #[pin]
+    inner: B,
+    /// Bytes left before reaching the limit.
+    remaining: u64,
+    /// Maximum request body size, in bytes.
+    limit: u64,
+    /// Set when limit is exceeded.
+    exceeded: Arc<AtomicBool>,
+}
+
+impl<B> HttpBody for LimitedBody<B>
+where
+    B: HttpBody<Data = Bytes>,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = match ready!(this.inner.poll_frame(cx)) {
+            Some(Ok(frame)) => frame,
+            Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+            None => return Poll::Ready(None),
+        };
+        if let Some(data) = frame.data_ref() {
+            let len = data.len() as u64;
+            if len > *this.remaining {
+                this.exceeded.store(true, Ordering::Release);
+                return Poll::Ready(Some(Err(BodyLimitError::TooLarge(*this.limit).into())));
+            }
+            *this.remaining -= len;
+        }
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// Layer around handler which echoes request body.
+    fn service(
+        limit: u64,
+    ) -> impl Service<Request<Body>, Response = Response<Body>, Error = BoxError> {
+        BodyLimitLayer::new(limit).layer(tower::service_fn(|req: Request<Body>| async move {
+            match axum::body::to_bytes(req.into_body(), usize::MAX).await {
+                Ok(bytes) => Ok::<_, BoxError>(bytes.into_response()),
+                Err(_) => Ok(StatusCode::BAD_REQUEST.into_response()),
+            }
+        }))
+    }
+
+    async fn problem(resp: Response<Body>) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn within_limit() {
+        let req = Request::new(Body::from("0123456789"));
+        let resp = service(10).oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(bytes, "0123456789");
+    }
+
+    #[tokio::test]
+    async fn declared_length() {
+        let req = Request::builder()
+            .header(CONTENT_LENGTH, "11")
+            .body(Body::from("0123456789a"))
+            .unwrap();
+        let resp = service(10).oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(resp.extensions().get(), Some(&codes::BODY_TOO_LARGE));
+        let body = problem(resp).await;
+        assert_eq!(body["code"], "body.too_large");
+        assert_eq!(body["limit"], 10);
+    }
+
+    #[tokio::test]
+    async fn streamed_body() {
+        let chunks = ["01234", "56789", "a"].map(|chunk| Ok::<_, BoxError>(Bytes::from(chunk)));
+        let req = Request::new(Body::from_stream(stream::iter(chunks)));
+        let resp = service(10).oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(problem(resp).await["code"], "body.too_large");
+    }
+}
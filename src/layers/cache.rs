@@ -0,0 +1,566 @@
+//! [`tower`] layer to apply caching headers policy to responses.
+
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    body::Body,
+    http::{
+        header::{ACCEPT, CACHE_CONTROL, EXPIRES, VARY},
+        HeaderValue, Method, Request, Response, StatusCode,
+    },
+};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tower::{BoxError, Layer, Service};
+use tracing::warn;
+
+/// Built-in caching policy presets.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum CachePreset {
+    /// Never store responses: `no-store`.
+    ApiNoStore,
+    /// Publicly cacheable for a day: `public, max-age=86400`.
+    PublicStatic,
+    /// Cacheable by client only, for a minute: `private, max-age=60`.
+    PrivateShort,
+}
+
+impl CachePreset {
+    /// Get `Cache-Control` header value for this preset.
+    #[must_use]
+    pub fn cache_control(self) -> &'static str {
+        match self {
+            Self::ApiNoStore => "no-store",
+            Self::PublicStatic => "public, max-age=86400",
+            Self::PrivateShort => "private, max-age=60",
+        }
+    }
+}
+
+/// Class of HTTP response status code.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum StatusClass {
+    /// 2xx status codes.
+    Success,
+    /// 3xx status codes.
+    Redirection,
+    /// 4xx status codes.
+    ClientError,
+    /// 5xx status codes.
+    ServerError,
+    /// 4xx and 5xx status codes.
+    Error,
+}
+
+impl StatusClass {
+    /// Check whether status code belongs to this class.
+    #[must_use]
+    fn matches(self, status: StatusCode) -> bool {
+        match self {
+            Self::Success => status.is_success(),
+            Self::Redirection => status.is_redirection(),
+            Self::ClientError => status.is_client_error(),
+            Self::ServerError => status.is_server_error(),
+            Self::Error => status.is_client_error() || status.is_server_error(),
+        }
+    }
+}
+
+/// Caching policy rule.
+///
+/// Rule applies to a response if all of its set matchers match. Either [`Self::preset`] or
+/// [`Self::cache_control`] must be set.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct CacheRule {
+    /// Match handler name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handler: Option<String>,
+    /// Match handler URL path.
+    ///
+    /// Either an exact path, in [`axum::extract::Path`] format, or a prefix ending with `*`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Match HTTP method.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    /// Match whether handler requires authentication.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<bool>,
+    /// Match class of response status code.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<StatusClass>,
+    /// Use built-in preset for `Cache-Control` header.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<CachePreset>,
+    /// Custom `Cache-Control` header value.
+    ///
+    /// Overrides [`Self::preset`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<String>,
+    /// Set `Expires` header to a time this far in future.
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub expires: Option<Duration>,
+    /// Additional request headers to list in `Vary` header.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vary: Vec<String>,
+}
+
+impl CacheRule {
+    /// Create new rule using a preset.
+    #[must_use]
+    pub fn preset(preset: CachePreset) -> Self {
+        Self {
+            preset: Some(preset),
+            ..Default::default()
+        }
+    }
+
+    /// Create new rule using custom `Cache-Control` value.
+    #[must_use]
+    pub fn cache_control(value: impl ToString) -> Self {
+        Self {
+            cache_control: Some(value.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Match handler name.
+    #[must_use]
+    pub fn for_handler(mut self, name: impl ToString) -> Self {
+        self.handler = Some(name.to_string());
+        self
+    }
+
+    /// Match handler URL path.
+    #[must_use]
+    pub fn for_path(mut self, path: impl ToString) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    /// Match HTTP method.
+    #[must_use]
+    pub fn for_method(mut self, method: &Method) -> Self {
+        self.method = Some(method.to_string());
+        self
+    }
+
+    /// Match whether handler requires authentication.
+    #[must_use]
+    pub fn for_auth(mut self, auth: bool) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Match class of response status code.
+    #[must_use]
+    pub fn for_status(mut self, status: StatusClass) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Set `Expires` header offset.
+    #[must_use]
+    pub fn with_expires(mut self, expires: Duration) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// Add request header to list in `Vary` header.
+    #[must_use]
+    pub fn with_vary(mut self, header: impl ToString) -> Self {
+        self.vary.push(header.to_string());
+        self
+    }
+
+    /// Effective `Cache-Control` header value.
+    fn cache_control_value(&self) -> Option<&str> {
+        self.cache_control
+            .as_deref()
+            .or(self.preset.map(CachePreset::cache_control))
+    }
+
+    /// Check whether static handler properties match this rule.
+    fn matches_handler(&self, handler: &HandlerSemantics<'_>) -> bool {
+        self.handler.as_ref().map_or(true, |h| h == handler.name)
+            && self
+                .path
+                .as_ref()
+                .map_or(true, |p| match p.strip_suffix('*') {
+                    Some(prefix) => handler.path.starts_with(prefix),
+                    None => p == handler.path,
+                })
+            && self
+                .method
+                .as_ref()
+                .map_or(true, |m| m.eq_ignore_ascii_case(handler.method.as_str()))
+            && self.auth.map_or(true, |a| a == handler.auth)
+    }
+}
+
+/// Handler properties used to select caching policy rules.
+#[derive(Clone, Debug)]
+pub(crate) struct HandlerSemantics<'a> {
+    /// Handler name.
+    pub(crate) name: &'a str,
+    /// Handler URL path.
+    pub(crate) path: &'a str,
+    /// HTTP method.
+    pub(crate) method: &'a Method,
+    /// Handler requires authentication.
+    pub(crate) auth: bool,
+    /// Request headers used by authentication.
+    pub(crate) auth_headers: &'a [String],
+}
+
+/// Caching headers policy configuration.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct CachePolicyConfig {
+    /// Ordered list of rules. First matching rule is applied.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rules: Vec<CacheRule>,
+    /// Export selected policy as `x-uxum-cache-policy` operation extension in OpenAPI
+    /// specification.
+    #[serde(default)]
+    spec_extension: bool,
+}
+
+impl CachePolicyConfig {
+    /// Add rule to the end of rule list.
+    #[must_use]
+    pub fn with_rule(mut self, rule: CacheRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Enable or disable exporting policy in OpenAPI specification.
+    #[must_use]
+    pub fn with_spec_extension(mut self, enable: bool) -> Self {
+        self.spec_extension = enable;
+        self
+    }
+
+    /// Whether policy is exported in OpenAPI specification.
+    #[must_use]
+    pub fn has_spec_extension(&self) -> bool {
+        self.spec_extension
+    }
+
+    /// Rules applicable to a handler, in order.
+    fn handler_rules<'a>(
+        &'a self,
+        handler: &'a HandlerSemantics<'_>,
+    ) -> impl Iterator<Item = &'a CacheRule> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches_handler(handler))
+    }
+
+    /// Describe policy selected for a handler, for use in OpenAPI specification.
+    #[must_use]
+    pub(crate) fn describe(&self, handler: &HandlerSemantics<'_>) -> serde_json::Value {
+        self.handler_rules(handler)
+            .filter_map(|rule| {
+                let cache_control = rule.cache_control_value()?;
+                let mut obj = serde_json::Map::new();
+                if let Some(status) = rule.status {
+                    obj.insert("status".into(), serde_json::to_value(status).ok()?);
+                }
+                obj.insert("cache_control".into(), cache_control.into());
+                Some(serde_json::Value::Object(obj))
+            })
+            .collect()
+    }
+
+    /// Build caching policy layer for a handler.
+    ///
+    /// Returns [`None`] if no rules apply to the handler.
+    #[must_use]
+    pub(crate) fn make_layer(&self, handler: &HandlerSemantics<'_>) -> Option<CacheLayer> {
+        let mut rules = Vec::new();
+        for rule in self.handler_rules(handler) {
+            let Some(cache_control) = rule.cache_control_value() else {
+                warn!(
+                    handler = handler.name,
+                    "cache rule has neither preset nor cache_control, skipping"
+                );
+                continue;
+            };
+            let mut vary: Vec<&str> = vec![ACCEPT.as_str()];
+            if handler.auth {
+                vary.extend(handler.auth_headers.iter().map(String::as_str));
+            }
+            vary.extend(rule.vary.iter().map(String::as_str));
+            match (
+                HeaderValue::try_from(cache_control),
+                HeaderValue::try_from(merge_vary(None, &vary)),
+            ) {
+                (Ok(cache_control), Ok(vary)) => rules.push(CompiledRule {
+                    status: rule.status,
+                    cache_control,
+                    expires: rule.expires,
+                    vary,
+                }),
+                _ => warn!(
+                    handler = handler.name,
+                    cache_control, "invalid cache rule header, skipping"
+                ),
+            }
+            // Subsequent rules are unreachable after a rule without status matcher.
+            if rule.status.is_none() {
+                break;
+            }
+        }
+        (!rules.is_empty()).then(|| CacheLayer {
+            rules: rules.into(),
+        })
+    }
+}
+
+/// Merge lists of header names for use in `Vary` header, removing duplicates.
+fn merge_vary(existing: Option<&str>, extra: &[&str]) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    for name in existing
+        .into_iter()
+        .chain(extra.iter().copied())
+        .flat_map(|val| val.split(','))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        if !names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+            names.push(name);
+        }
+    }
+    names.join(", ")
+}
+
+/// Caching policy rule, prepared for applying to responses.
+#[derive(Clone, Debug)]
+struct CompiledRule {
+    /// Class of response status code.
+    status: Option<StatusClass>,
+    /// Value of `Cache-Control` header.
+    cache_control: HeaderValue,
+    /// Offset used for `Expires` header.
+    expires: Option<Duration>,
+    /// Value of `Vary` header.
+    vary: HeaderValue,
+}
+
+impl CompiledRule {
+    /// Set caching headers on a response.
+    fn apply<U>(&self, resp: &mut Response<U>) {
+        let headers = resp.headers_mut();
+        headers.insert(CACHE_CONTROL, self.cache_control.clone());
+        if let Some(expires) = self.expires {
+            if let Ok(val) =
+                HeaderValue::try_from(httpdate::fmt_http_date(SystemTime::now() + expires))
+            {
+                headers.insert(EXPIRES, val);
+            }
+        }
+        let vary = match headers.get(VARY).and_then(|val| val.to_str().ok()) {
+            Some(existing) => self
+                .vary
+                .to_str()
+                .ok()
+                .and_then(|own| HeaderValue::try_from(merge_vary(Some(existing), &[own])).ok())
+                .unwrap_or_else(|| self.vary.clone()),
+            None => self.vary.clone(),
+        };
+        headers.insert(VARY, vary);
+    }
+}
+
+/// Caching policy [`tower`] layer.
+#[derive(Clone, Debug)]
+pub(crate) struct CacheLayer {
+    /// Applicable rules, in order.
+    rules: Arc<[CompiledRule]>,
+}
+
+impl<S> Layer<S> for CacheLayer {
+    type Service = CachePolicy<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CachePolicy {
+            inner,
+            rules: self.rules.clone(),
+        }
+    }
+}
+
+/// Caching policy [`tower`] service.
+#[derive(Clone, Debug)]
+pub(crate) struct CachePolicy<S> {
+    /// Inner service.
+    inner: S,
+    /// Applicable rules, in order.
+    rules: Arc<[CompiledRule]>,
+}
+
+impl<S, T> Service<Request<T>> for CachePolicy<S>
+where
+    S: Service<Request<T>, Response = Response<Body>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<T>) -> Self::Future {
+        let future = self.inner.call(req);
+        let rules = self.rules.clone();
+        Box::pin(async move {
+            // Errors are converted here, so that policy applies to error responses too.
+            let result: Result<_, BoxError> = future.await.map_err(Into::into);
+            let mut resp = match result {
+                Ok(resp) => resp,
+                Err(err) => crate::builder::app::error_handler(err).await,
+            };
+            // Explicitly set Cache-Control always wins.
+            if resp.headers().contains_key(CACHE_CONTROL) {
+                return Ok(resp);
+            }
+            if let Some(rule) = rules
+                .iter()
+                .find(|rule| rule.status.map_or(true, |st| st.matches(resp.status())))
+            {
+                rule.apply(&mut resp);
+            }
+            Ok(resp)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+
+    const AUTH_HEADERS: &[String] = &[];
+
+    fn semantics(auth: bool, auth_headers: &[String]) -> HandlerSemantics<'_> {
+        HandlerSemantics {
+            name: "items",
+            path: "/api/items",
+            method: &Method::GET,
+            auth,
+            auth_headers,
+        }
+    }
+
+    async fn call(
+        layer: &CacheLayer,
+        status: StatusCode,
+        headers: &[(&'static str, &'static str)],
+    ) -> Response<Body> {
+        let headers = headers.to_vec();
+        let svc = layer.clone().layer(service_fn(move |_| {
+            let mut resp = Response::new(Body::empty());
+            *resp.status_mut() = status;
+            for (name, value) in &headers {
+                resp.headers_mut()
+                    .insert(*name, HeaderValue::from_static(value));
+            }
+            async move { Ok::<_, Infallible>(resp) }
+        }));
+        svc.oneshot(Request::new(Body::empty())).await.unwrap()
+    }
+
+    fn config() -> CachePolicyConfig {
+        CachePolicyConfig::default()
+            .with_rule(CacheRule::preset(CachePreset::ApiNoStore).for_status(StatusClass::Error))
+            .with_rule(CacheRule::preset(CachePreset::ApiNoStore).for_auth(true))
+            .with_rule(CacheRule::preset(CachePreset::PrivateShort).for_path("/api/*"))
+            .with_rule(CacheRule::preset(CachePreset::PublicStatic))
+    }
+
+    /// First matching rule wins.
+    #[tokio::test]
+    async fn rule_ordering() {
+        let layer = config()
+            .make_layer(&semantics(false, AUTH_HEADERS))
+            .unwrap();
+        let resp = call(&layer, StatusCode::OK, &[]).await;
+        assert_eq!(resp.headers()[CACHE_CONTROL], "private, max-age=60");
+        let resp = call(&layer, StatusCode::NOT_FOUND, &[]).await;
+        assert_eq!(resp.headers()[CACHE_CONTROL], "no-store");
+
+        let layer = config().make_layer(&semantics(true, AUTH_HEADERS)).unwrap();
+        let resp = call(&layer, StatusCode::OK, &[]).await;
+        assert_eq!(resp.headers()[CACHE_CONTROL], "no-store");
+
+        let layer = CachePolicyConfig::default()
+            .with_rule(CacheRule::preset(CachePreset::ApiNoStore).for_handler("other"))
+            .make_layer(&semantics(false, AUTH_HEADERS));
+        assert!(layer.is_none());
+    }
+
+    /// Explicitly set Cache-Control is left intact.
+    #[tokio::test]
+    async fn explicit_wins() {
+        let layer = CachePolicyConfig::default()
+            .with_rule(
+                CacheRule::preset(CachePreset::PublicStatic).with_expires(Duration::from_secs(60)),
+            )
+            .make_layer(&semantics(false, AUTH_HEADERS))
+            .unwrap();
+        let resp = call(&layer, StatusCode::OK, &[("cache-control", "max-age=5")]).await;
+        assert_eq!(resp.headers()[CACHE_CONTROL], "max-age=5");
+        assert!(!resp.headers().contains_key(EXPIRES));
+        assert!(!resp.headers().contains_key(VARY));
+
+        let resp = call(&layer, StatusCode::OK, &[]).await;
+        assert_eq!(resp.headers()[CACHE_CONTROL], "public, max-age=86400");
+        assert!(resp.headers().contains_key(EXPIRES));
+    }
+
+    /// Vary includes content negotiation, auth and rule headers, merged with existing ones.
+    #[tokio::test]
+    async fn vary_composition() {
+        let auth_headers = ["X-API-Name".to_owned(), "X-API-Key".to_owned()];
+        let layer = CachePolicyConfig::default()
+            .with_rule(
+                CacheRule::preset(CachePreset::PrivateShort)
+                    .with_vary("accept-language")
+                    .with_vary("x-api-key"),
+            )
+            .make_layer(&semantics(true, &auth_headers))
+            .unwrap();
+        let resp = call(&layer, StatusCode::OK, &[("vary", "origin, Accept")]).await;
+        assert_eq!(
+            resp.headers()[VARY],
+            "origin, Accept, X-API-Name, X-API-Key, accept-language"
+        );
+
+        let layer = CachePolicyConfig::default()
+            .with_rule(CacheRule::preset(CachePreset::PrivateShort))
+            .make_layer(&semantics(false, &auth_headers))
+            .unwrap();
+        let resp = call(&layer, StatusCode::OK, &[]).await;
+        assert_eq!(resp.headers()[VARY], ACCEPT.as_str());
+    }
+}
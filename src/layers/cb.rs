@@ -0,0 +1,446 @@
+//! Per-handler circuit breaker [`tower`] layer.
+//!
+//! Handler errors and 5xx responses count as failures. When failure rate exceeds configured
+//! threshold, requests are rejected with 503 Service Unavailable until the breaker lets probe
+//! requests through again.
+//!
+//! [`recloser`] does not expose breaker state, so state transitions reported in metrics are
+//! inferred from request outcomes: the first rejection means the breaker opened, and requests
+//! passing through an open breaker are half-open probes.
+
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    http::{Request, Response, StatusCode},
+    response::IntoResponse,
+};
+use futures::future::BoxFuture;
+use opentelemetry::KeyValue;
+use parking_lot::Mutex;
+use recloser::{AsyncRecloser, Recloser};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tower::{BoxError, Layer, Service};
+use tracing::{info, warn};
+
+use crate::{
+    errors::{codes, ErrorCode},
+    metrics::CircuitBreakerMetrics,
+    retry::{RetryAdvice, RetryAdviceConfig, RetrySource},
+};
+
+/// Handler circuit breaker configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct HandlerCircuitBreakerConfig {
+    /// Error rate threshold for tripping the breaker.
+    ///
+    /// Default is 0.5.
+    #[serde(default = "HandlerCircuitBreakerConfig::default_error_rate")]
+    pub error_rate: f32,
+    /// Size of CB history buffer in closed state. CB will try calculating the error rate after it
+    /// has amassed this many responses.
+    ///
+    /// Default is 100.
+    #[serde(default = "HandlerCircuitBreakerConfig::default_closed_len")]
+    pub closed_len: usize,
+    /// Size of CB history buffer in half-open state. CB will try calculating the error rate after
+    /// it has amassed this many responses.
+    ///
+    /// Default is 10.
+    #[serde(default = "HandlerCircuitBreakerConfig::default_half_open_len")]
+    pub half_open_len: usize,
+    /// Time that CB stays open after being tripped.
+    ///
+    /// Default is 30 seconds.
+    #[serde(
+        default = "HandlerCircuitBreakerConfig::default_open_wait",
+        with = "humantime_serde"
+    )]
+    pub open_wait: Duration,
+}
+
+impl Default for HandlerCircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            error_rate: Self::default_error_rate(),
+            closed_len: Self::default_closed_len(),
+            half_open_len: Self::default_half_open_len(),
+            open_wait: Self::default_open_wait(),
+        }
+    }
+}
+
+impl HandlerCircuitBreakerConfig {
+    /// Default value for [`Self::error_rate`].
+    #[must_use]
+    #[inline]
+    fn default_error_rate() -> f32 {
+        0.5
+    }
+
+    /// Default value for [`Self::closed_len`].
+    #[must_use]
+    #[inline]
+    fn default_closed_len() -> usize {
+        100
+    }
+
+    /// Default value for [`Self::half_open_len`].
+    #[must_use]
+    #[inline]
+    fn default_half_open_len() -> usize {
+        10
+    }
+
+    /// Default value for [`Self::open_wait`].
+    #[must_use]
+    #[inline]
+    fn default_open_wait() -> Duration {
+        Duration::from_secs(30)
+    }
+
+    /// Set error rate threshold for tripping the breaker.
+    #[must_use]
+    pub fn with_error_rate(mut self, error_rate: f32) -> Self {
+        self.error_rate = error_rate;
+        self
+    }
+
+    /// Set size of history buffer in closed state.
+    #[must_use]
+    pub fn with_closed_len(mut self, closed_len: usize) -> Self {
+        self.closed_len = closed_len;
+        self
+    }
+
+    /// Set size of history buffer in half-open state.
+    #[must_use]
+    pub fn with_half_open_len(mut self, half_open_len: usize) -> Self {
+        self.half_open_len = half_open_len;
+        self
+    }
+
+    /// Set time that breaker stays open after being tripped.
+    #[must_use]
+    pub fn with_open_wait(mut self, open_wait: Duration) -> Self {
+        self.open_wait = open_wait;
+        self
+    }
+
+    /// Create layer for use in [`tower`] services.
+    #[must_use]
+    pub(crate) fn make_layer(
+        &self,
+        handler: &'static str,
+        metrics: Option<CircuitBreakerMetrics>,
+        retry: &RetryAdviceConfig,
+    ) -> CircuitBreakerLayer {
+        CircuitBreakerLayer {
+            state: Arc::new(BreakerState {
+                handler,
+                breaker: AsyncRecloser::from(
+                    Recloser::custom()
+                        .error_rate(self.error_rate)
+                        .closed_len(self.closed_len)
+                        .half_open_len(self.half_open_len)
+                        .open_wait(self.open_wait)
+                        .build(),
+                ),
+                half_open_len: self.half_open_len,
+                open_wait: self.open_wait,
+                observed: Mutex::new(Observed::Closed),
+                opened_at: Mutex::new(None),
+                metrics,
+                retry: retry.clone(),
+            }),
+        }
+    }
+}
+
+/// Error type returned by circuit breaker layer.
+#[derive(Clone, Debug, Error)]
+#[non_exhaustive]
+pub enum CircuitBreakerError {
+    /// Circuit breaker is open.
+    #[error("Handler is temporarily unavailable: circuit breaker is open")]
+    Open {
+        /// Advice used for `Retry-After` header.
+        advice: RetryAdvice,
+    },
+}
+
+impl CircuitBreakerError {
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Open { .. } => codes::CIRCUIT_OPEN,
+        }
+    }
+}
+
+impl IntoResponse for CircuitBreakerError {
+    fn into_response(self) -> Response<Body> {
+        let code = self.code();
+        let problem = code
+            .problem(StatusCode::SERVICE_UNAVAILABLE)
+            .with_type("tag:uxum.github.io,2024:circuit-breaker")
+            .with_title(self.to_string());
+        match self {
+            Self::Open { advice } => (code, advice.problem_response(problem)).into_response(),
+        }
+    }
+}
+
+/// Breaker state, as inferred from request outcomes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Observed {
+    /// Requests are allowed.
+    Closed,
+    /// Requests are rejected.
+    Open,
+    /// Probe requests are allowed.
+    HalfOpen {
+        /// Number of probe requests seen so far.
+        probes: usize,
+    },
+}
+
+impl Observed {
+    /// Metric label value.
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen { .. } => "half_open",
+        }
+    }
+}
+
+/// Shared state of circuit breaker for a single handler.
+struct BreakerState {
+    /// Handler name.
+    handler: &'static str,
+    /// Circuit breaker.
+    breaker: AsyncRecloser,
+    /// Size of history buffer in half-open state.
+    half_open_len: usize,
+    /// Time that breaker stays open after being tripped.
+    open_wait: Duration,
+    /// Inferred breaker state.
+    observed: Mutex<Observed>,
+    /// Time when breaker was last observed to open.
+    opened_at: Mutex<Option<Instant>>,
+    /// Circuit breaker metrics.
+    metrics: Option<CircuitBreakerMetrics>,
+    /// Retry advice configuration.
+    retry: RetryAdviceConfig,
+}
+
+impl BreakerState {
+    /// Record inferred state transition.
+    fn transition(&self, observed: &mut Observed, to: Observed) {
+        let from = *observed;
+        *observed = to;
+        if from.as_str() == to.as_str() {
+            return;
+        }
+        match to {
+            Observed::Open => warn!(handler = self.handler, "circuit breaker opened"),
+            _ => info!(
+                handler = self.handler,
+                from = from.as_str(),
+                to = to.as_str(),
+                "circuit breaker state changed"
+            ),
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.transitions.add(
+                1,
+                &[
+                    KeyValue::new("uxum.handler", self.handler),
+                    KeyValue::new("from", from.as_str()),
+                    KeyValue::new("to", to.as_str()),
+                ],
+            );
+        }
+    }
+
+    /// Record request passing through the breaker.
+    fn passed(&self) {
+        let mut observed = self.observed.lock();
+        let probes = match *observed {
+            Observed::Closed => return,
+            Observed::Open => 1,
+            // Breaker evaluates error rate once history buffer is full, so it has made its
+            // decision after this many probes.
+            Observed::HalfOpen { probes } if probes > self.half_open_len => {
+                self.transition(&mut observed, Observed::Closed);
+                return;
+            }
+            Observed::HalfOpen { probes } => probes + 1,
+        };
+        self.transition(&mut observed, Observed::HalfOpen { probes });
+        if let Some(metrics) = &self.metrics {
+            metrics
+                .probes
+                .add(1, &[KeyValue::new("uxum.handler", self.handler)]);
+        }
+    }
+
+    /// Record rejected request, and build error.
+    fn rejected(&self) -> CircuitBreakerError {
+        let mut observed = self.observed.lock();
+        let mut opened_at = self.opened_at.lock();
+        if *observed != Observed::Open {
+            self.transition(&mut observed, Observed::Open);
+            *opened_at = Some(Instant::now());
+        }
+        let remaining = opened_at.map(|at| self.open_wait.saturating_sub(at.elapsed()));
+        CircuitBreakerError::Open {
+            advice: self.retry.advise(RetrySource::CircuitBreaker, remaining),
+        }
+    }
+}
+
+/// Request outcome counted as a failure.
+enum Failure {
+    /// Server error response.
+    Response(Response<Body>),
+    /// Error returned by inner service.
+    Error(BoxError),
+}
+
+/// Circuit breaker [`tower`] layer.
+#[derive(Clone)]
+pub(crate) struct CircuitBreakerLayer {
+    /// Shared state.
+    state: Arc<BreakerState>,
+}
+
+impl<S> Layer<S> for CircuitBreakerLayer {
+    type Service = CircuitBreaker<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CircuitBreaker {
+            inner,
+            state: self.state.clone(),
+        }
+    }
+}
+
+/// Circuit breaker [`tower`] service.
+#[derive(Clone)]
+pub(crate) struct CircuitBreaker<S> {
+    /// Inner service.
+    inner: S,
+    /// Shared state.
+    state: Arc<BreakerState>,
+}
+
+impl<S, T> Service<Request<T>> for CircuitBreaker<S>
+where
+    S: Service<Request<T>, Response = Response<Body>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<T>) -> Self::Future {
+        let future = self.inner.call(req);
+        let state = self.state.clone();
+        Box::pin(async move {
+            let outcome = state
+                .breaker
+                .call(async move {
+                    match future.await {
+                        Ok(resp) if resp.status().is_server_error() => Err(Failure::Response(resp)),
+                        Ok(resp) => Ok(resp),
+                        Err(err) => Err(Failure::Error(err.into())),
+                    }
+                })
+                .await;
+            match outcome {
+                Ok(resp) | Err(recloser::Error::Inner(Failure::Response(resp))) => {
+                    state.passed();
+                    Ok(resp)
+                }
+                Err(recloser::Error::Inner(Failure::Error(err))) => {
+                    state.passed();
+                    Err(err)
+                }
+                Err(recloser::Error::Rejected) => Err(state.rejected().into()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::builder::app::error_handler;
+
+    #[tokio::test]
+    async fn opens_and_recovers() {
+        let failing = Arc::new(AtomicBool::new(true));
+        let layer = HandlerCircuitBreakerConfig::default()
+            .with_closed_len(4)
+            .with_half_open_len(2)
+            .with_open_wait(Duration::from_millis(50))
+            .make_layer("flaky", None, &RetryAdviceConfig::default());
+        let state = layer.state.clone();
+        let flag = failing.clone();
+        let svc = layer.layer(tower::service_fn(move |_req: Request<Body>| {
+            let status = match flag.load(Ordering::Acquire) {
+                true => StatusCode::INTERNAL_SERVER_ERROR,
+                false => StatusCode::OK,
+            };
+            async move { Ok::<_, BoxError>(status.into_response()) }
+        }));
+        let call = || {
+            let svc = svc.clone();
+            async move {
+                match svc.oneshot(Request::new(Body::empty())).await {
+                    Ok(resp) => resp,
+                    Err(err) => error_handler(err).await,
+                }
+            }
+        };
+
+        // Failures are passed through until breaker trips.
+        for _ in 0..5 {
+            assert_eq!(call().await.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        let resp = call().await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.extensions().get(), Some(&codes::CIRCUIT_OPEN));
+        assert!(resp.headers().contains_key("retry-after"));
+        assert_eq!(*state.observed.lock(), Observed::Open);
+
+        // Handler recovers, breaker lets probes through after open wait, then closes.
+        failing.store(false, Ordering::Release);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        for probes in 1..=3 {
+            assert_eq!(call().await.status(), StatusCode::OK);
+            assert_eq!(*state.observed.lock(), Observed::HalfOpen { probes });
+        }
+        assert_eq!(call().await.status(), StatusCode::OK);
+        assert_eq!(*state.observed.lock(), Observed::Closed);
+    }
+}
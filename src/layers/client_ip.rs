@@ -0,0 +1,240 @@
+//! Client IP address resolution, shared by all layers which need original client address.
+
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{extract::ConnectInfo, http::Request};
+use forwarded_header_value::{ForwardedHeaderValue, Identifier};
+use http::{header::FORWARDED, HeaderMap};
+use serde::{Deserialize, Serialize};
+
+use crate::layers::ip_filter::IpNetwork;
+
+const X_REAL_IP: &str = "x-real-ip";
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Client IP address resolution configuration.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct ClientIpConfig {
+    /// Trusted reverse proxies.
+    ///
+    /// Client address is taken from `X-Forwarded-For`, `Forwarded` or `X-Real-IP` headers only
+    /// if the request came from one of these networks. Forwarded scheme and host, as well as
+    /// incoming request IDs, are trusted based on the same list.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_proxies: Vec<IpNetwork>,
+}
+
+impl ClientIpConfig {
+    /// Add trusted reverse proxy network.
+    #[must_use]
+    pub fn with_trusted_proxy(mut self, net: IpNetwork) -> Self {
+        self.trusted_proxies.push(net);
+        self
+    }
+
+    /// Resolve client IP address, and store it as a [`ClientIp`] request extension.
+    ///
+    /// Requests without a peer address, and internally dispatched requests which already carry
+    /// client address of the outer request, are left as is.
+    pub(crate) fn resolve_incoming<T>(&self, mut req: Request<T>) -> Request<T> {
+        if req.extensions().get::<ClientIp>().is_some() {
+            return req;
+        }
+        if let Some(client_ip) = self.resolve(&req) {
+            req.extensions_mut().insert(client_ip);
+        }
+        req
+    }
+
+    /// Resolve client IP address, only trusting forwarded addresses from trusted proxies.
+    ///
+    /// If peer address belongs to one of trusted proxies, walks forwarded hops from right to
+    /// left, and returns the first one which is not a trusted proxy itself.
+    #[must_use]
+    pub(crate) fn resolve<T>(&self, req: &Request<T>) -> Option<ClientIp> {
+        let peer = maybe_connect_info(req)?;
+        let mut client = ClientIp {
+            ip: peer,
+            proxied: self.is_trusted(peer),
+        };
+        if !client.proxied {
+            return Some(client);
+        }
+        for hop in forwarded_hops(req.headers()).into_iter().rev() {
+            match hop {
+                Some(ip) => {
+                    client.ip = ip;
+                    if !self.is_trusted(ip) {
+                        break;
+                    }
+                }
+                // Malformed hop: can't trust anything beyond it.
+                None => break,
+            }
+        }
+        Some(client)
+    }
+
+    /// Check whether address belongs to one of trusted proxies.
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|net| net.contains(ip))
+    }
+}
+
+/// Client IP address, resolved once per request.
+///
+/// Available as a request extension for all requests with a known peer address.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ClientIp {
+    /// Original client address.
+    ip: IpAddr,
+    /// Whether request came from one of trusted proxies.
+    proxied: bool,
+}
+
+impl ClientIp {
+    /// Original client address.
+    #[must_use]
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    /// Whether request came from one of trusted proxies, so forwarded headers can be used.
+    #[must_use]
+    pub fn is_proxied(&self) -> bool {
+        self.proxied
+    }
+}
+
+/// Get forwarded client address chain, from client to last proxy.
+///
+/// `X-Forwarded-For` takes precedence over `Forwarded`, which takes precedence over `X-Real-IP`.
+/// Malformed entries are returned as [`None`].
+fn forwarded_hops(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let values = |name| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|hv| hv.to_str().ok())
+    };
+    let hops: Vec<_> = values(X_FORWARDED_FOR)
+        .flat_map(|hstr| hstr.split(','))
+        .map(|hop| hop.trim().parse().ok())
+        .collect();
+    if !hops.is_empty() {
+        return hops;
+    }
+    let hops: Vec<_> = values(FORWARDED.as_str())
+        .flat_map(|hstr| match ForwardedHeaderValue::from_forwarded(hstr) {
+            Ok(fhv) => fhv
+                .iter()
+                .map(|stanza| match &stanza.forwarded_for {
+                    Some(Identifier::SocketAddr(addr)) => Some(addr.ip()),
+                    Some(Identifier::IpAddr(ip)) => Some(*ip),
+                    _ => None,
+                })
+                .collect(),
+            Err(_) => vec![None],
+        })
+        .collect();
+    if !hops.is_empty() {
+        return hops;
+    }
+    values(X_REAL_IP)
+        .map(|hstr| hstr.trim().parse().ok())
+        .collect()
+}
+
+/// Looks in `ConnectInfo` extension.
+pub(crate) fn maybe_connect_info<T>(req: &Request<T>) -> Option<IpAddr> {
+    req.extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::util::{KeyExtractor, SmartIpKeyExtractor};
+
+    fn resolve(peer: &str, headers: &[(&'static str, &str)]) -> ClientIp {
+        let cfg = ClientIpConfig::default().with_trusted_proxy("192.168.0.0/24".parse().unwrap());
+        let mut req = Request::new(());
+        for (name, value) in headers {
+            req.headers_mut().append(*name, value.parse().unwrap());
+        }
+        let peer: IpAddr = peer.parse().unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((peer, 12345))));
+        cfg.resolve(&req).unwrap()
+    }
+
+    /// Forwarded addresses are used only if peer is a trusted proxy.
+    #[test]
+    fn forwarded_headers() {
+        let client = |peer, headers| resolve(peer, headers).ip().to_string();
+        // Untrusted peer tries to pass another address.
+        assert_eq!(
+            client("203.0.113.5", &[("x-forwarded-for", "10.0.0.1")]),
+            "203.0.113.5"
+        );
+        assert!(!resolve("203.0.113.5", &[]).is_proxied());
+        // Trusted proxy passes client address.
+        assert_eq!(
+            client("192.168.0.1", &[("x-forwarded-for", "10.0.0.1")]),
+            "10.0.0.1"
+        );
+        assert!(resolve("192.168.0.1", &[]).is_proxied());
+        // Client prepends spoofed address, trusted proxy appends real one.
+        assert_eq!(
+            client(
+                "192.168.0.1",
+                &[("x-forwarded-for", "10.0.0.1, 203.0.113.5")]
+            ),
+            "203.0.113.5"
+        );
+        // Chain of trusted proxies.
+        assert_eq!(
+            client(
+                "192.168.0.1",
+                &[("x-forwarded-for", "10.0.0.1, 192.168.0.2")]
+            ),
+            "10.0.0.1"
+        );
+        // Malformed hop stops the walk.
+        assert_eq!(
+            client("192.168.0.1", &[("x-forwarded-for", "10.0.0.1, junk")]),
+            "192.168.0.1"
+        );
+        // Other forwarding headers.
+        assert_eq!(
+            client("192.168.0.1", &[("forwarded", "for=10.0.0.2;proto=https")]),
+            "10.0.0.2"
+        );
+        assert_eq!(
+            client("192.168.0.1", &[("x-real-ip", "10.0.0.3")]),
+            "10.0.0.3"
+        );
+    }
+
+    /// Rate limiting key uses resolved address, so spoofed headers are ignored.
+    #[test]
+    fn smart_ip_key() {
+        let cfg = ClientIpConfig::default().with_trusted_proxy("192.168.0.0/24".parse().unwrap());
+        let key = |peer: [u8; 4], xff: &str| {
+            let mut req = Request::new(());
+            req.headers_mut()
+                .insert(X_FORWARDED_FOR, xff.parse().unwrap());
+            req.extensions_mut()
+                .insert(ConnectInfo(SocketAddr::from((peer, 12345))));
+            SmartIpKeyExtractor
+                .extract(&cfg.resolve_incoming(req))
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(key([203, 0, 113, 5], "10.0.0.1"), "203.0.113.5");
+        assert_eq!(key([192, 168, 0, 1], "10.0.0.1"), "10.0.0.1");
+        assert!(SmartIpKeyExtractor.extract(&Request::new(())).is_err());
+    }
+}
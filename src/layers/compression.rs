@@ -0,0 +1,257 @@
+//! Response compression configuration and predicate.
+
+use std::sync::Arc;
+
+use axum::http::{header, Response};
+use bytesize::ByteSize;
+use http_body::Body as HttpBody;
+use serde::{Deserialize, Serialize};
+use tower_http::compression::{predicate::Predicate, CompressionLayer};
+
+/// Response compression algorithm.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum CompressionAlgorithm {
+    /// `gzip` content encoding.
+    Gzip,
+    /// `deflate` content encoding.
+    Deflate,
+    /// `br` (Brotli) content encoding.
+    Br,
+}
+
+/// Response compression configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct CompressionConfig {
+    /// Compress responses.
+    ///
+    /// Disabled by default.
+    #[serde(default)]
+    enabled: bool,
+    /// Allowed algorithms, chosen based on client's `Accept-Encoding`.
+    #[serde(default = "CompressionConfig::default_algorithms")]
+    algorithms: Vec<CompressionAlgorithm>,
+    /// Do not compress responses smaller than this.
+    ///
+    /// Responses of unknown size are always compressed.
+    #[serde(default = "CompressionConfig::default_min_size")]
+    min_size: ByteSize,
+    /// Content type prefixes that are never compressed.
+    #[serde(default = "CompressionConfig::default_exclude_content_types")]
+    exclude_content_types: Vec<String>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            algorithms: Self::default_algorithms(),
+            min_size: Self::default_min_size(),
+            exclude_content_types: Self::default_exclude_content_types(),
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Default value for [`Self::algorithms`].
+    #[must_use]
+    #[inline]
+    fn default_algorithms() -> Vec<CompressionAlgorithm> {
+        vec![
+            CompressionAlgorithm::Gzip,
+            CompressionAlgorithm::Deflate,
+            CompressionAlgorithm::Br,
+        ]
+    }
+
+    /// Default value for [`Self::min_size`].
+    #[must_use]
+    #[inline]
+    fn default_min_size() -> ByteSize {
+        ByteSize::b(256)
+    }
+
+    /// Default value for [`Self::exclude_content_types`].
+    #[must_use]
+    #[inline]
+    fn default_exclude_content_types() -> Vec<String> {
+        ["application/grpc", "image/", "video/", "text/event-stream"]
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect()
+    }
+
+    /// Enable or disable compression.
+    #[must_use]
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// Set allowed algorithms.
+    #[must_use]
+    pub fn with_algorithms(
+        mut self,
+        algorithms: impl IntoIterator<Item = CompressionAlgorithm>,
+    ) -> Self {
+        self.algorithms = algorithms.into_iter().collect();
+        self
+    }
+
+    /// Set minimum size of compressed responses.
+    #[must_use]
+    pub fn with_min_size(mut self, min_size: ByteSize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Set content type prefixes that are never compressed.
+    #[must_use]
+    pub fn with_exclude_content_types(
+        mut self,
+        content_types: impl IntoIterator<Item = impl ToString>,
+    ) -> Self {
+        self.exclude_content_types = content_types.into_iter().map(|ct| ct.to_string()).collect();
+        self
+    }
+
+    /// Build compression layer.
+    ///
+    /// Returns [`None`] if disabled, or if no algorithms are allowed.
+    #[must_use]
+    pub(crate) fn make_layer(&self) -> Option<CompressionLayer<CompressionPredicate>> {
+        if !self.enabled || self.algorithms.is_empty() {
+            return None;
+        }
+        let has = |alg| self.algorithms.contains(&alg);
+        Some(
+            CompressionLayer::new()
+                .gzip(has(CompressionAlgorithm::Gzip))
+                .deflate(has(CompressionAlgorithm::Deflate))
+                .br(has(CompressionAlgorithm::Br))
+                .compress_when(CompressionPredicate {
+                    min_size: self.min_size.as_u64(),
+                    exclude_content_types: self.exclude_content_types.clone().into(),
+                }),
+        )
+    }
+}
+
+/// Response extension used to disable compression.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct NoCompression;
+
+/// Decides which responses get compressed.
+#[derive(Clone, Debug)]
+pub(crate) struct CompressionPredicate {
+    /// Minimum size of compressed responses, in bytes.
+    min_size: u64,
+    /// Content type prefixes that are never compressed.
+    exclude_content_types: Arc<[String]>,
+}
+
+impl Predicate for CompressionPredicate {
+    fn should_compress<B>(&self, response: &Response<B>) -> bool
+    where
+        B: HttpBody,
+    {
+        if response.extensions().get::<NoCompression>().is_some() {
+            return false;
+        }
+        let size = response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|val| val.to_str().ok()?.parse().ok())
+            .or_else(|| response.body().size_hint().exact());
+        if size.is_some_and(|size| size < self.min_size) {
+            return false;
+        }
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|val| val.to_str().ok())
+            .unwrap_or_default();
+        !self
+            .exclude_content_types
+            .iter()
+            .any(|prefix| content_type.starts_with(prefix.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::util::ResponseExtension;
+
+    const TEXT: &str = "All work and no play makes Jack a dull boy. ";
+
+    fn app(config: &CompressionConfig) -> Router {
+        Router::new()
+            .route("/text", get(|| async { TEXT.repeat(20) }))
+            .route("/short", get(|| async { TEXT }))
+            .route(
+                "/image",
+                get(|| async { ([(header::CONTENT_TYPE, "image/png")], TEXT.repeat(20)) }),
+            )
+            .route(
+                "/opt_out",
+                get(|| async { TEXT.repeat(20) }).layer(ResponseExtension(NoCompression)),
+            )
+            .layer(config.make_layer().unwrap())
+    }
+
+    async fn encoding(rtr: Router, path: &str, accept: &str) -> Option<String> {
+        let resp = rtr
+            .oneshot(
+                Request::get(path)
+                    .header(header::ACCEPT_ENCODING, accept)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        resp.headers()
+            .get(header::CONTENT_ENCODING)
+            .map(|val| val.to_str().unwrap().to_owned())
+    }
+
+    /// Compression is skipped for small, excluded or opted-out responses.
+    #[tokio::test]
+    async fn compression_rules() {
+        let rtr = app(&CompressionConfig::default().with_enabled(true));
+        let enc = |path| encoding(rtr.clone(), path, "gzip");
+        assert_eq!(enc("/text").await.as_deref(), Some("gzip"));
+        assert_eq!(enc("/short").await, None);
+        assert_eq!(enc("/image").await, None);
+        assert_eq!(enc("/opt_out").await, None);
+    }
+
+    /// Only allowed algorithms are used.
+    #[tokio::test]
+    async fn algorithms() {
+        let rtr = app(&CompressionConfig::default()
+            .with_enabled(true)
+            .with_algorithms([CompressionAlgorithm::Br]));
+        assert_eq!(encoding(rtr.clone(), "/text", "gzip").await, None);
+        assert_eq!(
+            encoding(rtr, "/text", "gzip, br").await.as_deref(),
+            Some("br")
+        );
+    }
+
+    /// Disabled configuration produces no layer.
+    #[test]
+    fn disabled() {
+        assert!(CompressionConfig::default().make_layer().is_none());
+        assert!(CompressionConfig::default()
+            .with_enabled(true)
+            .with_algorithms([])
+            .make_layer()
+            .is_none());
+    }
+}
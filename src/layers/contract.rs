@@ -0,0 +1,963 @@
+//! Response contract validation layer.
+//!
+//! Validates JSON responses of handlers against response schemas declared in OpenAPI
+//! specification, catching drift between code and documentation. Intended for contract testing
+//! environments, as every validated response is buffered in memory.
+//!
+//! Schemas are generated from handler metadata and compiled once, when the handler service is
+//! built. Responses with non-JSON content types, undeclared status codes, or bodies of unknown or
+//! excessive size are passed through unchecked.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::{
+    body::Body,
+    http::{header::CONTENT_TYPE, Request, Response, StatusCode},
+    response::IntoResponse,
+};
+use futures::future::BoxFuture;
+use http_body::Body as _;
+use okapi::openapi3;
+use opentelemetry::{metrics::Counter, KeyValue};
+use regex::Regex;
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Schema, SchemaObject, SingleOrVec},
+    Map,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tower::{BoxError, Layer, Service};
+use tracing::{error, warn};
+
+use crate::{
+    errors::codes,
+    memory,
+    validate::{validate_pointer, ValidationError, ValidationErrors},
+};
+
+/// Problem details type URI of contract violation responses.
+const CONTRACT_PROBLEM_TYPE: &str = "tag:uxum.github.io,2024:contract";
+
+/// Name of the layer, used in memory budget accounting.
+const LAYER_NAME: &str = "response_validation";
+
+/// Maximum number of violations reported per response.
+const MAX_VIOLATIONS: usize = 32;
+
+/// Maximum nesting of schema references, guards against reference cycles.
+const MAX_DEPTH: usize = 128;
+
+/// Response validation mode.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum ResponseValidationMode {
+    /// Do not validate responses.
+    #[default]
+    Off,
+    /// Log violations and count them in metrics, pass responses through unchanged.
+    Warn,
+    /// Replace violating responses with `500 Internal Server Error` problem details.
+    Enforce,
+}
+
+/// Configuration for response contract validation.
+///
+/// Not intended for production use.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct ResponseValidationConfig {
+    /// Validation mode for all handlers.
+    ///
+    /// Can be overridden per handler with [`crate::HandlerConfig::response_validation`].
+    #[serde(default)]
+    pub mode: ResponseValidationMode,
+    /// Maximum body size of a validated response, in bytes.
+    ///
+    /// Larger responses are skipped. Default is 1 MiB.
+    #[serde(default = "ResponseValidationConfig::default_max_body_size")]
+    pub max_body_size: usize,
+}
+
+impl Default for ResponseValidationConfig {
+    fn default() -> Self {
+        Self {
+            mode: ResponseValidationMode::default(),
+            max_body_size: Self::default_max_body_size(),
+        }
+    }
+}
+
+impl ResponseValidationConfig {
+    /// Default value for [`Self::max_body_size`].
+    #[must_use]
+    #[inline]
+    fn default_max_body_size() -> usize {
+        1024 * 1024
+    }
+
+    /// Set validation mode for all handlers.
+    #[must_use]
+    pub fn with_mode(mut self, mode: ResponseValidationMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set maximum body size of a validated response.
+    #[must_use]
+    pub fn with_max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Create layer for a handler, if validation is enabled for it.
+    ///
+    /// Response schemas are only compiled if validation is enabled.
+    pub(crate) fn make_layer(
+        &self,
+        handler: &'static str,
+        mode: Option<ResponseValidationMode>,
+        schemas: impl FnOnce() -> ResponseSchemas,
+        metrics: Option<(Counter<u64>, Counter<u64>)>,
+    ) -> Option<ResponseValidationLayer> {
+        let mode = mode.unwrap_or(self.mode);
+        if mode == ResponseValidationMode::Off {
+            return None;
+        }
+        warn!(handler, ?mode, "response contract validation enabled");
+        let (violations, skipped) = metrics.unzip();
+        Some(ResponseValidationLayer {
+            state: Arc::new(ValidationState {
+                handler,
+                mode,
+                max_body_size: self.max_body_size,
+                schemas: schemas(),
+                violations,
+                skipped,
+            }),
+        })
+    }
+}
+
+/// Response schemas of a handler, compiled for validation.
+pub(crate) struct ResponseSchemas {
+    /// JSON schemas per status code pattern, like `200`, `4XX` or `default`, and media type.
+    responses: Vec<(String, Vec<(String, Schema)>)>,
+    /// Referenced schema definitions.
+    definitions: Map<String, Schema>,
+    /// Prefix of schema references.
+    ref_prefix: String,
+    /// Compiled string patterns.
+    patterns: HashMap<String, Regex>,
+}
+
+impl fmt::Debug for ResponseSchemas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseSchemas")
+            .field(
+                "statuses",
+                &self.responses.iter().map(|(st, _)| st).collect::<Vec<_>>(),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+impl ResponseSchemas {
+    /// Compile response schemas of an operation.
+    ///
+    /// Generator must be the one used to create the operation, as it holds schema definitions.
+    pub(crate) fn compile(spec: &openapi3::Operation, mut gen: SchemaGenerator) -> Self {
+        let responses = spec
+            .responses
+            .responses
+            .iter()
+            .map(|(status, resp)| (status.to_ascii_uppercase(), resp))
+            .chain(
+                spec.responses
+                    .default
+                    .iter()
+                    .map(|resp| ("default".to_owned(), resp)),
+            )
+            .filter_map(|(status, resp)| match resp {
+                openapi3::RefOr::Object(resp) => Some((status, json_schemas(resp))),
+                openapi3::RefOr::Ref(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let ref_prefix = gen.settings().definitions_path.clone();
+        let definitions = gen.take_definitions();
+        let mut patterns = HashMap::new();
+        for schema in responses
+            .iter()
+            .flat_map(|(_, media)| media.iter().map(|(_, schema)| schema))
+            .chain(definitions.values())
+        {
+            collect_patterns(schema, &mut patterns);
+        }
+        Self {
+            responses,
+            definitions,
+            ref_prefix,
+            patterns,
+        }
+    }
+
+    /// Find schema for response status and media type.
+    ///
+    /// Exact status codes take precedence over ranges, which take precedence over `default`.
+    fn lookup(&self, status: StatusCode, media_type: &str) -> Option<&Schema> {
+        let exact = status.as_str();
+        let range = format!("{}XX", exact.chars().next().unwrap_or_default());
+        let media = [exact, &range, "default"].into_iter().find_map(|key| {
+            self.responses
+                .iter()
+                .find(|(status, _)| status == key)
+                .map(|(_, media)| media)
+        })?;
+        media
+            .iter()
+            .find(|(mt, _)| mt == media_type)
+            .or_else(|| media.first())
+            .map(|(_, schema)| schema)
+    }
+
+    /// Validate value against schema, returning all found violations.
+    fn validate(&self, schema: &Schema, value: &Value) -> ValidationErrors {
+        let mut errors = ValidationErrors::new();
+        self.check(schema, value, "", 0, &mut errors);
+        errors
+    }
+
+    /// Check if value matches schema.
+    fn matches(&self, schema: &Schema, value: &Value, depth: usize) -> bool {
+        let mut errors = ValidationErrors::new();
+        self.check(schema, value, "", depth, &mut errors);
+        errors.is_empty()
+    }
+
+    /// Record violations of a schema by value at JSON pointer.
+    fn check(
+        &self,
+        schema: &Schema,
+        value: &Value,
+        pointer: &str,
+        depth: usize,
+        errors: &mut ValidationErrors,
+    ) {
+        if errors.errors().len() >= MAX_VIOLATIONS || depth > MAX_DEPTH {
+            return;
+        }
+        let obj = match schema {
+            Schema::Bool(true) => return,
+            Schema::Bool(false) => {
+                errors.add(pointer, violation("never", "value is not allowed"));
+                return;
+            }
+            Schema::Object(obj) => obj,
+        };
+        if value.is_null() && obj.extensions.get("nullable") == Some(&Value::Bool(true)) {
+            return;
+        }
+        if let Some(target) = obj
+            .reference
+            .as_deref()
+            .and_then(|reference| reference.strip_prefix(&self.ref_prefix))
+            .and_then(|name| self.definitions.get(name))
+        {
+            self.check(target, value, pointer, depth + 1, errors);
+        }
+        if let Some(types) = &obj.instance_type {
+            if !type_matches(types, value) {
+                let message = format!(
+                    "expected {}, got {}",
+                    type_names(types),
+                    value_type_name(value)
+                );
+                errors.add(pointer, violation("type", message));
+                return;
+            }
+        }
+        if let Some(values) = &obj.enum_values {
+            if !values.contains(value) {
+                errors.add(
+                    pointer,
+                    violation("enum", "value is not one of allowed values"),
+                );
+            }
+        }
+        if let Some(expected) = &obj.const_value {
+            if expected != value {
+                errors.add(pointer, violation("const", format!("expected {expected}")));
+            }
+        }
+        self.check_subschemas(obj, value, pointer, depth, errors);
+        match value {
+            Value::Number(num) => {
+                check_number(obj, num.as_f64().unwrap_or_default(), pointer, errors)
+            }
+            Value::String(s) => self.check_string(obj, s, pointer, errors),
+            Value::Array(items) => self.check_array(obj, items, pointer, depth, errors),
+            Value::Object(props) => self.check_object(obj, props, pointer, depth, errors),
+            Value::Null | Value::Bool(_) => {}
+        }
+    }
+
+    /// Check `allOf`, `anyOf`, `oneOf` and `not` subschemas.
+    fn check_subschemas(
+        &self,
+        obj: &SchemaObject,
+        value: &Value,
+        pointer: &str,
+        depth: usize,
+        errors: &mut ValidationErrors,
+    ) {
+        let Some(sub) = &obj.subschemas else {
+            return;
+        };
+        for schema in sub.all_of.iter().flatten() {
+            self.check(schema, value, pointer, depth + 1, errors);
+        }
+        if let Some(any_of) = &sub.any_of {
+            if !any_of.iter().any(|sch| self.matches(sch, value, depth + 1)) {
+                let message = format!("value matches none of {} alternatives", any_of.len());
+                errors.add(pointer, violation("any_of", message));
+            }
+        }
+        if let Some(one_of) = &sub.one_of {
+            let matched = one_of
+                .iter()
+                .filter(|sch| self.matches(sch, value, depth + 1))
+                .count();
+            if matched != 1 {
+                let message = format!(
+                    "value matches {matched} of {} alternatives, expected exactly one",
+                    one_of.len()
+                );
+                errors.add(pointer, violation("one_of", message));
+            }
+        }
+        if let Some(not) = &sub.not {
+            if self.matches(not, value, depth + 1) {
+                errors.add(pointer, violation("not", "value matches disallowed schema"));
+            }
+        }
+    }
+
+    /// Check string length and pattern.
+    fn check_string(
+        &self,
+        obj: &SchemaObject,
+        value: &str,
+        pointer: &str,
+        errors: &mut ValidationErrors,
+    ) {
+        let Some(rules) = &obj.string else {
+            return;
+        };
+        let len = value.chars().count();
+        if rules.min_length.is_some_and(|min| len < min as usize) {
+            errors.add(pointer, violation("min_length", "string is too short"));
+        }
+        if rules.max_length.is_some_and(|max| len > max as usize) {
+            errors.add(pointer, violation("max_length", "string is too long"));
+        }
+        if let Some(re) = rules
+            .pattern
+            .as_ref()
+            .and_then(|pattern| self.patterns.get(pattern))
+        {
+            if !re.is_match(value) {
+                let message = format!("string does not match pattern {}", re.as_str());
+                errors.add(pointer, violation("pattern", message));
+            }
+        }
+    }
+
+    /// Check array items and length.
+    fn check_array(
+        &self,
+        obj: &SchemaObject,
+        items: &[Value],
+        pointer: &str,
+        depth: usize,
+        errors: &mut ValidationErrors,
+    ) {
+        let Some(rules) = &obj.array else {
+            return;
+        };
+        if rules
+            .min_items
+            .is_some_and(|min| items.len() < min as usize)
+        {
+            errors.add(pointer, violation("min_items", "array has too few items"));
+        }
+        if rules
+            .max_items
+            .is_some_and(|max| items.len() > max as usize)
+        {
+            errors.add(pointer, violation("max_items", "array has too many items"));
+        }
+        if rules.unique_items == Some(true)
+            && items
+                .iter()
+                .enumerate()
+                .any(|(idx, item)| items[..idx].contains(item))
+        {
+            errors.add(
+                pointer,
+                violation("unique_items", "array items are not unique"),
+            );
+        }
+        let schemas: Box<dyn Iterator<Item = &Schema>> = match &rules.items {
+            None => return,
+            Some(SingleOrVec::Single(schema)) => Box::new(std::iter::repeat(&**schema)),
+            Some(SingleOrVec::Vec(schemas)) => Box::new(schemas.iter()),
+        };
+        for (idx, (item, schema)) in items.iter().zip(schemas).enumerate() {
+            let pointer = validate_pointer(pointer, &idx.to_string());
+            self.check(schema, item, &pointer, depth + 1, errors);
+        }
+    }
+
+    /// Check object properties.
+    fn check_object(
+        &self,
+        obj: &SchemaObject,
+        props: &serde_json::Map<String, Value>,
+        pointer: &str,
+        depth: usize,
+        errors: &mut ValidationErrors,
+    ) {
+        let Some(rules) = &obj.object else {
+            return;
+        };
+        for name in &rules.required {
+            if !props.contains_key(name) {
+                let pointer = validate_pointer(pointer, name);
+                errors.add(
+                    pointer,
+                    violation("required", "required property is missing"),
+                );
+            }
+        }
+        for (name, value) in props {
+            let pointer = validate_pointer(pointer, name);
+            match rules.properties.get(name) {
+                Some(schema) => self.check(schema, value, &pointer, depth + 1, errors),
+                // Pattern properties are not supported, so additional properties can't be told
+                // apart from them.
+                None if rules.pattern_properties.is_empty() => {
+                    if let Some(schema) = &rules.additional_properties {
+                        if matches!(**schema, Schema::Bool(false)) {
+                            errors.add(
+                                pointer,
+                                violation("additional_property", "property is not declared"),
+                            );
+                        } else {
+                            self.check(schema, value, &pointer, depth + 1, errors);
+                        }
+                    }
+                }
+                None => {}
+            }
+        }
+        if rules
+            .min_properties
+            .is_some_and(|min| props.len() < min as usize)
+        {
+            errors.add(
+                pointer,
+                violation("min_properties", "object has too few properties"),
+            );
+        }
+        if rules
+            .max_properties
+            .is_some_and(|max| props.len() > max as usize)
+        {
+            errors.add(
+                pointer,
+                violation("max_properties", "object has too many properties"),
+            );
+        }
+    }
+}
+
+/// Extract JSON schemas from response spec, keyed by media type.
+fn json_schemas(resp: &openapi3::Response) -> Vec<(String, Schema)> {
+    resp.content
+        .iter()
+        .filter_map(|(media_type, content)| {
+            let media_type = essence(media_type);
+            is_json(&media_type)
+                .then_some(content.schema.as_ref())
+                .flatten()
+                .map(|schema| (media_type, Schema::Object(schema.clone())))
+        })
+        .collect()
+}
+
+/// Collect string patterns used in schema, compiling them.
+fn collect_patterns(schema: &Schema, patterns: &mut HashMap<String, Regex>) {
+    let Schema::Object(obj) = schema else {
+        return;
+    };
+    if let Some(pattern) = obj.string.as_ref().and_then(|s| s.pattern.as_ref()) {
+        if !patterns.contains_key(pattern) {
+            match Regex::new(pattern) {
+                Ok(re) => {
+                    patterns.insert(pattern.clone(), re);
+                }
+                Err(err) => warn!(pattern, error = %err, "skipping invalid schema pattern"),
+            }
+        }
+    }
+    let sub = obj.subschemas.iter().flat_map(|sub| {
+        [&sub.all_of, &sub.any_of, &sub.one_of]
+            .into_iter()
+            .flatten()
+            .flatten()
+            .chain(sub.not.as_deref())
+    });
+    let items = obj
+        .array
+        .iter()
+        .flat_map(|arr| arr.items.iter())
+        .flat_map(|items| match items {
+            SingleOrVec::Single(schema) => std::slice::from_ref(&**schema),
+            SingleOrVec::Vec(schemas) => schemas.as_slice(),
+        });
+    let props = obj.object.iter().flat_map(|obj| {
+        obj.properties
+            .values()
+            .chain(obj.additional_properties.as_deref())
+    });
+    for schema in sub.chain(items).chain(props) {
+        collect_patterns(schema, patterns);
+    }
+}
+
+/// Check numeric bounds.
+fn check_number(obj: &SchemaObject, value: f64, pointer: &str, errors: &mut ValidationErrors) {
+    let Some(rules) = &obj.number else {
+        return;
+    };
+    if rules.minimum.is_some_and(|min| value < min)
+        || rules.exclusive_minimum.is_some_and(|min| value <= min)
+    {
+        errors.add(pointer, violation("minimum", "number is too small"));
+    }
+    if rules.maximum.is_some_and(|max| value > max)
+        || rules.exclusive_maximum.is_some_and(|max| value >= max)
+    {
+        errors.add(pointer, violation("maximum", "number is too large"));
+    }
+    if rules
+        .multiple_of
+        .is_some_and(|div| div > 0.0 && (value / div).fract() != 0.0)
+    {
+        errors.add(
+            pointer,
+            violation("multiple_of", "number is not a multiple of divisor"),
+        );
+    }
+}
+
+/// Create violation with code in `contract.` namespace.
+fn violation(code: &'static str, message: impl ToString) -> ValidationError {
+    ValidationError::new(format!("contract.{code}"), message)
+}
+
+/// Check if value has one of allowed types.
+fn type_matches(types: &SingleOrVec<InstanceType>, value: &Value) -> bool {
+    let matches = |ty: &InstanceType| match ty {
+        InstanceType::Null => value.is_null(),
+        InstanceType::Boolean => value.is_boolean(),
+        InstanceType::Object => value.is_object(),
+        InstanceType::Array => value.is_array(),
+        InstanceType::Number => value.is_number(),
+        InstanceType::String => value.is_string(),
+        InstanceType::Integer => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|f| f.fract() == 0.0)
+        }
+    };
+    match types {
+        SingleOrVec::Single(ty) => matches(ty),
+        SingleOrVec::Vec(types) => types.iter().any(matches),
+    }
+}
+
+/// Human-readable list of schema types.
+fn type_names(types: &SingleOrVec<InstanceType>) -> String {
+    let name = |ty: &InstanceType| {
+        serde_json::to_value(ty)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_owned))
+            .unwrap_or_default()
+    };
+    match types {
+        SingleOrVec::Single(ty) => name(ty),
+        SingleOrVec::Vec(types) => types.iter().map(name).collect::<Vec<_>>().join(" or "),
+    }
+}
+
+/// JSON type name of a value.
+fn value_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Media type without parameters, in lowercase.
+fn essence(media_type: &str) -> String {
+    media_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// Check if media type denotes JSON content.
+fn is_json(media_type: &str) -> bool {
+    media_type == "application/json" || media_type.ends_with("+json")
+}
+
+/// Shared state of response validation layer.
+#[derive(Debug)]
+struct ValidationState {
+    /// Handler name, used in logs and as metric label.
+    handler: &'static str,
+    /// Validation mode.
+    mode: ResponseValidationMode,
+    /// Maximum body size of a validated response.
+    max_body_size: usize,
+    /// Compiled response schemas.
+    schemas: ResponseSchemas,
+    /// Counter of responses violating declared schemas.
+    violations: Option<Counter<u64>>,
+    /// Counter of responses skipped by validation.
+    skipped: Option<Counter<u64>>,
+}
+
+impl ValidationState {
+    /// Record skipped response.
+    fn skip(&self, resp: Response<Body>, reason: &'static str) -> Result<Response<Body>, BoxError> {
+        if let Some(counter) = &self.skipped {
+            counter.add(
+                1,
+                &[
+                    KeyValue::new("uxum.handler", self.handler),
+                    KeyValue::new("reason", reason),
+                ],
+            );
+        }
+        Ok(resp)
+    }
+
+    /// Validate response, replacing it in enforcing mode if it violates declared schema.
+    async fn validate(&self, resp: Response<Body>) -> Result<Response<Body>, BoxError> {
+        let media_type = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|hv| hv.to_str().ok())
+            .map(essence)
+            .unwrap_or_default();
+        if !is_json(&media_type) {
+            return self.skip(resp, "not_json");
+        }
+        let status = resp.status();
+        let Some(schema) = self.schemas.lookup(status, &media_type) else {
+            return self.skip(resp, "undeclared");
+        };
+        // Never buffer bodies of unknown length.
+        let Some(size) = resp
+            .body()
+            .size_hint()
+            .upper()
+            .and_then(|size| usize::try_from(size).ok())
+        else {
+            return self.skip(resp, "streaming");
+        };
+        if size > self.max_body_size {
+            return self.skip(resp, "too_large");
+        }
+        let mut reservation = memory::global().reservation(LAYER_NAME);
+        if reservation.grow_to(size).is_err() {
+            return self.skip(resp, "memory");
+        }
+        let (parts, body) = resp.into_parts();
+        let body = axum::body::to_bytes(body, size).await?;
+        let errors = match serde_json::from_slice::<Value>(&body) {
+            Ok(value) => self.schemas.validate(schema, &value),
+            Err(err) => {
+                let mut errors = ValidationErrors::new();
+                errors.add("", violation("invalid_json", err));
+                errors
+            }
+        };
+        if errors.is_empty() {
+            return Ok(Response::from_parts(parts, Body::from(body)));
+        }
+        if let Some(counter) = &self.violations {
+            counter.add(
+                1,
+                &[
+                    KeyValue::new("uxum.handler", self.handler),
+                    KeyValue::new("http.response.status_code", i64::from(status.as_u16())),
+                ],
+            );
+        }
+        error!(
+            handler = self.handler,
+            status = status.as_u16(),
+            violations = %errors,
+            "response does not match declared schema"
+        );
+        match self.mode {
+            ResponseValidationMode::Enforce => Ok(contract_violation(status, errors)),
+            _ => Ok(Response::from_parts(parts, Body::from(body))),
+        }
+    }
+}
+
+/// Build problem details response describing contract violation.
+pub(crate) fn contract_violation(status: StatusCode, errors: ValidationErrors) -> Response<Body> {
+    let code = codes::CONTRACT_VIOLATION;
+    let problem = code
+        .problem(StatusCode::INTERNAL_SERVER_ERROR)
+        .with_type(CONTRACT_PROBLEM_TYPE)
+        .with_title("Response does not match declared schema")
+        .with_value("status", status.as_u16())
+        .with_value("errors", serde_json::to_value(errors).unwrap_or_default());
+    (code, problem).into_response()
+}
+
+/// Response contract validation [`tower`] layer.
+#[derive(Clone, Debug)]
+pub(crate) struct ResponseValidationLayer {
+    /// Shared state.
+    state: Arc<ValidationState>,
+}
+
+impl<S> Layer<S> for ResponseValidationLayer {
+    type Service = ResponseValidation<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ResponseValidation {
+            inner,
+            state: self.state.clone(),
+        }
+    }
+}
+
+/// Response contract validation [`tower`] service.
+#[derive(Clone, Debug)]
+pub(crate) struct ResponseValidation<S> {
+    /// Inner service.
+    inner: S,
+    /// Shared state.
+    state: Arc<ValidationState>,
+}
+
+impl<S, T> Service<Request<T>> for ResponseValidation<S>
+where
+    S: Service<Request<T>, Response = Response<Body>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<T>) -> Self::Future {
+        let future = self.inner.call(req);
+        let state = self.state.clone();
+        Box::pin(async move {
+            let resp = future.await.map_err(Into::into)?;
+            state.validate(resp).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use schemars::{gen::SchemaSettings, JsonSchema};
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::response::{GetResponseSchemas, Json};
+
+    /// Declared response type.
+    #[derive(JsonSchema)]
+    #[allow(dead_code)]
+    struct Declared {
+        id: u32,
+        name: String,
+        kind: Kind,
+        child: Option<Child>,
+    }
+
+    #[derive(JsonSchema)]
+    #[allow(dead_code)]
+    enum Kind {
+        Plain,
+        Fancy,
+    }
+
+    #[derive(JsonSchema)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)]
+    struct Child {
+        tags: Vec<String>,
+    }
+
+    fn schemas() -> ResponseSchemas {
+        let mut gen = SchemaSettings::openapi3().into_generator();
+        let spec = openapi3::Operation {
+            responses: <Json<Declared>>::get_responses(&mut gen),
+            ..Default::default()
+        };
+        ResponseSchemas::compile(&spec, gen)
+    }
+
+    fn pointers(errors: &ValidationErrors) -> Vec<&str> {
+        errors
+            .errors()
+            .iter()
+            .map(|err| err.pointer.as_str())
+            .collect()
+    }
+
+    /// Layer around handler returning provided JSON body.
+    fn service(
+        mode: ResponseValidationMode,
+        body: Value,
+    ) -> impl Service<Request<Body>, Response = Response<Body>, Error = BoxError> {
+        let layer = ResponseValidationConfig::default()
+            .with_mode(mode)
+            .make_layer("drifting", None, schemas, None)
+            .unwrap();
+        layer.layer(tower::service_fn(move |_req: Request<Body>| {
+            let body = body.clone();
+            async move { Ok::<_, BoxError>(axum::Json(body).into_response()) }
+        }))
+    }
+
+    async fn call(
+        svc: impl Service<Request<Body>, Response = Response<Body>, Error = BoxError>,
+    ) -> (StatusCode, Value) {
+        let resp = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+        let status = resp.status();
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[test]
+    fn validator() {
+        let schemas = schemas();
+        let schema = schemas.lookup(StatusCode::OK, "application/json").unwrap();
+        let valid = json!({"id": 1, "name": "a", "kind": "Plain", "child": null});
+        assert!(schemas.validate(schema, &valid).is_empty());
+        let valid = json!({"id": 1, "name": "a", "kind": "Fancy", "child": {"tags": ["x"]}});
+        assert!(schemas.validate(schema, &valid).is_empty());
+
+        let drifted = json!({
+            "id": -1,
+            "kind": "Other",
+            "child": {"tags": [1], "extra": true},
+        });
+        let errors = schemas.validate(schema, &drifted);
+        assert_eq!(
+            pointers(&errors),
+            ["/name", "/id", "/kind", "/child/tags/0", "/child/extra"]
+        );
+        assert!(errors
+            .errors()
+            .iter()
+            .all(|err| err.error.code.starts_with("contract.")));
+
+        let errors = schemas.validate(schema, &json!([]));
+        assert_eq!(
+            errors.errors()[0].error.message,
+            "expected object, got array"
+        );
+        assert!(schemas
+            .lookup(StatusCode::NOT_FOUND, "application/json")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn warn_mode() {
+        let drifted = json!({"id": "1", "name": "a", "kind": "Plain"});
+        let svc = service(ResponseValidationMode::Warn, drifted.clone());
+        assert_eq!(call(svc).await, (StatusCode::OK, drifted));
+    }
+
+    #[tokio::test]
+    async fn enforce_mode() {
+        let drifted = json!({"id": "1", "name": "a", "kind": "Plain"});
+        let (status, body) = call(service(ResponseValidationMode::Enforce, drifted)).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["code"], "contract.violation");
+        assert_eq!(body["status"], 200);
+        assert_eq!(body["errors"][0]["pointer"], "/id");
+        assert_eq!(body["errors"][0]["code"], "contract.type");
+
+        let valid = json!({"id": 1, "name": "a", "kind": "Plain"});
+        let svc = service(ResponseValidationMode::Enforce, valid.clone());
+        assert_eq!(call(svc).await, (StatusCode::OK, valid));
+    }
+
+    #[tokio::test]
+    async fn skipped() {
+        let layer = ResponseValidationConfig::default()
+            .with_mode(ResponseValidationMode::Enforce)
+            .make_layer("drifting", None, schemas, None)
+            .unwrap();
+        let text = layer.layer(tower::service_fn(|_req: Request<Body>| async {
+            Ok::<_, BoxError>("not json".into_response())
+        }));
+        let resp = text.oneshot(Request::new(Body::empty())).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let streaming = layer.layer(tower::service_fn(|_req: Request<Body>| async {
+            let chunks = futures::stream::iter([Ok::<_, BoxError>("{}")]);
+            let mut resp = Response::new(Body::from_stream(chunks));
+            resp.headers_mut()
+                .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+            Ok::<_, BoxError>(resp)
+        }));
+        let resp = streaming
+            .oneshot(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn disabled() {
+        let config = ResponseValidationConfig::default();
+        assert!(config
+            .make_layer("h", None, || unreachable!(), None)
+            .is_none());
+        let layer = config.make_layer("h", Some(ResponseValidationMode::Warn), schemas, None);
+        assert!(layer.is_some());
+    }
+}
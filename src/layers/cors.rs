@@ -1,9 +1,24 @@
-use std::{str::FromStr, time::Duration};
+//! CORS configuration.
 
-use axum::http::{header, HeaderName, HeaderValue};
+use std::{
+    collections::HashSet,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    http::{header, HeaderName, HeaderValue, Method, Request, Response},
+};
+use futures::future::Either;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tower_http::cors::{Any, CorsLayer};
+use tower::{BoxError, Layer, Service};
+use tower_http::cors::{self, AllowOrigin, Any, CorsLayer};
+
+use crate::layers::ip_filter::{IpFilterFuture, IpFilterLayer};
 
 /// Error type returned by CORS module.
 #[derive(Debug, Error)]
@@ -15,6 +30,12 @@ pub enum CorsError {
     /// Invalid CORS header.
     #[error("Invalid CORS header")]
     InvalidHeader(header::InvalidHeaderName),
+    /// Invalid CORS origin pattern.
+    #[error("Invalid CORS origin pattern {0}: wildcard is only allowed as leading host label")]
+    InvalidPattern(String),
+    /// Wildcard value used along with credentials.
+    #[error("Wildcard {0} cannot be used when credentials are allowed")]
+    CredentialedWildcard(&'static str),
 }
 
 /// Allow either any value, or listed values.
@@ -25,17 +46,31 @@ pub(crate) enum AnyOr<T> {
     Some(Vec<T>),
 }
 
+impl AnyOr<String> {
+    /// Check whether any value is allowed, either explicitly, or with a `*` list item.
+    fn is_any(&self) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Some(vals) => vals.iter().any(|val| val == "*"),
+        }
+    }
+}
+
 /// CORS configuration for a handler.
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 #[non_exhaustive]
 pub struct CorsConfig {
     /// Control [`Access-Control-Allow-Origin`][mdn] header.
     ///
+    /// Origins may contain a wildcard as the leading host label, like `https://*.example.com`.
+    /// Such patterns match any subdomain, but not the domain itself.
+    ///
     /// [mdn]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Access-Control-Allow-Origin
     origins: AnyOr<String>,
     /// Control [`Access-Control-Allow-Credentials`][mdn] header.
     ///
-    /// `false` excludes the header from response.
+    /// `false` excludes the header from response. Cannot be used with wildcard origins, headers
+    /// or exposed headers.
     ///
     /// [mdn]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Access-Control-Allow-Credentials
     #[serde(default)]
@@ -45,6 +80,11 @@ pub struct CorsConfig {
     /// [mdn]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Access-Control-Allow-Headers
     #[serde(default)]
     headers: Option<AnyOr<String>>,
+    /// Control [`Access-Control-Expose-Headers`][mdn] header.
+    ///
+    /// [mdn]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Access-Control-Expose-Headers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expose_headers: Option<AnyOr<String>>,
     /// Control [`Access-Control-Max-Age`][mdn] header.
     ///
     /// [mdn]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Access-Control-Max-Age
@@ -57,25 +97,45 @@ pub struct CorsConfig {
 }
 
 impl CorsConfig {
+    /// Check configuration for combinations rejected by browsers.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if credentials are allowed along with wildcard origins, headers or exposed
+    /// headers.
+    pub fn validate(&self) -> Result<(), CorsError> {
+        if !self.credentials {
+            return Ok(());
+        }
+        if self.origins.is_any() {
+            return Err(CorsError::CredentialedWildcard("origin"));
+        }
+        if self.headers.as_ref().is_some_and(AnyOr::is_any) {
+            return Err(CorsError::CredentialedWildcard("allowed headers"));
+        }
+        if self.expose_headers.as_ref().is_some_and(AnyOr::is_any) {
+            return Err(CorsError::CredentialedWildcard("exposed headers"));
+        }
+        Ok(())
+    }
+
     /// Create CORS [`tower`] layer.
     ///
     /// # Errors
     ///
     /// Returns `Err` if:
+    /// * Configuration is invalid, see [`Self::validate`].
     /// * Origin cannot be transformed into HTTP header value encoding.
+    /// * Origin pattern is malformed.
     /// * Some of header names cannot be transformed into HTTP header name encoding.
     pub fn make_layer(&self) -> Result<CorsLayer, CorsError> {
+        self.validate()?;
         let mut layer = CorsLayer::new();
         layer = match &self.origins {
-            AnyOr::Any => layer.allow_origin(Any),
-            AnyOr::Some(origins) => {
-                let headers = origins
-                    .iter()
-                    .map(|origin| HeaderValue::from_str(origin))
-                    .collect::<Result<Vec<_>, _>>()
-                    .map_err(CorsError::InvalidOrigin)?;
-                layer.allow_origin(headers)
+            AnyOr::Some(origins) if !self.origins.is_any() => {
+                layer.allow_origin(OriginMatcher::new(origins)?.into_allow())
             }
+            _ => layer.allow_origin(Any),
         };
         layer = match self.credentials {
             true => layer.allow_credentials(true),
@@ -83,15 +143,17 @@ impl CorsConfig {
         };
         layer = match &self.headers {
             None => layer,
-            Some(AnyOr::Any) => layer.allow_headers(Any),
-            Some(AnyOr::Some(headers)) => {
-                let headers = headers
-                    .iter()
-                    .map(|origin| HeaderName::from_str(origin))
-                    .collect::<Result<Vec<_>, _>>()
-                    .map_err(CorsError::InvalidHeader)?;
-                layer.allow_headers(headers)
+            Some(AnyOr::Some(names)) if !names.iter().any(|name| name == "*") => {
+                layer.allow_headers(header_names(names)?)
             }
+            Some(_) => layer.allow_headers(Any),
+        };
+        layer = match &self.expose_headers {
+            None => layer,
+            Some(AnyOr::Some(names)) if !names.iter().any(|name| name == "*") => {
+                layer.expose_headers(header_names(names)?)
+            }
+            Some(_) => layer.expose_headers(Any),
         };
         if let Some(max_age) = self.max_age {
             layer = layer.max_age(max_age);
@@ -100,6 +162,189 @@ impl CorsConfig {
     }
 }
 
+/// Parse list of header names.
+fn header_names(headers: &[String]) -> Result<Vec<HeaderName>, CorsError> {
+    headers
+        .iter()
+        .map(|name| HeaderName::from_str(name))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(CorsError::InvalidHeader)
+}
+
+/// Compiled list of allowed origins and origin patterns.
+#[derive(Debug, Default)]
+struct OriginMatcher {
+    /// Exact origins.
+    exact: HashSet<HeaderValue>,
+    /// Wildcard patterns, split into parts before and after the wildcard.
+    patterns: Vec<(String, String)>,
+}
+
+impl OriginMatcher {
+    /// Compile list of origins and patterns.
+    fn new(origins: &[String]) -> Result<Self, CorsError> {
+        let mut matcher = Self::default();
+        for origin in origins {
+            match origin.split_once('*') {
+                None => {
+                    matcher
+                        .exact
+                        .insert(HeaderValue::from_str(origin).map_err(CorsError::InvalidOrigin)?);
+                }
+                Some((prefix, suffix)) => {
+                    if !prefix.ends_with("://")
+                        || !suffix.starts_with('.')
+                        || suffix.len() < 2
+                        || suffix.contains('*')
+                    {
+                        return Err(CorsError::InvalidPattern(origin.clone()));
+                    }
+                    matcher
+                        .patterns
+                        .push((prefix.to_ascii_lowercase(), suffix.to_ascii_lowercase()));
+                }
+            }
+        }
+        Ok(matcher)
+    }
+
+    /// Check whether origin is allowed.
+    fn matches(&self, origin: &HeaderValue) -> bool {
+        if self.exact.contains(origin) {
+            return true;
+        }
+        let Ok(origin) = origin.to_str() else {
+            return false;
+        };
+        let origin = origin.to_ascii_lowercase();
+        self.patterns.iter().any(|(prefix, suffix)| {
+            origin
+                .strip_prefix(prefix.as_str())
+                .and_then(|rest| rest.strip_suffix(suffix.as_str()))
+                .is_some_and(|label| {
+                    !label.is_empty()
+                        && !label.starts_with('.')
+                        && label
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+                })
+        })
+    }
+
+    /// Convert into [`tower_http`] origin policy.
+    fn into_allow(self) -> AllowOrigin {
+        if self.patterns.is_empty() {
+            return AllowOrigin::list(self.exact);
+        }
+        let matcher = Arc::new(self);
+        AllowOrigin::predicate(move |origin, _parts| matcher.matches(origin))
+    }
+}
+
+/// CORS preflight policy of a single handler.
+#[derive(Clone)]
+struct PreflightPolicy {
+    /// HTTP method served by handler.
+    method: Method,
+    /// CORS policy of handler.
+    cors: CorsLayer,
+    /// IP filter of handler, applied before answering preflight requests.
+    ip_filter: Option<IpFilterLayer>,
+}
+
+/// Path-level CORS preflight [`tower`] layer.
+///
+/// Preflight requests are answered using CORS policy of the handler serving requested method,
+/// before any handler-level layers such as authentication. Handler IP filter, if any, is still
+/// applied to preflight requests. Other requests are passed through.
+#[derive(Clone, Default)]
+pub(crate) struct PreflightLayer {
+    /// CORS policies of handlers.
+    policies: Vec<PreflightPolicy>,
+}
+
+impl PreflightLayer {
+    /// Add CORS policy and IP filter of a handler serving provided methods.
+    pub(crate) fn add(
+        &mut self,
+        methods: &[Method],
+        policy: &CorsLayer,
+        ip_filter: Option<IpFilterLayer>,
+    ) {
+        self.policies
+            .extend(methods.iter().map(|method| PreflightPolicy {
+                method: method.clone(),
+                cors: policy.clone(),
+                ip_filter: ip_filter.clone(),
+            }));
+    }
+
+    /// Check whether no policies were added.
+    #[must_use]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.policies.is_empty()
+    }
+
+    /// Find policy for a preflight request.
+    fn policy<T>(&self, req: &Request<T>) -> Option<&PreflightPolicy> {
+        if req.method() != Method::OPTIONS {
+            return None;
+        }
+        let requested = req.headers().get(header::ACCESS_CONTROL_REQUEST_METHOD)?;
+        self.policies
+            .iter()
+            .find(|policy| policy.method.as_str().as_bytes() == requested.as_bytes())
+    }
+}
+
+impl<S> Layer<S> for PreflightLayer {
+    type Service = PreflightService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PreflightService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Path-level CORS preflight [`tower`] service.
+#[derive(Clone)]
+pub(crate) struct PreflightService<S> {
+    /// Inner service.
+    inner: S,
+    /// Layer configuration.
+    layer: PreflightLayer,
+}
+
+impl<S> Service<Request<Body>> for PreflightService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = BoxError> + Clone,
+{
+    type Response = Response<Body>;
+    type Error = BoxError;
+    type Future = Either<
+        S::Future,
+        Either<cors::ResponseFuture<S::Future>, IpFilterFuture<cors::ResponseFuture<S::Future>>>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let Some(policy) = self.layer.policy(&req) else {
+            return Either::Left(self.inner.call(req));
+        };
+        // CORS service answers OPTIONS requests without calling inner service.
+        let mut cors = policy.cors.layer(self.inner.clone());
+        Either::Right(match &policy.ip_filter {
+            Some(ip_filter) => Either::Right(ip_filter.layer(cors).call(req)),
+            None => Either::Left(cors.call(req)),
+        })
+    }
+}
+
 mod serde_impls {
     use std::{fmt, marker::PhantomData};
 
@@ -320,4 +565,116 @@ mod tests {
             })
         );
     }
+
+    /// Wildcards are rejected when credentials are allowed.
+    #[test]
+    fn credentialed_wildcard() {
+        for cfg in [
+            json!({"origins": "any", "credentials": true}),
+            json!({"origins": ["*"], "credentials": true}),
+            json!({"origins": ["https://a.example.com"], "headers": "any", "credentials": true}),
+            json!({"origins": ["https://a.example.com"], "expose_headers": ["*"], "credentials": true}),
+        ] {
+            let cfg: CorsConfig = serde_json::from_value(cfg).unwrap();
+            assert!(matches!(
+                cfg.validate(),
+                Err(CorsError::CredentialedWildcard(_))
+            ));
+            assert!(cfg.make_layer().is_err());
+        }
+        let cfg: CorsConfig = serde_json::from_value(json!({
+            "origins": ["https://*.example.com"],
+            "credentials": true,
+        }))
+        .unwrap();
+        assert!(cfg.make_layer().is_ok());
+    }
+
+    /// Origin patterns match subdomains only.
+    #[test]
+    fn origin_patterns() {
+        let matcher = OriginMatcher::new(&[
+            "https://app.example.org".into(),
+            "https://*.example.com".into(),
+        ])
+        .unwrap();
+        let check = |origin: &'static str| matcher.matches(&HeaderValue::from_static(origin));
+        assert!(check("https://app.example.org"));
+        assert!(check("https://a.example.com"));
+        assert!(check("https://a.b.example.com"));
+        assert!(check("https://A.Example.com"));
+        assert!(!check("https://example.com"));
+        assert!(!check("http://a.example.com"));
+        assert!(!check("https://a.example.com.evil.org"));
+        assert!(!check("https://evil.org/.example.com"));
+        assert!(!check("https://.example.com"));
+
+        for pattern in [
+            "https://a.*.com",
+            "*.example.com",
+            "https://*",
+            "https://*.*.com",
+        ] {
+            assert!(matches!(
+                OriginMatcher::new(&[pattern.into()]),
+                Err(CorsError::InvalidPattern(_))
+            ));
+        }
+    }
+
+    /// Preflight requests get CORS headers for allowed origins only.
+    #[tokio::test]
+    async fn preflight() {
+        use tower::ServiceExt;
+
+        let cfg: CorsConfig = serde_json::from_value(json!({
+            "origins": ["https://*.example.com"],
+            "credentials": true,
+            "headers": ["x-custom"],
+            "expose_headers": ["x-total"],
+            "max_age": "10m",
+        }))
+        .unwrap();
+        let service =
+            cfg.make_layer()
+                .unwrap()
+                .allow_methods(Method::POST)
+                .layer(tower::service_fn(|_req: Request<Body>| async {
+                    Ok::<_, std::convert::Infallible>(Response::new(Body::empty()))
+                }));
+        let preflight = |origin: &'static str| {
+            Request::builder()
+                .method(Method::OPTIONS)
+                .header(header::ORIGIN, origin)
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let resp = service
+            .clone()
+            .oneshot(preflight("https://app.example.com"))
+            .await
+            .unwrap();
+        let headers = resp.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS], "x-custom");
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+        assert!(headers
+            .get_all(header::VARY)
+            .iter()
+            .any(|val| val.to_str().unwrap().contains("origin")));
+
+        let resp = service
+            .oneshot(preflight("https://evil.org"))
+            .await
+            .unwrap();
+        assert!(!resp
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
 }
@@ -0,0 +1,470 @@
+//! Detection of handlers which do not yield to async runtime for too long.
+//!
+//! Tokio can't preempt a task which is busy on CPU, so a spinning handler starves all other tasks
+//! scheduled on the same worker thread. For handlers marked with `cpu_guard` attribute, duration
+//! of each poll is tracked. Watchdog task periodically checks polls which are still in progress,
+//! and reports ones exceeding configured threshold. Polls are also checked when they return, which
+//! covers single-threaded runtimes, where watchdog can't run while handler is spinning.
+//!
+//! Nothing is ever preempted. Optionally, after a stall is detected, subsequent executions of the
+//! handler are moved off async worker threads for a cool-down period.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Weak,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use opentelemetry::{metrics::Counter, KeyValue};
+use parking_lot::Mutex;
+use pin_project::{pin_project, pinned_drop};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    runtime::{Handle, RuntimeFlavor},
+    time::{interval, MissedTickBehavior},
+};
+use tower::{Layer, Service};
+use tracing::{debug, warn};
+
+/// CPU stall detection configuration.
+///
+/// Only used for handlers marked with `cpu_guard` attribute.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct CpuGuardConfig {
+    /// Duration of a single poll without yielding, which is considered a stall.
+    ///
+    /// Default is 100 milliseconds.
+    #[serde(
+        default = "CpuGuardConfig::default_threshold",
+        with = "humantime_serde"
+    )]
+    threshold: Duration,
+    /// Move subsequent executions of a stalled handler to blocking threads.
+    ///
+    /// Uses [`tokio::task::block_in_place`], so that request-scoped task locals are kept intact.
+    /// Only works on multi-threaded runtime.
+    #[serde(default)]
+    migrate: bool,
+    /// How long executions are kept on blocking threads after the last stall.
+    ///
+    /// Default is 1 minute.
+    #[serde(default = "CpuGuardConfig::default_cooldown", with = "humantime_serde")]
+    cooldown: Duration,
+}
+
+impl Default for CpuGuardConfig {
+    fn default() -> Self {
+        Self {
+            threshold: Self::default_threshold(),
+            migrate: false,
+            cooldown: Self::default_cooldown(),
+        }
+    }
+}
+
+impl CpuGuardConfig {
+    /// Default value for [`Self::threshold`].
+    #[must_use]
+    #[inline]
+    fn default_threshold() -> Duration {
+        Duration::from_millis(100)
+    }
+
+    /// Default value for [`Self::cooldown`].
+    #[must_use]
+    #[inline]
+    fn default_cooldown() -> Duration {
+        Duration::from_secs(60)
+    }
+
+    /// Set poll duration which is considered a stall.
+    #[must_use]
+    pub fn with_threshold(mut self, threshold: Duration) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Enable or disable moving executions of stalled handlers to blocking threads.
+    #[must_use]
+    pub fn with_migrate(mut self, migrate: bool) -> Self {
+        self.migrate = migrate;
+        self
+    }
+
+    /// Set how long executions are kept on blocking threads after the last stall.
+    #[must_use]
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Create layer for use in [`tower`] services.
+    #[must_use]
+    pub(crate) fn make_layer(
+        &self,
+        handler: &'static str,
+        stalls: Option<Counter<u64>>,
+    ) -> CpuGuardLayer {
+        CpuGuardLayer {
+            state: Arc::new(CpuGuardState {
+                handler,
+                threshold: self.threshold,
+                migrate: self.migrate,
+                cooldown: self.cooldown,
+                metrics: stalls,
+                base: Instant::now(),
+                stalls: AtomicU64::new(0),
+                migrate_until: AtomicU64::new(0),
+                in_flight: Mutex::new(HashMap::new()),
+                next_id: AtomicU64::new(0),
+                watchdog: AtomicBool::new(false),
+            }),
+        }
+    }
+}
+
+/// Shared state of CPU guard for a single handler.
+#[derive(Debug)]
+struct CpuGuardState {
+    /// Handler name.
+    handler: &'static str,
+    /// Poll duration considered a stall.
+    threshold: Duration,
+    /// Whether to migrate executions after a stall.
+    migrate: bool,
+    /// Migration cool-down period.
+    cooldown: Duration,
+    /// Stall counter metric.
+    metrics: Option<Counter<u64>>,
+    /// Reference point for timestamps.
+    base: Instant,
+    /// Total number of detected stalls.
+    stalls: AtomicU64,
+    /// End of migration period, in microseconds since [`Self::base`].
+    migrate_until: AtomicU64,
+    /// Executions currently in progress, by ID.
+    in_flight: Mutex<HashMap<u64, Arc<PollState>>>,
+    /// Next execution ID.
+    next_id: AtomicU64,
+    /// Whether watchdog task was started.
+    watchdog: AtomicBool,
+}
+
+/// Progress of a single execution.
+#[derive(Debug, Default)]
+struct PollState {
+    /// Start of current poll, in microseconds since [`CpuGuardState::base`], plus one.
+    ///
+    /// Zero if execution is not being polled right now.
+    started: AtomicU64,
+    /// Whether current poll was already reported as a stall.
+    reported: AtomicBool,
+}
+
+impl CpuGuardState {
+    /// Current time, in microseconds since [`Self::base`].
+    fn now_us(&self) -> u64 {
+        self.base.elapsed().as_micros() as u64
+    }
+
+    /// Whether executions should be moved to blocking threads right now.
+    fn migrating(&self) -> bool {
+        self.migrate && self.now_us() < self.migrate_until.load(Ordering::Relaxed)
+    }
+
+    /// Report a stall.
+    fn report(&self, elapsed: Duration) {
+        let total = self.stalls.fetch_add(1, Ordering::Relaxed) + 1;
+        warn!(
+            handler = self.handler,
+            elapsed_ms = elapsed.as_millis() as u64,
+            total,
+            "handler has not yielded for too long"
+        );
+        if let Some(counter) = &self.metrics {
+            counter.add(1, &[KeyValue::new("uxum.handler", self.handler)]);
+        }
+        if self.migrate {
+            let until = self.now_us() + self.cooldown.as_micros() as u64;
+            self.migrate_until.fetch_max(until, Ordering::Relaxed);
+        }
+    }
+
+    /// Report all in-progress polls exceeding threshold.
+    fn check(&self) {
+        let now = self.now_us();
+        let in_flight: Vec<_> = self.in_flight.lock().values().cloned().collect();
+        for poll in in_flight {
+            let started = poll.started.load(Ordering::Acquire);
+            if started == 0 {
+                continue;
+            }
+            let elapsed = Duration::from_micros(now.saturating_sub(started - 1));
+            if elapsed > self.threshold && !poll.reported.swap(true, Ordering::AcqRel) {
+                self.report(elapsed);
+            }
+        }
+    }
+
+    /// Start watchdog task, if not started yet.
+    fn ensure_watchdog(self: &Arc<Self>) {
+        if self.watchdog.load(Ordering::Relaxed) {
+            return;
+        }
+        let Ok(handle) = Handle::try_current() else {
+            return;
+        };
+        if self.watchdog.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let state = Arc::downgrade(self);
+        let period = (self.threshold / 2).max(Duration::from_millis(1));
+        handle.spawn(watchdog(state, period));
+    }
+}
+
+/// Watchdog task, exits when handler service is dropped.
+async fn watchdog(state: Weak<CpuGuardState>, period: Duration) {
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let Some(state) = state.upgrade() else {
+            break;
+        };
+        state.check();
+    }
+}
+
+/// CPU guard [`tower`] layer.
+#[derive(Clone, Debug)]
+pub(crate) struct CpuGuardLayer {
+    /// Shared state.
+    state: Arc<CpuGuardState>,
+}
+
+impl<S> Layer<S> for CpuGuardLayer {
+    type Service = CpuGuardService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CpuGuardService {
+            inner,
+            state: self.state.clone(),
+        }
+    }
+}
+
+/// CPU guard [`tower`] service.
+#[derive(Clone, Debug)]
+pub(crate) struct CpuGuardService<S> {
+    /// Inner service.
+    inner: S,
+    /// Shared state.
+    state: Arc<CpuGuardState>,
+}
+
+impl<S, R> Service<R> for CpuGuardService<S>
+where
+    S: Service<R>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = CpuGuardFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        self.state.ensure_watchdog();
+        let migrated = self.state.migrating()
+            && Handle::try_current()
+                .is_ok_and(|handle| handle.runtime_flavor() == RuntimeFlavor::MultiThread);
+        if migrated {
+            debug!(
+                handler = self.state.handler,
+                "running on blocking thread after CPU stall"
+            );
+        }
+        let id = self.state.next_id.fetch_add(1, Ordering::Relaxed);
+        let poll = Arc::new(PollState::default());
+        if !migrated {
+            self.state.in_flight.lock().insert(id, poll.clone());
+        }
+        CpuGuardFuture {
+            inner: self.inner.call(req),
+            state: self.state.clone(),
+            poll,
+            id,
+            migrated,
+        }
+    }
+}
+
+/// Response future of [`CpuGuardService`].
+#[pin_project(PinnedDrop)]
+pub(crate) struct CpuGuardFuture<F> {
+    /// Inner future.
+    #[pin]
+    inner: F,
+    /// Shared state.
+    state: Arc<CpuGuardState>,
+    /// Progress of this execution.
+    poll: Arc<PollState>,
+    /// Execution ID.
+    id: u64,
+    /// Whether execution was moved to blocking thread.
+    migrated: bool,
+}
+
+impl<F: Future> Future for CpuGuardFuture<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+        if *this.migrated {
+            // Blocking inside current poll keeps task locals of enclosing layers available.
+            let inner = this.inner.as_mut();
+            return Poll::Ready(tokio::task::block_in_place(|| {
+                Handle::current().block_on(inner)
+            }));
+        }
+        let start = this.state.now_us();
+        this.poll.reported.store(false, Ordering::Release);
+        this.poll.started.store(start + 1, Ordering::Release);
+        let res = this.inner.poll(cx);
+        this.poll.started.store(0, Ordering::Release);
+        let elapsed = Duration::from_micros(this.state.now_us().saturating_sub(start));
+        if elapsed > this.state.threshold && !this.poll.reported.swap(true, Ordering::AcqRel) {
+            this.state.report(elapsed);
+        }
+        res
+    }
+}
+
+#[pinned_drop]
+impl<F> PinnedDrop for CpuGuardFuture<F> {
+    fn drop(self: Pin<&mut Self>) {
+        if !self.migrated {
+            self.state.in_flight.lock().remove(&self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+
+    /// Busy-loop for a given duration, without yielding.
+    fn spin(duration: Duration) {
+        let start = Instant::now();
+        while start.elapsed() < duration {
+            std::hint::spin_loop();
+        }
+    }
+
+    fn layer(threshold_ms: u64, migrate: bool) -> CpuGuardLayer {
+        CpuGuardConfig::default()
+            .with_threshold(Duration::from_millis(threshold_ms))
+            .with_migrate(migrate)
+            .make_layer("spinner", None)
+    }
+
+    async fn call(layer: &CpuGuardLayer, spin_ms: u64) {
+        layer
+            .layer(service_fn(move |()| async move {
+                spin(Duration::from_millis(spin_ms));
+                Ok::<_, Infallible>(())
+            }))
+            .oneshot(())
+            .await
+            .unwrap();
+    }
+
+    /// Stalls are detected when poll returns, even if watchdog can't run.
+    #[tokio::test]
+    async fn detect_on_return() {
+        let layer = layer(10, false);
+        call(&layer, 1).await;
+        assert_eq!(layer.state.stalls.load(Ordering::Relaxed), 0);
+        call(&layer, 30).await;
+        assert_eq!(layer.state.stalls.load(Ordering::Relaxed), 1);
+        assert!(!layer.state.migrating());
+        assert!(layer.state.in_flight.lock().is_empty());
+    }
+
+    /// Watchdog reports a stall while handler is still spinning.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn detect_in_progress() {
+        let layer = layer(20, false);
+        call(&layer, 0).await;
+        let done = Arc::new(AtomicBool::new(false));
+        let task = tokio::spawn({
+            let (layer, done) = (layer.clone(), done.clone());
+            async move {
+                call(&layer, 500).await;
+                done.store(true, Ordering::Release);
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(layer.state.stalls.load(Ordering::Relaxed), 1);
+        assert!(!done.load(Ordering::Acquire));
+        task.await.unwrap();
+        // Stall is reported once per poll.
+        assert_eq!(layer.state.stalls.load(Ordering::Relaxed), 1);
+    }
+
+    /// After a stall, executions are moved off the worker thread, so other tasks keep running.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn migration() {
+        let layer = layer(10, true);
+        assert!(!layer.state.migrating());
+        call(&layer, 30).await;
+        assert_eq!(layer.state.stalls.load(Ordering::Relaxed), 1);
+        assert!(layer.state.migrating());
+
+        let ticks = Arc::new(Mutex::new(Vec::new()));
+        let ticker = tokio::spawn({
+            let ticks = ticks.clone();
+            async move {
+                loop {
+                    ticks.lock().push(Instant::now());
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+            }
+        });
+        let start = Instant::now();
+        tokio::spawn({
+            let layer = layer.clone();
+            async move { call(&layer, 300).await }
+        })
+        .await
+        .unwrap();
+        let end = Instant::now();
+        ticker.abort();
+        let during = ticks
+            .lock()
+            .iter()
+            .filter(|tick| **tick > start + Duration::from_millis(50))
+            .filter(|tick| **tick < end - Duration::from_millis(50))
+            .count();
+        assert!(during > 0, "worker thread was starved");
+        // Migrated executions are not measured.
+        assert_eq!(layer.state.stalls.load(Ordering::Relaxed), 1);
+
+        // Migration is disabled outside of cool-down period.
+        layer.state.migrate_until.store(0, Ordering::Relaxed);
+        assert!(!layer.state.migrating());
+    }
+}
@@ -0,0 +1,328 @@
+//! Request body decompression.
+//!
+//! Bodies with `Content-Encoding` of `gzip`, `deflate` or `br` are decoded before reaching
+//! handlers. Decoded size is capped, and if the cap is exceeded, whatever the handler produced is
+//! replaced with an error response.
+
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    task::{ready, Context, Poll},
+};
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    http::{header::CONTENT_ENCODING, Request, Response, StatusCode},
+    response::IntoResponse,
+};
+use futures::future::BoxFuture;
+use http_body::{Frame, SizeHint};
+use pin_project::pin_project;
+use thiserror::Error;
+use tower::{BoxError, Layer, Service};
+use tower_http::decompression::{DecompressionBody, RequestDecompression};
+use tracing::debug;
+
+use crate::errors::{codes, ErrorCode};
+
+/// Default cap on decompressed request body size.
+pub(crate) const DEFAULT_MAX_DECOMPRESSED_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Request decompression error.
+#[derive(Clone, Debug, Error)]
+#[non_exhaustive]
+pub enum DecompressionError {
+    /// Decompressed request body exceeds size limit.
+    #[error("Decompressed request body exceeds size limit of {0} bytes")]
+    TooLarge(u64),
+}
+
+impl DecompressionError {
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::TooLarge(_) => codes::DECOMPRESSED_TOO_LARGE,
+        }
+    }
+}
+
+impl IntoResponse for DecompressionError {
+    fn into_response(self) -> axum::response::Response {
+        let code = self.code();
+        let mut problem = code
+            .problem(StatusCode::PAYLOAD_TOO_LARGE)
+            .with_type("tag:uxum.github.io,2024:decompression")
+            .with_title(self.to_string());
+        match self {
+            Self::TooLarge(limit) => problem = problem.with_value("limit", limit),
+        }
+        (code, problem).into_response()
+    }
+}
+
+/// Response extension with decoded size of compressed request body.
+///
+/// Used by metrics layer.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DecodedBodySize(pub(crate) u64);
+
+/// Request extension marking requests with compressed bodies.
+#[derive(Clone, Copy, Debug)]
+struct Compressed;
+
+/// Request decompression [`tower`] layer.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RequestDecompressionLayer {
+    /// Maximum decompressed body size, in bytes.
+    limit: u64,
+}
+
+impl RequestDecompressionLayer {
+    /// Create new request decompression layer.
+    #[must_use]
+    pub(crate) fn new(limit: u64) -> Self {
+        Self { limit }
+    }
+}
+
+impl<S> Layer<S> for RequestDecompressionLayer {
+    type Service = Decompression<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Decompression {
+            inner: RequestDecompression::new(DecodedLimit {
+                inner,
+                limit: self.limit,
+            }),
+        }
+    }
+}
+
+/// Request decompression [`tower`] service.
+#[derive(Clone, Debug)]
+pub(crate) struct Decompression<S> {
+    /// Inner service, wrapped in decoder.
+    inner: RequestDecompression<DecodedLimit<S>>,
+}
+
+impl<S> Service<Request<Body>> for Decompression<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Future: Send + 'static,
+    S::Error: 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        if req
+            .headers()
+            .get(CONTENT_ENCODING)
+            .is_some_and(|enc| enc != "identity")
+        {
+            req.extensions_mut().insert(Compressed);
+        }
+        let future = self.inner.call(req);
+        Box::pin(async move { Ok(future.await?.map(Body::new)) })
+    }
+}
+
+/// Decoded body size limit [`tower`] service.
+#[derive(Clone, Debug)]
+pub(crate) struct DecodedLimit<S> {
+    /// Inner service.
+    inner: S,
+    /// Maximum decompressed body size, in bytes.
+    limit: u64,
+}
+
+impl<S> Service<Request<DecompressionBody<Body>>> for DecodedLimit<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<DecompressionBody<Body>>) -> Self::Future {
+        if req.extensions().get::<Compressed>().is_none() {
+            return Box::pin(self.inner.call(req.map(Body::new)));
+        }
+        let limit = self.limit;
+        let decoded = Arc::new(AtomicU64::new(0));
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let req = req.map(|body| {
+            Body::new(DecodedBody {
+                inner: body,
+                limit,
+                decoded: decoded.clone(),
+                exceeded: exceeded.clone(),
+            })
+        });
+        let future = self.inner.call(req);
+        Box::pin(async move {
+            let result = future.await;
+            let mut resp = match exceeded.load(Ordering::Acquire) {
+                true => {
+                    debug!(limit, "decompressed request body exceeded size limit");
+                    DecompressionError::TooLarge(limit).into_response()
+                }
+                false => result?,
+            };
+            resp.extensions_mut()
+                .insert(DecodedBodySize(decoded.load(Ordering::Acquire)));
+            Ok(resp)
+        })
+    }
+}
+
+/// Decoded request body wrapper, which counts bytes and fails once size limit is exceeded.
+#[pin_project]
+struct DecodedBody<B> {
+    /// Inner body.
+    #[pin]
+    inner: B,
+    /// Maximum decompressed body size, in bytes.
+    limit: u64,
+    /// Bytes decoded so far.
+    decoded: Arc<AtomicU64>,
+    /// Set when limit is exceeded.
+    exceeded: Arc<AtomicBool>,
+}
+
+impl<B> HttpBody for DecodedBody<B>
+where
+    B: HttpBody<Data = Bytes>,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = match ready!(this.inner.poll_frame(cx)) {
+            Some(Ok(frame)) => frame,
+            Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+            None => return Poll::Ready(None),
+        };
+        if let Some(data) = frame.data_ref() {
+            let len = data.len() as u64;
+            let total = this.decoded.fetch_add(len, Ordering::AcqRel) + len;
+            if total > *this.limit {
+                this.exceeded.store(true, Ordering::Release);
+                return Poll::Ready(Some(Err(DecompressionError::TooLarge(*this.limit).into())));
+            }
+        }
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use axum::http::header::CONTENT_LENGTH;
+    use flate2::{write::GzEncoder, Compression};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// Layer around handler which echoes request body.
+    fn service(
+        limit: u64,
+    ) -> impl Service<Request<Body>, Response = Response<Body>, Error = BoxError> {
+        RequestDecompressionLayer::new(limit).layer(tower::service_fn(
+            |req: Request<Body>| async move {
+                match axum::body::to_bytes(req.into_body(), usize::MAX).await {
+                    Ok(bytes) => Ok::<_, BoxError>(bytes.into_response()),
+                    Err(_) => Ok(StatusCode::BAD_REQUEST.into_response()),
+                }
+            },
+        ))
+    }
+
+    fn gzip_request(data: &[u8]) -> Request<Body> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        let body = encoder.finish().unwrap();
+        Request::builder()
+            .header(CONTENT_ENCODING, "gzip")
+            .header(CONTENT_LENGTH, body.len())
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    /// Compressed bodies are decoded, and decoded size is reported.
+    #[tokio::test]
+    async fn decoded() {
+        let data = "0123456789".repeat(100);
+        let resp = service(1000)
+            .oneshot(gzip_request(data.as_bytes()))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.extensions().get::<DecodedBodySize>().unwrap().0, 1000);
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(bytes, data);
+    }
+
+    /// Uncompressed bodies are passed through as is.
+    #[tokio::test]
+    async fn uncompressed() {
+        let resp = service(5)
+            .oneshot(Request::new(Body::from("0123456789")))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.extensions().get::<DecodedBodySize>().is_none());
+    }
+
+    /// Decoding stops once size limit is exceeded.
+    #[tokio::test]
+    async fn zip_bomb() {
+        let data = vec![0; 1024 * 1024];
+        let resp = service(64 * 1024)
+            .oneshot(gzip_request(&data))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            resp.extensions().get(),
+            Some(&codes::DECOMPRESSED_TOO_LARGE)
+        );
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["code"], "body.decompressed_too_large");
+        assert_eq!(body["type"], "tag:uxum.github.io,2024:decompression");
+        assert_eq!(body["limit"], 64 * 1024);
+    }
+}
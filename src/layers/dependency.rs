@@ -0,0 +1,286 @@
+//! Per-request downstream dependency latency attribution.
+//!
+//! Time spent awaiting downstream dependencies (instrumented HTTP clients, connection pools,
+//! anything wrapped in [`track_dependency`]) is accumulated per request, and reported when the
+//! response is ready, along with the remaining "self" time.
+//!
+//! Concurrent awaits are accounted independently, so when a handler awaits several dependencies
+//! at once, sum of dependency times may exceed wall time of the request. In that case self time
+//! is reported as zero.
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    mem,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use axum::http::{HeaderValue, Request, Response};
+use futures::future::BoxFuture;
+use opentelemetry::{metrics::Histogram, KeyValue};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tower::{BoxError, Layer, Service};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Name used for time not attributed to any dependency.
+const SELF_NAME: &str = "self";
+
+/// Standard header used to expose timing breakdown.
+const SERVER_TIMING: &str = "server-timing";
+
+tokio::task_local! {
+    /// Dependency time ledger of currently executing request.
+    static CURRENT_LEDGER: Ledger;
+}
+
+/// Accumulated dependency times of a single request.
+#[derive(Clone, Debug, Default)]
+struct Ledger(Arc<Mutex<BTreeMap<String, Duration>>>);
+
+/// Record time spent awaiting a downstream dependency.
+///
+/// Does nothing outside of request handling task, or if dependency timing is not enabled.
+pub fn record_dependency(name: &str, elapsed: Duration) {
+    let _ = CURRENT_LEDGER.try_with(|ledger| {
+        *ledger.0.lock().entry(name.to_string()).or_default() += elapsed;
+    });
+}
+
+/// Await future, attributing time spent to a downstream dependency.
+///
+/// Use this to wrap connection pool operations, database queries and other calls not made via
+/// instrumented HTTP clients.
+pub async fn track_dependency<F: Future>(name: &str, fut: F) -> F::Output {
+    let start = Instant::now();
+    let out = fut.await;
+    record_dependency(name, start.elapsed());
+    out
+}
+
+/// Attribute wall time of a request to dependencies.
+///
+/// Returns per-dependency totals, followed by self time.
+fn attribute(wall: Duration, deps: BTreeMap<String, Duration>) -> Vec<(String, Duration)> {
+    let total: Duration = deps.values().sum();
+    let mut times: Vec<_> = deps.into_iter().collect();
+    times.push((SELF_NAME.into(), wall.saturating_sub(total)));
+    times
+}
+
+/// Format timing breakdown as `Server-Timing` header value.
+fn server_timing(times: &[(String, Duration)]) -> Option<HeaderValue> {
+    let value = times
+        .iter()
+        .map(|(name, elapsed)| format!("{name};dur={:.1}", elapsed.as_secs_f64() * 1000.0))
+        .collect::<Vec<_>>()
+        .join(", ");
+    HeaderValue::from_str(&value).ok()
+}
+
+/// Downstream dependency latency attribution configuration.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct DependencyTimingConfig {
+    /// Add `Server-Timing` header with timing breakdown to responses.
+    ///
+    /// Only enable this for internal services, as it exposes names of downstream dependencies.
+    #[serde(default)]
+    pub response_header: bool,
+}
+
+impl DependencyTimingConfig {
+    /// Create layer for use in [`tower`] services.
+    #[must_use]
+    pub(crate) fn make_layer(
+        &self,
+        handler: &'static str,
+        metrics: Option<Histogram<f64>>,
+    ) -> DependencyTimingLayer {
+        DependencyTimingLayer {
+            timing: Arc::new(DependencyTiming {
+                handler,
+                response_header: self.response_header,
+                metrics,
+            }),
+        }
+    }
+}
+
+/// Shared state of dependency timing layer.
+struct DependencyTiming {
+    /// Handler name, used in metric labels.
+    handler: &'static str,
+    /// Whether to add `Server-Timing` header.
+    response_header: bool,
+    /// Dependency time histogram.
+    metrics: Option<Histogram<f64>>,
+}
+
+impl DependencyTiming {
+    /// Report timing breakdown of a finished request.
+    fn report(&self, times: &[(String, Duration)]) {
+        let span = Span::current();
+        for (name, elapsed) in times {
+            let millis = elapsed.as_secs_f64() * 1000.0;
+            span.set_attribute(format!("uxum.dep.{name}_ms"), millis);
+            if let Some(metrics) = &self.metrics {
+                metrics.record(
+                    elapsed.as_secs_f64(),
+                    &[
+                        KeyValue::new("uxum.handler", self.handler),
+                        KeyValue::new("uxum.dependency", name.clone()),
+                    ],
+                );
+            }
+        }
+    }
+}
+
+/// Dependency timing [`tower`] layer.
+#[derive(Clone)]
+pub(crate) struct DependencyTimingLayer {
+    /// Shared layer state.
+    timing: Arc<DependencyTiming>,
+}
+
+impl<S> Layer<S> for DependencyTimingLayer {
+    type Service = DependencyTimingService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        DependencyTimingService {
+            inner,
+            timing: Arc::clone(&self.timing),
+        }
+    }
+}
+
+/// Dependency timing [`tower`] service.
+#[derive(Clone)]
+pub(crate) struct DependencyTimingService<S> {
+    /// Inner service.
+    inner: S,
+    /// Shared layer state.
+    timing: Arc<DependencyTiming>,
+}
+
+impl<S, T, U> Service<Request<T>> for DependencyTimingService<S>
+where
+    S: Service<Request<T>, Response = Response<U>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    T: Send + 'static,
+    U: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<T>) -> Self::Future {
+        let timing = Arc::clone(&self.timing);
+        // Use the service which was polled ready, leave a fresh clone in its place.
+        let clone = self.inner.clone();
+        let mut inner = mem::replace(&mut self.inner, clone);
+        let ledger = Ledger::default();
+        let fut = CURRENT_LEDGER.scope(ledger.clone(), inner.call(req));
+        Box::pin(async move {
+            let start = Instant::now();
+            let res = fut.await;
+            let deps = mem::take(&mut *ledger.0.lock());
+            let times = attribute(start.elapsed(), deps);
+            timing.report(&times);
+            let mut resp = res.map_err(Into::into)?;
+            if timing.response_header {
+                if let Some(value) = server_timing(&times) {
+                    resp.headers_mut().append(SERVER_TIMING, value);
+                }
+            }
+            Ok(resp)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// Self time is wall time minus all dependency times.
+    #[test]
+    fn attribution_math() {
+        let deps = BTreeMap::from([
+            ("billing".to_string(), Duration::from_millis(30)),
+            ("redis".to_string(), Duration::from_millis(15)),
+        ]);
+        let times = attribute(Duration::from_millis(100), deps);
+        assert_eq!(
+            times,
+            [
+                ("billing".to_string(), Duration::from_millis(30)),
+                ("redis".to_string(), Duration::from_millis(15)),
+                ("self".to_string(), Duration::from_millis(55)),
+            ]
+        );
+
+        // Concurrent awaits may exceed wall time.
+        let deps = BTreeMap::from([
+            ("billing".to_string(), Duration::from_millis(80)),
+            ("redis".to_string(), Duration::from_millis(80)),
+        ]);
+        let times = attribute(Duration::from_millis(100), deps);
+        assert_eq!(times[2], ("self".to_string(), Duration::ZERO));
+        assert_eq!(
+            server_timing(&times).unwrap(),
+            "billing;dur=80.0, redis;dur=80.0, self;dur=0.0"
+        );
+    }
+
+    /// Handler calling a fake pool and a fake HTTP client gets its time attributed.
+    #[tokio::test]
+    async fn handler_attribution() {
+        let handler = tower::service_fn(|_req: Request<Body>| async {
+            // Fake pool operations.
+            for _ in 0..2 {
+                track_dependency("redis", tokio::time::sleep(Duration::from_millis(10))).await;
+            }
+            // Fake HTTP client call.
+            record_dependency("billing", Duration::from_millis(25));
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        });
+        let config = DependencyTimingConfig {
+            response_header: true,
+        };
+        let resp = config
+            .make_layer("test", None)
+            .layer(handler)
+            .oneshot(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        let header = resp.headers()[SERVER_TIMING].to_str().unwrap();
+        let times: BTreeMap<_, _> = header
+            .split(", ")
+            .filter_map(|entry| {
+                let (name, dur) = entry.split_once(";dur=")?;
+                Some((name, dur.parse::<f64>().ok()?))
+            })
+            .collect();
+        assert_eq!(times.len(), 3);
+        assert_eq!(times["billing"], 25.0);
+        assert!(times["redis"] >= 20.0);
+        assert!(times["self"] >= 0.0);
+
+        // Outside of request scope, recording is a no-op.
+        record_dependency("redis", Duration::from_secs(1));
+    }
+}
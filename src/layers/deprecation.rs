@@ -0,0 +1,486 @@
+//! [`tower`] layer to signal deprecation of handlers to clients.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    body::Body,
+    http::{HeaderName, HeaderValue, Request, Response, StatusCode},
+    response::IntoResponse,
+};
+use dashmap::DashMap;
+use opentelemetry::{metrics::Counter, KeyValue};
+use pin_project::pin_project;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tower::{BoxError, Layer, Service};
+use tracing::{info, warn};
+
+use crate::{
+    auth::UserId,
+    errors::{codes, ErrorCode},
+    metrics::LabelGuard,
+};
+
+/// Name of `Deprecation` header.
+const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+
+/// Name of `Sunset` header, as defined in RFC 8594.
+const SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+/// Error returned when calling a handler past its sunset date.
+#[derive(Clone, Debug, Error)]
+#[non_exhaustive]
+pub enum DeprecationError {
+    /// Handler was removed.
+    #[error("Handler {handler} is no longer available")]
+    Gone {
+        /// Handler name.
+        handler: &'static str,
+        /// Link to successor version, if any.
+        successor: Option<String>,
+    },
+}
+
+impl DeprecationError {
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Gone { .. } => codes::GONE,
+        }
+    }
+}
+
+impl IntoResponse for DeprecationError {
+    fn into_response(self) -> Response<Body> {
+        match self {
+            Self::Gone {
+                ref successor,
+                handler: _,
+            } => {
+                let code = self.code();
+                let mut problem = code
+                    .problem(StatusCode::GONE)
+                    .with_type("tag:uxum.github.io,2024:gone")
+                    .with_title(self.to_string());
+                if let Some(successor) = successor {
+                    problem = problem.with_value("successor", successor.clone());
+                }
+                (code, problem).into_response()
+            }
+        }
+    }
+}
+
+/// Behavior of deprecated handler after its sunset date.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum SunsetPolicy {
+    /// Keep serving requests, with deprecation headers.
+    #[default]
+    Serve,
+    /// Reject requests with 410 Gone.
+    Reject,
+}
+
+/// Handler deprecation configuration.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct DeprecationConfig {
+    /// Date since which the handler is deprecated.
+    ///
+    /// If set, sent as `Deprecation` header value, otherwise `Deprecation: true` is sent.
+    #[serde(default, with = "humantime_serde")]
+    pub since: Option<SystemTime>,
+    /// Date after which the handler will be removed.
+    ///
+    /// Sent as `Sunset` header value.
+    #[serde(default, with = "humantime_serde")]
+    pub sunset: Option<SystemTime>,
+    /// Link to successor version of the handler.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub successor: Option<String>,
+    /// Behavior after sunset date.
+    #[serde(default)]
+    pub after_sunset: SunsetPolicy,
+}
+
+impl DeprecationConfig {
+    /// Build deprecation layer.
+    pub(crate) fn make_layer(
+        &self,
+        handler: &'static str,
+        usage: Option<(Counter<u64>, LabelGuard)>,
+        tracker: Option<DeprecationTracker>,
+    ) -> DeprecationLayer {
+        let mut headers = vec![(
+            DEPRECATION,
+            match self.since {
+                Some(since) => http_date(since),
+                None => HeaderValue::from_static("true"),
+            },
+        )];
+        if let Some(sunset) = self.sunset {
+            headers.push((SUNSET, http_date(sunset)));
+        }
+        if let Some(successor) = &self.successor {
+            match HeaderValue::from_str(&format!("<{successor}>; rel=\"successor-version\"")) {
+                Ok(val) => headers.push((axum::http::header::LINK, val)),
+                Err(_) => warn!(handler, successor, "invalid successor link"),
+            }
+        }
+        DeprecationLayer {
+            handler,
+            headers: headers.into(),
+            reject_after: match self.after_sunset {
+                SunsetPolicy::Serve => None,
+                SunsetPolicy::Reject => self.sunset,
+            },
+            successor: self.successor.clone(),
+            usage,
+            tracker,
+        }
+    }
+}
+
+/// Format timestamp as HTTP-date header value.
+fn http_date(time: SystemTime) -> HeaderValue {
+    // SAFETY: HTTP-date is always a valid header value.
+    HeaderValue::from_str(&httpdate::fmt_http_date(time)).unwrap()
+}
+
+/// Deprecated handler usage report configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct DeprecationReportConfig {
+    /// Interval between usage summary log messages.
+    #[serde(
+        default = "DeprecationReportConfig::default_interval",
+        with = "humantime_serde"
+    )]
+    pub interval: Duration,
+    /// Number of top clients listed in summary.
+    #[serde(default = "DeprecationReportConfig::default_top")]
+    pub top: usize,
+}
+
+impl Default for DeprecationReportConfig {
+    fn default() -> Self {
+        Self {
+            interval: Self::default_interval(),
+            top: Self::default_top(),
+        }
+    }
+}
+
+impl DeprecationReportConfig {
+    /// Default value for [`Self::interval`].
+    #[must_use]
+    #[inline]
+    fn default_interval() -> Duration {
+        Duration::from_secs(3600)
+    }
+
+    /// Default value for [`Self::top`].
+    #[must_use]
+    #[inline]
+    fn default_top() -> usize {
+        10
+    }
+}
+
+/// In-process tracker of deprecated handler usage, for periodic summary logs.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DeprecationTracker(Arc<DashMap<(&'static str, String), u64>>);
+
+impl DeprecationTracker {
+    /// Record single request.
+    fn record(&self, handler: &'static str, client: String) {
+        *self.0.entry((handler, client)).or_default() += 1;
+    }
+
+    /// Take usage counts accumulated since last call, sorted from most to least used.
+    fn take(&self) -> Vec<(&'static str, String, u64)> {
+        let keys: Vec<_> = self.0.iter().map(|entry| entry.key().clone()).collect();
+        let mut usage: Vec<_> = keys
+            .into_iter()
+            .filter_map(|key| self.0.remove(&key))
+            .map(|((handler, client), count)| (handler, client, count))
+            .collect();
+        usage.sort_by_key(|(_, _, count)| std::cmp::Reverse(*count));
+        usage
+    }
+
+    /// Periodically log top clients still using deprecated handlers.
+    pub(crate) async fn report(self, config: DeprecationReportConfig) {
+        let mut interval = tokio::time::interval(config.interval);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let usage = self.take();
+            if usage.is_empty() {
+                continue;
+            }
+            let total: u64 = usage.iter().map(|(_, _, count)| count).sum();
+            let top: Vec<_> = usage
+                .iter()
+                .take(config.top)
+                .map(|(handler, client, count)| format!("{handler}:{client}={count}"))
+                .collect();
+            info!(total, top = ?top, "deprecated handlers still in use");
+        }
+    }
+}
+
+/// Deprecation [`tower`] layer.
+#[derive(Clone, Debug)]
+pub(crate) struct DeprecationLayer {
+    /// Handler name.
+    handler: &'static str,
+    /// Headers added to every response.
+    headers: Arc<[(HeaderName, HeaderValue)]>,
+    /// Reject requests after this time.
+    reject_after: Option<SystemTime>,
+    /// Link to successor version of the handler.
+    successor: Option<String>,
+    /// Usage counter and client label guard.
+    usage: Option<(Counter<u64>, LabelGuard)>,
+    /// Usage tracker for summary logs.
+    tracker: Option<DeprecationTracker>,
+}
+
+impl<S> Layer<S> for DeprecationLayer {
+    type Service = Deprecation<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Deprecation {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Deprecation [`tower`] service.
+#[derive(Clone, Debug)]
+pub(crate) struct Deprecation<S> {
+    /// Inner service.
+    inner: S,
+    /// Layer configuration.
+    layer: DeprecationLayer,
+}
+
+impl<S, T, U> Service<Request<T>> for Deprecation<S>
+where
+    S: Service<Request<T>, Response = Response<U>>,
+    S::Error: Into<BoxError>,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = DeprecationFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<T>) -> Self::Future {
+        let layer = &self.layer;
+        let client = req
+            .extensions()
+            .get::<UserId>()
+            .map(|user| user.to_string());
+        if let Some((counter, clients)) = &layer.usage {
+            counter.add(
+                1,
+                &[
+                    KeyValue::new("uxum.handler", layer.handler),
+                    KeyValue::new("uxum.client", clients.label(client.as_deref())),
+                ],
+            );
+        }
+        if let Some(tracker) = &layer.tracker {
+            tracker.record(layer.handler, client.unwrap_or_default());
+        }
+        if layer
+            .reject_after
+            .is_some_and(|sunset| SystemTime::now() >= sunset)
+        {
+            return DeprecationFuture::Negative {
+                error: DeprecationError::Gone {
+                    handler: layer.handler,
+                    successor: layer.successor.clone(),
+                },
+            };
+        }
+        DeprecationFuture::Positive {
+            inner: self.inner.call(req),
+            headers: layer.headers.clone(),
+        }
+    }
+}
+
+/// Deprecation [`tower`] service future.
+#[pin_project(project = ProjectedOutcome)]
+pub(crate) enum DeprecationFuture<F> {
+    /// Handler is still available, calling inner service.
+    Positive {
+        /// Inner future.
+        #[pin]
+        inner: F,
+        /// Headers added to response.
+        headers: Arc<[(HeaderName, HeaderValue)]>,
+    },
+    /// Handler is past its sunset date.
+    Negative {
+        /// Cause of negative response.
+        error: DeprecationError,
+    },
+}
+
+impl<F, U, E> Future for DeprecationFuture<F>
+where
+    F: Future<Output = Result<Response<U>, E>>,
+    E: Into<BoxError>,
+{
+    type Output = Result<Response<U>, BoxError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            ProjectedOutcome::Positive { inner, headers } => {
+                let mut resp = ready!(inner.poll(cx).map_err(Into::into))?;
+                for (name, value) in headers.iter() {
+                    resp.headers_mut().insert(name.clone(), value.clone());
+                }
+                Poll::Ready(Ok(resp))
+            }
+            ProjectedOutcome::Negative { error } => Poll::Ready(Err(Box::new(error.clone()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use axum::http::header::LINK;
+    use opentelemetry_sdk::Resource;
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::MetricsBuilder;
+
+    async fn call(layer: &DeprecationLayer, user: Option<&str>) -> Response<Body> {
+        let svc = layer.clone().layer(service_fn(|_| async {
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        }));
+        let mut req = Request::new(Body::empty());
+        if let Some(user) = user {
+            req.extensions_mut().insert(UserId::from(user));
+        }
+        match svc.oneshot(req).await {
+            Ok(resp) => resp,
+            Err(err) => crate::builder::app::error_handler(err).await,
+        }
+    }
+
+    /// Deprecation, sunset and successor headers are emitted.
+    #[tokio::test]
+    async fn headers() {
+        let layer = DeprecationConfig::default().make_layer("test", None, None);
+        let resp = call(&layer, None).await;
+        assert_eq!(resp.headers()[DEPRECATION], "true");
+        assert!(!resp.headers().contains_key(SUNSET));
+
+        let since = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let sunset = SystemTime::now() + Duration::from_secs(86400);
+        let cfg = DeprecationConfig {
+            since: Some(since),
+            sunset: Some(sunset),
+            successor: Some("/v2/test".into()),
+            after_sunset: SunsetPolicy::Reject,
+        };
+        let resp = call(&cfg.make_layer("test", None, None), None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[DEPRECATION], "Tue, 14 Nov 2023 22:13:20 GMT");
+        assert_eq!(resp.headers()[SUNSET], http_date(sunset));
+        assert_eq!(
+            resp.headers()[LINK],
+            "</v2/test>; rel=\"successor-version\""
+        );
+    }
+
+    /// Requests past sunset date are served or rejected, depending on policy.
+    #[tokio::test]
+    async fn after_sunset() {
+        let mut cfg = DeprecationConfig {
+            sunset: Some(SystemTime::now() - Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let resp = call(&cfg.make_layer("test", None, None), None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        cfg.after_sunset = SunsetPolicy::Reject;
+        let resp = call(&cfg.make_layer("test", None, None), None).await;
+        assert_eq!(resp.status(), StatusCode::GONE);
+    }
+
+    /// Usage is counted per client.
+    #[tokio::test]
+    async fn per_client_counter() {
+        let state = MetricsBuilder::default()
+            .with_max_clients(2)
+            .build_state(Resource::empty())
+            .unwrap();
+        let tracker = DeprecationTracker::default();
+        let layer = DeprecationConfig::default().make_layer(
+            "test",
+            Some(state.deprecated_requests()),
+            Some(tracker.clone()),
+        );
+        for user in [
+            Some("alice"),
+            Some("alice"),
+            None,
+            Some("bob"),
+            Some("carol"),
+        ] {
+            call(&layer, user).await;
+        }
+        let mut counts: Vec<_> = state
+            .registry
+            .gather()
+            .into_iter()
+            .filter(|fam| fam.get_name() == "http_server_deprecated_requests_total")
+            .flat_map(|fam| fam.get_metric().to_vec())
+            .map(|m| {
+                let client = m
+                    .get_label()
+                    .iter()
+                    .find(|l| l.get_name() == "uxum_client")
+                    .unwrap()
+                    .get_value()
+                    .to_owned();
+                (client, m.get_counter().get_value() as u64)
+            })
+            .collect();
+        counts.sort();
+        assert_eq!(
+            counts,
+            [
+                ("<anonymous>".to_owned(), 1),
+                ("<other>".to_owned(), 1),
+                ("alice".to_owned(), 2),
+                ("bob".to_owned(), 1),
+            ]
+        );
+        let usage = tracker.take();
+        assert_eq!(usage[0], ("test", "alice".to_owned(), 2));
+        assert_eq!(usage.len(), 4);
+    }
+}
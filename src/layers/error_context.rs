@@ -0,0 +1,222 @@
+//! [`tower`] layer to add trace context to error responses.
+
+use std::task::{Context, Poll};
+
+use axum::{
+    body::{Body, HttpBody},
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+        HeaderValue, Request, Response,
+    },
+};
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use opentelemetry::trace::{SpanContext, TraceContextExt};
+use tower::{BoxError, Layer, Service};
+use tracing::{warn, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Maximum size of error response body which will be amended.
+const MAX_PROBLEM_SIZE: usize = 64 * 1024;
+
+/// Name of W3C trace context header.
+const TRACEPARENT: &str = "traceparent";
+
+/// Error response trace context [`tower`] layer.
+#[derive(Clone, Debug)]
+pub(crate) struct ErrorContextLayer {
+    /// Whether trace context is added.
+    enabled: bool,
+}
+
+impl ErrorContextLayer {
+    /// Create new error context layer.
+    #[must_use]
+    pub(crate) fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+impl<S> Layer<S> for ErrorContextLayer {
+    type Service = ErrorContext<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ErrorContext {
+            inner,
+            enabled: self.enabled,
+        }
+    }
+}
+
+/// Error response trace context [`tower`] service.
+#[derive(Clone, Debug)]
+pub(crate) struct ErrorContext<S> {
+    /// Inner service.
+    inner: S,
+    /// Whether trace context is added.
+    enabled: bool,
+}
+
+impl<S, T, U> Service<Request<T>> for ErrorContext<S>
+where
+    S: Service<Request<T>, Response = Response<U>>,
+    S::Future: Send + 'static,
+    U: HttpBody<Data = Bytes> + Send + 'static,
+    U::Error: Into<BoxError>,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<T>) -> Self::Future {
+        let future = self.inner.call(req);
+        let enabled = self.enabled;
+        Box::pin(async move {
+            let resp = future.await?.map(Body::new);
+            match enabled && is_problem(&resp) {
+                true => Ok(add_trace_context(resp).await),
+                false => Ok(resp),
+            }
+        })
+    }
+}
+
+/// Check if response is a problem details document.
+fn is_problem(resp: &Response<Body>) -> bool {
+    resp.headers()
+        .get(CONTENT_TYPE)
+        .is_some_and(|val| val.as_bytes().starts_with(b"application/problem+json"))
+}
+
+/// Add trace context of current span to problem details response.
+async fn add_trace_context(resp: Response<Body>) -> Response<Body> {
+    let span_ctx = Span::current().context().span().span_context().clone();
+    if !span_ctx.is_valid() {
+        return resp;
+    }
+    let (mut parts, body) = resp.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_PROBLEM_SIZE).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            warn!(%err, "unable to read error response body");
+            parts.headers.remove(CONTENT_LENGTH);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let mut problem = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Object(obj)) => obj,
+        _ => return Response::from_parts(parts, Body::from(bytes)),
+    };
+    problem.insert("trace_id".into(), span_ctx.trace_id().to_string().into());
+    match span_ctx.is_sampled() {
+        true => problem.insert("span_id".into(), span_ctx.span_id().to_string().into()),
+        false => problem.insert("sampled".into(), false.into()),
+    };
+    let Ok(body) = serde_json::to_vec(&problem) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    if let Some(val) = traceparent(&span_ctx) {
+        parts.headers.insert(TRACEPARENT, val);
+    }
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// Format W3C `traceparent` header value.
+fn traceparent(span_ctx: &SpanContext) -> Option<HeaderValue> {
+    HeaderValue::from_str(&format!(
+        "00-{}-{}-{:02x}",
+        span_ctx.trace_id(),
+        span_ctx.span_id(),
+        span_ctx.trace_flags().to_u8()
+    ))
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use axum::{http::StatusCode, response::IntoResponse};
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::{Config, Sampler, TracerProvider};
+    use tower::{service_fn, ServiceExt};
+    use tracing::{subscriber::DefaultGuard, Instrument};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    fn install_tracer(sampler: Sampler) -> DefaultGuard {
+        let provider = TracerProvider::builder()
+            .with_config(Config::default().with_sampler(sampler))
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        tracing::subscriber::set_default(subscriber)
+    }
+
+    async fn call(enabled: bool, problem: bool) -> (Response<Body>, serde_json::Value) {
+        let svc =
+            ErrorContextLayer::new(enabled).layer(service_fn(move |_: Request<Body>| async move {
+                Ok::<_, Infallible>(match problem {
+                    true => problemdetails::new(StatusCode::IM_A_TEAPOT)
+                        .with_title("Teapot")
+                        .into_response(),
+                    false => axum::Json(serde_json::json!({"ok": true})).into_response(),
+                })
+            }));
+        let resp = svc
+            .oneshot(Request::new(Body::empty()))
+            .instrument(tracing::info_span!("request"))
+            .await
+            .unwrap();
+        let (parts, body) = resp.into_parts();
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        let json = serde_json::from_slice(&bytes).unwrap();
+        (Response::from_parts(parts, Body::empty()), json)
+    }
+
+    /// Sampled span: trace ID and span ID are included.
+    #[tokio::test]
+    async fn sampled() {
+        let _guard = install_tracer(Sampler::AlwaysOn);
+        let (resp, body) = call(true, true).await;
+        let trace_id = body["trace_id"].as_str().unwrap();
+        let span_id = body["span_id"].as_str().unwrap();
+        assert!(body.get("sampled").is_none());
+        assert_eq!(
+            resp.headers()[TRACEPARENT],
+            format!("00-{trace_id}-{span_id}-01").as_str()
+        );
+    }
+
+    /// Unsampled span: only trace ID is included, with explicit flag.
+    #[tokio::test]
+    async fn unsampled() {
+        let _guard = install_tracer(Sampler::AlwaysOff);
+        let (resp, body) = call(true, true).await;
+        assert_eq!(body["trace_id"].as_str().unwrap().len(), 32);
+        assert!(body.get("span_id").is_none());
+        assert_eq!(body["sampled"], false);
+        assert!(resp.headers()[TRACEPARENT]
+            .to_str()
+            .unwrap()
+            .ends_with("-00"));
+    }
+
+    /// Disabled in config, or not an error response.
+    #[tokio::test]
+    async fn not_included() {
+        let _guard = install_tracer(Sampler::AlwaysOn);
+        let (resp, body) = call(false, true).await;
+        assert!(body.get("trace_id").is_none());
+        assert!(!resp.headers().contains_key(TRACEPARENT));
+        let (resp, body) = call(true, false).await;
+        assert_eq!(body, serde_json::json!({"ok": true}));
+        assert!(!resp.headers().contains_key(TRACEPARENT));
+    }
+}
@@ -5,7 +5,7 @@ use std::{
     fmt,
     hash::Hash,
     ops::{Deref, DerefMut},
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use tokio::time::Instant as TokioInstant;
@@ -73,7 +73,7 @@ impl Deadline {
     /// Check if deadline has passed.
     #[must_use]
     pub fn is_expired(&self) -> bool {
-        self.0 >= Instant::now()
+        self.0 <= Instant::now()
     }
 
     /// Get remaining time.
@@ -81,7 +81,27 @@ impl Deadline {
     /// Returns [`None`] if deadline has passed.
     #[must_use]
     pub fn time_left(&self) -> Option<Duration> {
-        Instant::now().checked_duration_since(self.0)
+        self.0.checked_duration_since(Instant::now())
+    }
+
+    /// Construct [`Deadline`] from wall clock time, in milliseconds since UNIX epoch.
+    ///
+    /// Deadlines in the past produce an already expired [`Deadline`].
+    #[must_use]
+    pub fn from_unix_millis(millis: u64) -> Self {
+        let at = UNIX_EPOCH + Duration::from_millis(millis);
+        match at.duration_since(SystemTime::now()) {
+            Ok(left) => Self::from(left),
+            Err(_) => Self::new(),
+        }
+    }
+
+    /// Convert to wall clock time, in milliseconds since UNIX epoch.
+    #[must_use]
+    pub fn to_unix_millis(&self) -> u64 {
+        let at = SystemTime::now() + self.time_left().unwrap_or_default();
+        at.duration_since(UNIX_EPOCH)
+            .map_or(0, |dur| u64::try_from(dur.as_millis()).unwrap_or(u64::MAX))
     }
 }
 
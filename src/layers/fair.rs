@@ -0,0 +1,670 @@
+//! Weighted fair queuing [`tower`] layer.
+//!
+//! Requests are queued per tenant and dispatched to a bounded number of concurrent executions
+//! using deficit round-robin, so that each backlogged tenant receives a share of throughput
+//! proportional to its weight.
+
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    mem,
+    num::{NonZeroU32, NonZeroUsize},
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    http::{Request, Response, StatusCode},
+    response::IntoResponse,
+};
+use futures::future::BoxFuture;
+use opentelemetry::KeyValue;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::oneshot;
+use tower::{BoxError, Layer, Service};
+use tracing::{trace_span, warn};
+
+use crate::{
+    auth::UserId,
+    errors::{codes, ErrorCode},
+    metrics::FairQueueMetrics,
+    retry::{RetryAdvice, RetryAdviceConfig, RetrySource},
+};
+
+/// Metric label value used for tenants without explicitly configured weight.
+const OTHER_TENANT: &str = "other";
+
+/// Error type returned by fair queuing layer.
+#[derive(Clone, Debug, Error)]
+#[non_exhaustive]
+pub enum FairQueueError {
+    /// Tenant has too many queued requests.
+    #[error("Too many queued requests for tenant")]
+    QueueFull {
+        /// Advice used for `Retry-After` header.
+        advice: RetryAdvice,
+    },
+    /// Queue was shut down while request was waiting.
+    #[error("Request queue was shut down")]
+    Closed,
+}
+
+impl FairQueueError {
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::QueueFull { .. } => codes::FAIR_QUEUE_FULL,
+            Self::Closed => codes::FAIR_QUEUE_CLOSED,
+        }
+    }
+}
+
+impl IntoResponse for FairQueueError {
+    fn into_response(self) -> Response<Body> {
+        let status = match self {
+            Self::QueueFull { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::Closed => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        let code = self.code();
+        let problem = code
+            .problem(status)
+            .with_type("tag:uxum.github.io,2024:fair-queue")
+            .with_title(self.to_string());
+        match self {
+            Self::QueueFull { advice } => (code, advice.problem_response(problem)),
+            Self::Closed => (code, problem.into_response()),
+        }
+        .into_response()
+    }
+}
+
+/// Configuration for weighted fair queuing layer.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct HandlerFairQueueConfig {
+    /// Source of tenant identifier.
+    ///
+    /// Requests without tenant identifier share a single anonymous tenant.
+    #[serde(default)]
+    tenant: TenantSource,
+    /// Maximum number of concurrently executing requests.
+    concurrency: NonZeroUsize,
+    /// Weights of individual tenants.
+    ///
+    /// Only tenants listed here get their own metric labels, all others are reported as
+    /// `other`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    weights: HashMap<String, NonZeroU32>,
+    /// Weight of tenants not listed in [`Self::weights`].
+    #[serde(default = "HandlerFairQueueConfig::default_weight")]
+    default_weight: NonZeroU32,
+    /// Maximum number of queued requests per tenant.
+    #[serde(default = "HandlerFairQueueConfig::default_queue_size")]
+    queue_size: usize,
+    /// Starvation protection: requests waiting longer than this are dispatched ahead of fair
+    /// order, oldest first.
+    #[serde(
+        default = "HandlerFairQueueConfig::default_max_wait",
+        with = "humantime_serde"
+    )]
+    max_wait: Duration,
+}
+
+impl HandlerFairQueueConfig {
+    /// Create new configuration with provided concurrency limit.
+    #[must_use]
+    pub fn new(concurrency: NonZeroUsize) -> Self {
+        Self {
+            tenant: TenantSource::default(),
+            concurrency,
+            weights: HashMap::new(),
+            default_weight: Self::default_weight(),
+            queue_size: Self::default_queue_size(),
+            max_wait: Self::default_max_wait(),
+        }
+    }
+
+    /// Default value for [`Self::default_weight`].
+    #[must_use]
+    #[inline]
+    fn default_weight() -> NonZeroU32 {
+        NonZeroU32::MIN
+    }
+
+    /// Default value for [`Self::queue_size`].
+    #[must_use]
+    #[inline]
+    fn default_queue_size() -> usize {
+        64
+    }
+
+    /// Default value for [`Self::max_wait`].
+    #[must_use]
+    #[inline]
+    fn default_max_wait() -> Duration {
+        Duration::from_secs(10)
+    }
+
+    /// Use value of request header as tenant identifier.
+    #[must_use]
+    pub fn with_tenant_header(mut self, name: impl ToString) -> Self {
+        self.tenant = TenantSource::Header(name.to_string());
+        self
+    }
+
+    /// Set weight of a tenant.
+    #[must_use]
+    pub fn with_weight(mut self, tenant: impl ToString, weight: NonZeroU32) -> Self {
+        self.weights.insert(tenant.to_string(), weight);
+        self
+    }
+
+    /// Set maximum number of queued requests per tenant.
+    #[must_use]
+    pub fn with_queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size;
+        self
+    }
+
+    /// Set maximum wait time before request is dispatched ahead of fair order.
+    #[must_use]
+    pub fn with_max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = max_wait;
+        self
+    }
+
+    /// Create layer for use in [`tower`] services.
+    #[must_use]
+    pub(crate) fn make_layer(
+        &self,
+        handler: &'static str,
+        metrics: Option<FairQueueMetrics>,
+        retry: &RetryAdviceConfig,
+    ) -> FairQueueLayer {
+        FairQueueLayer {
+            queue: Arc::new(FairQueue {
+                config: self.clone(),
+                handler,
+                metrics,
+                retry: retry.clone(),
+                state: Mutex::new(QueueState::default()),
+            }),
+        }
+    }
+}
+
+/// Source of tenant identifier.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+enum TenantSource {
+    /// Authenticated user ID.
+    #[default]
+    UserId,
+    /// Value of a request header.
+    Header(String),
+}
+
+/// Weighted fair queuing [`tower`] layer.
+#[derive(Clone)]
+pub(crate) struct FairQueueLayer {
+    /// Shared queue state.
+    queue: Arc<FairQueue>,
+}
+
+impl<S> Layer<S> for FairQueueLayer {
+    type Service = FairQueueService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        FairQueueService {
+            inner,
+            queue: Arc::clone(&self.queue),
+        }
+    }
+}
+
+/// Weighted fair queuing [`tower`] service.
+#[derive(Clone)]
+pub(crate) struct FairQueueService<S> {
+    /// Inner service.
+    inner: S,
+    /// Shared queue state.
+    queue: Arc<FairQueue>,
+}
+
+impl<S, T> Service<Request<T>> for FairQueueService<S>
+where
+    S: Service<Request<T>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Response: Send,
+    S::Error: Into<BoxError>,
+    T: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<T>) -> Self::Future {
+        let tenant = self.queue.tenant(&req);
+        let queue = Arc::clone(&self.queue);
+        // Use the service which was polled ready, leave a fresh clone in its place.
+        let clone = self.inner.clone();
+        let mut inner = mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let _permit = queue.acquire(tenant).await?;
+            inner.call(req).await.map_err(Into::into)
+        })
+    }
+}
+
+/// Shared state of fair queue.
+struct FairQueue {
+    /// Queue configuration.
+    config: HandlerFairQueueConfig,
+    /// Handler name, used in metric labels.
+    handler: &'static str,
+    /// Fair queuing metrics.
+    metrics: Option<FairQueueMetrics>,
+    /// Retry advice configuration.
+    retry: RetryAdviceConfig,
+    /// Mutable queue state.
+    state: Mutex<QueueState>,
+}
+
+/// Mutable state of fair queue.
+#[derive(Default)]
+struct QueueState {
+    /// Number of currently executing requests.
+    in_flight: usize,
+    /// Waiting requests.
+    scheduler: DrrScheduler<oneshot::Sender<Permit>>,
+}
+
+impl FairQueue {
+    /// Extract tenant identifier from request.
+    fn tenant<T>(&self, req: &Request<T>) -> String {
+        match &self.config.tenant {
+            TenantSource::UserId => req
+                .extensions()
+                .get::<UserId>()
+                .map(|user_id| user_id.to_string()),
+            TenantSource::Header(name) => req
+                .headers()
+                .get(name)
+                .and_then(|val| val.to_str().ok())
+                .map(ToString::to_string),
+        }
+        .unwrap_or_default()
+    }
+
+    /// Get weight of a tenant.
+    fn weight(&self, tenant: &str) -> u32 {
+        self.config
+            .weights
+            .get(tenant)
+            .unwrap_or(&self.config.default_weight)
+            .get()
+    }
+
+    /// Get metric label value for a tenant.
+    fn tenant_label(&self, tenant: &str) -> String {
+        match self.config.weights.contains_key(tenant) {
+            true => tenant.to_string(),
+            false => OTHER_TENANT.to_string(),
+        }
+    }
+
+    /// Wait until request can be executed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if tenant queue is full.
+    async fn acquire(self: &Arc<Self>, tenant: String) -> Result<Permit, FairQueueError> {
+        let rx = {
+            let _span = trace_span!("fair_queue").entered();
+            let mut state = self.state.lock();
+            if state.in_flight < self.config.concurrency.get() && state.scheduler.is_empty() {
+                state.in_flight += 1;
+                self.record_dispatch(&tenant, Duration::ZERO);
+                return Ok(Permit::new(self));
+            }
+            let cancelled = state
+                .scheduler
+                .retain(&tenant, |waiter| !waiter.is_closed());
+            self.record_drops(&tenant, "cancelled", cancelled);
+            if state.scheduler.queued(&tenant) >= self.config.queue_size {
+                warn!(tenant, "fair queue is full");
+                self.record_drops(&tenant, "queue_full", 1);
+                return Err(FairQueueError::QueueFull {
+                    advice: self.retry.advise(RetrySource::FairQueue, None),
+                });
+            }
+            let (tx, rx) = oneshot::channel();
+            let weight = self.weight(&tenant);
+            state.scheduler.push(tenant, weight, tx);
+            // Capacity might be free if all queued requests were cancelled.
+            self.dispatch(&mut state);
+            rx
+        };
+        rx.await.map_err(|_| FairQueueError::Closed)
+    }
+
+    /// Dispatch queued requests while there is free capacity.
+    fn dispatch(self: &Arc<Self>, state: &mut QueueState) {
+        while state.in_flight < self.config.concurrency.get() {
+            let Some(entry) = state.scheduler.pop(self.config.max_wait) else {
+                break;
+            };
+            let mut permit = Permit::new(self);
+            state.in_flight += 1;
+            match entry.item.send(permit) {
+                Ok(()) => self.record_dispatch(&entry.tenant, entry.enqueued.elapsed()),
+                Err(returned) => {
+                    // Waiting request was cancelled.
+                    permit = returned;
+                    permit.disarm();
+                    state.in_flight -= 1;
+                    self.record_drops(&entry.tenant, "cancelled", 1);
+                }
+            }
+        }
+    }
+
+    /// Release execution slot.
+    fn release(self: &Arc<Self>) {
+        let mut state = self.state.lock();
+        state.in_flight -= 1;
+        self.dispatch(&mut state);
+    }
+
+    /// Record metrics for dispatched request.
+    fn record_dispatch(&self, tenant: &str, wait: Duration) {
+        if let Some(metrics) = &self.metrics {
+            let labels = [
+                KeyValue::new("uxum.handler", self.handler),
+                KeyValue::new("uxum.tenant", self.tenant_label(tenant)),
+            ];
+            metrics.wait_duration.record(wait.as_secs_f64(), &labels);
+            metrics.dispatched.add(1, &labels);
+        }
+    }
+
+    /// Record metrics for dropped requests.
+    fn record_drops(&self, tenant: &str, reason: &'static str, count: usize) {
+        if count == 0 {
+            return;
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.dropped.add(
+                count as u64,
+                &[
+                    KeyValue::new("uxum.handler", self.handler),
+                    KeyValue::new("uxum.tenant", self.tenant_label(tenant)),
+                    KeyValue::new("reason", reason),
+                ],
+            );
+        }
+    }
+}
+
+/// Execution slot, released on drop.
+struct Permit(Option<Arc<FairQueue>>);
+
+impl Permit {
+    /// Create new permit for already accounted execution slot.
+    fn new(queue: &Arc<FairQueue>) -> Self {
+        Self(Some(Arc::clone(queue)))
+    }
+
+    /// Prevent releasing the slot on drop.
+    fn disarm(&mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(queue) = self.0.take() {
+            queue.release();
+        }
+    }
+}
+
+/// Queued item, along with its tenant and enqueue time.
+struct QueueEntry<T> {
+    /// Tenant identifier.
+    tenant: String,
+    /// Time of enqueueing.
+    enqueued: Instant,
+    /// Queued item.
+    item: T,
+}
+
+/// Queue of a single tenant.
+struct TenantQueue<T> {
+    /// Tenant weight, i.e. number of items dispatched per round.
+    weight: u32,
+    /// Number of items tenant may still dispatch in current round.
+    deficit: u32,
+    /// Queued items, along with their enqueue times.
+    items: VecDeque<(Instant, T)>,
+}
+
+/// Deficit round-robin scheduler.
+///
+/// Every item has a unit cost. When a tenant reaches the front of the round, its deficit is
+/// replenished by its weight, and it dispatches items until the deficit is spent or its queue
+/// is empty.
+struct DrrScheduler<T> {
+    /// Per-tenant queues.
+    tenants: HashMap<String, TenantQueue<T>>,
+    /// Tenants with queued items, in round-robin order.
+    round: VecDeque<String>,
+}
+
+impl<T> Default for DrrScheduler<T> {
+    fn default() -> Self {
+        Self {
+            tenants: HashMap::new(),
+            round: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> DrrScheduler<T> {
+    /// Check whether there are no queued items.
+    fn is_empty(&self) -> bool {
+        self.round.is_empty()
+    }
+
+    /// Number of items queued for a tenant.
+    fn queued(&self, tenant: &str) -> usize {
+        self.tenants
+            .get(tenant)
+            .map_or(0, |queue| queue.items.len())
+    }
+
+    /// Enqueue an item.
+    fn push(&mut self, tenant: String, weight: u32, item: T) {
+        let queue = match self.tenants.entry(tenant) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                self.round.push_back(entry.key().clone());
+                entry.insert(TenantQueue {
+                    weight,
+                    deficit: 0,
+                    items: VecDeque::new(),
+                })
+            }
+        };
+        queue.items.push_back((Instant::now(), item));
+    }
+
+    /// Keep only tenant items for which `keep` returns `true`.
+    ///
+    /// Returns number of removed items.
+    fn retain(&mut self, tenant: &str, keep: impl Fn(&T) -> bool) -> usize {
+        let Some(queue) = self.tenants.get_mut(tenant) else {
+            return 0;
+        };
+        let before = queue.items.len();
+        queue.items.retain(|(_, item)| keep(item));
+        let removed = before - queue.items.len();
+        if queue.items.is_empty() {
+            self.remove(tenant);
+        }
+        removed
+    }
+
+    /// Dequeue next item.
+    ///
+    /// Items waiting for longer than `max_wait` are dequeued first, oldest first.
+    fn pop(&mut self, max_wait: Duration) -> Option<QueueEntry<T>> {
+        let now = Instant::now();
+        let starved = self
+            .tenants
+            .iter()
+            .filter_map(|(tenant, queue)| Some((tenant, queue.items.front()?.0)))
+            .filter(|(_, enqueued)| now.saturating_duration_since(*enqueued) > max_wait)
+            .min_by_key(|(_, enqueued)| *enqueued)
+            .map(|(tenant, _)| tenant.clone());
+        let is_starved = starved.is_some();
+        let tenant = match starved {
+            Some(tenant) => tenant,
+            None => self.round.front()?.clone(),
+        };
+        let queue = self.tenants.get_mut(&tenant)?;
+        let (enqueued, item) = queue.items.pop_front()?;
+        if !is_starved {
+            if queue.deficit == 0 {
+                queue.deficit = queue.weight;
+            }
+            queue.deficit -= 1;
+            if queue.deficit == 0 {
+                self.round.rotate_left(1);
+            }
+        }
+        if queue.items.is_empty() {
+            self.remove(&tenant);
+        }
+        Some(QueueEntry {
+            tenant,
+            enqueued,
+            item,
+        })
+    }
+
+    /// Remove tenant queue.
+    fn remove(&mut self, tenant: &str) {
+        self.tenants.remove(tenant);
+        self.round.retain(|name| name != tenant);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+
+    /// Fill scheduler with backlog offered at 10:1 ratio, and count dispatched items per tenant
+    /// while both tenants are still backlogged.
+    fn simulate(weight_a: u32, weight_b: u32, dispatched: usize) -> (usize, usize) {
+        let mut sched = DrrScheduler::default();
+        for _ in 0..100 {
+            for _ in 0..10 {
+                sched.push("a".into(), weight_a, ());
+            }
+            sched.push("b".into(), weight_b, ());
+        }
+        let mut counts = (0, 0);
+        for _ in 0..dispatched {
+            match sched.pop(Duration::MAX).unwrap().tenant.as_str() {
+                "a" => counts.0 += 1,
+                _ => counts.1 += 1,
+            }
+        }
+        counts
+    }
+
+    /// Throughput shares track weights, not offered load.
+    #[test]
+    fn shares_track_weights() {
+        // Plain FIFO would give tenant "a" about 10/11 of throughput.
+        assert_eq!(simulate(1, 1, 100), (50, 50));
+        assert_eq!(simulate(3, 1, 100), (75, 25));
+        assert_eq!(simulate(1, 4, 100), (20, 80));
+    }
+
+    /// Starved items are dispatched in arrival order.
+    #[test]
+    fn starvation_protection() {
+        let mut sched = DrrScheduler::default();
+        sched.push("a".into(), 100, 1);
+        sched.push("a".into(), 100, 2);
+        sched.push("b".into(), 1, 3);
+        sched.push("a".into(), 100, 4);
+        let order: Vec<_> = std::iter::from_fn(|| sched.pop(Duration::ZERO))
+            .map(|entry| entry.item)
+            .collect();
+        assert_eq!(order, [1, 2, 3, 4]);
+        assert!(sched.is_empty());
+    }
+
+    /// Requests over per-tenant queue bound are rejected, others wait for capacity.
+    #[tokio::test]
+    async fn queue_bound() {
+        let (release_tx, release_rx) = tokio::sync::watch::channel(false);
+        let layer = HandlerFairQueueConfig::new(NonZeroUsize::MIN)
+            .with_tenant_header("x-tenant")
+            .with_queue_size(1)
+            .make_layer(
+                "test",
+                None,
+                &RetryAdviceConfig::default()
+                    .with_jitter(0.0)
+                    .with_base(RetrySource::RateLimit, Duration::from_secs(60))
+                    .with_base(RetrySource::FairQueue, Duration::from_secs(7)),
+            );
+        let svc = layer.layer(service_fn(move |_: Request<Body>| {
+            let mut release_rx = release_rx.clone();
+            async move {
+                let _ = release_rx.wait_for(|released| *released).await;
+                Ok::<_, Infallible>(StatusCode::OK.into_response())
+            }
+        }));
+        let request = |tenant: &str| {
+            Request::builder()
+                .header("x-tenant", tenant)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let running = tokio::spawn(svc.clone().oneshot(request("a")));
+        let queued = tokio::spawn(svc.clone().oneshot(request("a")));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let err = svc.clone().oneshot(request("a")).await.unwrap_err();
+        let Some(FairQueueError::QueueFull { advice }) = err.downcast_ref::<FairQueueError>()
+        else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!(advice.delay(), Duration::from_secs(7));
+        // Other tenant has its own queue.
+        let other = tokio::spawn(svc.clone().oneshot(request("b")));
+        release_tx.send(true).unwrap();
+        for task in [running, queued, other] {
+            assert_eq!(task.await.unwrap().unwrap().status(), StatusCode::OK);
+        }
+        assert!(svc.queue.state.lock().scheduler.is_empty());
+        assert_eq!(svc.queue.state.lock().in_flight, 0);
+    }
+}
@@ -0,0 +1,326 @@
+//! [`tower`] layer to stamp responses with service identity headers.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    task::{ready, Context, Poll},
+};
+
+use axum::http::{HeaderName, HeaderValue, Request, Response};
+use pin_project::pin_project;
+use serde::{Deserialize, Serialize};
+use tower::{Layer, Service};
+use tracing::warn;
+
+/// Get unique identifier of this service instance.
+///
+/// Generated once per process.
+#[must_use]
+pub fn instance_id() -> &'static str {
+    static INSTANCE_ID: OnceLock<String> = OnceLock::new();
+    INSTANCE_ID.get_or_init(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Service identity header kind.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum IdentityHeader {
+    /// Application name, from [`crate::AppConfig::app_name`].
+    ServiceName,
+    /// Application version, from [`crate::AppConfig::app_version`].
+    ServiceVersion,
+    /// Unique instance identifier, see [`instance_id`].
+    InstanceId,
+    /// Name of the host or pod that served the request.
+    ServedBy,
+}
+
+impl IdentityHeader {
+    /// Default header name.
+    #[must_use]
+    fn default_name(self) -> &'static str {
+        match self {
+            Self::ServiceName => "x-service-name",
+            Self::ServiceVersion => "x-service-version",
+            Self::InstanceId => "x-instance-id",
+            Self::ServedBy => "x-served-by",
+        }
+    }
+}
+
+/// Response identity headers configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct ResponseIdentityConfig {
+    /// Add identity headers to responses.
+    ///
+    /// Disabled by default.
+    #[serde(default)]
+    enabled: bool,
+    /// Headers to include.
+    #[serde(default = "ResponseIdentityConfig::default_include")]
+    include: Vec<IdentityHeader>,
+    /// Custom header names.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    names: BTreeMap<IdentityHeader, String>,
+    /// Value of `x-served-by` header.
+    ///
+    /// Taken from `HOSTNAME` environment variable if not set, or from instance ID if that
+    /// variable is absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    served_by: Option<String>,
+}
+
+impl Default for ResponseIdentityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            include: Self::default_include(),
+            names: BTreeMap::new(),
+            served_by: None,
+        }
+    }
+}
+
+impl ResponseIdentityConfig {
+    /// Default value for [`Self::include`].
+    #[must_use]
+    #[inline]
+    fn default_include() -> Vec<IdentityHeader> {
+        vec![
+            IdentityHeader::ServiceName,
+            IdentityHeader::ServiceVersion,
+            IdentityHeader::InstanceId,
+            IdentityHeader::ServedBy,
+        ]
+    }
+
+    /// Enable or disable identity headers.
+    #[must_use]
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// Set headers to include.
+    #[must_use]
+    pub fn with_include(mut self, include: impl IntoIterator<Item = IdentityHeader>) -> Self {
+        self.include = include.into_iter().collect();
+        self
+    }
+
+    /// Set custom header name.
+    #[must_use]
+    pub fn with_name(mut self, header: IdentityHeader, name: impl ToString) -> Self {
+        self.names.insert(header, name.to_string());
+        self
+    }
+
+    /// Set value of `x-served-by` header.
+    #[must_use]
+    pub fn with_served_by(mut self, served_by: impl ToString) -> Self {
+        self.served_by = Some(served_by.to_string());
+        self
+    }
+
+    /// Build identity layer.
+    ///
+    /// Returns [`None`] if disabled, or if no headers could be built.
+    #[must_use]
+    pub(crate) fn make_layer(
+        &self,
+        app_name: Option<&str>,
+        app_version: Option<&str>,
+    ) -> Option<ResponseIdentityLayer> {
+        if !self.enabled {
+            return None;
+        }
+        let mut headers = Vec::with_capacity(self.include.len());
+        for kind in &self.include {
+            let value = match kind {
+                IdentityHeader::ServiceName => app_name.map(ToOwned::to_owned),
+                IdentityHeader::ServiceVersion => app_version.map(ToOwned::to_owned),
+                IdentityHeader::InstanceId => Some(instance_id().to_owned()),
+                IdentityHeader::ServedBy => Some(
+                    self.served_by
+                        .clone()
+                        .or_else(|| env::var("HOSTNAME").ok())
+                        .unwrap_or_else(|| instance_id().to_owned()),
+                ),
+            };
+            let Some(value) = value else {
+                continue;
+            };
+            let name = self
+                .names
+                .get(kind)
+                .map_or(kind.default_name(), String::as_str);
+            match (
+                HeaderName::try_from(name),
+                HeaderValue::try_from(value.as_str()),
+            ) {
+                (Ok(name), Ok(value)) => headers.push((name, value)),
+                _ => warn!(header = name, value, "invalid identity header, skipping"),
+            }
+        }
+        (!headers.is_empty()).then(|| ResponseIdentityLayer {
+            headers: headers.into(),
+        })
+    }
+}
+
+/// Response extension used to suppress identity headers.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct SuppressIdentity;
+
+/// Response identity [`tower`] layer.
+#[derive(Clone, Debug)]
+pub(crate) struct ResponseIdentityLayer {
+    /// Precomputed headers added to every response.
+    headers: Arc<[(HeaderName, HeaderValue)]>,
+}
+
+impl<S> Layer<S> for ResponseIdentityLayer {
+    type Service = ResponseIdentity<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ResponseIdentity {
+            inner,
+            headers: self.headers.clone(),
+        }
+    }
+}
+
+/// Response identity [`tower`] service.
+#[derive(Clone, Debug)]
+pub(crate) struct ResponseIdentity<S> {
+    /// Inner service.
+    inner: S,
+    /// Precomputed headers added to every response.
+    headers: Arc<[(HeaderName, HeaderValue)]>,
+}
+
+impl<S, T, U> Service<Request<T>> for ResponseIdentity<S>
+where
+    S: Service<Request<T>, Response = Response<U>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseIdentityFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<T>) -> Self::Future {
+        ResponseIdentityFuture {
+            inner: self.inner.call(req),
+            headers: self.headers.clone(),
+        }
+    }
+}
+
+/// Response identity [`tower`] service future.
+#[pin_project]
+pub(crate) struct ResponseIdentityFuture<F> {
+    /// Inner future.
+    #[pin]
+    inner: F,
+    /// Precomputed headers added to response.
+    headers: Arc<[(HeaderName, HeaderValue)]>,
+}
+
+impl<F, U, E> Future for ResponseIdentityFuture<F>
+where
+    F: Future<Output = Result<Response<U>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut resp = ready!(this.inner.poll(cx))?;
+        if resp.extensions().get::<SuppressIdentity>().is_none() {
+            let headers = resp.headers_mut();
+            for (name, value) in this.headers.iter() {
+                // Cloning shares underlying buffers, no allocation is made here.
+                headers.insert(name.clone(), value.clone());
+            }
+        }
+        Poll::Ready(Ok(resp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::util::ResponseExtension;
+
+    fn app(layer: ResponseIdentityLayer) -> Router {
+        Router::new()
+            .route(
+                "/public",
+                get(|| async {}).layer(ResponseExtension(SuppressIdentity)),
+            )
+            .route("/internal", get(|| async {}))
+            .layer(layer)
+    }
+
+    async fn call(rtr: Router, path: &str) -> Response<Body> {
+        rtr.oneshot(Request::get(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    /// Headers are added to all responses, unless suppressed.
+    #[tokio::test]
+    async fn presence_and_suppression() {
+        let layer = ResponseIdentityConfig::default()
+            .with_enabled(true)
+            .with_name(IdentityHeader::ServedBy, "x-pod")
+            .with_served_by("pod-1")
+            .make_layer(Some("svc"), None)
+            .unwrap();
+        let resp = call(app(layer.clone()), "/internal").await;
+        let headers = resp.headers();
+        assert_eq!(headers["x-service-name"], "svc");
+        assert!(!headers.contains_key("x-service-version"));
+        assert_eq!(headers["x-instance-id"], instance_id());
+        assert_eq!(headers["x-pod"], "pod-1");
+
+        let resp = call(app(layer), "/public").await;
+        assert!(!resp.headers().contains_key("x-service-name"));
+        assert!(!resp.headers().contains_key("x-instance-id"));
+    }
+
+    /// Disabled configuration produces no layer.
+    #[test]
+    fn disabled() {
+        assert!(ResponseIdentityConfig::default()
+            .make_layer(Some("svc"), Some("1.0"))
+            .is_none());
+    }
+
+    /// Response header values share storage with precomputed values.
+    #[tokio::test]
+    async fn no_allocation() {
+        let layer = ResponseIdentityConfig::default()
+            .with_enabled(true)
+            .make_layer(Some("svc"), Some("1.0"))
+            .unwrap();
+        let resp = call(app(layer.clone()), "/internal").await;
+        for (name, value) in layer.headers.iter() {
+            assert_eq!(
+                resp.headers()[name].as_bytes().as_ptr(),
+                value.as_bytes().as_ptr(),
+                "header {name} was copied"
+            );
+        }
+    }
+}
@@ -0,0 +1,611 @@
+//! IP address filtering [`tower`] layer.
+
+use std::{
+    fmt,
+    future::Future,
+    net::IpAddr,
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+
+use axum::{
+    body::Body,
+    http::{Request, Response, StatusCode},
+    response::IntoResponse,
+};
+use opentelemetry::{metrics::Counter, KeyValue};
+use pin_project::pin_project;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+use tower::{util::Oneshot, BoxError, Layer, Service, ServiceExt};
+use tracing::{trace_span, warn};
+
+use crate::{
+    builder::fallback::{not_found, FallbackService},
+    errors::{codes, ErrorCode},
+    layers::client_ip::ClientIp,
+};
+
+/// Error type returned when parsing IP network specification.
+#[derive(Clone, Debug, Error, PartialEq)]
+#[error("Invalid IP network specification: {0}")]
+pub struct IpNetworkError(String);
+
+/// IPv4 or IPv6 network in CIDR notation.
+///
+/// Bare IP addresses are treated as host networks (`/32` for IPv4, `/128` for IPv6).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct IpNetwork {
+    /// Network address, with host bits cleared.
+    addr: IpAddr,
+    /// Network prefix length.
+    prefix: u8,
+}
+
+impl IpNetwork {
+    /// Create new network from address and prefix length.
+    ///
+    /// Host bits of the address are cleared.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if prefix length is out of range for the address family.
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self, IpNetworkError> {
+        let addr = addr.to_canonical();
+        let max_prefix = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix > max_prefix {
+            return Err(IpNetworkError(format!("{addr}/{prefix}")));
+        }
+        let addr = match addr {
+            IpAddr::V4(v4) => IpAddr::V4((u32::from(v4) & v4_mask(prefix)).into()),
+            IpAddr::V6(v6) => IpAddr::V6((u128::from(v6) & v6_mask(prefix)).into()),
+        };
+        Ok(Self { addr, prefix })
+    }
+
+    /// Network address.
+    #[must_use]
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// Network prefix length.
+    #[must_use]
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Check whether IP address belongs to this network.
+    ///
+    /// IPv4-mapped IPv6 addresses are matched against IPv4 networks.
+    #[must_use]
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                u32::from(ip) & v4_mask(self.prefix) == u32::from(net)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                u128::from(ip) & v6_mask(self.prefix) == u128::from(net)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Build IPv4 network mask from prefix length.
+fn v4_mask(prefix: u8) -> u32 {
+    u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0)
+}
+
+/// Build IPv6 network mask from prefix length.
+fn v6_mask(prefix: u8) -> u128 {
+    u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0)
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = IpNetworkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || IpNetworkError(s.to_string());
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s.trim(), None),
+        };
+        let addr = addr.parse::<IpAddr>().map_err(|_| err())?;
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().map_err(|_| err())?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        Self::new(addr, prefix).map_err(|_| err())
+    }
+}
+
+impl From<IpAddr> for IpNetwork {
+    fn from(value: IpAddr) -> Self {
+        let prefix = if value.to_canonical().is_ipv4() {
+            32
+        } else {
+            128
+        };
+        // SAFETY: host prefix is always in range.
+        Self::new(value, prefix).unwrap()
+    }
+}
+
+impl Serialize for IpNetwork {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for IpNetwork {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Error type returned by IP filtering layer.
+#[derive(Clone, Debug, Error)]
+#[non_exhaustive]
+pub enum IpFilterError {
+    /// Client IP address could not be determined.
+    #[error("Unable to determine client IP address")]
+    NoClientIp {
+        /// Whether to hide the resource from the client.
+        hide: bool,
+    },
+    /// Client IP address is not allowed.
+    #[error("Access denied for IP address {ip}")]
+    Denied {
+        /// Client IP address.
+        ip: IpAddr,
+        /// Whether to hide the resource from the client.
+        hide: bool,
+    },
+}
+
+impl IpFilterError {
+    /// HTTP status code for used for this error.
+    fn http_status(&self) -> StatusCode {
+        match self.is_hidden() {
+            true => StatusCode::NOT_FOUND,
+            false => StatusCode::FORBIDDEN,
+        }
+    }
+
+    /// Whether the resource is hidden from the client.
+    fn is_hidden(&self) -> bool {
+        matches!(
+            self,
+            Self::NoClientIp { hide: true } | Self::Denied { hide: true, .. }
+        )
+    }
+
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::NoClientIp { .. } => codes::IP_NO_CLIENT_IP,
+            Self::Denied { .. } => codes::IP_DENIED,
+        }
+    }
+}
+
+impl IntoResponse for IpFilterError {
+    fn into_response(self) -> Response<Body> {
+        if self.is_hidden() {
+            // Client sees a regular 404 response, actual error code is kept for logging.
+            let mut resp = not_found();
+            resp.extensions_mut().insert(self.code());
+            return resp;
+        }
+        let status = self.http_status();
+        let code = self.code();
+        let problem = code
+            .problem(status)
+            .with_type("tag:uxum.github.io,2024:ip-filter")
+            .with_title(self.to_string());
+        (code, problem).into_response()
+    }
+}
+
+/// Response used when request is rejected by IP filter.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum IpFilterRejection {
+    /// Respond with 403 Forbidden.
+    #[default]
+    Forbidden,
+    /// Respond the same way as to requests not matching any route, hiding existence of the
+    /// resource.
+    NotFound,
+}
+
+/// Configuration for IP filtering layer.
+///
+/// Denied networks take precedence over allowed ones. Empty allow list permits every address
+/// not explicitly denied.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct IpFilterConfig {
+    /// Allowed networks.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    allow: Vec<IpNetwork>,
+    /// Denied networks.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    deny: Vec<IpNetwork>,
+    /// Response used for rejected requests.
+    #[serde(default)]
+    reject: IpFilterRejection,
+}
+
+impl IpFilterConfig {
+    /// Add allowed network.
+    #[must_use]
+    pub fn with_allow(mut self, net: IpNetwork) -> Self {
+        self.allow.push(net);
+        self
+    }
+
+    /// Add denied network.
+    #[must_use]
+    pub fn with_deny(mut self, net: IpNetwork) -> Self {
+        self.deny.push(net);
+        self
+    }
+
+    /// Set response used for rejected requests.
+    #[must_use]
+    pub fn with_reject(mut self, reject: IpFilterRejection) -> Self {
+        self.reject = reject;
+        self
+    }
+
+    /// Check whether client IP address passes the filter.
+    #[must_use]
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        if self.deny.iter().any(|net| net.contains(ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(ip))
+    }
+
+    /// Create layer for use in [`tower`] services.
+    pub(crate) fn make_layer(
+        &self,
+        handler: &'static str,
+        rejections: Option<Counter<u64>>,
+    ) -> IpFilterLayer {
+        IpFilterLayer {
+            config: Arc::new(self.clone()),
+            handler,
+            rejections,
+            fallback: None,
+        }
+    }
+}
+
+/// IP filtering [`tower`] layer.
+#[derive(Clone)]
+pub(crate) struct IpFilterLayer {
+    /// Filter configuration.
+    config: Arc<IpFilterConfig>,
+    /// Handler name, used in metric labels.
+    handler: &'static str,
+    /// Rejected requests counter.
+    rejections: Option<Counter<u64>>,
+    /// Service responding to hidden rejections.
+    fallback: Option<FallbackService>,
+}
+
+impl IpFilterLayer {
+    /// Respond to rejected requests using application fallback service, if hiding resources.
+    #[must_use]
+    pub(crate) fn with_fallback(mut self, fallback: Option<FallbackService>) -> Self {
+        self.fallback = fallback;
+        self
+    }
+}
+
+impl<S> Layer<S> for IpFilterLayer {
+    type Service = IpFilter<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        IpFilter {
+            inner,
+            config: Arc::clone(&self.config),
+            handler: self.handler,
+            rejections: self.rejections.clone(),
+            fallback: self.fallback.clone(),
+        }
+    }
+}
+
+/// IP filtering [`tower`] service.
+#[derive(Clone)]
+pub(crate) struct IpFilter<S> {
+    /// Inner service.
+    inner: S,
+    /// Filter configuration.
+    config: Arc<IpFilterConfig>,
+    /// Handler name, used in metric labels.
+    handler: &'static str,
+    /// Rejected requests counter.
+    rejections: Option<Counter<u64>>,
+    /// Service responding to hidden rejections.
+    fallback: Option<FallbackService>,
+}
+
+impl<S> IpFilter<S> {
+    /// Check request against configured filter.
+    fn check<T>(&self, req: &Request<T>) -> Result<(), IpFilterError> {
+        let hide = self.config.reject == IpFilterRejection::NotFound;
+        let ip = req
+            .extensions()
+            .get::<ClientIp>()
+            .map(ClientIp::ip)
+            .ok_or(IpFilterError::NoClientIp { hide })?;
+        match self.config.is_allowed(ip) {
+            true => Ok(()),
+            false => Err(IpFilterError::Denied { ip, hide }),
+        }
+    }
+}
+
+impl<S> Service<Request<Body>> for IpFilter<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<BoxError>,
+{
+    type Response = Response<Body>;
+    type Error = BoxError;
+    type Future = IpFilterFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match self.inner.poll_ready(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(res) => Poll::Ready(res.map_err(Into::into)),
+        }
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let check_result = {
+            let _span = trace_span!("ip_filter").entered();
+            self.check(&req)
+        };
+        match check_result {
+            Ok(()) => IpFilterFuture::Positive {
+                inner: self.inner.call(req),
+            },
+            Err(error) => {
+                warn!(%error, "request rejected by IP filter");
+                if let Some(counter) = &self.rejections {
+                    counter.add(1, &[KeyValue::new("uxum.handler", self.handler)]);
+                }
+                match (error.is_hidden(), &self.fallback) {
+                    (true, Some(fallback)) => IpFilterFuture::Hidden {
+                        inner: Box::pin(fallback.clone().oneshot(req)),
+                        code: error.code(),
+                    },
+                    _ => IpFilterFuture::Negative { error },
+                }
+            }
+        }
+    }
+}
+
+/// IP filtering [`tower`] service future.
+#[pin_project(project = ProjectedOutcome)]
+pub(crate) enum IpFilterFuture<F> {
+    /// Happy path, calling inner service.
+    Positive {
+        /// Inner future.
+        #[pin]
+        inner: F,
+    },
+    /// Client address was rejected.
+    Negative {
+        /// Cause of negative response.
+        error: IpFilterError,
+    },
+    /// Client address was rejected, responding as if there was no such resource.
+    Hidden {
+        /// Fallback service future, boxed as this path is rarely taken.
+        inner: Pin<Box<Oneshot<FallbackService, Request<Body>>>>,
+        /// Error code, kept for logging.
+        code: ErrorCode,
+    },
+}
+
+impl<F, E> Future for IpFilterFuture<F>
+where
+    F: Future<Output = Result<Response<Body>, E>>,
+    E: Into<BoxError>,
+{
+    type Output = Result<Response<Body>, BoxError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            ProjectedOutcome::Positive { inner } => {
+                let resp = ready!(inner.poll(cx).map_err(Into::into))?;
+                Poll::Ready(Ok(resp))
+            }
+            ProjectedOutcome::Negative { error } => Poll::Ready(Err(Box::new(error.clone()))),
+            ProjectedOutcome::Hidden { inner, code } => {
+                let mut resp = ready!(inner.as_mut().poll(cx))?;
+                resp.extensions_mut().insert(*code);
+                Poll::Ready(Ok(resp))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, net::SocketAddr};
+
+    use axum::extract::ConnectInfo;
+    use serde_json::from_str;
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::layers::client_ip::ClientIpConfig;
+
+    fn net(s: &str) -> IpNetwork {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    async fn call(cfg: &IpFilterConfig, peer: &str, xff: Option<&str>) -> StatusCode {
+        let svc = cfg.make_layer("test", None).layer(service_fn(|_| async {
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        }));
+        let mut req = Request::new(Body::empty());
+        let peer: SocketAddr = (ip(peer), 12345).into();
+        req.extensions_mut().insert(ConnectInfo(peer));
+        if let Some(xff) = xff {
+            req.headers_mut()
+                .insert("x-forwarded-for", xff.parse().unwrap());
+        }
+        let req = ClientIpConfig::default()
+            .with_trusted_proxy(net("192.168.0.0/24"))
+            .resolve_incoming(req);
+        match svc.oneshot(req).await {
+            Ok(resp) => resp.status(),
+            Err(err) => err.downcast_ref::<IpFilterError>().unwrap().http_status(),
+        }
+    }
+
+    /// Parse networks, including bare addresses and host bits.
+    #[test]
+    fn parse_networks() {
+        assert_eq!(net("10.1.2.3/8").to_string(), "10.0.0.0/8");
+        assert_eq!(net("192.168.0.1").to_string(), "192.168.0.1/32");
+        assert_eq!(net("2001:db8::1/32").to_string(), "2001:db8::/32");
+        assert_eq!(net("::1").to_string(), "::1/128");
+        assert_eq!(net("0.0.0.0/0").prefix(), 0);
+        assert!("10.0.0.0/33".parse::<IpNetwork>().is_err());
+        assert!("::/129".parse::<IpNetwork>().is_err());
+        assert!("10.0.0/8".parse::<IpNetwork>().is_err());
+    }
+
+    /// Deserialization error names the offending entry.
+    #[test]
+    fn de_error_names_entry() {
+        let err = from_str::<IpFilterConfig>(r#"{"allow": ["10.0.0.0/8", "10.0.0.300/24"]}"#)
+            .unwrap_err();
+        assert!(err.to_string().contains("10.0.0.300/24"));
+    }
+
+    /// IPv6 and IPv4-mapped addresses.
+    #[test]
+    fn contains_ipv6() {
+        let v6 = net("2001:db8::/32");
+        assert!(v6.contains(ip("2001:db8:ffff::1")));
+        assert!(!v6.contains(ip("2001:db9::1")));
+        assert!(!v6.contains(ip("10.0.0.1")));
+        let v4 = net("10.0.0.0/8");
+        assert!(v4.contains(ip("::ffff:10.1.1.1")));
+        assert!(net("::/0").contains(ip("::1")));
+    }
+
+    /// Deny wins over allow, including nested ranges.
+    #[test]
+    fn deny_wins_nested() {
+        let cfg = IpFilterConfig::default()
+            .with_allow(net("10.0.0.0/8"))
+            .with_deny(net("10.1.0.0/16"))
+            .with_allow(net("10.1.2.0/24"));
+        assert!(cfg.is_allowed(ip("10.2.0.1")));
+        assert!(!cfg.is_allowed(ip("10.1.2.3")));
+        assert!(!cfg.is_allowed(ip("192.168.0.1")));
+        let deny_only = IpFilterConfig::default().with_deny(net("192.168.0.0/16"));
+        assert!(deny_only.is_allowed(ip("10.0.0.1")));
+        assert!(!deny_only.is_allowed(ip("192.168.1.1")));
+    }
+
+    /// Filter uses client address resolved from forwarded headers of trusted proxies only.
+    #[tokio::test]
+    async fn xff_spoofing() {
+        let cfg = IpFilterConfig::default().with_allow(net("10.0.0.0/8"));
+        // Untrusted peer tries to pass allowed address.
+        assert_eq!(
+            call(&cfg, "203.0.113.5", Some("10.0.0.1")).await,
+            StatusCode::FORBIDDEN
+        );
+        // Trusted proxy passes allowed address.
+        assert_eq!(
+            call(&cfg, "192.168.0.1", Some("10.0.0.1")).await,
+            StatusCode::OK
+        );
+        // Client prepends spoofed address, trusted proxy appends real one.
+        assert_eq!(
+            call(&cfg, "192.168.0.1", Some("10.0.0.1, 203.0.113.5")).await,
+            StatusCode::FORBIDDEN
+        );
+        // Chain of trusted proxies.
+        assert_eq!(
+            call(&cfg, "192.168.0.1", Some("10.0.0.1, 192.168.0.2")).await,
+            StatusCode::OK
+        );
+    }
+
+    /// Rejection with 404 Not Found.
+    #[tokio::test]
+    async fn reject_not_found() {
+        let cfg = IpFilterConfig::default()
+            .with_deny(net("::/0"))
+            .with_reject(IpFilterRejection::NotFound);
+        assert_eq!(call(&cfg, "::1", None).await, StatusCode::NOT_FOUND);
+        assert_eq!(call(&cfg, "127.0.0.1", None).await, StatusCode::OK);
+
+        // Hidden rejection looks the same as default fallback.
+        let resp = IpFilterError::Denied {
+            ip: ip("::1"),
+            hide: true,
+        }
+        .into_response();
+        assert_eq!(resp.extensions().get(), Some(&codes::IP_DENIED));
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], codes::NOT_FOUND.as_str());
+
+        // Configured fallback service is used if available.
+        let fallback = crate::builder::fallback::FallbackConfig::Redirect {
+            url: "/app/".into(),
+            permanent: false,
+        };
+        let svc = cfg
+            .make_layer("test", None)
+            .with_fallback(Some(fallback.make_service().unwrap()))
+            .layer(service_fn(|_| async {
+                Ok::<_, Infallible>(Response::new(Body::empty()))
+            }));
+        let mut req = Request::new(Body::empty());
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((ip("::1"), 12345))));
+        let req = ClientIpConfig::default().resolve_incoming(req);
+        let resp = svc.oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(resp.extensions().get(), Some(&codes::IP_DENIED));
+    }
+}
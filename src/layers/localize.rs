@@ -0,0 +1,353 @@
+//! [`tower`] layer to localize error responses.
+
+use std::task::{Context, Poll};
+
+use axum::{
+    body::{Body, HttpBody},
+    http::{
+        header::{ACCEPT_LANGUAGE, CONTENT_LANGUAGE, CONTENT_LENGTH, CONTENT_TYPE},
+        HeaderValue, Request, Response,
+    },
+};
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use opentelemetry::{metrics::Counter, KeyValue};
+use tower::{BoxError, Layer, Service};
+use tracing::{debug, warn};
+
+use crate::{
+    i18n::{MessageCatalog, MessageKey, BUILTIN_LOCALE},
+    validate::VALIDATION_MESSAGE_KEY_PREFIX,
+};
+
+/// Maximum size of error response body which will be localized.
+const MAX_PROBLEM_SIZE: usize = 64 * 1024;
+
+/// Problem details fields which are never translated.
+const UNTRANSLATED_FIELDS: &[&str] = &["type", "status", "instance"];
+
+/// Error response localization [`tower`] layer.
+#[derive(Clone, Debug)]
+pub(crate) struct LocalizeLayer {
+    /// Message catalog, or [`None`] if localization is disabled.
+    catalog: Option<MessageCatalog>,
+    /// Lifetime counter of missing translations.
+    missing: Counter<u64>,
+}
+
+impl LocalizeLayer {
+    /// Create new error localization layer.
+    #[must_use]
+    pub(crate) fn new(catalog: Option<MessageCatalog>, missing: Counter<u64>) -> Self {
+        Self { catalog, missing }
+    }
+}
+
+impl<S> Layer<S> for LocalizeLayer {
+    type Service = Localize<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Localize {
+            inner,
+            catalog: self.catalog.clone(),
+            missing: self.missing.clone(),
+        }
+    }
+}
+
+/// Error response localization [`tower`] service.
+#[derive(Clone, Debug)]
+pub(crate) struct Localize<S> {
+    /// Inner service.
+    inner: S,
+    /// Message catalog, or [`None`] if localization is disabled.
+    catalog: Option<MessageCatalog>,
+    /// Lifetime counter of missing translations.
+    missing: Counter<u64>,
+}
+
+impl<S, T, U> Service<Request<T>> for Localize<S>
+where
+    S: Service<Request<T>, Response = Response<U>>,
+    S::Future: Send + 'static,
+    U: HttpBody<Data = Bytes> + Send + 'static,
+    U::Error: Into<BoxError>,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<T>) -> Self::Future {
+        let locale = self.catalog.as_ref().map(|catalog| {
+            catalog.negotiate(
+                req.headers()
+                    .get(ACCEPT_LANGUAGE)
+                    .and_then(|val| val.to_str().ok()),
+            )
+        });
+        let future = self.inner.call(req);
+        let catalog = self.catalog.clone();
+        let missing = self.missing.clone();
+        Box::pin(async move {
+            let resp = future.await?.map(Body::new);
+            match (catalog, locale) {
+                (Some(catalog), Some(locale)) if is_problem(&resp) => {
+                    Ok(localize(resp, &catalog, &locale, &missing).await)
+                }
+                _ => Ok(resp),
+            }
+        })
+    }
+}
+
+/// Check if response is a problem details document.
+fn is_problem(resp: &Response<Body>) -> bool {
+    resp.headers()
+        .get(CONTENT_TYPE)
+        .is_some_and(|val| val.as_bytes().starts_with(b"application/problem+json"))
+}
+
+/// Replace text fields of problem details response with their translations.
+///
+/// Fields without translation for chosen locale are left in built-in language.
+async fn localize(
+    resp: Response<Body>,
+    catalog: &MessageCatalog,
+    locale: &str,
+    missing: &Counter<u64>,
+) -> Response<Body> {
+    let (mut parts, body) = resp.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_PROBLEM_SIZE).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            warn!(%err, "unable to read error response body");
+            parts.headers.remove(CONTENT_LENGTH);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let mut problem = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Object(obj)) => obj,
+        _ => return Response::from_parts(parts, Body::from(bytes)),
+    };
+    let key = match parts.extensions.get::<MessageKey>() {
+        Some(key) => key.0.clone(),
+        None => match problem.get("type").and_then(serde_json::Value::as_str) {
+            Some(uri) => uri.to_string(),
+            None => "about:blank".into(),
+        },
+    };
+    let mut translated = false;
+    for (field, value) in problem.iter_mut() {
+        if field == "errors" {
+            translated |= localize_violations(value, catalog, locale, missing);
+            continue;
+        }
+        if UNTRANSLATED_FIELDS.contains(&field.as_str()) || !value.is_string() {
+            continue;
+        }
+        match catalog.lookup(&key, field, locale) {
+            Some(text) => {
+                *value = text.into();
+                translated = true;
+            }
+            None => report_missing(&key, field, locale, missing),
+        }
+    }
+    if !translated {
+        return Response::from_parts(parts, Body::from(bytes));
+    }
+    let Ok(body) = serde_json::to_vec(&problem) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    if let Ok(val) = HeaderValue::from_str(locale) {
+        parts.headers.insert(CONTENT_LANGUAGE, val);
+    }
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// Replace messages of validation violations with their translations.
+///
+/// Rule parameters are substituted into translated messages in place of `{name}`.
+fn localize_violations(
+    errors: &mut serde_json::Value,
+    catalog: &MessageCatalog,
+    locale: &str,
+    missing: &Counter<u64>,
+) -> bool {
+    let Some(errors) = errors.as_array_mut() else {
+        return false;
+    };
+    let mut translated = false;
+    for error in errors
+        .iter_mut()
+        .filter_map(serde_json::Value::as_object_mut)
+    {
+        let Some(code) = error.get("code").and_then(serde_json::Value::as_str) else {
+            continue;
+        };
+        let key = format!("{VALIDATION_MESSAGE_KEY_PREFIX}{code}");
+        let Some(mut text) = catalog.lookup(&key, "message", locale) else {
+            report_missing(&key, "message", locale, missing);
+            continue;
+        };
+        if let Some(params) = error.get("params").and_then(serde_json::Value::as_object) {
+            for (name, value) in params {
+                let value = match value {
+                    serde_json::Value::String(val) => val.clone(),
+                    other => other.to_string(),
+                };
+                text = text.replace(&format!("{{{name}}}"), &value);
+            }
+        }
+        error.insert("message".into(), text.into());
+        translated = true;
+    }
+    translated
+}
+
+/// Record missing translation, unless built-in locale was requested.
+fn report_missing(key: &str, field: &str, locale: &str, missing: &Counter<u64>) {
+    if locale == BUILTIN_LOCALE {
+        return;
+    }
+    debug!(key, field, locale, "missing error message translation");
+    missing.add(
+        1,
+        &[
+            KeyValue::new("key", key.to_string()),
+            KeyValue::new("field", field.to_string()),
+            KeyValue::new("locale", locale.to_string()),
+        ],
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use axum::{http::StatusCode, response::IntoResponse};
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::{i18n::LocalizationConfig, ResponseExtension};
+
+    const NOT_FOUND: &str = "tag:test,2024:not-found";
+
+    fn layer() -> LocalizeLayer {
+        let catalog = LocalizationConfig::default()
+            .with_default_locale("de")
+            .with_message(NOT_FOUND, "title", "de", "Nicht gefunden")
+            .with_message(NOT_FOUND, "title", "fr", "Introuvable")
+            .with_message(NOT_FOUND, "detail", "de", "Bestellung fehlt")
+            .with_message("orders.gone", "title", "fr", "Commande supprimée")
+            .with_message(
+                "validation.length.max",
+                "message",
+                "de",
+                "Höchstens {max} Zeichen",
+            )
+            .load()
+            .unwrap();
+        let missing = opentelemetry::global::meter("test")
+            .u64_counter("missing")
+            .init();
+        LocalizeLayer::new(Some(catalog), missing)
+    }
+
+    async fn call(
+        accept_language: Option<&str>,
+        resp: fn() -> Response<Body>,
+    ) -> (Response<Body>, serde_json::Value) {
+        let svc = layer().layer(service_fn(move |_: Request<Body>| async move {
+            Ok::<_, Infallible>(resp())
+        }));
+        let mut req = Request::new(Body::empty());
+        if let Some(val) = accept_language {
+            req.headers_mut()
+                .insert(ACCEPT_LANGUAGE, HeaderValue::from_str(val).unwrap());
+        }
+        let resp = svc.oneshot(req).await.unwrap();
+        let (parts, body) = resp.into_parts();
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        let json = serde_json::from_slice(&bytes).unwrap();
+        (Response::from_parts(parts, Body::empty()), json)
+    }
+
+    fn not_found() -> Response<Body> {
+        problemdetails::new(StatusCode::NOT_FOUND)
+            .with_type(NOT_FOUND)
+            .with_title("Not found")
+            .with_detail("Order is missing")
+            .into_response()
+    }
+
+    /// Translated fields are replaced, missing ones fall back to built-in text.
+    #[tokio::test]
+    async fn translation_and_fallback() {
+        let (resp, body) = call(Some("fr-FR, de;q=0.8"), not_found).await;
+        assert_eq!(body["title"], "Introuvable");
+        assert_eq!(body["detail"], "Order is missing");
+        assert_eq!(body["type"], NOT_FOUND);
+        assert_eq!(resp.headers()[CONTENT_LANGUAGE], "fr");
+
+        // Default locale is used when nothing matches.
+        let (_, body) = call(Some("it"), not_found).await;
+        assert_eq!(body["title"], "Nicht gefunden");
+        assert_eq!(body["detail"], "Bestellung fehlt");
+
+        // Built-in locale is left as-is.
+        let (resp, body) = call(Some("en"), not_found).await;
+        assert_eq!(body["title"], "Not found");
+        assert!(!resp.headers().contains_key(CONTENT_LANGUAGE));
+    }
+
+    /// Explicit message key takes precedence over problem type.
+    #[tokio::test]
+    async fn message_key() {
+        let (_, body) = call(Some("fr"), || {
+            (
+                ResponseExtension(MessageKey::new("orders.gone")),
+                not_found(),
+            )
+                .into_response()
+        })
+        .await;
+        assert_eq!(body["title"], "Commande supprimée");
+    }
+
+    /// Non-error responses are untouched.
+    #[tokio::test]
+    async fn non_error() {
+        let (resp, body) = call(Some("de"), || {
+            axum::Json(serde_json::json!({"title": "Not found"})).into_response()
+        })
+        .await;
+        assert_eq!(body["title"], "Not found");
+        assert!(!resp.headers().contains_key(CONTENT_LANGUAGE));
+    }
+
+    /// Messages of validation violations are translated, with rule parameters substituted.
+    #[tokio::test]
+    async fn validation_messages() {
+        fn invalid() -> Response<Body> {
+            let mut errors = crate::ValidationErrors::new();
+            errors.add(
+                "/name",
+                crate::ValidationError::new("length.max", "length must be at most 3")
+                    .with_param("max", 3),
+            );
+            errors.add("/email", crate::ValidationError::new("email", "invalid"));
+            errors.into_response()
+        }
+        let (resp, body) = call(Some("de"), invalid).await;
+        assert_eq!(body["errors"][0]["message"], "Höchstens 3 Zeichen");
+        assert_eq!(body["errors"][0]["pointer"], "/name");
+        assert_eq!(body["errors"][1]["message"], "invalid");
+        assert_eq!(resp.headers()[CONTENT_LANGUAGE], "de");
+    }
+}
@@ -1,10 +1,31 @@
 //! Various [`tower`] layers used in the framework.
 
+pub(crate) mod access_log;
+pub(crate) mod body_limit;
 pub(crate) mod buffer;
+pub(crate) mod cache;
+pub(crate) mod cb;
+pub(crate) mod client_ip;
+pub(crate) mod compression;
+pub(crate) mod contract;
 pub(crate) mod cors;
+pub(crate) mod cpu_guard;
+pub(crate) mod decompression;
+pub(crate) mod dependency;
+pub(crate) mod deprecation;
+pub(crate) mod error_context;
 pub(crate) mod ext;
+pub(crate) mod fair;
+pub(crate) mod identity;
+pub(crate) mod ip_filter;
+pub(crate) mod localize;
 pub(crate) mod rate;
+pub(crate) mod recent_errors;
 pub(crate) mod request_id;
+pub(crate) mod response_cache;
 pub(crate) mod throttle;
 pub(crate) mod timeout;
+pub(crate) mod toggle;
+pub(crate) mod trailing_slash;
+pub(crate) mod transform;
 pub(crate) mod util;
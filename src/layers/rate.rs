@@ -1,35 +1,46 @@
 //! Rate limiting [`tower`] layer.
 
 use std::{
+    collections::BTreeMap,
     future::Future,
+    hash::Hash,
     marker::PhantomData,
+    net::IpAddr,
     num::NonZeroU32,
     pin::Pin,
     sync::Arc,
     task::{ready, Context, Poll},
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 use axum::{
     body::Body,
-    http::{Request, Response, StatusCode},
+    http::{HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode},
     response::IntoResponse,
 };
 use dashmap::DashMap;
 use governor::{
-    clock::{Clock, DefaultClock, QuantaClock, QuantaInstant},
-    middleware::NoOpMiddleware,
+    clock::{Clock, DefaultClock, QuantaClock},
+    middleware::{StateInformationMiddleware, StateSnapshot},
     state::{InMemoryState, NotKeyed},
-    Quota, RateLimiter,
+    NotUntil, Quota, RateLimiter,
 };
+use opentelemetry::{metrics::Counter, KeyValue};
 use pin_project::pin_project;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tower::{BoxError, Layer, Service};
 use tracing::{trace_span, warn};
 
-use crate::layers::util::{
-    ExtractionError, KeyExtractor, PeerIpKeyExtractor, SmartIpKeyExtractor, UserIdKeyExtractor,
+use crate::{
+    apidoc::ConfigAnnotation,
+    auth::{CurrentUser, UserId},
+    errors::{codes, ErrorCode},
+    layers::util::{
+        ExtractionError, KeyExtractor, PeerIpKeyExtractor, SmartIpKeyExtractor, UserIdKeyExtractor,
+    },
+    persist::{register_limiter, BucketSnapshot, PersistentLimiter},
+    retry::{RetryAdvice, RetryAdviceConfig, RetrySource},
 };
 
 /// Error type returned by rate-limiting layer.
@@ -46,6 +57,12 @@ pub enum RateLimitError {
         ///
         /// NOTE: Retry-After cannot be specified with fractional digits as per RFC 9110.
         remaining_seconds: u64,
+        /// Advice used for `Retry-After` header.
+        advice: RetryAdvice,
+        /// Client rate limit state, if it should be reported in `RateLimit-*` headers.
+        state: Option<RateLimitState>,
+        /// Dimension of exhausted limit.
+        key: RateLimitKey,
     },
 }
 
@@ -57,17 +74,125 @@ impl RateLimitError {
             _ => StatusCode::BAD_REQUEST,
         }
     }
+
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Extraction(_) => codes::RATE_LIMIT_KEY,
+            Self::LimitReached { .. } => codes::RATE_LIMITED,
+        }
+    }
 }
 
 impl IntoResponse for RateLimitError {
     fn into_response(self) -> Response<Body> {
-        problemdetails::new(self.http_status())
+        let code = self.code();
+        let problem = code
+            .problem(self.http_status())
             .with_type("tag:uxum.github.io,2024:rate-limit")
-            .with_title(self.to_string())
-            .into_response()
+            .with_title(self.to_string());
+        match self {
+            Self::LimitReached {
+                advice, state, key, ..
+            } => {
+                let problem = problem
+                    .with_detail(format!("Rate limit is applied {}", key.scope()))
+                    .with_value("rate_limit_key", key.as_str());
+                let mut resp = advice.problem_response(problem);
+                if let Some(state) = state {
+                    state.apply(resp.headers_mut());
+                }
+                (code, resp)
+            }
+            _ => (code, problem.into_response()),
+        }
+        .into_response()
+    }
+}
+
+/// `RateLimit-Limit` header name.
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+/// `RateLimit-Remaining` header name.
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+/// `RateLimit-Reset` header name.
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+/// `RateLimit-Policy` header name.
+const RATELIMIT_POLICY: HeaderName = HeaderName::from_static("ratelimit-policy");
+
+/// Rate limit state of a client, as seen by the limiter when checking a request.
+///
+/// Reported to clients using `RateLimit-*` headers from IETF draft
+/// `draft-ietf-httpapi-ratelimit-headers`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RateLimitState {
+    /// Maximum number of requests available at once.
+    pub limit: u32,
+    /// Number of requests available right now.
+    pub remaining: u32,
+    /// Time until full quota is available again.
+    pub reset: Duration,
+    /// Time needed to replenish full quota from zero.
+    pub window: Duration,
+}
+
+impl RateLimitState {
+    /// Build state from positive outcome of a rate limiter check.
+    fn allowed(snapshot: &StateSnapshot) -> Self {
+        let quota = snapshot.quota();
+        let limit = quota.burst_size().get();
+        let remaining = snapshot.remaining_burst_capacity().min(limit);
+        Self {
+            limit,
+            remaining,
+            reset: quota.replenish_interval() * (limit - remaining),
+            window: quota.burst_size_replenished_in(),
+        }
+    }
+
+    /// Build state from negative outcome of a rate limiter check.
+    fn denied(not_until: &NotUntil<<QuantaClock as Clock>::Instant>, wait: Duration) -> Self {
+        let quota = not_until.quota();
+        let limit = quota.burst_size().get();
+        Self {
+            limit,
+            remaining: 0,
+            reset: wait + quota.replenish_interval() * (limit - 1),
+            window: quota.burst_size_replenished_in(),
+        }
+    }
+
+    /// Add `RateLimit-*` headers to a header map.
+    fn apply(&self, headers: &mut HeaderMap) {
+        let policy = format!("{};w={}", self.limit, ceil_secs(self.window));
+        headers.insert(RATELIMIT_LIMIT, HeaderValue::from(self.limit));
+        headers.insert(RATELIMIT_REMAINING, HeaderValue::from(self.remaining));
+        headers.insert(RATELIMIT_RESET, HeaderValue::from(ceil_secs(self.reset)));
+        // SAFETY: policy consists only of digits and ASCII punctuation.
+        headers.insert(RATELIMIT_POLICY, HeaderValue::from_str(&policy).unwrap());
     }
 }
 
+/// Round duration up to whole seconds, as required by `RateLimit-*` headers.
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+/// When to emit `RateLimit-*` headers.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum RateLimitHeaders {
+    /// Never emit headers.
+    #[default]
+    Off,
+    /// Emit headers only when rate limit is exceeded.
+    Limited,
+    /// Emit headers on every response.
+    Always,
+}
+
 /// Configuration for rate-limiting layer.
 ///
 /// Uses [`governor`] crate internally.
@@ -88,9 +213,33 @@ pub struct HandlerRateLimitConfig {
         with = "humantime_serde"
     )]
     burst_duration: Duration,
+    /// When to report client rate limit state in `RateLimit-*` response headers.
+    #[serde(default)]
+    headers: RateLimitHeaders,
+    /// Overrides for authenticated users with specific roles.
+    ///
+    /// If several roles of a user are overridden, bypass takes precedence, followed by the
+    /// highest sustained rate.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    roles: BTreeMap<String, RoleRateLimitConfig>,
     // TODO: boolean - ignore extraction errors.
 }
 
+/// Rate limit override for users with a specific role.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct RoleRateLimitConfig {
+    /// Do not rate limit users with this role.
+    #[serde(default, skip_serializing_if = "<&bool as std::ops::Not>::not")]
+    bypass: bool,
+    /// Sustained requests per second, replacing handler-wide value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rps: Option<NonZeroU32>,
+    /// Maximum requests per second during burst, replacing handler-wide value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    burst_rps: Option<NonZeroU32>,
+}
+
 impl HandlerRateLimitConfig {
     /// Default value for [`Self::burst_duration`].
     #[must_use]
@@ -114,6 +263,39 @@ impl HandlerRateLimitConfig {
         Duration::from_secs(1) / self.rps.get()
     }
 
+    /// Build configuration for users with overridden role.
+    fn for_role(&self, role: &RoleRateLimitConfig) -> Self {
+        Self {
+            rps: role.rps.unwrap_or(self.rps),
+            burst_rps: role.burst_rps.or(self.burst_rps),
+            roles: BTreeMap::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Describe rate limit for use in OpenAPI specification.
+    #[must_use]
+    pub(crate) fn annotation(&self) -> ConfigAnnotation {
+        let burst = self.burst_size();
+        let scope = match self.key {
+            RateLimitKey::Global => String::new(),
+            key => format!(" {}", key.scope()),
+        };
+        ConfigAnnotation {
+            extension: "x-rate-limit",
+            value: serde_json::json!({
+                "rps": self.rps,
+                "burst": burst,
+                "key": self.key,
+            }),
+            summary: format!(
+                "Rate limited to {} requests per second{scope}, with bursts of up to {burst} \
+                 requests.",
+                self.rps
+            ),
+        }
+    }
+
     /// Create layer for use in [`tower`] services.
     pub fn make_layer<S, T>(&self) -> RateLimitLayer<S, T> {
         self.into()
@@ -121,28 +303,59 @@ impl HandlerRateLimitConfig {
 }
 
 /// Method of key extraction for rate limiting.
-#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "snake_case")]
 #[non_exhaustive]
-enum RateLimitKey {
+pub enum RateLimitKey {
     /// Global rate limit.
     #[default]
     Global,
     /// Per-peer-IP-address rate limit.
+    #[serde(alias = "client_ip")]
     PeerIp,
     /// Smart per-peer-IP-address rate limit.
     ///
     /// Same as [`RateLimitKey::PeerIp`], but accounts for addresses passed via
-    /// `X-Forwarded-For` and similar headers.
+    /// `X-Forwarded-For` and similar headers by trusted proxies, see
+    /// [`ClientIpConfig`](crate::ClientIpConfig).
     SmartIp,
     /// Per-authenticated-user-ID rate limit.
+    #[serde(alias = "user")]
     UserId,
 }
 
+impl RateLimitKey {
+    /// Name of key dimension, as used in responses and metrics.
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Global => "global",
+            Self::PeerIp => "peer_ip",
+            Self::SmartIp => "smart_ip",
+            Self::UserId => "user_id",
+        }
+    }
+
+    /// Human-readable scope of rate limit.
+    fn scope(&self) -> &'static str {
+        match self {
+            Self::Global => "globally",
+            Self::PeerIp | Self::SmartIp => "per client IP address",
+            Self::UserId => "per user",
+        }
+    }
+}
+
 /// Rate-limiting [`tower`] layer.
 pub struct RateLimitLayer<S, T> {
     /// Rate limiter configuration.
     config: HandlerRateLimitConfig,
+    /// Retry advice configuration.
+    retry: RetryAdviceConfig,
+    /// Name used to persist limiter state across restarts, if enabled.
+    persist: Option<String>,
+    /// Handler name and counter of rejected requests.
+    rejections: Option<(&'static str, Counter<u64>)>,
     /// Inner service type.
     _phantom_service: PhantomData<S>,
     /// Request body type.
@@ -154,12 +367,38 @@ impl<S, T> From<&HandlerRateLimitConfig> for RateLimitLayer<S, T> {
         // TODO: don't clone, but share, for runtime updates maybe?
         Self {
             config: value.clone(),
+            retry: RetryAdviceConfig::default(),
+            persist: None,
+            rejections: None,
             _phantom_service: PhantomData,
             _phantom_request: PhantomData,
         }
     }
 }
 
+impl<S, T> RateLimitLayer<S, T> {
+    /// Set retry advice configuration, used for `Retry-After` header.
+    #[must_use]
+    pub fn with_retry_advice(mut self, retry: &RetryAdviceConfig) -> Self {
+        self.retry = retry.clone();
+        self
+    }
+
+    /// Enable persistence of limiter state across restarts, using provided name.
+    #[must_use]
+    pub(crate) fn with_persistence(mut self, name: impl ToString) -> Self {
+        self.persist = Some(name.to_string());
+        self
+    }
+
+    /// Count rejected requests using provided counter.
+    #[must_use]
+    pub(crate) fn with_rejections(mut self, handler: &'static str, counter: Counter<u64>) -> Self {
+        self.rejections = Some((handler, counter));
+        self
+    }
+}
+
 impl<S, T> Layer<S> for RateLimitLayer<S, T>
 where
     S: Service<Request<T>> + Send + 'static,
@@ -168,7 +407,10 @@ where
     type Service = RateLimit<S, T>;
 
     fn layer(&self, service: S) -> Self::Service {
-        RateLimit::new(service, &self.config)
+        let mut svc = RateLimit::build(service, &self.config, self.persist.as_deref())
+            .with_retry_advice(&self.retry);
+        svc.rejections.clone_from(&self.rejections);
+        svc
     }
 }
 
@@ -177,9 +419,24 @@ pub struct RateLimit<S, T> {
     /// Inner service.
     inner: S,
     /// Rate limiter.
-    limiter: Arc<Box<dyn Limiter<T> + Send + Sync>>,
+    limiter: SharedLimiter<T>,
+    /// Rate limiters for overridden roles, in order of precedence.
+    ///
+    /// `None` means rate limiting is bypassed.
+    role_limiters: Arc<[(String, Option<SharedLimiter<T>>)]>,
+    /// Retry advice configuration.
+    retry: Arc<RetryAdviceConfig>,
+    /// When to emit `RateLimit-*` headers.
+    headers: RateLimitHeaders,
+    /// Dimension of rate limiter key.
+    key: RateLimitKey,
+    /// Handler name and counter of rejected requests.
+    rejections: Option<(&'static str, Counter<u64>)>,
 }
 
+/// Rate limiter shared between service clones.
+type SharedLimiter<T> = Arc<dyn Limiter<T> + Send + Sync>;
+
 impl<S, T> Clone for RateLimit<S, T>
 where
     S: Clone,
@@ -188,13 +445,18 @@ where
         Self {
             inner: self.inner.clone(),
             limiter: Arc::clone(&self.limiter),
+            role_limiters: Arc::clone(&self.role_limiters),
+            retry: Arc::clone(&self.retry),
+            headers: self.headers,
+            key: self.key,
+            rejections: self.rejections.clone(),
         }
     }
 }
 
-impl<S, T> Service<Request<T>> for RateLimit<S, T>
+impl<S, T, B> Service<Request<T>> for RateLimit<S, T>
 where
-    S: Service<Request<T>>,
+    S: Service<Request<T>, Response = Response<B>>,
     S::Error: Into<BoxError>,
 {
     type Response = S::Response;
@@ -211,16 +473,41 @@ where
     fn call(&mut self, req: Request<T>) -> Self::Future {
         let rate_result = {
             let _span = trace_span!("rate").entered();
-            self.limiter.check_limit(&req)
+            match self.limiter_for(&req) {
+                Some(limiter) => limiter.check_limit(&req, &self.retry).map(Some),
+                None => Ok(None),
+            }
         };
         match rate_result {
-            Ok(()) => RateLimitFuture::Positive {
+            Ok(state) => RateLimitFuture::Positive {
                 inner: self.inner.call(req),
+                state: state.filter(|_| self.headers == RateLimitHeaders::Always),
             },
             // TODO: option to allow ignoring extraction errors.
-            Err(error) => {
-                if let RateLimitError::LimitReached { remaining_seconds } = &error {
-                    warn!(wait = remaining_seconds, "rate limit exceeded");
+            Err(mut error) => {
+                if let RateLimitError::LimitReached {
+                    remaining_seconds,
+                    state,
+                    ..
+                } = &mut error
+                {
+                    warn!(
+                        wait = remaining_seconds,
+                        key = self.key.as_str(),
+                        "rate limit exceeded"
+                    );
+                    if self.headers == RateLimitHeaders::Off {
+                        *state = None;
+                    }
+                }
+                if let Some((handler, counter)) = &self.rejections {
+                    counter.add(
+                        1,
+                        &[
+                            KeyValue::new("uxum.handler", *handler),
+                            KeyValue::new("uxum.rate_limit.key", self.key.as_str()),
+                        ],
+                    );
                 }
                 RateLimitFuture::Negative { error }
             }
@@ -228,6 +515,21 @@ where
     }
 }
 
+impl<S, T> RateLimit<S, T> {
+    /// Select rate limiter based on roles of authenticated user.
+    ///
+    /// Returns `None` if rate limiting is bypassed.
+    fn limiter_for(&self, req: &Request<T>) -> Option<&SharedLimiter<T>> {
+        let Some(user) = req.extensions().get::<CurrentUser>() else {
+            return Some(&self.limiter);
+        };
+        self.role_limiters
+            .iter()
+            .find(|(role, _)| user.has_role(role))
+            .map_or(Some(&self.limiter), |(_, limiter)| limiter.as_ref())
+    }
+}
+
 impl<S, T> RateLimit<S, T>
 where
     S: Service<Request<T>> + Send + 'static,
@@ -236,17 +538,69 @@ where
     /// Create new rate limiting service.
     #[must_use]
     pub fn new(inner: S, config: &HandlerRateLimitConfig) -> Self {
-        let limiter: Box<dyn Limiter<T> + Send + Sync> = match config.key {
-            RateLimitKey::Global => Box::new(GlobalLimiter::new(config)),
-            RateLimitKey::PeerIp => Box::new(KeyedLimiter::new(PeerIpKeyExtractor, config)),
-            RateLimitKey::SmartIp => Box::new(KeyedLimiter::new(SmartIpKeyExtractor, config)),
-            RateLimitKey::UserId => Box::new(KeyedLimiter::new(UserIdKeyExtractor, config)),
-        };
+        Self::build(inner, config, None)
+    }
+
+    /// Create new rate limiting service, optionally registering it for state persistence.
+    fn build(inner: S, config: &HandlerRateLimitConfig, persist: Option<&str>) -> Self {
+        let mut role_limiters: Vec<_> = config
+            .roles
+            .iter()
+            .map(|(role, rcfg)| {
+                let limiter = (!rcfg.bypass).then(|| {
+                    let persist = persist.map(|name| format!("{name}@{role}"));
+                    Self::limiter(&config.for_role(rcfg), persist.as_deref())
+                });
+                (
+                    role.clone(),
+                    rcfg.bypass,
+                    rcfg.rps.unwrap_or(config.rps),
+                    limiter,
+                )
+            })
+            .collect();
+        role_limiters.sort_by_key(|(_, bypass, rps, _)| std::cmp::Reverse((*bypass, *rps)));
         Self {
             inner,
-            limiter: Arc::new(limiter),
+            limiter: Self::limiter(config, persist),
+            role_limiters: role_limiters
+                .into_iter()
+                .map(|(role, _, _, limiter)| (role, limiter))
+                .collect(),
+            retry: Arc::new(RetryAdviceConfig::default()),
+            headers: config.headers,
+            key: config.key,
+            rejections: None,
+        }
+    }
+
+    /// Create rate limiter, optionally registering it for state persistence.
+    fn limiter(config: &HandlerRateLimitConfig, persist: Option<&str>) -> SharedLimiter<T> {
+        let tracked = persist.is_some();
+        let key = config.key;
+        match key {
+            RateLimitKey::Global => register(persist, GlobalLimiter::new(config, tracked)),
+            RateLimitKey::PeerIp => register(
+                persist,
+                KeyedLimiter::new(PeerIpKeyExtractor, key, config, tracked),
+            ),
+            RateLimitKey::SmartIp => register(
+                persist,
+                KeyedLimiter::new(SmartIpKeyExtractor, key, config, tracked),
+            ),
+            RateLimitKey::UserId => register(
+                persist,
+                KeyedLimiter::new(UserIdKeyExtractor, key, config, tracked),
+            ),
         }
     }
+
+    /// Set retry advice configuration, used for `Retry-After` header.
+    #[must_use]
+    pub fn with_retry_advice(mut self, retry: &RetryAdviceConfig) -> Self {
+        self.retry = Arc::new(retry.clone());
+        self
+    }
 }
 
 /// Rate-limiting [`tower`] service future.
@@ -257,6 +611,8 @@ pub enum RateLimitFuture<F> {
         /// Inner future.
         #[pin]
         inner: F,
+        /// Client rate limit state to report in response headers.
+        state: Option<RateLimitState>,
     },
     /// Key extraction error or rate limit exceeded.
     Negative {
@@ -265,17 +621,20 @@ pub enum RateLimitFuture<F> {
     },
 }
 
-impl<F, U, E> Future for RateLimitFuture<F>
+impl<F, B, E> Future for RateLimitFuture<F>
 where
-    F: Future<Output = Result<U, E>>,
+    F: Future<Output = Result<Response<B>, E>>,
     E: Into<BoxError>,
 {
-    type Output = Result<U, BoxError>;
+    type Output = Result<Response<B>, BoxError>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         match self.project() {
-            ProjectedOutcome::Positive { inner } => {
-                let resp = ready!(inner.poll(cx).map_err(Into::into))?;
+            ProjectedOutcome::Positive { inner, state } => {
+                let mut resp = ready!(inner.poll(cx).map_err(Into::into))?;
+                if let Some(state) = state {
+                    state.apply(resp.headers_mut());
+                }
                 Poll::Ready(Ok(resp))
             }
             ProjectedOutcome::Negative { error } => Poll::Ready(Err(Box::new(error.clone()))),
@@ -286,7 +645,154 @@ where
 /// Trait for all rate limiters.
 trait Limiter<T> {
     /// Check whether a request can pass through a rate-limiter.
-    fn check_limit(&self, req: &Request<T>) -> Result<(), RateLimitError>;
+    ///
+    /// Returns client rate limit state after accounting for the request.
+    fn check_limit(
+        &self,
+        req: &Request<T>,
+        retry: &RetryAdviceConfig,
+    ) -> Result<RateLimitState, RateLimitError>;
+}
+
+/// Register limiter for state persistence, if requested.
+fn register<L>(persist: Option<&str>, limiter: L) -> Arc<L>
+where
+    L: PersistentLimiter + 'static,
+{
+    let limiter = Arc::new(limiter);
+    if let Some(name) = persist {
+        register_limiter(name, limiter.clone());
+    }
+    limiter
+}
+
+/// Convert outcome of governor check into client rate limit state or error.
+fn check_outcome(
+    outcome: Result<StateSnapshot, NotUntil<<QuantaClock as Clock>::Instant>>,
+    retry: &RetryAdviceConfig,
+    key: RateLimitKey,
+) -> Result<RateLimitState, RateLimitError> {
+    match outcome {
+        Ok(snapshot) => Ok(RateLimitState::allowed(&snapshot)),
+        Err(neg) => {
+            let wait = neg.wait_time_from(DefaultClock::default().now());
+            Err(RateLimitError::LimitReached {
+                remaining_seconds: wait.as_secs(),
+                advice: retry.advise(RetrySource::RateLimit, Some(wait)),
+                state: Some(RateLimitState::denied(&neg, wait)),
+                key,
+            })
+        }
+    }
+}
+
+/// Build governor quota from configuration.
+fn quota(config: &HandlerRateLimitConfig) -> Quota {
+    Quota::with_period(config.period())
+        .unwrap()
+        .allow_burst(config.burst_size())
+}
+
+/// Rate limiter key which can be persisted.
+pub(crate) trait StateKey: Sized {
+    /// Encode key as a string.
+    fn encode(&self) -> String;
+
+    /// Decode key from a string.
+    fn decode(value: &str) -> Option<Self>;
+}
+
+impl StateKey for () {
+    fn encode(&self) -> String {
+        String::new()
+    }
+
+    fn decode(_value: &str) -> Option<Self> {
+        Some(())
+    }
+}
+
+impl StateKey for IpAddr {
+    fn encode(&self) -> String {
+        self.to_string()
+    }
+
+    fn decode(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+}
+
+impl StateKey for UserId {
+    fn encode(&self) -> String {
+        self.as_str().to_owned()
+    }
+
+    fn decode(value: &str) -> Option<Self> {
+        Some(value.into())
+    }
+}
+
+/// Bucket state tracker, used for state persistence.
+struct BucketTracker<K> {
+    /// Bucket size.
+    burst: NonZeroU32,
+    /// Time needed to replenish one token.
+    period: Duration,
+    /// Last observed remaining capacity of each bucket, along with time of observation.
+    buckets: DashMap<K, (u32, SystemTime)>,
+}
+
+impl<K: StateKey + Hash + Eq> BucketTracker<K> {
+    /// Create new tracker.
+    fn new(config: &HandlerRateLimitConfig) -> Self {
+        Self {
+            burst: config.burst_size(),
+            period: config.period(),
+            buckets: DashMap::new(),
+        }
+    }
+
+    /// Record outcome of rate limit check.
+    fn record<E>(&self, key: &K, outcome: &Result<StateSnapshot, E>)
+    where
+        K: Clone,
+    {
+        let tokens = outcome
+            .as_ref()
+            .map_or(0, StateSnapshot::remaining_burst_capacity);
+        self.buckets
+            .insert(key.clone(), (tokens, SystemTime::now()));
+    }
+
+    /// Get persisted states of all tracked buckets.
+    fn snapshot(&self) -> Vec<BucketSnapshot> {
+        self.buckets
+            .iter()
+            .map(|entry| BucketSnapshot {
+                key: entry.key().encode(),
+                tokens: entry.value().0,
+                at: entry.value().1,
+            })
+            .collect()
+    }
+
+    /// Decode persisted bucket states, returning number of tokens to consume for each key.
+    ///
+    /// Tokens replenished since the state was saved are taken into account.
+    fn restore(&self, buckets: &[BucketSnapshot]) -> Vec<(K, NonZeroU32)> {
+        let now = SystemTime::now();
+        buckets
+            .iter()
+            .filter_map(|bucket| {
+                let key = K::decode(&bucket.key)?;
+                let elapsed = now.duration_since(bucket.at).unwrap_or_default();
+                let replenished = (elapsed.as_secs_f64() / self.period.as_secs_f64()) as u32;
+                let tokens = bucket.tokens.saturating_add(replenished);
+                let used = NonZeroU32::new(self.burst.get().saturating_sub(tokens))?;
+                Some((key, used))
+            })
+            .collect()
+    }
 }
 
 /// Global rate limiter.
@@ -294,28 +800,50 @@ trait Limiter<T> {
 /// Does no key extraction from requests.
 struct GlobalLimiter {
     /// Internal limiter state.
-    limiter: RateLimiter<NotKeyed, InMemoryState, QuantaClock, NoOpMiddleware<QuantaInstant>>,
+    limiter: RateLimiter<NotKeyed, InMemoryState, QuantaClock, StateInformationMiddleware>,
+    /// Bucket state tracker, if state persistence is enabled.
+    tracker: Option<BucketTracker<()>>,
 }
 
 impl<T> Limiter<T> for GlobalLimiter {
-    fn check_limit(&self, _req: &Request<T>) -> Result<(), RateLimitError> {
-        self.limiter.check().map_err(|neg| {
-            let remaining_seconds = neg.wait_time_from(DefaultClock::default().now()).as_secs();
-            RateLimitError::LimitReached { remaining_seconds }
-        })
+    fn check_limit(
+        &self,
+        _req: &Request<T>,
+        retry: &RetryAdviceConfig,
+    ) -> Result<RateLimitState, RateLimitError> {
+        let outcome = self.limiter.check();
+        if let Some(tracker) = &self.tracker {
+            tracker.record(&(), &outcome);
+        }
+        check_outcome(outcome, retry, RateLimitKey::Global)
+    }
+}
+
+impl PersistentLimiter for GlobalLimiter {
+    fn snapshot(&self) -> Vec<BucketSnapshot> {
+        self.tracker
+            .as_ref()
+            .map(BucketTracker::snapshot)
+            .unwrap_or_default()
+    }
+
+    fn restore(&self, buckets: &[BucketSnapshot]) {
+        if let Some(tracker) = &self.tracker {
+            for ((), used) in tracker.restore(buckets) {
+                let _ = self.limiter.check_n(used);
+            }
+        }
     }
 }
 
 impl GlobalLimiter {
     /// Create new global limiter.
     #[must_use]
-    fn new(config: &HandlerRateLimitConfig) -> Self {
+    fn new(config: &HandlerRateLimitConfig, tracked: bool) -> Self {
         Self {
-            limiter: RateLimiter::direct(
-                Quota::with_period(config.period())
-                    .unwrap()
-                    .allow_burst(config.burst_size()),
-            ),
+            limiter: RateLimiter::direct(quota(config))
+                .with_middleware::<StateInformationMiddleware>(),
+            tracker: tracked.then(|| BucketTracker::new(config)),
         }
     }
 }
@@ -326,36 +854,241 @@ impl GlobalLimiter {
 struct KeyedLimiter<K: KeyExtractor> {
     /// Key extractor.
     extractor: K,
+    /// Dimension of extracted key.
+    key: RateLimitKey,
     /// Internal keyed limiter states.
     limiters: RateLimiter<
         K::Key,
         DashMap<K::Key, InMemoryState>,
         QuantaClock,
-        NoOpMiddleware<QuantaInstant>,
+        StateInformationMiddleware,
     >,
+    /// Bucket state tracker, if state persistence is enabled.
+    tracker: Option<BucketTracker<K::Key>>,
 }
 
-impl<T, K: KeyExtractor> Limiter<T> for KeyedLimiter<K> {
-    fn check_limit(&self, req: &Request<T>) -> Result<(), RateLimitError> {
+impl<T, K> Limiter<T> for KeyedLimiter<K>
+where
+    K: KeyExtractor,
+    K::Key: StateKey,
+{
+    fn check_limit(
+        &self,
+        req: &Request<T>,
+        retry: &RetryAdviceConfig,
+    ) -> Result<RateLimitState, RateLimitError> {
         let key = self.extractor.extract(req)?;
-        self.limiters.check_key(&key).map_err(|neg| {
-            let remaining_seconds = neg.wait_time_from(DefaultClock::default().now()).as_secs();
-            RateLimitError::LimitReached { remaining_seconds }
-        })
+        let outcome = self.limiters.check_key(&key);
+        if let Some(tracker) = &self.tracker {
+            tracker.record(&key, &outcome);
+        }
+        check_outcome(outcome, retry, self.key)
     }
 }
 
-impl<K: KeyExtractor> KeyedLimiter<K> {
+impl<K> PersistentLimiter for KeyedLimiter<K>
+where
+    K: KeyExtractor + Send + Sync,
+    K::Key: StateKey + Send + Sync,
+{
+    fn snapshot(&self) -> Vec<BucketSnapshot> {
+        self.tracker
+            .as_ref()
+            .map(BucketTracker::snapshot)
+            .unwrap_or_default()
+    }
+
+    fn restore(&self, buckets: &[BucketSnapshot]) {
+        if let Some(tracker) = &self.tracker {
+            for (key, used) in tracker.restore(buckets) {
+                let _ = self.limiters.check_key_n(&key, used);
+            }
+        }
+    }
+}
+
+impl<K> KeyedLimiter<K>
+where
+    K: KeyExtractor,
+    K::Key: StateKey,
+{
     /// Create new keyed limiter.
     #[must_use]
-    fn new(extractor: K, config: &HandlerRateLimitConfig) -> Self {
+    fn new(
+        extractor: K,
+        key: RateLimitKey,
+        config: &HandlerRateLimitConfig,
+        tracked: bool,
+    ) -> Self {
         Self {
             extractor,
-            limiters: RateLimiter::keyed(
-                Quota::with_period(config.period())
-                    .unwrap()
-                    .allow_burst(config.burst_size()),
-            ),
+            key,
+            limiters: RateLimiter::keyed(quota(config))
+                .with_middleware::<StateInformationMiddleware>(),
+            tracker: tracked.then(|| BucketTracker::new(config)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// Call rate-limited service, converting errors into responses.
+    async fn call(headers: &str, count: usize) -> Vec<Response<Body>> {
+        let config: HandlerRateLimitConfig = serde_json::from_value(
+            serde_json::json!({"rps": 1, "burst_rps": 3, "headers": headers}),
+        )
+        .unwrap();
+        let inner = tower::service_fn(|_req: Request<Body>| async {
+            Ok::<_, BoxError>(Response::new(Body::empty()))
+        });
+        let svc = RateLimit::new(inner, &config);
+        let mut resps = Vec::new();
+        for _ in 0..count {
+            let resp = match svc.clone().oneshot(Request::new(Body::empty())).await {
+                Ok(resp) => resp,
+                Err(err) => err.downcast::<RateLimitError>().unwrap().into_response(),
+            };
+            resps.push(resp);
         }
+        resps
+    }
+
+    fn header<'a>(resp: &'a Response<Body>, name: &HeaderName) -> Option<&'a str> {
+        resp.headers().get(name).map(|val| val.to_str().unwrap())
+    }
+
+    #[tokio::test]
+    async fn headers_always() {
+        let resps = call("always", 4).await;
+        let expected = [
+            (StatusCode::OK, "2", "1"),
+            (StatusCode::OK, "1", "2"),
+            (StatusCode::OK, "0", "3"),
+            (StatusCode::TOO_MANY_REQUESTS, "0", "3"),
+        ];
+        for (resp, (status, remaining, reset)) in resps.iter().zip(expected) {
+            assert_eq!(resp.status(), status);
+            assert_eq!(header(resp, &RATELIMIT_LIMIT), Some("3"));
+            assert_eq!(header(resp, &RATELIMIT_REMAINING), Some(remaining));
+            assert_eq!(header(resp, &RATELIMIT_RESET), Some(reset));
+            assert_eq!(header(resp, &RATELIMIT_POLICY), Some("3;w=3"));
+        }
+    }
+
+    #[tokio::test]
+    async fn headers_limited() {
+        let resps = call("limited", 4).await;
+        assert!(resps[..3]
+            .iter()
+            .all(|resp| header(resp, &RATELIMIT_LIMIT).is_none()));
+        assert_eq!(resps[3].status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(header(&resps[3], &RATELIMIT_REMAINING), Some("0"));
+    }
+
+    #[tokio::test]
+    async fn headers_off() {
+        let resps = call("off", 4).await;
+        assert_eq!(resps[3].status(), StatusCode::TOO_MANY_REQUESTS);
+        for resp in &resps {
+            for name in [
+                &RATELIMIT_LIMIT,
+                &RATELIMIT_REMAINING,
+                &RATELIMIT_RESET,
+                &RATELIMIT_POLICY,
+            ] {
+                assert!(header(resp, name).is_none());
+            }
+        }
+    }
+
+    /// Roles can raise or bypass per-user limits.
+    #[tokio::test]
+    async fn role_overrides() {
+        let config: HandlerRateLimitConfig = serde_json::from_value(serde_json::json!({
+            "key": "user",
+            "rps": 1,
+            "roles": {
+                "admin": {"bypass": true},
+                "partner": {"rps": 5},
+            },
+        }))
+        .unwrap();
+        let inner = tower::service_fn(|_req: Request<Body>| async {
+            Ok::<_, BoxError>(Response::new(Body::empty()))
+        });
+        let svc = RateLimit::new(inner, &config);
+        let call = |user: &str, roles: &[&str]| {
+            let mut req = Request::new(Body::empty());
+            req.extensions_mut().insert(UserId::from(user));
+            req.extensions_mut().insert(CurrentUser {
+                id: Some(UserId::from(user)),
+                roles: roles.iter().map(ToString::to_string).collect(),
+                permissions: &[],
+            });
+            let svc = svc.clone();
+            async move {
+                match svc.oneshot(req).await {
+                    Ok(resp) => resp,
+                    Err(err) => err.downcast::<RateLimitError>().unwrap().into_response(),
+                }
+            }
+        };
+
+        assert_eq!(call("bob", &[]).await.status(), StatusCode::OK);
+        let resp = call("bob", &[]).await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["rate_limit_key"], "user_id");
+        assert_eq!(problem["detail"], "Rate limit is applied per user");
+
+        for _ in 0..10 {
+            assert_eq!(
+                call("alice", &["admin", "partner"]).await.status(),
+                StatusCode::OK
+            );
+        }
+        for _ in 0..5 {
+            assert_eq!(call("carol", &["partner"]).await.status(), StatusCode::OK);
+        }
+        assert_eq!(
+            call("carol", &["partner"]).await.status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+
+        let ip: HandlerRateLimitConfig =
+            serde_json::from_value(serde_json::json!({"key": "client_ip", "rps": 1})).unwrap();
+        assert_eq!(ip.key, RateLimitKey::PeerIp);
+    }
+
+    /// Restored limiter continues from saved bucket state.
+    #[test]
+    fn restore_buckets() {
+        let config: HandlerRateLimitConfig =
+            serde_json::from_value(serde_json::json!({"rps": 1, "burst_rps": 5})).unwrap();
+        let limiter = KeyedLimiter::new(UserIdKeyExtractor, RateLimitKey::UserId, &config, true);
+        let user = UserId::from("alice");
+        let _ = limiter
+            .limiters
+            .check_key_n(&user, NonZeroU32::new(4).unwrap());
+        limiter
+            .tracker
+            .as_ref()
+            .unwrap()
+            .record(&user, &limiter.limiters.check_key(&user));
+        let saved = limiter.snapshot();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].tokens, 0);
+
+        let restored = KeyedLimiter::new(UserIdKeyExtractor, RateLimitKey::UserId, &config, true);
+        restored.restore(&saved);
+        assert!(restored.limiters.check_key(&user).is_err());
+        assert!(restored.limiters.check_key(&UserId::from("bob")).is_ok());
     }
 }
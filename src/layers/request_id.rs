@@ -1,4 +1,4 @@
-//! [`tower`] layer to record request ID.
+//! [`tower`] layer to record request ID, and incoming request ID policy.
 
 use std::{
     marker::PhantomData,
@@ -6,9 +6,13 @@ use std::{
 };
 
 use axum::{body::Body, http::Request};
+use serde::{Deserialize, Serialize};
 use tokio::task::futures::TaskLocalFuture;
 use tower::{Layer, Service};
 use tower_http::request_id::RequestId;
+use tracing::debug;
+
+use crate::layers::client_ip::ClientIp;
 
 tokio::task_local! {
     /// Request ID of currently executing request, if any.
@@ -17,6 +21,91 @@ tokio::task_local! {
 
 pub(crate) const X_REQUEST_ID: &str = "x-request-id";
 
+/// How to treat request IDs passed by clients.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum RequestIdMode {
+    /// Always generate new request ID, ignoring incoming one.
+    AlwaysGenerate,
+    /// Use incoming request ID if it is valid.
+    #[default]
+    TrustIncoming,
+    /// Use incoming request ID if it is valid, and peer is one of trusted proxies.
+    ///
+    /// Trusted proxies are configured in [`ClientIpConfig`](crate::ClientIpConfig).
+    TrustListedProxies,
+}
+
+/// Request ID configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct RequestIdConfig {
+    /// How to treat request IDs passed by clients.
+    #[serde(default)]
+    pub mode: RequestIdMode,
+    /// Maximum length of incoming request ID.
+    #[serde(default = "RequestIdConfig::default_max_length")]
+    pub max_length: usize,
+}
+
+impl Default for RequestIdConfig {
+    fn default() -> Self {
+        Self {
+            mode: RequestIdMode::default(),
+            max_length: Self::default_max_length(),
+        }
+    }
+}
+
+impl RequestIdConfig {
+    /// Default value for [`Self::max_length`].
+    #[must_use]
+    #[inline]
+    fn default_max_length() -> usize {
+        128
+    }
+
+    /// Remove incoming request ID from request, unless it is valid and trusted.
+    ///
+    /// Removed request ID is then replaced with a generated one.
+    pub(crate) fn filter_incoming<T>(&self, mut req: Request<T>) -> Request<T> {
+        let Some(value) = req.headers().get(X_REQUEST_ID) else {
+            return req;
+        };
+        let trusted = match self.mode {
+            RequestIdMode::AlwaysGenerate => false,
+            RequestIdMode::TrustIncoming => true,
+            RequestIdMode::TrustListedProxies => req
+                .extensions()
+                .get::<ClientIp>()
+                .is_some_and(ClientIp::is_proxied),
+        };
+        let valid = self.is_valid(value.as_bytes());
+        if trusted && !valid {
+            debug!(
+                length = value.len(),
+                "discarding invalid incoming request ID"
+            );
+        }
+        if !trusted || !valid {
+            req.headers_mut().remove(X_REQUEST_ID);
+        }
+        req
+    }
+
+    /// Check length and character set of request ID.
+    ///
+    /// Only a conservative set of characters is allowed, to avoid log injection.
+    fn is_valid(&self, value: &[u8]) -> bool {
+        !value.is_empty()
+            && value.len() <= self.max_length
+            && value
+                .iter()
+                .all(|ch| ch.is_ascii_alphanumeric() || b"-_.:+/=".contains(ch))
+    }
+}
+
 /// Record request ID [`tower`] layer.
 #[derive(Clone)]
 pub(crate) struct RecordRequestIdLayer<S> {
@@ -30,7 +119,6 @@ where
 {
     type Service = RecordRequestIdService<S>;
 
-    #[must_use]
     fn layer(&self, inner: S) -> Self::Service {
         RecordRequestIdService::new(inner)
     }
@@ -78,3 +166,89 @@ impl<S> RecordRequestIdService<S> {
         Self { inner }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::extract::ConnectInfo;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{AppBuilder, AppConfig, ClientIpConfig};
+
+    /// Send request through the app, returning request ID from response header and handler.
+    async fn request_id(
+        config: RequestIdConfig,
+        incoming: &str,
+        peer: [u8; 4],
+    ) -> (String, String) {
+        let app_cfg = AppConfig {
+            client_ip: ClientIpConfig::default().with_trusted_proxy("10.0.0.0/8".parse().unwrap()),
+            request_id: config,
+            ..AppConfig::default()
+        };
+        let mut app_builder = AppBuilder::from_config(&app_cfg);
+        app_builder.with_fallback(|| async {
+            CURRENT_REQUEST_ID.with(|id| {
+                id.as_ref()
+                    .map(|id| id.header_value().to_str().unwrap().to_owned())
+                    .unwrap_or_default()
+            })
+        });
+        let rtr = app_builder.build().unwrap();
+        let mut req = Request::get("/nothing")
+            .header(X_REQUEST_ID, incoming)
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((peer, 1234))));
+        let resp = rtr.oneshot(req).await.unwrap();
+        let header = resp.headers()[X_REQUEST_ID].to_str().unwrap().to_owned();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (header, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    /// Valid incoming request IDs are kept, invalid or untrusted ones are replaced.
+    #[tokio::test]
+    async fn incoming_request_ids() {
+        let trusted = "edge-4f2a:17";
+        let (header, current) =
+            request_id(RequestIdConfig::default(), trusted, [10, 0, 0, 1]).await;
+        assert_eq!(header, trusted);
+        assert_eq!(current, trusted);
+
+        let oversized = "a".repeat(4096);
+        for spoofed in [
+            oversized.as_str(),
+            "id with spaces",
+            "<script>",
+            "\"quoted\"",
+        ] {
+            let (header, current) =
+                request_id(RequestIdConfig::default(), spoofed, [10, 0, 0, 1]).await;
+            assert_ne!(header, spoofed);
+            assert_eq!(header, current);
+            assert!(uuid::Uuid::parse_str(&header).is_ok());
+        }
+
+        let config = RequestIdConfig {
+            mode: RequestIdMode::AlwaysGenerate,
+            ..RequestIdConfig::default()
+        };
+        let (header, _) = request_id(config, trusted, [10, 0, 0, 1]).await;
+        assert_ne!(header, trusted);
+
+        let config = RequestIdConfig {
+            mode: RequestIdMode::TrustListedProxies,
+            ..RequestIdConfig::default()
+        };
+        let (header, _) = request_id(config.clone(), trusted, [10, 0, 0, 1]).await;
+        assert_eq!(header, trusted);
+        let (header, current) = request_id(config, trusted, [192, 0, 2, 1]).await;
+        assert_ne!(header, trusted);
+        assert_eq!(header, current);
+    }
+}
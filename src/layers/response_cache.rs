@@ -0,0 +1,557 @@
+//! In-memory response cache layer.
+//!
+//! Caches successful responses of `GET` and `HEAD` requests for a configured time. Responses are
+//! only cached if their body size is known in advance and fits into configured limit, streaming
+//! responses bypass the cache. Memory used by cached bodies is reserved from global budget, see
+//! [`crate::memory`].
+
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::{Body, Bytes},
+    http::{
+        header::{CACHE_CONTROL, CONTENT_LENGTH},
+        HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode,
+    },
+};
+use futures::future::BoxFuture;
+use http_body::Body as _;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tower::{BoxError, Layer, Service};
+use tracing::{debug, warn};
+
+use crate::memory::{self, Reservation};
+
+/// Response header indicating whether response was served from cache.
+const X_CACHE: HeaderName = HeaderName::from_static("x-cache");
+
+/// Name of the layer, used in memory budget accounting.
+const LAYER_NAME: &str = "response_cache";
+
+/// Configuration for in-memory response cache layer.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct HandlerCacheConfig {
+    /// Time to keep cached responses for.
+    ///
+    /// Default is 1 minute.
+    #[serde(default = "HandlerCacheConfig::default_ttl", with = "humantime_serde")]
+    pub ttl: Duration,
+    /// Maximum number of cached responses.
+    ///
+    /// Default is 1024.
+    #[serde(default = "HandlerCacheConfig::default_max_entries")]
+    pub max_entries: NonZeroUsize,
+    /// Request headers to include in cache key, in addition to URL path and query.
+    ///
+    /// Headers used by authentication are always included for handlers requiring it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub key_headers: Vec<String>,
+    /// Maximum body size of a cached response, in bytes.
+    ///
+    /// Default is 1 MiB.
+    #[serde(default = "HandlerCacheConfig::default_max_body_size")]
+    pub max_body_size: usize,
+}
+
+impl Default for HandlerCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Self::default_ttl(),
+            max_entries: Self::default_max_entries(),
+            key_headers: Vec::new(),
+            max_body_size: Self::default_max_body_size(),
+        }
+    }
+}
+
+impl HandlerCacheConfig {
+    /// Default value for [`Self::ttl`].
+    #[must_use]
+    #[inline]
+    fn default_ttl() -> Duration {
+        Duration::from_secs(60)
+    }
+
+    /// Default value for [`Self::max_entries`].
+    #[must_use]
+    #[inline]
+    fn default_max_entries() -> NonZeroUsize {
+        NonZeroUsize::new(1024).unwrap_or(NonZeroUsize::MIN)
+    }
+
+    /// Default value for [`Self::max_body_size`].
+    #[must_use]
+    #[inline]
+    fn default_max_body_size() -> usize {
+        1024 * 1024
+    }
+
+    /// Set time to keep cached responses for.
+    #[must_use]
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Set maximum number of cached responses.
+    #[must_use]
+    pub fn with_max_entries(mut self, max_entries: NonZeroUsize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Add request header to include in cache key.
+    #[must_use]
+    pub fn with_key_header(mut self, header: impl ToString) -> Self {
+        self.key_headers.push(header.to_string());
+        self
+    }
+
+    /// Set maximum body size of a cached response.
+    #[must_use]
+    pub fn with_max_body_size(mut self, max_body_size: usize) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Create layer for use in [`tower`] services.
+    ///
+    /// `extra_headers` are included in cache key along with [`Self::key_headers`].
+    #[must_use]
+    pub(crate) fn make_layer(
+        &self,
+        handler: &'static str,
+        extra_headers: &[String],
+    ) -> ResponseCacheLayer {
+        let mut key_headers: Vec<HeaderName> = Vec::new();
+        for name in self.key_headers.iter().chain(extra_headers) {
+            match HeaderName::try_from(name.as_str()) {
+                Ok(name) if !key_headers.contains(&name) => key_headers.push(name),
+                Ok(_) => {}
+                Err(_) => warn!(handler, header = name, "invalid cache key header, skipping"),
+            }
+        }
+        ResponseCacheLayer {
+            state: Arc::new(CacheState {
+                handler,
+                ttl: self.ttl,
+                max_entries: self.max_entries.get(),
+                max_body_size: self.max_body_size,
+                key_headers,
+                entries: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+}
+
+/// Cache key of a request.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct CacheKey {
+    /// HTTP method.
+    method: Method,
+    /// URL path and query.
+    uri: String,
+    /// Values of key headers, in order.
+    headers: Vec<Option<HeaderValue>>,
+}
+
+/// Cached response.
+#[derive(Debug)]
+struct CacheEntry {
+    /// Response status code.
+    status: StatusCode,
+    /// Response headers.
+    headers: HeaderMap,
+    /// Response body.
+    body: Bytes,
+    /// Time after which entry is no longer valid.
+    expires: Instant,
+    /// Memory reserved for body, released on eviction.
+    _reservation: Reservation,
+}
+
+impl CacheEntry {
+    /// Build response from cached data.
+    fn response(&self) -> Response<Body> {
+        let mut resp = Response::new(Body::from(self.body.clone()));
+        *resp.status_mut() = self.status;
+        *resp.headers_mut() = self.headers.clone();
+        resp.headers_mut()
+            .insert(X_CACHE, HeaderValue::from_static("hit"));
+        resp
+    }
+}
+
+/// Shared state of response cache for a single handler.
+#[derive(Debug)]
+struct CacheState {
+    /// Handler name.
+    handler: &'static str,
+    /// Time to keep cached responses for.
+    ttl: Duration,
+    /// Maximum number of cached responses.
+    max_entries: usize,
+    /// Maximum body size of a cached response.
+    max_body_size: usize,
+    /// Request headers included in cache key.
+    key_headers: Vec<HeaderName>,
+    /// Cached responses.
+    entries: Mutex<HashMap<CacheKey, CacheEntry>>,
+}
+
+impl CacheState {
+    /// Build cache key for a request.
+    fn key<T>(&self, req: &Request<T>) -> CacheKey {
+        CacheKey {
+            method: req.method().clone(),
+            uri: req
+                .uri()
+                .path_and_query()
+                .map_or_else(|| req.uri().path().to_string(), ToString::to_string),
+            headers: self
+                .key_headers
+                .iter()
+                .map(|name| req.headers().get(name).cloned())
+                .collect(),
+        }
+    }
+
+    /// Get cached response, if present and not expired.
+    fn get(&self, key: &CacheKey) -> Option<Response<Body>> {
+        let mut entries = self.entries.lock();
+        let entry = entries.get(key)?;
+        if entry.expires > Instant::now() {
+            return Some(entry.response());
+        }
+        entries.remove(key);
+        None
+    }
+
+    /// Store response in cache, evicting expired or oldest entries if full.
+    fn insert(&self, key: CacheKey, entry: CacheEntry) {
+        let mut entries = self.entries.lock();
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            let now = Instant::now();
+            entries.retain(|_, entry| entry.expires > now);
+            if entries.len() >= self.max_entries {
+                if let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires)
+                    .map(|(key, _)| key.clone())
+                {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+        entries.insert(key, entry);
+    }
+}
+
+/// Check whether response may be stored in cache.
+fn is_cacheable(resp: &Response<Body>) -> bool {
+    resp.status().is_success()
+        && !resp
+            .headers()
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|val| val.to_str().ok())
+            .flat_map(|val| val.split(','))
+            .any(|directive| directive.trim().eq_ignore_ascii_case("no-store"))
+}
+
+/// Response cache [`tower`] layer.
+#[derive(Clone, Debug)]
+pub(crate) struct ResponseCacheLayer {
+    /// Shared state.
+    state: Arc<CacheState>,
+}
+
+impl<S> Layer<S> for ResponseCacheLayer {
+    type Service = ResponseCache<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ResponseCache {
+            inner,
+            state: self.state.clone(),
+        }
+    }
+}
+
+/// Response cache [`tower`] service.
+#[derive(Clone, Debug)]
+pub(crate) struct ResponseCache<S> {
+    /// Inner service.
+    inner: S,
+    /// Shared state.
+    state: Arc<CacheState>,
+}
+
+impl<S, T> Service<Request<T>> for ResponseCache<S>
+where
+    S: Service<Request<T>, Response = Response<Body>>,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<T>) -> Self::Future {
+        if !matches!(*req.method(), Method::GET | Method::HEAD) {
+            let future = self.inner.call(req);
+            return Box::pin(async move { future.await.map_err(Into::into) });
+        }
+        let key = self.state.key(&req);
+        if let Some(resp) = self.state.get(&key) {
+            return Box::pin(async move { Ok(resp) });
+        }
+        let future = self.inner.call(req);
+        let state = self.state.clone();
+        Box::pin(async move {
+            let mut resp = future.await.map_err(Into::into)?;
+            resp.headers_mut()
+                .insert(X_CACHE, HeaderValue::from_static("miss"));
+            if !is_cacheable(&resp) {
+                return Ok(resp);
+            }
+            // Never buffer bodies of unknown length.
+            let Some(size) = resp
+                .body()
+                .size_hint()
+                .upper()
+                .and_then(|size| usize::try_from(size).ok())
+                .filter(|size| *size <= state.max_body_size)
+            else {
+                debug!(
+                    handler = state.handler,
+                    "response body too large or streaming, not cached"
+                );
+                return Ok(resp);
+            };
+            let mut reservation = memory::global().reservation(LAYER_NAME);
+            if reservation.grow_to(size).is_err() {
+                debug!(
+                    handler = state.handler,
+                    "memory budget exhausted, not cached"
+                );
+                return Ok(resp);
+            }
+            let (parts, body) = resp.into_parts();
+            let body = axum::body::to_bytes(body, size).await?;
+            let mut headers = parts.headers.clone();
+            headers.remove(X_CACHE);
+            headers.insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+            state.insert(
+                key,
+                CacheEntry {
+                    status: parts.status,
+                    headers,
+                    body: body.clone(),
+                    expires: Instant::now() + state.ttl,
+                    _reservation: reservation,
+                },
+            );
+            Ok(Response::from_parts(parts, Body::from(body)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use futures::stream;
+    use tower::{service_fn, util::BoxCloneService, ServiceExt};
+
+    use super::*;
+
+    /// Service counting its calls, responding with call number.
+    fn service(
+        layer: &ResponseCacheLayer,
+        calls: &Arc<AtomicUsize>,
+        status: StatusCode,
+        cache_control: Option<&'static str>,
+    ) -> BoxCloneService<Request<Body>, Response<Body>, BoxError> {
+        let calls = calls.clone();
+        BoxCloneService::new(layer.layer(service_fn(move |_req: Request<Body>| {
+            let call = calls.fetch_add(1, Ordering::Relaxed) + 1;
+            async move {
+                let mut resp = Response::new(Body::from(call.to_string()));
+                *resp.status_mut() = status;
+                if let Some(value) = cache_control {
+                    resp.headers_mut()
+                        .insert(CACHE_CONTROL, HeaderValue::from_static(value));
+                }
+                Ok::<_, Infallible>(resp)
+            }
+        })))
+    }
+
+    fn request(method: Method, uri: &str, lang: Option<&'static str>) -> Request<Body> {
+        let mut req = Request::builder().method(method).uri(uri);
+        if let Some(lang) = lang {
+            req = req.header("accept-language", lang);
+        }
+        req.body(Body::empty()).unwrap()
+    }
+
+    async fn call<S>(svc: &S, req: Request<Body>) -> (String, String)
+    where
+        S: Service<Request<Body>, Response = Response<Body>, Error = BoxError> + Clone,
+    {
+        let resp = svc.clone().oneshot(req).await.unwrap();
+        let cache = resp
+            .headers()
+            .get(X_CACHE)
+            .map(|val| val.to_str().unwrap().to_string())
+            .unwrap_or_default();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (cache, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn hit(body: &str) -> (String, String) {
+        ("hit".into(), body.into())
+    }
+
+    fn miss(body: &str) -> (String, String) {
+        ("miss".into(), body.into())
+    }
+
+    /// Responses are cached per method, URL and key headers.
+    #[tokio::test]
+    async fn hit_and_miss() {
+        let layer = HandlerCacheConfig::default()
+            .with_key_header("Accept-Language")
+            .make_layer("test", &[]);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = service(&layer, &calls, StatusCode::OK, None);
+        assert_eq!(
+            call(&svc, request(Method::GET, "/a", None)).await,
+            miss("1")
+        );
+        assert_eq!(call(&svc, request(Method::GET, "/a", None)).await, hit("1"));
+        assert_eq!(
+            call(&svc, request(Method::GET, "/a?q=1", None)).await,
+            miss("2")
+        );
+        assert_eq!(
+            call(&svc, request(Method::GET, "/a", Some("en"))).await,
+            miss("3")
+        );
+        assert_eq!(
+            call(&svc, request(Method::GET, "/a", Some("en"))).await,
+            hit("3")
+        );
+        assert_eq!(
+            call(&svc, request(Method::HEAD, "/a", None)).await,
+            miss("4")
+        );
+        // Other methods are passed through.
+        assert_eq!(
+            call(&svc, request(Method::POST, "/a", None)).await,
+            (String::new(), "5".into())
+        );
+        assert_eq!(calls.load(Ordering::Relaxed), 5);
+    }
+
+    /// Entries are evicted after TTL expires.
+    #[tokio::test]
+    async fn ttl_expiry() {
+        let layer = HandlerCacheConfig::default()
+            .with_ttl(Duration::from_millis(50))
+            .make_layer("test", &[]);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = service(&layer, &calls, StatusCode::OK, None);
+        assert_eq!(call(&svc, request(Method::GET, "/", None)).await, miss("1"));
+        assert_eq!(call(&svc, request(Method::GET, "/", None)).await, hit("1"));
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert_eq!(call(&svc, request(Method::GET, "/", None)).await, miss("2"));
+        assert_eq!(layer.state.entries.lock().len(), 1);
+    }
+
+    /// Oldest entry is evicted when cache is full.
+    #[tokio::test]
+    async fn max_entries() {
+        let layer = HandlerCacheConfig::default()
+            .with_max_entries(NonZeroUsize::new(2).unwrap())
+            .make_layer("test", &[]);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = service(&layer, &calls, StatusCode::OK, None);
+        assert_eq!(
+            call(&svc, request(Method::GET, "/1", None)).await,
+            miss("1")
+        );
+        assert_eq!(
+            call(&svc, request(Method::GET, "/2", None)).await,
+            miss("2")
+        );
+        assert_eq!(
+            call(&svc, request(Method::GET, "/3", None)).await,
+            miss("3")
+        );
+        assert_eq!(layer.state.entries.lock().len(), 2);
+        assert_eq!(call(&svc, request(Method::GET, "/3", None)).await, hit("3"));
+        assert_eq!(call(&svc, request(Method::GET, "/2", None)).await, hit("2"));
+        assert_eq!(
+            call(&svc, request(Method::GET, "/1", None)).await,
+            miss("4")
+        );
+    }
+
+    /// Error responses and `no-store` responses are never cached.
+    #[tokio::test]
+    async fn not_cacheable() {
+        let layer = HandlerCacheConfig::default().make_layer("test", &[]);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = service(&layer, &calls, StatusCode::NOT_FOUND, None);
+        assert_eq!(call(&svc, request(Method::GET, "/", None)).await, miss("1"));
+        assert_eq!(call(&svc, request(Method::GET, "/", None)).await, miss("2"));
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let svc = service(&layer, &calls, StatusCode::OK, Some("private, no-store"));
+        assert_eq!(call(&svc, request(Method::GET, "/", None)).await, miss("1"));
+        assert_eq!(call(&svc, request(Method::GET, "/", None)).await, miss("2"));
+        assert!(layer.state.entries.lock().is_empty());
+    }
+
+    /// Streaming and oversized bodies bypass the cache.
+    #[tokio::test]
+    async fn bypass_large_bodies() {
+        let layer = HandlerCacheConfig::default()
+            .with_max_body_size(4)
+            .make_layer("test", &[]);
+        let svc = layer.layer(service_fn(|req: Request<Body>| async move {
+            let body = match req.uri().path() {
+                "/stream" => Body::from_stream(stream::iter([Ok::<_, Infallible>(
+                    Bytes::from_static(b"data"),
+                )])),
+                _ => Body::from("too large"),
+            };
+            Ok::<_, Infallible>(Response::new(body))
+        }));
+        for uri in ["/stream", "/large"] {
+            let (cache, _) = call(&svc, request(Method::GET, uri, None)).await;
+            assert_eq!(cache, "miss");
+            let (cache, _) = call(&svc, request(Method::GET, uri, None)).await;
+            assert_eq!(cache, "miss");
+        }
+        assert!(layer.state.entries.lock().is_empty());
+    }
+}
@@ -5,16 +5,17 @@ use std::{
     marker::PhantomData,
     pin::Pin,
     sync::Arc,
-    task::{Context, Poll},
+    task::{ready, Context, Poll},
     time::Duration,
 };
 
 use axum::{
     body::Body,
-    http::{header::HeaderValue, Request, Response, StatusCode},
+    http::{header::HeaderValue, HeaderMap, Request, Response, StatusCode},
     response::IntoResponse,
 };
 use iso8601_duration::Duration as IsoDuration;
+use opentelemetry::metrics::Counter;
 use pin_project::pin_project;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -22,10 +23,16 @@ use tokio::{
     task::futures::TaskLocalFuture,
     time::{sleep_until, Instant, Sleep},
 };
+use tokio_util::sync::CancellationToken;
 use tower::{BoxError, Layer, Service};
 use tracing::warn;
 
-use crate::layers::ext::Deadline;
+use crate::{
+    apidoc::ConfigAnnotation,
+    cancel::{self, CancelCause, RequestCancellation},
+    errors::{codes, ErrorCode},
+    layers::ext::Deadline,
+};
 
 tokio::task_local! {
     /// Deadline of currently executing request, if any.
@@ -48,14 +55,24 @@ impl TimeoutError {
             Self::TimedOut => StatusCode::GATEWAY_TIMEOUT,
         }
     }
+
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::TimedOut => codes::HANDLER_TIMEOUT,
+        }
+    }
 }
 
 impl IntoResponse for TimeoutError {
     fn into_response(self) -> Response<Body> {
-        problemdetails::new(self.http_status())
+        let code = self.code();
+        let problem = code
+            .problem(self.http_status())
             .with_type("tag:uxum.github.io,2024:timeout")
-            .with_title(self.to_string())
-            .into_response()
+            .with_title(self.to_string());
+        (code, problem).into_response()
     }
 }
 
@@ -94,6 +111,11 @@ pub struct HandlerTimeoutConfig {
         with = "humantime_serde"
     )]
     pub max_timeout: Option<Duration>,
+    /// Name of HTTP header with client-supplied request deadline, in milliseconds since UNIX epoch.
+    ///
+    /// Deadline from this header is only used if it is shorter than the configured timeout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline_header: Option<String>,
 }
 
 impl Default for HandlerTimeoutConfig {
@@ -103,6 +125,7 @@ impl Default for HandlerTimeoutConfig {
             default_timeout: None,
             min_timeout: None,
             max_timeout: None,
+            deadline_header: None,
         }
     }
 }
@@ -113,15 +136,80 @@ impl HandlerTimeoutConfig {
         *self == Self::default()
     }
 
+    /// Describe timeouts for use in OpenAPI specification.
+    ///
+    /// Returns [`None`] if no timeouts are configured.
+    #[must_use]
+    pub(crate) fn annotation(&self) -> Option<ConfigAnnotation> {
+        if self.default_timeout.is_none()
+            && self.min_timeout.is_none()
+            && self.max_timeout.is_none()
+        {
+            return None;
+        }
+        let mut value = serde_json::Map::new();
+        let mut summary = Vec::new();
+        if let Some(timeout) = self.default_timeout {
+            value.insert("default".into(), timeout.as_secs_f64().into());
+            summary.push(format!("Requests time out after {timeout:?}."));
+        }
+        if let Some(timeout) = self.min_timeout {
+            value.insert("min".into(), timeout.as_secs_f64().into());
+        }
+        if let Some(timeout) = self.max_timeout {
+            value.insert("max".into(), timeout.as_secs_f64().into());
+        }
+        if self.use_x_timeout {
+            value.insert("header".into(), X_TIMEOUT.into());
+            let range = match (self.min_timeout, self.max_timeout) {
+                (Some(min), Some(max)) => format!(", between {min:?} and {max:?}"),
+                (Some(min), None) => format!(", of at least {min:?}"),
+                (None, Some(max)) => format!(", of at most {max:?}"),
+                (None, None) => String::new(),
+            };
+            summary.push(format!(
+                "Clients may set timeout in `X-Timeout` header{range}."
+            ));
+        }
+        Some(ConfigAnnotation {
+            extension: "x-timeout",
+            value: value.into(),
+            summary: summary.join(" "),
+        })
+    }
+
     /// Create layer for use in tower services.
     pub fn make_layer<S>(&self) -> Option<TimeoutLayer<S>> {
-        if self.use_x_timeout || self.default_timeout.is_some() {
+        if self.use_x_timeout || self.default_timeout.is_some() || self.deadline_header.is_some() {
             Some(self.into())
         } else {
             None
         }
     }
 
+    /// Get deadline based on configuration and request headers.
+    pub fn request_deadline(&self, headers: &HeaderMap) -> Option<Instant> {
+        let deadline = self.get_deadline(headers.get(X_TIMEOUT));
+        let Some(incoming) = self
+            .deadline_header
+            .as_ref()
+            .and_then(|name| headers.get(name.as_str()))
+        else {
+            return deadline;
+        };
+        let incoming = match incoming.to_str().map(str::parse::<u64>) {
+            Ok(Ok(millis)) => Instant::from_std(*Deadline::from_unix_millis(millis)),
+            _ => {
+                warn!(value = ?incoming, "invalid request deadline header");
+                return deadline;
+            }
+        };
+        match deadline {
+            Some(deadline) if deadline <= incoming => Some(deadline),
+            _ => Some(incoming),
+        }
+    }
+
     /// Get deadline based on configuration and `X-Timeout` header.
     pub fn get_deadline(&self, timeout_header: Option<&HeaderValue>) -> Option<Instant> {
         if self.use_x_timeout && timeout_header.is_some() {
@@ -161,6 +249,10 @@ impl HandlerTimeoutConfig {
 pub struct TimeoutLayer<S> {
     /// Timeout configuration.
     config: HandlerTimeoutConfig,
+    /// Handler name, used in metrics.
+    handler: &'static str,
+    /// Counter of cancelled requests.
+    cancellations: Option<Counter<u64>>,
     /// Inner service type.
     _phantom_service: PhantomData<S>,
 }
@@ -170,11 +262,27 @@ impl<S> From<&HandlerTimeoutConfig> for TimeoutLayer<S> {
         // TODO: don't clone, but share, for runtime updates maybe?
         Self {
             config: value.clone(),
+            handler: "",
+            cancellations: None,
             _phantom_service: PhantomData,
         }
     }
 }
 
+impl<S> TimeoutLayer<S> {
+    /// Count cancelled requests for a handler.
+    #[must_use]
+    pub(crate) fn with_cancellation_metrics(
+        mut self,
+        handler: &'static str,
+        counter: Option<Counter<u64>>,
+    ) -> Self {
+        self.handler = handler;
+        self.cancellations = counter;
+        self
+    }
+}
+
 impl<S> Layer<S> for TimeoutLayer<S>
 where
     S: Service<Request<Body>>,
@@ -182,7 +290,10 @@ where
     type Service = TimeoutService<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        TimeoutService::new(inner, &self.config)
+        let mut svc = TimeoutService::new(inner, &self.config);
+        svc.handler = self.handler;
+        svc.cancellations.clone_from(&self.cancellations);
+        svc
     }
 }
 
@@ -191,6 +302,10 @@ where
 pub struct TimeoutService<S> {
     /// Timeout configuration.
     config: Arc<HandlerTimeoutConfig>,
+    /// Handler name, used in metrics.
+    handler: &'static str,
+    /// Counter of cancelled requests.
+    cancellations: Option<Counter<u64>>,
     /// Inner service.
     inner: S,
 }
@@ -214,13 +329,17 @@ where
     }
 
     fn call(&mut self, mut req: Request<Body>) -> Self::Future {
-        let deadline = self.config.get_deadline(req.headers().get(X_TIMEOUT));
+        let deadline = self.config.request_deadline(req.headers());
         let deadline_obj = deadline.map(Deadline::from);
         if let Some(d) = deadline_obj {
             req.extensions_mut().insert(d);
         }
-        let inner = CURRENT_DEADLINE.scope(deadline_obj, self.inner.call(req));
-        TimeoutFuture::new(inner, deadline)
+        let cancel = RequestCancellation::new(self.handler, self.cancellations.clone());
+        let token = cancel.token().clone();
+        req.extensions_mut().insert(token.clone());
+        let inner =
+            CURRENT_DEADLINE.scope(deadline_obj, cancel::scope(token, self.inner.call(req)));
+        TimeoutFuture::new(inner, deadline, cancel)
     }
 }
 
@@ -230,12 +349,20 @@ impl<S> TimeoutService<S> {
     pub fn new(inner: S, config: &HandlerTimeoutConfig) -> Self {
         Self {
             config: Arc::new(config.clone()),
+            handler: "",
+            cancellations: None,
             inner,
         }
     }
 }
 
+/// Inner future with request deadline and cancellation token in scope.
+type ScopedFuture<F> = TaskLocalFuture<Option<Deadline>, TaskLocalFuture<CancellationToken, F>>;
+
 /// Timeout [`tower`] service future.
+///
+/// Request cancellation token is cancelled when deadline expires, or when this future is dropped
+/// before completion.
 #[pin_project(project = Type)]
 #[derive(Debug)]
 pub enum TimeoutFuture<F> {
@@ -243,16 +370,20 @@ pub enum TimeoutFuture<F> {
     Bounded {
         /// Inner future.
         #[pin]
-        inner: TaskLocalFuture<Option<Deadline>, F>,
+        inner: ScopedFuture<F>,
         /// Sleep future.
         #[pin]
         sleep: Sleep,
+        /// Request cancellation state.
+        cancel: RequestCancellation,
     },
     /// Timeout doesn't exist.
     Unbounded {
         /// Inner future.
         #[pin]
-        inner: TaskLocalFuture<Option<Deadline>, F>,
+        inner: ScopedFuture<F>,
+        /// Request cancellation state.
+        cancel: RequestCancellation,
     },
 }
 
@@ -265,11 +396,18 @@ where
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         match self.project() {
-            Type::Bounded { inner, sleep } => {
+            Type::Bounded {
+                inner,
+                sleep,
+                cancel,
+            } => {
                 // Check if future is ready.
                 match inner.poll(cx) {
                     Poll::Pending => {}
-                    Poll::Ready(res) => return Poll::Ready(res.map_err(Into::into)),
+                    Poll::Ready(res) => {
+                        cancel.complete();
+                        return Poll::Ready(res.map_err(Into::into));
+                    }
                 }
 
                 // Inner future is not ready yet, so check the timeout.
@@ -277,11 +415,16 @@ where
                     Poll::Pending => Poll::Pending,
                     Poll::Ready(_) => {
                         warn!("request timed out");
+                        cancel.cancel(CancelCause::Timeout);
                         Poll::Ready(Err(TimeoutError::TimedOut.into()))
                     }
                 }
             }
-            Type::Unbounded { inner } => inner.poll(cx).map_err(Into::into),
+            Type::Unbounded { inner, cancel } => {
+                let res = ready!(inner.poll(cx));
+                cancel.complete();
+                Poll::Ready(res.map_err(Into::into))
+            }
         }
     }
 }
@@ -289,13 +432,85 @@ where
 impl<F> TimeoutFuture<F> {
     /// Create new timeout service future.
     #[must_use]
-    pub fn new(inner: TaskLocalFuture<Option<Deadline>, F>, deadline: Option<Instant>) -> Self {
+    pub(crate) fn new(
+        inner: ScopedFuture<F>,
+        deadline: Option<Instant>,
+        cancel: RequestCancellation,
+    ) -> Self {
         match deadline {
             Some(d) => Self::Bounded {
                 inner,
                 sleep: sleep_until(d),
+                cancel,
             },
-            None => Self::Unbounded { inner },
+            None => Self::Unbounded { inner, cancel },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> HandlerTimeoutConfig {
+        HandlerTimeoutConfig {
+            default_timeout: Some(Duration::from_secs(10)),
+            deadline_header: Some("x-request-deadline".into()),
+            ..Default::default()
+        }
+    }
+
+    fn headers(deadline: Deadline) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-request-deadline",
+            HeaderValue::from(deadline.to_unix_millis()),
+        );
+        headers
+    }
+
+    #[test]
+    fn shorter_incoming_deadline() {
+        let deadline = config()
+            .request_deadline(&headers(Duration::from_secs(2).into()))
+            .unwrap();
+        let left = deadline - Instant::now();
+        assert!(left > Duration::from_secs(1) && left <= Duration::from_secs(2));
+    }
+
+    #[test]
+    fn longer_incoming_deadline() {
+        let deadline = config()
+            .request_deadline(&headers(Duration::from_secs(60).into()))
+            .unwrap();
+        assert!(deadline - Instant::now() <= Duration::from_secs(10));
+    }
+
+    #[test]
+    fn expired_incoming_deadline() {
+        let deadline = config()
+            .request_deadline(&headers(Deadline::new()))
+            .unwrap();
+        assert!(deadline <= Instant::now());
+    }
+
+    #[test]
+    fn invalid_incoming_deadline() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-deadline", HeaderValue::from_static("soon"));
+        let deadline = config().request_deadline(&headers).unwrap();
+        assert!(deadline - Instant::now() > Duration::from_secs(9));
+    }
+
+    #[test]
+    fn deadline_without_timeout() {
+        let config = HandlerTimeoutConfig {
+            deadline_header: Some("x-request-deadline".into()),
+            ..Default::default()
+        };
+        assert!(config.request_deadline(&HeaderMap::new()).is_none());
+        assert!(config
+            .request_deadline(&headers(Duration::from_secs(2).into()))
+            .is_some());
+    }
+}
@@ -0,0 +1,311 @@
+//! Runtime toggling of handlers.
+//!
+//! Each handler service consults a shared flag on every request, which can be flipped using
+//! management endpoints. State is kept in memory only, and is reset on restart.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{Method, Request, Response, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use dashmap::DashMap;
+use futures::future::{ready, Either, Ready};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tower::{Layer, Service};
+use tracing::{info, warn};
+
+use crate::{
+    errors::{codes, ErrorCode},
+    layers::cors::CorsConfig,
+    retry::{RetryAdvice, RetryAdviceConfig, RetrySource},
+};
+
+/// Error type returned by disabled handlers and handler toggle endpoints.
+#[derive(Clone, Debug, Error, PartialEq)]
+#[non_exhaustive]
+pub enum HandlerToggleError {
+    /// Handler was disabled at runtime.
+    #[error("Handler {name} is disabled")]
+    Disabled {
+        /// Handler name.
+        name: String,
+        /// Advice used for `Retry-After` header.
+        advice: RetryAdvice,
+    },
+    /// No handler with provided name.
+    #[error("Unknown handler: {0}")]
+    UnknownHandler(String),
+}
+
+impl HandlerToggleError {
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Disabled { .. } => codes::HANDLER_DISABLED,
+            Self::UnknownHandler(_) => codes::HANDLER_UNKNOWN,
+        }
+    }
+}
+
+impl IntoResponse for HandlerToggleError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            Self::Disabled { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            Self::UnknownHandler(_) => StatusCode::NOT_FOUND,
+        };
+        let code = self.code();
+        let problem = code
+            .problem(status)
+            .with_type("tag:uxum.github.io,2024:handler-toggle")
+            .with_title(self.to_string());
+        match self {
+            Self::Disabled { advice, .. } => (code, advice.problem_response(problem)),
+            Self::UnknownHandler(_) => (code, problem.into_response()),
+        }
+        .into_response()
+    }
+}
+
+/// Registered handler, along with its runtime state flag.
+#[derive(Debug)]
+struct HandlerToggle {
+    /// URL path of handler.
+    path: &'static str,
+    /// HTTP methods of handler.
+    methods: Vec<Method>,
+    /// Effective CORS policy of handler.
+    cors: Option<CorsConfig>,
+    /// Whether handler is enabled.
+    enabled: Arc<AtomicBool>,
+}
+
+/// Handler state, as reported by management endpoints.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct HandlerStatus {
+    /// Handler name.
+    name: String,
+    /// URL path of handler.
+    path: String,
+    /// HTTP methods of handler.
+    methods: Vec<String>,
+    /// Effective CORS policy of handler, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cors: Option<CorsConfig>,
+    /// Whether handler is enabled.
+    enabled: bool,
+}
+
+/// Registry of runtime handler state flags.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HandlerToggles(Arc<DashMap<&'static str, HandlerToggle>>);
+
+impl HandlerToggles {
+    /// Register handler, and build a layer checking its state flag.
+    ///
+    /// Repeated registrations of the same handler share a single flag.
+    #[must_use]
+    pub(crate) fn register(
+        &self,
+        name: &'static str,
+        path: &'static str,
+        methods: Vec<Method>,
+        cors: Option<CorsConfig>,
+        retry: &RetryAdviceConfig,
+    ) -> HandlerToggleLayer {
+        let enabled = self
+            .0
+            .entry(name)
+            .or_insert_with(|| HandlerToggle {
+                path,
+                methods,
+                cors,
+                enabled: Arc::new(AtomicBool::new(true)),
+            })
+            .enabled
+            .clone();
+        HandlerToggleLayer {
+            name,
+            enabled,
+            retry: retry.clone(),
+        }
+    }
+
+    /// Get state of all registered handlers, sorted by name.
+    #[must_use]
+    pub(crate) fn list(&self) -> Vec<HandlerStatus> {
+        let mut handlers: Vec<_> = self
+            .0
+            .iter()
+            .map(|entry| status(entry.key(), entry.value()))
+            .collect();
+        handlers.sort_by(|a, b| a.name.cmp(&b.name));
+        handlers
+    }
+
+    /// Enable or disable handler.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if no handler with provided name is registered.
+    pub(crate) fn set(
+        &self,
+        name: &str,
+        enabled: bool,
+    ) -> Result<HandlerStatus, HandlerToggleError> {
+        let entry = self
+            .0
+            .get(name)
+            .ok_or_else(|| HandlerToggleError::UnknownHandler(name.into()))?;
+        if entry.enabled.swap(enabled, Ordering::Relaxed) != enabled {
+            match enabled {
+                true => info!(handler = name, "handler enabled"),
+                false => warn!(handler = name, "handler disabled"),
+            }
+        }
+        Ok(status(entry.key(), entry.value()))
+    }
+}
+
+/// Build handler status report.
+fn status(name: &str, toggle: &HandlerToggle) -> HandlerStatus {
+    HandlerStatus {
+        name: name.into(),
+        path: toggle.path.into(),
+        methods: toggle.methods.iter().map(ToString::to_string).collect(),
+        cors: toggle.cors.clone(),
+        enabled: toggle.enabled.load(Ordering::Relaxed),
+    }
+}
+
+/// Request body of handler toggle endpoint.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub(crate) struct SetEnabled {
+    /// New handler state.
+    enabled: bool,
+}
+
+/// List all registered handlers, along with their state.
+pub(crate) async fn list_handlers(
+    State(toggles): State<HandlerToggles>,
+) -> Json<Vec<HandlerStatus>> {
+    Json(toggles.list())
+}
+
+/// Enable or disable a single handler.
+pub(crate) async fn set_handler_enabled(
+    State(toggles): State<HandlerToggles>,
+    Path(name): Path<String>,
+    Json(body): Json<SetEnabled>,
+) -> Result<Json<HandlerStatus>, HandlerToggleError> {
+    toggles.set(&name, body.enabled).map(Json)
+}
+
+/// Handler toggle [`tower`] layer.
+#[derive(Clone, Debug)]
+pub(crate) struct HandlerToggleLayer {
+    /// Handler name.
+    name: &'static str,
+    /// Whether handler is enabled.
+    enabled: Arc<AtomicBool>,
+    /// Retry advice configuration.
+    retry: RetryAdviceConfig,
+}
+
+impl<S> Layer<S> for HandlerToggleLayer {
+    type Service = HandlerToggleService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HandlerToggleService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Handler toggle [`tower`] service.
+#[derive(Clone, Debug)]
+pub(crate) struct HandlerToggleService<S> {
+    /// Inner service.
+    inner: S,
+    /// Layer configuration.
+    layer: HandlerToggleLayer,
+}
+
+impl<S, T> Service<Request<T>> for HandlerToggleService<S>
+where
+    S: Service<Request<T>, Response = Response<Body>>,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = Either<S::Future, Ready<Result<Self::Response, Self::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<T>) -> Self::Future {
+        match self.layer.enabled.load(Ordering::Relaxed) {
+            true => Either::Left(self.inner.call(req)),
+            false => Either::Right(ready(Ok(HandlerToggleError::Disabled {
+                name: self.layer.name.into(),
+                advice: self.layer.retry.advise(RetrySource::Maintenance, None),
+            }
+            .into_response()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+
+    /// Disabled handlers respond with 503 until enabled again.
+    #[tokio::test]
+    async fn toggle() {
+        let toggles = HandlerToggles::default();
+        let layer = toggles.register(
+            "hello",
+            "/hello",
+            vec![Method::GET],
+            None,
+            &RetryAdviceConfig::default(),
+        );
+        let svc = layer.layer(service_fn(|_: Request<Body>| async {
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        }));
+        let call = || svc.clone().oneshot(Request::new(Body::empty()));
+
+        assert_eq!(call().await.unwrap().status(), StatusCode::OK);
+        let status = toggles.set("hello", false).unwrap();
+        assert!(!status.enabled);
+        assert_eq!(status.methods, ["GET"]);
+        let resp = call().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.extensions().get(), Some(&codes::HANDLER_DISABLED));
+        assert!(resp.headers().contains_key(axum::http::header::RETRY_AFTER));
+        assert!(!toggles.list()[0].enabled);
+
+        toggles.set("hello", true).unwrap();
+        assert_eq!(call().await.unwrap().status(), StatusCode::OK);
+        assert_eq!(
+            toggles.set("missing", false),
+            Err(HandlerToggleError::UnknownHandler("missing".into()))
+        );
+    }
+}
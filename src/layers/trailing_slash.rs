@@ -0,0 +1,318 @@
+//! [`tower`] layer to handle trailing slashes in request paths.
+//!
+//! [`axum`] treats `/users` and `/users/` as distinct routes. With a non-strict policy, the path
+//! without trailing slash is considered canonical, and requests to the other form are either
+//! redirected or transparently rewritten before routing.
+//!
+//! Paths under static directories are never touched, as directory listings rely on trailing
+//! slashes.
+
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::{
+    body::Body,
+    extract::OriginalUri,
+    http::{header, uri::PathAndQuery, HeaderValue, Request, Response, StatusCode, Uri},
+    Router,
+};
+use futures::future::{self, Either, Ready};
+use opentelemetry::{metrics::Counter, KeyValue};
+use serde::{Deserialize, Serialize};
+use tower::{Layer, Service};
+
+/// Policy for request paths with trailing slashes.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum TrailingSlash {
+    /// Treat paths with and without trailing slash as distinct routes.
+    #[default]
+    Strict,
+    /// Redirect to canonical path using `308 Permanent Redirect`.
+    ///
+    /// This status code preserves request method and body.
+    Redirect,
+    /// Route both forms to the same handler, by rewriting path before routing.
+    ///
+    /// Original URI is available to handlers via [`OriginalUri`] extractor.
+    Merge,
+}
+
+impl TrailingSlash {
+    /// Get canonical form of a path.
+    ///
+    /// Returns path unchanged for [`Self::Strict`].
+    #[must_use]
+    pub fn canonical(self, path: &str) -> &str {
+        match self {
+            Self::Strict => path,
+            Self::Redirect | Self::Merge => match path.trim_end_matches('/') {
+                "" => &path[..path.len().min(1)],
+                trimmed => trimmed,
+            },
+        }
+    }
+
+    /// Wrap router, applying policy before routing.
+    ///
+    /// `exempt` contains URL prefixes which are left untouched.
+    pub(crate) fn wrap_router(
+        self,
+        rtr: Router,
+        exempt: impl IntoIterator<Item = String>,
+        redirects: Option<Counter<u64>>,
+    ) -> Router {
+        if self == Self::Strict {
+            return rtr;
+        }
+        let layer = TrailingSlashLayer {
+            policy: self,
+            exempt: exempt
+                .into_iter()
+                .map(|prefix| prefix.trim_end_matches('/').to_string())
+                .collect(),
+            redirects,
+        };
+        Router::new().fallback_service(layer.layer(rtr))
+    }
+}
+
+/// Trailing slash [`tower`] layer.
+#[derive(Clone)]
+pub(crate) struct TrailingSlashLayer {
+    /// Policy to apply.
+    policy: TrailingSlash,
+    /// URL prefixes left untouched, without trailing slashes.
+    exempt: Arc<[String]>,
+    /// Redirect counter.
+    redirects: Option<Counter<u64>>,
+}
+
+impl TrailingSlashLayer {
+    /// Get canonical form of a path, if it differs from the original and is not exempt.
+    fn rewrite<'a>(&self, path: &'a str) -> Option<&'a str> {
+        let canonical = self.policy.canonical(path);
+        if canonical.len() == path.len() {
+            return None;
+        }
+        let exempt = self.exempt.iter().any(|prefix| {
+            path.strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        });
+        (!exempt).then_some(canonical)
+    }
+}
+
+impl<S> Layer<S> for TrailingSlashLayer {
+    type Service = TrailingSlashService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TrailingSlashService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Trailing slash [`tower`] service.
+#[derive(Clone)]
+pub(crate) struct TrailingSlashService<S> {
+    /// Inner service.
+    inner: S,
+    /// Layer configuration.
+    layer: TrailingSlashLayer,
+}
+
+impl<S, T> Service<Request<T>> for TrailingSlashService<S>
+where
+    S: Service<Request<T>, Response = Response<Body>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Either<S::Future, Ready<Result<Self::Response, Self::Error>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<T>) -> Self::Future {
+        let Some(canonical) = self.layer.rewrite(req.uri().path()) else {
+            return Either::Left(self.inner.call(req));
+        };
+        let target = match req.uri().query() {
+            Some(query) => format!("{canonical}?{query}"),
+            None => canonical.to_string(),
+        };
+        match self.layer.policy {
+            TrailingSlash::Redirect => {
+                if let Some(counter) = &self.layer.redirects {
+                    counter.add(
+                        1,
+                        &[KeyValue::new(
+                            "http.request.method",
+                            req.method().to_string(),
+                        )],
+                    );
+                }
+                let mut resp = Response::new(Body::empty());
+                *resp.status_mut() = StatusCode::PERMANENT_REDIRECT;
+                if let Ok(location) = HeaderValue::try_from(target) {
+                    resp.headers_mut().insert(header::LOCATION, location);
+                }
+                Either::Right(future::ok(resp))
+            }
+            TrailingSlash::Merge => {
+                if let Ok(path_and_query) = PathAndQuery::try_from(target) {
+                    let original = req.uri().clone();
+                    let mut parts = original.clone().into_parts();
+                    parts.path_and_query = Some(path_and_query);
+                    if let Ok(uri) = Uri::from_parts(parts) {
+                        req.extensions_mut()
+                            .get_or_insert_with(|| OriginalUri(original));
+                        *req.uri_mut() = uri;
+                    }
+                }
+                Either::Left(self.inner.call(req))
+            }
+            TrailingSlash::Strict => Either::Left(self.inner.call(req)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{extract::Path, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn router(policy: TrailingSlash) -> Router {
+        let rtr = Router::new()
+            .route(
+                "/users",
+                get(|OriginalUri(uri): OriginalUri| async move { uri.to_string() })
+                    .post(|body: String| async move { body }),
+            )
+            .route(
+                "/files/*path",
+                get(|Path(path): Path<String>| async move { path }),
+            )
+            .nest_service(
+                "/static",
+                tower::service_fn(|req: Request<Body>| async move {
+                    Ok::<_, std::convert::Infallible>(Response::new(Body::from(
+                        req.uri().path().to_string(),
+                    )))
+                }),
+            )
+            .fallback(|uri: Uri| async move { (StatusCode::NOT_FOUND, uri.path().to_string()) });
+        policy.wrap_router(rtr, ["/static/".to_string()], None)
+    }
+
+    async fn call(
+        rtr: &Router,
+        method: &str,
+        uri: &str,
+        body: &'static str,
+    ) -> (StatusCode, Option<String>, String) {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::from(body))
+            .unwrap();
+        let resp = rtr.clone().oneshot(req).await.unwrap();
+        let location = resp
+            .headers()
+            .get(header::LOCATION)
+            .map(|val| val.to_str().unwrap().to_string());
+        let status = resp.status();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, location, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    /// Canonical form has no trailing slashes, except for root path.
+    #[test]
+    fn canonical_paths() {
+        assert_eq!(TrailingSlash::Strict.canonical("/users/"), "/users/");
+        assert_eq!(TrailingSlash::Merge.canonical("/users/"), "/users");
+        assert_eq!(TrailingSlash::Merge.canonical("/users//"), "/users");
+        assert_eq!(TrailingSlash::Redirect.canonical("/users"), "/users");
+        assert_eq!(TrailingSlash::Redirect.canonical("/"), "/");
+        assert_eq!(TrailingSlash::Redirect.canonical("//"), "/");
+    }
+
+    /// Both forms are distinct routes with strict policy.
+    #[tokio::test]
+    async fn strict() {
+        let rtr = router(TrailingSlash::Strict);
+        let (status, _, body) = call(&rtr, "GET", "/users", "").await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "/users"));
+        let (status, ..) = call(&rtr, "GET", "/users/", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, ..) = call(&rtr, "POST", "/users/", "data").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (_, _, body) = call(&rtr, "GET", "/files/a/b/", "").await;
+        assert_eq!(body, "a/b/");
+    }
+
+    /// Non-canonical paths are redirected, preserving query string.
+    #[tokio::test]
+    async fn redirect() {
+        let rtr = router(TrailingSlash::Redirect);
+        for method in ["GET", "POST"] {
+            let (status, location, body) = call(&rtr, method, "/users/?page=2", "data").await;
+            assert_eq!(status, StatusCode::PERMANENT_REDIRECT);
+            assert_eq!(location.as_deref(), Some("/users?page=2"));
+            assert!(body.is_empty());
+        }
+        let (status, location, _) = call(&rtr, "GET", "/files/a/b/", "").await;
+        assert_eq!(status, StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(location.as_deref(), Some("/files/a/b"));
+        let (status, location, _) = call(&rtr, "GET", "/missing/", "").await;
+        assert_eq!(status, StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(location.as_deref(), Some("/missing"));
+
+        // Canonical paths, root path and exempt prefixes are served as is.
+        let (status, location, body) = call(&rtr, "POST", "/users", "data").await;
+        assert_eq!(
+            (status, location, body.as_str()),
+            (StatusCode::OK, None, "data")
+        );
+        let (status, ..) = call(&rtr, "GET", "/", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _, body) = call(&rtr, "GET", "/static/dir/", "").await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "/dir/"));
+    }
+
+    /// Both forms are routed to the same handler, original URI is preserved.
+    #[tokio::test]
+    async fn merge() {
+        let rtr = router(TrailingSlash::Merge);
+        let (status, location, body) = call(&rtr, "GET", "/users/?page=2", "").await;
+        assert_eq!(
+            (status, location, body.as_str()),
+            (StatusCode::OK, None, "/users/?page=2")
+        );
+        let (status, _, body) = call(&rtr, "POST", "/users/", "data").await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "data"));
+        let (status, _, body) = call(&rtr, "POST", "/users", "data").await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "data"));
+
+        // Wildcards capture canonical path.
+        let (_, _, body) = call(&rtr, "GET", "/files/a/b/", "").await;
+        assert_eq!(body, "a/b");
+
+        // Fallback gets canonical path too.
+        let (status, _, body) = call(&rtr, "GET", "/missing/", "").await;
+        assert_eq!((status, body.as_str()), (StatusCode::NOT_FOUND, "/missing"));
+
+        // Exempt prefixes are left untouched.
+        let (_, _, body) = call(&rtr, "GET", "/static/dir/", "").await;
+        assert_eq!(body, "/dir/");
+    }
+}
@@ -0,0 +1,339 @@
+//! [`tower`] layer to transform request bodies before they reach handler extractors.
+
+use std::{
+    fmt,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::{
+    body::{Body, Bytes},
+    http::{header::CONTENT_TYPE, HeaderValue, Request, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures::future::BoxFuture;
+use mime::Mime;
+use opentelemetry::{metrics::Counter, KeyValue};
+use thiserror::Error;
+use tower::{BoxError, Layer, Service};
+use tracing::debug;
+
+use crate::{
+    errors::{codes, ErrorCode},
+    memory::{self, buffer_body, BufferError},
+};
+
+/// Maximum body size, both before and after transformation.
+///
+/// Matches default body limit used by [`axum`] extractors.
+const BODY_LIMIT: usize = 2 * 1024 * 1024;
+
+/// Request body transformation error.
+#[derive(Clone, Debug, Error)]
+#[non_exhaustive]
+pub enum TransformError {
+    /// Transformer rejected request body.
+    #[error("Unable to transform request body: {0}")]
+    Rejected(String),
+    /// Request body could not be read.
+    #[error("Unable to read request body: {0}")]
+    Read(String),
+    /// Request body exceeds size limit.
+    #[error("Request body exceeds size limit of {0} bytes")]
+    TooLarge(usize),
+}
+
+impl TransformError {
+    /// Create new transformation error with a message.
+    #[must_use]
+    pub fn new(message: impl ToString) -> Self {
+        Self::Rejected(message.to_string())
+    }
+}
+
+impl TransformError {
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Rejected(_) => codes::TRANSFORM_REJECTED,
+            Self::Read(_) => codes::TRANSFORM_READ,
+            Self::TooLarge(_) => codes::TRANSFORM_TOO_LARGE,
+        }
+    }
+}
+
+impl IntoResponse for TransformError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        let code = self.code();
+        let problem = code
+            .problem(status)
+            .with_type("tag:uxum.github.io,2024:transform")
+            .with_title(self.to_string());
+        (code, problem).into_response()
+    }
+}
+
+/// Request extension added to transformed requests.
+#[derive(Clone, Debug)]
+pub struct Transformed {
+    /// Original content type of request.
+    original: Mime,
+}
+
+impl Transformed {
+    /// Original content type of request, before transformation.
+    #[must_use]
+    pub fn original_content_type(&self) -> &Mime {
+        &self.original
+    }
+}
+
+/// Request body transformation function.
+type TransformFn = dyn Fn(Bytes) -> Result<(Bytes, Mime), TransformError> + Send + Sync;
+
+/// Request body transformer, applied to requests with matching content type.
+#[derive(Clone)]
+pub(crate) struct RequestTransformer {
+    /// Content type pattern, like `application/xml` or `text/*`.
+    content_type: String,
+    /// Transformation function.
+    func: Arc<TransformFn>,
+}
+
+impl fmt::Debug for RequestTransformer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestTransformer")
+            .field("content_type", &self.content_type)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RequestTransformer {
+    /// Create new request transformer.
+    pub(crate) fn new<F>(content_type: impl ToString, func: F) -> Self
+    where
+        F: Fn(Bytes) -> Result<(Bytes, Mime), TransformError> + Send + Sync + 'static,
+    {
+        Self {
+            content_type: content_type.to_string().to_ascii_lowercase(),
+            func: Arc::new(func),
+        }
+    }
+
+    /// Content type pattern.
+    pub(crate) fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    /// Check whether request content type matches this transformer.
+    fn matches(&self, content_type: &Mime) -> bool {
+        let Some((ty, subty)) = self.content_type.split_once('/') else {
+            return false;
+        };
+        (ty == "*" || ty == content_type.type_().as_str())
+            && (subty == "*" || subty == content_type.subtype().as_str())
+    }
+}
+
+/// Request body transformation [`tower`] layer.
+#[derive(Clone, Debug)]
+pub(crate) struct TransformLayer {
+    /// Registered transformers, first match wins.
+    transformers: Arc<[RequestTransformer]>,
+    /// Handler name, used as metric label.
+    handler: &'static str,
+    /// Counter of transformed requests.
+    counter: Option<Counter<u64>>,
+}
+
+impl TransformLayer {
+    /// Create new request body transformation layer.
+    pub(crate) fn new(
+        transformers: Vec<RequestTransformer>,
+        handler: &'static str,
+        counter: Option<Counter<u64>>,
+    ) -> Self {
+        Self {
+            transformers: transformers.into(),
+            handler,
+            counter,
+        }
+    }
+}
+
+impl<S> Layer<S> for TransformLayer {
+    type Service = Transform<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Transform {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Request body transformation [`tower`] service.
+#[derive(Clone, Debug)]
+pub(crate) struct Transform<S> {
+    /// Inner service.
+    inner: S,
+    /// Layer configuration.
+    layer: TransformLayer,
+}
+
+impl<S> Service<Request<Body>> for Transform<S>
+where
+    S: Service<Request<Body>> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let content_type = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|val| val.to_str().ok())
+            .and_then(|val| val.parse::<Mime>().ok());
+        let transformer = content_type.as_ref().and_then(|ct| {
+            self.layer
+                .transformers
+                .iter()
+                .find(|tr| tr.matches(ct))
+                .cloned()
+        });
+        let (Some(content_type), Some(transformer)) = (content_type, transformer) else {
+            let future = self.inner.call(req);
+            return Box::pin(async move { future.await.map_err(Into::into) });
+        };
+        // Take the service that was driven to readiness, leave a fresh clone in its place.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let handler = self.layer.handler;
+        let counter = self.layer.counter.clone();
+        Box::pin(async move {
+            let (mut parts, body) = req.into_parts();
+            let (bytes, _reservation) =
+                buffer_body(memory::global(), "transform", body, BODY_LIMIT)
+                    .await
+                    .map_err(|err| -> BoxError {
+                        match err {
+                            BufferError::Memory(err) => err.into(),
+                            BufferError::TooLarge(limit) => TransformError::TooLarge(limit).into(),
+                            BufferError::Read(err) => TransformError::Read(err).into(),
+                        }
+                    })?;
+            let (bytes, new_type) = (transformer.func)(bytes)?;
+            if bytes.len() > BODY_LIMIT {
+                return Err(TransformError::TooLarge(BODY_LIMIT).into());
+            }
+            debug!(from = %content_type, to = %new_type, "transformed request body");
+            if let Ok(val) = HeaderValue::from_str(new_type.as_ref()) {
+                parts.headers.insert(CONTENT_TYPE, val);
+            }
+            parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+            parts.extensions.insert(Transformed {
+                original: content_type,
+            });
+            if let Some(counter) = counter {
+                counter.add(1, &[KeyValue::new("uxum.handler", handler)]);
+            }
+            inner
+                .call(Request::from_parts(parts, Body::from(bytes)))
+                .await
+                .map_err(Into::into)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{error_handling::HandleErrorLayer, routing, Extension, Json, Router};
+    use tower::{ServiceBuilder, ServiceExt};
+
+    use super::*;
+
+    fn legacy(body: Bytes) -> Result<(Bytes, Mime), TransformError> {
+        let text = std::str::from_utf8(&body).map_err(TransformError::new)?;
+        let value = text
+            .strip_prefix("<value>")
+            .and_then(|rest| rest.strip_suffix("</value>"))
+            .ok_or_else(|| TransformError::new("missing <value> element"))?;
+        let json = serde_json::json!({ "value": value }).to_string();
+        Ok((json.into(), mime::APPLICATION_JSON))
+    }
+
+    fn app() -> Router {
+        let layer = TransformLayer::new(
+            vec![RequestTransformer::new("application/xml", legacy)],
+            "test",
+            None,
+        );
+        Router::new().route(
+            "/",
+            routing::post(
+                |transformed: Option<Extension<Transformed>>,
+                 Json(body): Json<serde_json::Value>| async move {
+                    format!("{}:{}", body["value"], transformed.is_some())
+                },
+            )
+            .layer(
+                ServiceBuilder::new()
+                    .layer(HandleErrorLayer::new(crate::builder::app::error_handler))
+                    .layer(layer),
+            ),
+        )
+    }
+
+    async fn call(content_type: &str, body: &'static str) -> (StatusCode, String) {
+        let req = Request::post("/")
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap();
+        let resp = app().oneshot(req).await.unwrap();
+        let status = resp.status();
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(bytes.to_vec()).unwrap())
+    }
+
+    /// Matching content type is transformed.
+    #[tokio::test]
+    async fn matching() {
+        let (status, body) = call("application/xml; charset=utf-8", "<value>x</value>").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#""x":true"#);
+    }
+
+    /// Other content types pass through unchanged.
+    #[tokio::test]
+    async fn pass_through() {
+        let (status, body) = call("application/json", r#"{"value":"y"}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#""y":false"#);
+    }
+
+    /// Transformer errors map to 400 problem details.
+    #[tokio::test]
+    async fn error_mapping() {
+        let (status, body) = call("application/xml", "<other/>").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            body["title"],
+            "Unable to transform request body: missing <value> element"
+        );
+    }
+}
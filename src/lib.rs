@@ -24,59 +24,162 @@
 
 mod apidoc;
 mod auth;
+pub mod baggage;
+mod batch;
 mod builder;
+mod cancel;
+mod changelog;
 mod config;
+mod drain;
 mod errors;
 mod handle;
+mod hooks;
 mod http_client;
+mod i18n;
+mod kv;
 mod layers;
 mod logging;
+mod memory;
 mod metrics;
+mod normalize;
 mod notify;
+mod payload;
+mod persist;
 pub mod prelude;
 mod probes;
+#[cfg(feature = "profiling")]
+mod profiling;
+pub mod queue;
 pub mod reexport;
 mod response;
+mod retry;
 mod runtime;
+mod secret;
+mod service;
 mod signal;
+mod startup;
 pub mod state;
+mod static_dir;
+mod subsystem;
 mod telemetry;
 mod tracing;
 mod util;
+mod validate;
+mod warmup;
 mod watchdog;
 
+pub use async_trait::async_trait;
 pub use uxum_macros::handler;
 
+// Allow macro-generated code to refer to this crate by name in tests.
+#[cfg(test)]
+extern crate self as uxum;
+
+#[cfg(feature = "profiling")]
+pub use self::profiling::{ProfilingConfig, ProfilingError};
 pub use self::{
-    apidoc::{ApiDocBuilder, ApiDocError},
+    apidoc::{
+        add_response_header, merge_parameters, ApiDocBranding, ApiDocBuilder, ApiDocError,
+        ApiDocLogo, ApiDocUi, ApiVisibility, PublicApiDocConfig,
+    },
     auth::*,
+    baggage::BaggageConfig,
+    batch::{BatchConfig, BatchError, BatchItem, BatchItemResponse},
     builder::{
-        app::{AppBuilder, AppBuilderError, HandlerExt},
+        app::{AppBuilder, AppBuilderError, HandlerExt, HandlerFilter},
+        fallback::FallbackConfig,
+        layer::{HandlerLayer, HandlerLayerContext, HandlerLayerPosition, HandlerService},
+        listen::{ListenAddrs, MultiServer},
+        routing::{RouteShadowing, RoutingConfig},
         server::{
             Http1Config, Http2Config, Http2KeepaliveConfig, IpConfig, ServerBuilder,
-            ServerBuilderError, TcpConfig, TcpKeepaliveConfig,
+            ServerBuilderError, ShutdownConfig, TcpConfig, TcpKeepaliveConfig, TlsConfig,
+            TlsVersion,
         },
+        unix::{UnixServer, UnixSocketConfig},
     },
+    cancel::{cancel_aware, current_cancellation, with_cancellation, Cancelled},
+    changelog::{ApiChange, Changelog, ChangelogEntry, ChangelogVersion},
     config::*,
+    drain::{
+        drain_snapshot, drainable_sse, register_stream, DrainSnapshot, StreamGuard, StreamInfo,
+        StreamKind,
+    },
+    errors::{codes, ErrorCode, ErrorCodeInfo, ErrorsConfig, ERROR_CODES},
     handle::{Handle, HandleError},
+    hooks::{HookContext, ResponseHooks, ResponseHooksConfig},
     http_client::*,
+    i18n::{LocalizationConfig, LocalizationError, MessageKey, MessageMap},
+    kv::{KeyValueStore, KvError, MemoryStore},
     layers::{
+        body_limit::BodyLimitError,
         buffer::HandlerBufferConfig,
-        cors::CorsConfig,
+        cache::{CachePolicyConfig, CachePreset, CacheRule, StatusClass},
+        cb::{CircuitBreakerError, HandlerCircuitBreakerConfig},
+        client_ip::{ClientIp, ClientIpConfig},
+        compression::{CompressionAlgorithm, CompressionConfig},
+        contract::{ResponseValidationConfig, ResponseValidationMode},
+        cors::{CorsConfig, CorsError},
+        cpu_guard::CpuGuardConfig,
+        decompression::DecompressionError,
+        dependency::{record_dependency, track_dependency, DependencyTimingConfig},
+        deprecation::{DeprecationConfig, DeprecationError, DeprecationReportConfig, SunsetPolicy},
         ext::{Deadline, HandlerName},
-        rate::{HandlerRateLimitConfig, RateLimitError},
-        request_id::CURRENT_REQUEST_ID,
+        fair::{FairQueueError, HandlerFairQueueConfig},
+        identity::{instance_id, IdentityHeader, ResponseIdentityConfig},
+        ip_filter::{IpFilterConfig, IpFilterError, IpFilterRejection, IpNetwork, IpNetworkError},
+        rate::{
+            HandlerRateLimitConfig, RateLimitError, RateLimitHeaders, RateLimitKey, RateLimitState,
+            RoleRateLimitConfig,
+        },
+        recent_errors::{RecentErrorsConfig, RecentErrorsError},
+        request_id::{RequestIdConfig, RequestIdMode, CURRENT_REQUEST_ID},
+        response_cache::HandlerCacheConfig,
         timeout::{HandlerTimeoutConfig, TimeoutError, CURRENT_DEADLINE},
+        toggle::HandlerToggleError,
+        trailing_slash::TrailingSlash,
+        transform::{TransformError, Transformed},
+    },
+    logging::{
+        access::{access_log_to_ndjson, AccessLogConfig, AccessLogError, AccessLogFormat},
+        control::{LoggingControlConfig, LoggingControlError},
+        LoggingConfig,
+    },
+    memory::{MemoryConfig, MemoryError},
+    metrics::{HandlerMetricsConfig, MetricsBuilder, MetricsError, MetricsState},
+    normalize::{
+        annotate_normalize_schema, normalize_field_path, CaseFold, Normalize, NormalizeError,
+        NormalizeRules, NormalizeSpec, Normalized,
     },
-    logging::LoggingConfig,
-    metrics::{MetricsBuilder, MetricsError, MetricsState},
     notify::ServiceNotifier,
-    probes::{ProbeConfig, ProbeState},
-    response::{GetResponseSchemas, ResponseSchema},
+    payload::StaticPayload,
+    persist::{StatePersistenceConfig, StatePersistenceError},
+    probes::{Lifecycle, ProbeConfig, ProbeState, ReadinessCheckSpec},
+    response::{GetResponseSchemas, Json, ResponseSchema},
+    retry::{RetryAdvice, RetryAdviceConfig, RetryAfterFormat, RetrySource},
     runtime::RuntimeConfig,
+    secret::{SecretError, SecretValue},
+    service::{
+        print_default_config, print_default_config_if_requested, Profile, ServiceConfig,
+        ServiceConfigBuilder, ServiceConfigError, PRINT_DEFAULT_CONFIG_FLAG,
+    },
     signal::{SignalError, SignalStream},
+    startup::{StartupConfig, StartupError, StartupSpec},
+    static_dir::{StaticCacheRule, StaticDirConfig, StaticDirError, StaticOptions},
+    subsystem::{subsystem_statuses, InitFailurePolicy, SubsystemState, SubsystemStatus},
     telemetry::OpenTelemetryConfig,
-    tracing::TracingConfig,
+    tracing::{
+        propagation::TracePropagation,
+        sampling::{AdaptiveSamplingConfig, SamplingControlConfig, SamplingControlError},
+        TracingConfig,
+    },
     util::ResponseExtension,
+    validate::{
+        annotate_validate_schema, check_email, check_length, check_pattern, check_range,
+        validate_pointer, AsNumber, AsText, FieldError, HasLength, SkipValidation, Validate,
+        Validated, ValidationError, ValidationErrors, ValidationRejection, ValidationRule,
+        ValidationSpec, ValidationTarget,
+    },
+    warmup::{Warmup, WarmupConfig, WarmupRequest},
     watchdog::WatchdogConfig,
 };
@@ -0,0 +1,560 @@
+//! Dedicated access log, with compact binary format option.
+//!
+//! Binary access log files start with a header containing magic bytes, format version and a
+//! dictionary of handler names. Header is followed by length-prefixed records, each holding
+//! a single request. Use [`access_log_to_ndjson`] or `uxum-logcat` tool to convert binary files
+//! to newline-delimited JSON, identical to output of [`AccessLogFormat::Json`].
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+
+use crate::logging::LoggingBufferConfig;
+
+/// Magic bytes at the start of binary access log file.
+const MAGIC: &[u8; 8] = b"UXUMALOG";
+
+/// Current binary format version.
+const VERSION: u8 = 2;
+
+/// Upper limit on size of a single binary record or header.
+const MAX_RECORD_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Error type used in access log.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum AccessLogError {
+    /// Access log I/O error.
+    #[error("Access log I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// Input is not a binary access log file.
+    #[error("Not a binary access log file")]
+    BadMagic,
+    /// Unsupported binary format version.
+    #[error("Unsupported access log format version: {0}")]
+    UnsupportedVersion(u8),
+    /// Malformed binary record.
+    #[error("Malformed access log record: {0}")]
+    Decode(#[from] bincode::Error),
+    /// Error while writing JSON output.
+    #[error("Error while writing JSON output: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Access log output format.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum AccessLogFormat {
+    /// Newline-delimited JSON.
+    #[default]
+    Json,
+    /// Length-prefixed compact binary records.
+    Binary,
+}
+
+/// Access log configuration.
+///
+/// Each request served by the application is written as a single record, containing
+/// timestamp, handler name, response status, duration, response body size, hash of
+/// authenticated user ID, request ID and [error code](crate::ErrorCode).
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct AccessLogConfig {
+    /// Output format.
+    #[serde(default)]
+    pub format: AccessLogFormat,
+    /// Path to access log file.
+    pub path: PathBuf,
+    /// Rotate file after it reaches this size, in bytes.
+    #[serde(default = "AccessLogConfig::default_max_file_size")]
+    pub max_file_size: u64,
+    /// Number of rotated files to keep.
+    #[serde(default = "AccessLogConfig::default_max_files")]
+    pub max_files: usize,
+    /// Write buffer configuration.
+    #[serde(default)]
+    pub buffer: LoggingBufferConfig,
+}
+
+impl AccessLogConfig {
+    /// Default value for [`Self::max_file_size`].
+    #[must_use]
+    #[inline]
+    fn default_max_file_size() -> u64 {
+        256 * 1024 * 1024
+    }
+
+    /// Default value for [`Self::max_files`].
+    #[must_use]
+    #[inline]
+    fn default_max_files() -> usize {
+        10
+    }
+
+    /// Create new access log configuration.
+    #[must_use]
+    pub fn new(format: AccessLogFormat, path: impl Into<PathBuf>) -> Self {
+        Self {
+            format,
+            path: path.into(),
+            max_file_size: Self::default_max_file_size(),
+            max_files: Self::default_max_files(),
+            buffer: LoggingBufferConfig::default(),
+        }
+    }
+
+    /// Build access log sink.
+    ///
+    /// `handlers` are names of all handlers, used as a dictionary in binary format.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if access log file could not be opened.
+    pub(crate) fn build_sink<'a>(
+        &self,
+        handlers: impl IntoIterator<Item = &'a str>,
+    ) -> Result<AccessLogSink, AccessLogError> {
+        let (encoder, header) = match self.format {
+            AccessLogFormat::Json => (Encoder::Json, Vec::new()),
+            AccessLogFormat::Binary => {
+                let mut names = vec![String::new()];
+                names.extend(handlers.into_iter().map(ToOwned::to_owned));
+                names.sort_unstable();
+                names.dedup();
+                let header = encode_header(&names)?;
+                let ids = names
+                    .into_iter()
+                    .enumerate()
+                    .map(|(id, name)| (name, id as u32))
+                    .collect();
+                (Encoder::Binary(Arc::new(ids)), header)
+            }
+        };
+        let file = RotatingFile::open(&self.path, self.max_file_size, self.max_files, header)?;
+        let (writer, guard) = self.buffer.make_writer(file);
+        Ok(AccessLogSink {
+            encoder,
+            writer,
+            _guard: Arc::new(guard),
+        })
+    }
+}
+
+/// Single access log record.
+///
+/// Handler is stored as a dictionary index in binary format, and as a name in JSON.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct AccessRecord<H> {
+    /// Request start time, in microseconds since UNIX epoch.
+    pub(crate) timestamp_us: u64,
+    /// Handler name or index, empty if request was not routed to a handler.
+    pub(crate) handler: H,
+    /// HTTP response status code.
+    pub(crate) status: u16,
+    /// Time until response headers were ready, in microseconds.
+    pub(crate) duration_us: u64,
+    /// Response body size in bytes, if known in advance.
+    pub(crate) bytes: u64,
+    /// Hash of authenticated user ID.
+    pub(crate) user_hash: Option<u64>,
+    /// Request ID.
+    pub(crate) request_id: Option<String>,
+    /// Error code, for built-in error responses.
+    pub(crate) code: Option<String>,
+}
+
+/// Stable 64-bit FNV-1a hash, used to pseudonymize user IDs.
+#[must_use]
+pub(crate) fn user_hash(user: &str) -> u64 {
+    user.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Access record encoder.
+#[derive(Clone, Debug)]
+enum Encoder {
+    /// Newline-delimited JSON.
+    Json,
+    /// Binary records, with handler name dictionary.
+    Binary(Arc<HashMap<String, u32>>),
+}
+
+/// Access log writer, shared by all request handling tasks.
+#[derive(Clone, Debug)]
+pub(crate) struct AccessLogSink {
+    /// Record encoder.
+    encoder: Encoder,
+    /// Non-blocking buffered writer.
+    writer: NonBlocking,
+    /// Buffered writer guard, flushes remaining records when last sink is dropped.
+    _guard: Arc<WorkerGuard>,
+}
+
+impl AccessLogSink {
+    /// Encode and write a single record.
+    pub(crate) fn write(&self, record: &AccessRecord<&str>) {
+        if let Ok(buf) = self.encoder.encode(record) {
+            // Errors are handled by buffered writer, either by dropping or by blocking.
+            let _ = self.writer.clone().write_all(&buf);
+        }
+    }
+}
+
+impl Encoder {
+    /// Encode record as a single write buffer.
+    fn encode(&self, record: &AccessRecord<&str>) -> Result<Vec<u8>, AccessLogError> {
+        match self {
+            Self::Json => {
+                let mut buf = serde_json::to_vec(record)?;
+                buf.push(b'\n');
+                Ok(buf)
+            }
+            Self::Binary(ids) => {
+                let record = AccessRecord {
+                    timestamp_us: record.timestamp_us,
+                    handler: ids.get(record.handler).copied().unwrap_or_default(),
+                    status: record.status,
+                    duration_us: record.duration_us,
+                    bytes: record.bytes,
+                    user_hash: record.user_hash,
+                    request_id: record.request_id.clone(),
+                    code: record.code.clone(),
+                };
+                let size = bincode_options().serialized_size(&record)?;
+                let mut buf = Vec::with_capacity(size as usize + 4);
+                buf.extend_from_slice(&(size as u32).to_le_bytes());
+                bincode_options().serialize_into(&mut buf, &record)?;
+                Ok(buf)
+            }
+        }
+    }
+}
+
+/// Binary encoding options.
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::new().with_limit(u64::from(MAX_RECORD_SIZE))
+}
+
+/// Encode binary file header.
+fn encode_header(names: &[String]) -> Result<Vec<u8>, AccessLogError> {
+    let dict = bincode_options().serialize(names)?;
+    let mut buf = Vec::with_capacity(MAGIC.len() + 5 + dict.len());
+    buf.extend_from_slice(MAGIC);
+    buf.push(VERSION);
+    buf.extend_from_slice(&(dict.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&dict);
+    Ok(buf)
+}
+
+/// Read length-prefixed block, returning [`None`] on clean end of input.
+fn read_block(reader: &mut impl Read) -> Result<Option<Vec<u8>>, AccessLogError> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    let len = u32::from_le_bytes(len);
+    if len > MAX_RECORD_SIZE {
+        return Err(Box::new(bincode::ErrorKind::SizeLimit).into());
+    }
+    let mut buf = vec![0; len as usize];
+    reader.read_exact(&mut buf)?;
+    Ok(Some(buf))
+}
+
+/// Convert binary access log to newline-delimited JSON.
+///
+/// Returns number of converted records.
+///
+/// # Errors
+///
+/// Returns `Err` if input is not a valid binary access log, or on I/O error.
+pub fn access_log_to_ndjson(
+    mut reader: impl Read,
+    mut writer: impl Write,
+) -> Result<u64, AccessLogError> {
+    let mut magic = [0; MAGIC.len() + 1];
+    reader.read_exact(&mut magic)?;
+    if &magic[..MAGIC.len()] != MAGIC {
+        return Err(AccessLogError::BadMagic);
+    }
+    if magic[MAGIC.len()] != VERSION {
+        return Err(AccessLogError::UnsupportedVersion(magic[MAGIC.len()]));
+    }
+    let dict = read_block(&mut reader)?.ok_or(AccessLogError::BadMagic)?;
+    let names: Vec<String> = bincode_options().deserialize(&dict)?;
+    let mut count = 0;
+    while let Some(block) = read_block(&mut reader)? {
+        let record: AccessRecord<u32> = bincode_options().deserialize(&block)?;
+        let record = AccessRecord {
+            timestamp_us: record.timestamp_us,
+            handler: names
+                .get(record.handler as usize)
+                .map_or("", String::as_str),
+            status: record.status,
+            duration_us: record.duration_us,
+            bytes: record.bytes,
+            user_hash: record.user_hash,
+            request_id: record.request_id,
+            code: record.code,
+        };
+        serde_json::to_writer(&mut writer, &record)?;
+        writer.write_all(b"\n")?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+/// File writer with size-based rotation.
+///
+/// Each new file starts with a header. Rotated files get numeric suffixes, with `.1` being the
+/// most recent one.
+#[derive(Debug)]
+pub(crate) struct RotatingFile {
+    /// Path to current file.
+    path: PathBuf,
+    /// Current file.
+    file: fs::File,
+    /// Bytes written to current file.
+    size: u64,
+    /// Rotation threshold.
+    max_size: u64,
+    /// Number of rotated files to keep.
+    max_files: usize,
+    /// Header written at the start of each file.
+    header: Vec<u8>,
+}
+
+impl RotatingFile {
+    /// Open new file, rotating away existing one.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` on I/O error.
+    pub(crate) fn open(
+        path: &Path,
+        max_size: u64,
+        max_files: usize,
+        header: Vec<u8>,
+    ) -> io::Result<Self> {
+        if fs::metadata(path).is_ok_and(|meta| meta.len() > 0) {
+            Self::shift(path, max_files)?;
+        }
+        let mut file = fs::File::create(path)?;
+        file.write_all(&header)?;
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+            size: header.len() as u64,
+            max_size,
+            max_files,
+            header,
+        })
+    }
+
+    /// Path of rotated file with specified index.
+    fn rotated_path(path: &Path, idx: usize) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{idx}"));
+        name.into()
+    }
+
+    /// Shift rotated files by one, moving current file to `.1`.
+    fn shift(path: &Path, max_files: usize) -> io::Result<()> {
+        if max_files == 0 {
+            return fs::remove_file(path);
+        }
+        let _ = fs::remove_file(Self::rotated_path(path, max_files));
+        for idx in (1..max_files).rev() {
+            let from = Self::rotated_path(path, idx);
+            if from.exists() {
+                fs::rename(from, Self::rotated_path(path, idx + 1))?;
+            }
+        }
+        fs::rename(path, Self::rotated_path(path, 1))
+    }
+
+    /// Rotate current file.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        Self::shift(&self.path, self.max_files)?;
+        self.file = fs::File::create(&self.path)?;
+        self.file.write_all(&self.header)?;
+        self.size = self.header.len() as u64;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    /// Write a single record.
+    ///
+    /// Records are never split between files.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > self.header.len() as u64 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> Vec<AccessRecord<&'static str>> {
+        vec![
+            AccessRecord {
+                timestamp_us: 1_700_000_000_000_000,
+                handler: "get_user",
+                status: 200,
+                duration_us: 1532,
+                bytes: 312,
+                user_hash: Some(user_hash("alice")),
+                request_id: Some("0b6d1f0e-7c1a-4a53-9d0f-1e1f5c0f2a11".into()),
+                code: None,
+            },
+            AccessRecord {
+                timestamp_us: 1_700_000_000_000_100,
+                handler: "",
+                status: 404,
+                duration_us: 12,
+                bytes: 0,
+                user_hash: None,
+                request_id: None,
+                code: None,
+            },
+            AccessRecord {
+                timestamp_us: 1_700_000_000_000_200,
+                handler: "unknown_handler",
+                status: 500,
+                duration_us: 99,
+                bytes: 17,
+                user_hash: None,
+                request_id: None,
+                code: Some("panic".into()),
+            },
+        ]
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "uxum-access-{}-{name}-{}",
+            std::process::id(),
+            uuid::Uuid::new_v4()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("access.log")
+    }
+
+    fn encode_all(format: AccessLogFormat, path: &Path) {
+        let sink = AccessLogConfig::new(format, path)
+            .build_sink(["get_user", "list_users"])
+            .unwrap();
+        for record in records() {
+            sink.write(&record);
+        }
+    }
+
+    /// Binary log converts to the same NDJSON as written by JSON sink.
+    #[test]
+    fn round_trip() {
+        let json_path = temp_path("json");
+        let bin_path = temp_path("bin");
+        encode_all(AccessLogFormat::Json, &json_path);
+        encode_all(AccessLogFormat::Binary, &bin_path);
+        let json = fs::read_to_string(&json_path).unwrap();
+        let binary = fs::read(&bin_path).unwrap();
+        assert!(binary.len() < json.len());
+        let mut converted = Vec::new();
+        let count = access_log_to_ndjson(binary.as_slice(), &mut converted).unwrap();
+        assert_eq!(count, 3);
+        let converted = String::from_utf8(converted).unwrap();
+        // Handlers absent from dictionary are written as empty strings.
+        assert_eq!(converted, json.replace("unknown_handler", ""));
+        let first: serde_json::Value =
+            serde_json::from_str(converted.lines().next().unwrap()).unwrap();
+        assert_eq!(first["handler"], "get_user");
+        assert_eq!(first["duration_us"], 1532);
+        let last: serde_json::Value =
+            serde_json::from_str(converted.lines().last().unwrap()).unwrap();
+        assert_eq!(last["code"], "panic");
+    }
+
+    /// Invalid input is rejected.
+    #[test]
+    fn bad_input() {
+        let err = access_log_to_ndjson(&b"{\"status\":200}\n"[..], io::sink()).unwrap_err();
+        assert!(matches!(err, AccessLogError::BadMagic));
+        let mut truncated = encode_header(&["a".into()]).unwrap();
+        truncated.extend_from_slice(&100_u32.to_le_bytes());
+        truncated.extend_from_slice(&[1, 2, 3]);
+        let err = access_log_to_ndjson(truncated.as_slice(), io::sink()).unwrap_err();
+        assert!(matches!(err, AccessLogError::Io(_)));
+    }
+
+    /// Files are rotated by size, each starting with a header.
+    #[test]
+    fn rotation() {
+        let path = temp_path("rotate");
+        let header = encode_header(&["a".into()]).unwrap();
+        let mut file =
+            RotatingFile::open(&path, header.len() as u64 + 10, 2, header.clone()).unwrap();
+        for _ in 0..4 {
+            file.write_all(&[0; 8]).unwrap();
+        }
+        file.flush().unwrap();
+        for idx in [0, 1, 2] {
+            let path = match idx {
+                0 => path.clone(),
+                idx => RotatingFile::rotated_path(&path, idx),
+            };
+            let data = fs::read(path).unwrap();
+            assert_eq!(data.len(), header.len() + 8);
+            assert!(data.starts_with(&header));
+        }
+        assert!(!RotatingFile::rotated_path(&path, 3).exists());
+    }
+
+    /// Compare encoding cost and size of binary and JSON formats.
+    ///
+    /// Run with `cargo test --release -- --ignored bench_encoding --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_encoding() {
+        const ITERATIONS: u32 = 1_000_000;
+        let ids = Arc::new(HashMap::from([("get_user".to_owned(), 1)]));
+        let record = &records()[0];
+        for (label, encoder) in [("json", Encoder::Json), ("binary", Encoder::Binary(ids))] {
+            let start = std::time::Instant::now();
+            let mut bytes = 0;
+            for _ in 0..ITERATIONS {
+                bytes += std::hint::black_box(encoder.encode(record).unwrap()).len();
+            }
+            let elapsed = start.elapsed();
+            println!(
+                "{label}: {:?}/record, {} bytes/record",
+                elapsed / ITERATIONS,
+                bytes / ITERATIONS as usize,
+            );
+        }
+    }
+}
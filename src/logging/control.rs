@@ -0,0 +1,666 @@
+//! Runtime control of logging subscribers.
+
+use std::{
+    borrow::Borrow,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    error_handling::HandleErrorLayer,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing, Json, Router,
+};
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tower::ServiceBuilder;
+use tracing::{debug_span, info, span, Dispatch, Event, Metadata, Subscriber};
+use tracing_subscriber::{
+    layer::{Context, Layer},
+    registry::LookupSpan,
+};
+
+use crate::{
+    auth::{AuthExtractor, AuthLayer, AuthProvider},
+    builder::app::error_handler,
+    errors::{codes, ErrorCode},
+    logging::LoggingFormatKind,
+};
+
+/// Controls of subscribers in globally installed registry.
+static CONTROLS: Lazy<RwLock<Vec<Arc<SubscriberControl>>>> = Lazy::new(Default::default);
+
+/// Replace globally registered subscriber controls.
+pub(crate) fn register(controls: Vec<Arc<SubscriberControl>>) {
+    *CONTROLS.write() = controls;
+}
+
+/// Error type returned by logging control endpoint.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LoggingControlError {
+    /// Subscriber must be addressed either by index or by name.
+    #[error("Subscriber must be addressed either by index or by name")]
+    InvalidSelector,
+    /// No subscriber matches provided index or name.
+    #[error("Unknown logging subscriber: {0}")]
+    UnknownSubscriber(String),
+    /// Subscriber format cannot be changed at runtime.
+    #[error("Logging subscriber is immutable: {0}")]
+    Immutable(String),
+}
+
+impl LoggingControlError {
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::InvalidSelector => codes::LOGGING_INVALID_SELECTOR,
+            Self::UnknownSubscriber(_) => codes::LOGGING_UNKNOWN_SUBSCRIBER,
+            Self::Immutable(_) => codes::LOGGING_IMMUTABLE,
+        }
+    }
+}
+
+impl IntoResponse for LoggingControlError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::InvalidSelector => StatusCode::BAD_REQUEST,
+            Self::UnknownSubscriber(_) => StatusCode::NOT_FOUND,
+            Self::Immutable(_) => StatusCode::FORBIDDEN,
+        };
+        let code = self.code();
+        let problem = code
+            .problem(status)
+            .with_type("tag:uxum.github.io,2024:logging")
+            .with_title(self.to_string());
+        (code, problem).into_response()
+    }
+}
+
+/// Runtime logging control endpoint configuration.
+///
+/// `GET <path>` returns current state of all subscribers. `PUT <path>` changes output format of
+/// one or more subscribers, until TTL expires:
+///
+/// ```json
+/// {"subscribers": [{"name": "console", "format": "pretty"}], "ttl": "10m"}
+/// ```
+///
+/// Subscribers are addressed either by `index` in [`super::LoggingConfig::subscribers`], or by
+/// `name`. Omitting `format` reverts subscriber to its configured format. Both methods require
+/// `logging` permission.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct LoggingControlConfig {
+    /// URL path of logging control endpoint.
+    #[serde(default = "LoggingControlConfig::default_path")]
+    path: String,
+    /// Time after which changed format is reverted, if not specified in request.
+    #[serde(
+        default = "LoggingControlConfig::default_ttl",
+        with = "humantime_serde"
+    )]
+    default_ttl: Duration,
+    /// Maximum time after which changed format is reverted.
+    #[serde(
+        default = "LoggingControlConfig::default_max_ttl",
+        with = "humantime_serde"
+    )]
+    max_ttl: Duration,
+}
+
+impl Default for LoggingControlConfig {
+    fn default() -> Self {
+        Self {
+            path: Self::default_path(),
+            default_ttl: Self::default_ttl(),
+            max_ttl: Self::default_max_ttl(),
+        }
+    }
+}
+
+impl LoggingControlConfig {
+    /// Default value for [`Self::path`].
+    #[must_use]
+    #[inline]
+    fn default_path() -> String {
+        "/logging".into()
+    }
+
+    /// Default value for [`Self::default_ttl`].
+    #[must_use]
+    #[inline]
+    fn default_ttl() -> Duration {
+        Duration::from_secs(900)
+    }
+
+    /// Default value for [`Self::max_ttl`].
+    #[must_use]
+    #[inline]
+    fn default_max_ttl() -> Duration {
+        Duration::from_secs(3600)
+    }
+
+    /// Set URL path of logging control endpoint.
+    #[must_use]
+    pub fn with_path(mut self, path: impl ToString) -> Self {
+        self.path = path.to_string();
+        self
+    }
+
+    /// Set time after which changed format is reverted, if not specified in request.
+    #[must_use]
+    pub fn with_default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = ttl;
+        self
+    }
+
+    /// Set maximum time after which changed format is reverted.
+    #[must_use]
+    pub fn with_max_ttl(mut self, ttl: Duration) -> Self {
+        self.max_ttl = ttl;
+        self
+    }
+
+    /// Build Axum router containing logging control endpoint.
+    pub(crate) fn build_router<AuthProv, AuthExt>(
+        &self,
+        auth_provider: AuthProv,
+        auth_extractor: AuthExt,
+    ) -> Router
+    where
+        AuthProv: AuthProvider + Sync + 'static,
+        AuthExt: AuthExtractor + Sync + 'static,
+        AuthExt::User: Borrow<AuthProv::User>,
+        AuthExt::AuthTokens: Borrow<AuthProv::AuthTokens>,
+    {
+        let _span = debug_span!("build_logging_control").entered();
+        Router::new()
+            .route(
+                &self.path,
+                routing::get(get_subscribers).put(put_subscribers),
+            )
+            .layer(
+                ServiceBuilder::new()
+                    .layer(HandleErrorLayer::new(error_handler))
+                    .layer(AuthLayer::new(&["logging"], auth_provider, auth_extractor)),
+            )
+            .with_state(self.clone())
+    }
+}
+
+/// Subscriber address and requested format.
+#[derive(Clone, Debug, Deserialize)]
+struct SubscriberChange {
+    /// Subscriber index.
+    #[serde(default)]
+    index: Option<usize>,
+    /// Subscriber name.
+    #[serde(default)]
+    name: Option<String>,
+    /// New format, or [`None`] to revert to configured format.
+    #[serde(default)]
+    format: Option<LoggingFormatKind>,
+}
+
+/// Body of format change request.
+#[derive(Clone, Debug, Deserialize)]
+struct ChangeRequest {
+    /// Changes to apply.
+    subscribers: Vec<SubscriberChange>,
+    /// Time after which changed formats are reverted.
+    #[serde(default, with = "humantime_serde")]
+    ttl: Option<Duration>,
+}
+
+/// Current state of a subscriber.
+#[derive(Clone, Debug, Serialize)]
+struct SubscriberStatus {
+    /// Subscriber index.
+    index: usize,
+    /// Subscriber name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    /// Format changes are forbidden.
+    immutable: bool,
+    /// Current format.
+    format: LoggingFormatKind,
+    /// Configured format.
+    default_format: LoggingFormatKind,
+    /// Time when current format will be reverted.
+    #[serde(with = "humantime_serde", skip_serializing_if = "Option::is_none")]
+    expires: Option<SystemTime>,
+}
+
+/// Get current state of all subscribers.
+async fn get_subscribers() -> Json<Vec<SubscriberStatus>> {
+    Json(CONTROLS.read().iter().map(|ctl| ctl.status()).collect())
+}
+
+/// Change output format of subscribers.
+async fn put_subscribers(
+    State(config): State<LoggingControlConfig>,
+    Json(req): Json<ChangeRequest>,
+) -> Result<Json<Vec<SubscriberStatus>>, LoggingControlError> {
+    let ttl = req.ttl.unwrap_or(config.default_ttl).min(config.max_ttl);
+    let controls = CONTROLS.read().clone();
+    apply(&controls, &req.subscribers, ttl)?;
+    Ok(Json(controls.iter().map(|ctl| ctl.status()).collect()))
+}
+
+/// Validate all changes, then apply them.
+fn apply(
+    controls: &[Arc<SubscriberControl>],
+    changes: &[SubscriberChange],
+    ttl: Duration,
+) -> Result<(), LoggingControlError> {
+    let mut targets = Vec::with_capacity(changes.len());
+    for change in changes {
+        let (control, label) = match (change.index, &change.name) {
+            (Some(index), None) => (
+                controls.iter().find(|ctl| ctl.index == index),
+                index.to_string(),
+            ),
+            (None, Some(name)) => (
+                controls.iter().find(|ctl| ctl.name.as_ref() == Some(name)),
+                name.clone(),
+            ),
+            _ => return Err(LoggingControlError::InvalidSelector),
+        };
+        let control = control.ok_or(LoggingControlError::UnknownSubscriber(label.clone()))?;
+        if control.immutable {
+            return Err(LoggingControlError::Immutable(label));
+        }
+        targets.push((control, change.format.unwrap_or(control.default)));
+    }
+    for (control, format) in targets {
+        control.set_format(format, ttl);
+    }
+    Ok(())
+}
+
+/// Format switch state, guarded by a lock.
+#[derive(Debug, Default)]
+struct SwitchState {
+    /// Incremented on every switch, used to cancel outdated reverts.
+    generation: u64,
+    /// Time when current format will be reverted.
+    expires: Option<SystemTime>,
+}
+
+/// Runtime control of a single logging subscriber.
+#[derive(Debug)]
+pub(crate) struct SubscriberControl {
+    /// Subscriber index in configuration.
+    index: usize,
+    /// Subscriber name.
+    name: Option<String>,
+    /// Format changes are forbidden.
+    immutable: bool,
+    /// Configured format.
+    default: LoggingFormatKind,
+    /// Current format, shared with [`FormatSwitch`].
+    current: Arc<AtomicUsize>,
+    /// Switch state.
+    state: Mutex<SwitchState>,
+}
+
+impl SubscriberControl {
+    /// Create new subscriber control.
+    pub(crate) fn new(
+        index: usize,
+        name: Option<String>,
+        immutable: bool,
+        default: LoggingFormatKind,
+        current: Arc<AtomicUsize>,
+    ) -> Self {
+        Self {
+            index,
+            name,
+            immutable,
+            default,
+            current,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Current format.
+    fn format(&self) -> LoggingFormatKind {
+        let current = self.current.load(Ordering::Acquire);
+        LoggingFormatKind::ALL
+            .into_iter()
+            .find(|kind| *kind as usize == current)
+            .unwrap_or(self.default)
+    }
+
+    /// Current state of subscriber.
+    fn status(&self) -> SubscriberStatus {
+        SubscriberStatus {
+            index: self.index,
+            name: self.name.clone(),
+            immutable: self.immutable,
+            format: self.format(),
+            default_format: self.default,
+            expires: self.state.lock().expires,
+        }
+    }
+
+    /// Switch output format, reverting to configured format after `ttl`.
+    fn set_format(self: &Arc<Self>, format: LoggingFormatKind, ttl: Duration) {
+        let mut state = self.state.lock();
+        state.generation += 1;
+        self.current.store(format as usize, Ordering::Release);
+        if format == self.default {
+            state.expires = None;
+            info!(subscriber = self.index, ?format, "logging format reverted");
+            return;
+        }
+        state.expires = Some(SystemTime::now() + ttl);
+        info!(
+            subscriber = self.index,
+            ?format,
+            ?ttl,
+            "logging format changed"
+        );
+        let generation = state.generation;
+        let control = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(ttl).await;
+            control.revert(generation);
+        });
+    }
+
+    /// Revert to configured format, unless format was switched again since `generation`.
+    fn revert(&self, generation: u64) {
+        let mut state = self.state.lock();
+        if state.generation == generation {
+            self.current.store(self.default as usize, Ordering::Release);
+            state.expires = None;
+            info!(subscriber = self.index, format = ?self.default, "logging format reverted after TTL");
+        }
+    }
+}
+
+/// Layer dispatching each event to exactly one of pre-built formatting layers.
+///
+/// Span lifecycle notifications are passed to all layers, so that span fields are available
+/// whichever format is selected. Events are never split between formats, as format is selected
+/// once per event, and each formatting layer writes complete lines.
+pub(crate) struct FormatSwitch<S> {
+    /// Formatting layers.
+    layers: Vec<(LoggingFormatKind, Box<dyn Layer<S> + Send + Sync>)>,
+    /// Currently selected format.
+    current: Arc<AtomicUsize>,
+    /// Subscriber type marker.
+    _subscriber: PhantomData<fn(S)>,
+}
+
+impl<S> FormatSwitch<S> {
+    /// Create new format switch.
+    pub(crate) fn new(
+        layers: Vec<(LoggingFormatKind, Box<dyn Layer<S> + Send + Sync>)>,
+        current: Arc<AtomicUsize>,
+    ) -> Self {
+        Self {
+            layers,
+            current,
+            _subscriber: PhantomData,
+        }
+    }
+}
+
+impl<S> Layer<S> for FormatSwitch<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_register_dispatch(&self, subscriber: &Dispatch) {
+        for (_, layer) in &self.layers {
+            layer.on_register_dispatch(subscriber);
+        }
+    }
+
+    fn on_layer(&mut self, subscriber: &mut S) {
+        for (_, layer) in &mut self.layers {
+            layer.on_layer(subscriber);
+        }
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        for (_, layer) in &self.layers {
+            layer.on_new_span(attrs, id, ctx.clone());
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        for (_, layer) in &self.layers {
+            layer.on_record(id, values, ctx.clone());
+        }
+    }
+
+    fn on_follows_from(&self, id: &span::Id, follows: &span::Id, ctx: Context<'_, S>) {
+        for (_, layer) in &self.layers {
+            layer.on_follows_from(id, follows, ctx.clone());
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let current = self.current.load(Ordering::Acquire);
+        let selected = self
+            .layers
+            .iter()
+            .find(|(kind, _)| *kind as usize == current)
+            .or_else(|| self.layers.first());
+        if let Some((_, layer)) = selected {
+            layer.on_event(event, ctx);
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        for (_, layer) in &self.layers {
+            layer.on_enter(id, ctx.clone());
+        }
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        for (_, layer) in &self.layers {
+            layer.on_exit(id, ctx.clone());
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        for (_, layer) in &self.layers {
+            layer.on_close(id.clone(), ctx.clone());
+        }
+    }
+
+    fn on_id_change(&self, old: &span::Id, new: &span::Id, ctx: Context<'_, S>) {
+        for (_, layer) in &self.layers {
+            layer.on_id_change(old, new, ctx.clone());
+        }
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, S>) -> bool {
+        self.layers
+            .iter()
+            .any(|(_, layer)| layer.enabled(metadata, ctx.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, thread};
+
+    use tracing_subscriber::{fmt::MakeWriter, layer::SubscriberExt, Registry};
+
+    use super::*;
+    use crate::logging::{LoggingFormat, LoggingLevel, LoggingSubscriberConfig};
+
+    /// In-memory log destination.
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Capture {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    impl Capture {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.lock().clone())
+                .unwrap()
+                .lines()
+                .map(ToOwned::to_owned)
+                .collect()
+        }
+    }
+
+    fn subscriber(immutable: bool) -> (Dispatch, Arc<SubscriberControl>, Capture) {
+        let capture = Capture::default();
+        let config = LoggingSubscriberConfig {
+            name: Some("console".into()),
+            immutable,
+            format: LoggingFormat::Compact,
+            level: LoggingLevel::Info,
+            ..Default::default()
+        };
+        let (layer, control) = config.make_switchable_layer(0, capture.clone());
+        (
+            Dispatch::new(Registry::default().with(layer)),
+            control,
+            capture,
+        )
+    }
+
+    fn is_json(line: &str) -> bool {
+        serde_json::from_str::<serde_json::Value>(line).is_ok()
+    }
+
+    /// Format switches concurrently with logging never produce mixed or partial lines.
+    #[tokio::test]
+    async fn transitions_mid_stream() {
+        let (dispatch, control, capture) = subscriber(false);
+        let workers: Vec<_> = (0..4)
+            .map(|worker| {
+                let dispatch = dispatch.clone();
+                thread::spawn(move || {
+                    tracing::dispatcher::with_default(&dispatch, || {
+                        for seq in 0..500 {
+                            tracing::info!(worker, seq, "tick");
+                        }
+                    });
+                })
+            })
+            .collect();
+        for i in 0..50 {
+            let format = match i % 2 {
+                0 => LoggingFormatKind::Json,
+                _ => LoggingFormatKind::Compact,
+            };
+            control.set_format(format, Duration::from_secs(60));
+            thread::yield_now();
+        }
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        let lines = capture.lines();
+        assert_eq!(lines.len(), 2000);
+        for line in &lines {
+            if line.starts_with('{') {
+                let value: serde_json::Value = serde_json::from_str(line).unwrap();
+                assert_eq!(value["fields"]["message"], "tick");
+            } else {
+                assert!(line.contains("tick"), "partial line: {line}");
+                assert!(!line.contains('{'), "mixed line: {line}");
+            }
+        }
+    }
+
+    /// Changed format is reverted after TTL, unless switched again.
+    #[tokio::test]
+    async fn ttl_reversion() {
+        let (dispatch, control, capture) = subscriber(false);
+        let log = || tracing::dispatcher::with_default(&dispatch, || tracing::info!("tick"));
+        control.set_format(LoggingFormatKind::Json, Duration::from_millis(50));
+        log();
+        assert_eq!(control.status().format, LoggingFormatKind::Json);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        log();
+        assert_eq!(control.status().format, LoggingFormatKind::Compact);
+        assert!(control.status().expires.is_none());
+
+        control.set_format(LoggingFormatKind::Json, Duration::from_millis(50));
+        control.set_format(LoggingFormatKind::Json, Duration::from_secs(60));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        log();
+        let lines = capture.lines();
+        assert!(is_json(&lines[0]));
+        assert!(!is_json(&lines[1]));
+        assert!(is_json(&lines[2]));
+    }
+
+    /// Immutable subscribers and unknown addresses are rejected.
+    #[tokio::test]
+    async fn validation() {
+        let (_, mutable, _) = subscriber(false);
+        let immutable = Arc::new(SubscriberControl::new(
+            1,
+            Some("audit".into()),
+            true,
+            LoggingFormatKind::Json,
+            Arc::new(AtomicUsize::new(LoggingFormatKind::Json as usize)),
+        ));
+        let controls = [mutable.clone(), immutable];
+        let change = |index, name: Option<&str>| SubscriberChange {
+            index,
+            name: name.map(Into::into),
+            format: Some(LoggingFormatKind::Pretty),
+        };
+        let ttl = Duration::from_secs(60);
+        assert_eq!(
+            apply(&controls, &[change(Some(1), None)], ttl),
+            Err(LoggingControlError::Immutable("1".into()))
+        );
+        assert_eq!(
+            apply(
+                &controls,
+                &[change(None, Some("console")), change(None, Some("audit"))],
+                ttl
+            ),
+            Err(LoggingControlError::Immutable("audit".into()))
+        );
+        assert_eq!(mutable.status().format, LoggingFormatKind::Compact);
+        assert_eq!(
+            apply(&controls, &[change(Some(5), None)], ttl),
+            Err(LoggingControlError::UnknownSubscriber("5".into()))
+        );
+        assert_eq!(
+            apply(&controls, &[change(Some(0), Some("console"))], ttl),
+            Err(LoggingControlError::InvalidSelector)
+        );
+        apply(&controls, &[change(None, Some("console"))], ttl).unwrap();
+        assert_eq!(mutable.status().format, LoggingFormatKind::Pretty);
+    }
+}
@@ -162,7 +162,6 @@ pub(crate) struct ExtensibleJsonFormat<T = SystemTime> {
 }
 
 impl Default for ExtensibleJsonFormat {
-    #[must_use]
     fn default() -> Self {
         Self {
             timer: SystemTime,
@@ -529,11 +528,9 @@ impl<'a> io::Write for JsonWriter<'a> {
         let s =
             std::str::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
 
-        self.fmt_write
-            .write_str(s)
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        self.fmt_write.write_str(s).map_err(io::Error::other)?;
 
-        Ok(s.as_bytes().len())
+        Ok(s.len())
     }
 
     fn flush(&mut self) -> io::Result<()> {
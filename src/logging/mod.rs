@@ -1,9 +1,15 @@
 //! Logging configuration via [`tracing`] crate.
 
+pub(crate) mod access;
+pub(crate) mod control;
 pub(crate) mod json;
 pub(crate) mod span;
 
-use std::{collections::BTreeMap, fs, io};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    sync::{atomic::AtomicUsize, Arc},
+};
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -13,12 +19,16 @@ use tracing_appender::{
 };
 use tracing_subscriber::{
     filter::{LevelFilter, Targets},
-    fmt::{self, writer::BoxMakeWriter},
+    fmt::{self, writer::BoxMakeWriter, MakeWriter},
     layer::{Layer, Layered, SubscriberExt},
     registry::Registry,
 };
 
-use crate::logging::json::{ExtensibleJsonFormat, JsonKeyNames};
+use crate::logging::{
+    access::AccessLogConfig,
+    control::{FormatSwitch, LoggingControlConfig, SubscriberControl},
+    json::{ExtensibleJsonFormat, JsonKeyNames},
+};
 
 type LoggingRegistry = Layered<Vec<Box<dyn Layer<Registry> + Send + Sync>>, Registry>;
 
@@ -40,6 +50,16 @@ pub struct LoggingConfig {
     /// List of subscribers defined in configuration.
     #[serde(default)]
     pub subscribers: Vec<LoggingSubscriberConfig>,
+    /// Dedicated access log configuration.
+    ///
+    /// Access log is disabled if this section is absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<AccessLogConfig>,
+    /// Runtime logging control endpoint configuration.
+    ///
+    /// Endpoint is disabled if this section is absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub control: Option<LoggingControlConfig>,
 }
 
 impl LoggingConfig {
@@ -48,17 +68,23 @@ impl LoggingConfig {
     /// # Errors
     ///
     /// Returns `Err` if any of the subscribers cannot be initialized.
+    ///
+    /// Subscribers of the returned registry can be controlled at runtime via logging control
+    /// endpoint, see [`Self::control`].
     pub fn make_registry(&self) -> Result<(LoggingRegistry, Vec<WorkerGuard>), LoggingError> {
         let num_subs = self.subscribers.len();
-        let (subs, buf_guards) = self.subscribers.iter().try_fold(
-            (Vec::with_capacity(num_subs), Vec::with_capacity(num_subs)),
-            |(mut acc_s, mut acc_g), sub_cfg| {
-                let (sub, guard) = sub_cfg.make_layer()?;
-                acc_s.push(sub);
-                acc_g.push(guard);
-                Ok::<_, LoggingError>((acc_s, acc_g))
-            },
-        )?;
+        let mut subs = Vec::with_capacity(num_subs);
+        let mut buf_guards = Vec::with_capacity(num_subs);
+        let mut controls = Vec::with_capacity(num_subs);
+        for (index, sub_cfg) in self.subscribers.iter().enumerate() {
+            let buf_builder = sub_cfg.buffer.make_builder();
+            let (buf_writer, guard) = sub_cfg.output.make_non_blocking(buf_builder)?;
+            let (sub, control) = sub_cfg.make_switchable_layer(index, buf_writer);
+            subs.push(sub);
+            buf_guards.push(guard);
+            controls.push(control);
+        }
+        control::register(controls);
         Ok((Registry::default().with(subs), buf_guards))
     }
 }
@@ -67,6 +93,14 @@ impl LoggingConfig {
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[non_exhaustive]
 pub struct LoggingSubscriberConfig {
+    /// Subscriber name, used to address it in logging control endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Forbid changing output format at runtime.
+    ///
+    /// Use this for subscribers whose output is parsed by machines, like audit logs.
+    #[serde(default)]
+    pub immutable: bool,
     /// Overall format for logging output.
     #[serde(default, flatten)]
     pub format: LoggingFormat,
@@ -96,6 +130,8 @@ pub struct LoggingSubscriberConfig {
 impl Default for LoggingSubscriberConfig {
     fn default() -> Self {
         Self {
+            name: None,
+            immutable: false,
             format: LoggingFormat::default(),
             level: LoggingLevel::default(),
             targets: BTreeMap::new(),
@@ -113,6 +149,8 @@ impl LoggingSubscriberConfig {
     #[must_use]
     pub fn default_for_dev() -> Self {
         Self {
+            name: None,
+            immutable: false,
             format: LoggingFormat::Pretty,
             level: LoggingLevel::Trace,
             targets: BTreeMap::new(),
@@ -140,8 +178,56 @@ impl LoggingSubscriberConfig {
     {
         let buf_builder = self.buffer.make_builder();
         let (buf_writer, buf_guard) = self.output.make_writer(buf_builder)?;
+        let layer = self.make_format_layer(self.format.kind(), buf_writer);
+        Ok((self.apply_filter(layer), buf_guard))
+    }
+
+    /// Make [`tracing_subscriber::Layer`] with output format switchable at runtime.
+    ///
+    /// Layers for all formats are pre-built, so that every event is formatted by exactly one of
+    /// them. Immutable subscribers only get a layer for configured format.
+    pub(crate) fn make_switchable_layer<T, W>(
+        &self,
+        index: usize,
+        writer: W,
+    ) -> (Box<dyn Layer<T> + Send + Sync>, Arc<SubscriberControl>)
+    where
+        T: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+        W: for<'w> MakeWriter<'w> + Clone + Send + Sync + 'static,
+    {
+        let default = self.format.kind();
+        let current = Arc::new(AtomicUsize::new(default as usize));
+        let layers = if self.immutable {
+            vec![(default, self.make_format_layer(default, writer))]
+        } else {
+            LoggingFormatKind::ALL
+                .into_iter()
+                .map(|kind| (kind, self.make_format_layer(kind, writer.clone())))
+                .collect()
+        };
+        let control = Arc::new(SubscriberControl::new(
+            index,
+            self.name.clone(),
+            self.immutable,
+            default,
+            current.clone(),
+        ));
+        let switch = FormatSwitch::new(layers, current);
+        (self.apply_filter(switch.boxed()), control)
+    }
+
+    /// Make unfiltered formatting layer for specified format.
+    fn make_format_layer<T, W>(
+        &self,
+        kind: LoggingFormatKind,
+        writer: W,
+    ) -> Box<dyn Layer<T> + Send + Sync>
+    where
+        T: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+        W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+    {
         let layer = fmt::layer()
-            .with_writer(buf_writer)
+            .with_writer(writer)
             .with_ansi(self.color)
             .log_internal_errors(self.internal_errors)
             .with_target(self.print.target)
@@ -150,44 +236,55 @@ impl LoggingSubscriberConfig {
             .with_level(self.print.level)
             .with_thread_names(self.print.thread_name)
             .with_thread_ids(self.print.thread_id);
-        let boxed_layer = match self.format {
-            LoggingFormat::Full => layer.boxed(),
-            LoggingFormat::Compact => layer.compact().boxed(),
-            LoggingFormat::Pretty => layer.pretty().boxed(),
-            LoggingFormat::Json {
-                flatten_metadata,
-                current_span,
-                ref static_fields,
-                ref key_names,
-            } => {
-                let json_fmt = ExtensibleJsonFormat::new()
+        match kind {
+            LoggingFormatKind::Full => layer.boxed(),
+            LoggingFormatKind::Compact => layer.compact().boxed(),
+            LoggingFormatKind::Pretty => layer.pretty().boxed(),
+            LoggingFormatKind::Json => {
+                let mut json_fmt = ExtensibleJsonFormat::new()
                     .with_target(self.print.target)
                     .with_file(self.print.file)
                     .with_line_number(self.print.line_number)
                     .with_level(self.print.level)
                     .with_thread_names(self.print.thread_name)
-                    .with_thread_ids(self.print.thread_id)
-                    .flatten_event(flatten_metadata)
-                    .with_current_span(current_span)
-                    .with_static_fields(static_fields.clone())
-                    .with_key_names(*key_names.clone());
+                    .with_thread_ids(self.print.thread_id);
+                if let LoggingFormat::Json {
+                    flatten_metadata,
+                    current_span,
+                    ref static_fields,
+                    ref key_names,
+                } = self.format
+                {
+                    json_fmt = json_fmt
+                        .flatten_event(flatten_metadata)
+                        .with_current_span(current_span)
+                        .with_static_fields(static_fields.clone())
+                        .with_key_names(*key_names.clone());
+                }
                 layer.json().event_format(json_fmt).boxed()
             }
-        };
-        let boxed_layer = if self.targets.is_empty() {
-            boxed_layer
-                .with_filter(LevelFilter::from(self.level))
-                .boxed()
+        }
+    }
+
+    /// Wrap layer in configured severity filter.
+    fn apply_filter<T>(
+        &self,
+        layer: Box<dyn Layer<T> + Send + Sync>,
+    ) -> Box<dyn Layer<T> + Send + Sync>
+    where
+        T: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    {
+        if self.targets.is_empty() {
+            layer.with_filter(LevelFilter::from(self.level)).boxed()
         } else {
-            boxed_layer
+            layer
                 .with_filter(
                     Targets::new()
                         .with_targets(self.targets.clone())
                         .with_default(LevelFilter::from(self.level)),
                 )
                 .boxed()
-        };
-        Ok((boxed_layer, buf_guard))
+        }
     }
 }
 
@@ -233,6 +330,38 @@ pub enum LoggingFormat {
     },
 }
 
+impl LoggingFormat {
+    /// Get format kind, without format-specific parameters.
+    #[must_use]
+    pub(crate) fn kind(&self) -> LoggingFormatKind {
+        match self {
+            Self::Full => LoggingFormatKind::Full,
+            Self::Compact => LoggingFormatKind::Compact,
+            Self::Pretty => LoggingFormatKind::Pretty,
+            Self::Json { .. } => LoggingFormatKind::Json,
+        }
+    }
+}
+
+/// Format for logging output, without format-specific parameters.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LoggingFormatKind {
+    /// See [`LoggingFormat::Full`].
+    Full = 0,
+    /// See [`LoggingFormat::Compact`].
+    Compact = 1,
+    /// See [`LoggingFormat::Pretty`].
+    Pretty = 2,
+    /// See [`LoggingFormat::Json`].
+    Json = 3,
+}
+
+impl LoggingFormatKind {
+    /// All format kinds, ordered by discriminant.
+    pub(crate) const ALL: [Self; 4] = [Self::Full, Self::Compact, Self::Pretty, Self::Json];
+}
+
 /// Minumum event severity for log output.
 #[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
 #[serde(rename_all = "UPPERCASE")]
@@ -412,22 +541,24 @@ impl LoggingDestination {
         &self,
         buf_builder: NonBlockingBuilder,
     ) -> Result<(BoxMakeWriter, WorkerGuard), LoggingError> {
+        let (wr, wg) = self.make_non_blocking(buf_builder)?;
+        Ok((BoxMakeWriter::new(wr), wg))
+    }
+
+    /// Make cloneable non-blocking writer from configuration.
+    pub(crate) fn make_non_blocking(
+        &self,
+        buf_builder: NonBlockingBuilder,
+    ) -> Result<(NonBlocking, WorkerGuard), LoggingError> {
         match self {
-            Self::StdOut => {
-                let (wr, wg) = buf_builder.finish(io::stdout());
-                Ok((BoxMakeWriter::new(wr), wg))
-            }
-            Self::StdErr => {
-                let (wr, wg) = buf_builder.finish(io::stderr());
-                Ok((BoxMakeWriter::new(wr), wg))
-            }
+            Self::StdOut => Ok(buf_builder.finish(io::stdout())),
+            Self::StdErr => Ok(buf_builder.finish(io::stderr())),
             Self::File(file_cfg) => {
                 let file = fs::OpenOptions::new()
                     .append(true)
                     .create(true)
                     .open(&file_cfg.path)?;
-                let (wr, wg) = buf_builder.finish(file);
-                Ok((BoxMakeWriter::new(wr), wg))
+                Ok(buf_builder.finish(file))
             }
             Self::Directory(dir_cfg) => {
                 let mut builder = RollingFileAppender::builder().rotation(dir_cfg.rotate.into());
@@ -441,8 +572,7 @@ impl LoggingDestination {
                     builder = builder.max_log_files(max_files);
                 }
                 let appender = builder.build(&dir_cfg.path)?;
-                let (wr, wg) = buf_builder.finish(appender);
-                Ok((BoxMakeWriter::new(wr), wg))
+                Ok(buf_builder.finish(appender))
             }
         }
     }
@@ -76,6 +76,9 @@ impl MakeSpan<Body> for CustomMakeSpan {
                         "url.full" = %request.uri(),
                         "http.version" = ?request.version(),
                         "http.request.headers" = ?request.headers(),
+                        "uxum.response.serialization_time" = Empty,
+                        "uxum.response.first_byte_time" = Empty,
+                        "uxum.response.completion_time" = Empty,
                     )
                 } else {
                     tracing::span!(
@@ -88,6 +91,9 @@ impl MakeSpan<Body> for CustomMakeSpan {
                         "http.request.method" = %request.method(),
                         "url.full" = %request.uri(),
                         "http.version" = ?request.version(),
+                        "uxum.response.serialization_time" = Empty,
+                        "uxum.response.first_byte_time" = Empty,
+                        "uxum.response.completion_time" = Empty,
                     )
                 }
             }
@@ -128,7 +134,7 @@ impl<'a> Extractor for HeaderExtractor<'a> {
     }
 }
 
-pub(crate) fn register_request(req: Request<Body>) -> Request<Body> {
+pub(crate) fn register_request(req: Request<Body>, baggage_attributes: &[String]) -> Request<Body> {
     // TODO: don't lookup trace/span IDs, use values pre-extracted by tracing-opentelemetry.
     // TODO: don't send trace/span IDs as redundant attributes in otel traces.
     let parent_context = opentelemetry::global::get_text_map_propagator(|prop| {
@@ -140,5 +146,6 @@ pub(crate) fn register_request(req: Request<Body>) -> Request<Body> {
     let span_id = span.context().span().span_context().span_id();
     span.record("trace_id", trace_id.to_string());
     span.record("span_id", span_id.to_string());
+    crate::baggage::record_attributes(&span, baggage_attributes);
     req
 }
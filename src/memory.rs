@@ -0,0 +1,412 @@
+//! Global budget for memory used by buffered request and response bodies.
+//!
+//! Layers buffering whole bodies reserve memory from a shared budget before accumulating data,
+//! on top of their own size limits. Requests are rejected with `503 Service Unavailable` once the
+//! budget is exhausted, instead of allocating. Reservations are released when dropped.
+
+use std::{
+    fs,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use axum::{
+    body::{Body, Bytes},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use bytes::BytesMut;
+use http_body_util::BodyExt;
+use once_cell::sync::Lazy;
+use opentelemetry::{metrics::Counter, KeyValue};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::{
+    errors::{codes, ErrorCode},
+    retry::{RetryAdvice, RetryAdviceConfig, RetrySource},
+};
+
+/// Budget used if available memory can't be determined.
+const FALLBACK_BUDGET: usize = 256 * 1024 * 1024;
+
+/// Fraction of available memory used as default budget.
+const AVAILABLE_FRACTION: usize = 4;
+
+/// Error returned when global memory budget is exhausted.
+#[derive(Clone, Debug, Error)]
+#[non_exhaustive]
+pub enum MemoryError {
+    /// No budget left to buffer a body.
+    #[error("Buffered memory budget exhausted in {layer} layer")]
+    Exhausted {
+        /// Name of the layer which requested memory.
+        layer: &'static str,
+        /// Advice used for `Retry-After` header.
+        advice: RetryAdvice,
+    },
+}
+
+impl MemoryError {
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Exhausted { .. } => codes::MEMORY_EXHAUSTED,
+        }
+    }
+}
+
+impl IntoResponse for MemoryError {
+    fn into_response(self) -> axum::response::Response {
+        let code = self.code();
+        let problem = code
+            .problem(StatusCode::SERVICE_UNAVAILABLE)
+            .with_type("tag:uxum.github.io,2024:memory")
+            .with_title(self.to_string());
+        match self {
+            Self::Exhausted { advice, .. } => (code, advice.problem_response(problem)),
+        }
+        .into_response()
+    }
+}
+
+/// Buffered memory configuration.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct MemoryConfig {
+    /// Maximum total size of bodies buffered at once, in bytes.
+    ///
+    /// Defaults to a quarter of memory available at startup.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget: Option<usize>,
+}
+
+impl MemoryConfig {
+    /// Set maximum total size of bodies buffered at once, in bytes.
+    #[must_use]
+    pub fn with_budget(mut self, budget: usize) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Get effective budget, either explicitly set or derived from available memory.
+    #[must_use]
+    pub fn effective_budget(&self) -> usize {
+        self.budget.unwrap_or_else(|| match available_memory() {
+            Some(avail) => avail / AVAILABLE_FRACTION,
+            None => {
+                warn!("unable to determine available memory, using fallback budget");
+                FALLBACK_BUDGET
+            }
+        })
+    }
+
+    /// Apply configuration to global memory budget.
+    pub(crate) fn apply(&self, rejections: Option<Counter<u64>>, retry: &RetryAdviceConfig) {
+        let budget = global();
+        budget
+            .0
+            .limit
+            .store(self.effective_budget(), Ordering::Relaxed);
+        *budget.0.rejections.write() = rejections;
+        *budget.0.retry.write() = retry.clone();
+        debug!(
+            limit = self.effective_budget(),
+            "buffered memory budget set"
+        );
+    }
+}
+
+/// Read available memory from `/proc/meminfo`.
+fn available_memory() -> Option<usize> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let kib: usize = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    kib.checked_mul(1024)
+}
+
+/// Global memory budget, unlimited until configured.
+static GLOBAL: Lazy<MemoryBudget> = Lazy::new(|| MemoryBudget::new(usize::MAX));
+
+/// Get global memory budget.
+#[must_use]
+pub(crate) fn global() -> &'static MemoryBudget {
+    &GLOBAL
+}
+
+/// Shared byte accountant.
+#[derive(Clone, Debug)]
+pub(crate) struct MemoryBudget(Arc<MemoryBudgetInner>);
+
+/// Inner container for [`MemoryBudget`].
+#[derive(Debug)]
+struct MemoryBudgetInner {
+    /// Maximum number of reserved bytes.
+    limit: AtomicUsize,
+    /// Currently reserved bytes.
+    used: AtomicUsize,
+    /// Maximum number of reserved bytes observed.
+    high_water: AtomicUsize,
+    /// Rejection counter, only accessed when budget is exhausted.
+    rejections: RwLock<Option<Counter<u64>>>,
+    /// Retry advice configuration, only accessed when budget is exhausted.
+    retry: RwLock<RetryAdviceConfig>,
+}
+
+impl MemoryBudget {
+    /// Create new budget.
+    #[must_use]
+    pub(crate) fn new(limit: usize) -> Self {
+        Self(Arc::new(MemoryBudgetInner {
+            limit: AtomicUsize::new(limit),
+            used: AtomicUsize::new(0),
+            high_water: AtomicUsize::new(0),
+            rejections: RwLock::new(None),
+            retry: RwLock::new(RetryAdviceConfig::default()),
+        }))
+    }
+
+    /// Currently reserved bytes.
+    #[must_use]
+    pub(crate) fn usage(&self) -> usize {
+        self.0.used.load(Ordering::Relaxed)
+    }
+
+    /// Maximum number of reserved bytes observed.
+    #[must_use]
+    pub(crate) fn high_water(&self) -> usize {
+        self.0.high_water.load(Ordering::Relaxed)
+    }
+
+    /// Create empty reservation, to be grown as data arrives.
+    #[must_use]
+    pub(crate) fn reservation(&self, layer: &'static str) -> Reservation {
+        Reservation {
+            budget: self.clone(),
+            layer,
+            bytes: 0,
+        }
+    }
+
+    /// Try to reserve bytes, without exceeding the limit.
+    fn try_reserve(&self, bytes: usize) -> bool {
+        let limit = self.0.limit.load(Ordering::Relaxed);
+        let mut used = self.0.used.load(Ordering::Relaxed);
+        loop {
+            let Some(new) = used.checked_add(bytes).filter(|new| *new <= limit) else {
+                return false;
+            };
+            match self
+                .0
+                .used
+                .compare_exchange_weak(used, new, Ordering::AcqRel, Ordering::Relaxed)
+            {
+                Ok(_) => {
+                    self.0.high_water.fetch_max(new, Ordering::Relaxed);
+                    return true;
+                }
+                Err(current) => used = current,
+            }
+        }
+    }
+
+    /// Record rejected reservation.
+    fn reject(&self, layer: &'static str) -> MemoryError {
+        if let Some(counter) = self.0.rejections.read().as_ref() {
+            counter.add(1, &[KeyValue::new("uxum.layer", layer)]);
+        }
+        MemoryError::Exhausted {
+            layer,
+            advice: self.0.retry.read().advise(RetrySource::Memory, None),
+        }
+    }
+}
+
+/// Bytes reserved from [`MemoryBudget`], released on drop.
+#[derive(Debug)]
+pub(crate) struct Reservation {
+    /// Budget this reservation belongs to.
+    budget: MemoryBudget,
+    /// Name of the layer holding the reservation.
+    layer: &'static str,
+    /// Reserved bytes.
+    bytes: usize,
+}
+
+impl Reservation {
+    /// Grow reservation to at least `total` bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the budget is exhausted.
+    pub(crate) fn grow_to(&mut self, total: usize) -> Result<(), MemoryError> {
+        if total <= self.bytes {
+            return Ok(());
+        }
+        let extra = total - self.bytes;
+        if !self.budget.try_reserve(extra) {
+            return Err(self.budget.reject(self.layer));
+        }
+        self.bytes = total;
+        Ok(())
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.budget.0.used.fetch_sub(self.bytes, Ordering::AcqRel);
+    }
+}
+
+/// Body buffering error.
+#[derive(Debug)]
+pub(crate) enum BufferError {
+    /// Memory budget exhausted.
+    Memory(MemoryError),
+    /// Body exceeds size limit.
+    TooLarge(usize),
+    /// Body could not be read.
+    Read(String),
+}
+
+/// Buffer whole body, reserving memory from budget before accumulating data.
+///
+/// Returned reservation must be held for as long as the buffered data is in use.
+pub(crate) async fn buffer_body(
+    budget: &MemoryBudget,
+    layer: &'static str,
+    mut body: Body,
+    limit: usize,
+) -> Result<(Bytes, Reservation), BufferError> {
+    let mut reservation = budget.reservation(layer);
+    let hint = usize::try_from(http_body::Body::size_hint(&body).lower()).unwrap_or(usize::MAX);
+    if hint > limit {
+        return Err(BufferError::TooLarge(limit));
+    }
+    reservation.grow_to(hint).map_err(BufferError::Memory)?;
+    let mut buf = BytesMut::with_capacity(hint);
+    while let Some(frame) = body.frame().await {
+        let frame = frame.map_err(|err| BufferError::Read(err.to_string()))?;
+        let Ok(data) = frame.into_data() else {
+            continue;
+        };
+        let total = buf.len().saturating_add(data.len());
+        if total > limit {
+            return Err(BufferError::TooLarge(limit));
+        }
+        reservation.grow_to(total).map_err(BufferError::Memory)?;
+        buf.extend_from_slice(&data);
+    }
+    Ok((buf.freeze(), reservation))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::stream;
+
+    use super::*;
+
+    const MIB: usize = 1024 * 1024;
+
+    /// Streaming body of `chunks` chunks, `chunk_size` bytes each.
+    fn slow_body(chunks: usize, chunk_size: usize) -> Body {
+        Body::from_stream(stream::unfold(0, move |sent| async move {
+            if sent == chunks {
+                return None;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            Some((
+                Ok::<_, std::io::Error>(Bytes::from(vec![0; chunk_size])),
+                sent + 1,
+            ))
+        }))
+    }
+
+    /// Reservations are bounded by limit, and released on drop.
+    #[test]
+    fn reservations() {
+        let budget = MemoryBudget::new(100);
+        *budget.0.retry.write() = RetryAdviceConfig::default()
+            .with_jitter(0.0)
+            .with_base(RetrySource::RateLimit, Duration::from_secs(60))
+            .with_base(RetrySource::Memory, Duration::from_secs(3));
+        let mut first = budget.reservation("test");
+        first.grow_to(60).unwrap();
+        let mut second = budget.reservation("test");
+        assert!(matches!(
+            second.grow_to(50),
+            Err(MemoryError::Exhausted { layer: "test", .. })
+        ));
+        let resp = second.grow_to(50).unwrap_err().into_response();
+        assert_eq!(resp.headers()[axum::http::header::RETRY_AFTER], "3");
+        second.grow_to(40).unwrap();
+        assert_eq!(budget.usage(), 100);
+        drop(first);
+        assert_eq!(budget.usage(), 40);
+        second.grow_to(90).unwrap();
+        drop(second);
+        assert_eq!(budget.usage(), 0);
+        assert_eq!(budget.high_water(), 100);
+    }
+
+    /// Concurrent large bodies exceeding the budget are rejected, instead of allocating.
+    #[tokio::test]
+    async fn concurrent_bodies() {
+        let budget = MemoryBudget::new(4 * MIB);
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let budget = budget.clone();
+                tokio::spawn(async move {
+                    let (bytes, reservation) =
+                        buffer_body(&budget, "test", slow_body(8, 128 * 1024), 2 * MIB).await?;
+                    assert_eq!(bytes.len(), MIB);
+                    // Hold buffered data for a while.
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    drop(reservation);
+                    Ok::<_, BufferError>(())
+                })
+            })
+            .collect();
+        let mut succeeded = 0;
+        let mut rejected = 0;
+        for task in tasks {
+            match task.await.unwrap() {
+                Ok(()) => succeeded += 1,
+                Err(BufferError::Memory(_)) => rejected += 1,
+                Err(err) => panic!("unexpected error: {err:?}"),
+            }
+        }
+        assert!(rejected > 0);
+        assert_eq!(succeeded + rejected, 8);
+        assert!(budget.high_water() <= 4 * MIB);
+        assert_eq!(budget.usage(), 0);
+
+        // Released budget is available again.
+        let (bytes, reservation) = buffer_body(&budget, "test", slow_body(8, 512 * 1024), 4 * MIB)
+            .await
+            .unwrap();
+        assert_eq!(bytes.len(), 4 * MIB);
+        assert_eq!(budget.usage(), 4 * MIB);
+        drop(reservation);
+
+        // Per-layer size limit still applies.
+        assert!(matches!(
+            buffer_body(&budget, "test", Body::from(vec![0; MIB]), 1024).await,
+            Err(BufferError::TooLarge(1024))
+        ));
+        assert_eq!(budget.usage(), 0);
+    }
+}
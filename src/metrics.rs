@@ -1,41 +1,80 @@
 //! Subsystem to gather and export application metrics.
 
 use std::{
-    borrow::Cow,
-    collections::HashMap,
+    borrow::Borrow,
+    collections::{HashMap, HashSet},
+    convert::Infallible,
     future::Future,
     ops::Deref,
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     task::{ready, Context, Poll},
-    time::Instant,
+    time::{Instant, SystemTime},
 };
 
 use axum::{
-    body::HttpBody,
-    extract::{MatchedPath, State},
-    http::{header, StatusCode},
+    body::{Body, Bytes, HttpBody},
+    error_handling::HandleErrorLayer,
+    extract::{MatchedPath, RawQuery, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::map_response,
     response::{IntoResponse, Response},
     routing::{self, Router},
 };
-use hyper::{Method, Request};
+use bytes::Buf;
+use dashmap::{DashMap, DashSet};
+use futures::{stream, StreamExt};
+use http_body::{Frame, SizeHint};
+use hyper::Request;
 use opentelemetry::{
     global,
-    metrics::{Counter, Histogram, MeterProvider, ObservableGauge, UpDownCounter},
-    KeyValue,
+    metrics::{Counter, Histogram, Meter, MeterProvider, ObservableGauge, UpDownCounter},
+    trace::{TraceContextExt, TraceId},
+    KeyValue, Value,
 };
 use opentelemetry_sdk::{
-    metrics::{new_view, Aggregation, Instrument, MeterProviderBuilder, Stream},
+    metrics::{new_view, Aggregation, Instrument, MeterProviderBuilder, SdkMeterProvider, Stream},
     Resource,
 };
-use pin_project::pin_project;
-use prometheus::{Encoder, Registry, TextEncoder};
+use pin_project::{pin_project, pinned_drop};
+use prometheus::{proto::MetricFamily, Encoder, Registry, TextEncoder};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tower::{Layer, Service};
-use tracing::{debug_span, trace};
+use tower::{Layer, Service, ServiceBuilder};
+use tracing::{debug, debug_span, trace, warn, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use url::form_urlencoded;
 
-use crate::layers::ext::HandlerName;
+use crate::{
+    auth::{AuthExtractor, AuthLayer, AuthProvider},
+    batch::BatchedRequest,
+    builder::app::error_handler,
+    errors::{codes, ErrorCode},
+    layers::{decompression::DecodedBodySize, ext::HandlerName, ip_filter::IpFilterConfig},
+    response::SerializationTime,
+    warmup::Warmup,
+};
+
+/// Content type of Prometheus text exposition format.
+const TEXT_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Content type of OpenMetrics text exposition format.
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Global switch for response timing breakdown.
+///
+/// Checked by response wrappers to avoid measuring serialization time when nobody is interested.
+static RESPONSE_TIMING: AtomicBool = AtomicBool::new(false);
+
+/// Whether response timing breakdown is enabled.
+#[must_use]
+#[inline]
+pub(crate) fn response_timing_enabled() -> bool {
+    RESPONSE_TIMING.load(Ordering::Relaxed)
+}
 
 /// Error type used in metrics subsystem.
 #[derive(Debug, Error)]
@@ -49,12 +88,24 @@ pub enum MetricsError {
     OpenTelemetry(#[from] opentelemetry::metrics::MetricsError),
 }
 
+impl MetricsError {
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Prometheus(_) | Self::OpenTelemetry(_) => codes::METRICS_INTERNAL,
+        }
+    }
+}
+
 impl IntoResponse for MetricsError {
     fn into_response(self) -> Response {
-        problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
+        let code = self.code();
+        let problem = code
+            .problem(StatusCode::INTERNAL_SERVER_ERROR)
             .with_type("tag:uxum.github.io,2024:metrics")
-            .with_title(self.to_string())
-            .into_response()
+            .with_title(self.to_string());
+        (code, problem).into_response()
     }
 }
 
@@ -79,11 +130,79 @@ pub struct MetricsBuilder {
     #[serde(default = "MetricsBuilder::default_metrics_path")]
     metrics_path: String,
     /// Static labels to add to gathered metrics.
+    ///
+    /// Applied by Prometheus registry when exporting, so these are not visible to other
+    /// OpenTelemetry exporters.
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     labels: HashMap<String, String>,
     /// Optional prefix for metric names.
+    ///
+    /// Prepended to exported names, separated by underscore. As with [`Self::labels`], this
+    /// applies only to Prometheus exporter.
     #[serde(default)]
     prefix: Option<String>,
+    /// Whether to break down response latency into serialization, first byte and completion
+    /// times.
+    ///
+    /// Disabled by default.
+    #[serde(default)]
+    response_timing: bool,
+    /// Whether to attach trace IDs of sampled requests as exemplars to request duration
+    /// histogram.
+    ///
+    /// Exemplars are only exposed to scrapers requesting OpenMetrics format. Disabled by default,
+    /// as exemplars noticeably inflate exposition size.
+    #[serde(default)]
+    exemplars: bool,
+    /// Whether to collapse `http.response.status_code` label values into status classes, such as
+    /// `2xx` or `4xx`.
+    ///
+    /// Disabled by default.
+    #[serde(default)]
+    status_code_classes: bool,
+    /// Whether to add `url.scheme` label to HTTP server request metrics.
+    #[serde(default = "crate::util::default_true")]
+    scheme_label: bool,
+    /// Route templates to exclude from HTTP server request metrics.
+    ///
+    /// Requests to these routes are not counted as active, and are not recorded at all.
+    #[serde(default, skip_serializing_if = "HashSet::is_empty")]
+    excluded_routes: HashSet<String>,
+    /// Maximum length of `http.route` and `uxum.client` label values.
+    ///
+    /// Longer values are truncated.
+    #[serde(default = "MetricsBuilder::default_max_route_length")]
+    max_route_length: usize,
+    /// Maximum number of distinct `http.route` label values.
+    ///
+    /// Routes seen after this limit is reached are recorded as `<other>`.
+    #[serde(default = "MetricsBuilder::default_max_routes")]
+    max_routes: usize,
+    /// Maximum number of distinct `uxum.client` label values.
+    ///
+    /// Clients seen after this limit is reached are recorded as `<other>`.
+    #[serde(default = "MetricsBuilder::default_max_clients")]
+    max_clients: usize,
+    /// Maximum size of Prometheus exposition, in bytes.
+    ///
+    /// When exceeded, metric families from [`Self::drop_families`] are omitted from response,
+    /// in order, until exposition fits the limit. Unlimited by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_exposition_bytes: Option<usize>,
+    /// Low-priority metric families, in order of dropping.
+    ///
+    /// Uses exported Prometheus family names, including prefix and unit suffixes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    drop_families: Vec<String>,
+    /// Permissions required to scrape metrics endpoint.
+    ///
+    /// Metrics endpoint is not authenticated if not set. Set to an empty list to only require
+    /// authentication.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    permissions: Option<Vec<String>>,
+    /// Client IP address filter for metrics endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ip_filter: Option<IpFilterConfig>,
 }
 
 impl Default for MetricsBuilder {
@@ -95,6 +214,18 @@ impl Default for MetricsBuilder {
             metrics_path: Self::default_metrics_path(),
             labels: HashMap::new(),
             prefix: None,
+            response_timing: false,
+            exemplars: false,
+            status_code_classes: false,
+            scheme_label: true,
+            excluded_routes: HashSet::new(),
+            max_route_length: Self::default_max_route_length(),
+            max_routes: Self::default_max_routes(),
+            max_clients: Self::default_max_clients(),
+            max_exposition_bytes: None,
+            drop_families: Vec::new(),
+            permissions: None,
+            ip_filter: None,
         }
     }
 }
@@ -149,6 +280,27 @@ impl MetricsBuilder {
         "/metrics".into()
     }
 
+    /// Default value for [`Self::max_route_length`].
+    #[must_use]
+    #[inline]
+    fn default_max_route_length() -> usize {
+        128
+    }
+
+    /// Default value for [`Self::max_routes`].
+    #[must_use]
+    #[inline]
+    fn default_max_routes() -> usize {
+        1000
+    }
+
+    /// Default value for [`Self::max_clients`].
+    #[must_use]
+    #[inline]
+    fn default_max_clients() -> usize {
+        100
+    }
+
     /// Whether HTTP metrics gathering is enabled.
     #[must_use]
     #[inline]
@@ -220,6 +372,103 @@ impl MetricsBuilder {
         self
     }
 
+    /// Enable or disable response timing breakdown.
+    ///
+    /// When enabled, time spent serializing response bodies (using [`crate::Json`]) and time until
+    /// full response body is sent are recorded as separate metrics and span attributes.
+    #[must_use]
+    pub fn with_response_timing(mut self, enabled: bool) -> Self {
+        self.response_timing = enabled;
+        self
+    }
+
+    /// Enable or disable trace ID exemplars on request duration histogram.
+    #[must_use]
+    pub fn with_exemplars(mut self, enabled: bool) -> Self {
+        self.exemplars = enabled;
+        self
+    }
+
+    /// Enable or disable collapsing of status codes into status classes.
+    ///
+    /// When enabled, `http.response.status_code` label values are reported as `1xx` to `5xx`.
+    #[must_use]
+    pub fn with_status_code_classes(mut self, enabled: bool) -> Self {
+        self.status_code_classes = enabled;
+        self
+    }
+
+    /// Enable or disable `url.scheme` label on HTTP server request metrics.
+    #[must_use]
+    pub fn with_scheme_label(mut self, enabled: bool) -> Self {
+        self.scheme_label = enabled;
+        self
+    }
+
+    /// Exclude route from HTTP server request metrics.
+    ///
+    /// Route is matched against [`axum`] route template, or request path if no route matched.
+    #[must_use]
+    pub fn with_excluded_route(mut self, route: impl ToString) -> Self {
+        self.excluded_routes.insert(route.to_string());
+        self
+    }
+
+    /// Set maximum length of `http.route` label value.
+    #[must_use]
+    pub fn with_max_route_length(mut self, max_route_length: usize) -> Self {
+        self.max_route_length = max_route_length;
+        self
+    }
+
+    /// Set maximum number of distinct `http.route` label values.
+    #[must_use]
+    pub fn with_max_routes(mut self, max_routes: usize) -> Self {
+        self.max_routes = max_routes;
+        self
+    }
+
+    /// Set maximum number of distinct `uxum.client` label values.
+    #[must_use]
+    pub fn with_max_clients(mut self, max_clients: usize) -> Self {
+        self.max_clients = max_clients;
+        self
+    }
+
+    /// Set maximum size of Prometheus exposition, in bytes.
+    #[must_use]
+    pub fn with_max_exposition_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_exposition_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Add low-priority metric family, dropped when exposition exceeds its size limit.
+    ///
+    /// Families are dropped in order of addition.
+    #[must_use]
+    pub fn with_drop_family(mut self, name: impl ToString) -> Self {
+        self.drop_families.push(name.to_string());
+        self
+    }
+
+    /// Require authentication with specified permissions to scrape metrics endpoint.
+    #[must_use]
+    pub fn with_permissions<I, T>(mut self, permissions: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: ToString,
+    {
+        self.permissions = Some(permissions.into_iter().map(|p| p.to_string()).collect());
+        self
+    }
+
+    /// Set client IP address filter for metrics endpoint.
+    #[must_use]
+    pub fn with_ip_filter(mut self, ip_filter: IpFilterConfig) -> Self {
+        self.ip_filter = Some(ip_filter);
+        self
+    }
+
     /// Build new Prometheus registry.
     fn build_prometheus_registry(&self) -> Result<Registry, MetricsError> {
         Registry::new_custom(
@@ -241,14 +490,47 @@ impl MetricsBuilder {
     pub fn build_state(&self, resource: Resource) -> Result<MetricsState, MetricsError> {
         let _span = debug_span!("build_metrics").entered();
         let registry = self.build_prometheus_registry()?;
+        let handler_durations = Arc::new(HandlerDurations::default());
         let exporter = opentelemetry_prometheus::exporter()
             .with_registry(registry.clone())
             .build()?;
         let provider = MeterProviderBuilder::default()
             .with_resource(resource)
             .with_reader(exporter)
+            .with_view({
+                let default_buckets = self.duration_buckets.clone();
+                let handler_durations = handler_durations.clone();
+                move |inst: &Instrument| {
+                    if !inst.name.ends_with("http.server.request.duration") {
+                        return None;
+                    }
+                    let boundaries = inst
+                        .scope
+                        .name
+                        .strip_prefix(HANDLER_SCOPE_PREFIX)
+                        .and_then(|name| handler_durations.buckets.get(name))
+                        .map_or_else(|| default_buckets.clone(), |buckets| buckets.clone());
+                    Some(
+                        Stream::new()
+                            .name(inst.name.clone())
+                            .description(inst.description.clone())
+                            .unit(inst.unit.clone())
+                            .aggregation(Aggregation::ExplicitBucketHistogram {
+                                boundaries,
+                                record_min_max: true,
+                            }),
+                    )
+                }
+            })
             .with_view(new_view(
-                Instrument::new().name("*http.server.request.duration"),
+                Instrument::new().name("*http.server.response.serialization.duration"),
+                Stream::new().aggregation(Aggregation::ExplicitBucketHistogram {
+                    boundaries: self.duration_buckets.clone(),
+                    record_min_max: true,
+                }),
+            )?)
+            .with_view(new_view(
+                Instrument::new().name("*http.server.response.completion.duration"),
                 Stream::new().aggregation(Aggregation::ExplicitBucketHistogram {
                     boundaries: self.duration_buckets.clone(),
                     record_min_max: true,
@@ -261,6 +543,13 @@ impl MetricsBuilder {
                     record_min_max: true,
                 }),
             )?)
+            .with_view(new_view(
+                Instrument::new().name("*http.server.request.decoded.body.size"),
+                Stream::new().aggregation(Aggregation::ExplicitBucketHistogram {
+                    boundaries: self.size_buckets.clone(),
+                    record_min_max: true,
+                }),
+            )?)
             .with_view(new_view(
                 Instrument::new().name("*http.server.response.body.size"),
                 Stream::new().aggregation(Aggregation::ExplicitBucketHistogram {
@@ -271,16 +560,14 @@ impl MetricsBuilder {
             .build();
 
         global::set_meter_provider(provider.clone());
+        // Once enabled, stays enabled, as response wrappers have no access to metrics state.
+        RESPONSE_TIMING.fetch_or(self.response_timing, Ordering::Relaxed);
         let meter = provider.meter("uxum");
 
         // TODO: try_init() and handle errors.
 
         // HTTP server metrics.
-        let request_duration = meter
-            .f64_histogram("http.server.request.duration")
-            .with_unit("s")
-            .with_description("The HTTP request latencies in seconds.")
-            .init();
+        let request_duration = request_duration_histogram(&meter);
         let requests_total = meter
             .u64_counter("http.server.requests")
             .with_description(
@@ -296,17 +583,207 @@ impl MetricsBuilder {
             .with_unit("By")
             .with_description("The HTTP request body sizes in bytes.")
             .init();
+        let request_decoded_body_size = meter
+            .u64_histogram("http.server.request.decoded.body.size")
+            .with_unit("By")
+            .with_description("The decompressed HTTP request body sizes in bytes.")
+            .init();
         let response_body_size = meter
             .u64_histogram("http.server.response.body.size")
             .with_unit("By")
             .with_description("The HTTP reponse body sizes in bytes.")
             .init();
+        let ip_filter_rejections = meter
+            .u64_counter("http.server.ip_filter.rejections")
+            .with_description("How many HTTP requests were rejected by IP filter, per handler.")
+            .init();
+        let rate_limit_rejections = meter
+            .u64_counter("http.server.rate_limit.rejections")
+            .with_description(
+                "How many HTTP requests were rejected by rate limiter, per handler and key type.",
+            )
+            .init();
+        let deprecated_requests = meter
+            .u64_counter("http.server.deprecated.requests")
+            .with_description("How many requests were made to deprecated handlers, per client.")
+            .init();
+        let cancelled_requests = meter
+            .u64_counter("http.server.cancelled.requests")
+            .with_description("How many requests were cancelled, per handler and cause.")
+            .init();
+        let missing_translations = meter
+            .u64_counter("http.server.errors.missing_translations")
+            .with_description(
+                "How many error message fields were left untranslated, per key and locale.",
+            )
+            .init();
+        let requests_transformed = meter
+            .u64_counter("http.server.requests.transformed")
+            .with_description("How many HTTP request bodies were transformed, per handler.")
+            .init();
+        let contract_violations = meter
+            .u64_counter("http.server.responses.contract_violations")
+            .with_description(
+                "How many responses did not match declared schema, per handler and status.",
+            )
+            .init();
+        let validation_skipped = meter
+            .u64_counter("http.server.responses.validation_skipped")
+            .with_description(
+                "How many responses were not validated against declared schema, per handler and reason.",
+            )
+            .init();
+        let fair_queue = FairQueueMetrics {
+            wait_duration: meter
+                .f64_histogram("http.server.fair_queue.wait.duration")
+                .with_unit("s")
+                .with_description("Time spent by requests in fair queue in seconds, per tenant.")
+                .init(),
+            dispatched: meter
+                .u64_counter("http.server.fair_queue.dispatched")
+                .with_description("How many requests were dispatched from fair queue, per tenant.")
+                .init(),
+            dropped: meter
+                .u64_counter("http.server.fair_queue.dropped")
+                .with_description(
+                    "How many requests were dropped from fair queue, per tenant and reason.",
+                )
+                .init(),
+        };
+        let circuit_breaker = CircuitBreakerMetrics {
+            transitions: meter
+                .u64_counter("uxum.handler.circuit_breaker.transitions")
+                .with_description(
+                    "How many times handler circuit breakers changed state, per handler and states.",
+                )
+                .init(),
+            probes: meter
+                .u64_counter("uxum.handler.circuit_breaker.probes")
+                .with_description(
+                    "How many requests passed through half-open circuit breakers, per handler.",
+                )
+                .init(),
+        };
+        let dependency_duration = meter
+            .f64_histogram("http.server.dependency.duration")
+            .with_unit("s")
+            .with_description(
+                "Time spent awaiting downstream dependencies in seconds, per handler and dependency.",
+            )
+            .init();
+        let cpu_stalls = meter
+            .u64_counter("uxum.handler.cpu_stalls")
+            .with_description(
+                "How many times handlers did not yield to async runtime for too long, per handler.",
+            )
+            .init();
+        let state_restored = meter
+            .u64_counter("resilience.state.restored")
+            .with_description(
+                "How many persisted resilience state entries were restored, per kind.",
+            )
+            .init();
+        let state_discarded = meter
+            .u64_counter("resilience.state.discarded")
+            .with_description(
+                "How many persisted resilience state entries were discarded, per kind and reason.",
+            )
+            .init();
+        let memory_rejections = meter
+            .u64_counter("memory.buffered.rejections")
+            .with_description(
+                "How many bodies were rejected due to exhausted memory budget, per layer.",
+            )
+            .init();
+        let trailing_slash_redirects = meter
+            .u64_counter("http.server.trailing_slash_redirects")
+            .with_description(
+                "How many requests were redirected to canonical path without trailing slash.",
+            )
+            .init();
+        let job_queue = JobQueueMetrics {
+            enqueued: meter
+                .u64_counter("queue.jobs.enqueued")
+                .with_description("How many jobs were enqueued, per job type.")
+                .init(),
+            processed: meter
+                .u64_counter("queue.jobs.processed")
+                .with_description("How many jobs were processed successfully, per job type.")
+                .init(),
+            retried: meter
+                .u64_counter("queue.jobs.retried")
+                .with_description("How many failed jobs were scheduled for retry, per job type.")
+                .init(),
+            dead_lettered: meter
+                .u64_counter("queue.jobs.dead_lettered")
+                .with_description(
+                    "How many jobs were moved to dead-letter namespace, per job type.",
+                )
+                .init(),
+            duration: meter
+                .f64_histogram("queue.job.duration")
+                .with_unit("s")
+                .with_description("Job processing durations in seconds, per job type.")
+                .init(),
+        };
+        let response_hooks = ResponseHookMetrics {
+            scheduled: meter
+                .u64_counter("http.server.response_hooks.scheduled")
+                .with_description("How many post-response hooks were scheduled, per handler.")
+                .init(),
+            completed: meter
+                .u64_counter("http.server.response_hooks.completed")
+                .with_description(
+                    "How many post-response hooks completed successfully, per handler.",
+                )
+                .init(),
+            failed: meter
+                .u64_counter("http.server.response_hooks.failed")
+                .with_description("How many post-response hooks failed or panicked, per handler.")
+                .init(),
+            timed_out: meter
+                .u64_counter("http.server.response_hooks.timed_out")
+                .with_description("How many post-response hooks timed out, per handler.")
+                .init(),
+        };
+        let response_timing = self.response_timing.then(|| ResponseTimingMetrics {
+            serialization_duration: meter
+                .f64_histogram("http.server.response.serialization.duration")
+                .with_unit("s")
+                .with_description("Time spent serializing HTTP response bodies in seconds.")
+                .init(),
+            completion_duration: meter
+                .f64_histogram("http.server.response.completion.duration")
+                .with_unit("s")
+                .with_description("Time until HTTP response body is fully sent in seconds.")
+                .init(),
+        });
         let http_server = HttpServerMetrics {
             request_duration,
             requests_total,
             requests_active,
             request_body_size,
+            request_decoded_body_size,
             response_body_size,
+            ip_filter_rejections,
+            rate_limit_rejections,
+            requests_transformed,
+            contract_violations,
+            validation_skipped,
+            deprecated_requests,
+            cancelled_requests,
+            missing_translations,
+            fair_queue,
+            circuit_breaker,
+            dependency_duration,
+            cpu_stalls,
+            state_restored,
+            state_discarded,
+            memory_rejections,
+            trailing_slash_redirects,
+            job_queue,
+            response_hooks,
+            response_timing,
         };
 
         // HTTP client metrics
@@ -335,15 +812,50 @@ impl MetricsBuilder {
             .with_unit("By")
             .with_description("The HTTP reponse body sizes in bytes.")
             .init();
+        let egress_violations = meter
+            .u64_counter("http.client.egress.violations")
+            .with_description("How many outgoing requests violated egress policy, per rule.")
+            .init();
         let http_client = HttpClientMetricsInner {
             request_duration,
             requests_total,
             requests_active,
             request_body_size,
             response_body_size,
+            egress_violations,
         };
         let http_client = HttpClientMetrics(Arc::new(http_client));
 
+        // Prometheus exposition metrics.
+        let exposition = ExpositionPolicy {
+            max_bytes: self.max_exposition_bytes,
+            drop_families: self.drop_families.clone().into(),
+            dropped: meter
+                .u64_counter("metrics.exposition.dropped")
+                .with_description(
+                    "How many times metric families were dropped from exposition, per family.",
+                )
+                .init(),
+        };
+
+        // Buffered memory metrics.
+        let memory_usage = meter
+            .u64_observable_gauge("memory.buffered.usage")
+            .with_unit("By")
+            .with_description("Memory currently reserved for buffered bodies in bytes.")
+            .init();
+        let memory_high_water = meter
+            .u64_observable_gauge("memory.buffered.high_water")
+            .with_unit("By")
+            .with_description("Maximum memory reserved for buffered bodies in bytes.")
+            .init();
+
+        // Trace sampling metrics.
+        let sampling_ratio = meter
+            .f64_observable_gauge("tracing.sampling.ratio")
+            .with_description("Effective trace sampling ratio.")
+            .init();
+
         // Tokio runtime metrics.
         let num_workers = meter
             .u64_observable_gauge("runtime.workers")
@@ -360,10 +872,50 @@ impl MetricsBuilder {
 
         Ok(MetricsState {
             registry,
+            provider,
+            handler_durations,
+            routes: LabelGuard::new(
+                "http.route",
+                UNMATCHED_ROUTE,
+                self.max_route_length,
+                self.max_routes,
+            ),
+            clients: LabelGuard::new(
+                "uxum.client",
+                ANONYMOUS_CLIENT,
+                self.max_route_length,
+                self.max_clients,
+            ),
             http_server,
             http_client,
             runtime,
+            sampling_ratio,
+            memory_usage,
+            memory_high_water,
+            exposition,
+            status_code_classes: self.status_code_classes,
+            scheme_label: self.scheme_label,
+            excluded_routes: Arc::new(self.excluded_routes.clone()),
+            exemplars: self.exemplars.then(|| {
+                ExemplarStore::new(
+                    match &self.prefix {
+                        Some(prefix) => format!("{prefix}_{REQUEST_DURATION_FAMILY}"),
+                        None => REQUEST_DURATION_FAMILY.into(),
+                    },
+                    &self.duration_buckets,
+                    self.labels.keys().cloned().collect(),
+                )
+            }),
             metrics_path: self.metrics_path.clone(),
+            // Leaked once at build time, as auth layer requires static permissions.
+            permissions: self.permissions.as_ref().map(|perms| {
+                let perms: Vec<&'static str> = perms
+                    .iter()
+                    .map(|perm| &*Box::leak(perm.clone().into_boxed_str()))
+                    .collect();
+                &*Box::leak(perms.into_boxed_slice())
+            }),
+            ip_filter: self.ip_filter.clone(),
         })
     }
 }
@@ -375,15 +927,322 @@ pub struct MetricsState {
     /// Prometheus registry.
     ///
     /// Holds all configured metrics and their collected values.
-    registry: Registry,
+    pub(crate) registry: Registry,
+    /// OpenTelemetry meter provider.
+    provider: SdkMeterProvider,
+    /// Request duration histograms of handlers with overridden buckets.
+    handler_durations: Arc<HandlerDurations>,
+    /// Guard for `http.route` label cardinality.
+    routes: LabelGuard,
+    /// Guard for `uxum.client` label cardinality.
+    clients: LabelGuard,
     /// HTTP server metrics.
     http_server: HttpServerMetrics,
     /// HTTP client metrics.
     http_client: HttpClientMetrics,
     /// Tokio runtime metrics.
     runtime: RuntimeMetrics,
+    /// Effective trace sampling ratio, if adjustable at runtime.
+    sampling_ratio: ObservableGauge<f64>,
+    /// Memory currently reserved for buffered bodies.
+    memory_usage: ObservableGauge<u64>,
+    /// Maximum memory reserved for buffered bodies.
+    memory_high_water: ObservableGauge<u64>,
+    /// Prometheus exposition size limits.
+    exposition: ExpositionPolicy,
+    /// Whether to collapse status codes into status classes.
+    status_code_classes: bool,
+    /// Whether to add `url.scheme` label to HTTP server request metrics.
+    scheme_label: bool,
+    /// Route templates excluded from HTTP server request metrics.
+    excluded_routes: Arc<HashSet<String>>,
+    /// Trace ID exemplars for request duration histogram, if enabled.
+    exemplars: Option<ExemplarStore>,
     /// URL path for metrics prometheus exporter.
     metrics_path: String,
+    /// Permissions required to scrape metrics endpoint.
+    permissions: Option<&'static [&'static str]>,
+    /// Client IP address filter for metrics endpoint.
+    ip_filter: Option<IpFilterConfig>,
+}
+
+/// Per-handler metrics configuration.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct HandlerMetricsConfig {
+    /// Histogram metric buckets for total request duration of this handler.
+    ///
+    /// Overrides [`MetricsBuilder`] duration buckets. Measured in seconds.
+    ///
+    /// Handler series are recorded by a separate histogram instrument, and are exported with
+    /// distinct `otel_scope_name` label. Each series keeps a counter per bucket, so memory use
+    /// and exposition size grow with number of buckets times number of label combinations seen
+    /// by this handler.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_buckets: Option<Vec<f64>>,
+}
+
+impl HandlerMetricsConfig {
+    /// Set histogram metric buckets for total request duration of this handler.
+    #[must_use]
+    pub fn with_duration_buckets<B, I>(mut self, buckets: B) -> Self
+    where
+        B: IntoIterator<Item = I>,
+        I: Into<f64>,
+    {
+        self.duration_buckets = Some(buckets.into_iter().map(Into::into).collect());
+        self
+    }
+}
+
+/// Instrumentation scope name prefix for per-handler instruments.
+const HANDLER_SCOPE_PREFIX: &str = "uxum.handler.";
+
+/// Request duration histograms of handlers with overridden buckets.
+#[derive(Debug, Default)]
+struct HandlerDurations {
+    /// Bucket overrides, consulted by metric view when instrument is created.
+    buckets: DashMap<String, Vec<f64>>,
+    /// Histogram instruments, per handler.
+    histograms: DashMap<String, Histogram<f64>>,
+}
+
+/// Create request duration histogram instrument.
+fn request_duration_histogram(meter: &Meter) -> Histogram<f64> {
+    meter
+        .f64_histogram("http.server.request.duration")
+        .with_unit("s")
+        .with_description("The HTTP request latencies in seconds.")
+        .init()
+}
+
+/// Response extension to exclude a response from HTTP server request metrics.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Unmetered;
+
+/// Route label value used for requests not matched by any route.
+const UNMATCHED_ROUTE: &str = "<unmatched>";
+
+/// Client label value used for unauthenticated requests.
+const ANONYMOUS_CLIENT: &str = "<anonymous>";
+
+/// Label value used for values seen after cardinality limit was reached.
+const OTHER_VALUE: &str = "<other>";
+
+/// Bounded set of label values.
+///
+/// Used to protect metrics from cardinality explosions.
+#[derive(Clone, Debug)]
+pub(crate) struct LabelGuard(Arc<LabelGuardInner>);
+
+/// Inner container for [`LabelGuard`].
+#[derive(Debug)]
+struct LabelGuardInner {
+    /// Label name, used in logs.
+    name: &'static str,
+    /// Label value used when there is no value.
+    missing: &'static str,
+    /// Maximum length of label value.
+    max_length: usize,
+    /// Maximum number of distinct label values.
+    max_values: usize,
+    /// Label values seen so far.
+    seen: DashSet<String>,
+    /// Whether cardinality limit was reached.
+    tripped: AtomicBool,
+}
+
+impl LabelGuard {
+    /// Create new label guard.
+    fn new(
+        name: &'static str,
+        missing: &'static str,
+        max_length: usize,
+        max_values: usize,
+    ) -> Self {
+        Self(Arc::new(LabelGuardInner {
+            name,
+            missing,
+            max_length,
+            max_values,
+            seen: DashSet::new(),
+            tripped: AtomicBool::new(false),
+        }))
+    }
+
+    /// Get bounded label value.
+    pub(crate) fn label(&self, value: Option<&str>) -> String {
+        let Some(mut value) = value else {
+            return self.0.missing.into();
+        };
+        if value.len() > self.0.max_length {
+            let mut end = self.0.max_length;
+            while !value.is_char_boundary(end) {
+                end -= 1;
+            }
+            value = &value[..end];
+        }
+        if self.0.seen.contains(value) {
+            return value.into();
+        }
+        // Slight overshoot is possible under concurrent inserts, which is acceptable.
+        if self.0.seen.len() < self.0.max_values {
+            self.0.seen.insert(value.into());
+            return value.into();
+        }
+        if !self.0.tripped.swap(true, Ordering::Relaxed) {
+            warn!(
+                label = self.0.name,
+                limit = self.0.max_values,
+                "label cardinality limit reached, collapsing new values into {OTHER_VALUE}"
+            );
+        }
+        OTHER_VALUE.into()
+    }
+}
+
+/// Exported name of request duration histogram family, without prefix.
+const REQUEST_DURATION_FAMILY: &str = "http_server_request_duration_seconds";
+
+/// Latest exemplar recorded into a histogram bucket.
+#[derive(Clone, Copy, Debug)]
+struct Exemplar {
+    /// Trace ID of sampled request.
+    trace_id: TraceId,
+    /// Recorded value.
+    value: f64,
+    /// Time of recording.
+    timestamp: SystemTime,
+}
+
+/// Trace ID exemplars for a single histogram family.
+///
+/// Neither OpenTelemetry SDK nor Prometheus text encoder support exemplars, so latest sampled
+/// value for each bucket of each series is kept here, and added to OpenMetrics exposition.
+#[derive(Clone, Debug)]
+pub(crate) struct ExemplarStore(Arc<ExemplarStoreInner>);
+
+/// Inner container for [`ExemplarStore`].
+#[derive(Debug)]
+struct ExemplarStoreInner {
+    /// Exported family name, including prefix.
+    family: String,
+    /// Histogram bucket boundaries.
+    bounds: Vec<f64>,
+    /// Static labels added by Prometheus registry, not present at recording time.
+    static_labels: HashSet<String>,
+    /// Latest exemplars, per series and bucket.
+    series: DashMap<String, Box<[Option<Exemplar>]>>,
+}
+
+impl ExemplarStore {
+    /// Create new exemplar store.
+    fn new(family: String, bounds: &[f64], static_labels: HashSet<String>) -> Self {
+        Self(Arc::new(ExemplarStoreInner {
+            family,
+            bounds: bounds.to_vec(),
+            static_labels,
+            series: DashMap::new(),
+        }))
+    }
+
+    /// Build series key from exported label names and values.
+    fn series_key<'a>(&self, labels: impl Iterator<Item = (String, &'a str)>) -> String {
+        let mut labels: Vec<_> = labels
+            .filter(|(name, _)| {
+                !name.starts_with("otel_scope_") && !self.0.static_labels.contains(name)
+            })
+            .collect();
+        labels.sort_unstable();
+        labels
+            .into_iter()
+            .map(|(name, value)| format!("{name}={value:?}"))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Record exemplar, if current span is sampled.
+    ///
+    /// Bucket boundaries of the series are passed if they differ from family defaults.
+    fn record(&self, value: f64, labels: &[KeyValue], bounds: Option<&[f64]>) {
+        let span_context = Span::current().context().span().span_context().clone();
+        if !span_context.is_sampled() {
+            return;
+        }
+        let key = self.series_key(labels.iter().map(|kv| {
+            let value = match &kv.value {
+                Value::String(value) => value.as_str(),
+                Value::Bool(true) => "true",
+                Value::Bool(false) => "false",
+                _ => "",
+            };
+            (kv.key.as_str().replace('.', "_"), value)
+        }));
+        let bounds = bounds.unwrap_or(&self.0.bounds);
+        let bucket = bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(bounds.len());
+        let mut series = self
+            .0
+            .series
+            .entry(key)
+            .or_insert_with(|| vec![None; bounds.len() + 1].into());
+        series[bucket] = Some(Exemplar {
+            trace_id: span_context.trace_id(),
+            value,
+            timestamp: SystemTime::now(),
+        });
+    }
+
+    /// Append exemplars to bucket lines of encoded histogram family.
+    fn annotate(&self, family: &MetricFamily, encoded: &[u8]) -> Vec<u8> {
+        let text = String::from_utf8_lossy(encoded);
+        let bucket_prefix = format!("{}_bucket", self.0.family);
+        let count_prefix = format!("{}_count", self.0.family);
+        let mut metrics = family.get_metric().iter();
+        let mut series = None;
+        let mut bucket = 0;
+        let mut buf = Vec::with_capacity(encoded.len() * 2);
+        for line in text.lines() {
+            buf.extend_from_slice(line.as_bytes());
+            if line.starts_with(&bucket_prefix) {
+                if bucket == 0 {
+                    series = metrics.next().and_then(|metric| {
+                        let key = self.series_key(
+                            metric
+                                .get_label()
+                                .iter()
+                                .map(|l| (l.get_name().to_owned(), l.get_value())),
+                        );
+                        self.0.series.get(&key).map(|series| series.clone())
+                    });
+                }
+                if let Some(ex) = series
+                    .as_ref()
+                    .and_then(|s| s.get(bucket).copied().flatten())
+                {
+                    let timestamp = ex
+                        .timestamp
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs_f64();
+                    buf.extend_from_slice(
+                        format!(
+                            " # {{trace_id=\"{}\"}} {} {timestamp:.3}",
+                            ex.trace_id, ex.value
+                        )
+                        .as_bytes(),
+                    );
+                }
+                bucket += 1;
+            } else if line.starts_with(&count_prefix) {
+                bucket = 0;
+            }
+            buf.push(b'\n');
+        }
+        buf
+    }
 }
 
 /// Container for HTTP server metrics.
@@ -391,6 +1250,8 @@ pub struct MetricsState {
 #[non_exhaustive]
 pub(crate) struct HttpServerMetrics {
     /// Distribution of request handling durations.
+    ///
+    /// Measured until response headers are ready, i.e. this is the first byte latency.
     request_duration: Histogram<f64>,
     /// Lifetime counter of received requests.
     requests_total: Counter<u64>,
@@ -398,8 +1259,109 @@ pub(crate) struct HttpServerMetrics {
     requests_active: UpDownCounter<i64>,
     /// Distribution of request body sizes.
     request_body_size: Histogram<u64>,
+    /// Distribution of decompressed request body sizes.
+    ///
+    /// Only recorded for compressed requests.
+    request_decoded_body_size: Histogram<u64>,
     /// Distribution of response body sizes.
     response_body_size: Histogram<u64>,
+    /// Lifetime counter of requests rejected by IP filter.
+    ip_filter_rejections: Counter<u64>,
+    /// Lifetime counter of requests rejected by rate limiter.
+    rate_limit_rejections: Counter<u64>,
+    /// Lifetime counter of requests with transformed bodies.
+    requests_transformed: Counter<u64>,
+    /// Lifetime counter of responses violating declared schemas.
+    contract_violations: Counter<u64>,
+    /// Lifetime counter of responses skipped by contract validation.
+    validation_skipped: Counter<u64>,
+    /// Lifetime counter of requests to deprecated handlers.
+    deprecated_requests: Counter<u64>,
+    /// Lifetime counter of requests cancelled on timeout or client disconnect.
+    cancelled_requests: Counter<u64>,
+    /// Lifetime counter of untranslated error message fields.
+    missing_translations: Counter<u64>,
+    /// Fair queuing metrics.
+    fair_queue: FairQueueMetrics,
+    /// Handler circuit breaker metrics.
+    circuit_breaker: CircuitBreakerMetrics,
+    /// Distribution of time spent awaiting downstream dependencies.
+    dependency_duration: Histogram<f64>,
+    /// Lifetime counter of handler polls exceeding CPU stall threshold.
+    cpu_stalls: Counter<u64>,
+    /// Lifetime counter of restored resilience state entries.
+    state_restored: Counter<u64>,
+    /// Lifetime counter of discarded resilience state entries.
+    state_discarded: Counter<u64>,
+    /// Lifetime counter of bodies rejected due to exhausted memory budget.
+    memory_rejections: Counter<u64>,
+    /// Lifetime counter of redirects to canonical paths.
+    ///
+    /// These redirects are not included in request metrics.
+    trailing_slash_redirects: Counter<u64>,
+    /// Job queue metrics.
+    job_queue: JobQueueMetrics,
+    /// Post-response hook metrics.
+    response_hooks: ResponseHookMetrics,
+    /// Response timing breakdown, if enabled.
+    response_timing: Option<ResponseTimingMetrics>,
+}
+
+/// Container for handler circuit breaker metrics.
+#[derive(Clone, Debug)]
+pub(crate) struct CircuitBreakerMetrics {
+    /// Lifetime counter of observed state transitions.
+    pub(crate) transitions: Counter<u64>,
+    /// Lifetime counter of requests passed through half-open breakers.
+    pub(crate) probes: Counter<u64>,
+}
+
+/// Container for fair queuing metrics.
+#[derive(Clone, Debug)]
+pub(crate) struct FairQueueMetrics {
+    /// Distribution of queue wait durations.
+    pub(crate) wait_duration: Histogram<f64>,
+    /// Lifetime counter of dispatched requests.
+    pub(crate) dispatched: Counter<u64>,
+    /// Lifetime counter of dropped requests.
+    pub(crate) dropped: Counter<u64>,
+}
+
+/// Container for job queue metrics.
+#[derive(Clone, Debug)]
+pub(crate) struct JobQueueMetrics {
+    /// Lifetime counter of enqueued jobs.
+    pub(crate) enqueued: Counter<u64>,
+    /// Lifetime counter of successfully processed jobs.
+    pub(crate) processed: Counter<u64>,
+    /// Lifetime counter of retried jobs.
+    pub(crate) retried: Counter<u64>,
+    /// Lifetime counter of dead-lettered jobs.
+    pub(crate) dead_lettered: Counter<u64>,
+    /// Distribution of job processing durations.
+    pub(crate) duration: Histogram<f64>,
+}
+
+/// Container for post-response hook metrics.
+#[derive(Clone, Debug)]
+pub(crate) struct ResponseHookMetrics {
+    /// Lifetime counter of scheduled hooks.
+    pub(crate) scheduled: Counter<u64>,
+    /// Lifetime counter of successfully completed hooks.
+    pub(crate) completed: Counter<u64>,
+    /// Lifetime counter of failed hooks.
+    pub(crate) failed: Counter<u64>,
+    /// Lifetime counter of timed out hooks.
+    pub(crate) timed_out: Counter<u64>,
+}
+
+/// Container for response timing breakdown metrics.
+#[derive(Clone, Debug)]
+pub(crate) struct ResponseTimingMetrics {
+    /// Distribution of response body serialization durations.
+    serialization_duration: Histogram<f64>,
+    /// Distribution of durations until full response body is sent.
+    completion_duration: Histogram<f64>,
 }
 
 /// Shared container for HTTP client metrics
@@ -429,6 +1391,89 @@ pub struct HttpClientMetricsInner {
     pub request_body_size: Histogram<u64>,
     /// Distribution of response body sizes.
     pub response_body_size: Histogram<u64>,
+    /// Lifetime counter of egress policy violations.
+    pub egress_violations: Counter<u64>,
+}
+
+/// Prometheus exposition size limits.
+#[derive(Clone, Debug)]
+struct ExpositionPolicy {
+    /// Maximum size of exposition, in bytes.
+    max_bytes: Option<usize>,
+    /// Low-priority metric families, in order of dropping.
+    drop_families: Arc<[String]>,
+    /// Lifetime counter of dropped metric families.
+    dropped: Counter<u64>,
+}
+
+impl ExpositionPolicy {
+    /// Encode metric families, dropping low-priority ones to fit size limit.
+    fn limit(
+        &self,
+        families: &[MetricFamily],
+        max_bytes: usize,
+        exemplars: Option<&ExemplarStore>,
+    ) -> Result<Vec<Bytes>, MetricsError> {
+        let mut chunks = families
+            .iter()
+            .map(|fam| Ok((fam.get_name(), encode_family(fam, exemplars)?)))
+            .collect::<Result<Vec<_>, MetricsError>>()?;
+        let mut total: usize = chunks.iter().map(|(_, chunk)| chunk.len()).sum();
+        for name in self.drop_families.iter() {
+            if total <= max_bytes {
+                break;
+            }
+            if let Some(idx) = chunks.iter().position(|(fam, _)| fam == name) {
+                let (_, chunk) = chunks.remove(idx);
+                total -= chunk.len();
+                self.dropped
+                    .add(1, &[KeyValue::new("family", name.clone())]);
+                debug!(family = name, "metric family dropped from exposition");
+            }
+        }
+        if total > max_bytes {
+            warn!(total, max_bytes, "metrics exposition exceeds size limit");
+        }
+        Ok(chunks.into_iter().map(|(_, chunk)| chunk).collect())
+    }
+}
+
+/// Encode single metric family in Prometheus text format.
+///
+/// If exemplar store is passed, its exemplars are added to matching family.
+fn encode_family(
+    family: &MetricFamily,
+    exemplars: Option<&ExemplarStore>,
+) -> Result<Bytes, MetricsError> {
+    let mut buf = Vec::new();
+    TextEncoder::new().encode(std::slice::from_ref(family), &mut buf)?;
+    if let Some(exemplars) = exemplars.filter(|ex| ex.0.family == family.get_name()) {
+        buf = exemplars.annotate(family, &buf);
+    }
+    Ok(buf.into())
+}
+
+/// Whether scraper accepts OpenMetrics text format.
+fn accepts_openmetrics(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|val| val.to_str().ok())
+        .any(|val| val.contains("application/openmetrics-text"))
+}
+
+/// Parse `families` query parameters, each containing comma-separated family names.
+fn family_filter(query: &str) -> HashSet<String> {
+    form_urlencoded::parse(query.as_bytes())
+        .filter(|(key, _)| key == "families")
+        .flat_map(|(_, val)| {
+            val.split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(ToOwned::to_owned)
+                .collect::<Vec<_>>()
+        })
+        .collect()
 }
 
 /// Container for Tokio runtime metrics.
@@ -459,11 +1504,72 @@ impl<S> Layer<S> for MetricsState {
 
 impl MetricsState {
     /// Build Axum router containing all metrics methods.
-    pub fn build_router(&self) -> Router {
+    ///
+    /// Rejected scrapes are not counted in HTTP server request metrics.
+    pub fn build_router<AuthProv, AuthExt>(
+        &self,
+        auth_provider: AuthProv,
+        auth_extractor: AuthExt,
+    ) -> Router
+    where
+        AuthProv: AuthProvider + Sync + 'static,
+        AuthExt: AuthExtractor + Sync + 'static,
+        AuthExt::User: Borrow<AuthProv::User>,
+        AuthExt::AuthTokens: Borrow<AuthProv::AuthTokens>,
+    {
         let _span = debug_span!("build_metrics_router").entered();
-        Router::new()
-            .route(&self.metrics_path, routing::get(get_metrics))
-            .with_state(self.clone())
+        let mut rtr = Router::new().route(&self.metrics_path, routing::get(get_metrics));
+        if let Some(permissions) = self.permissions {
+            rtr = rtr.layer(
+                ServiceBuilder::new()
+                    .layer(HandleErrorLayer::new(error_handler))
+                    .layer(AuthLayer::new(permissions, auth_provider, auth_extractor)),
+            );
+        }
+        if let Some(ip_filter) = &self.ip_filter {
+            rtr = rtr.layer(
+                ServiceBuilder::new()
+                    .layer(HandleErrorLayer::new(error_handler))
+                    .layer(ip_filter.make_layer("", None)),
+            );
+        }
+        rtr.layer(map_response(|mut resp: Response| async move {
+            if matches!(
+                resp.status(),
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::NOT_FOUND
+            ) {
+                resp.extensions_mut().insert(Unmetered);
+            }
+            resp
+        }))
+        .with_state(self.clone())
+    }
+
+    /// Apply per-handler metrics configuration.
+    pub(crate) fn register_handler(&self, name: &str, config: &HandlerMetricsConfig) {
+        let Some(buckets) = &config.duration_buckets else {
+            return;
+        };
+        if self.handler_durations.histograms.contains_key(name) {
+            return;
+        }
+        // Bucket override must be in place before instrument is created.
+        self.handler_durations
+            .buckets
+            .insert(name.into(), buckets.clone());
+        let meter = self.provider.meter(format!("{HANDLER_SCOPE_PREFIX}{name}"));
+        self.handler_durations
+            .histograms
+            .insert(name.into(), request_duration_histogram(&meter));
+    }
+
+    /// Get meter for application-defined instruments, such as connection pool gauges.
+    ///
+    /// Instruments created with this meter are always exported by this state's Prometheus
+    /// endpoint, regardless of whether global meter provider was set up before they were created.
+    #[must_use]
+    pub fn meter(&self, name: &'static str) -> Meter {
+        self.provider.meter(name)
     }
 
     /// Get HTTP client metrics state object.
@@ -474,6 +1580,111 @@ impl MetricsState {
             metrics: self.http_client.clone(),
         }
     }
+
+    /// Get counter of requests rejected by IP filter.
+    #[must_use]
+    pub(crate) fn ip_filter_rejections(&self) -> Counter<u64> {
+        self.http_server.ip_filter_rejections.clone()
+    }
+
+    /// Get counter of requests rejected by rate limiter.
+    #[must_use]
+    pub(crate) fn rate_limit_rejections(&self) -> Counter<u64> {
+        self.http_server.rate_limit_rejections.clone()
+    }
+
+    /// Get counter of requests to deprecated handlers, along with client label guard.
+    #[must_use]
+    pub(crate) fn deprecated_requests(&self) -> (Counter<u64>, LabelGuard) {
+        (
+            self.http_server.deprecated_requests.clone(),
+            self.clients.clone(),
+        )
+    }
+
+    /// Get counter of cancelled requests.
+    #[must_use]
+    pub(crate) fn cancelled_requests(&self) -> Counter<u64> {
+        self.http_server.cancelled_requests.clone()
+    }
+
+    /// Get handler circuit breaker metrics.
+    #[must_use]
+    pub(crate) fn circuit_breaker(&self) -> CircuitBreakerMetrics {
+        self.http_server.circuit_breaker.clone()
+    }
+
+    /// Get fair queuing metrics.
+    #[must_use]
+    pub(crate) fn fair_queue(&self) -> FairQueueMetrics {
+        self.http_server.fair_queue.clone()
+    }
+
+    /// Get histogram of time spent awaiting downstream dependencies.
+    #[must_use]
+    pub(crate) fn dependency_duration(&self) -> Histogram<f64> {
+        self.http_server.dependency_duration.clone()
+    }
+
+    /// Get counter of detected CPU stalls in handlers.
+    #[must_use]
+    pub(crate) fn cpu_stalls(&self) -> Counter<u64> {
+        self.http_server.cpu_stalls.clone()
+    }
+
+    /// Get counters of restored and discarded resilience state entries.
+    #[must_use]
+    pub(crate) fn state_persistence(&self) -> (Counter<u64>, Counter<u64>) {
+        (
+            self.http_server.state_restored.clone(),
+            self.http_server.state_discarded.clone(),
+        )
+    }
+
+    /// Get counter of bodies rejected due to exhausted memory budget.
+    #[must_use]
+    pub(crate) fn memory_rejections(&self) -> Counter<u64> {
+        self.http_server.memory_rejections.clone()
+    }
+
+    /// Get counter of redirects to canonical paths.
+    #[must_use]
+    pub(crate) fn trailing_slash_redirects(&self) -> Counter<u64> {
+        self.http_server.trailing_slash_redirects.clone()
+    }
+
+    /// Get job queue metrics.
+    #[must_use]
+    pub(crate) fn job_queue(&self) -> JobQueueMetrics {
+        self.http_server.job_queue.clone()
+    }
+
+    /// Get post-response hook metrics.
+    #[must_use]
+    pub(crate) fn response_hooks(&self) -> ResponseHookMetrics {
+        self.http_server.response_hooks.clone()
+    }
+
+    /// Get counter of untranslated error message fields.
+    #[must_use]
+    pub(crate) fn missing_translations(&self) -> Counter<u64> {
+        self.http_server.missing_translations.clone()
+    }
+
+    /// Get counter of requests with transformed bodies.
+    #[must_use]
+    pub(crate) fn requests_transformed(&self) -> Counter<u64> {
+        self.http_server.requests_transformed.clone()
+    }
+
+    /// Get counters of contract violations and responses skipped by contract validation.
+    #[must_use]
+    pub(crate) fn response_validation(&self) -> (Counter<u64>, Counter<u64>) {
+        (
+            self.http_server.contract_violations.clone(),
+            self.http_server.validation_skipped.clone(),
+        )
+    }
 }
 
 /// HTTP client metrics state object.
@@ -516,7 +1727,7 @@ where
     T: HttpBody,
     U: HttpBody,
 {
-    type Response = S::Response;
+    type Response = Response<HttpMetricsBody<U>>;
     type Error = S::Error;
     type Future = HttpMetricsFuture<S::Future>;
 
@@ -527,28 +1738,54 @@ where
     fn call(&mut self, req: Request<T>) -> Self::Future {
         let start = Instant::now();
         let ext = req.extensions();
-        let method = req.method().clone();
-        // TODO: fix once https://github.com/tokio-rs/axum/issues/2504 is released.
-        let scheme = match req.uri().scheme() {
-            Some(sch) => sch.to_string(),
-            None => String::new(),
-        };
-        let path = ext.get::<MatchedPath>().cloned();
+        let matched = ext.get::<MatchedPath>().map(MatchedPath::as_str);
+        if !self.state.excluded_routes.is_empty()
+            && self
+                .state
+                .excluded_routes
+                .contains(matched.unwrap_or_else(|| req.uri().path()))
+        {
+            return HttpMetricsFuture {
+                inner: self.inner.call(req),
+                state: self.state.clone(),
+                start,
+                metered: false,
+                common_labels: Vec::new(),
+                route: String::new(),
+                batched: false,
+                warmup: false,
+                request_size: 0,
+            };
+        }
+        let mut common_labels = vec![KeyValue::new(
+            "http.request.method",
+            req.method().to_string(),
+        )];
+        if self.state.scheme_label {
+            // TODO: fix once https://github.com/tokio-rs/axum/issues/2504 is released.
+            let scheme = match req.uri().scheme() {
+                Some(sch) => sch.to_string(),
+                None => String::new(),
+            };
+            common_labels.push(KeyValue::new("url.scheme", scheme));
+        }
+        let route = self.state.routes.label(matched);
+        let batched = ext.get::<BatchedRequest>().is_some();
+        let warmup = ext.get::<Warmup>().is_some();
         let request_size = req.size_hint().upper().unwrap_or_default();
-        self.state.http_server.requests_active.add(
-            1,
-            &[
-                KeyValue::new("http.request.method", method.to_string()),
-                KeyValue::new("url.scheme", scheme.clone()),
-            ],
-        );
+        self.state
+            .http_server
+            .requests_active
+            .add(1, &common_labels);
         HttpMetricsFuture {
             inner: self.inner.call(req),
             state: self.state.clone(),
             start,
-            method,
-            scheme,
-            path,
+            metered: true,
+            common_labels,
+            route,
+            batched,
+            warmup,
             request_size,
         }
     }
@@ -565,12 +1802,17 @@ pub struct HttpMetricsFuture<F> {
     state: MetricsState,
     /// Request processing beginning timestamp.
     start: Instant,
-    /// HTTP request method.
-    method: Method,
-    /// HTTP URI scheme.
-    scheme: String,
-    /// Matched [`axum`] route.
-    path: Option<MatchedPath>,
+    /// Whether request is recorded in metrics, i.e. its route is not excluded.
+    metered: bool,
+    /// Labels shared with active requests metric: HTTP request method and, optionally, URI
+    /// scheme.
+    common_labels: Vec<KeyValue>,
+    /// Route label value, derived from matched [`axum`] route template.
+    route: String,
+    /// Whether request was dispatched from a batch.
+    batched: bool,
+    /// Whether request is a synthetic warmup request.
+    warmup: bool,
     /// HTTP request size, in bytes.
     request_size: u64,
 }
@@ -580,62 +1822,244 @@ where
     F: Future<Output = Result<Response<U>, E>>,
     U: HttpBody,
 {
-    type Output = F::Output;
+    type Output = Result<Response<HttpMetricsBody<U>>, E>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.project();
         let resp_result = ready!(this.inner.poll(cx));
 
-        let kv_method = KeyValue::new("http.request.method", this.method.to_string());
-        let kv_scheme = KeyValue::new("url.scheme", this.scheme.clone());
-        this.state
-            .http_server
-            .requests_active
-            .add(-1, &[kv_method.clone(), kv_scheme.clone()]);
+        if *this.metered {
+            this.state
+                .http_server
+                .requests_active
+                .add(-1, this.common_labels);
+        }
 
         let resp = resp_result?;
+        if !*this.metered || resp.extensions().get::<Unmetered>().is_some() {
+            let (parts, body) = resp.into_parts();
+            return Poll::Ready(Ok(Response::from_parts(
+                parts,
+                HttpMetricsBody {
+                    inner: body,
+                    timer: None,
+                    size: None,
+                },
+            )));
+        }
         let handler = resp.extensions().get::<HandlerName>();
         let duration = this.start.elapsed().as_secs_f64();
-        let status = resp.status().as_str().to_owned();
-        let response_size = resp.size_hint().upper().unwrap_or(0);
+        let status = if this.state.status_code_classes {
+            format!("{}xx", resp.status().as_u16() / 100)
+        } else {
+            resp.status().as_str().to_owned()
+        };
+        let response_size = resp.size_hint().exact();
 
-        let labels = [
-            kv_method,
-            kv_scheme,
+        let mut labels = std::mem::take(this.common_labels);
+        labels.extend([
             KeyValue::new("http.response.status_code", status),
-            KeyValue::new(
-                "http.route",
-                this.path.as_ref().map_or(Cow::Borrowed(""), |path| {
-                    Cow::Owned(path.as_str().to_owned())
-                }),
-            ),
+            KeyValue::new("http.route", this.route.clone()),
             KeyValue::new("uxum.handler", handler.map_or("", |hdl| hdl.as_str())),
-        ];
+        ]);
+        if *this.batched {
+            labels.push(KeyValue::new("uxum.batched", true));
+        }
+        if *this.warmup {
+            labels.push(KeyValue::new("uxum.warmup", true));
+        }
         // server.address?
         // server.port?
         // network.protocol.name?
         // network.protocol.version?
         this.state.http_server.requests_total.add(1, &labels);
-        this.state
-            .http_server
-            .request_duration
-            .record(duration, &labels);
+        match handler.and_then(|hdl| this.state.handler_durations.histograms.get(hdl.as_str())) {
+            Some(histogram) => histogram.record(duration, &labels),
+            None => this
+                .state
+                .http_server
+                .request_duration
+                .record(duration, &labels),
+        }
+        if let Some(exemplars) = &this.state.exemplars {
+            let bounds =
+                handler.and_then(|hdl| this.state.handler_durations.buckets.get(hdl.as_str()));
+            exemplars.record(duration, &labels, bounds.as_deref().map(Vec::as_slice));
+        }
         this.state
             .http_server
             .request_body_size
             .record(*this.request_size, &labels);
-        this.state
+        if let Some(decoded) = resp.extensions().get::<DecodedBodySize>() {
+            this.state
+                .http_server
+                .request_decoded_body_size
+                .record(decoded.0, &labels);
+        }
+        // Size of streamed or compressed bodies is only known once they are sent.
+        let size = match response_size {
+            Some(size) => {
+                this.state
+                    .http_server
+                    .response_body_size
+                    .record(size, &labels);
+                None
+            }
+            None => Some(SizeCounter {
+                histogram: this.state.http_server.response_body_size.clone(),
+                labels: labels.clone(),
+                bytes: 0,
+            }),
+        };
+        let (parts, body) = resp.into_parts();
+        let timer = this
+            .state
             .http_server
-            .response_body_size
-            .record(response_size, &labels);
+            .response_timing
+            .as_ref()
+            .map(|metrics| {
+                let span = Span::current();
+                span.record("uxum.response.first_byte_time", duration);
+                if let Some(SerializationTime(ser)) = parts.extensions.get() {
+                    let ser = ser.as_secs_f64();
+                    metrics.serialization_duration.record(ser, &labels);
+                    span.record("uxum.response.serialization_time", ser);
+                }
+                CompletionTimer {
+                    histogram: metrics.completion_duration.clone(),
+                    labels,
+                    start: *this.start,
+                    span,
+                }
+            });
         trace!("metrics recorded");
 
-        Poll::Ready(Ok(resp))
+        Poll::Ready(Ok(Response::from_parts(
+            parts,
+            HttpMetricsBody {
+                inner: body,
+                timer,
+                size,
+            },
+        )))
+    }
+}
+
+/// Pending measurement of full response completion time.
+struct CompletionTimer {
+    /// Histogram to record duration into.
+    histogram: Histogram<f64>,
+    /// Metric labels.
+    labels: Vec<KeyValue>,
+    /// Request processing beginning timestamp.
+    start: Instant,
+    /// Request span.
+    span: Span,
+}
+
+impl CompletionTimer {
+    /// Record completion duration.
+    fn finish(self) {
+        let duration = self.start.elapsed().as_secs_f64();
+        self.histogram.record(duration, &self.labels);
+        self.span.record("uxum.response.completion_time", duration);
+    }
+}
+
+/// Pending measurement of response body size, for bodies of unknown size.
+struct SizeCounter {
+    /// Histogram to record size into.
+    histogram: Histogram<u64>,
+    /// Metric labels.
+    labels: Vec<KeyValue>,
+    /// Bytes sent so far.
+    bytes: u64,
+}
+
+impl SizeCounter {
+    /// Record body size.
+    fn finish(self) {
+        self.histogram.record(self.bytes, &self.labels);
+    }
+}
+
+/// Response body wrapper for [`HttpMetrics`] middleware.
+///
+/// Records full response completion time, if response timing breakdown is enabled, and size of
+/// bodies not known in advance.
+#[pin_project(PinnedDrop)]
+pub struct HttpMetricsBody<B> {
+    /// Inner body.
+    #[pin]
+    inner: B,
+    /// Completion timer, taken when body is finished or dropped.
+    timer: Option<CompletionTimer>,
+    /// Body size counter, taken when body is finished or dropped.
+    size: Option<SizeCounter>,
+}
+
+impl<B> HttpBody for HttpMetricsBody<B>
+where
+    B: HttpBody,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        let frame = ready!(this.inner.as_mut().poll_frame(cx));
+        if let (Some(size), Some(Ok(frame))) = (this.size.as_mut(), &frame) {
+            if let Some(data) = frame.data_ref() {
+                size.bytes += data.remaining() as u64;
+            }
+        }
+        if frame.is_none() || this.inner.is_end_stream() {
+            if let Some(timer) = this.timer.take() {
+                timer.finish();
+            }
+            if let Some(size) = this.size.take() {
+                size.finish();
+            }
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[pinned_drop]
+impl<B> PinnedDrop for HttpMetricsBody<B> {
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+        if let Some(timer) = this.timer.take() {
+            timer.finish();
+        }
+        if let Some(size) = this.size.take() {
+            size.finish();
+        }
     }
 }
 
 /// Method handler to generate metrics
-async fn get_metrics(metrics: State<MetricsState>) -> Result<impl IntoResponse, MetricsError> {
+///
+/// Metric families are serialized incrementally while response body is being sent, unless
+/// exposition size limit is set. Optional `families` query parameter restricts output to
+/// specific metric families. If exemplars are enabled and scraper accepts OpenMetrics, output
+/// is produced in that format, with exemplars included.
+async fn get_metrics(
+    metrics: State<MetricsState>,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, MetricsError> {
     // Record runtime metrics just-in-time
     let rt_metrics = tokio::runtime::Handle::current().metrics();
     metrics
@@ -646,10 +2070,635 @@ async fn get_metrics(metrics: State<MetricsState>) -> Result<impl IntoResponse,
         .runtime
         .num_alive_tasks
         .observe(rt_metrics.num_alive_tasks() as u64, &[]);
+    if let Some(ratio) = crate::tracing::sampling::current_ratio() {
+        metrics.sampling_ratio.observe(ratio, &[]);
+    }
+    let budget = crate::memory::global();
+    metrics.memory_usage.observe(budget.usage() as u64, &[]);
+    metrics
+        .memory_high_water
+        .observe(budget.high_water() as u64, &[]);
 
     // Serialize metrics
-    let encoder = TextEncoder::new();
-    let mut buf = Vec::new();
-    encoder.encode(&metrics.registry.gather(), &mut buf)?;
-    Ok(([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], buf))
+    let mut families = metrics.registry.gather();
+    if let Some(filter) = query.as_deref().map(family_filter) {
+        if !filter.is_empty() {
+            families.retain(|fam| filter.contains(fam.get_name()));
+        }
+    }
+    let exemplars = metrics
+        .exemplars
+        .clone()
+        .filter(|_| accepts_openmetrics(&headers));
+    let (content_type, eof) = match exemplars {
+        Some(_) => (
+            OPENMETRICS_CONTENT_TYPE,
+            Some(Bytes::from_static(b"# EOF\n")),
+        ),
+        None => (TEXT_CONTENT_TYPE, None),
+    };
+    let body = match metrics.exposition.max_bytes {
+        Some(max_bytes) => {
+            let mut chunks = metrics
+                .exposition
+                .limit(&families, max_bytes, exemplars.as_ref())?;
+            chunks.extend(eof);
+            Body::from_stream(stream::iter(chunks).map(Ok::<_, Infallible>))
+        }
+        None => Body::from_stream(
+            stream::iter(families)
+                .map(move |fam| encode_family(&fam, exemplars.as_ref()))
+                .chain(stream::iter(eof.map(Ok))),
+        ),
+    };
+    Ok(([(header::CONTENT_TYPE, content_type)], body))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::body::Body;
+    use serde::Serializer;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        auth::{NoOpAuthExtractor, NoOpAuthProvider},
+        Json,
+    };
+
+    /// Payload which takes a long time to serialize.
+    struct SlowPayload;
+
+    impl Serialize for SlowPayload {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            std::thread::sleep(Duration::from_millis(100));
+            serializer.serialize_str("slow")
+        }
+    }
+
+    fn histogram_sum(state: &MetricsState, name: &str, route: &str) -> f64 {
+        state
+            .registry
+            .gather()
+            .iter()
+            .filter(|fam| fam.get_name() == name)
+            .flat_map(|fam| fam.get_metric())
+            .filter(|m| {
+                m.get_label()
+                    .iter()
+                    .any(|l| l.get_name() == "http_route" && l.get_value() == route)
+            })
+            .map(|m| m.get_histogram().get_sample_sum())
+            .sum()
+    }
+
+    /// Serialization time is attributed separately from handler execution time.
+    #[tokio::test]
+    async fn serialization_attribution() {
+        let state = MetricsBuilder::default()
+            .with_response_timing(true)
+            .build_state(Resource::empty())
+            .unwrap();
+        let app = Router::new()
+            .route("/slow_ser", routing::get(|| async { Json(SlowPayload) }))
+            .route(
+                "/slow_handler",
+                routing::get(|| async {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    Json("fast")
+                }),
+            )
+            .layer(state.clone());
+        for path in ["/slow_ser", "/slow_handler"] {
+            let resp = app
+                .clone()
+                .oneshot(Request::get(path).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+            axum::body::to_bytes(resp.into_body(), usize::MAX)
+                .await
+                .unwrap();
+        }
+
+        let ser = "http_server_response_serialization_duration_seconds";
+        let done = "http_server_response_completion_duration_seconds";
+        assert!(histogram_sum(&state, ser, "/slow_ser") >= 0.1);
+        assert!(histogram_sum(&state, ser, "/slow_handler") < 0.05);
+        assert!(histogram_sum(&state, done, "/slow_ser") >= 0.1);
+        assert!(histogram_sum(&state, done, "/slow_handler") >= 0.1);
+    }
+
+    fn route_labels(state: &MetricsState) -> Vec<String> {
+        let mut routes: Vec<_> = state
+            .registry
+            .gather()
+            .iter()
+            .filter(|fam| fam.get_name() == "http_server_requests_total")
+            .flat_map(|fam| fam.get_metric())
+            .flat_map(|m| m.get_label())
+            .filter(|l| l.get_name() == "http_route")
+            .map(|l| l.get_value().to_owned())
+            .collect();
+        routes.sort();
+        routes.dedup();
+        routes
+    }
+
+    /// Wildcard routes use route template, unmatched requests use a constant label.
+    #[tokio::test]
+    async fn route_label_template() {
+        let state = MetricsBuilder::default()
+            .build_state(Resource::empty())
+            .unwrap();
+        let app = Router::new()
+            .route("/files/*path", routing::get(|| async { "file" }))
+            .fallback(|| async { StatusCode::NOT_FOUND })
+            .layer(state.clone());
+        for path in ["/files/a/b/c", "/files/d", "/nothing/here", "/other"] {
+            app.clone()
+                .oneshot(Request::get(path).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+        }
+        assert_eq!(route_labels(&state), ["/files/*path", UNMATCHED_ROUTE]);
+    }
+
+    /// Compressed responses are measured by on-wire size.
+    #[tokio::test]
+    async fn compressed_body_size() {
+        use crate::layers::compression::CompressionConfig;
+
+        let state = MetricsBuilder::default()
+            .build_state(Resource::empty())
+            .unwrap();
+        let app = Router::new()
+            .route(
+                "/text",
+                routing::get(|| async { "compress me ".repeat(100) }),
+            )
+            .layer(
+                CompressionConfig::default()
+                    .with_enabled(true)
+                    .make_layer()
+                    .unwrap(),
+            )
+            .layer(state.clone());
+        let resp = app
+            .oneshot(
+                Request::get("/text")
+                    .header(header::ACCEPT_ENCODING, "gzip")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.headers()[header::CONTENT_ENCODING], "gzip");
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.len() < 1200);
+        let size = histogram_sum(&state, "http_server_response_body_size_bytes", "/text");
+        assert_eq!(size, body.len() as f64);
+    }
+
+    /// Decoded size of compressed requests is recorded separately from wire size.
+    #[tokio::test]
+    async fn decoded_request_body_size() {
+        use std::io::Write;
+
+        use flate2::{write::GzEncoder, Compression};
+
+        use crate::layers::decompression::RequestDecompressionLayer;
+
+        let state = MetricsBuilder::default()
+            .build_state(Resource::empty())
+            .unwrap();
+        let app = Router::new()
+            .route("/echo", routing::post(|body: String| async move { body }))
+            .layer(RequestDecompressionLayer::new(1024 * 1024))
+            .layer(state.clone());
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[b'x'; 1000]).unwrap();
+        let body = encoder.finish().unwrap();
+        let wire_size = body.len();
+        let resp = app
+            .oneshot(
+                Request::post("/echo")
+                    .header(header::CONTENT_ENCODING, "gzip")
+                    .header(header::CONTENT_LENGTH, wire_size)
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let wire = histogram_sum(&state, "http_server_request_body_size_bytes", "/echo");
+        let decoded = histogram_sum(
+            &state,
+            "http_server_request_decoded_body_size_bytes",
+            "/echo",
+        );
+        assert_eq!(wire, wire_size as f64);
+        assert_eq!(decoded, 1000.0);
+    }
+
+    /// Rejected scrapes get problem responses, and are not counted in request metrics.
+    #[tokio::test]
+    async fn protected_endpoint() {
+        use std::net::SocketAddr;
+
+        use axum::extract::ConnectInfo;
+
+        use crate::{
+            auth::{AuthConfig, BasicAuthExtractor, ConfigAuthProvider},
+            layers::client_ip::ClientIpConfig,
+        };
+
+        let auth: AuthConfig = serde_json::from_value(serde_json::json!({
+            "users": {
+                "scraper": {"password": "secret", "roles": ["monitoring"]},
+                "guest": {"password": "guest"},
+            },
+            "roles": {"monitoring": {"permissions": ["metrics"]}},
+        }))
+        .unwrap();
+        let state = MetricsBuilder::default()
+            .with_permissions(["metrics"])
+            .with_ip_filter(IpFilterConfig::default().with_allow("10.0.0.0/8".parse().unwrap()))
+            .build_state(Resource::empty())
+            .unwrap();
+        let app = state
+            .build_router(
+                ConfigAuthProvider::from(auth),
+                BasicAuthExtractor::default(),
+            )
+            .layer(state.clone());
+        let scrape = |peer: &str, credentials: Option<&str>| {
+            let mut req = Request::get("/metrics");
+            if let Some(credentials) = credentials {
+                req = req.header(header::AUTHORIZATION, format!("Basic {credentials}"));
+            }
+            let mut req = req.body(Body::empty()).unwrap();
+            let peer: SocketAddr = peer.parse().unwrap();
+            req.extensions_mut().insert(ConnectInfo(peer));
+            app.clone()
+                .oneshot(ClientIpConfig::default().resolve_incoming(req))
+        };
+
+        let resp = scrape("192.168.1.1:1234", Some("c2NyYXBlcjpzZWNyZXQ="))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            resp.headers()[header::CONTENT_TYPE],
+            "application/problem+json"
+        );
+        let resp = scrape("10.1.1.1:1234", None).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let resp = scrape("10.1.1.1:1234", Some("Z3Vlc3Q6Z3Vlc3Q="))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        assert!(route_labels(&state).is_empty());
+
+        let resp = scrape("10.1.1.1:1234", Some("c2NyYXBlcjpzZWNyZXQ="))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(route_labels(&state), ["/metrics"]);
+    }
+
+    /// Route labels are truncated, and new routes are collapsed past the limit.
+    #[test]
+    fn route_label_guard() {
+        let routes = LabelGuard::new("http.route", UNMATCHED_ROUTE, 8, 2);
+        let label = |path| routes.label(Some(path));
+        assert_eq!(label("/first"), "/first");
+        assert_eq!(label("/second/very/long"), "/second/");
+        assert_eq!(label("/third"), OTHER_VALUE);
+        assert_eq!(label("/first"), "/first");
+        assert_eq!(routes.label(None), UNMATCHED_ROUTE);
+    }
+
+    /// Prefix and static labels are applied to exported metrics, using the same config keys.
+    #[tokio::test]
+    async fn prefix_and_labels() {
+        let builder: MetricsBuilder = serde_json::from_value(serde_json::json!({
+            "prefix": "legacy",
+            "labels": {"env": "test", "dc": "east"},
+        }))
+        .unwrap();
+        let state = builder.build_state(Resource::empty()).unwrap();
+        let app = Router::new()
+            .route("/hello", routing::get(|| async { "hello" }))
+            .layer(state.clone());
+        app.oneshot(Request::get("/hello").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let families = state.registry.gather();
+        let names: Vec<_> = families.iter().map(|fam| fam.get_name()).collect();
+        for name in [
+            "legacy_http_server_requests_total",
+            "legacy_http_server_request_duration_seconds",
+            "legacy_http_server_active_requests",
+        ] {
+            assert!(names.contains(&name), "{name} not in {names:?}");
+        }
+        let requests = families
+            .iter()
+            .find(|fam| fam.get_name() == "legacy_http_server_requests_total")
+            .unwrap();
+        let labels: HashMap<_, _> = requests.get_metric()[0]
+            .get_label()
+            .iter()
+            .map(|l| (l.get_name(), l.get_value()))
+            .collect();
+        assert_eq!(labels["env"], "test");
+        assert_eq!(labels["dc"], "east");
+        assert_eq!(labels["http_route"], "/hello");
+    }
+
+    /// Register counter family with many label values.
+    fn synthetic_family(state: &MetricsState, name: &str, series: usize) {
+        let counter =
+            prometheus::IntCounterVec::new(prometheus::Opts::new(name, "Synthetic."), &["id"])
+                .unwrap();
+        for id in 0..series {
+            counter.with_label_values(&[&id.to_string()]).inc();
+        }
+        state.registry.register(Box::new(counter)).unwrap();
+    }
+
+    /// Scrape metrics endpoint, returning body and number of body frames.
+    async fn scrape(state: &MetricsState, query: &str) -> (String, usize) {
+        use http_body_util::BodyExt;
+
+        let resp = state
+            .build_router(NoOpAuthProvider, NoOpAuthExtractor)
+            .oneshot(
+                Request::get(format!("{}{query}", state.metrics_path))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let mut body = resp.into_body();
+        let (mut buf, mut frames) = (Vec::new(), 0);
+        while let Some(frame) = body.frame().await {
+            buf.extend_from_slice(frame.unwrap().data_ref().unwrap());
+            frames += 1;
+        }
+        (String::from_utf8(buf).unwrap(), frames)
+    }
+
+    /// Large exposition is streamed per family, low-priority families are dropped to fit limit.
+    #[tokio::test]
+    async fn exposition_limit() {
+        let state = MetricsBuilder::default()
+            .with_max_exposition_bytes(100_000)
+            .with_drop_family("not_registered")
+            .with_drop_family("low_priority_a")
+            .with_drop_family("low_priority_b")
+            .with_drop_family("low_priority_c")
+            .build_state(Resource::empty())
+            .unwrap();
+        synthetic_family(&state, "important", 1000);
+        synthetic_family(&state, "low_priority_a", 5000);
+        synthetic_family(&state, "low_priority_b", 5000);
+        synthetic_family(&state, "low_priority_c", 10);
+
+        let (body, frames) = scrape(&state, "").await;
+        assert!(frames > 1);
+        assert!(body.len() <= 100_000);
+        assert!(body.contains("\nimportant{id=\"999\"} 1\n"));
+        assert!(body.contains("\nlow_priority_c{id=\"9\"} 1\n"));
+        assert!(!body.contains("low_priority_a{"));
+        assert!(!body.contains("low_priority_b{"));
+
+        let (body, _) = scrape(&state, "").await;
+        let dropped: Vec<_> = body
+            .lines()
+            .filter(|line| line.starts_with("metrics_exposition_dropped_total{"))
+            .collect();
+        assert_eq!(dropped.len(), 2);
+        assert!(dropped
+            .iter()
+            .any(|line| line.contains("family=\"low_priority_a\"")));
+        assert!(dropped
+            .iter()
+            .any(|line| line.contains("family=\"low_priority_b\"")));
+
+        // Unlimited exposition is streamed as well.
+        let state = MetricsBuilder::default()
+            .build_state(Resource::empty())
+            .unwrap();
+        synthetic_family(&state, "low_priority_a", 5000);
+        synthetic_family(&state, "low_priority_b", 5000);
+        let (body, frames) = scrape(&state, "").await;
+        assert!(frames > 1);
+        assert!(body.contains("\nlow_priority_b{id=\"4999\"} 1\n"));
+    }
+
+    /// Exposition is restricted to requested metric families.
+    #[tokio::test]
+    async fn exposition_family_filter() {
+        let state = MetricsBuilder::default()
+            .build_state(Resource::empty())
+            .unwrap();
+        for name in ["first", "second", "third"] {
+            synthetic_family(&state, name, 3);
+        }
+        let (body, _) = scrape(&state, "?families=first,third&families=missing").await;
+        let families: Vec<_> = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("# TYPE "))
+            .collect();
+        assert_eq!(families, ["first counter", "third counter"]);
+    }
+
+    /// Trace IDs of sampled requests are exposed as exemplars to OpenMetrics scrapers.
+    #[tokio::test]
+    async fn request_duration_exemplars() {
+        use http_body_util::BodyExt;
+        use opentelemetry::trace::TracerProvider as _;
+        use opentelemetry_sdk::trace::TracerProvider;
+        use tracing::Instrument;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let provider = TracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let state = MetricsBuilder::default()
+            .with_exemplars(true)
+            .build_state(Resource::empty())
+            .unwrap();
+        let app = Router::new()
+            .route("/traced", routing::get(|| async { "traced" }))
+            .layer(state.clone());
+        let span = tracing::info_span!("request");
+        let trace_id = span.context().span().span_context().trace_id();
+        assert_ne!(trace_id, TraceId::INVALID);
+        app.oneshot(Request::get("/traced").body(Body::empty()).unwrap())
+            .instrument(span)
+            .await
+            .unwrap();
+
+        let exemplar = format!(" # {{trace_id=\"{trace_id}\"}} ");
+        let scrape = |accept: &'static str| {
+            let state = state.clone();
+            async move {
+                let resp = state
+                    .build_router(NoOpAuthProvider, NoOpAuthExtractor)
+                    .oneshot(
+                        Request::get("/metrics")
+                            .header(header::ACCEPT, accept)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let content_type = resp.headers()[header::CONTENT_TYPE].clone();
+                let body = resp.into_body().collect().await.unwrap().to_bytes();
+                (content_type, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        let (content_type, body) = scrape("application/openmetrics-text; version=1.0.0").await;
+        assert_eq!(content_type, OPENMETRICS_CONTENT_TYPE);
+        assert!(body.ends_with("# EOF\n"));
+        let annotated: Vec<_> = body
+            .lines()
+            .filter(|line| line.contains(&exemplar))
+            .collect();
+        assert!(!annotated.is_empty(), "{body}");
+        assert!(annotated.iter().all(|line| {
+            line.starts_with("http_server_request_duration_seconds_bucket{")
+                && line.contains("http_route=\"/traced\"")
+        }));
+
+        let (content_type, body) = scrape("text/plain").await;
+        assert_eq!(content_type, TEXT_CONTENT_TYPE);
+        assert!(!body.contains(&exemplar));
+    }
+
+    /// Status codes are collapsed into classes, scheme label is dropped, excluded routes are not
+    /// recorded at all.
+    #[tokio::test]
+    async fn reduced_label_cardinality() {
+        let state = MetricsBuilder::default()
+            .with_status_code_classes(true)
+            .with_scheme_label(false)
+            .with_excluded_route("/probe")
+            .build_state(Resource::empty())
+            .unwrap();
+        let app = Router::new()
+            .route("/created", routing::get(|| async { StatusCode::CREATED }))
+            .route("/ok", routing::get(|| async { "ok" }))
+            .route("/probe", routing::get(|| async { "alive" }))
+            .layer(state.clone());
+        for path in ["/created", "/ok", "/probe"] {
+            app.clone()
+                .oneshot(Request::get(path).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+        }
+
+        let families = state.registry.gather();
+        let requests = families
+            .iter()
+            .find(|fam| fam.get_name() == "http_server_requests_total")
+            .unwrap();
+        let mut statuses = Vec::new();
+        for metric in requests.get_metric() {
+            let labels: HashMap<_, _> = metric
+                .get_label()
+                .iter()
+                .map(|l| (l.get_name(), l.get_value()))
+                .collect();
+            assert!(!labels.contains_key("url_scheme"));
+            statuses.push(labels["http_response_status_code"]);
+        }
+        assert_eq!(statuses, ["2xx", "2xx"]);
+        assert_eq!(route_labels(&state), ["/created", "/ok"]);
+        let active = families
+            .iter()
+            .find(|fam| fam.get_name() == "http_server_active_requests")
+            .unwrap();
+        assert_eq!(active.get_metric().len(), 1);
+        assert!(active.get_metric()[0]
+            .get_label()
+            .iter()
+            .all(|l| l.get_name() != "url_scheme"));
+    }
+
+    /// Handler with overridden duration buckets is exported with its own bucket boundaries.
+    #[tokio::test]
+    async fn handler_duration_buckets() {
+        let state = MetricsBuilder::default()
+            .with_duration_buckets([0.01, 0.1])
+            .build_state(Resource::empty())
+            .unwrap();
+        state.register_handler(
+            "upload",
+            &HandlerMetricsConfig::default().with_duration_buckets([1.0, 60.0, 300.0]),
+        );
+        let app = Router::new()
+            .route(
+                "/upload",
+                routing::post(|| async { (axum::Extension(HandlerName::new("upload")), "ok") }),
+            )
+            .route(
+                "/hello",
+                routing::get(|| async { (axum::Extension(HandlerName::new("hello")), "ok") }),
+            )
+            .layer(state.clone());
+        for req in [Request::post("/upload"), Request::get("/hello")] {
+            app.clone()
+                .oneshot(req.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+        }
+
+        let bounds = |handler: &str| -> Vec<f64> {
+            state
+                .registry
+                .gather()
+                .iter()
+                .filter(|fam| fam.get_name() == REQUEST_DURATION_FAMILY)
+                .flat_map(|fam| fam.get_metric())
+                .filter(|m| {
+                    m.get_label()
+                        .iter()
+                        .any(|l| l.get_name() == "uxum_handler" && l.get_value() == handler)
+                })
+                .flat_map(|m| m.get_histogram().get_bucket())
+                .map(|b| b.get_upper_bound())
+                .collect()
+        };
+        assert_eq!(bounds("upload"), [1.0, 60.0, 300.0]);
+        assert_eq!(bounds("hello"), [0.01, 0.1]);
+    }
+
+    /// Instruments created with state meter are exported.
+    #[tokio::test]
+    async fn application_meter() {
+        let state = MetricsBuilder::default()
+            .build_state(Resource::empty())
+            .unwrap();
+        let in_use = state
+            .meter("pool")
+            .u64_counter("pool.connections.acquired")
+            .init();
+        in_use.add(3, &[KeyValue::new("pool.name", "redis")]);
+        let (body, _) = scrape(&state, "?families=pool_connections_acquired_total").await;
+        assert!(body.contains("pool_name=\"redis\""), "{body}");
+        assert!(body.contains("} 3\n"), "{body}");
+    }
 }
@@ -0,0 +1,518 @@
+//! Declarative normalization of deserialized request input.
+//!
+//! Values extracted with [`Normalized`] are normalized after deserialization, before the handler
+//! sees them. Rules are declared per field with `#[normalize(...)]` attributes of
+//! [`derive@Normalize`], or per handler with `normalize_strings` parameter of
+//! [`crate::handler`]. Field-level rules take precedence.
+//!
+//! Normalization happens after deserialization, so deserialization errors always reference
+//! positions in original input.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    hash::{BuildHasher, Hash},
+    ops::{Deref, DerefMut},
+    str::FromStr,
+};
+
+use axum::{
+    async_trait,
+    extract::{rejection::FormRejection, rejection::JsonRejection, FromRequest, Request},
+    Form,
+};
+use schemars::schema::SchemaObject;
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+
+pub use uxum_macros::Normalize;
+
+use crate::response::Json;
+
+/// Error returned when parsing normalization rules.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NormalizeError {
+    /// Unknown rule name.
+    #[error("Unknown normalization rule: {0}")]
+    UnknownRule(String),
+}
+
+/// Case folding applied to strings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CaseFold {
+    /// Convert to lower case.
+    Lower,
+    /// Convert to upper case.
+    Upper,
+}
+
+/// Set of normalization rules applied to string values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct NormalizeRules {
+    /// Remove leading and trailing whitespace.
+    pub trim: bool,
+    /// Fold case.
+    pub case: Option<CaseFold>,
+    /// Treat empty optional strings as missing.
+    ///
+    /// Applied after trimming, so whitespace-only strings are treated as missing too.
+    pub none_if_empty: bool,
+}
+
+impl NormalizeRules {
+    /// No normalization.
+    pub const NONE: Self = Self {
+        trim: false,
+        case: None,
+        none_if_empty: false,
+    };
+
+    /// Add whitespace trimming.
+    #[must_use]
+    pub const fn trim(mut self) -> Self {
+        self.trim = true;
+        self
+    }
+
+    /// Add lower case folding.
+    #[must_use]
+    pub const fn lowercase(mut self) -> Self {
+        self.case = Some(CaseFold::Lower);
+        self
+    }
+
+    /// Add upper case folding.
+    #[must_use]
+    pub const fn uppercase(mut self) -> Self {
+        self.case = Some(CaseFold::Upper);
+        self
+    }
+
+    /// Treat empty optional strings as missing.
+    #[must_use]
+    pub const fn none_if_empty(mut self) -> Self {
+        self.none_if_empty = true;
+        self
+    }
+
+    /// Check if no rules are set.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == Self::NONE
+    }
+
+    /// Get names of active rules, as used in attributes and OpenAPI specification.
+    #[must_use]
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        if self.trim {
+            names.push("trim");
+        }
+        match self.case {
+            Some(CaseFold::Lower) => names.push("lowercase"),
+            Some(CaseFold::Upper) => names.push("uppercase"),
+            None => {}
+        }
+        if self.none_if_empty {
+            names.push("none_if_empty");
+        }
+        names
+    }
+
+    /// Normalize string in place.
+    pub fn apply(&self, value: &mut String) {
+        if self.trim {
+            let trimmed = value.trim();
+            if trimmed.len() != value.len() {
+                *value = trimmed.to_string();
+            }
+        }
+        match self.case {
+            Some(CaseFold::Lower) => *value = value.to_lowercase(),
+            Some(CaseFold::Upper) => *value = value.to_uppercase(),
+            None => {}
+        }
+    }
+}
+
+impl FromStr for NormalizeRules {
+    type Err = NormalizeError;
+
+    /// Parse comma-separated list of rule names.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .try_fold(Self::NONE, |rules, name| match name {
+                "trim" => Ok(rules.trim()),
+                "lowercase" => Ok(rules.lowercase()),
+                "uppercase" => Ok(rules.uppercase()),
+                "none_if_empty" => Ok(rules.none_if_empty()),
+                other => Err(NormalizeError::UnknownRule(other.to_string())),
+            })
+    }
+}
+
+/// Normalization rules applied to a value in OpenAPI specification, keyed by field path.
+///
+/// Nested fields are separated with `.`, collection items are denoted with `[]`, and map values
+/// with `*`.
+pub type NormalizeSpec = BTreeMap<String, Vec<&'static str>>;
+
+/// Join field path segments.
+///
+/// Used in [`derive@Normalize`] macro expansion.
+#[doc(hidden)]
+#[must_use]
+pub fn normalize_field_path(prefix: &str, field: &str) -> String {
+    match prefix.is_empty() {
+        true => field.to_string(),
+        false => format!("{prefix}.{field}"),
+    }
+}
+
+/// Add `x-normalize` extension to request body schema.
+///
+/// Used in [`crate::handler`] macro expansion.
+#[doc(hidden)]
+pub fn annotate_normalize_schema<T: Normalize>(schema: &mut SchemaObject, rules: NormalizeRules) {
+    let mut spec = NormalizeSpec::new();
+    T::describe("", rules, &mut spec);
+    if !spec.is_empty() {
+        schema
+            .extensions
+            .insert("x-normalize".into(), serde_json::json!(spec));
+    }
+}
+
+/// Values which can be normalized after deserialization.
+///
+/// Usually implemented using [`derive@Normalize`].
+pub trait Normalize {
+    /// Normalize value in place, using rules inherited from enclosing value.
+    fn normalize(&mut self, rules: NormalizeRules);
+
+    /// Whether value is empty, and should be treated as missing if `none_if_empty` rule applies.
+    #[must_use]
+    fn is_empty_value(&self) -> bool {
+        false
+    }
+
+    /// Describe normalization rules applied to value and its nested fields.
+    fn describe(_path: &str, _rules: NormalizeRules, _spec: &mut NormalizeSpec)
+    where
+        Self: Sized,
+    {
+    }
+}
+
+impl Normalize for String {
+    fn normalize(&mut self, rules: NormalizeRules) {
+        rules.apply(self);
+    }
+
+    fn is_empty_value(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn describe(path: &str, rules: NormalizeRules, spec: &mut NormalizeSpec) {
+        if !rules.is_empty() {
+            spec.insert(path.to_string(), rules.names());
+        }
+    }
+}
+
+impl<T: Normalize> Normalize for Option<T> {
+    fn normalize(&mut self, rules: NormalizeRules) {
+        if let Some(inner) = self {
+            inner.normalize(rules);
+            if rules.none_if_empty && inner.is_empty_value() {
+                *self = None;
+            }
+        }
+    }
+
+    fn describe(path: &str, rules: NormalizeRules, spec: &mut NormalizeSpec) {
+        T::describe(path, rules, spec);
+    }
+}
+
+impl<T: Normalize> Normalize for Box<T> {
+    fn normalize(&mut self, rules: NormalizeRules) {
+        self.as_mut().normalize(rules);
+    }
+
+    fn is_empty_value(&self) -> bool {
+        self.as_ref().is_empty_value()
+    }
+
+    fn describe(path: &str, rules: NormalizeRules, spec: &mut NormalizeSpec) {
+        T::describe(path, rules, spec);
+    }
+}
+
+/// Implement [`Normalize`] for sequence types.
+macro_rules! impl_normalize_seq {
+    ($($ty:ident),+) => {
+        $(
+            impl<T: Normalize> Normalize for $ty<T> {
+                fn normalize(&mut self, rules: NormalizeRules) {
+                    self.iter_mut().for_each(|item| item.normalize(rules));
+                }
+
+                fn describe(path: &str, rules: NormalizeRules, spec: &mut NormalizeSpec) {
+                    T::describe(&format!("{path}[]"), rules, spec);
+                }
+            }
+        )+
+    };
+}
+
+impl_normalize_seq!(Vec, VecDeque);
+
+impl<K: Ord, V: Normalize> Normalize for BTreeMap<K, V> {
+    fn normalize(&mut self, rules: NormalizeRules) {
+        self.values_mut().for_each(|val| val.normalize(rules));
+    }
+
+    fn describe(path: &str, rules: NormalizeRules, spec: &mut NormalizeSpec) {
+        V::describe(&normalize_field_path(path, "*"), rules, spec);
+    }
+}
+
+impl<K: Eq + Hash, V: Normalize, S: BuildHasher> Normalize for HashMap<K, V, S> {
+    fn normalize(&mut self, rules: NormalizeRules) {
+        self.values_mut().for_each(|val| val.normalize(rules));
+    }
+
+    fn describe(path: &str, rules: NormalizeRules, spec: &mut NormalizeSpec) {
+        V::describe(&normalize_field_path(path, "*"), rules, spec);
+    }
+}
+
+/// Implement no-op [`Normalize`] for types without normalizable content.
+macro_rules! impl_normalize_noop {
+    ($($ty:ty),+) => {
+        $(
+            impl Normalize for $ty {
+                #[inline]
+                fn normalize(&mut self, _rules: NormalizeRules) {}
+            }
+        )+
+    };
+}
+
+impl_normalize_noop!(
+    bool,
+    char,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    f32,
+    f64,
+    serde_json::Value
+);
+
+/// Extractor wrapper, normalizing extracted value.
+///
+/// Supports [`Json`] and [`Form`] extractors. Handler-level rules set with `normalize_strings`
+/// parameter of [`crate::handler`] are used for fields without their own rules.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Normalized<T>(pub T);
+
+impl<T> Normalized<T> {
+    /// Consume wrapper, returning inner extractor.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Normalized<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Normalized<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Get handler-level normalization rules from request extensions.
+fn handler_rules(req: &Request) -> NormalizeRules {
+    req.extensions()
+        .get::<NormalizeRules>()
+        .copied()
+        .unwrap_or_default()
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Normalized<Json<T>>
+where
+    T: DeserializeOwned + Normalize,
+    S: Send + Sync,
+{
+    type Rejection = JsonRejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let rules = handler_rules(&req);
+        let Json(mut inner) = Json::<T>::from_request(req, state).await?;
+        inner.normalize(rules);
+        Ok(Self(Json(inner)))
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Normalized<Form<T>>
+where
+    T: DeserializeOwned + Normalize,
+    S: Send + Sync,
+{
+    type Rejection = FormRejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let rules = handler_rules(&req);
+        let Form(mut inner) = Form::<T>::from_request(req, state).await?;
+        inner.normalize(rules);
+        Ok(Self(Form(inner)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::header, response::IntoResponse};
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, Normalize, PartialEq)]
+    struct Address {
+        #[normalize(trim, uppercase)]
+        country: String,
+        city: String,
+    }
+
+    #[derive(Debug, Deserialize, Normalize, PartialEq)]
+    struct Signup {
+        #[normalize(trim, lowercase)]
+        email: String,
+        #[normalize(skip)]
+        password: String,
+        #[serde(rename = "displayName")]
+        display_name: String,
+        #[normalize(trim, none_if_empty)]
+        nickname: Option<String>,
+        addresses: Vec<Address>,
+        labels: BTreeMap<String, String>,
+        age: u32,
+    }
+
+    async fn extract(body: &str, rules: Option<NormalizeRules>) -> Result<Signup, String> {
+        let mut req = Request::post("/")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        if let Some(rules) = rules {
+            req.extensions_mut().insert(rules);
+        }
+        match Normalized::<Json<Signup>>::from_request(req, &()).await {
+            Ok(Normalized(Json(signup))) => Ok(signup),
+            Err(rej) => {
+                let resp = rej.into_response();
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                Err(String::from_utf8(body.to_vec()).unwrap())
+            }
+        }
+    }
+
+    /// Rule names round-trip through parsing.
+    #[test]
+    fn parse_rules() {
+        let rules: NormalizeRules = " trim,lowercase ,".parse().unwrap();
+        assert_eq!(rules, NormalizeRules::NONE.trim().lowercase());
+        assert_eq!(rules.names(), ["trim", "lowercase"]);
+        assert_eq!(
+            "trim,titlecase".parse::<NormalizeRules>(),
+            Err(NormalizeError::UnknownRule("titlecase".into()))
+        );
+    }
+
+    /// Rules are applied to nested structs and collections, skipped fields are left intact.
+    #[tokio::test]
+    async fn nested_normalization() {
+        let body = r#"{
+            "email": "  John.Doe@Example.COM ",
+            "password": "  Secret ",
+            "displayName": " John ",
+            "nickname": "   ",
+            "addresses": [{"country": " de ", "city": " Berlin "}],
+            "labels": {"team": " Core "},
+            "age": 42
+        }"#;
+
+        // Field-level rules only.
+        let signup = extract(body, None).await.unwrap();
+        assert_eq!(signup.email, "john.doe@example.com");
+        assert_eq!(signup.password, "  Secret ");
+        assert_eq!(signup.display_name, " John ");
+        assert_eq!(signup.nickname, None);
+        assert_eq!(signup.addresses[0].country, "DE");
+        assert_eq!(signup.addresses[0].city, " Berlin ");
+        assert_eq!(signup.labels["team"], " Core ");
+
+        // Handler-level default applies to fields without own rules, but not to opted-out ones.
+        let signup = extract(body, Some(NormalizeRules::NONE.trim()))
+            .await
+            .unwrap();
+        assert_eq!(signup.email, "john.doe@example.com");
+        assert_eq!(signup.password, "  Secret ");
+        assert_eq!(signup.display_name, "John");
+        assert_eq!(signup.addresses[0].country, "DE");
+        assert_eq!(signup.addresses[0].city, "Berlin");
+        assert_eq!(signup.labels["team"], "Core");
+    }
+
+    /// Deserialization errors reference positions in original, untrimmed input.
+    #[tokio::test]
+    async fn error_positions() {
+        let body = "{\n  \"email\": \"  a@b.c  \",\n  \"age\": \"old\"\n}";
+        let err = extract(body, Some(NormalizeRules::NONE.trim()))
+            .await
+            .unwrap_err();
+        assert!(err.contains("line 3 column"), "{err}");
+    }
+
+    /// Normalization rules are described per field path.
+    #[test]
+    fn describe_rules() {
+        let mut spec = NormalizeSpec::new();
+        Signup::describe("", NormalizeRules::NONE.trim(), &mut spec);
+        assert_eq!(
+            spec,
+            NormalizeSpec::from([
+                ("addresses[].city".into(), vec!["trim"]),
+                ("addresses[].country".into(), vec!["trim", "uppercase"]),
+                ("displayName".into(), vec!["trim"]),
+                ("email".into(), vec!["trim", "lowercase"]),
+                ("labels.*".into(), vec!["trim"]),
+                ("nickname".into(), vec!["trim", "none_if_empty"]),
+            ])
+        );
+    }
+}
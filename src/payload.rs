@@ -0,0 +1,242 @@
+//! Pre-rendered static response payloads.
+
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderMap, HeaderValue, Response, StatusCode},
+    response::IntoResponse,
+};
+
+use crate::static_dir::etag_matches;
+
+/// FNV-1a hash offset basis.
+pub(crate) const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Continue FNV-1a hash of a byte sequence.
+///
+/// Used to build `ETag` values, which must stay stable across process restarts.
+pub(crate) fn fnv1a(state: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(state, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Pre-rendered response payload, along with its precompressed variant and `ETag`.
+///
+/// Payload is stored as [`Bytes`], so responses share the same buffer instead of copying it on
+/// every request.
+#[derive(Clone, Debug)]
+pub struct StaticPayload {
+    /// Value of `Content-Type` header.
+    content_type: HeaderValue,
+    /// Uncompressed payload.
+    raw: Bytes,
+    /// Gzip-compressed payload, if available.
+    gzip: Option<Bytes>,
+    /// Hash of uncompressed payload.
+    hash: u64,
+}
+
+impl StaticPayload {
+    /// Create payload from content type and data.
+    #[must_use]
+    pub fn new(content_type: HeaderValue, raw: impl Into<Bytes>) -> Self {
+        let raw = raw.into();
+        Self {
+            content_type,
+            hash: fnv1a(FNV_OFFSET, &raw),
+            raw,
+            gzip: None,
+        }
+    }
+
+    /// Create payload from static data, like the one produced by [`include_bytes!`].
+    #[must_use]
+    pub fn from_static(content_type: &'static str, raw: &'static [u8]) -> Self {
+        Self::new(
+            HeaderValue::from_static(content_type),
+            Bytes::from_static(raw),
+        )
+    }
+
+    /// Add gzip-compressed variant of the payload.
+    ///
+    /// It is served to clients which accept gzip content coding.
+    #[must_use]
+    pub fn with_gzip(mut self, gzip: impl Into<Bytes>) -> Self {
+        self.gzip = Some(gzip.into());
+        self
+    }
+
+    /// Uncompressed payload.
+    #[must_use]
+    pub fn raw(&self) -> &Bytes {
+        &self.raw
+    }
+
+    /// Hash of uncompressed payload, used as a base for `ETag`.
+    #[must_use]
+    pub(crate) fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Build response, taking `If-None-Match` and `Accept-Encoding` request headers into account.
+    #[must_use]
+    pub fn response(&self, headers: &HeaderMap) -> Response<Body> {
+        let gzip = self.gzip.as_ref().filter(|_| accepts_gzip(headers));
+        let etag = match gzip {
+            Some(_) => format!("\"{:016x}-gzip\"", self.hash),
+            None => format!("\"{:016x}\"", self.hash),
+        };
+        // SAFETY: ETag consists only of hex digits and ASCII punctuation.
+        let etag = HeaderValue::from_str(&etag).unwrap();
+        let matched = headers
+            .get(header::IF_NONE_MATCH)
+            .and_then(|hv| hv.to_str().ok())
+            .is_some_and(|inm| etag_matches(inm, &etag));
+        let mut resp = match matched {
+            true => StatusCode::NOT_MODIFIED.into_response(),
+            false => {
+                let mut resp = Response::new(Body::from(gzip.unwrap_or(&self.raw).clone()));
+                resp.headers_mut()
+                    .insert(header::CONTENT_TYPE, self.content_type.clone());
+                if gzip.is_some() {
+                    resp.headers_mut()
+                        .insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+                }
+                resp
+            }
+        };
+        resp.headers_mut().insert(header::ETAG, etag);
+        if self.gzip.is_some() {
+            resp.headers_mut()
+                .insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+        }
+        resp
+    }
+}
+
+impl IntoResponse for StaticPayload {
+    fn into_response(self) -> Response<Body> {
+        self.response(&HeaderMap::new())
+    }
+}
+
+/// Check whether client accepts gzip content coding.
+fn accepts_gzip(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|hv| hv.to_str().ok())
+        .flat_map(|hv| hv.split(','))
+        .any(|coding| {
+            let mut parts = coding.split(';').map(str::trim);
+            let name = parts.next().unwrap_or_default();
+            let quality = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .map_or(1.0, |q| q.parse().unwrap_or(0.0));
+            (name.eq_ignore_ascii_case("gzip") || name == "*") && quality > 0.0
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::BodyExt;
+
+    use super::*;
+
+    /// Compute CRC-32 checksum, as used in gzip trailer.
+    fn crc32(data: &[u8]) -> u32 {
+        !data.iter().fold(!0_u32, |crc, byte| {
+            (0..8).fold(crc ^ u32::from(*byte), |crc, _| {
+                (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+            })
+        })
+    }
+
+    fn headers(pairs: &[(header::HeaderName, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    async fn body(resp: Response<Body>) -> Bytes {
+        resp.into_body().collect().await.unwrap().to_bytes()
+    }
+
+    #[test]
+    fn gzip_negotiation() {
+        for (value, expected) in [
+            ("gzip", true),
+            ("br, GZIP;q=0.5", true),
+            ("*", true),
+            ("gzip;q=0", false),
+            ("deflate, br", false),
+        ] {
+            let hdrs = headers(&[(header::ACCEPT_ENCODING, value)]);
+            assert_eq!(accepts_gzip(&hdrs), expected, "{value}");
+        }
+        assert!(!accepts_gzip(&HeaderMap::new()));
+    }
+
+    #[tokio::test]
+    async fn zero_copy() {
+        static DATA: &[u8] = b"static payload";
+        let payload = StaticPayload::from_static("text/plain", DATA).with_gzip(&b"gz"[..]);
+        let first = body(payload.response(&HeaderMap::new())).await;
+        let second = body(payload.clone().into_response()).await;
+        assert_eq!(first, DATA);
+        assert_eq!(first.as_ptr(), DATA.as_ptr());
+        assert_eq!(second.as_ptr(), DATA.as_ptr());
+
+        let gzip = headers(&[(header::ACCEPT_ENCODING, "gzip")]);
+        let first = body(payload.response(&gzip)).await;
+        let second = body(payload.response(&gzip)).await;
+        assert_eq!(first, &b"gz"[..]);
+        assert_eq!(first.as_ptr(), second.as_ptr());
+    }
+
+    #[tokio::test]
+    async fn etag() {
+        let payload = StaticPayload::from_static("text/plain", b"data").with_gzip(&b"gz"[..]);
+        let resp = payload.response(&HeaderMap::new());
+        let etag = resp.headers()[header::ETAG].to_str().unwrap().to_owned();
+        assert_eq!(resp.headers()[header::VARY], "accept-encoding");
+        assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+
+        let resp = payload.response(&headers(&[(header::ACCEPT_ENCODING, "gzip")]));
+        assert_eq!(resp.headers()[header::CONTENT_ENCODING], "gzip");
+        let gzip_etag = resp.headers()[header::ETAG].to_str().unwrap().to_owned();
+        assert_ne!(etag, gzip_etag);
+
+        let mut hdrs = HeaderMap::new();
+        hdrs.insert(header::IF_NONE_MATCH, etag.parse().unwrap());
+        let resp = payload.response(&hdrs);
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert!(body(resp).await.is_empty());
+    }
+
+    /// Precompressed assets must match their uncompressed versions.
+    #[test]
+    fn precompressed_assets() {
+        let assets: [(&[u8], &[u8]); 2] = [
+            (
+                include_bytes!("../static/rapidoc-min.js"),
+                include_bytes!("../static/rapidoc-min.js.gz"),
+            ),
+            (
+                include_bytes!("../static/rapidoc-min.js.map"),
+                include_bytes!("../static/rapidoc-min.js.map.gz"),
+            ),
+        ];
+        for (raw, gzip) in assets {
+            let (_, trailer) = gzip.split_at(gzip.len() - 8);
+            let (crc, size) = trailer.split_at(4);
+            assert_eq!(u32::from_le_bytes(crc.try_into().unwrap()), crc32(raw));
+            assert_eq!(
+                u32::from_le_bytes(size.try_into().unwrap()),
+                raw.len() as u32
+            );
+        }
+    }
+}
@@ -0,0 +1,615 @@
+//! Persistence of resilience state across restarts.
+//!
+//! Circuit breaker and rate limiter states are saved on graceful shutdown, and restored on
+//! startup, so that restarts do not reset protection against degraded downstream services and
+//! abusive clients.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use bytes::Bytes;
+use once_cell::sync::Lazy;
+use opentelemetry::{metrics::Counter, KeyValue};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug_span, info, warn};
+
+use crate::{
+    errors::IoError,
+    kv::{KeyValueStore, KvError},
+};
+
+/// Version of persisted state schema.
+///
+/// State saved with a different version is discarded.
+pub(crate) const STATE_VERSION: u32 = 1;
+
+/// Error type used in state persistence.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum StatePersistenceError {
+    /// Unable to read or write state file.
+    #[error("Unable to access state file {0}: {1}")]
+    File(PathBuf, IoError),
+    /// Key-value store error.
+    #[error(transparent)]
+    Store(#[from] KvError),
+    /// Unable to encode state.
+    #[error("Unable to encode state: {0}")]
+    Encode(#[from] serde_json::Error),
+    /// Neither state file nor key-value store is configured.
+    #[error("No state file or key-value store configured")]
+    NoBackend,
+}
+
+/// Configuration of resilience state persistence.
+///
+/// State is written to a local file if [`Self::file`] is set, or to a key-value store otherwise
+/// (see [`crate::AppBuilder::with_state_store`]).
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct StatePersistenceConfig {
+    /// Path to state file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file: Option<PathBuf>,
+    /// Key used in key-value store.
+    #[serde(default = "StatePersistenceConfig::default_key")]
+    key: String,
+    /// Maximum age of restored state entries.
+    #[serde(
+        default = "StatePersistenceConfig::default_ttl",
+        with = "humantime_serde"
+    )]
+    ttl: Duration,
+}
+
+impl Default for StatePersistenceConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            key: Self::default_key(),
+            ttl: Self::default_ttl(),
+        }
+    }
+}
+
+impl StatePersistenceConfig {
+    /// Default value for [`Self::key`].
+    #[must_use]
+    #[inline]
+    fn default_key() -> String {
+        "uxum/resilience".into()
+    }
+
+    /// Default value for [`Self::ttl`].
+    #[must_use]
+    #[inline]
+    fn default_ttl() -> Duration {
+        Duration::from_secs(300)
+    }
+
+    /// Set path to state file.
+    #[must_use]
+    pub fn with_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.file = Some(path.into());
+        self
+    }
+
+    /// Set maximum age of restored state entries.
+    #[must_use]
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Create state persistence object.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if no state file is configured, and no key-value store is provided.
+    pub(crate) fn build(
+        &self,
+        store: Option<Arc<dyn KeyValueStore>>,
+        counters: Option<(Counter<u64>, Counter<u64>)>,
+    ) -> Result<StatePersistence, StatePersistenceError> {
+        let backend = match (&self.file, store) {
+            (Some(path), _) => Backend::File(path.clone()),
+            (None, Some(store)) => Backend::Store(store, self.key.clone()),
+            (None, None) => return Err(StatePersistenceError::NoBackend),
+        };
+        Ok(StatePersistence(Arc::new(StatePersistenceInner {
+            backend,
+            ttl: self.ttl,
+            counters,
+        })))
+    }
+}
+
+/// Observed state of a circuit breaker.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BreakerStatus {
+    /// Requests are allowed.
+    Closed,
+    /// Requests are rejected.
+    Open,
+}
+
+/// Persisted circuit breaker state.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub(crate) struct BreakerSnapshot {
+    /// Breaker state.
+    pub(crate) status: BreakerStatus,
+    /// Time of last state transition.
+    #[serde(with = "humantime_serde")]
+    pub(crate) since: SystemTime,
+}
+
+/// Persisted rate limiter bucket state.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub(crate) struct BucketSnapshot {
+    /// Encoded limiter key.
+    pub(crate) key: String,
+    /// Remaining burst capacity.
+    pub(crate) tokens: u32,
+    /// Time when capacity was observed.
+    #[serde(with = "humantime_serde")]
+    pub(crate) at: SystemTime,
+}
+
+/// Circuit breaker with persistent state.
+pub(crate) trait PersistentBreaker: Send + Sync {
+    /// Get current breaker state.
+    fn snapshot(&self) -> BreakerSnapshot;
+
+    /// Resume from previously saved state.
+    fn restore(&self, snapshot: &BreakerSnapshot);
+}
+
+/// Rate limiter with persistent state.
+pub(crate) trait PersistentLimiter: Send + Sync {
+    /// Get current bucket states.
+    fn snapshot(&self) -> Vec<BucketSnapshot>;
+
+    /// Resume from previously saved bucket states.
+    fn restore(&self, buckets: &[BucketSnapshot]);
+}
+
+/// Persisted state document.
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+struct StateDocument {
+    /// Schema version.
+    version: u32,
+    /// Time of saving.
+    #[serde(with = "humantime_serde")]
+    saved_at: Option<SystemTime>,
+    /// Circuit breaker states, keyed by HTTP client name.
+    #[serde(default)]
+    breakers: BTreeMap<String, BreakerSnapshot>,
+    /// Rate limiter states, keyed by handler name.
+    #[serde(default)]
+    limiters: BTreeMap<String, Vec<BucketSnapshot>>,
+}
+
+/// Registered persistent objects, and restored states not yet claimed by them.
+#[derive(Default)]
+struct Registry {
+    /// Registered circuit breakers.
+    breakers: BTreeMap<String, Arc<dyn PersistentBreaker>>,
+    /// Registered rate limiters.
+    limiters: BTreeMap<String, Arc<dyn PersistentLimiter>>,
+    /// Restored circuit breaker states, waiting for breaker registration.
+    pending_breakers: BTreeMap<String, BreakerSnapshot>,
+    /// Restored rate limiter states, waiting for limiter registration.
+    pending_limiters: BTreeMap<String, Vec<BucketSnapshot>>,
+}
+
+/// Registry of persistent objects.
+static REGISTRY: Lazy<Mutex<Registry>> = Lazy::new(Default::default);
+
+/// Globally registered state persistence, used on shutdown.
+static PERSISTENCE: Lazy<RwLock<Option<StatePersistence>>> = Lazy::new(Default::default);
+
+/// Register circuit breaker, restoring its state if it was already loaded.
+pub(crate) fn register_breaker(name: &str, breaker: Arc<dyn PersistentBreaker>) {
+    let mut registry = REGISTRY.lock();
+    if let Some(snapshot) = registry.pending_breakers.remove(name) {
+        breaker.restore(&snapshot);
+    }
+    registry.breakers.insert(name.to_string(), breaker);
+}
+
+/// Register rate limiter, restoring its state if it was already loaded.
+pub(crate) fn register_limiter(name: &str, limiter: Arc<dyn PersistentLimiter>) {
+    let mut registry = REGISTRY.lock();
+    if let Some(buckets) = registry.pending_limiters.remove(name) {
+        limiter.restore(&buckets);
+    }
+    registry.limiters.insert(name.to_string(), limiter);
+}
+
+/// Save state using globally registered state persistence, if any.
+///
+/// Errors are logged, but otherwise ignored.
+pub(crate) fn save() {
+    let persistence = PERSISTENCE.read().clone();
+    if let Some(persistence) = persistence {
+        match persistence.save() {
+            Ok(()) => info!("resilience state saved"),
+            Err(err) => warn!(%err, "unable to save resilience state"),
+        }
+    }
+}
+
+/// State persistence back-end.
+enum Backend {
+    /// Local file.
+    File(PathBuf),
+    /// Key-value store, along with a key.
+    Store(Arc<dyn KeyValueStore>, String),
+}
+
+/// Resilience state persistence.
+#[derive(Clone)]
+pub(crate) struct StatePersistence(Arc<StatePersistenceInner>);
+
+/// Inner container for [`StatePersistence`].
+struct StatePersistenceInner {
+    /// Storage back-end.
+    backend: Backend,
+    /// Maximum age of restored entries.
+    ttl: Duration,
+    /// Counters of restored and discarded entries.
+    counters: Option<(Counter<u64>, Counter<u64>)>,
+}
+
+/// Counts of restored and discarded entries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct RestoreStats {
+    /// Entries restored.
+    pub(crate) restored: u64,
+    /// Entries discarded as expired, invalid, or saved with incompatible schema version.
+    pub(crate) discarded: u64,
+}
+
+impl StatePersistence {
+    /// Register as a global instance, used when saving state on shutdown.
+    pub(crate) fn register(&self) {
+        *PERSISTENCE.write() = Some(self.clone());
+    }
+
+    /// Restore state in background, if possible.
+    ///
+    /// Never blocks or fails startup, errors are logged.
+    pub(crate) fn spawn_restore(&self) {
+        let persistence = self.clone();
+        let restore = move || match persistence.restore() {
+            Ok(stats) => info!(
+                restored = stats.restored,
+                discarded = stats.discarded,
+                "resilience state restored"
+            ),
+            Err(err) => warn!(%err, "unable to restore resilience state"),
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => drop(handle.spawn_blocking(restore)),
+            Err(_) => restore(),
+        }
+    }
+
+    /// Read raw state, if any.
+    fn load(&self) -> Result<Option<Bytes>, StatePersistenceError> {
+        match &self.0.backend {
+            Backend::File(path) => match fs::read(path) {
+                Ok(raw) => Ok(Some(raw.into())),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(StatePersistenceError::File(path.clone(), err.into())),
+            },
+            Backend::Store(store, key) => Ok(store.get(key)?),
+        }
+    }
+
+    /// Write raw state.
+    fn store(&self, raw: Bytes) -> Result<(), StatePersistenceError> {
+        match &self.0.backend {
+            Backend::File(path) => {
+                // Write to a temporary file first, so that state is never left half-written.
+                let tmp = path.with_extension("tmp");
+                fs::write(&tmp, &raw)
+                    .and_then(|()| fs::rename(&tmp, path))
+                    .map_err(|err| StatePersistenceError::File(path.clone(), err.into()))
+            }
+            Backend::Store(store, key) => Ok(store.put(key, raw)?),
+        }
+    }
+
+    /// Save states of all registered objects.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if state cannot be encoded or written.
+    pub(crate) fn save(&self) -> Result<(), StatePersistenceError> {
+        let _span = debug_span!("save_state").entered();
+        let doc = {
+            let registry = REGISTRY.lock();
+            StateDocument {
+                version: STATE_VERSION,
+                saved_at: Some(SystemTime::now()),
+                breakers: registry
+                    .breakers
+                    .iter()
+                    .map(|(name, breaker)| (name.clone(), breaker.snapshot()))
+                    .collect(),
+                limiters: registry
+                    .limiters
+                    .iter()
+                    .map(|(name, limiter)| (name.clone(), limiter.snapshot()))
+                    .filter(|(_, buckets)| !buckets.is_empty())
+                    .collect(),
+            }
+        };
+        self.store(serde_json::to_vec(&doc)?.into())
+    }
+
+    /// Load saved state, and apply it to registered objects.
+    ///
+    /// States of objects which are not registered yet are applied on registration.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if state cannot be read.
+    pub(crate) fn restore(&self) -> Result<RestoreStats, StatePersistenceError> {
+        let _span = debug_span!("restore_state").entered();
+        let Some(raw) = self.load()? else {
+            return Ok(RestoreStats::default());
+        };
+        let (doc, stats) = self.decode(&raw, SystemTime::now());
+        let mut registry = REGISTRY.lock();
+        for (name, snapshot) in doc.breakers {
+            match registry.breakers.get(&name) {
+                Some(breaker) => breaker.restore(&snapshot),
+                None => drop(registry.pending_breakers.insert(name, snapshot)),
+            }
+        }
+        for (name, buckets) in doc.limiters {
+            match registry.limiters.get(&name) {
+                Some(limiter) => limiter.restore(&buckets),
+                None => drop(registry.pending_limiters.insert(name, buckets)),
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Decode raw state, dropping expired entries.
+    fn decode(&self, raw: &[u8], now: SystemTime) -> (StateDocument, RestoreStats) {
+        let mut stats = RestoreStats::default();
+        let value: serde_json::Value = match serde_json::from_slice(raw) {
+            Ok(value) => value,
+            Err(err) => {
+                warn!(%err, "discarding unreadable resilience state");
+                return (StateDocument::default(), stats);
+            }
+        };
+        let version = value.get("version").and_then(serde_json::Value::as_u64);
+        let doc = match version == Some(STATE_VERSION.into()) {
+            true => serde_json::from_value::<StateDocument>(value.clone()).ok(),
+            false => None,
+        };
+        let Some(mut doc) = doc else {
+            // Count entries without interpreting them.
+            let breakers = value
+                .get("breakers")
+                .and_then(serde_json::Value::as_object)
+                .map_or(0, serde_json::Map::len);
+            let buckets = value
+                .get("limiters")
+                .and_then(serde_json::Value::as_object)
+                .map_or(0, |limiters| {
+                    limiters
+                        .values()
+                        .filter_map(serde_json::Value::as_array)
+                        .map(Vec::len)
+                        .sum()
+                });
+            warn!(?version, "discarding incompatible resilience state");
+            self.count_discarded("breaker", "version", breakers as u64);
+            self.count_discarded("limiter", "version", buckets as u64);
+            stats.discarded = (breakers + buckets) as u64;
+            return (StateDocument::default(), stats);
+        };
+        let is_fresh =
+            |at: SystemTime| now.duration_since(at).map_or(true, |age| age <= self.0.ttl);
+        let total = doc.breakers.len() as u64;
+        if !doc.saved_at.is_some_and(is_fresh) {
+            doc.breakers.clear();
+        }
+        let kept = doc.breakers.len() as u64;
+        self.count_restored("breaker", kept);
+        self.count_discarded("breaker", "expired", total - kept);
+        stats.restored += kept;
+        stats.discarded += total - kept;
+        for buckets in doc.limiters.values_mut() {
+            let total = buckets.len() as u64;
+            buckets.retain(|bucket| is_fresh(bucket.at));
+            let kept = buckets.len() as u64;
+            self.count_restored("limiter", kept);
+            self.count_discarded("limiter", "expired", total - kept);
+            stats.restored += kept;
+            stats.discarded += total - kept;
+        }
+        doc.limiters.retain(|_, buckets| !buckets.is_empty());
+        (doc, stats)
+    }
+
+    /// Add to counter of restored entries.
+    fn count_restored(&self, kind: &'static str, count: u64) {
+        if let Some((restored, _)) = &self.0.counters {
+            if count > 0 {
+                restored.add(count, &[KeyValue::new("kind", kind)]);
+            }
+        }
+    }
+
+    /// Add to counter of discarded entries.
+    fn count_discarded(&self, kind: &'static str, reason: &'static str, count: u64) {
+        if let Some((_, discarded)) = &self.0.counters {
+            if count > 0 {
+                discarded.add(
+                    count,
+                    &[KeyValue::new("kind", kind), KeyValue::new("reason", reason)],
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv::MemoryStore;
+
+    /// Breaker which records restored state.
+    #[derive(Default)]
+    struct TestBreaker(Mutex<Option<BreakerSnapshot>>);
+
+    impl PersistentBreaker for TestBreaker {
+        fn snapshot(&self) -> BreakerSnapshot {
+            self.0.lock().unwrap_or(BreakerSnapshot {
+                status: BreakerStatus::Closed,
+                since: SystemTime::UNIX_EPOCH,
+            })
+        }
+
+        fn restore(&self, snapshot: &BreakerSnapshot) {
+            *self.0.lock() = Some(*snapshot);
+        }
+    }
+
+    /// Limiter which records restored state.
+    #[derive(Default)]
+    struct TestLimiter(Mutex<Vec<BucketSnapshot>>);
+
+    impl PersistentLimiter for TestLimiter {
+        fn snapshot(&self) -> Vec<BucketSnapshot> {
+            self.0.lock().clone()
+        }
+
+        fn restore(&self, buckets: &[BucketSnapshot]) {
+            *self.0.lock() = buckets.to_vec();
+        }
+    }
+
+    fn persistence(ttl: Duration) -> (StatePersistence, Arc<MemoryStore>) {
+        let store = Arc::new(MemoryStore::new());
+        let persistence = StatePersistenceConfig::default()
+            .with_ttl(ttl)
+            .build(Some(store.clone()), None)
+            .unwrap();
+        (persistence, store)
+    }
+
+    fn bucket(key: &str, age: Duration) -> BucketSnapshot {
+        BucketSnapshot {
+            key: key.into(),
+            tokens: 3,
+            at: SystemTime::now() - age,
+        }
+    }
+
+    /// Saved state is restored to objects registered before and after restoring.
+    #[test]
+    fn round_trip() {
+        let (persistence, _) = persistence(Duration::from_secs(60));
+        let open = BreakerSnapshot {
+            status: BreakerStatus::Open,
+            since: SystemTime::now(),
+        };
+        let breaker = Arc::new(TestBreaker(Mutex::new(Some(open))));
+        let limiter = Arc::new(TestLimiter(Mutex::new(vec![bucket("a", Duration::ZERO)])));
+        register_breaker("test.round_trip", breaker.clone());
+        register_limiter("test.round_trip", limiter.clone());
+        persistence.save().unwrap();
+
+        // Simulate restart.
+        let breaker = Arc::new(TestBreaker::default());
+        register_breaker("test.round_trip", breaker.clone());
+        REGISTRY.lock().limiters.remove("test.round_trip");
+        let stats = persistence.restore().unwrap();
+        assert!(stats.restored >= 2);
+        assert_eq!(*breaker.0.lock(), Some(open));
+        let limiter = Arc::new(TestLimiter::default());
+        register_limiter("test.round_trip", limiter.clone());
+        assert_eq!(limiter.0.lock()[0].key, "a");
+    }
+
+    /// Entries older than TTL are discarded.
+    #[test]
+    fn ttl_expiry() {
+        let (persistence, _) = persistence(Duration::from_secs(60));
+        let now = SystemTime::now();
+        let doc = StateDocument {
+            version: STATE_VERSION,
+            saved_at: Some(now - Duration::from_secs(120)),
+            breakers: [(
+                "client".to_string(),
+                BreakerSnapshot {
+                    status: BreakerStatus::Open,
+                    since: now - Duration::from_secs(120),
+                },
+            )]
+            .into(),
+            limiters: [(
+                "handler".to_string(),
+                vec![
+                    bucket("old", Duration::from_secs(120)),
+                    bucket("new", Duration::from_secs(10)),
+                ],
+            )]
+            .into(),
+        };
+        let (doc, stats) = persistence.decode(&serde_json::to_vec(&doc).unwrap(), now);
+        assert_eq!(
+            stats,
+            RestoreStats {
+                restored: 1,
+                discarded: 2
+            }
+        );
+        assert!(doc.breakers.is_empty());
+        assert_eq!(doc.limiters["handler"].len(), 1);
+        assert_eq!(doc.limiters["handler"][0].key, "new");
+    }
+
+    /// State saved with a different schema version is discarded as a whole.
+    #[test]
+    fn version_mismatch() {
+        let (persistence, store) = persistence(Duration::from_secs(60));
+        let raw = serde_json::json!({
+            "version": STATE_VERSION + 1,
+            "breakers": {"client": {"status": "open", "since": "2024-01-01T00:00:00Z", "x": 1}},
+            "limiters": {"handler": [{"key": "a"}, {"key": "b"}]},
+        });
+        store
+            .put(
+                &StatePersistenceConfig::default_key(),
+                serde_json::to_vec(&raw).unwrap().into(),
+            )
+            .unwrap();
+        let stats = persistence.restore().unwrap();
+        assert_eq!(
+            stats,
+            RestoreStats {
+                restored: 0,
+                discarded: 3
+            }
+        );
+        let (_, stats) = persistence.decode(b"not json", SystemTime::now());
+        assert_eq!(stats, RestoreStats::default());
+    }
+}
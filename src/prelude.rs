@@ -8,11 +8,10 @@ pub use crate::{
             extract::{ConnectInfo, Path, Query, State},
             http::{self, HeaderValue, StatusCode},
             response::{IntoResponse, IntoResponseParts},
-            Json,
         },
         mime, okapi, openapi3,
         schemars::{self, JsonSchema},
         tracing,
     },
-    AppBuilder, AppConfig, Handle, HandleError, ServerBuilder,
+    AppBuilder, AppConfig, CurrentUser, Handle, HandleError, Json, ServerBuilder,
 };
@@ -1,35 +1,100 @@
 //! Service probe and maintenance mode API endpoints.
+//!
+//! Probe states follow service lifecycle: `starting`, then `ready` once server listeners are
+//! bound, then `draining` once shutdown is requested. Lifecycle is updated by [`crate::Handle`]
+//! for all probe states built by [`crate::AppBuilder`].
 
 use std::{
     borrow::Borrow,
+    collections::BTreeMap,
+    fmt,
+    future::Future,
     ops::Deref,
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        Arc, Weak,
     },
+    time::{Duration, Instant},
 };
 
 use axum::{
     error_handling::HandleErrorLayer,
-    extract::State,
-    http::StatusCode,
+    extract::{Query, State},
+    http::{header::RETRY_AFTER, StatusCode},
     response::IntoResponse,
     routing::{self, Router},
+    Json,
 };
+use futures::future::{join_all, BoxFuture};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use tower::ServiceBuilder;
-use tracing::{debug_span, info};
+use tracing::{debug_span, info, warn};
 
 use crate::{
     auth::{AuthExtractor, AuthLayer, AuthProvider},
     builder::app::error_handler,
+    layers::{
+        ext::Deadline,
+        toggle::{list_handlers, set_handler_enabled, HandlerToggles},
+    },
+    retry::{RetryAdviceConfig, RetrySource},
+    subsystem::{subsystem_statuses, SubsystemState},
     watchdog::{Watchdog, WatchdogConfig},
 };
 
+/// Current lifecycle, along with all registered probe states.
+static LIFECYCLE: Lazy<Mutex<(Lifecycle, Vec<Weak<ProbeStateInner>>)>> =
+    Lazy::new(|| Mutex::new((Lifecycle::Starting, Vec::new())));
+
+/// Set lifecycle stage of all registered probe states.
+pub(crate) fn set_lifecycle(lifecycle: Lifecycle) {
+    let mut global = LIFECYCLE.lock();
+    if global.0 != lifecycle {
+        info!(?lifecycle, "service lifecycle changed");
+    }
+    global.0 = lifecycle;
+    global.1.retain(|state| match state.upgrade() {
+        Some(state) => {
+            state.set_lifecycle(lifecycle);
+            true
+        }
+        None => false,
+    });
+}
+
+/// Service lifecycle stage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+#[repr(u8)]
+pub enum Lifecycle {
+    /// Server is not listening yet.
+    Starting,
+    /// Server is listening for requests.
+    Ready,
+    /// Shutdown was requested, server is finishing in-progress requests.
+    Draining,
+}
+
+impl From<u8> for Lifecycle {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Starting,
+            1 => Self::Ready,
+            _ => Self::Draining,
+        }
+    }
+}
+
 /// Configuration for service probes and management mode API.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[non_exhaustive]
 pub struct ProbeConfig {
+    /// URL path for startup probe.
+    #[serde(default = "ProbeConfig::default_startup_path")]
+    startup_path: String,
     /// URL path for readiness probe.
     #[serde(default = "ProbeConfig::default_readiness_path")]
     readiness_path: String,
@@ -45,33 +110,56 @@ pub struct ProbeConfig {
     /// Runtime watchdog configuration.
     #[serde(default)]
     watchdog: Option<WatchdogConfig>,
+    /// Timeout for a single custom readiness check.
+    #[serde(
+        default = "ProbeConfig::default_check_timeout",
+        with = "humantime_serde"
+    )]
+    check_timeout: Duration,
+    /// URL path for handler toggle API.
+    #[serde(default = "ProbeConfig::default_handlers_path")]
+    handlers_path: String,
+    /// Permission required to use handler toggle API.
+    #[serde(default = "ProbeConfig::default_handlers_permission")]
+    handlers_permission: String,
 }
 
 impl Default for ProbeConfig {
     fn default() -> Self {
         Self {
+            startup_path: Self::default_startup_path(),
             readiness_path: Self::default_readiness_path(),
             liveness_path: Self::default_liveness_path(),
             maintenance_on_path: Self::default_maintenance_on_path(),
             maintenance_off_path: Self::default_maintenance_off_path(),
             watchdog: Some(WatchdogConfig::default()),
+            check_timeout: Self::default_check_timeout(),
+            handlers_path: Self::default_handlers_path(),
+            handlers_permission: Self::default_handlers_permission(),
         }
     }
 }
 
 impl ProbeConfig {
+    /// Default value for [`Self::startup_path`].
+    #[must_use]
+    #[inline]
+    fn default_startup_path() -> String {
+        "/healthz/startup".into()
+    }
+
     /// Default value for [`Self::readiness_path`].
     #[must_use]
     #[inline]
     fn default_readiness_path() -> String {
-        "/probe/ready".into()
+        "/healthz/ready".into()
     }
 
     /// Default value for [`Self::liveness_path`].
     #[must_use]
     #[inline]
     fn default_liveness_path() -> String {
-        "/probe/live".into()
+        "/healthz/live".into()
     }
 
     /// Default value for [`Self::maintenance_on_path`].
@@ -88,9 +176,64 @@ impl ProbeConfig {
         "/maintenance/off".into()
     }
 
+    /// Default value for [`Self::check_timeout`].
+    #[must_use]
+    #[inline]
+    fn default_check_timeout() -> Duration {
+        Duration::from_secs(1)
+    }
+
+    /// Default value for [`Self::handlers_path`].
+    #[must_use]
+    #[inline]
+    fn default_handlers_path() -> String {
+        "/handlers".into()
+    }
+
+    /// Default value for [`Self::handlers_permission`].
+    #[must_use]
+    #[inline]
+    fn default_handlers_permission() -> String {
+        "handlers".into()
+    }
+
+    /// Set URL path for handler toggle API.
+    #[must_use]
+    pub fn with_handlers_path(mut self, path: impl ToString) -> Self {
+        self.handlers_path = path.to_string();
+        self
+    }
+
+    /// Set permission required to use handler toggle API.
+    #[must_use]
+    pub fn with_handlers_permission(mut self, permission: impl ToString) -> Self {
+        self.handlers_permission = permission.to_string();
+        self
+    }
+
+    /// Set timeout for a single custom readiness check.
+    #[must_use]
+    pub fn with_check_timeout(mut self, timeout: Duration) -> Self {
+        self.check_timeout = timeout;
+        self
+    }
+
+    /// Build shared probe state.
+    ///
+    /// `retry_advice` is used to generate `Retry-After` header while in maintenance mode.
+    #[must_use]
+    pub fn build_state(&self, retry_advice: &RetryAdviceConfig) -> ProbeState {
+        let mut state = ProbeState::new(self.watchdog.as_ref()).with_retry_advice(retry_advice);
+        if let Some(inner) = Arc::get_mut(&mut state.0) {
+            inner.check_timeout = self.check_timeout;
+        }
+        state
+    }
+
     /// Build Axum router containing all probe and maintenance methods.
     pub fn build_router<AuthProv, AuthExt>(
         &self,
+        state: ProbeState,
         auth_provider: AuthProv,
         auth_extractor: AuthExt,
     ) -> Router
@@ -102,8 +245,28 @@ impl ProbeConfig {
     {
         // TODO: add toggle for probes, and possibly for maintenance mode.
         let _span = debug_span!("build_probes").entered();
-        let state = ProbeState::new(self.watchdog.as_ref());
+        // Leaked once at build time, as auth layer requires static permissions.
+        let handlers_perm: &'static str =
+            Box::leak(self.handlers_permission.clone().into_boxed_str());
+        let handlers_perms: &'static [&'static str] = Box::leak(Box::new([handlers_perm]));
+        let handlers = Router::new()
+            .route(&self.handlers_path, routing::get(list_handlers))
+            .route(
+                &format!("{}/:name/enabled", self.handlers_path.trim_end_matches('/')),
+                routing::put(set_handler_enabled),
+            )
+            .layer(
+                ServiceBuilder::new()
+                    .layer(HandleErrorLayer::new(error_handler))
+                    .layer(AuthLayer::new(
+                        handlers_perms,
+                        auth_provider.clone(),
+                        auth_extractor.clone(),
+                    )),
+            )
+            .with_state(state.handler_toggles.clone());
         Router::new()
+            .route(&self.startup_path, routing::get(startup_probe))
             .route(&self.readiness_path, routing::get(readiness_probe))
             .route(&self.liveness_path, routing::get(liveness_probe))
             .merge(
@@ -121,6 +284,7 @@ impl ProbeConfig {
                     ),
             )
             .with_state(state)
+            .merge(handlers)
     }
 }
 
@@ -140,7 +304,13 @@ impl Default for ProbeState {
     fn default() -> Self {
         Self(Arc::new(ProbeStateInner {
             in_maintenance: AtomicBool::new(true),
+            warmed_up: AtomicBool::new(true),
+            lifecycle: AtomicU8::new(Lifecycle::Starting as u8),
             watchdog: None,
+            retry_advice: RetryAdviceConfig::default(),
+            checks: Vec::new(),
+            check_timeout: ProbeConfig::default_check_timeout(),
+            handler_toggles: HandlerToggles::default(),
         }))
     }
 }
@@ -151,30 +321,342 @@ impl ProbeState {
     pub fn new(watchdog: Option<&WatchdogConfig>) -> Self {
         Self(Arc::new(ProbeStateInner {
             in_maintenance: AtomicBool::new(true),
+            warmed_up: AtomicBool::new(true),
+            lifecycle: AtomicU8::new(Lifecycle::Starting as u8),
             watchdog: watchdog.map(|wc| {
                 let mut watchdog: Watchdog = wc.clone().into();
                 watchdog.start();
                 watchdog
             }),
+            retry_advice: RetryAdviceConfig::default(),
+            checks: Vec::new(),
+            check_timeout: ProbeConfig::default_check_timeout(),
+            handler_toggles: HandlerToggles::default(),
         }))
     }
+
+    /// Set retry advice configuration, used while in maintenance mode.
+    ///
+    /// Has no effect if this state is already shared.
+    #[must_use]
+    pub fn with_retry_advice(mut self, retry_advice: &RetryAdviceConfig) -> Self {
+        if let Some(inner) = Arc::get_mut(&mut self.0) {
+            inner.retry_advice = retry_advice.clone();
+        }
+        self
+    }
+
+    /// Set custom readiness checks.
+    ///
+    /// Has no effect if this state is already shared.
+    #[must_use]
+    pub(crate) fn with_checks(mut self, checks: Vec<ReadinessCheck>) -> Self {
+        if let Some(inner) = Arc::get_mut(&mut self.0) {
+            inner.checks = checks;
+        }
+        self
+    }
+
+    /// Set registry of runtime handler state flags.
+    ///
+    /// Has no effect if this state is already shared.
+    #[must_use]
+    pub(crate) fn with_handler_toggles(mut self, toggles: HandlerToggles) -> Self {
+        if let Some(inner) = Arc::get_mut(&mut self.0) {
+            inner.handler_toggles = toggles;
+        }
+        self
+    }
+
+    /// Run all custom readiness checks concurrently.
+    async fn run_checks(&self) -> BTreeMap<String, CheckResult> {
+        let timeout = self.check_timeout;
+        join_all(self.checks.iter().map(|check| async move {
+            let start = Instant::now();
+            let status =
+                match tokio::time::timeout(timeout, (check.check)(Deadline::from(timeout))).await {
+                    Ok(Ok(())) => CheckStatus::Ok,
+                    Ok(Err(error)) => {
+                        warn!(check = check.name, %error, "readiness check failed");
+                        CheckStatus::Failed
+                    }
+                    Err(_) => {
+                        warn!(check = check.name, ?timeout, "readiness check timed out");
+                        CheckStatus::TimedOut
+                    }
+                };
+            let result = CheckResult {
+                status,
+                optional: check.optional,
+                latency: start.elapsed().as_secs_f64(),
+            };
+            (check.name.clone(), result)
+        }))
+        .await
+        .into_iter()
+        .collect()
+    }
+
+    /// Follow lifecycle changes made by [`crate::Handle`].
+    pub(crate) fn register(&self) {
+        let mut global = LIFECYCLE.lock();
+        self.lifecycle.store(global.0 as u8, Ordering::Relaxed);
+        global.1.push(Arc::downgrade(&self.0));
+    }
+
+    /// Get current lifecycle stage.
+    #[must_use]
+    pub fn lifecycle(&self) -> Lifecycle {
+        self.lifecycle.load(Ordering::Relaxed).into()
+    }
+
+    /// Check whether service is ready to receive requests.
+    ///
+    /// Service is ready when server is listening and not draining, it is not in maintenance mode,
+    /// and warmup is finished.
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        self.lifecycle() == Lifecycle::Ready
+            && !self.in_maintenance.load(Ordering::Relaxed)
+            && self.warmed_up.load(Ordering::Relaxed)
+    }
+
+    /// Mark warmup as started, holding readiness until [`Self::end_warmup`] is called.
+    pub(crate) fn begin_warmup(&self) {
+        self.warmed_up.store(false, Ordering::Relaxed);
+    }
+
+    /// Mark warmup as finished.
+    pub(crate) fn end_warmup(&self) {
+        self.warmed_up.store(true, Ordering::Relaxed);
+    }
 }
 
 /// Inner struct for probes/maintenance shared state.
 pub struct ProbeStateInner {
     /// Maintenance mode flag.
     in_maintenance: AtomicBool,
+    /// Warmup completion flag.
+    warmed_up: AtomicBool,
+    /// Lifecycle stage, stored as [`Lifecycle`] discriminant.
+    lifecycle: AtomicU8,
     /// Optional runtime watchdog for use in liveness probes.
     watchdog: Option<Watchdog>,
+    /// Retry advice configuration, used while in maintenance mode.
+    retry_advice: RetryAdviceConfig,
+    /// Custom readiness checks.
+    checks: Vec<ReadinessCheck>,
+    /// Timeout for a single custom readiness check.
+    check_timeout: Duration,
+    /// Registry of runtime handler state flags.
+    handler_toggles: HandlerToggles,
+}
+
+impl ProbeStateInner {
+    /// Set lifecycle stage of this state only.
+    pub(crate) fn set_lifecycle(&self, lifecycle: Lifecycle) {
+        self.lifecycle.store(lifecycle as u8, Ordering::Relaxed);
+    }
+}
+
+/// Custom readiness check function.
+type CheckFn = Box<dyn Fn(Deadline) -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+/// Custom readiness check specification.
+#[derive(Clone, Debug)]
+pub struct ReadinessCheckSpec {
+    /// Check name.
+    name: String,
+    /// Failure of optional check does not affect readiness.
+    optional: bool,
+}
+
+impl ReadinessCheckSpec {
+    /// Create new mandatory check specification.
+    #[must_use]
+    pub fn new(name: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            optional: false,
+        }
+    }
+
+    /// Mark check as optional.
+    ///
+    /// Failed optional checks are reported in readiness probe response, but do not affect its
+    /// status code.
+    #[must_use]
+    pub fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+}
+
+impl From<&str> for ReadinessCheckSpec {
+    fn from(value: &str) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<String> for ReadinessCheckSpec {
+    fn from(value: String) -> Self {
+        Self::new(value)
+    }
+}
+
+/// Custom readiness check.
+pub(crate) struct ReadinessCheck {
+    /// Check name.
+    name: String,
+    /// Failure of optional check does not affect readiness.
+    optional: bool,
+    /// Check function.
+    check: CheckFn,
+}
+
+impl fmt::Debug for ReadinessCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadinessCheck")
+            .field("name", &self.name)
+            .field("optional", &self.optional)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ReadinessCheck {
+    /// Create new custom readiness check.
+    pub(crate) fn new<F, Fut>(spec: ReadinessCheckSpec, check: F) -> Self
+    where
+        F: Fn(Deadline) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        Self {
+            name: spec.name,
+            optional: spec.optional,
+            check: Box::new(move |deadline| Box::pin(check(deadline))),
+        }
+    }
+}
+
+/// Outcome of a custom readiness check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum CheckStatus {
+    /// Check succeeded.
+    Ok,
+    /// Check returned an error.
+    Failed,
+    /// Check did not finish in time.
+    TimedOut,
+}
+
+/// Result of a custom readiness check.
+///
+/// Check errors are logged, but not included, as probes are not authenticated.
+#[derive(Debug, Serialize)]
+struct CheckResult {
+    /// Check outcome.
+    status: CheckStatus,
+    /// Failure of optional check does not affect readiness.
+    optional: bool,
+    /// Check duration, in seconds.
+    latency: f64,
+}
+
+impl CheckResult {
+    /// Whether this result makes service not ready.
+    fn is_blocking(&self) -> bool {
+        !self.optional && self.status != CheckStatus::Ok
+    }
+}
+
+/// Readiness probe query parameters.
+#[derive(Debug, Default, Deserialize)]
+struct ReadinessQuery {
+    /// Include detailed status in response body.
+    #[serde(default)]
+    verbose: Option<String>,
+}
+
+impl ReadinessQuery {
+    /// Check whether detailed status was requested.
+    fn is_verbose(&self) -> bool {
+        self.verbose
+            .as_deref()
+            .is_some_and(|val| !matches!(val, "0" | "false" | "no"))
+    }
+}
+
+/// Detailed readiness status.
+///
+/// Degraded optional subsystems do not affect readiness. Subsystem errors are not included, as
+/// probes are not authenticated.
+#[derive(Debug, Serialize)]
+struct ReadinessStatus {
+    /// Overall readiness.
+    ready: bool,
+    /// Lifecycle stage.
+    lifecycle: Lifecycle,
+    /// Maintenance mode flag.
+    maintenance: bool,
+    /// Warmup completion flag.
+    warmed_up: bool,
+    /// Some of the optional checks failed.
+    degraded: bool,
+    /// States of optional subsystems.
+    subsystems: BTreeMap<String, SubsystemState>,
+    /// Results of custom readiness checks.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    checks: BTreeMap<String, CheckResult>,
 }
 
 /// Readiness probe handler.
 ///
-/// For use in k8s-like deployments.
-async fn readiness_probe(state: State<ProbeState>) -> impl IntoResponse {
-    match state.in_maintenance.load(Ordering::Relaxed) {
-        true => StatusCode::SERVICE_UNAVAILABLE,
-        false => StatusCode::OK,
+/// For use in k8s-like deployments. Add `verbose=1` query parameter to get detailed status.
+/// Detailed status is always returned if custom readiness checks are registered.
+async fn readiness_probe(
+    state: State<ProbeState>,
+    query: Option<Query<ReadinessQuery>>,
+) -> impl IntoResponse {
+    let checks = state.run_checks().await;
+    let ready = state.is_ready() && !checks.values().any(CheckResult::is_blocking);
+    let mut resp = match ready {
+        false => {
+            let advice = state.retry_advice.advise(RetrySource::Maintenance, None);
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(RETRY_AFTER, advice.header_value())],
+            )
+                .into_response()
+        }
+        true => StatusCode::OK.into_response(),
+    };
+    if !checks.is_empty() || query.is_some_and(|Query(query)| query.is_verbose()) {
+        let status = ReadinessStatus {
+            ready,
+            lifecycle: state.lifecycle(),
+            maintenance: state.in_maintenance.load(Ordering::Relaxed),
+            warmed_up: state.warmed_up.load(Ordering::Relaxed),
+            degraded: checks.values().any(|check| check.status != CheckStatus::Ok),
+            checks,
+            subsystems: subsystem_statuses()
+                .into_iter()
+                .map(|(name, status)| (name, status.state))
+                .collect(),
+        };
+        let (parts, _) = resp.into_parts();
+        resp = (parts, Json(status)).into_response();
+    }
+    resp
+}
+
+/// Startup probe handler.
+///
+/// For use in k8s-like deployments. Succeeds once server is listening for requests.
+async fn startup_probe(state: State<ProbeState>) -> impl IntoResponse {
+    match state.lifecycle() {
+        Lifecycle::Starting => StatusCode::SERVICE_UNAVAILABLE,
+        Lifecycle::Ready | Lifecycle::Draining => StatusCode::OK,
     }
 }
 
@@ -206,3 +688,110 @@ async fn maintenance_off(state: State<ProbeState>) -> impl IntoResponse {
     }
     StatusCode::OK
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::auth::{NoOpAuthExtractor, NoOpAuthProvider};
+
+    /// Request readiness probe, returning status code and response body.
+    async fn ready(checks: Vec<ReadinessCheck>) -> (StatusCode, serde_json::Value) {
+        let config = ProbeConfig::default().with_check_timeout(Duration::from_millis(50));
+        let state = config.build_state(&Default::default()).with_checks(checks);
+        state.in_maintenance.store(false, Ordering::Relaxed);
+        state.set_lifecycle(Lifecycle::Ready);
+        let app = config.build_router(state, NoOpAuthProvider, NoOpAuthExtractor);
+        let req = Request::get("/healthz/ready").body(Body::empty()).unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        let status = resp.status();
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn passing_checks() {
+        let (status, body) =
+            ready(vec![ReadinessCheck::new("db".into(), |_| async { Ok(()) })]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["ready"], true);
+        assert_eq!(body["degraded"], false);
+        assert_eq!(body["checks"]["db"]["status"], "ok");
+        assert!(body["checks"]["db"]["latency"].is_number());
+    }
+
+    #[tokio::test]
+    async fn failed_mandatory_check() {
+        let (status, body) = ready(vec![
+            ReadinessCheck::new("db".into(), |_| async { Ok(()) }),
+            ReadinessCheck::new("slow".into(), |deadline: Deadline| async move {
+                tokio::time::sleep(deadline.time_left().unwrap_or_default() * 4).await;
+                Ok(())
+            }),
+        ])
+        .await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["ready"], false);
+        assert_eq!(body["checks"]["slow"]["status"], "timed_out");
+    }
+
+    #[tokio::test]
+    async fn failed_optional_check() {
+        let (status, body) = ready(vec![ReadinessCheck::new(
+            ReadinessCheckSpec::new("cache").optional(),
+            |_| async { Err("not connected".to_string()) },
+        )])
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["ready"], true);
+        assert_eq!(body["degraded"], true);
+        assert_eq!(body["checks"]["cache"]["status"], "failed");
+        assert_eq!(body["checks"]["cache"]["optional"], true);
+    }
+
+    #[tokio::test]
+    async fn no_checks() {
+        let (status, body) = ready(Vec::new()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.is_null());
+    }
+
+    #[tokio::test]
+    async fn lifecycle() {
+        async fn status(app: &Router, path: &str) -> StatusCode {
+            let req = Request::get(path).body(Body::empty()).unwrap();
+            app.clone().oneshot(req).await.unwrap().status()
+        }
+
+        let config = ProbeConfig::default();
+        let state = config.build_state(&Default::default());
+        state.in_maintenance.store(false, Ordering::Relaxed);
+        let app = config.build_router(state.clone(), NoOpAuthProvider, NoOpAuthExtractor);
+        assert_eq!(state.lifecycle(), Lifecycle::Starting);
+        assert_eq!(
+            status(&app, "/healthz/startup").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            status(&app, "/healthz/ready").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(status(&app, "/healthz/live").await, StatusCode::OK);
+
+        state.set_lifecycle(Lifecycle::Ready);
+        assert_eq!(status(&app, "/healthz/startup").await, StatusCode::OK);
+        assert_eq!(status(&app, "/healthz/ready").await, StatusCode::OK);
+
+        state.set_lifecycle(Lifecycle::Draining);
+        assert_eq!(status(&app, "/healthz/startup").await, StatusCode::OK);
+        assert_eq!(
+            status(&app, "/healthz/ready").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(status(&app, "/healthz/live").await, StatusCode::OK);
+    }
+}
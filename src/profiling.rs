@@ -0,0 +1,480 @@
+//! Profiling and allocator introspection endpoints.
+
+use std::{
+    borrow::Borrow,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    error_handling::HandleErrorLayer,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing, Extension, Json, Router,
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tower::ServiceBuilder;
+use tracing::{debug_span, warn};
+
+use crate::{
+    auth::{AuthExtractor, AuthLayer, AuthProvider, UserId},
+    builder::app::error_handler,
+    errors::{codes, ErrorCode},
+    retry::{RetryAdvice, RetryAdviceConfig, RetrySource},
+};
+
+/// Error type returned by profiling endpoints.
+#[derive(Clone, Debug, Error)]
+#[non_exhaustive]
+pub enum ProfilingError {
+    /// Another request to the same endpoint is in progress.
+    #[error("Another profiling request is in progress")]
+    Busy {
+        /// Advice used for `Retry-After` header.
+        advice: RetryAdvice,
+    },
+    /// Endpoint was called too recently.
+    #[error("Profiling endpoint is cooling down")]
+    Cooldown {
+        /// Advice used for `Retry-After` header.
+        advice: RetryAdvice,
+    },
+    /// Heap profiling was not activated on process start.
+    #[error("Heap profiling is not active")]
+    NotActive,
+    /// Error while collecting profiling data.
+    #[error("Unable to collect profiling data: {0}")]
+    Collect(String),
+}
+
+impl ProfilingError {
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Busy { .. } | Self::Cooldown { .. } => codes::PROFILING_BUSY,
+            Self::NotActive => codes::PROFILING_NOT_ACTIVE,
+            Self::Collect(_) => codes::PROFILING_FAILED,
+        }
+    }
+}
+
+impl IntoResponse for ProfilingError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::Busy { .. } | Self::Cooldown { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::NotActive => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Collect(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let code = self.code();
+        let problem = code
+            .problem(status)
+            .with_type("tag:uxum.github.io,2024:profiling")
+            .with_title(self.to_string());
+        match self {
+            Self::Busy { advice } | Self::Cooldown { advice } => {
+                (code, advice.problem_response(problem))
+            }
+            _ => (code, problem.into_response()),
+        }
+        .into_response()
+    }
+}
+
+/// Profiling endpoints configuration.
+///
+/// Compiled in with `profiling` crate feature, and enabled at runtime by adding
+/// [`crate::AppConfig::profiling`] section. All endpoints require `profiling` permission. Each
+/// endpoint serves at most one request at a time, and rejects requests arriving within cooldown
+/// interval after the previous one.
+///
+/// * `GET <prefix>/tasks` returns Tokio runtime task counts as JSON.
+/// * `GET <prefix>/heap_profile` returns heap profile dump. Requires `jemalloc` crate feature.
+/// * `GET <prefix>/allocator_stats` returns jemalloc statistics as JSON. Requires `jemalloc`
+///   crate feature.
+///
+/// Allocator endpoints require the application to use jemalloc as its global allocator:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static GLOBAL: uxum::reexport::tikv_jemallocator::Jemalloc =
+///     uxum::reexport::tikv_jemallocator::Jemalloc;
+/// ```
+///
+/// Heap profiling must also be activated when starting the process, by setting
+/// `_RJEM_MALLOC_CONF=prof:true,lg_prof_sample:19` environment variable. Sampling interval
+/// (`lg_prof_sample`, log base 2 of bytes) trades profile fidelity for overhead. Profiles are
+/// returned in jemalloc heap profile format, readable by `jeprof` and `pprof` tools.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct ProfilingConfig {
+    /// URL path prefix for profiling endpoints.
+    #[serde(default = "ProfilingConfig::default_prefix")]
+    prefix: String,
+    /// Minimum interval between consecutive calls to the same endpoint.
+    #[serde(
+        default = "ProfilingConfig::default_cooldown",
+        with = "humantime_serde"
+    )]
+    cooldown: Duration,
+}
+
+impl Default for ProfilingConfig {
+    fn default() -> Self {
+        Self {
+            prefix: Self::default_prefix(),
+            cooldown: Self::default_cooldown(),
+        }
+    }
+}
+
+impl ProfilingConfig {
+    /// Default value for [`Self::prefix`].
+    #[must_use]
+    #[inline]
+    fn default_prefix() -> String {
+        "/debug".into()
+    }
+
+    /// Default value for [`Self::cooldown`].
+    #[must_use]
+    #[inline]
+    fn default_cooldown() -> Duration {
+        Duration::from_secs(10)
+    }
+
+    /// Set URL path prefix for profiling endpoints.
+    #[must_use]
+    pub fn with_prefix(mut self, prefix: impl ToString) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// Set minimum interval between consecutive calls to the same endpoint.
+    #[must_use]
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Build Axum router containing profiling endpoints.
+    pub(crate) fn build_router<AuthProv, AuthExt>(
+        &self,
+        retry_advice: &RetryAdviceConfig,
+        auth_provider: AuthProv,
+        auth_extractor: AuthExt,
+    ) -> Router
+    where
+        AuthProv: AuthProvider + Sync + 'static,
+        AuthExt: AuthExtractor + Sync + 'static,
+        AuthExt::User: Borrow<AuthProv::User>,
+        AuthExt::AuthTokens: Borrow<AuthProv::AuthTokens>,
+    {
+        let _span = debug_span!("build_profiling").entered();
+        let prefix = self.prefix.trim_end_matches('/');
+        let state = ProfilingState {
+            tasks: Arc::new(EndpointGuard::new(self.cooldown, retry_advice)),
+            #[cfg(feature = "jemalloc")]
+            heap_profile: Arc::new(EndpointGuard::new(self.cooldown, retry_advice)),
+            #[cfg(feature = "jemalloc")]
+            allocator_stats: Arc::new(EndpointGuard::new(self.cooldown, retry_advice)),
+        };
+        let rtr = Router::new().route(&format!("{prefix}/tasks"), routing::get(get_tasks));
+        #[cfg(feature = "jemalloc")]
+        let rtr = rtr
+            .route(
+                &format!("{prefix}/heap_profile"),
+                routing::get(jemalloc::get_heap_profile),
+            )
+            .route(
+                &format!("{prefix}/allocator_stats"),
+                routing::get(jemalloc::get_allocator_stats),
+            );
+        rtr.layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(error_handler))
+                .layer(AuthLayer::new(
+                    &["profiling"],
+                    auth_provider,
+                    auth_extractor,
+                )),
+        )
+        .with_state(state)
+    }
+}
+
+/// Concurrency and rate guard for a single endpoint.
+#[derive(Debug)]
+struct EndpointGuard {
+    /// Request is in progress.
+    busy: AtomicBool,
+    /// Time of last accepted request.
+    last: Mutex<Option<Instant>>,
+    /// Minimum interval between requests.
+    cooldown: Duration,
+    /// Retry advice configuration.
+    retry: RetryAdviceConfig,
+}
+
+impl EndpointGuard {
+    /// Create new endpoint guard.
+    fn new(cooldown: Duration, retry: &RetryAdviceConfig) -> Self {
+        Self {
+            busy: AtomicBool::new(false),
+            last: Mutex::new(None),
+            cooldown,
+            retry: retry.clone(),
+        }
+    }
+
+    /// Try to start processing a request.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if another request is in progress, or if cooldown interval has not passed.
+    fn acquire(&self) -> Result<EndpointPermit<'_>, ProfilingError> {
+        if self.busy.swap(true, Ordering::AcqRel) {
+            return Err(ProfilingError::Busy {
+                advice: self.retry.advise(RetrySource::Profiling, None),
+            });
+        }
+        let permit = EndpointPermit(self);
+        let mut last = self.last.lock();
+        let now = Instant::now();
+        if let Some(elapsed) = last.map(|last| now.duration_since(last)) {
+            if elapsed < self.cooldown {
+                return Err(ProfilingError::Cooldown {
+                    advice: self
+                        .retry
+                        .advise(RetrySource::Profiling, Some(self.cooldown - elapsed)),
+                });
+            }
+        }
+        *last = Some(now);
+        Ok(permit)
+    }
+}
+
+/// Permit to process a request, released on drop.
+struct EndpointPermit<'a>(&'a EndpointGuard);
+
+impl Drop for EndpointPermit<'_> {
+    fn drop(&mut self) {
+        self.0.busy.store(false, Ordering::Release);
+    }
+}
+
+/// Shared state of profiling endpoints.
+#[derive(Clone, Debug)]
+struct ProfilingState {
+    /// Guard for task summary endpoint.
+    tasks: Arc<EndpointGuard>,
+    /// Guard for heap profile endpoint.
+    #[cfg(feature = "jemalloc")]
+    heap_profile: Arc<EndpointGuard>,
+    /// Guard for allocator statistics endpoint.
+    #[cfg(feature = "jemalloc")]
+    allocator_stats: Arc<EndpointGuard>,
+}
+
+/// Write audit log record for profiling request.
+fn audit(endpoint: &str, user: Option<&UserId>) {
+    warn!(
+        endpoint,
+        user = user.map(|user| user.as_str()),
+        "profiling endpoint requested"
+    );
+}
+
+/// Summary of Tokio runtime tasks.
+#[derive(Clone, Debug, Serialize)]
+struct TaskSummary {
+    /// Number of worker threads.
+    workers: usize,
+    /// Number of alive tasks.
+    alive_tasks: usize,
+    /// Number of tasks in global queue.
+    global_queue_depth: usize,
+}
+
+/// Summarize Tokio runtime task counts.
+async fn get_tasks(
+    State(state): State<ProfilingState>,
+    user: Option<Extension<UserId>>,
+) -> Result<Json<TaskSummary>, ProfilingError> {
+    audit("tasks", user.as_deref());
+    let _permit = state.tasks.acquire()?;
+    let metrics = tokio::runtime::Handle::current().metrics();
+    Ok(Json(TaskSummary {
+        workers: metrics.num_workers(),
+        alive_tasks: metrics.num_alive_tasks(),
+        global_queue_depth: metrics.global_queue_depth(),
+    }))
+}
+
+/// Endpoints specific to jemalloc allocator.
+#[cfg(feature = "jemalloc")]
+mod jemalloc {
+    use std::{env, sync::OnceLock};
+
+    use axum::http::{header, HeaderValue};
+
+    use super::*;
+
+    /// Path to temporary heap profile file, as a null-terminated string.
+    fn dump_path() -> &'static [u8] {
+        static DUMP_PATH: OnceLock<Vec<u8>> = OnceLock::new();
+        DUMP_PATH.get_or_init(|| {
+            let path = env::temp_dir().join(format!("uxum-heap-{}.prof", std::process::id()));
+            let mut bytes = path.to_string_lossy().into_owned().into_bytes();
+            bytes.push(0);
+            bytes
+        })
+    }
+
+    /// Dump heap profile.
+    pub(super) async fn get_heap_profile(
+        State(state): State<ProfilingState>,
+        user: Option<Extension<UserId>>,
+    ) -> Result<impl IntoResponse, ProfilingError> {
+        audit("heap_profile", user.as_deref());
+        let _permit = state.heap_profile.acquire()?;
+        let active = tikv_jemalloc_ctl::profiling::prof::read().unwrap_or(false);
+        if !active {
+            return Err(ProfilingError::NotActive);
+        }
+        let path = dump_path();
+        tikv_jemalloc_ctl::raw::write_str(b"prof.dump\0", path)
+            .map_err(|err| ProfilingError::Collect(err.to_string()))?;
+        let fs_path = String::from_utf8_lossy(&path[..path.len() - 1]).into_owned();
+        let profile = tokio::fs::read(&fs_path)
+            .await
+            .map_err(|err| ProfilingError::Collect(err.to_string()))?;
+        let _ = tokio::fs::remove_file(&fs_path).await;
+        Ok((
+            [(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/octet-stream"),
+            )],
+            profile,
+        ))
+    }
+
+    /// Get allocator statistics as JSON.
+    pub(super) async fn get_allocator_stats(
+        State(state): State<ProfilingState>,
+        user: Option<Extension<UserId>>,
+    ) -> Result<impl IntoResponse, ProfilingError> {
+        audit("allocator_stats", user.as_deref());
+        let _permit = state.allocator_stats.acquire()?;
+        let stats = tokio::task::spawn_blocking(|| {
+            let mut opts = tikv_jemalloc_ctl::stats_print::Options::default();
+            opts.json_format = true;
+            opts.skip_per_arena = true;
+            let mut buf = Vec::new();
+            tikv_jemalloc_ctl::stats_print::stats_print(&mut buf, opts).map(|()| buf)
+        })
+        .await
+        .map_err(|err| ProfilingError::Collect(err.to_string()))?
+        .map_err(|err| ProfilingError::Collect(err.to_string()))?;
+        Ok((
+            [(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(mime::APPLICATION_JSON.as_ref()),
+            )],
+            stats,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{header, Request},
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::auth::{NoOpAuthExtractor, NoOpAuthProvider};
+
+    fn app(cooldown: Duration) -> Router {
+        ProfilingConfig::default()
+            .with_cooldown(cooldown)
+            .build_router(
+                &RetryAdviceConfig::default().with_jitter(0.0),
+                NoOpAuthProvider,
+                NoOpAuthExtractor,
+            )
+    }
+
+    async fn call(rtr: &Router, path: &str) -> Response {
+        rtr.clone()
+            .oneshot(Request::get(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    /// Task summary is returned as JSON.
+    #[tokio::test]
+    async fn tasks() {
+        let rtr = app(Duration::ZERO);
+        let resp = call(&rtr, "/debug/tasks").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/json");
+    }
+
+    /// Repeated calls within cooldown interval are rejected.
+    #[tokio::test]
+    async fn cooldown() {
+        let rtr = app(Duration::from_secs(60));
+        assert_eq!(call(&rtr, "/debug/tasks").await.status(), StatusCode::OK);
+        let resp = call(&rtr, "/debug/tasks").await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers()[header::RETRY_AFTER], "60");
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(body["retry_after_ms"].as_u64().unwrap() > 59_000, "{body}");
+    }
+
+    /// Concurrent requests are rejected.
+    #[test]
+    fn concurrency() {
+        let retry = RetryAdviceConfig::default()
+            .with_jitter(0.0)
+            .with_base(RetrySource::RateLimit, Duration::from_secs(60))
+            .with_base(RetrySource::Profiling, Duration::from_secs(4));
+        let guard = EndpointGuard::new(Duration::ZERO, &retry);
+        let permit = guard.acquire().unwrap();
+        let Err(ProfilingError::Busy { advice }) = guard.acquire() else {
+            panic!("concurrent request was not rejected");
+        };
+        assert_eq!(advice.delay(), Duration::from_secs(4));
+        drop(permit);
+        assert!(guard.acquire().is_ok());
+    }
+
+    /// Allocator endpoints respond with expected content types.
+    #[cfg(feature = "jemalloc")]
+    #[tokio::test]
+    async fn jemalloc_endpoints() {
+        let rtr = app(Duration::ZERO);
+        let resp = call(&rtr, "/debug/allocator_stats").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/json");
+        let resp = call(&rtr, "/debug/heap_profile").await;
+        let content_type = match resp.status() {
+            StatusCode::OK => "application/octet-stream",
+            _ => "application/problem+json",
+        };
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], content_type);
+    }
+}
@@ -0,0 +1,792 @@
+//! Embedded asynchronous job queue, backed by a [`KeyValueStore`].
+//!
+//! Jobs are enqueued with [`enqueue`], and processed by handlers registered with
+//! [`crate::AppBuilder::with_job_handler`].
+//!
+//! # Delivery semantics
+//!
+//! Jobs are delivered **at least once**. A claimed job becomes invisible to other workers for
+//! the duration of visibility timeout, and is only removed from the store after its handler
+//! succeeds. If the process crashes, or a handler runs longer than visibility timeout, the job
+//! is delivered again. Job handlers must therefore be idempotent.
+//!
+//! Failed jobs are retried with exponential backoff. After reaching maximum number of attempts,
+//! jobs are moved to the dead-letter namespace, where they can be inspected and requeued using
+//! management endpoints.
+//!
+//! Claims are serialized within a single process only, since [`KeyValueStore`] provides no
+//! atomic compare-and-swap. Sharing a store between several instances preserves at-least-once
+//! delivery, but increases the chance of duplicate deliveries.
+
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    fmt,
+    future::Future,
+    num::{NonZeroU32, NonZeroUsize},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use axum::{
+    error_handling::HandleErrorLayer,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing, Json, Router,
+};
+use futures::future::BoxFuture;
+use once_cell::sync::Lazy;
+use opentelemetry::KeyValue;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{
+    sync::{Notify, Semaphore},
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+use tower::{BoxError, ServiceBuilder};
+use tracing::{debug_span, error, info, warn};
+
+use crate::{
+    auth::{AuthExtractor, AuthLayer, AuthProvider},
+    builder::app::error_handler,
+    errors::{codes, ErrorCode},
+    kv::{KeyValueStore, KvError},
+    metrics::JobQueueMetrics,
+};
+
+/// Namespace of pending jobs in key-value store.
+const JOB_PREFIX: &str = "queue/job/";
+
+/// Namespace of dead-lettered jobs in key-value store.
+const DEAD_PREFIX: &str = "queue/dead/";
+
+/// Error type used in job queue.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum QueueError {
+    /// Job queue is not configured.
+    #[error("Job queue is not configured")]
+    NotConfigured,
+    /// No handler registered for job type.
+    #[error("No handler registered for job type {0}")]
+    UnknownJobType(String),
+    /// No dead-lettered job with provided ID.
+    #[error("Job not found: {0}")]
+    NotFound(String),
+    /// Unable to encode job payload.
+    #[error("Unable to encode job payload: {0}")]
+    Payload(#[from] serde_json::Error),
+    /// Key-value store error.
+    #[error(transparent)]
+    Store(#[from] KvError),
+}
+
+impl QueueError {
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::NotConfigured => codes::QUEUE_NOT_CONFIGURED,
+            Self::UnknownJobType(_) => codes::QUEUE_UNKNOWN_JOB_TYPE,
+            Self::NotFound(_) => codes::QUEUE_NOT_FOUND,
+            Self::Payload(_) => codes::QUEUE_INVALID_PAYLOAD,
+            Self::Store(_) => codes::QUEUE_INTERNAL,
+        }
+    }
+}
+
+impl IntoResponse for QueueError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            Self::NotConfigured => StatusCode::SERVICE_UNAVAILABLE,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::UnknownJobType(_) | Self::Payload(_) => StatusCode::BAD_REQUEST,
+            Self::Store(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let code = self.code();
+        let problem = code
+            .problem(status)
+            .with_type("tag:uxum.github.io,2024:queue")
+            .with_title(self.to_string());
+        (code, problem).into_response()
+    }
+}
+
+/// Job queue configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct JobQueueConfig {
+    /// Maximum number of concurrently processed jobs.
+    #[serde(default = "JobQueueConfig::default_concurrency")]
+    pub concurrency: NonZeroUsize,
+    /// Interval between polls of the store, when there are no visible jobs.
+    #[serde(
+        default = "JobQueueConfig::default_poll_interval",
+        with = "humantime_serde"
+    )]
+    pub poll_interval: Duration,
+    /// Time during which a claimed job is invisible to other workers.
+    ///
+    /// Should be longer than the slowest expected job, otherwise the job is delivered again
+    /// while still being processed.
+    #[serde(
+        default = "JobQueueConfig::default_visibility_timeout",
+        with = "humantime_serde"
+    )]
+    pub visibility_timeout: Duration,
+    /// Maximum number of delivery attempts, after which a job is dead-lettered.
+    #[serde(default = "JobQueueConfig::default_max_attempts")]
+    pub max_attempts: NonZeroU32,
+    /// Delay before the first retry, doubled after each subsequent failure.
+    #[serde(
+        default = "JobQueueConfig::default_initial_backoff",
+        with = "humantime_serde"
+    )]
+    pub initial_backoff: Duration,
+    /// Upper bound of delay between retries.
+    #[serde(
+        default = "JobQueueConfig::default_max_backoff",
+        with = "humantime_serde"
+    )]
+    pub max_backoff: Duration,
+    /// URL path of dead-letter management endpoints.
+    #[serde(default = "JobQueueConfig::default_path")]
+    pub path: String,
+}
+
+impl Default for JobQueueConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: Self::default_concurrency(),
+            poll_interval: Self::default_poll_interval(),
+            visibility_timeout: Self::default_visibility_timeout(),
+            max_attempts: Self::default_max_attempts(),
+            initial_backoff: Self::default_initial_backoff(),
+            max_backoff: Self::default_max_backoff(),
+            path: Self::default_path(),
+        }
+    }
+}
+
+impl JobQueueConfig {
+    /// Permission required to use dead-letter management endpoints.
+    pub const PERMISSION: &'static str = "queue";
+
+    /// Default value for [`Self::concurrency`].
+    #[must_use]
+    #[inline]
+    fn default_concurrency() -> NonZeroUsize {
+        // SAFETY: value is non-zero.
+        NonZeroUsize::new(4).unwrap()
+    }
+
+    /// Default value for [`Self::poll_interval`].
+    #[must_use]
+    #[inline]
+    fn default_poll_interval() -> Duration {
+        Duration::from_secs(1)
+    }
+
+    /// Default value for [`Self::visibility_timeout`].
+    #[must_use]
+    #[inline]
+    fn default_visibility_timeout() -> Duration {
+        Duration::from_secs(30)
+    }
+
+    /// Default value for [`Self::max_attempts`].
+    #[must_use]
+    #[inline]
+    fn default_max_attempts() -> NonZeroU32 {
+        // SAFETY: value is non-zero.
+        NonZeroU32::new(5).unwrap()
+    }
+
+    /// Default value for [`Self::initial_backoff`].
+    #[must_use]
+    #[inline]
+    fn default_initial_backoff() -> Duration {
+        Duration::from_secs(1)
+    }
+
+    /// Default value for [`Self::max_backoff`].
+    #[must_use]
+    #[inline]
+    fn default_max_backoff() -> Duration {
+        Duration::from_secs(300)
+    }
+
+    /// Default value for [`Self::path`].
+    #[must_use]
+    #[inline]
+    fn default_path() -> String {
+        "/queue/dead".into()
+    }
+
+    /// Delay before next attempt, after `attempts` failed attempts.
+    #[must_use]
+    fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(attempts.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// Queued job.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct Job {
+    /// Unique job ID.
+    pub id: String,
+    /// Job type, used to find job handler.
+    pub job_type: String,
+    /// Job payload.
+    pub payload: serde_json::Value,
+    /// Number of delivery attempts made so far, including the current one.
+    pub attempts: u32,
+    /// Time of enqueueing.
+    #[serde(with = "humantime_serde")]
+    pub enqueued_at: SystemTime,
+    /// Time after which the job can be claimed by a worker.
+    #[serde(with = "humantime_serde")]
+    pub visible_at: SystemTime,
+    /// Error returned by last failed attempt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Job handler function.
+type JobFn = dyn Fn(Job) -> BoxFuture<'static, Result<(), BoxError>> + Send + Sync;
+
+/// Registered job handler.
+#[derive(Clone)]
+pub(crate) struct JobHandler(Arc<JobFn>);
+
+impl fmt::Debug for JobHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JobHandler").finish_non_exhaustive()
+    }
+}
+
+impl JobHandler {
+    /// Wrap async function into job handler.
+    pub(crate) fn new<F, Fut, E>(func: F) -> Self
+    where
+        F: Fn(Job) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<BoxError>,
+    {
+        Self(Arc::new(move |job| {
+            let fut = func(job);
+            Box::pin(async move { fut.await.map_err(Into::into) })
+        }))
+    }
+}
+
+/// Globally registered job queue.
+static QUEUE: Lazy<RwLock<Option<JobQueue>>> = Lazy::new(Default::default);
+
+/// Enqueue a job using globally registered job queue.
+///
+/// Job becomes visible to workers after `delay`. Returns ID of the new job.
+///
+/// # Errors
+///
+/// Returns `Err` if job queue is not configured, no handler is registered for `job_type`, or the
+/// job cannot be persisted.
+pub fn enqueue<T: Serialize>(
+    job_type: &str,
+    payload: &T,
+    delay: Duration,
+) -> Result<String, QueueError> {
+    let queue = QUEUE.read().clone().ok_or(QueueError::NotConfigured)?;
+    queue.enqueue(job_type, payload, delay)
+}
+
+/// Stop claiming new jobs, and wait for in-flight jobs to finish.
+///
+/// Does nothing if job queue is not configured.
+pub(crate) async fn drain(timeout: Option<Duration>) {
+    let queue = QUEUE.read().clone();
+    if let Some(queue) = queue {
+        queue.drain(timeout).await;
+    }
+}
+
+/// Outcome of a failed attempt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FailOutcome {
+    /// Job will be retried.
+    Retry,
+    /// Job was moved to dead-letter namespace.
+    DeadLetter,
+}
+
+/// Job queue.
+#[derive(Clone)]
+pub struct JobQueue(Arc<JobQueueInner>);
+
+/// Inner container for [`JobQueue`].
+struct JobQueueInner {
+    /// Queue configuration.
+    config: JobQueueConfig,
+    /// Job storage.
+    store: Arc<dyn KeyValueStore>,
+    /// Job handlers, keyed by job type.
+    handlers: HashMap<String, JobHandler>,
+    /// Job queue metrics.
+    metrics: Option<JobQueueMetrics>,
+    /// Lock serializing claims.
+    claim_lock: Mutex<()>,
+    /// Wakes up idle workers when new job is enqueued.
+    notify: Notify,
+    /// Cancelled when queue is draining.
+    shutdown: CancellationToken,
+    /// Permits for concurrently processed jobs.
+    slots: Arc<Semaphore>,
+}
+
+impl fmt::Debug for JobQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JobQueue")
+            .field("config", &self.0.config)
+            .field("job_types", &self.0.handlers.keys())
+            .finish_non_exhaustive()
+    }
+}
+
+impl JobQueue {
+    /// Create new job queue.
+    #[must_use]
+    pub(crate) fn new(
+        config: JobQueueConfig,
+        store: Arc<dyn KeyValueStore>,
+        handlers: HashMap<String, JobHandler>,
+        metrics: Option<JobQueueMetrics>,
+    ) -> Self {
+        let slots = Arc::new(Semaphore::new(config.concurrency.get()));
+        Self(Arc::new(JobQueueInner {
+            config,
+            store,
+            handlers,
+            metrics,
+            claim_lock: Mutex::new(()),
+            notify: Notify::new(),
+            shutdown: CancellationToken::new(),
+            slots,
+        }))
+    }
+
+    /// Register as a global instance, used by [`enqueue`].
+    pub(crate) fn register(&self) {
+        *QUEUE.write() = Some(self.clone());
+    }
+
+    /// Metric labels for a job type.
+    fn labels(job_type: &str) -> [KeyValue; 1] {
+        [KeyValue::new("uxum.job_type", job_type.to_string())]
+    }
+
+    /// Enqueue a job.
+    ///
+    /// Job becomes visible to workers after `delay`. Returns ID of the new job.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if no handler is registered for `job_type`, or the job cannot be persisted.
+    pub fn enqueue<T: Serialize>(
+        &self,
+        job_type: &str,
+        payload: &T,
+        delay: Duration,
+    ) -> Result<String, QueueError> {
+        if !self.0.handlers.contains_key(job_type) {
+            return Err(QueueError::UnknownJobType(job_type.to_string()));
+        }
+        let now = SystemTime::now();
+        let job = Job {
+            id: uuid::Uuid::new_v4().to_string(),
+            job_type: job_type.to_string(),
+            payload: serde_json::to_value(payload)?,
+            attempts: 0,
+            enqueued_at: now,
+            visible_at: now + delay,
+            last_error: None,
+        };
+        self.put(JOB_PREFIX, &job)?;
+        if let Some(metrics) = &self.0.metrics {
+            metrics.enqueued.add(1, &Self::labels(job_type));
+        }
+        if delay.is_zero() {
+            self.0.notify.notify_one();
+        }
+        Ok(job.id)
+    }
+
+    /// Write job record to store.
+    fn put(&self, prefix: &str, job: &Job) -> Result<(), KvError> {
+        self.0.store.put(
+            &format!("{prefix}{}", job.id),
+            serde_json::to_vec(job)?.into(),
+        )
+    }
+
+    /// Read all job records in a namespace.
+    fn scan(&self, prefix: &str) -> Result<Vec<Job>, KvError> {
+        let mut jobs = Vec::new();
+        for (key, raw) in self.0.store.scan_prefix(prefix)? {
+            match serde_json::from_slice(&raw) {
+                Ok(job) => jobs.push(job),
+                Err(err) => warn!(key, %err, "skipping malformed job record"),
+            }
+        }
+        Ok(jobs)
+    }
+
+    /// Claim the oldest visible job, hiding it from other workers for visibility timeout.
+    fn claim(&self, now: SystemTime) -> Result<Option<Job>, KvError> {
+        let _lock = self.0.claim_lock.lock();
+        let next = self
+            .scan(JOB_PREFIX)?
+            .into_iter()
+            .filter(|job| job.visible_at <= now)
+            .min_by_key(|job| job.visible_at);
+        let Some(mut job) = next else {
+            return Ok(None);
+        };
+        job.attempts += 1;
+        job.visible_at = now + self.0.config.visibility_timeout;
+        self.put(JOB_PREFIX, &job)?;
+        Ok(Some(job))
+    }
+
+    /// Remove successfully processed job.
+    fn complete(&self, job: &Job) -> Result<(), KvError> {
+        self.0.store.delete(&format!("{JOB_PREFIX}{}", job.id))?;
+        Ok(())
+    }
+
+    /// Schedule failed job for retry, or move it to dead-letter namespace.
+    fn fail(&self, mut job: Job, error: String, now: SystemTime) -> Result<FailOutcome, KvError> {
+        job.last_error = Some(error);
+        if job.attempts >= self.0.config.max_attempts.get() {
+            self.put(DEAD_PREFIX, &job)?;
+            self.0.store.delete(&format!("{JOB_PREFIX}{}", job.id))?;
+            return Ok(FailOutcome::DeadLetter);
+        }
+        job.visible_at = now + self.0.config.backoff(job.attempts);
+        self.put(JOB_PREFIX, &job)?;
+        Ok(FailOutcome::Retry)
+    }
+
+    /// Get all dead-lettered jobs.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if jobs cannot be read from store.
+    pub fn dead_letters(&self) -> Result<Vec<Job>, QueueError> {
+        let mut jobs = self.scan(DEAD_PREFIX)?;
+        jobs.sort_by_key(|job| job.enqueued_at);
+        Ok(jobs)
+    }
+
+    /// Move dead-lettered job back to the queue, resetting its attempt counter.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if there is no such job, or it cannot be moved.
+    pub fn requeue(&self, id: &str) -> Result<Job, QueueError> {
+        let key = format!("{DEAD_PREFIX}{id}");
+        let raw = self
+            .0
+            .store
+            .get(&key)?
+            .ok_or_else(|| QueueError::NotFound(id.to_string()))?;
+        let mut job: Job = serde_json::from_slice(&raw).map_err(KvError::from)?;
+        job.attempts = 0;
+        job.visible_at = SystemTime::now();
+        self.put(JOB_PREFIX, &job)?;
+        self.0.store.delete(&key)?;
+        self.0.notify.notify_one();
+        Ok(job)
+    }
+
+    /// Run job handler, and record its outcome.
+    async fn process(&self, job: Job) {
+        let start = Instant::now();
+        let labels = Self::labels(&job.job_type);
+        let result = match self.0.handlers.get(&job.job_type) {
+            Some(handler) => match tokio::spawn((handler.0)(job.clone())).await {
+                Ok(res) => res.map_err(|err| err.to_string()),
+                Err(err) => Err(err.to_string()),
+            },
+            None => Err(QueueError::UnknownJobType(job.job_type.clone()).to_string()),
+        };
+        if let Some(metrics) = &self.0.metrics {
+            metrics
+                .duration
+                .record(start.elapsed().as_secs_f64(), &labels);
+        }
+        let (id, job_type, attempts) = (job.id.clone(), job.job_type.clone(), job.attempts);
+        let outcome = match result {
+            Ok(()) => self.complete(&job).map(|()| None),
+            Err(err) => {
+                warn!(id, job_type, attempts, %err, "job failed");
+                self.fail(job, err, SystemTime::now()).map(Some)
+            }
+        };
+        let Some(metrics) = &self.0.metrics else {
+            return;
+        };
+        match outcome {
+            Ok(None) => metrics.processed.add(1, &labels),
+            Ok(Some(FailOutcome::Retry)) => metrics.retried.add(1, &labels),
+            Ok(Some(FailOutcome::DeadLetter)) => {
+                error!(id, job_type, attempts, "job dead-lettered");
+                metrics.dead_lettered.add(1, &labels);
+            }
+            Err(err) => error!(id, job_type, %err, "unable to record job outcome"),
+        }
+    }
+
+    /// Spawn worker task, claiming and processing jobs until queue is drained.
+    pub(crate) fn spawn_workers(&self) -> JoinHandle<()> {
+        let queue = self.clone();
+        tokio::spawn(async move {
+            let inner = &queue.0;
+            loop {
+                let permit = tokio::select! {
+                    () = inner.shutdown.cancelled() => break,
+                    permit = Arc::clone(&inner.slots).acquire_owned() => match permit {
+                        Ok(permit) => permit,
+                        Err(_) => break,
+                    },
+                };
+                match queue.claim(SystemTime::now()) {
+                    Ok(Some(job)) => {
+                        let queue = queue.clone();
+                        tokio::spawn(async move {
+                            queue.process(job).await;
+                            drop(permit);
+                        });
+                        continue;
+                    }
+                    Ok(None) => {}
+                    Err(err) => warn!(%err, "unable to claim job"),
+                }
+                drop(permit);
+                tokio::select! {
+                    () = inner.shutdown.cancelled() => break,
+                    () = inner.notify.notified() => {}
+                    () = tokio::time::sleep(inner.config.poll_interval) => {}
+                }
+            }
+        })
+    }
+
+    /// Stop claiming new jobs, and wait for in-flight jobs to finish.
+    ///
+    /// Jobs still running after `timeout` are delivered again after visibility timeout.
+    pub(crate) async fn drain(&self, timeout: Option<Duration>) {
+        self.0.shutdown.cancel();
+        let slots = u32::try_from(self.0.config.concurrency.get()).unwrap_or(u32::MAX);
+        let wait = self.0.slots.acquire_many(slots);
+        let drained = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, wait).await.is_ok(),
+            None => {
+                let _ = wait.await;
+                true
+            }
+        };
+        match drained {
+            true => info!("job queue drained"),
+            false => warn!("job queue drain timed out, unfinished jobs will be redelivered"),
+        }
+    }
+
+    /// Build Axum router containing dead-letter management endpoints.
+    pub(crate) fn build_router<AuthProv, AuthExt>(
+        &self,
+        auth_provider: AuthProv,
+        auth_extractor: AuthExt,
+    ) -> Router
+    where
+        AuthProv: AuthProvider + Sync + 'static,
+        AuthExt: AuthExtractor + Sync + 'static,
+        AuthExt::User: Borrow<AuthProv::User>,
+        AuthExt::AuthTokens: Borrow<AuthProv::AuthTokens>,
+    {
+        let _span = debug_span!("build_queue_router").entered();
+        let path = &self.0.config.path;
+        Router::new()
+            .route(path, routing::get(list_dead_letters))
+            .route(
+                &format!("{}/:id/requeue", path.trim_end_matches('/')),
+                routing::post(requeue_job),
+            )
+            .layer(
+                ServiceBuilder::new()
+                    .layer(HandleErrorLayer::new(error_handler))
+                    .layer(AuthLayer::new(
+                        &[JobQueueConfig::PERMISSION],
+                        auth_provider,
+                        auth_extractor,
+                    )),
+            )
+            .with_state(self.clone())
+    }
+}
+
+/// List dead-lettered jobs.
+async fn list_dead_letters(State(queue): State<JobQueue>) -> Result<Json<Vec<Job>>, QueueError> {
+    queue.dead_letters().map(Json)
+}
+
+/// Move dead-lettered job back to the queue.
+async fn requeue_job(
+    State(queue): State<JobQueue>,
+    Path(id): Path<String>,
+) -> Result<Json<Job>, QueueError> {
+    let job = queue.requeue(&id)?;
+    warn!(id, job_type = job.job_type, "dead-lettered job requeued");
+    Ok(Json(job))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::kv::MemoryStore;
+
+    fn queue(handlers: HashMap<String, JobHandler>) -> JobQueue {
+        let config = JobQueueConfig {
+            max_attempts: NonZeroU32::new(3).unwrap(),
+            poll_interval: Duration::from_millis(10),
+            initial_backoff: Duration::from_secs(2),
+            max_backoff: Duration::from_secs(3),
+            ..Default::default()
+        };
+        JobQueue::new(config, Arc::new(MemoryStore::new()), handlers, None)
+    }
+
+    fn noop() -> HashMap<String, JobHandler> {
+        HashMap::from([(
+            "noop".to_string(),
+            JobHandler::new(|_job| async { Ok::<_, BoxError>(()) }),
+        )])
+    }
+
+    /// Failed jobs are retried with exponential backoff, capped at maximum.
+    #[test]
+    fn retry_backoff() {
+        let queue = queue(noop());
+        assert_eq!(queue.0.config.backoff(1), Duration::from_secs(2));
+        assert_eq!(queue.0.config.backoff(2), Duration::from_secs(3));
+
+        queue.enqueue("noop", &1, Duration::ZERO).unwrap();
+        let now = SystemTime::now();
+        let job = queue.claim(now).unwrap().unwrap();
+        assert_eq!(job.attempts, 1);
+        assert_eq!(
+            queue.fail(job, "oops".into(), now).unwrap(),
+            FailOutcome::Retry
+        );
+        assert!(queue.claim(now + Duration::from_secs(1)).unwrap().is_none());
+        let job = queue.claim(now + Duration::from_secs(2)).unwrap().unwrap();
+        assert_eq!(job.attempts, 2);
+        assert_eq!(job.last_error.as_deref(), Some("oops"));
+        queue.complete(&job).unwrap();
+        assert!(queue
+            .claim(now + Duration::from_secs(60))
+            .unwrap()
+            .is_none());
+
+        assert!(matches!(
+            queue.enqueue("unknown", &1, Duration::ZERO),
+            Err(QueueError::UnknownJobType(_))
+        ));
+    }
+
+    /// Job claimed by a crashed worker is delivered again after visibility timeout.
+    #[test]
+    fn visibility_timeout_recovery() {
+        let queue = queue(noop());
+        let id = queue.enqueue("noop", &"payload", Duration::ZERO).unwrap();
+        let now = SystemTime::now();
+        let job = queue.claim(now).unwrap().unwrap();
+        assert_eq!(job.id, id);
+        // Simulated crash: job is neither completed nor failed.
+        drop(job);
+        assert!(queue
+            .claim(now + Duration::from_secs(29))
+            .unwrap()
+            .is_none());
+        let job = queue.claim(now + Duration::from_secs(30)).unwrap().unwrap();
+        assert_eq!(job.id, id);
+        assert_eq!(job.attempts, 2);
+        assert_eq!(job.payload, "payload");
+    }
+
+    /// Jobs are dead-lettered after maximum attempts, and can be requeued.
+    #[test]
+    fn dead_letter() {
+        let queue = queue(noop());
+        let id = queue.enqueue("noop", &1, Duration::ZERO).unwrap();
+        let mut now = SystemTime::now();
+        for attempt in 1..=3 {
+            let job = queue.claim(now).unwrap().unwrap();
+            let expected = match attempt {
+                3 => FailOutcome::DeadLetter,
+                _ => FailOutcome::Retry,
+            };
+            assert_eq!(queue.fail(job, "oops".into(), now).unwrap(), expected);
+            now += Duration::from_secs(60);
+        }
+        assert!(queue.claim(now).unwrap().is_none());
+        let dead = queue.dead_letters().unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].id, id);
+        assert_eq!(dead[0].attempts, 3);
+
+        let job = queue.requeue(&id).unwrap();
+        assert_eq!(job.attempts, 0);
+        assert!(queue.dead_letters().unwrap().is_empty());
+        assert!(queue.claim(now).unwrap().is_some());
+        assert!(matches!(
+            queue.requeue("missing"),
+            Err(QueueError::NotFound(_))
+        ));
+    }
+
+    /// Workers process jobs, and drain waits for in-flight jobs.
+    #[tokio::test]
+    async fn workers() {
+        let processed = Arc::new(AtomicU32::new(0));
+        let counter = processed.clone();
+        let queue = queue(HashMap::from([(
+            "count".to_string(),
+            JobHandler::new(move |job: Job| {
+                let counter = counter.clone();
+                async move {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    counter.fetch_add(job.payload.as_u64().unwrap() as u32, Ordering::SeqCst);
+                    Ok::<_, BoxError>(())
+                }
+            }),
+        )]));
+        let worker = queue.spawn_workers();
+        for val in [1, 2, 3] {
+            queue.enqueue("count", &val, Duration::ZERO).unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        queue.drain(Some(Duration::from_secs(1))).await;
+        worker.await.unwrap();
+        assert_eq!(processed.load(Ordering::SeqCst), 6);
+        assert!(queue.scan(JOB_PREFIX).unwrap().is_empty());
+    }
+}
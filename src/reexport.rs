@@ -2,6 +2,7 @@
 
 pub use axum;
 pub use axum_server;
+pub use bytesize;
 pub use http;
 pub use hyper;
 pub use inventory;
@@ -9,6 +10,9 @@ pub use mime;
 pub use okapi::{self, openapi3, schemars};
 pub use reqwest;
 pub use reqwest_middleware;
+#[cfg(feature = "jemalloc")]
+pub use tikv_jemallocator;
+pub use tokio_util;
 pub use tower;
 pub use tower_http;
 pub use tracing;
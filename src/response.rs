@@ -1,6 +1,89 @@
-use axum::http::StatusCode;
+use std::{
+    ops::{Deref, DerefMut},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    async_trait,
+    extract::{rejection::JsonRejection, FromRequest, Request},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
 use okapi::openapi3;
 use schemars::gen::SchemaGenerator;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Time spent serializing response body.
+///
+/// Attached to response extensions by response wrappers, picked up by metrics middleware.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct SerializationTime(pub(crate) Duration);
+
+/// JSON extractor and response.
+///
+/// Drop-in replacement for [`axum::Json`], which also measures time spent serializing response
+/// body when response timing breakdown is enabled in [`crate::MetricsBuilder`].
+#[derive(Clone, Copy, Debug, Default)]
+#[must_use]
+pub struct Json<T>(pub T);
+
+impl<T> Json<T> {
+    /// Consume wrapper, returning inner value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Json<T> {
+    fn from(inner: T) -> Self {
+        Self(inner)
+    }
+}
+
+impl<T> Deref for Json<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Json<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = JsonRejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        axum::Json::from_request(req, state)
+            .await
+            .map(|axum::Json(inner)| Self(inner))
+    }
+}
+
+impl<T> IntoResponse for Json<T>
+where
+    T: Serialize,
+{
+    fn into_response(self) -> Response {
+        if !crate::metrics::response_timing_enabled() {
+            return axum::Json(self.0).into_response();
+        }
+        let start = Instant::now();
+        let mut resp = axum::Json(self.0).into_response();
+        resp.extensions_mut()
+            .insert(SerializationTime(start.elapsed()));
+        resp
+    }
+}
 
 /// Object for documenting handler responses as OpenAPI schema.
 pub struct ResponseSchema {
@@ -47,7 +130,6 @@ mod impls {
     impl GetResponseSchemas for String {
         type ResponseIter = [ResponseSchema; 1];
 
-        #[must_use]
         fn get_response_schemas(gen: &mut SchemaGenerator) -> Self::ResponseIter {
             [ResponseSchema {
                 status: StatusCode::OK,
@@ -68,13 +150,12 @@ mod impls {
     impl GetResponseSchemas for str {
         type ResponseIter = [ResponseSchema; 1];
 
-        #[must_use]
         fn get_response_schemas(gen: &mut SchemaGenerator) -> Self::ResponseIter {
             <String as GetResponseSchemas>::get_response_schemas(gen)
         }
     }
 
-    impl<'a, T> GetResponseSchemas for &'a T
+    impl<T> GetResponseSchemas for &T
     where
         T: GetResponseSchemas + ?Sized,
     {
@@ -90,7 +171,6 @@ mod impls {
     {
         type ResponseIter = T::ResponseIter;
 
-        #[must_use]
         fn get_response_schemas(gen: &mut schemars::gen::SchemaGenerator) -> Self::ResponseIter {
             T::get_response_schemas(gen)
         }
@@ -102,7 +182,17 @@ mod impls {
     {
         type ResponseIter = [ResponseSchema; 1];
 
-        #[must_use]
+        fn get_response_schemas(gen: &mut SchemaGenerator) -> Self::ResponseIter {
+            <super::Json<T> as GetResponseSchemas>::get_response_schemas(gen)
+        }
+    }
+
+    impl<T> GetResponseSchemas for super::Json<T>
+    where
+        T: JsonSchema,
+    {
+        type ResponseIter = [ResponseSchema; 1];
+
         fn get_response_schemas(gen: &mut SchemaGenerator) -> Self::ResponseIter {
             [ResponseSchema {
                 status: StatusCode::OK,
@@ -127,7 +217,6 @@ mod impls {
     {
         type ResponseIter = Vec<ResponseSchema>;
 
-        #[must_use]
         fn get_response_schemas(gen: &mut SchemaGenerator) -> Self::ResponseIter {
             T::get_response_schemas(gen)
                 .into_iter()
@@ -0,0 +1,399 @@
+//! Retry advice for rejected requests.
+//!
+//! All layers which reject requests with 429 Too Many Requests or 503 Service Unavailable use
+//! this module to generate `Retry-After` header and `retry_after_ms` problem details field.
+
+use std::time::{Duration, SystemTime};
+
+use axum::{
+    body::Body,
+    http::{header::RETRY_AFTER, HeaderValue, Response},
+    response::IntoResponse,
+};
+use problemdetails::Problem;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Source of a rejection, used to select base retry delay.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum RetrySource {
+    /// Rate limit exceeded.
+    RateLimit,
+    /// Service is in maintenance mode.
+    Maintenance,
+    /// Handler circuit breaker is open.
+    CircuitBreaker,
+    /// Fair queue of a handler is full.
+    FairQueue,
+    /// Global memory budget for buffered bodies is exhausted.
+    Memory,
+    /// Profiling endpoint is busy, or its cooldown interval has not passed.
+    Profiling,
+}
+
+/// Format of `Retry-After` header.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum RetryAfterFormat {
+    /// Delay in whole seconds.
+    #[default]
+    Seconds,
+    /// Absolute HTTP-date.
+    HttpDate,
+}
+
+/// Configuration for retry advice generation.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct RetryAdviceConfig {
+    /// Format of `Retry-After` header.
+    #[serde(default)]
+    format: RetryAfterFormat,
+    /// Maximum jitter added to retry delay, as a fraction of the delay.
+    ///
+    /// Zero disables jitter.
+    #[serde(default = "RetryAdviceConfig::default_jitter")]
+    jitter: f64,
+    /// Upper bound for advised retry delay, including jitter.
+    #[serde(default = "RetryAdviceConfig::default_cap", with = "humantime_serde")]
+    cap: Duration,
+    /// Minimum retry delay for rate-limited requests.
+    #[serde(
+        default = "RetryAdviceConfig::default_rate_limit",
+        with = "humantime_serde"
+    )]
+    rate_limit: Duration,
+    /// Retry delay used while in maintenance mode.
+    #[serde(
+        default = "RetryAdviceConfig::default_maintenance",
+        with = "humantime_serde"
+    )]
+    maintenance: Duration,
+    /// Minimum retry delay for requests rejected by open circuit breaker.
+    #[serde(
+        default = "RetryAdviceConfig::default_circuit_breaker",
+        with = "humantime_serde"
+    )]
+    circuit_breaker: Duration,
+    /// Minimum retry delay for requests rejected by a full fair queue.
+    #[serde(
+        default = "RetryAdviceConfig::default_fair_queue",
+        with = "humantime_serde"
+    )]
+    fair_queue: Duration,
+    /// Minimum retry delay for requests rejected due to exhausted memory budget.
+    #[serde(
+        default = "RetryAdviceConfig::default_memory",
+        with = "humantime_serde"
+    )]
+    memory: Duration,
+    /// Minimum retry delay for rejected profiling requests.
+    #[serde(
+        default = "RetryAdviceConfig::default_profiling",
+        with = "humantime_serde"
+    )]
+    profiling: Duration,
+}
+
+impl Default for RetryAdviceConfig {
+    fn default() -> Self {
+        Self {
+            format: RetryAfterFormat::default(),
+            jitter: Self::default_jitter(),
+            cap: Self::default_cap(),
+            rate_limit: Self::default_rate_limit(),
+            maintenance: Self::default_maintenance(),
+            circuit_breaker: Self::default_circuit_breaker(),
+            fair_queue: Self::default_fair_queue(),
+            memory: Self::default_memory(),
+            profiling: Self::default_profiling(),
+        }
+    }
+}
+
+impl RetryAdviceConfig {
+    /// Default value for [`Self::jitter`].
+    #[must_use]
+    #[inline]
+    fn default_jitter() -> f64 {
+        0.1
+    }
+
+    /// Default value for [`Self::cap`].
+    #[must_use]
+    #[inline]
+    fn default_cap() -> Duration {
+        Duration::from_secs(600)
+    }
+
+    /// Default value for [`Self::rate_limit`].
+    #[must_use]
+    #[inline]
+    fn default_rate_limit() -> Duration {
+        Duration::from_secs(1)
+    }
+
+    /// Default value for [`Self::maintenance`].
+    #[must_use]
+    #[inline]
+    fn default_maintenance() -> Duration {
+        Duration::from_secs(30)
+    }
+
+    /// Default value for [`Self::circuit_breaker`].
+    #[must_use]
+    #[inline]
+    fn default_circuit_breaker() -> Duration {
+        Duration::from_secs(1)
+    }
+
+    /// Default value for [`Self::fair_queue`].
+    #[must_use]
+    #[inline]
+    fn default_fair_queue() -> Duration {
+        Duration::from_secs(1)
+    }
+
+    /// Default value for [`Self::memory`].
+    #[must_use]
+    #[inline]
+    fn default_memory() -> Duration {
+        Duration::from_secs(1)
+    }
+
+    /// Default value for [`Self::profiling`].
+    #[must_use]
+    #[inline]
+    fn default_profiling() -> Duration {
+        Duration::from_secs(1)
+    }
+
+    /// Set format of `Retry-After` header.
+    #[must_use]
+    pub fn with_format(mut self, format: RetryAfterFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set maximum jitter, as a fraction of the delay.
+    #[must_use]
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set upper bound for advised retry delay.
+    #[must_use]
+    pub fn with_cap(mut self, cap: Duration) -> Self {
+        self.cap = cap;
+        self
+    }
+
+    /// Set base retry delay for a rejection source.
+    #[must_use]
+    pub fn with_base(mut self, source: RetrySource, delay: Duration) -> Self {
+        match source {
+            RetrySource::RateLimit => self.rate_limit = delay,
+            RetrySource::Maintenance => self.maintenance = delay,
+            RetrySource::CircuitBreaker => self.circuit_breaker = delay,
+            RetrySource::FairQueue => self.fair_queue = delay,
+            RetrySource::Memory => self.memory = delay,
+            RetrySource::Profiling => self.profiling = delay,
+        }
+        self
+    }
+
+    /// Base retry delay for a rejection source.
+    #[must_use]
+    pub fn base(&self, source: RetrySource) -> Duration {
+        match source {
+            RetrySource::RateLimit => self.rate_limit,
+            RetrySource::Maintenance => self.maintenance,
+            RetrySource::CircuitBreaker => self.circuit_breaker,
+            RetrySource::FairQueue => self.fair_queue,
+            RetrySource::Memory => self.memory,
+            RetrySource::Profiling => self.profiling,
+        }
+    }
+
+    /// Compute retry advice.
+    ///
+    /// `hint` is a delay computed by the rejecting layer itself, if any. Resulting delay is the
+    /// largest of `hint` and the base delay for `source`, plus random jitter, capped by
+    /// [`Self::cap`] and truncated to whole milliseconds.
+    #[must_use]
+    pub fn advise(&self, source: RetrySource, hint: Option<Duration>) -> RetryAdvice {
+        let base = hint.map_or(self.base(source), |hint| hint.max(self.base(source)));
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let delay = match jitter > 0.0 {
+            true => base.mul_f64(1.0 + rand::thread_rng().gen_range(0.0..=jitter)),
+            false => base,
+        };
+        let delay_ms = delay
+            .min(self.cap)
+            .as_millis()
+            .try_into()
+            .unwrap_or(u64::MAX);
+        RetryAdvice {
+            delay: Duration::from_millis(delay_ms),
+            format: self.format,
+        }
+    }
+}
+
+/// Computed retry advice for a rejected request.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryAdvice {
+    /// Advised delay before retrying.
+    delay: Duration,
+    /// Format of `Retry-After` header.
+    format: RetryAfterFormat,
+}
+
+impl RetryAdvice {
+    /// Advised delay before retrying.
+    #[must_use]
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Advised delay in milliseconds, as used in `retry_after_ms` field.
+    #[must_use]
+    pub fn delay_ms(&self) -> u64 {
+        self.delay.as_millis().try_into().unwrap_or(u64::MAX)
+    }
+
+    /// Value of `Retry-After` header.
+    ///
+    /// Delay is rounded up to whole seconds, as fractional values are not allowed by RFC 9110.
+    #[must_use]
+    pub fn header_value(&self) -> HeaderValue {
+        let secs = self.delay.as_secs() + u64::from(self.delay.subsec_nanos() > 0);
+        match self.format {
+            RetryAfterFormat::Seconds => HeaderValue::from(secs),
+            RetryAfterFormat::HttpDate => {
+                let when = SystemTime::now() + Duration::from_secs(secs);
+                // SAFETY: HTTP-date is always a valid header value.
+                HeaderValue::from_str(&httpdate::fmt_http_date(when)).unwrap()
+            }
+        }
+    }
+
+    /// Add `Retry-After` header to response.
+    pub fn apply_header<T>(&self, resp: &mut Response<T>) {
+        resp.headers_mut().insert(RETRY_AFTER, self.header_value());
+    }
+
+    /// Build problem details response with `Retry-After` header and `retry_after_ms` field.
+    #[must_use]
+    pub fn problem_response(&self, problem: Problem) -> Response<Body> {
+        let mut resp = problem
+            .with_value("retry_after_ms", self.delay_ms())
+            .into_response();
+        self.apply_header(&mut resp);
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+
+    use super::*;
+
+    async fn body_json(resp: Response<Body>) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    /// Jitter stays within configured bounds.
+    #[test]
+    fn jitter_bounds() {
+        let cfg = RetryAdviceConfig::default().with_jitter(0.5);
+        let base = Duration::from_secs(2);
+        for _ in 0..1000 {
+            let delay = cfg.advise(RetrySource::RateLimit, Some(base)).delay();
+            assert!(delay >= base && delay <= base.mul_f64(1.5), "{delay:?}");
+        }
+        let cfg = cfg.with_jitter(0.0);
+        assert_eq!(cfg.advise(RetrySource::RateLimit, Some(base)).delay(), base);
+    }
+
+    /// Base delay per source, hint and cap.
+    #[test]
+    fn base_hint_cap() {
+        let cfg = RetryAdviceConfig::default()
+            .with_jitter(0.0)
+            .with_cap(Duration::from_secs(60))
+            .with_base(RetrySource::RateLimit, Duration::from_secs(5));
+        let advise = |source, hint| cfg.advise(source, hint).delay().as_secs();
+        assert_eq!(advise(RetrySource::RateLimit, None), 5);
+        assert_eq!(
+            advise(RetrySource::RateLimit, Some(Duration::from_secs(1))),
+            5
+        );
+        assert_eq!(
+            advise(RetrySource::RateLimit, Some(Duration::from_secs(7))),
+            7
+        );
+        assert_eq!(advise(RetrySource::Maintenance, None), 30);
+        assert_eq!(advise(RetrySource::FairQueue, None), 1);
+        assert_eq!(advise(RetrySource::Memory, None), 1);
+        assert_eq!(advise(RetrySource::Profiling, None), 1);
+        assert_eq!(
+            advise(RetrySource::RateLimit, Some(Duration::from_secs(90))),
+            60
+        );
+    }
+
+    /// Header is present and consistent with JSON field.
+    #[tokio::test]
+    async fn header_matches_body() {
+        let cfg = RetryAdviceConfig::default().with_jitter(1.0);
+        for _ in 0..20 {
+            let advice = cfg.advise(RetrySource::RateLimit, Some(Duration::from_millis(1500)));
+            let resp = advice.problem_response(problemdetails::new(StatusCode::TOO_MANY_REQUESTS));
+            let header: u64 = resp.headers()[RETRY_AFTER]
+                .to_str()
+                .unwrap()
+                .parse()
+                .unwrap();
+            let body = body_json(resp).await;
+            let ms = body["retry_after_ms"].as_u64().unwrap();
+            assert_eq!(ms, advice.delay_ms());
+            assert!(header * 1000 >= ms && header * 1000 < ms + 1000);
+        }
+    }
+
+    /// Rate limit rejection carries advice.
+    #[tokio::test]
+    async fn rate_limit_response() {
+        let advice = RetryAdviceConfig::default().advise(RetrySource::RateLimit, None);
+        let resp = crate::layers::rate::RateLimitError::LimitReached {
+            remaining_seconds: 0,
+            advice,
+            state: None,
+            key: crate::layers::rate::RateLimitKey::Global,
+        }
+        .into_response();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers()[RETRY_AFTER], advice.header_value());
+        assert_eq!(body_json(resp).await["retry_after_ms"], advice.delay_ms());
+    }
+
+    /// HTTP-date header format.
+    #[test]
+    fn header_http_date() {
+        let advice = RetryAdviceConfig::default()
+            .with_format(RetryAfterFormat::HttpDate)
+            .advise(RetrySource::Maintenance, None);
+        let value = advice.header_value();
+        let when = httpdate::parse_http_date(value.to_str().unwrap()).unwrap();
+        let delta = when.duration_since(SystemTime::now()).unwrap();
+        assert!(delta <= advice.delay() + Duration::from_secs(1));
+    }
+}
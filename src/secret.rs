@@ -0,0 +1,237 @@
+//! Secret configuration values.
+//!
+//! Secrets can be set inline, or read from an environment variable or a file when configuration
+//! is loaded. Resolved values are never serialized or printed in debug output.
+
+use std::{env, fmt, fs, path::PathBuf};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::errors::IoError;
+
+/// Placeholder used in place of inline secret values when serializing.
+const REDACTED: &str = "<redacted>";
+
+/// Error type used when resolving secrets.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum SecretError {
+    /// Environment variable is not set, or is not valid Unicode.
+    #[error("Secret environment variable {0} is not set or is not valid Unicode")]
+    Env(String),
+    /// Unable to read secret file.
+    #[error("Unable to read secret file {0}: {1}")]
+    File(PathBuf, IoError),
+    /// Value is a redaction placeholder, probably copied from serialized configuration.
+    #[error("Secret value is a redaction placeholder {REDACTED:?}, actual value must be set")]
+    Redacted,
+}
+
+/// Where secret value was resolved from.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+enum SecretSource {
+    /// Value set directly in configuration.
+    Inline(String),
+    /// Value read from environment variable.
+    Env {
+        /// Environment variable name.
+        env: String,
+    },
+    /// Value read from file.
+    ///
+    /// Trailing newline is removed.
+    File {
+        /// Path to file.
+        file: PathBuf,
+    },
+}
+
+/// Secret configuration value, such as password or shared key.
+///
+/// Deserialized from either a plain string, `{"env": "VAR_NAME"}` or `{"file": "/path"}`.
+/// Redaction placeholder is rejected, so serialized configuration can't be loaded back as is.
+/// Indirect values are resolved during deserialization, so that configuration errors are
+/// reported on startup.
+///
+/// When serialized, environment variable or file references are written back as is, while inline
+/// values are redacted.
+#[derive(Clone)]
+pub struct SecretValue {
+    /// Where this value was resolved from.
+    source: SecretSource,
+    /// Resolved value.
+    value: String,
+}
+
+impl SecretValue {
+    /// Create secret with inline value.
+    #[must_use]
+    pub fn new(value: impl ToString) -> Self {
+        let value = value.to_string();
+        Self {
+            source: SecretSource::Inline(value.clone()),
+            value,
+        }
+    }
+
+    /// Read secret from environment variable.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if variable is not set, or is not valid Unicode.
+    pub fn from_env(name: impl ToString) -> Result<Self, SecretError> {
+        Self::resolve(SecretSource::Env {
+            env: name.to_string(),
+        })
+    }
+
+    /// Read secret from file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if file could not be read, or is not valid UTF-8.
+    pub fn from_file(path: impl Into<PathBuf>) -> Result<Self, SecretError> {
+        Self::resolve(SecretSource::File { file: path.into() })
+    }
+
+    /// Resolve secret value from its source.
+    fn resolve(source: SecretSource) -> Result<Self, SecretError> {
+        let value = match &source {
+            SecretSource::Inline(value) if value == REDACTED => return Err(SecretError::Redacted),
+            SecretSource::Inline(value) => value.clone(),
+            SecretSource::Env { env } => {
+                env::var(env).map_err(|_| SecretError::Env(env.clone()))?
+            }
+            SecretSource::File { file } => {
+                let mut value = fs::read_to_string(file)
+                    .map_err(|err| SecretError::File(file.clone(), err.into()))?;
+                let len = value.trim_end_matches(['\r', '\n']).len();
+                value.truncate(len);
+                value
+            }
+        };
+        Ok(Self { source, value })
+    }
+
+    /// Get resolved secret value.
+    #[must_use]
+    pub fn expose(&self) -> &str {
+        &self.value
+    }
+}
+
+impl From<&str> for SecretValue {
+    fn from(value: &str) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<String> for SecretValue {
+    fn from(value: String) -> Self {
+        Self::new(value)
+    }
+}
+
+impl PartialEq for SecretValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl fmt::Debug for SecretValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            SecretSource::Inline(_) => f.write_str(REDACTED),
+            SecretSource::Env { env } => write!(f, "<env {env}>"),
+            SecretSource::File { file } => write!(f, "<file {}>", file.display()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = SecretSource::deserialize(deserializer).map_err(|_| {
+            de::Error::custom(
+                "expected secret value as a string, {\"env\": \"VAR\"} or {\"file\": \"/path\"}",
+            )
+        })?;
+        Self::resolve(source).map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for SecretValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.source {
+            SecretSource::Inline(_) => serializer.serialize_str(REDACTED),
+            source => source.serialize(serializer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Secrets are resolved from all sources, with helpful errors.
+    #[test]
+    fn resolve_sources() {
+        let secret: SecretValue = serde_json::from_value(serde_json::json!("inline")).unwrap();
+        assert_eq!(secret.expose(), "inline");
+
+        env::set_var("UXUM_TEST_SECRET_VALUE", "from env");
+        let secret: SecretValue =
+            serde_json::from_value(serde_json::json!({"env": "UXUM_TEST_SECRET_VALUE"})).unwrap();
+        assert_eq!(secret.expose(), "from env");
+        let err = serde_json::from_value::<SecretValue>(
+            serde_json::json!({"env": "UXUM_TEST_SECRET_MISSING"}),
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("UXUM_TEST_SECRET_MISSING"),
+            "{err}"
+        );
+
+        let path = env::temp_dir().join(format!("uxum-secret-{}", std::process::id()));
+        fs::write(&path, "from file\n").unwrap();
+        let secret = SecretValue::from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(secret.expose(), "from file");
+        let err = SecretValue::from_file(&path).unwrap_err();
+        assert!(err.to_string().contains("uxum-secret-"), "{err}");
+
+        assert!(serde_json::from_value::<SecretValue>(serde_json::json!({"vault": "x"})).is_err());
+    }
+
+    /// Resolved secrets are not serialized or printed.
+    #[test]
+    fn redaction() {
+        env::set_var("UXUM_TEST_SECRET_REDACTED", "hidden");
+        let secret = SecretValue::from_env("UXUM_TEST_SECRET_REDACTED").unwrap();
+        assert_eq!(
+            serde_json::to_value(&secret).unwrap(),
+            serde_json::json!({"env": "UXUM_TEST_SECRET_REDACTED"})
+        );
+        assert!(!format!("{secret:?}").contains("hidden"));
+
+        let secret = SecretValue::new("hidden");
+        assert_eq!(serde_json::to_value(&secret).unwrap(), REDACTED);
+        assert!(!format!("{secret:?}").contains("hidden"));
+
+        let err = serde_json::from_value::<SecretValue>(serde_json::to_value(&secret).unwrap())
+            .unwrap_err();
+        assert!(err.to_string().contains("placeholder"), "{err}");
+
+        let auth: crate::auth::AuthConfig = serde_json::from_value(serde_json::json!({
+            "bearer_tokens": [{"token": "hidden", "user": "partner"}],
+        }))
+        .unwrap();
+        assert!(auth.bearer_tokens[0].token == "hidden");
+        assert_eq!(
+            serde_json::to_value(&auth).unwrap()["bearer_tokens"][0]["token"],
+            REDACTED
+        );
+        assert!(!format!("{auth:?}").contains("hidden"));
+    }
+}
@@ -0,0 +1,511 @@
+//! Complete service configuration, with built-in default profiles.
+//!
+//! Configuration can be assembled from a default profile and any number of YAML (or other
+//! formats supported by [`config`] crate) files, so that the same binary runs locally without any
+//! files at all, and in production with a mounted configuration file.
+//!
+//! Defaults can be printed as a commented YAML document, to be used as a starting point for a
+//! configuration file.
+
+use std::{
+    fmt::{self, Write as _},
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    apidoc::ApiDocBuilder,
+    builder::server::ServerBuilder,
+    config::AppConfig,
+    logging::{LoggingFormat, LoggingLevel, LoggingSubscriberConfig},
+};
+
+/// Command line flag used to print default configuration.
+pub const PRINT_DEFAULT_CONFIG_FLAG: &str = "--print-default-config";
+
+/// Documented field of a configuration type.
+struct FieldDoc {
+    /// Serialized key.
+    key: &'static str,
+    /// First paragraph of doc comment.
+    doc: &'static [&'static str],
+    /// Innermost type name.
+    ty: &'static str,
+    /// Field type is a map, keyed by arbitrary strings.
+    map: bool,
+    /// Field is flattened into enclosing type.
+    flatten: bool,
+}
+
+/// Documented configuration type.
+struct TypeDoc {
+    /// Type is an enum.
+    is_enum: bool,
+    /// Documented fields.
+    fields: &'static [FieldDoc],
+}
+
+// Doc comments of configuration types, collected by build script.
+include!(concat!(env!("OUT_DIR"), "/config_docs.rs"));
+
+/// Error type used when loading service configuration.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ServiceConfigError {
+    /// Unable to read, parse or merge configuration sources.
+    #[error("Unable to load configuration: {0}")]
+    Load(#[from] config::ConfigError),
+    /// Unable to serialize configuration.
+    #[error("Unable to serialize configuration: {0}")]
+    Serialize(#[from] serde_json::Error),
+    /// Unable to write configuration to output.
+    #[error("Unable to write configuration: {0}")]
+    Write(#[from] io::Error),
+}
+
+/// Built-in set of configuration defaults.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// Local development: verbose colored logs, API documentation enabled, listening on
+    /// localhost only.
+    #[default]
+    Dev,
+    /// Production: JSON logs, API documentation disabled, listening on all interfaces.
+    Prod,
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Dev => "dev",
+            Self::Prod => "prod",
+        })
+    }
+}
+
+/// Complete service configuration: application and HTTP server.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct ServiceConfig {
+    /// Application configuration.
+    #[serde(flatten)]
+    pub app: AppConfig,
+    /// HTTP server configuration.
+    #[serde(default)]
+    pub server: ServerBuilder,
+}
+
+impl ServiceConfig {
+    /// Create builder for service configuration.
+    #[must_use]
+    pub fn builder() -> ServiceConfigBuilder {
+        ServiceConfigBuilder::default()
+    }
+
+    /// Get configuration defaults for a profile.
+    #[must_use]
+    pub fn defaults(profile: Profile) -> Self {
+        let mut cfg = Self::default();
+        match profile {
+            Profile::Dev => {
+                cfg.app.logging.subscribers = vec![LoggingSubscriberConfig::default_for_dev()];
+                cfg.app.api_doc = Some(ApiDocBuilder::default());
+            }
+            Profile::Prod => {
+                cfg.app.logging.subscribers = vec![LoggingSubscriberConfig {
+                    format: LoggingFormat::Json {
+                        flatten_metadata: true,
+                        current_span: true,
+                        static_fields: Default::default(),
+                        key_names: Default::default(),
+                    },
+                    level: LoggingLevel::Info,
+                    ..Default::default()
+                }];
+                cfg.app.api_doc = None;
+                cfg.server.listen = "0.0.0.0:8080".into();
+            }
+        }
+        cfg
+    }
+
+    /// Render configuration defaults for a profile as a YAML document, commented with
+    /// descriptions of configuration fields.
+    ///
+    /// Fields which are absent by default are listed as comments.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if configuration cannot be serialized.
+    pub fn default_yaml(profile: Profile) -> Result<String, ServiceConfigError> {
+        let value = serde_json::to_value(Self::defaults(profile))?;
+        let mut out = format!(
+            "# Default {profile} configuration.\n#\n# Generated by `{PRINT_DEFAULT_CONFIG_FLAG}`, \
+             commented out fields are absent by default.\n\n"
+        );
+        match value {
+            serde_json::Value::Object(obj) => write_map(&mut out, &obj, Some("ServiceConfig"), 0),
+            other => write_scalar(&mut out, &other),
+        }
+        Ok(out)
+    }
+}
+
+/// Print default configuration for a profile to standard output.
+///
+/// # Errors
+///
+/// Returns `Err` if configuration cannot be serialized or written.
+pub fn print_default_config(profile: Profile) -> Result<(), ServiceConfigError> {
+    let yaml = ServiceConfig::default_yaml(profile)?;
+    io::stdout().lock().write_all(yaml.as_bytes())?;
+    Ok(())
+}
+
+/// Print default configuration if [`PRINT_DEFAULT_CONFIG_FLAG`] is present in command line
+/// arguments.
+///
+/// Returns `true` if configuration was printed, in which case service should exit.
+///
+/// # Errors
+///
+/// Returns `Err` if configuration cannot be serialized or written.
+pub fn print_default_config_if_requested(profile: Profile) -> Result<bool, ServiceConfigError> {
+    if !std::env::args()
+        .skip(1)
+        .any(|arg| arg == PRINT_DEFAULT_CONFIG_FLAG)
+    {
+        return Ok(false);
+    }
+    print_default_config(profile)?;
+    Ok(true)
+}
+
+/// Builder for [`ServiceConfig`].
+///
+/// Sources are merged in order: profile defaults first, then files in the order they were added.
+#[derive(Clone, Debug, Default)]
+pub struct ServiceConfigBuilder {
+    /// Default profile.
+    profile: Option<Profile>,
+    /// Configuration files, and whether they are required.
+    files: Vec<(PathBuf, bool)>,
+}
+
+impl ServiceConfigBuilder {
+    /// Use defaults from a built-in profile.
+    ///
+    /// Without a profile, plain defaults of configuration types are used.
+    #[must_use]
+    pub fn with_defaults_profile(mut self, profile: Profile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Add configuration file, which must exist.
+    ///
+    /// Format is detected by file extension.
+    #[must_use]
+    pub fn with_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.files.push((path.as_ref().to_path_buf(), true));
+        self
+    }
+
+    /// Add configuration file, which is silently skipped if it does not exist.
+    ///
+    /// Format is detected by file extension.
+    #[must_use]
+    pub fn with_optional_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.files.push((path.as_ref().to_path_buf(), false));
+        self
+    }
+
+    /// Load and merge all configuration sources.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if any of required files is missing, or if any of the sources cannot be
+    /// parsed or deserialized.
+    pub fn build(&self) -> Result<ServiceConfig, ServiceConfigError> {
+        let defaults = self
+            .profile
+            .map_or_else(ServiceConfig::default, ServiceConfig::defaults);
+        let mut builder =
+            config::Config::builder().add_source(config::Config::try_from(&defaults)?);
+        for (path, required) in &self.files {
+            builder = builder.add_source(config::File::from(path.as_path()).required(*required));
+        }
+        Ok(builder.build()?.try_deserialize()?)
+    }
+}
+
+/// Find documented type by name.
+fn type_doc(name: &str) -> Option<&'static TypeDoc> {
+    CONFIG_DOCS
+        .binary_search_by(|(ty, _)| (*ty).cmp(name))
+        .ok()
+        .map(|idx| &CONFIG_DOCS[idx].1)
+}
+
+/// Find documented field by key, including fields of flattened types.
+fn field_doc(ty: Option<&str>, key: &str) -> Option<&'static FieldDoc> {
+    let doc = type_doc(ty?)?;
+    doc.fields
+        .iter()
+        .find(|field| !field.flatten && field.key == key)
+        .or_else(|| {
+            doc.fields
+                .iter()
+                .filter(|field| field.flatten)
+                .find_map(|field| field_doc(Some(field.ty), key))
+        })
+}
+
+/// Collect documented fields of a struct, including fields of flattened structs.
+fn struct_fields(ty: &str, fields: &mut Vec<&'static FieldDoc>) {
+    let Some(doc) = type_doc(ty).filter(|doc| !doc.is_enum) else {
+        return;
+    };
+    for field in doc.fields {
+        match field.flatten {
+            true => struct_fields(field.ty, fields),
+            false => fields.push(field),
+        }
+    }
+}
+
+/// Write indentation.
+fn write_indent(out: &mut String, indent: usize) {
+    out.extend(std::iter::repeat(' ').take(indent));
+}
+
+/// Write comment lines.
+fn write_comment(out: &mut String, lines: &[&str], indent: usize) {
+    for line in lines {
+        write_indent(out, indent);
+        let _ = writeln!(out, "# {line}");
+    }
+}
+
+/// Write mapping key, quoting it if necessary.
+fn write_key(out: &mut String, key: &str) {
+    let plain = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    match plain {
+        true => out.push_str(key),
+        false => write_scalar(out, &serde_json::Value::from(key)),
+    }
+}
+
+/// Write scalar value.
+///
+/// Strings are written as JSON strings, which are valid double-quoted YAML scalars.
+fn write_scalar(out: &mut String, value: &serde_json::Value) {
+    match value {
+        serde_json::Value::Array(arr) if arr.is_empty() => out.push_str("[]"),
+        serde_json::Value::Object(obj) if obj.is_empty() => out.push_str("{}"),
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+/// Check if value is written on the same line as its key.
+fn is_inline(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Array(arr) => arr.is_empty(),
+        serde_json::Value::Object(obj) => obj.is_empty(),
+        _ => true,
+    }
+}
+
+/// Write mapping, commenting keys with descriptions from documented type `ty`.
+fn write_map(
+    out: &mut String,
+    obj: &serde_json::Map<String, serde_json::Value>,
+    ty: Option<&str>,
+    indent: usize,
+) {
+    for (key, value) in obj {
+        let field = field_doc(ty, key);
+        if let Some(field) = field {
+            write_comment(out, field.doc, indent);
+        }
+        let value_ty = field.map(|field| field.ty).filter(|ty| !ty.is_empty());
+        let map = field.is_some_and(|field| field.map);
+        write_entry(out, key, value, value_ty, map, indent);
+    }
+    let mut absent = Vec::new();
+    if let Some(ty) = ty {
+        struct_fields(ty, &mut absent);
+    }
+    for field in absent
+        .into_iter()
+        .filter(|field| !obj.contains_key(field.key))
+    {
+        write_comment(out, field.doc, indent);
+        write_indent(out, indent);
+        out.push_str("# ");
+        write_key(out, field.key);
+        out.push_str(":\n");
+    }
+}
+
+/// Write single mapping entry.
+///
+/// If `map` is set, value is a map with values of documented type `ty`.
+fn write_entry(
+    out: &mut String,
+    key: &str,
+    value: &serde_json::Value,
+    ty: Option<&str>,
+    map: bool,
+    indent: usize,
+) {
+    write_indent(out, indent);
+    write_key(out, key);
+    out.push(':');
+    if is_inline(value) {
+        out.push(' ');
+        write_scalar(out, value);
+        out.push('\n');
+        return;
+    }
+    out.push('\n');
+    match value {
+        serde_json::Value::Object(obj) if map => {
+            for (key, value) in obj {
+                write_entry(out, key, value, ty, false, indent + 2);
+            }
+        }
+        serde_json::Value::Object(obj) => write_map(out, obj, ty, indent + 2),
+        serde_json::Value::Array(arr) => write_seq(out, arr, ty, indent + 2),
+        _ => {}
+    }
+}
+
+/// Write sequence, commenting items with descriptions from documented type `ty`.
+fn write_seq(out: &mut String, arr: &[serde_json::Value], ty: Option<&str>, indent: usize) {
+    for value in arr {
+        if is_inline(value) {
+            write_indent(out, indent);
+            out.push_str("- ");
+            write_scalar(out, value);
+            out.push('\n');
+            continue;
+        }
+        let mut item = String::new();
+        match value {
+            serde_json::Value::Object(obj) => write_map(&mut item, obj, ty, indent + 2),
+            serde_json::Value::Array(arr) => write_seq(&mut item, arr, ty, indent + 2),
+            _ => {}
+        }
+        // Put sequence item marker on the first non-comment line.
+        let mut marked = false;
+        for line in item.lines() {
+            if !marked && !line.trim_start().starts_with('#') {
+                write_indent(out, indent);
+                out.push_str("- ");
+                out.push_str(&line[indent + 2..]);
+                marked = true;
+            } else {
+                out.push_str(line);
+            }
+            out.push('\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("uxum-service-{}-{name}", std::process::id()))
+    }
+
+    /// Printed defaults deserialize back into the same defaults.
+    #[test]
+    fn yaml_round_trip() {
+        for profile in [Profile::Dev, Profile::Prod] {
+            let yaml = ServiceConfig::default_yaml(profile).unwrap();
+            let path = temp_path(&format!("{profile}.yaml"));
+            fs_write(&path, &yaml);
+            let parsed = ServiceConfig::builder().with_file(&path).build();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(parsed.unwrap(), ServiceConfig::defaults(profile), "{yaml}");
+        }
+    }
+
+    /// Printed defaults are commented with field descriptions.
+    #[test]
+    fn yaml_comments() {
+        let yaml = ServiceConfig::default_yaml(Profile::Prod).unwrap();
+        assert!(
+            yaml.contains("# HTTP server configuration.\nserver:\n"),
+            "{yaml}"
+        );
+        assert!(yaml.contains("  listen: \"0.0.0.0:8080\"\n"), "{yaml}");
+        assert!(
+            yaml.contains("# Logging configuration.\nlogging:\n"),
+            "{yaml}"
+        );
+        // Comments of sequence items are aligned with item fields.
+        assert!(
+            yaml.contains(
+                "  subscribers:\n      # Forbid changing output format at runtime.\n    - "
+            ),
+            "{yaml}"
+        );
+        assert!(yaml.contains("\napi_doc: null\n"), "{yaml}");
+        // Absent fields are listed as comments.
+        assert!(yaml.contains("\n    # header_read_timeout:\n"), "{yaml}");
+    }
+
+    /// Missing optional files are skipped, missing required files are reported.
+    #[test]
+    fn optional_files() {
+        let missing = temp_path("missing.yaml");
+        let cfg = ServiceConfig::builder()
+            .with_defaults_profile(Profile::Prod)
+            .with_optional_file(&missing)
+            .build()
+            .unwrap();
+        assert_eq!(cfg, ServiceConfig::defaults(Profile::Prod));
+        assert!(ServiceConfig::builder()
+            .with_file(&missing)
+            .build()
+            .is_err());
+    }
+
+    /// Files override profile defaults.
+    #[test]
+    fn file_overrides() {
+        let path = temp_path("override.yaml");
+        fs_write(
+            &path,
+            "server:\n  listen: \"127.0.0.1:9090\"\nstrict_cors: true\n",
+        );
+        let cfg = ServiceConfig::builder()
+            .with_defaults_profile(Profile::Prod)
+            .with_optional_file(&path)
+            .build();
+        std::fs::remove_file(&path).unwrap();
+        let cfg = cfg.unwrap();
+        assert_eq!(cfg.server.listen, "127.0.0.1:9090");
+        assert!(cfg.app.strict_cors);
+        assert_eq!(
+            cfg.app.logging,
+            ServiceConfig::defaults(Profile::Prod).app.logging
+        );
+    }
+
+    fn fs_write(path: &Path, contents: &str) {
+        std::fs::write(path, contents).unwrap();
+    }
+}
@@ -0,0 +1,612 @@
+//! Ordered initialization of application components at startup.
+//!
+//! Lazy states, background tasks and warmup hooks are registered as named startup nodes, which
+//! can depend on each other. Nodes are initialized in dependency order, with independent nodes
+//! running in parallel.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{extract::State, routing, Json, Router};
+use futures::{future::BoxFuture, stream::FuturesUnordered, StreamExt};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tower::BoxError;
+use tracing::{debug_span, error, info, info_span, Instrument};
+
+/// Error type used when validating startup dependencies.
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StartupError {
+    /// More than one node registered with the same name.
+    #[error("Duplicate startup node: {0}")]
+    Duplicate(String),
+    /// Node depends on a node that was not registered.
+    #[error("Startup node {node} depends on missing node {dependency}")]
+    Missing {
+        /// Dependent node name.
+        node: String,
+        /// Missing dependency name.
+        dependency: String,
+    },
+    /// Dependency cycle detected.
+    #[error("Startup dependency cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+}
+
+/// Name and dependencies of a startup node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StartupSpec {
+    /// Node name.
+    name: String,
+    /// Names of nodes that must be initialized before this one.
+    depends_on: Vec<String>,
+}
+
+impl StartupSpec {
+    /// Create new node specification without dependencies.
+    #[must_use]
+    pub fn new(name: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            depends_on: Vec::new(),
+        }
+    }
+
+    /// Add dependencies on other nodes.
+    #[must_use]
+    pub fn depends_on<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        self.depends_on
+            .extend(names.into_iter().map(|name| name.to_string()));
+        self
+    }
+
+    /// Node name.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl From<&str> for StartupSpec {
+    fn from(value: &str) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<String> for StartupSpec {
+    fn from(value: String) -> Self {
+        Self::new(value)
+    }
+}
+
+/// Kind of startup node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum StartupNodeKind {
+    /// Lazily initialized state.
+    State,
+    /// Supervised background task.
+    Task,
+    /// Warmup hook.
+    Warmup,
+}
+
+/// Node initialization function.
+type InitFn = Box<dyn FnOnce() -> BoxFuture<'static, Result<(), BoxError>> + Send>;
+
+/// Startup node.
+pub(crate) struct StartupNode {
+    /// Name and dependencies.
+    spec: StartupSpec,
+    /// Node kind.
+    kind: StartupNodeKind,
+    /// Initialization timeout, overrides [`StartupConfig::node_timeout`].
+    timeout: Option<Duration>,
+    /// Initialization function.
+    init: InitFn,
+}
+
+impl fmt::Debug for StartupNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StartupNode")
+            .field("spec", &self.spec)
+            .field("kind", &self.kind)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl StartupNode {
+    /// Create new startup node.
+    pub(crate) fn new<F, Fut>(spec: StartupSpec, kind: StartupNodeKind, init: F) -> Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), BoxError>> + Send + 'static,
+    {
+        Self {
+            spec,
+            kind,
+            timeout: None,
+            init: Box::new(move || Box::pin(init())),
+        }
+    }
+
+    /// Node kind.
+    pub(crate) fn kind(&self) -> StartupNodeKind {
+        self.kind
+    }
+
+    /// Set initialization timeout.
+    pub(crate) fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+}
+
+/// Startup ordering configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct StartupConfig {
+    /// Maximum number of nodes initialized in parallel.
+    #[serde(default = "StartupConfig::default_parallelism")]
+    parallelism: usize,
+    /// Initialization timeout for a single node.
+    ///
+    /// Warmup hooks use [`crate::WarmupConfig`] timeout instead.
+    #[serde(
+        default = "StartupConfig::default_node_timeout",
+        with = "humantime_serde"
+    )]
+    node_timeout: Duration,
+    /// URL path of startup timeline endpoint.
+    #[serde(default = "StartupConfig::default_timeline_path")]
+    timeline_path: String,
+}
+
+impl Default for StartupConfig {
+    fn default() -> Self {
+        Self {
+            parallelism: Self::default_parallelism(),
+            node_timeout: Self::default_node_timeout(),
+            timeline_path: Self::default_timeline_path(),
+        }
+    }
+}
+
+impl StartupConfig {
+    /// Default value for [`Self::parallelism`].
+    #[must_use]
+    #[inline]
+    fn default_parallelism() -> usize {
+        4
+    }
+
+    /// Default value for [`Self::node_timeout`].
+    #[must_use]
+    #[inline]
+    fn default_node_timeout() -> Duration {
+        Duration::from_secs(30)
+    }
+
+    /// Default value for [`Self::timeline_path`].
+    #[must_use]
+    #[inline]
+    fn default_timeline_path() -> String {
+        "/probe/startup".into()
+    }
+
+    /// Set maximum number of nodes initialized in parallel.
+    #[must_use]
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism;
+        self
+    }
+
+    /// Set initialization timeout for a single node.
+    #[must_use]
+    pub fn with_node_timeout(mut self, timeout: Duration) -> Self {
+        self.node_timeout = timeout;
+        self
+    }
+
+    /// Set URL path of startup timeline endpoint.
+    #[must_use]
+    pub fn with_timeline_path(mut self, path: impl ToString) -> Self {
+        self.timeline_path = path.to_string();
+        self
+    }
+
+    /// Build Axum router containing startup timeline endpoint.
+    pub(crate) fn build_router(&self, timeline: StartupTimeline) -> Router {
+        let _span = debug_span!("build_startup").entered();
+        Router::new()
+            .route(&self.timeline_path, routing::get(get_timeline))
+            .with_state(timeline)
+    }
+}
+
+/// Outcome of node initialization.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum NodeStatus {
+    /// Not yet started.
+    Pending,
+    /// Initialization in progress.
+    Running,
+    /// Initialized successfully.
+    Ok,
+    /// Initialization failed or timed out.
+    Failed(String),
+    /// Not initialized, because one of dependencies failed.
+    Skipped,
+}
+
+/// Startup timeline entry for a single node.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct TimelineEntry {
+    /// Node name.
+    name: String,
+    /// Node kind.
+    kind: StartupNodeKind,
+    /// Names of dependencies.
+    depends_on: Vec<String>,
+    /// Start time, relative to start of initialization.
+    #[serde(with = "humantime_serde")]
+    started: Option<Duration>,
+    /// Initialization duration.
+    #[serde(with = "humantime_serde")]
+    duration: Option<Duration>,
+    /// Initialization outcome.
+    status: NodeStatus,
+}
+
+/// Shared startup timeline, queryable after startup.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct StartupTimeline(Arc<Mutex<Vec<TimelineEntry>>>);
+
+/// Get startup timeline.
+async fn get_timeline(State(timeline): State<StartupTimeline>) -> Json<Vec<TimelineEntry>> {
+    Json(timeline.0.lock().clone())
+}
+
+/// Validated startup dependency graph.
+#[derive(Debug, Default)]
+pub(crate) struct StartupGraph {
+    /// Registered nodes.
+    nodes: Vec<StartupNode>,
+    /// Indices of dependent nodes, for each node.
+    dependents: Vec<Vec<usize>>,
+}
+
+impl StartupGraph {
+    /// Validate dependencies and build graph.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if node names are not unique, if a dependency is missing, or if
+    /// dependencies form a cycle.
+    pub(crate) fn new(nodes: Vec<StartupNode>) -> Result<Self, StartupError> {
+        let mut index = HashMap::new();
+        for (idx, node) in nodes.iter().enumerate() {
+            if index.insert(node.spec.name.as_str(), idx).is_some() {
+                return Err(StartupError::Duplicate(node.spec.name.clone()));
+            }
+        }
+        let mut dependencies = Vec::with_capacity(nodes.len());
+        let mut dependents = vec![Vec::new(); nodes.len()];
+        for (idx, node) in nodes.iter().enumerate() {
+            let mut deps = Vec::with_capacity(node.spec.depends_on.len());
+            for dep in &node.spec.depends_on {
+                let Some(&dep_idx) = index.get(dep.as_str()) else {
+                    return Err(StartupError::Missing {
+                        node: node.spec.name.clone(),
+                        dependency: dep.clone(),
+                    });
+                };
+                deps.push(dep_idx);
+                dependents[dep_idx].push(idx);
+            }
+            dependencies.push(deps);
+        }
+        if let Some(cycle) = find_cycle(&dependencies) {
+            return Err(StartupError::Cycle(
+                cycle
+                    .into_iter()
+                    .map(|idx| nodes[idx].spec.name.clone())
+                    .collect(),
+            ));
+        }
+        Ok(Self { nodes, dependents })
+    }
+
+    /// Check if there are no nodes.
+    #[must_use]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Initialize all nodes in dependency order, recording progress in `timeline`.
+    ///
+    /// Returns `true` if all nodes were initialized successfully.
+    pub(crate) async fn run(self, config: &StartupConfig, timeline: StartupTimeline) -> bool {
+        let start = Instant::now();
+        let parallelism = config.parallelism.max(1);
+        let mut pending: Vec<usize> = self
+            .nodes
+            .iter()
+            .map(|node| node.spec.depends_on.len())
+            .collect();
+        *timeline.0.lock() = self
+            .nodes
+            .iter()
+            .map(|node| TimelineEntry {
+                name: node.spec.name.clone(),
+                kind: node.kind,
+                depends_on: node.spec.depends_on.clone(),
+                started: None,
+                duration: None,
+                status: NodeStatus::Pending,
+            })
+            .collect();
+        let mut ready: VecDeque<usize> = (0..pending.len()).filter(|&i| pending[i] == 0).collect();
+        let mut nodes: Vec<Option<StartupNode>> = self.nodes.into_iter().map(Some).collect();
+        let mut running = FuturesUnordered::new();
+        let mut success = true;
+        loop {
+            while running.len() < parallelism {
+                let Some(idx) = ready.pop_front() else {
+                    break;
+                };
+                let Some(node) = nodes[idx].take() else {
+                    continue;
+                };
+                let timeout = node.timeout.unwrap_or(config.node_timeout);
+                let span = info_span!("startup_node", name = node.spec.name, kind = ?node.kind);
+                {
+                    let entry = &mut timeline.0.lock()[idx];
+                    entry.started = Some(start.elapsed());
+                    entry.status = NodeStatus::Running;
+                }
+                running.push(async move {
+                    let node_start = Instant::now();
+                    let result = match tokio::time::timeout(timeout, (node.init)())
+                        .instrument(span.clone())
+                        .await
+                    {
+                        Ok(Ok(())) => Ok(()),
+                        Ok(Err(err)) => Err(err.to_string()),
+                        Err(_) => Err("timed out".to_owned()),
+                    };
+                    let elapsed = node_start.elapsed();
+                    span.in_scope(|| match &result {
+                        Ok(()) => info!(?elapsed, "startup node initialized"),
+                        Err(err) => error!(?elapsed, error = err, "startup node failed"),
+                    });
+                    (idx, elapsed, result)
+                });
+            }
+            let Some((idx, elapsed, result)) = running.next().await else {
+                break;
+            };
+            let mut entries = timeline.0.lock();
+            entries[idx].duration = Some(elapsed);
+            match result {
+                Ok(()) => {
+                    entries[idx].status = NodeStatus::Ok;
+                    for &dependent in &self.dependents[idx] {
+                        pending[dependent] -= 1;
+                        if pending[dependent] == 0 {
+                            ready.push_back(dependent);
+                        }
+                    }
+                }
+                Err(err) => {
+                    entries[idx].status = NodeStatus::Failed(err);
+                    success = false;
+                }
+            }
+        }
+        let mut entries = timeline.0.lock();
+        for entry in entries
+            .iter_mut()
+            .filter(|entry| entry.status == NodeStatus::Pending)
+        {
+            entry.status = NodeStatus::Skipped;
+        }
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|entry| format!("{}={:?}", entry.name, entry.duration.unwrap_or_default()))
+            .collect();
+        info!(elapsed = ?start.elapsed(), timeline = ?summary, "startup finished");
+        success
+    }
+}
+
+/// Find a dependency cycle, if any.
+///
+/// Returns node indices forming a cycle, with the first node repeated at the end.
+fn find_cycle(dependencies: &[Vec<usize>]) -> Option<Vec<usize>> {
+    /// Node visiting state in depth-first search.
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        New,
+        InStack,
+        Done,
+    }
+
+    fn visit(
+        idx: usize,
+        dependencies: &[Vec<usize>],
+        marks: &mut [Mark],
+        stack: &mut Vec<usize>,
+    ) -> Option<Vec<usize>> {
+        marks[idx] = Mark::InStack;
+        stack.push(idx);
+        for &dep in &dependencies[idx] {
+            match marks[dep] {
+                Mark::Done => {}
+                Mark::InStack => {
+                    let pos = stack.iter().position(|&i| i == dep).unwrap_or_default();
+                    let mut cycle = stack[pos..].to_vec();
+                    cycle.push(dep);
+                    return Some(cycle);
+                }
+                Mark::New => {
+                    if let Some(cycle) = visit(dep, dependencies, marks, stack) {
+                        return Some(cycle);
+                    }
+                }
+            }
+        }
+        stack.pop();
+        marks[idx] = Mark::Done;
+        None
+    }
+
+    let mut marks = vec![Mark::New; dependencies.len()];
+    let mut stack = Vec::new();
+    (0..dependencies.len()).find_map(|idx| match marks[idx] {
+        Mark::New => visit(idx, dependencies, &mut marks, &mut stack),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn node(spec: StartupSpec, log: &Arc<Mutex<Vec<String>>>) -> StartupNode {
+        let log = log.clone();
+        let name = spec.name.clone();
+        StartupNode::new(spec, StartupNodeKind::State, move || async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            log.lock().push(name);
+            Ok(())
+        })
+    }
+
+    fn noop(spec: StartupSpec) -> StartupNode {
+        StartupNode::new(spec, StartupNodeKind::Task, || async { Ok(()) })
+    }
+
+    /// Dependency cycles are reported with the full chain.
+    #[test]
+    fn cycle_detection() {
+        let err = StartupGraph::new(vec![
+            noop(StartupSpec::new("a").depends_on(["b"])),
+            noop(StartupSpec::new("b").depends_on(["c"])),
+            noop(StartupSpec::new("c").depends_on(["a"])),
+            noop(StartupSpec::new("d")),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Startup dependency cycle: a -> b -> c -> a"
+        );
+        let err =
+            StartupGraph::new(vec![noop(StartupSpec::new("a").depends_on(["a"]))]).unwrap_err();
+        assert_eq!(err, StartupError::Cycle(vec!["a".into(), "a".into()]));
+    }
+
+    /// Missing dependencies and duplicate names are rejected.
+    #[test]
+    fn missing_dependency() {
+        let err = StartupGraph::new(vec![noop(
+            StartupSpec::new("worker").depends_on(["redis_pool"]),
+        )])
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Startup node worker depends on missing node redis_pool"
+        );
+        let err = StartupGraph::new(vec![noop("a".into()), noop("a".into())]).unwrap_err();
+        assert_eq!(err, StartupError::Duplicate("a".into()));
+    }
+
+    /// Dependencies are initialized before dependents, failures skip dependents.
+    #[tokio::test]
+    async fn ordering() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let graph = StartupGraph::new(vec![
+            node(
+                StartupSpec::new("worker").depends_on(["pool", "client"]),
+                &log,
+            ),
+            node(StartupSpec::new("client").depends_on(["pool"]), &log),
+            node(StartupSpec::new("pool"), &log),
+            node(StartupSpec::new("other"), &log),
+        ])
+        .unwrap();
+        let timeline = StartupTimeline::default();
+        assert!(graph.run(&StartupConfig::default(), timeline.clone()).await);
+        let log = log.lock().clone();
+        let pos = |name: &str| log.iter().position(|n| n == name).unwrap();
+        assert!(pos("pool") < pos("client"));
+        assert!(pos("client") < pos("worker"));
+        assert!(timeline
+            .0
+            .lock()
+            .iter()
+            .all(|entry| entry.status == NodeStatus::Ok && entry.duration.is_some()));
+
+        let graph = StartupGraph::new(vec![
+            StartupNode::new("pool".into(), StartupNodeKind::State, || async {
+                Err("connection refused".into())
+            }),
+            noop(StartupSpec::new("worker").depends_on(["pool"])),
+        ])
+        .unwrap();
+        let timeline = StartupTimeline::default();
+        assert!(!graph.run(&StartupConfig::default(), timeline.clone()).await);
+        let entries = timeline.0.lock();
+        assert_eq!(
+            entries[0].status,
+            NodeStatus::Failed("connection refused".into())
+        );
+        assert_eq!(entries[1].status, NodeStatus::Skipped);
+    }
+
+    /// Independent nodes run in parallel, up to configured limit.
+    #[tokio::test]
+    async fn parallelism() {
+        let current = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let nodes = (0..6)
+            .map(|i| {
+                let (current, peak) = (current.clone(), peak.clone());
+                StartupNode::new(
+                    StartupSpec::new(i.to_string()),
+                    StartupNodeKind::Task,
+                    move || async move {
+                        let now = current.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        current.fetch_sub(1, Ordering::SeqCst);
+                        Ok(())
+                    },
+                )
+            })
+            .collect();
+        let config = StartupConfig::default().with_parallelism(2);
+        assert!(
+            StartupGraph::new(nodes)
+                .unwrap()
+                .run(&config, StartupTimeline::default())
+                .await
+        );
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+}
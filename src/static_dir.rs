@@ -0,0 +1,516 @@
+//! Static asset directory serving.
+
+use std::{
+    convert::Infallible,
+    path::PathBuf,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::{
+    body::Body,
+    http::{
+        header::{CACHE_CONTROL, CONTENT_LENGTH, ETAG, IF_NONE_MATCH, LAST_MODIFIED},
+        HeaderValue, Method, Request, Response, StatusCode,
+    },
+};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tower::{Service, ServiceExt};
+use tower_http::services::{ServeDir, ServeFile};
+
+use crate::layers::ext::HandlerName;
+
+/// Error type used in static asset directory configuration.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum StaticDirError {
+    /// Invalid Cache-Control header value.
+    #[error("Invalid Cache-Control value for static directory {0}: {1}")]
+    InvalidCacheControl(String, String),
+    /// Invalid URL prefix.
+    #[error("Invalid URL prefix for static directory: {0}")]
+    InvalidPrefix(String),
+    /// Static directory URL prefix conflicts with a handler path.
+    #[error("Static directory {prefix} conflicts with handler {handler} at {path}")]
+    Conflict {
+        /// URL prefix of static directory.
+        prefix: String,
+        /// Conflicting handler name.
+        handler: String,
+        /// Conflicting handler path.
+        path: String,
+    },
+}
+
+/// Mounted static asset directory.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct StaticDirConfig {
+    /// URL path prefix.
+    #[serde(alias = "path_prefix")]
+    pub prefix: String,
+    /// File system path to serve files from.
+    #[serde(alias = "directory")]
+    pub path: PathBuf,
+    /// Serving options.
+    #[serde(flatten)]
+    pub options: StaticOptions,
+}
+
+/// Options for serving static asset directory.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct StaticOptions {
+    /// Serve precompressed `.gz` and `.br` sibling files, if present and accepted by client.
+    #[serde(default)]
+    precompressed: bool,
+    /// Serve `index.html` when directory is requested.
+    ///
+    /// Directory listings are never generated.
+    #[serde(default = "crate::util::default_true")]
+    index_html: bool,
+    /// File served for GET and HEAD requests not matching any file, relative to directory.
+    ///
+    /// Usually `index.html`, for single-page applications with client-side routing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    index_file: Option<PathBuf>,
+    /// Generate weak `ETag` headers and handle `If-None-Match` requests.
+    ///
+    /// `Last-Modified` header is always generated.
+    #[serde(default = "crate::util::default_true")]
+    etag: bool,
+    /// Cache-Control header values for classes of file extensions.
+    ///
+    /// First matching rule wins.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    cache_control: Vec<StaticCacheRule>,
+    /// Cache-Control header value for files not matched by [`Self::cache_control`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default_cache_control: Option<String>,
+}
+
+impl Default for StaticOptions {
+    fn default() -> Self {
+        Self {
+            precompressed: false,
+            index_html: true,
+            index_file: None,
+            etag: true,
+            cache_control: Vec::new(),
+            default_cache_control: None,
+        }
+    }
+}
+
+/// Cache-Control rule for a class of file extensions.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct StaticCacheRule {
+    /// File extensions, without leading dot.
+    pub extensions: Vec<String>,
+    /// Cache-Control header value.
+    pub value: String,
+}
+
+impl StaticOptions {
+    /// Enable or disable serving of precompressed `.gz` and `.br` files.
+    #[must_use]
+    pub fn with_precompressed(mut self, precompressed: bool) -> Self {
+        self.precompressed = precompressed;
+        self
+    }
+
+    /// Enable or disable serving `index.html` for directories.
+    #[must_use]
+    pub fn with_index_html(mut self, index_html: bool) -> Self {
+        self.index_html = index_html;
+        self
+    }
+
+    /// Set file served for paths not matching any file.
+    #[must_use]
+    pub fn with_index_file(mut self, index_file: impl Into<PathBuf>) -> Self {
+        self.index_file = Some(index_file.into());
+        self
+    }
+
+    /// Enable or disable `ETag` generation.
+    #[must_use]
+    pub fn with_etag(mut self, etag: bool) -> Self {
+        self.etag = etag;
+        self
+    }
+
+    /// Add Cache-Control rule for a class of file extensions.
+    #[must_use]
+    pub fn with_cache_control<I, S>(mut self, extensions: I, value: impl ToString) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        self.cache_control.push(StaticCacheRule {
+            extensions: extensions.into_iter().map(|ext| ext.to_string()).collect(),
+            value: value.to_string(),
+        });
+        self
+    }
+
+    /// Set Cache-Control value for files not matched by any rule.
+    #[must_use]
+    pub fn with_default_cache_control(mut self, value: impl ToString) -> Self {
+        self.default_cache_control = Some(value.to_string());
+        self
+    }
+}
+
+impl StaticDirConfig {
+    /// Create new static directory configuration.
+    #[must_use]
+    pub fn new(prefix: impl ToString, path: impl Into<PathBuf>, options: StaticOptions) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            path: path.into(),
+            options,
+        }
+    }
+
+    /// Normalized URL prefix, without trailing slash.
+    #[must_use]
+    pub fn normalized_prefix(&self) -> String {
+        let prefix = self.prefix.trim_end_matches('/');
+        match prefix.starts_with('/') {
+            true => prefix.to_string(),
+            false => format!("/{prefix}"),
+        }
+    }
+
+    /// Pseudo-handler name used in metrics.
+    #[must_use]
+    pub fn handler_name(&self) -> String {
+        format!("static:{}", self.normalized_prefix())
+    }
+
+    /// Check URL prefix for conflicts with handler paths.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if any handler path is inside of this static directory.
+    pub fn check_conflicts<'a>(
+        &self,
+        handlers: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Result<(), StaticDirError> {
+        let prefix = self.normalized_prefix();
+        if prefix == "/" || prefix.is_empty() {
+            return Err(StaticDirError::InvalidPrefix(self.prefix.clone()));
+        }
+        for (name, path) in handlers {
+            let inside = path
+                .strip_prefix(&prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+            if inside {
+                return Err(StaticDirError::Conflict {
+                    prefix,
+                    handler: name.to_string(),
+                    path: path.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Build [`tower`] service for serving files.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if some Cache-Control value is invalid.
+    pub(crate) fn build_service(&self) -> Result<StaticDirService, StaticDirError> {
+        let opts = &self.options;
+        let parse = |value: &str| {
+            HeaderValue::from_str(value).map_err(|_| {
+                StaticDirError::InvalidCacheControl(self.normalized_prefix(), value.to_string())
+            })
+        };
+        let rules = opts
+            .cache_control
+            .iter()
+            .map(|rule| Ok((rule.extensions.clone(), parse(&rule.value)?)))
+            .collect::<Result<_, StaticDirError>>()?;
+        let default_cache_control = opts
+            .default_cache_control
+            .as_deref()
+            .map(parse)
+            .transpose()?;
+        let mut serve_dir =
+            ServeDir::new(&self.path).append_index_html_on_directories(opts.index_html);
+        let mut index_file = opts
+            .index_file
+            .as_ref()
+            .map(|file| ServeFile::new(self.path.join(file)));
+        if opts.precompressed {
+            serve_dir = serve_dir.precompressed_gzip().precompressed_br();
+            index_file = index_file.map(|file| file.precompressed_gzip().precompressed_br());
+        }
+        // Leaked once per mount at build time, as handler names must be static.
+        let name: &'static str = Box::leak(self.handler_name().into_boxed_str());
+        Ok(StaticDirService {
+            serve_dir,
+            index_file,
+            inner: Arc::new(StaticDirInner {
+                name: HandlerName::new(name),
+                rules,
+                default_cache_control,
+                index_path: opts
+                    .index_file
+                    .as_ref()
+                    .map(|file| file.to_string_lossy().into_owned()),
+                etag: opts.etag,
+            }),
+        })
+    }
+}
+
+/// Shared immutable state of [`StaticDirService`].
+struct StaticDirInner {
+    /// Pseudo-handler name.
+    name: HandlerName,
+    /// Parsed Cache-Control rules.
+    rules: Vec<(Vec<String>, HeaderValue)>,
+    /// Parsed default Cache-Control value.
+    default_cache_control: Option<HeaderValue>,
+    /// Path of fallback file, used to choose Cache-Control value.
+    index_path: Option<String>,
+    /// Whether `ETag` generation is enabled.
+    etag: bool,
+}
+
+impl StaticDirInner {
+    /// Find Cache-Control value for request path.
+    fn cache_control(&self, path: &str) -> Option<&HeaderValue> {
+        let file = path.rsplit('/').next().unwrap_or_default();
+        let ext = file.rsplit_once('.').map(|(_, ext)| ext);
+        ext.and_then(|ext| {
+            self.rules
+                .iter()
+                .find(|(exts, _)| exts.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+                .map(|(_, value)| value)
+        })
+        .or(self.default_cache_control.as_ref())
+    }
+}
+
+/// Static asset directory [`tower`] service.
+#[derive(Clone)]
+pub(crate) struct StaticDirService {
+    /// File serving service.
+    serve_dir: ServeDir,
+    /// Fallback file serving service.
+    index_file: Option<ServeFile>,
+    /// Shared state.
+    inner: Arc<StaticDirInner>,
+}
+
+impl Service<Request<Body>> for StaticDirService {
+    type Response = Response<Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let serve_dir = self.serve_dir.clone();
+        let index_file = self
+            .index_file
+            .clone()
+            .filter(|_| req.method() == Method::GET || req.method() == Method::HEAD);
+        let inner = Arc::clone(&self.inner);
+        let mut path = req.uri().path().to_string();
+        let if_none_match = req.headers().get(IF_NONE_MATCH).cloned();
+        // Only method and headers are used when serving index file.
+        let fallback_req = index_file.as_ref().map(|_| {
+            let mut fallback_req = Request::new(Body::empty());
+            *fallback_req.method_mut() = req.method().clone();
+            *fallback_req.headers_mut() = req.headers().clone();
+            fallback_req
+        });
+        Box::pin(async move {
+            let mut resp = serve_dir.oneshot(req).await?.map(Body::new);
+            if let (StatusCode::NOT_FOUND, Some(index_file), Some(fallback_req)) =
+                (resp.status(), index_file, fallback_req)
+            {
+                resp = index_file.oneshot(fallback_req).await?.map(Body::new);
+                path = inner.index_path.clone().unwrap_or_default();
+            }
+            let (mut parts, body) = resp.into_parts();
+            parts.extensions.insert(inner.name);
+            if !parts.status.is_success() && parts.status != StatusCode::NOT_MODIFIED {
+                return Ok(Response::from_parts(parts, body));
+            }
+            if let Some(value) = inner.cache_control(&path) {
+                parts.headers.insert(CACHE_CONTROL, value.clone());
+            }
+            if inner.etag {
+                if let Some(etag) = weak_etag(&parts.headers) {
+                    let matched = if_none_match
+                        .as_ref()
+                        .and_then(|hv| hv.to_str().ok())
+                        .is_some_and(|inm| etag_matches(inm, &etag));
+                    parts.headers.insert(ETAG, etag);
+                    if matched && parts.status == StatusCode::OK {
+                        parts.status = StatusCode::NOT_MODIFIED;
+                        parts.headers.remove(CONTENT_LENGTH);
+                        return Ok(Response::from_parts(parts, Body::empty()));
+                    }
+                }
+            }
+            Ok(Response::from_parts(parts, body))
+        })
+    }
+}
+
+/// Build weak `ETag` from file size and modification time.
+fn weak_etag(headers: &http::HeaderMap) -> Option<HeaderValue> {
+    let len = headers.get(CONTENT_LENGTH)?.to_str().ok()?;
+    let modified = headers.get(LAST_MODIFIED)?.to_str().ok()?;
+    let modified = httpdate::parse_http_date(modified)
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    HeaderValue::from_str(&format!("W/\"{len}-{modified:x}\"")).ok()
+}
+
+/// Check whether `If-None-Match` header value matches `ETag`.
+///
+/// Uses weak comparison, as per RFC 9110.
+pub(crate) fn etag_matches(if_none_match: &str, etag: &HeaderValue) -> bool {
+    let etag = etag.to_str().unwrap_or_default().trim_start_matches("W/");
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::Router;
+
+    use super::*;
+
+    fn router() -> Router {
+        let cfg = StaticDirConfig::new(
+            "/static/",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/src/layers"),
+            StaticOptions::default()
+                .with_cache_control(["rs", "toml"], "max-age=3600")
+                .with_default_cache_control("no-cache"),
+        );
+        Router::new().nest_service(&cfg.normalized_prefix(), cfg.build_service().unwrap())
+    }
+
+    async fn get(uri: &str, if_none_match: Option<&HeaderValue>) -> Response<Body> {
+        let mut req = Request::get(uri).body(Body::empty()).unwrap();
+        if let Some(inm) = if_none_match {
+            req.headers_mut().insert(IF_NONE_MATCH, inm.clone());
+        }
+        router().oneshot(req).await.unwrap()
+    }
+
+    /// Files are served with cache headers and pseudo-handler name.
+    #[tokio::test]
+    async fn serve_file() {
+        let resp = get("/static/mod.rs", None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[CACHE_CONTROL], "max-age=3600");
+        assert!(resp.headers().contains_key(LAST_MODIFIED));
+        assert_eq!(
+            resp.extensions().get::<HandlerName>().unwrap().as_str(),
+            "static:/static"
+        );
+        let etag = resp.headers()[ETAG].clone();
+        let resp = get("/static/mod.rs", Some(&etag)).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(resp.headers()[ETAG], etag);
+    }
+
+    /// Index file is served for unknown paths.
+    #[tokio::test]
+    async fn index_file() {
+        let cfg = StaticDirConfig::new(
+            "/app",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/src/layers"),
+            StaticOptions::default()
+                .with_index_file("mod.rs")
+                .with_cache_control(["rs"], "no-cache"),
+        );
+        let rtr =
+            Router::new().nest_service(&cfg.normalized_prefix(), cfg.build_service().unwrap());
+        let call = |method: Method, uri: &str| {
+            rtr.clone().oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+        let resp = call(Method::GET, "/app/users/42").await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()[CACHE_CONTROL], "no-cache");
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(bytes.starts_with(b"//! Various [`tower`] layers"));
+        let resp = call(Method::POST, "/app/users/42").await.unwrap();
+        assert_ne!(resp.status(), StatusCode::OK);
+    }
+
+    /// Configuration accepts alternative key names.
+    #[test]
+    fn aliases() {
+        let cfg: StaticDirConfig = serde_json::from_value(serde_json::json!({
+            "path_prefix": "/app",
+            "directory": "dist",
+            "index_file": "index.html",
+            "precompressed": true,
+            "default_cache_control": "no-cache",
+        }))
+        .unwrap();
+        assert_eq!(cfg.prefix, "/app");
+        assert_eq!(cfg.path, PathBuf::from("dist"));
+        assert_eq!(cfg.options.index_file, Some(PathBuf::from("index.html")));
+        assert!(cfg.options.precompressed);
+    }
+
+    /// Path traversal attempts do not escape served directory.
+    #[tokio::test]
+    async fn path_traversal() {
+        for uri in [
+            "/static/../lib.rs",
+            "/static/%2e%2e/lib.rs",
+            "/static/..%2flib.rs",
+            "/static/%2e%2e%2f%2e%2e/Cargo.toml",
+            "/static//etc/passwd",
+        ] {
+            assert_eq!(
+                get(uri, None).await.status(),
+                StatusCode::NOT_FOUND,
+                "{uri}"
+            );
+        }
+    }
+
+    /// Conflicts with handler paths are detected.
+    #[test]
+    fn conflicts() {
+        let cfg = StaticDirConfig::new("assets/", "/tmp", StaticOptions::default());
+        assert!(cfg.check_conflicts([("a", "/assets-v2/x")]).is_ok());
+        assert!(cfg.check_conflicts([("a", "/assets/x")]).is_err());
+        assert!(cfg.check_conflicts([("a", "/assets")]).is_err());
+        let root = StaticDirConfig::new("/", "/tmp", StaticOptions::default());
+        assert!(root.check_conflicts([]).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! Startup failure policies and status registry of optional subsystems.
+
+use std::{
+    borrow::Borrow,
+    collections::BTreeMap,
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use axum::{error_handling::HandleErrorLayer, response::IntoResponse, routing, Json, Router};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tower::ServiceBuilder;
+use tracing::{debug_span, error, info, warn};
+
+use crate::{
+    auth::{AuthExtractor, AuthLayer, AuthProvider},
+    builder::app::error_handler,
+};
+
+/// Upper bound of delay between background initialization attempts.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// What to do when an optional subsystem fails to initialize at startup.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+#[serde(rename_all = "snake_case")]
+pub enum InitFailurePolicy {
+    /// Abort startup.
+    #[default]
+    Fail,
+    /// Continue without the subsystem, marking it as degraded.
+    Degrade,
+    /// Continue without the subsystem, retrying initialization in background.
+    RetryBackground,
+}
+
+/// Current state of an optional subsystem.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+#[serde(rename_all = "snake_case")]
+pub enum SubsystemState {
+    /// Subsystem is installed and running.
+    Active,
+    /// Subsystem failed to initialize, service is running without it.
+    Degraded,
+    /// Subsystem failed to initialize, background retry is in progress.
+    Retrying,
+}
+
+/// Status record of an optional subsystem.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct SubsystemStatus {
+    /// Current state.
+    pub state: SubsystemState,
+    /// Last initialization error, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Number of failed initialization attempts.
+    pub failed_attempts: u32,
+    /// Time of last state change.
+    #[serde(with = "humantime_serde")]
+    pub since: SystemTime,
+}
+
+/// Registry of optional subsystem statuses, keyed by subsystem name.
+static REGISTRY: Lazy<RwLock<BTreeMap<String, SubsystemStatus>>> = Lazy::new(Default::default);
+
+/// Update status of a subsystem.
+fn set_status(name: &str, state: SubsystemState, error: Option<String>) {
+    let mut registry = REGISTRY.write();
+    let failed_attempts = registry
+        .get(name)
+        .map_or(0, |status| status.failed_attempts)
+        + u32::from(error.is_some());
+    registry.insert(
+        name.to_string(),
+        SubsystemStatus {
+            state,
+            error,
+            failed_attempts,
+            since: SystemTime::now(),
+        },
+    );
+}
+
+/// Mark subsystem as installed and running.
+pub(crate) fn mark_active(name: &str) {
+    set_status(name, SubsystemState::Active, None);
+}
+
+/// Get statuses of all registered optional subsystems.
+#[must_use]
+pub fn subsystem_statuses() -> BTreeMap<String, SubsystemStatus> {
+    REGISTRY.read().clone()
+}
+
+/// Apply startup failure policy to subsystem initialization result.
+///
+/// Returns `Ok(None)` if initialization failed, but the service should continue without the
+/// subsystem. With [`InitFailurePolicy::RetryBackground`], caller is expected to start a retry
+/// task using [`spawn_retry`].
+///
+/// # Errors
+///
+/// Returns initialization error as-is when policy is [`InitFailurePolicy::Fail`].
+pub(crate) fn apply_policy<T, E>(
+    name: &str,
+    policy: InitFailurePolicy,
+    result: Result<T, E>,
+) -> Result<Option<T>, E>
+where
+    E: fmt::Display,
+{
+    let err = match result {
+        Ok(val) => {
+            mark_active(name);
+            return Ok(Some(val));
+        }
+        Err(err) => err,
+    };
+    match policy {
+        InitFailurePolicy::Fail => Err(err),
+        InitFailurePolicy::Degrade => {
+            warn!(
+                subsystem = name,
+                %err,
+                "SUBSYSTEM DEGRADED: initialization failed, continuing without it"
+            );
+            set_status(name, SubsystemState::Degraded, Some(err.to_string()));
+            Ok(None)
+        }
+        InitFailurePolicy::RetryBackground => {
+            warn!(
+                subsystem = name,
+                %err,
+                "SUBSYSTEM DEGRADED: initialization failed, retrying in background"
+            );
+            set_status(name, SubsystemState::Retrying, Some(err.to_string()));
+            Ok(None)
+        }
+    }
+}
+
+/// Spawn a supervised task retrying subsystem initialization until it succeeds.
+///
+/// `build` is run on a blocking thread, so it may perform blocking I/O. Panics are treated as
+/// failed attempts. Delay between attempts starts at `initial_delay` and doubles after each
+/// failure, up to one minute. On success, the result is passed to `install`.
+pub(crate) fn spawn_retry<T, E, B, I>(
+    name: &str,
+    initial_delay: Duration,
+    build: B,
+    install: I,
+) -> JoinHandle<()>
+where
+    T: Send + 'static,
+    E: fmt::Display + Send + 'static,
+    B: Fn() -> Result<T, E> + Send + Sync + 'static,
+    I: FnOnce(T) + Send + 'static,
+{
+    let name = name.to_string();
+    let build = Arc::new(build);
+    tokio::spawn(async move {
+        let mut delay = initial_delay;
+        loop {
+            tokio::time::sleep(delay).await;
+            let attempt = Arc::clone(&build);
+            match tokio::task::spawn_blocking(move || attempt()).await {
+                Ok(Ok(val)) => {
+                    install(val);
+                    mark_active(&name);
+                    info!(subsystem = name, "subsystem initialized after retry");
+                    return;
+                }
+                Ok(Err(err)) => {
+                    warn!(subsystem = name, %err, ?delay, "subsystem initialization retry failed");
+                    set_status(&name, SubsystemState::Retrying, Some(err.to_string()));
+                }
+                Err(err) => {
+                    error!(subsystem = name, %err, "subsystem initialization attempt panicked");
+                    set_status(&name, SubsystemState::Retrying, Some(err.to_string()));
+                }
+            }
+            delay = (delay * 2).min(MAX_RETRY_DELAY.max(initial_delay));
+        }
+    })
+}
+
+/// Build Axum router containing subsystem status endpoint.
+pub(crate) fn build_router<AuthProv, AuthExt>(
+    path: &str,
+    auth_provider: AuthProv,
+    auth_extractor: AuthExt,
+) -> Router
+where
+    AuthProv: AuthProvider + Sync + 'static,
+    AuthExt: AuthExtractor + Sync + 'static,
+    AuthExt::User: Borrow<AuthProv::User>,
+    AuthExt::AuthTokens: Borrow<AuthProv::AuthTokens>,
+{
+    let _span = debug_span!("build_subsystem_status").entered();
+    Router::new().route(path, routing::get(get_statuses)).layer(
+        ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(error_handler))
+            .layer(AuthLayer::new(
+                &["telemetry"],
+                auth_provider,
+                auth_extractor,
+            )),
+    )
+}
+
+/// Get statuses of all optional subsystems.
+async fn get_statuses() -> impl IntoResponse {
+    Json(subsystem_statuses())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct Unreachable;
+
+    impl fmt::Display for Unreachable {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("unreachable")
+        }
+    }
+
+    /// Policies decide whether initialization error is propagated.
+    #[test]
+    fn policies() {
+        let res = apply_policy::<(), _>("test.fail", InitFailurePolicy::Fail, Err(Unreachable));
+        assert!(res.is_err());
+        assert!(!subsystem_statuses().contains_key("test.fail"));
+
+        let res =
+            apply_policy::<(), _>("test.degrade", InitFailurePolicy::Degrade, Err(Unreachable));
+        assert!(matches!(res, Ok(None)));
+        let status = &subsystem_statuses()["test.degrade"];
+        assert_eq!(status.state, SubsystemState::Degraded);
+        assert_eq!(status.error.as_deref(), Some("unreachable"));
+        assert_eq!(status.failed_attempts, 1);
+
+        let res = apply_policy(
+            "test.ok",
+            InitFailurePolicy::Degrade,
+            Ok::<_, Unreachable>(1),
+        );
+        assert!(matches!(res, Ok(Some(1))));
+        assert_eq!(
+            subsystem_statuses()["test.ok"].state,
+            SubsystemState::Active
+        );
+    }
+
+    /// Background retry installs the subsystem after it starts succeeding.
+    #[tokio::test]
+    async fn background_retry() {
+        let res = apply_policy::<(), _>(
+            "test.retry",
+            InitFailurePolicy::RetryBackground,
+            Err(Unreachable),
+        );
+        assert!(matches!(res, Ok(None)));
+        assert_eq!(
+            subsystem_statuses()["test.retry"].state,
+            SubsystemState::Retrying
+        );
+        let attempts = Arc::new(AtomicU32::new(0));
+        let installed = Arc::new(AtomicU32::new(0));
+        let (attempts_ref, installed_ref) = (attempts.clone(), installed.clone());
+        spawn_retry(
+            "test.retry",
+            Duration::from_millis(1),
+            move || match attempts_ref.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => Err(Unreachable),
+                2 => panic!("flaky"),
+                n => Ok(n),
+            },
+            move |val| installed_ref.store(val, Ordering::SeqCst),
+        )
+        .await
+        .unwrap();
+        assert_eq!(installed.load(Ordering::SeqCst), 3);
+        let status = &subsystem_statuses()["test.retry"];
+        assert_eq!(status.state, SubsystemState::Active);
+        assert_eq!(status.failed_attempts, 4);
+    }
+}
@@ -1,5 +1,6 @@
-use std::time::Duration;
+use std::{borrow::Borrow, time::Duration};
 
+use axum::Router;
 use opentelemetry::KeyValue;
 use opentelemetry_resource_detectors::{OsResourceDetector, ProcessResourceDetector};
 use opentelemetry_sdk::{
@@ -9,7 +10,10 @@ use opentelemetry_sdk::{
 use opentelemetry_semantic_conventions::resource as res;
 use serde::{Deserialize, Serialize};
 
-use crate::config::AppConfig;
+use crate::{
+    auth::{AuthExtractor, AuthProvider},
+    config::AppConfig,
+};
 
 /// Common OpenTelemetry configuration.
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
@@ -20,12 +24,16 @@ pub struct OpenTelemetryConfig {
         with = "humantime_serde"
     )]
     pub detector_timeout: Duration,
+    /// URL path for optional subsystem status endpoint.
+    #[serde(default = "OpenTelemetryConfig::default_status_path")]
+    pub status_path: String,
 }
 
 impl Default for OpenTelemetryConfig {
     fn default() -> Self {
         Self {
             detector_timeout: Self::default_detector_timeout(),
+            status_path: Self::default_status_path(),
         }
     }
 }
@@ -35,6 +43,26 @@ impl OpenTelemetryConfig {
     fn default_detector_timeout() -> Duration {
         Duration::from_secs(6)
     }
+
+    /// Default value for [`Self::status_path`].
+    fn default_status_path() -> String {
+        "/telemetry/exporters".into()
+    }
+
+    /// Build Axum router containing optional subsystem status endpoint.
+    pub(crate) fn build_router<AuthProv, AuthExt>(
+        &self,
+        auth_provider: AuthProv,
+        auth_extractor: AuthExt,
+    ) -> Router
+    where
+        AuthProv: AuthProvider + Sync + 'static,
+        AuthExt: AuthExtractor + Sync + 'static,
+        AuthExt::User: Borrow<AuthProv::User>,
+        AuthExt::AuthTokens: Borrow<AuthProv::AuthTokens>,
+    {
+        crate::subsystem::build_router(&self.status_path, auth_provider, auth_extractor)
+    }
 }
 
 impl AppConfig {
@@ -59,16 +87,18 @@ impl AppConfig {
         );
         // TODO: res::SERVICE_NAMESPACE.
         // TODO: res::DEPLOYMENT_ENVIRONMENT.
-        let mut static_resources = Vec::new();
+        let mut static_resources = vec![KeyValue::new(
+            // Experimental semantic convention, not exposed by default.
+            "service.instance.id",
+            crate::layers::identity::instance_id(),
+        )];
         if let Some(val) = &self.app_name {
             static_resources.push(KeyValue::new(res::SERVICE_NAME, val.clone()));
         }
         if let Some(val) = &self.app_version {
             static_resources.push(KeyValue::new(res::SERVICE_VERSION, val.clone()));
         }
-        if !static_resources.is_empty() {
-            resource = resource.merge(&mut Resource::new(static_resources));
-        }
+        resource = resource.merge(&mut Resource::new(static_resources));
         self.otel_res = Some(resource.clone());
         resource
     }
@@ -0,0 +1,76 @@
+//! Span processor which allows installing span exporter after tracing pipeline is built.
+
+use std::sync::Arc;
+
+use opentelemetry::{trace::TraceResult, Context};
+use opentelemetry_sdk::{
+    export::trace::SpanData,
+    runtime::Tokio,
+    trace::{BatchSpanProcessor, Span, SpanProcessor},
+    Resource,
+};
+use parking_lot::RwLock;
+
+/// Span processor forwarding spans to a late-installed batch processor.
+///
+/// Spans ended before a processor is installed are dropped.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ExporterSlot(Arc<RwLock<ExporterSlotInner>>);
+
+/// Inner state of [`ExporterSlot`].
+#[derive(Debug, Default)]
+struct ExporterSlotInner {
+    /// Installed batch processor.
+    processor: Option<BatchSpanProcessor<Tokio>>,
+    /// Resource set by tracer provider, passed to processor when it is installed.
+    resource: Option<Resource>,
+}
+
+impl ExporterSlot {
+    /// Install batch processor, replacing previous one.
+    pub(crate) fn install(&self, mut processor: BatchSpanProcessor<Tokio>) {
+        let mut inner = self.0.write();
+        if let Some(resource) = &inner.resource {
+            processor.set_resource(resource);
+        }
+        if let Some(old) = inner.processor.replace(processor) {
+            let _ = old.shutdown();
+        }
+    }
+}
+
+impl SpanProcessor for ExporterSlot {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        if let Some(processor) = &self.0.read().processor {
+            processor.on_start(span, cx);
+        }
+    }
+
+    fn on_end(&self, span: SpanData) {
+        if let Some(processor) = &self.0.read().processor {
+            processor.on_end(span);
+        }
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        match &self.0.read().processor {
+            Some(processor) => processor.force_flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn shutdown(&self) -> TraceResult<()> {
+        match &self.0.read().processor {
+            Some(processor) => processor.shutdown(),
+            None => Ok(()),
+        }
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        let mut inner = self.0.write();
+        if let Some(processor) = inner.processor.as_mut() {
+            processor.set_resource(resource);
+        }
+        inner.resource = Some(resource.clone());
+    }
+}
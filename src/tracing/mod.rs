@@ -1,12 +1,18 @@
 //! Code to set up trace collection, aggregation and transport.
 
-use std::{num::NonZeroUsize, time::Duration};
+mod exporter;
+pub(crate) mod propagation;
+pub(crate) mod sampling;
 
-use opentelemetry_otlp::{Protocol, TonicExporterBuilder, WithExportConfig};
+use std::{net::TcpStream, num::NonZeroUsize, sync::Arc, time::Duration};
+
+use opentelemetry::trace::TraceError;
+use opentelemetry_otlp::{Protocol, SpanExporterBuilder, TonicExporterBuilder, WithExportConfig};
 use opentelemetry_sdk::{
     runtime::Tokio,
     trace::{
-        BatchConfig, BatchConfigBuilder, Config, RandomIdGenerator, Sampler, Tracer, TracerProvider,
+        BatchConfig, BatchConfigBuilder, BatchSpanProcessor, Config, RandomIdGenerator, Sampler,
+        Tracer, TracerProvider,
     },
     Resource,
 };
@@ -21,7 +27,19 @@ use tracing_subscriber::{
 };
 use url::Url;
 
-use crate::logging::LoggingLevel;
+use crate::{
+    baggage::BaggageConfig,
+    logging::LoggingLevel,
+    subsystem::{self, InitFailurePolicy},
+    tracing::{
+        exporter::ExporterSlot,
+        propagation::TracePropagation,
+        sampling::{DynamicSampler, SamplingControl, SamplingControlConfig},
+    },
+};
+
+/// Name of tracing exporter in subsystem status registry.
+const SUBSYSTEM_NAME: &str = "tracing.exporter";
 
 /// Error type used in tracing configuration.
 #[derive(Debug, Error)]
@@ -29,7 +47,10 @@ use crate::logging::LoggingLevel;
 pub enum TracingError {
     // OTel tracing error.
     #[error("OTel tracing error: {0}")]
-    OpenTelemetry(#[from] opentelemetry::trace::TraceError),
+    OpenTelemetry(#[from] TraceError),
+    /// Trace collector does not accept connections.
+    #[error("Trace collector {0} is unreachable: {1}")]
+    Unreachable(String, String),
 }
 
 /// OpenTelemetry tracing configuration.
@@ -45,9 +66,32 @@ pub struct TracingConfig {
     /// OTLP collector timeout.
     #[serde(default = "TracingConfig::default_timeout")]
     timeout: Duration,
+    /// Check that collector accepts connections before installing exporter.
+    ///
+    /// Exporter connects lazily, so without this check unreachable collector is only detected
+    /// when exporting spans.
+    #[serde(default)]
+    verify_endpoint: bool,
+    /// What to do if exporter cannot be initialized at startup.
+    #[serde(default)]
+    on_init_failure: InitFailurePolicy,
+    /// Initial delay between background initialization attempts.
+    ///
+    /// Used only with [`InitFailurePolicy::RetryBackground`]. Doubles after each failed
+    /// attempt, up to one minute.
+    #[serde(
+        default = "TracingConfig::default_init_retry_delay",
+        with = "humantime_serde"
+    )]
+    init_retry_delay: Duration,
     /// Sampling rule.
     #[serde(default)]
     sample: TracingSampler,
+    /// Runtime adjustment of sampling ratio.
+    ///
+    /// Sampling ratio is fixed if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sampling_control: Option<SamplingControlConfig>,
     /// Minimum severity level to export.
     #[serde(default)]
     level: LoggingLevel,
@@ -60,6 +104,12 @@ pub struct TracingConfig {
     /// Batch span processor configuration.
     #[serde(default)]
     batch: TracingBatchConfig,
+    /// Trace context propagation format for incoming and outgoing HTTP requests.
+    #[serde(default)]
+    propagation: TracePropagation,
+    /// Baggage propagation configuration.
+    #[serde(default)]
+    baggage: BaggageConfig,
 }
 
 impl Default for TracingConfig {
@@ -68,11 +118,17 @@ impl Default for TracingConfig {
             endpoint: Self::default_endpoint(),
             protocol: TracingProtocol::default(),
             timeout: Self::default_timeout(),
+            verify_endpoint: false,
+            on_init_failure: InitFailurePolicy::default(),
+            init_retry_delay: Self::default_init_retry_delay(),
             sample: TracingSampler::default(),
+            sampling_control: None,
             level: LoggingLevel::default(),
             limits: TracingSpanLimits::default(),
             include: TracingIncludes::default(),
             batch: TracingBatchConfig::default(),
+            propagation: TracePropagation::default(),
+            baggage: BaggageConfig::default(),
         }
     }
 }
@@ -94,6 +150,73 @@ impl TracingConfig {
         Duration::from_secs(opentelemetry_otlp::OTEL_EXPORTER_OTLP_TIMEOUT_DEFAULT)
     }
 
+    /// Default value for [`Self::init_retry_delay`].
+    #[must_use]
+    #[inline]
+    fn default_init_retry_delay() -> Duration {
+        Duration::from_secs(5)
+    }
+
+    /// Set trace collector endpoint URL.
+    #[must_use]
+    pub fn with_endpoint(mut self, endpoint: Url) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    /// Enable or disable collector reachability check at startup.
+    #[must_use]
+    pub fn with_verify_endpoint(mut self, verify: bool) -> Self {
+        self.verify_endpoint = verify;
+        self
+    }
+
+    /// Set what to do if exporter cannot be initialized at startup.
+    #[must_use]
+    pub fn with_init_failure_policy(mut self, policy: InitFailurePolicy) -> Self {
+        self.on_init_failure = policy;
+        self
+    }
+
+    /// Set initial delay between background initialization attempts.
+    #[must_use]
+    pub fn with_init_retry_delay(mut self, delay: Duration) -> Self {
+        self.init_retry_delay = delay;
+        self
+    }
+
+    /// Set trace context propagation format.
+    #[must_use]
+    pub fn with_propagation(mut self, propagation: TracePropagation) -> Self {
+        self.propagation = propagation;
+        self
+    }
+
+    /// Get trace context propagation format.
+    #[must_use]
+    pub(crate) fn propagation(&self) -> TracePropagation {
+        self.propagation
+    }
+
+    /// Set baggage propagation configuration.
+    #[must_use]
+    pub fn with_baggage(mut self, baggage: BaggageConfig) -> Self {
+        self.baggage = baggage;
+        self
+    }
+
+    /// Get baggage propagation configuration.
+    #[must_use]
+    pub(crate) fn baggage(&self) -> &BaggageConfig {
+        &self.baggage
+    }
+
+    /// Get runtime sampling control configuration, if enabled.
+    #[must_use]
+    pub(crate) fn sampling_control(&self) -> Option<&SamplingControlConfig> {
+        self.sampling_control.as_ref()
+    }
+
     /// Build internal protocol exporter.
     fn build_exporter(&self) -> TonicExporterBuilder {
         // TODO: allow adding metadata.
@@ -104,10 +227,41 @@ impl TracingConfig {
             .with_timeout(self.timeout)
     }
 
+    /// Check that collector endpoint accepts TCP connections.
+    fn check_endpoint(&self) -> Result<(), TracingError> {
+        let unreachable = |err: String| TracingError::Unreachable(self.endpoint.to_string(), err);
+        let addrs = self
+            .endpoint
+            .socket_addrs(|| None)
+            .map_err(|err| unreachable(err.to_string()))?;
+        let mut last_err = "no addresses resolved".to_string();
+        for addr in addrs {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(_) => return Ok(()),
+                Err(err) => last_err = err.to_string(),
+            }
+        }
+        Err(unreachable(last_err))
+    }
+
+    /// Build batch span processor, along with its exporter.
+    fn build_processor(&self) -> Result<BatchSpanProcessor<Tokio>, TracingError> {
+        if self.verify_endpoint {
+            self.check_endpoint()?;
+        }
+        let exporter = SpanExporterBuilder::from(self.build_exporter()).build_span_exporter()?;
+        Ok(BatchSpanProcessor::builder(exporter, Tokio)
+            .with_batch_config(self.build_batch_config())
+            .build())
+    }
+
     /// Build OpenTelemetry SDK configuration.
-    fn build_config(&self, resource: Resource) -> Config {
-        Config::default()
-            .with_sampler::<Sampler>(self.sample.into())
+    fn build_config(&self, resource: Resource, control: Option<Arc<SamplingControl>>) -> Config {
+        let config = match control {
+            Some(control) => Config::default().with_sampler(DynamicSampler::new(control)),
+            None => Config::default().with_sampler::<Sampler>(self.sample.into()),
+        };
+        config
             .with_id_generator(RandomIdGenerator::default())
             .with_max_events_per_span(self.limits.max_events_per_span)
             .with_max_attributes_per_span(self.limits.max_attributes_per_span)
@@ -124,18 +278,43 @@ impl TracingConfig {
 
     /// Build OpenTelemetry tracing pipeline.
     ///
+    /// If span exporter cannot be built, configured [`InitFailurePolicy`] is applied. When the
+    /// service is allowed to continue, returned provider drops all spans until exporter is
+    /// installed by background retry task, if any.
+    ///
     /// # Errors
     ///
-    /// Returns `Err` if span exporter and/or processor cannot be installed for some reason.
+    /// Returns `Err` if span exporter and/or processor cannot be installed for some reason, and
+    /// startup failure policy is [`InitFailurePolicy::Fail`].
     pub fn build_pipeline(&self, resource: Resource) -> Result<TracerProvider, TracingError> {
         let _span = debug_span!("build_tracing_pipeline").entered();
-        opentelemetry_otlp::new_pipeline()
-            .tracing()
-            .with_exporter(self.build_exporter())
-            .with_trace_config(self.build_config(resource))
-            .with_batch_config(self.build_batch_config())
-            .install_batch(Tokio)
-            .map_err(Into::into)
+        let control = self
+            .sampling_control
+            .as_ref()
+            .map(|cfg| cfg.build_control(self.sample.ratio()));
+        if let Some(control) = &control {
+            sampling::register(control.clone());
+        }
+        let slot = ExporterSlot::default();
+        let processor =
+            subsystem::apply_policy(SUBSYSTEM_NAME, self.on_init_failure, self.build_processor())?;
+        match processor {
+            Some(processor) => slot.install(processor),
+            None if self.on_init_failure == InitFailurePolicy::RetryBackground => {
+                let (config, install_slot) = (self.clone(), slot.clone());
+                subsystem::spawn_retry(
+                    SUBSYSTEM_NAME,
+                    self.init_retry_delay,
+                    move || config.build_processor(),
+                    move |processor| install_slot.install(processor),
+                );
+            }
+            None => {}
+        }
+        Ok(TracerProvider::builder()
+            .with_span_processor(slot)
+            .with_config(self.build_config(resource, control))
+            .build())
     }
 
     /// Build OpenTelemetry layer for [`tracing`].
@@ -199,6 +378,17 @@ enum TracingSampler {
     Fraction(f64),
 }
 
+impl TracingSampler {
+    /// Fraction of data to export.
+    #[must_use]
+    fn ratio(self) -> f64 {
+        match self {
+            Self::Always => 1.0,
+            Self::Fraction(frac) => frac,
+        }
+    }
+}
+
 impl From<TracingSampler> for Sampler {
     fn from(value: TracingSampler) -> Self {
         match value {
@@ -384,3 +574,66 @@ impl TracingBatchConfig {
             .with_max_concurrent_exports(self.max_concurrent_exports.get())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::subsystem::{subsystem_statuses, SubsystemState};
+
+    /// Collector endpoint with nothing listening on it.
+    fn unreachable_endpoint() -> (Url, u16) {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        (
+            Url::parse(&format!("http://127.0.0.1:{port}")).unwrap(),
+            port,
+        )
+    }
+
+    fn config(endpoint: Url, policy: InitFailurePolicy) -> TracingConfig {
+        TracingConfig::default()
+            .with_endpoint(endpoint)
+            .with_verify_endpoint(true)
+            .with_init_failure_policy(policy)
+            .with_init_retry_delay(Duration::from_millis(20))
+    }
+
+    /// Unreachable collector is handled according to startup failure policy.
+    #[tokio::test]
+    async fn unreachable_collector() {
+        let (endpoint, port) = unreachable_endpoint();
+
+        let res =
+            config(endpoint.clone(), InitFailurePolicy::Fail).build_pipeline(Resource::empty());
+        assert!(matches!(res, Err(TracingError::Unreachable(..))));
+
+        config(endpoint.clone(), InitFailurePolicy::Degrade)
+            .build_pipeline(Resource::empty())
+            .unwrap();
+        let status = &subsystem_statuses()[SUBSYSTEM_NAME];
+        assert_eq!(status.state, SubsystemState::Degraded);
+        assert!(status.error.is_some());
+
+        config(endpoint, InitFailurePolicy::RetryBackground)
+            .build_pipeline(Resource::empty())
+            .unwrap();
+        assert_eq!(
+            subsystem_statuses()[SUBSYSTEM_NAME].state,
+            SubsystemState::Retrying
+        );
+        // Collector comes up later.
+        let _listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+        for _ in 0..100 {
+            if subsystem_statuses()[SUBSYSTEM_NAME].state == SubsystemState::Active {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("exporter was not installed by background retry");
+    }
+}
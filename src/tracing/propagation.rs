@@ -0,0 +1,318 @@
+//! Trace context propagation formats.
+
+use opentelemetry::{
+    global,
+    propagation::{
+        text_map_propagator::FieldIter, Extractor, Injector, TextMapCompositePropagator,
+        TextMapPropagator,
+    },
+    trace::{
+        noop::NoopTextMapPropagator, SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId,
+        TraceState,
+    },
+    Context,
+};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use serde::{Deserialize, Serialize};
+
+use crate::baggage::BaggageConfig;
+
+/// Single B3 header.
+const B3_SINGLE_HEADER: &str = "b3";
+/// B3 trace ID header.
+const B3_TRACE_ID_HEADER: &str = "x-b3-traceid";
+/// B3 span ID header.
+const B3_SPAN_ID_HEADER: &str = "x-b3-spanid";
+/// B3 sampling decision header.
+const B3_SAMPLED_HEADER: &str = "x-b3-sampled";
+/// B3 debug flag header.
+const B3_FLAGS_HEADER: &str = "x-b3-flags";
+
+/// Format used to propagate trace context in HTTP headers.
+///
+/// Applies both to extracting context from incoming requests and to injecting it into requests
+/// made by HTTP clients.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+#[serde(rename_all = "snake_case")]
+pub enum TracePropagation {
+    /// W3C Trace Context, using `traceparent` and `tracestate` headers.
+    #[default]
+    W3c,
+    /// Zipkin B3, using single `b3` header.
+    B3,
+    /// Zipkin B3, using multiple `X-B3-*` headers.
+    B3Multi,
+    /// Do not propagate trace context.
+    None,
+}
+
+impl TracePropagation {
+    /// Build trace context propagator.
+    fn propagator(self) -> Box<dyn TextMapPropagator + Send + Sync> {
+        match self {
+            Self::W3c => Box::new(TraceContextPropagator::new()),
+            Self::B3 => Box::new(B3Propagator::new(true)),
+            Self::B3Multi => Box::new(B3Propagator::new(false)),
+            Self::None => Box::new(NoopTextMapPropagator::new()),
+        }
+    }
+
+    /// Install as global OpenTelemetry text map propagator, along with baggage propagator if
+    /// enabled.
+    pub fn install(self, baggage: &BaggageConfig) {
+        let mut propagators = vec![self.propagator()];
+        if let Some(propagator) = baggage.propagator() {
+            propagators.push(Box::new(propagator));
+        }
+        global::set_text_map_propagator(TextMapCompositePropagator::new(propagators));
+    }
+}
+
+/// Zipkin B3 propagator.
+///
+/// Extracts context from both single and multi-header encodings, injects using the configured one.
+#[derive(Debug)]
+struct B3Propagator {
+    /// Inject single `b3` header instead of `X-B3-*` headers.
+    single: bool,
+    /// Header names used for injection.
+    fields: Vec<String>,
+}
+
+impl B3Propagator {
+    /// Create new B3 propagator.
+    fn new(single: bool) -> Self {
+        let fields = if single {
+            vec![B3_SINGLE_HEADER.to_string()]
+        } else {
+            [B3_TRACE_ID_HEADER, B3_SPAN_ID_HEADER, B3_SAMPLED_HEADER]
+                .map(ToString::to_string)
+                .to_vec()
+        };
+        Self { single, fields }
+    }
+
+    /// Parse trace ID, accepting both 64-bit and 128-bit encodings.
+    fn parse_trace_id(value: &str) -> Option<TraceId> {
+        match value.len() {
+            16 | 32 if value.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                TraceId::from_hex(value).ok()
+            }
+            _ => None,
+        }
+        .filter(|id| *id != TraceId::INVALID)
+    }
+
+    /// Parse span ID.
+    fn parse_span_id(value: &str) -> Option<SpanId> {
+        (value.len() == 16 && value.bytes().all(|b| b.is_ascii_hexdigit()))
+            .then(|| SpanId::from_hex(value).ok())
+            .flatten()
+            .filter(|id| *id != SpanId::INVALID)
+    }
+
+    /// Parse sampling decision.
+    fn parse_sampled(value: &str) -> Option<TraceFlags> {
+        match value {
+            "1" | "d" | "true" => Some(TraceFlags::SAMPLED),
+            "0" | "false" => Some(TraceFlags::default()),
+            _ => None,
+        }
+    }
+
+    /// Build remote span context.
+    fn span_context(trace_id: TraceId, span_id: SpanId, flags: TraceFlags) -> SpanContext {
+        SpanContext::new(trace_id, span_id, flags, true, TraceState::default())
+    }
+
+    /// Extract span context from single `b3` header.
+    fn extract_single(extractor: &dyn Extractor) -> Option<SpanContext> {
+        let mut parts = extractor.get(B3_SINGLE_HEADER)?.trim().split('-');
+        let trace_id = Self::parse_trace_id(parts.next()?)?;
+        let span_id = Self::parse_span_id(parts.next()?)?;
+        let flags = match parts.next() {
+            Some(sampled) => Self::parse_sampled(sampled)?,
+            None => TraceFlags::SAMPLED,
+        };
+        Some(Self::span_context(trace_id, span_id, flags))
+    }
+
+    /// Extract span context from `X-B3-*` headers.
+    fn extract_multi(extractor: &dyn Extractor) -> Option<SpanContext> {
+        let trace_id = Self::parse_trace_id(extractor.get(B3_TRACE_ID_HEADER)?.trim())?;
+        let span_id = Self::parse_span_id(extractor.get(B3_SPAN_ID_HEADER)?.trim())?;
+        let flags = if extractor.get(B3_FLAGS_HEADER).map(str::trim) == Some("1") {
+            TraceFlags::SAMPLED
+        } else {
+            match extractor.get(B3_SAMPLED_HEADER) {
+                Some(sampled) => Self::parse_sampled(sampled.trim())?,
+                None => TraceFlags::SAMPLED,
+            }
+        };
+        Some(Self::span_context(trace_id, span_id, flags))
+    }
+}
+
+impl TextMapPropagator for B3Propagator {
+    fn inject_context(&self, cx: &Context, injector: &mut dyn Injector) {
+        let span = cx.span();
+        let span_context = span.span_context();
+        if !span_context.is_valid() {
+            return;
+        }
+        let sampled = if span_context.is_sampled() { "1" } else { "0" };
+        if self.single {
+            injector.set(
+                B3_SINGLE_HEADER,
+                format!(
+                    "{}-{}-{sampled}",
+                    span_context.trace_id(),
+                    span_context.span_id()
+                ),
+            );
+        } else {
+            injector.set(B3_TRACE_ID_HEADER, span_context.trace_id().to_string());
+            injector.set(B3_SPAN_ID_HEADER, span_context.span_id().to_string());
+            injector.set(B3_SAMPLED_HEADER, sampled.to_string());
+        }
+    }
+
+    fn extract_with_context(&self, cx: &Context, extractor: &dyn Extractor) -> Context {
+        Self::extract_single(extractor)
+            .or_else(|| Self::extract_multi(extractor))
+            .map_or_else(|| cx.clone(), |sc| cx.with_remote_span_context(sc))
+    }
+
+    fn fields(&self) -> FieldIter<'_> {
+        FieldIter::new(&self.fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::{body::Body, http::Request};
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::TracerProvider;
+    use tokio::net::TcpListener;
+    use tracing::Span;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::{builder::app::AppBuilder, http_client::HttpClientConfig};
+
+    /// Current trace ID, as seen by a handler.
+    fn current_trace_id() -> String {
+        Span::current()
+            .context()
+            .span()
+            .span_context()
+            .trace_id()
+            .to_string()
+    }
+
+    /// Both B3 encodings are extracted, 64-bit trace IDs are accepted.
+    #[test]
+    fn b3_extract() {
+        let prop = B3Propagator::new(true);
+        let extract = |headers: &[(&str, &str)]| {
+            let map: HashMap<String, String> = headers
+                .iter()
+                .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+                .collect();
+            let cx = prop.extract(&map);
+            let sc = cx.span().span_context().clone();
+            sc.is_valid()
+                .then(|| (sc.trace_id().to_string(), sc.is_sampled()))
+        };
+        assert_eq!(
+            extract(&[("b3", "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-0")]),
+            Some(("80f198ee56343ba864fe8b2a57d3eff7".into(), false))
+        );
+        assert_eq!(
+            extract(&[
+                ("x-b3-traceid", "a3ce929d0e0e4736"),
+                ("x-b3-spanid", "00f067aa0ba902b7"),
+                ("x-b3-sampled", "1"),
+            ]),
+            Some(("0000000000000000a3ce929d0e0e4736".into(), true))
+        );
+        assert_eq!(extract(&[("b3", "0")]), None);
+        assert_eq!(extract(&[("b3", "xyz-00f067aa0ba902b7-1")]), None);
+    }
+
+    /// Outer service calls inner one, trace ID and baggage are propagated using each format.
+    #[tokio::test]
+    async fn propagation_roundtrip() {
+        let provider = TracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let mut inner = AppBuilder::new();
+        inner.with_fallback(|| async {
+            let tenant = crate::baggage::get("tenant").unwrap_or_default();
+            format!("{} {tenant}", current_trace_id())
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/inner", listener.local_addr().unwrap());
+        let inner = inner.build().unwrap();
+        tokio::spawn(async move { axum::serve(listener, inner).await });
+
+        let client = HttpClientConfig::default().to_client(None).await.unwrap();
+        let mut outer = AppBuilder::new();
+        outer.with_fallback(move || async move {
+            let outer_id = current_trace_id();
+            let inner = client.get(&url).send().await.unwrap().text().await.unwrap();
+            format!("{outer_id} {inner}")
+        });
+        let outer = outer.build().unwrap();
+
+        let no_baggage = BaggageConfig::default().with_enabled(false);
+        for (propagation, baggage, same, tenant) in [
+            (
+                TracePropagation::W3c,
+                BaggageConfig::default(),
+                true,
+                "acme",
+            ),
+            (TracePropagation::B3, BaggageConfig::default(), true, "acme"),
+            (
+                TracePropagation::B3Multi,
+                BaggageConfig::default(),
+                true,
+                "acme",
+            ),
+            (
+                TracePropagation::None,
+                BaggageConfig::default(),
+                false,
+                "acme",
+            ),
+            (TracePropagation::W3c, no_baggage, true, ""),
+        ] {
+            propagation.install(&baggage);
+            let resp = tower::ServiceExt::oneshot(
+                outer.clone(),
+                Request::get("/outer")
+                    .header("baggage", "tenant=acme")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+            let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body = String::from_utf8(body.to_vec()).unwrap();
+            let parts: Vec<_> = body.split(' ').collect();
+            assert_ne!(parts[0], TraceId::INVALID.to_string());
+            assert_eq!(parts[0] == parts[1], same, "{propagation:?}: {body}");
+            assert_eq!(parts[2], tenant, "{propagation:?}: {body}");
+        }
+        TracePropagation::default().install(&BaggageConfig::default());
+    }
+}
@@ -0,0 +1,658 @@
+//! Runtime control of trace sampling ratio.
+
+use std::{
+    borrow::Borrow,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use axum::{
+    error_handling::HandleErrorLayer,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing, Extension, Json, Router,
+};
+use once_cell::sync::Lazy;
+use opentelemetry::{
+    trace::{Link, SamplingResult, SpanKind, TraceId},
+    Context, KeyValue,
+};
+use opentelemetry_sdk::trace::{Sampler, ShouldSample};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tower::ServiceBuilder;
+use tracing::{debug, debug_span, info, warn};
+
+use crate::{
+    auth::{AuthExtractor, AuthLayer, AuthProvider, UserId},
+    builder::app::error_handler,
+    errors::{codes, ErrorCode},
+};
+
+/// Sampling control of globally installed tracing pipeline.
+static CONTROL: Lazy<RwLock<Option<Arc<SamplingControl>>>> = Lazy::new(Default::default);
+
+/// Replace globally registered sampling control.
+pub(crate) fn register(control: Arc<SamplingControl>) {
+    *CONTROL.write() = Some(control);
+}
+
+/// Get effective sampling ratio of globally installed tracing pipeline, if it is adjustable.
+pub(crate) fn current_ratio() -> Option<f64> {
+    CONTROL.read().as_ref().map(|ctl| ctl.ratio())
+}
+
+/// Error type returned by sampling control endpoint.
+#[derive(Clone, Debug, Error, PartialEq)]
+#[non_exhaustive]
+pub enum SamplingControlError {
+    /// Sampling ratio must be between 0 and 1.
+    #[error("Sampling ratio must be between 0 and 1, got {0}")]
+    InvalidRatio(f64),
+    /// Tracing pipeline with adjustable sampling is not running.
+    #[error("Tracing pipeline with adjustable sampling is not running")]
+    NotRunning,
+}
+
+impl SamplingControlError {
+    /// Stable error code.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::InvalidRatio(_) => codes::SAMPLING_INVALID_RATIO,
+            Self::NotRunning => codes::SAMPLING_NOT_RUNNING,
+        }
+    }
+}
+
+impl IntoResponse for SamplingControlError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::InvalidRatio(_) => StatusCode::BAD_REQUEST,
+            Self::NotRunning => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        let code = self.code();
+        let problem = code
+            .problem(status)
+            .with_type("tag:uxum.github.io,2024:sampling")
+            .with_title(self.to_string());
+        (code, problem).into_response()
+    }
+}
+
+/// Runtime sampling control endpoint configuration.
+///
+/// `GET <path>` returns current sampling state. `PUT <path>` overrides sampling ratio, until TTL
+/// expires:
+///
+/// ```json
+/// {"ratio": 0.5, "ttl": "10m"}
+/// ```
+///
+/// Omitting `ratio` reverts to configured or adaptive ratio. Both methods require `telemetry`
+/// permission.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct SamplingControlConfig {
+    /// URL path of sampling control endpoint.
+    #[serde(default = "SamplingControlConfig::default_path")]
+    path: String,
+    /// Time after which changed ratio is reverted, if not specified in request.
+    #[serde(
+        default = "SamplingControlConfig::default_ttl",
+        with = "humantime_serde"
+    )]
+    default_ttl: Duration,
+    /// Maximum time after which changed ratio is reverted.
+    #[serde(
+        default = "SamplingControlConfig::default_max_ttl",
+        with = "humantime_serde"
+    )]
+    max_ttl: Duration,
+    /// Automatic adjustment of sampling ratio to keep exported span rate within budget.
+    ///
+    /// Disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    adaptive: Option<AdaptiveSamplingConfig>,
+}
+
+impl Default for SamplingControlConfig {
+    fn default() -> Self {
+        Self {
+            path: Self::default_path(),
+            default_ttl: Self::default_ttl(),
+            max_ttl: Self::default_max_ttl(),
+            adaptive: None,
+        }
+    }
+}
+
+impl SamplingControlConfig {
+    /// Default value for [`Self::path`].
+    #[must_use]
+    #[inline]
+    fn default_path() -> String {
+        "/telemetry/sampling".into()
+    }
+
+    /// Default value for [`Self::default_ttl`].
+    #[must_use]
+    #[inline]
+    fn default_ttl() -> Duration {
+        Duration::from_secs(900)
+    }
+
+    /// Default value for [`Self::max_ttl`].
+    #[must_use]
+    #[inline]
+    fn default_max_ttl() -> Duration {
+        Duration::from_secs(3600)
+    }
+
+    /// Set URL path of sampling control endpoint.
+    #[must_use]
+    pub fn with_path(mut self, path: impl ToString) -> Self {
+        self.path = path.to_string();
+        self
+    }
+
+    /// Set time after which changed ratio is reverted, if not specified in request.
+    #[must_use]
+    pub fn with_default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = ttl;
+        self
+    }
+
+    /// Set maximum time after which changed ratio is reverted.
+    #[must_use]
+    pub fn with_max_ttl(mut self, ttl: Duration) -> Self {
+        self.max_ttl = ttl;
+        self
+    }
+
+    /// Enable automatic adjustment of sampling ratio.
+    #[must_use]
+    pub fn with_adaptive(mut self, adaptive: AdaptiveSamplingConfig) -> Self {
+        self.adaptive = Some(adaptive);
+        self
+    }
+
+    /// Create sampling control, starting at configured ratio.
+    pub(crate) fn build_control(&self, configured: f64) -> Arc<SamplingControl> {
+        let control = Arc::new(SamplingControl::new(configured, self.adaptive.clone()));
+        if let Some(adaptive) = &self.adaptive {
+            tokio::spawn(control.clone().adaptive_task(adaptive.interval));
+        }
+        control
+    }
+
+    /// Build Axum router containing sampling control endpoint.
+    pub(crate) fn build_router<AuthProv, AuthExt>(
+        &self,
+        auth_provider: AuthProv,
+        auth_extractor: AuthExt,
+    ) -> Router
+    where
+        AuthProv: AuthProvider + Sync + 'static,
+        AuthExt: AuthExtractor + Sync + 'static,
+        AuthExt::User: Borrow<AuthProv::User>,
+        AuthExt::AuthTokens: Borrow<AuthProv::AuthTokens>,
+    {
+        let _span = debug_span!("build_sampling_control").entered();
+        Router::new()
+            .route(&self.path, routing::get(get_sampling).put(put_sampling))
+            .layer(
+                ServiceBuilder::new()
+                    .layer(HandleErrorLayer::new(error_handler))
+                    .layer(AuthLayer::new(
+                        &["telemetry"],
+                        auth_provider,
+                        auth_extractor,
+                    )),
+            )
+            .with_state(self.clone())
+    }
+}
+
+/// Adaptive sampling configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct AdaptiveSamplingConfig {
+    /// Target number of sampled spans per second.
+    target_spans_per_sec: f64,
+    /// Lower bound of sampling ratio.
+    #[serde(default = "AdaptiveSamplingConfig::default_min_ratio")]
+    min_ratio: f64,
+    /// Upper bound of sampling ratio.
+    #[serde(default = "AdaptiveSamplingConfig::default_max_ratio")]
+    max_ratio: f64,
+    /// Interval between ratio adjustments.
+    #[serde(
+        default = "AdaptiveSamplingConfig::default_interval",
+        with = "humantime_serde"
+    )]
+    interval: Duration,
+}
+
+impl AdaptiveSamplingConfig {
+    /// Default value for [`Self::min_ratio`].
+    #[must_use]
+    #[inline]
+    fn default_min_ratio() -> f64 {
+        0.001
+    }
+
+    /// Default value for [`Self::max_ratio`].
+    #[must_use]
+    #[inline]
+    fn default_max_ratio() -> f64 {
+        1.0
+    }
+
+    /// Default value for [`Self::interval`].
+    #[must_use]
+    #[inline]
+    fn default_interval() -> Duration {
+        Duration::from_secs(1)
+    }
+
+    /// Create new adaptive sampling configuration.
+    #[must_use]
+    pub fn new(target_spans_per_sec: f64) -> Self {
+        Self {
+            target_spans_per_sec,
+            min_ratio: Self::default_min_ratio(),
+            max_ratio: Self::default_max_ratio(),
+            interval: Self::default_interval(),
+        }
+    }
+
+    /// Set bounds of sampling ratio.
+    #[must_use]
+    pub fn with_bounds(mut self, min_ratio: f64, max_ratio: f64) -> Self {
+        self.min_ratio = min_ratio;
+        self.max_ratio = max_ratio;
+        self
+    }
+
+    /// Set interval between ratio adjustments.
+    #[must_use]
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Clamp ratio to configured bounds.
+    fn clamp(&self, ratio: f64) -> f64 {
+        ratio
+            .max(self.min_ratio)
+            .min(self.max_ratio)
+            .clamp(0.0, 1.0)
+    }
+}
+
+/// Body of ratio change request.
+#[derive(Clone, Debug, Deserialize)]
+struct ChangeRequest {
+    /// New ratio, or [`None`] to revert.
+    #[serde(default)]
+    ratio: Option<f64>,
+    /// Time after which changed ratio is reverted.
+    #[serde(default, with = "humantime_serde")]
+    ttl: Option<Duration>,
+}
+
+/// Current sampling state.
+#[derive(Clone, Debug, Serialize)]
+struct SamplingStatus {
+    /// Effective sampling ratio.
+    ratio: f64,
+    /// Configured sampling ratio.
+    configured_ratio: f64,
+    /// Sampling ratio chosen by adaptive controller, if enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    adaptive_ratio: Option<f64>,
+    /// Manually set sampling ratio, if any.
+    #[serde(rename = "override", skip_serializing_if = "Option::is_none")]
+    override_ratio: Option<f64>,
+    /// Time when manually set ratio will be reverted.
+    #[serde(with = "humantime_serde", skip_serializing_if = "Option::is_none")]
+    expires: Option<SystemTime>,
+}
+
+/// Get current sampling state.
+async fn get_sampling() -> Result<Json<SamplingStatus>, SamplingControlError> {
+    let control = CONTROL.read().clone();
+    let control = control.ok_or(SamplingControlError::NotRunning)?;
+    Ok(Json(control.status()))
+}
+
+/// Override sampling ratio.
+async fn put_sampling(
+    State(config): State<SamplingControlConfig>,
+    user: Option<Extension<UserId>>,
+    Json(req): Json<ChangeRequest>,
+) -> Result<Json<SamplingStatus>, SamplingControlError> {
+    let control = CONTROL.read().clone();
+    let control = control.ok_or(SamplingControlError::NotRunning)?;
+    let ttl = req.ttl.unwrap_or(config.default_ttl).min(config.max_ttl);
+    warn!(
+        user = user.as_ref().map(|user| user.as_str()),
+        ratio = req.ratio,
+        ?ttl,
+        "trace sampling change requested"
+    );
+    control.set_override(req.ratio, ttl)?;
+    Ok(Json(control.status()))
+}
+
+/// Manual override state, guarded by a lock.
+#[derive(Debug, Default)]
+struct OverrideState {
+    /// Incremented on every change, used to cancel outdated reverts.
+    generation: u64,
+    /// Manually set ratio.
+    ratio: Option<f64>,
+    /// Time when manually set ratio will be reverted.
+    expires: Option<SystemTime>,
+}
+
+/// Runtime control of trace sampling ratio.
+#[derive(Debug)]
+pub(crate) struct SamplingControl {
+    /// Configured ratio.
+    configured: f64,
+    /// Adaptive controller configuration.
+    adaptive: Option<AdaptiveSamplingConfig>,
+    /// Effective ratio, as [`f64`] bits.
+    ratio: AtomicU64,
+    /// Ratio chosen by adaptive controller, as [`f64`] bits.
+    adaptive_ratio: AtomicU64,
+    /// Number of spans started since last adjustment.
+    started: AtomicU64,
+    /// Manual override state.
+    state: Mutex<OverrideState>,
+}
+
+impl SamplingControl {
+    /// Create new sampling control.
+    pub(crate) fn new(configured: f64, adaptive: Option<AdaptiveSamplingConfig>) -> Self {
+        let initial = match &adaptive {
+            Some(adaptive) => adaptive.clamp(configured),
+            None => configured,
+        };
+        Self {
+            configured,
+            adaptive,
+            ratio: AtomicU64::new(initial.to_bits()),
+            adaptive_ratio: AtomicU64::new(initial.to_bits()),
+            started: AtomicU64::new(0),
+            state: Mutex::default(),
+        }
+    }
+
+    /// Effective sampling ratio.
+    pub(crate) fn ratio(&self) -> f64 {
+        f64::from_bits(self.ratio.load(Ordering::Relaxed))
+    }
+
+    /// Ratio used when there is no manual override.
+    fn base_ratio(&self) -> f64 {
+        match self.adaptive {
+            Some(_) => f64::from_bits(self.adaptive_ratio.load(Ordering::Relaxed)),
+            None => self.configured,
+        }
+    }
+
+    /// Current sampling state.
+    fn status(&self) -> SamplingStatus {
+        let state = self.state.lock();
+        SamplingStatus {
+            ratio: self.ratio(),
+            configured_ratio: self.configured,
+            adaptive_ratio: self
+                .adaptive
+                .as_ref()
+                .map(|_| f64::from_bits(self.adaptive_ratio.load(Ordering::Relaxed))),
+            override_ratio: state.ratio,
+            expires: state.expires,
+        }
+    }
+
+    /// Manually set sampling ratio, reverting after `ttl`.
+    ///
+    /// Passing [`None`] reverts immediately.
+    fn set_override(
+        self: &Arc<Self>,
+        ratio: Option<f64>,
+        ttl: Duration,
+    ) -> Result<(), SamplingControlError> {
+        if let Some(ratio) = ratio {
+            if !(0.0..=1.0).contains(&ratio) {
+                return Err(SamplingControlError::InvalidRatio(ratio));
+            }
+        }
+        let mut state = self.state.lock();
+        state.generation += 1;
+        state.ratio = ratio;
+        let Some(ratio) = ratio else {
+            state.expires = None;
+            self.ratio
+                .store(self.base_ratio().to_bits(), Ordering::Relaxed);
+            info!("trace sampling ratio reverted");
+            return Ok(());
+        };
+        state.expires = Some(SystemTime::now() + ttl);
+        self.ratio.store(ratio.to_bits(), Ordering::Relaxed);
+        info!(ratio, ?ttl, "trace sampling ratio changed");
+        let generation = state.generation;
+        let control = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(ttl).await;
+            control.revert(generation);
+        });
+        Ok(())
+    }
+
+    /// Revert manual override, unless ratio was changed again since `generation`.
+    fn revert(&self, generation: u64) {
+        let mut state = self.state.lock();
+        if state.generation == generation {
+            state.ratio = None;
+            state.expires = None;
+            self.ratio
+                .store(self.base_ratio().to_bits(), Ordering::Relaxed);
+            info!(
+                ratio = self.ratio(),
+                "trace sampling ratio reverted after TTL"
+            );
+        }
+    }
+
+    /// Adjust adaptive ratio based on number of spans started during `elapsed`.
+    fn adjust(&self, elapsed: Duration) {
+        let Some(adaptive) = &self.adaptive else {
+            return;
+        };
+        let started = self.started.swap(0, Ordering::Relaxed);
+        let secs = elapsed.as_secs_f64();
+        if secs <= 0.0 {
+            return;
+        }
+        let rate = started as f64 / secs;
+        let current = f64::from_bits(self.adaptive_ratio.load(Ordering::Relaxed));
+        let desired = match rate > 0.0 {
+            true => adaptive.clamp(adaptive.target_spans_per_sec / rate),
+            false => adaptive.clamp(1.0),
+        };
+        // Move halfway towards desired ratio, to smooth out short bursts.
+        let ratio = adaptive.clamp(current + (desired - current) / 2.0);
+        self.adaptive_ratio
+            .store(ratio.to_bits(), Ordering::Relaxed);
+        let state = self.state.lock();
+        if state.ratio.is_none() {
+            self.ratio.store(ratio.to_bits(), Ordering::Relaxed);
+        }
+        debug!(rate, ratio, "adaptive sampling ratio adjusted");
+    }
+
+    /// Periodically adjust adaptive ratio.
+    async fn adaptive_task(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+        let mut last = Instant::now();
+        loop {
+            ticker.tick().await;
+            let now = Instant::now();
+            self.adjust(now - last);
+            last = now;
+        }
+    }
+}
+
+/// Trace ID ratio sampler with runtime-adjustable ratio.
+#[derive(Clone, Debug)]
+pub(crate) struct DynamicSampler(Arc<SamplingControl>);
+
+impl DynamicSampler {
+    /// Create new sampler using provided control.
+    pub(crate) fn new(control: Arc<SamplingControl>) -> Self {
+        Self(control)
+    }
+}
+
+impl ShouldSample for DynamicSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        self.0.started.fetch_add(1, Ordering::Relaxed);
+        Sampler::TraceIdRatioBased(self.0.ratio()).should_sample(
+            parent_context,
+            trace_id,
+            name,
+            span_kind,
+            attributes,
+            links,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::SamplingDecision;
+    use rand::Rng;
+
+    use super::*;
+
+    /// Fraction of random traces sampled by the sampler.
+    fn sampled_fraction(sampler: &DynamicSampler) -> f64 {
+        let mut rng = rand::thread_rng();
+        let sampled = (0..10_000)
+            .filter(|_| {
+                let res = sampler.should_sample(
+                    None,
+                    TraceId::from(rng.gen::<u128>()),
+                    "test",
+                    &SpanKind::Server,
+                    &[],
+                    &[],
+                );
+                res.decision == SamplingDecision::RecordAndSample
+            })
+            .count();
+        sampled as f64 / 10_000.0
+    }
+
+    /// Manually set ratio is used by sampler, invalid ratios are rejected.
+    #[tokio::test]
+    async fn manual_adjustment() {
+        let control = Arc::new(SamplingControl::new(0.1, None));
+        let sampler = DynamicSampler::new(control.clone());
+        assert!((sampled_fraction(&sampler) - 0.1).abs() < 0.03);
+
+        control
+            .set_override(Some(1.0), Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(sampled_fraction(&sampler), 1.0);
+        let status = control.status();
+        assert_eq!(status.override_ratio, Some(1.0));
+        assert!(status.expires.is_some());
+
+        assert_eq!(
+            control.set_override(Some(1.5), Duration::from_secs(60)),
+            Err(SamplingControlError::InvalidRatio(1.5))
+        );
+        assert_eq!(control.ratio(), 1.0);
+        control.set_override(None, Duration::ZERO).unwrap();
+        assert_eq!(control.ratio(), 0.1);
+        assert!(control.status().expires.is_none());
+    }
+
+    /// Changed ratio is reverted after TTL, unless changed again.
+    #[tokio::test]
+    async fn ttl_revert() {
+        let control = Arc::new(SamplingControl::new(0.1, None));
+        control
+            .set_override(Some(0.5), Duration::from_millis(50))
+            .unwrap();
+        assert_eq!(control.ratio(), 0.5);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(control.ratio(), 0.1);
+        assert!(control.status().override_ratio.is_none());
+
+        control
+            .set_override(Some(0.5), Duration::from_millis(50))
+            .unwrap();
+        control
+            .set_override(Some(0.7), Duration::from_secs(60))
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(control.ratio(), 0.7);
+    }
+
+    /// Adaptive controller converges to span budget, staying within bounds.
+    #[tokio::test]
+    async fn adaptive_convergence() {
+        let adaptive = AdaptiveSamplingConfig::new(100.0).with_bounds(0.005, 0.5);
+        let control = Arc::new(SamplingControl::new(0.5, Some(adaptive)));
+        let sampler = DynamicSampler::new(control.clone());
+        let step = |spans| {
+            for _ in 0..spans {
+                sampler.should_sample(None, TraceId::from(1), "test", &SpanKind::Server, &[], &[]);
+            }
+            control.adjust(Duration::from_secs(1));
+        };
+        // 10k spans per second, budget of 100 spans per second.
+        for _ in 0..20 {
+            step(10_000);
+        }
+        assert!((control.ratio() - 0.01).abs() < 0.001);
+        // Budget is unreachable, ratio stays at lower bound.
+        for _ in 0..20 {
+            step(100_000);
+        }
+        assert!((control.ratio() - 0.005).abs() < 1e-4);
+        // Manual override takes precedence over adaptive ratio.
+        control
+            .set_override(Some(0.2), Duration::from_secs(60))
+            .unwrap();
+        // Load drops, adaptive ratio is capped at upper bound once override is reverted.
+        for _ in 0..20 {
+            step(10);
+        }
+        assert_eq!(control.ratio(), 0.2);
+        control.set_override(None, Duration::ZERO).unwrap();
+        assert!((control.ratio() - 0.5).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,1002 @@
+//! Business rule validation of deserialized request input.
+//!
+//! Values extracted with [`Validated`] are validated after deserialization (and normalization,
+//! if combined with [`Normalized`]), before the handler sees them. Rules are declared per field
+//! with `#[validate(...)]` attributes of [`derive@Validate`], and are reflected in OpenAPI
+//! specification where representable.
+//!
+//! Violations are returned as `422 Unprocessable Entity` problem details, listing all failed
+//! rules in `errors` field. Messages of individual violations can be translated using message
+//! catalog (see [`crate::LocalizationConfig`]), with `validation.<code>` catalog key and
+//! `message` field. Rule parameters can be referenced in translations as `{name}`.
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    fmt,
+    hash::{BuildHasher, Hash},
+    ops::{Deref, DerefMut},
+};
+
+use axum::{
+    async_trait,
+    extract::{FromRequest, Request},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Form,
+};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use regex::Regex;
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{InstanceType, Schema, SchemaObject, SingleOrVec},
+};
+use serde::Serialize;
+
+pub use uxum_macros::Validate;
+
+use crate::{errors::codes, normalize::Normalized, response::Json};
+
+/// Problem details type URI of validation error responses.
+const VALIDATION_PROBLEM_TYPE: &str = "tag:uxum.github.io,2024:validation";
+
+/// Prefix of message catalog keys used to translate violation messages.
+pub(crate) const VALIDATION_MESSAGE_KEY_PREFIX: &str = "validation.";
+
+/// Single violated validation rule.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct ValidationError {
+    /// Machine-readable violation code.
+    pub code: Cow<'static, str>,
+    /// Human-readable message, in built-in language.
+    pub message: String,
+    /// Rule parameters, such as bounds.
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub params: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ValidationError {
+    /// Create new validation error.
+    #[must_use]
+    pub fn new(code: impl Into<Cow<'static, str>>, message: impl ToString) -> Self {
+        Self {
+            code: code.into(),
+            message: message.to_string(),
+            params: serde_json::Map::new(),
+        }
+    }
+
+    /// Add rule parameter.
+    #[must_use]
+    pub fn with_param(mut self, name: impl ToString, value: impl Into<serde_json::Value>) -> Self {
+        self.params.insert(name.to_string(), value.into());
+        self
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Validation error, attached to a position in validated value.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct FieldError {
+    /// JSON pointer to offending value.
+    pub pointer: String,
+    /// Violated rule.
+    #[serde(flatten)]
+    pub error: ValidationError,
+}
+
+/// Collection of validation errors.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct ValidationErrors(Vec<FieldError>);
+
+impl ValidationErrors {
+    /// Create empty error collection.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check if no errors were recorded.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Get recorded errors.
+    #[must_use]
+    pub fn errors(&self) -> &[FieldError] {
+        &self.0
+    }
+
+    /// Record error at JSON pointer.
+    pub fn add(&mut self, pointer: impl ToString, error: ValidationError) {
+        self.0.push(FieldError {
+            pointer: pointer.to_string(),
+            error,
+        });
+    }
+
+    /// Record error for a named field, relative to value being validated.
+    ///
+    /// Useful in struct-level custom validators.
+    pub fn add_field(&mut self, field: &str, error: ValidationError) {
+        self.add(validate_pointer("", field), error);
+    }
+
+    /// Append errors, prefixing their pointers.
+    ///
+    /// Used in [`derive@Validate`] macro expansion.
+    #[doc(hidden)]
+    pub fn extend_at(&mut self, prefix: &str, other: Self) {
+        self.0.extend(other.0.into_iter().map(|mut err| {
+            err.pointer.insert_str(0, prefix);
+            err
+        }));
+    }
+
+    /// Turn collection into a [`Result`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if any errors were recorded.
+    pub fn into_result(self) -> Result<(), Self> {
+        match self.is_empty() {
+            true => Ok(()),
+            false => Err(self),
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, err) in self.0.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}: {}", err.pointer, err.error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+impl IntoResponse for ValidationErrors {
+    fn into_response(self) -> Response {
+        let code = codes::VALIDATION_FAILED;
+        let problem = code
+            .problem(StatusCode::UNPROCESSABLE_ENTITY)
+            .with_type(VALIDATION_PROBLEM_TYPE)
+            .with_title("Request validation failed")
+            .with_value("errors", serde_json::to_value(self).unwrap_or_default());
+        (code, problem).into_response()
+    }
+}
+
+/// Validation rule, as reflected in OpenAPI specification.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ValidationRule {
+    /// Length of a string, or number of items in a collection.
+    Length {
+        /// Minimum length.
+        min: Option<u64>,
+        /// Maximum length.
+        max: Option<u64>,
+    },
+    /// Numeric range.
+    Range {
+        /// Minimum value, inclusive.
+        min: Option<f64>,
+        /// Maximum value, inclusive.
+        max: Option<f64>,
+    },
+    /// Regular expression which strings must match.
+    Pattern(&'static str),
+    /// Email address format.
+    Email,
+}
+
+impl ValidationRule {
+    /// Set corresponding keywords in JSON schema.
+    fn annotate(&self, schema: &mut SchemaObject) {
+        match self {
+            Self::Length { min, max } => {
+                let min = min.map(|val| u32::try_from(val).unwrap_or(u32::MAX));
+                let max = max.map(|val| u32::try_from(val).unwrap_or(u32::MAX));
+                if schema.has_type(InstanceType::Array) {
+                    let array = schema.array();
+                    array.min_items = min.or(array.min_items);
+                    array.max_items = max.or(array.max_items);
+                } else if schema.has_type(InstanceType::Object) {
+                    let object = schema.object();
+                    object.min_properties = min.or(object.min_properties);
+                    object.max_properties = max.or(object.max_properties);
+                } else {
+                    let string = schema.string();
+                    string.min_length = min.or(string.min_length);
+                    string.max_length = max.or(string.max_length);
+                }
+            }
+            Self::Range { min, max } => {
+                let number = schema.number();
+                number.minimum = min.or(number.minimum);
+                number.maximum = max.or(number.maximum);
+            }
+            Self::Pattern(pattern) => schema.string().pattern = Some((*pattern).into()),
+            Self::Email => schema.format = Some("email".into()),
+        }
+    }
+}
+
+/// Validation rules applied to a value in OpenAPI specification, keyed by field path.
+///
+/// Nested fields are separated with `.`, collection items are denoted with `[]`, and map values
+/// with `*`.
+pub type ValidationSpec = BTreeMap<String, Vec<ValidationRule>>;
+
+/// Values which can be validated after deserialization.
+///
+/// Usually implemented using [`derive@Validate`].
+pub trait Validate {
+    /// Validate value, recording errors with pointers prefixed with `pointer`.
+    fn validate_at(&self, pointer: &str, errors: &mut ValidationErrors);
+
+    /// Validate value.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` with all violations found.
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        self.validate_at("", &mut errors);
+        errors.into_result()
+    }
+
+    /// Describe validation rules applied to value and its nested fields.
+    fn describe(_path: &str, _spec: &mut ValidationSpec)
+    where
+        Self: Sized,
+    {
+    }
+}
+
+impl<T: Validate> Validate for Option<T> {
+    fn validate_at(&self, pointer: &str, errors: &mut ValidationErrors) {
+        if let Some(inner) = self {
+            inner.validate_at(pointer, errors);
+        }
+    }
+
+    fn describe(path: &str, spec: &mut ValidationSpec) {
+        T::describe(path, spec);
+    }
+}
+
+impl<T: Validate> Validate for Box<T> {
+    fn validate_at(&self, pointer: &str, errors: &mut ValidationErrors) {
+        self.as_ref().validate_at(pointer, errors);
+    }
+
+    fn describe(path: &str, spec: &mut ValidationSpec) {
+        T::describe(path, spec);
+    }
+}
+
+/// Implement [`Validate`] for sequence types.
+macro_rules! impl_validate_seq {
+    ($($ty:ident),+) => {
+        $(
+            impl<T: Validate> Validate for $ty<T> {
+                fn validate_at(&self, pointer: &str, errors: &mut ValidationErrors) {
+                    for (idx, item) in self.iter().enumerate() {
+                        item.validate_at(&format!("{pointer}/{idx}"), errors);
+                    }
+                }
+
+                fn describe(path: &str, spec: &mut ValidationSpec) {
+                    T::describe(&format!("{path}[]"), spec);
+                }
+            }
+        )+
+    };
+}
+
+impl_validate_seq!(Vec, VecDeque);
+
+impl<K: Ord + fmt::Display, V: Validate> Validate for BTreeMap<K, V> {
+    fn validate_at(&self, pointer: &str, errors: &mut ValidationErrors) {
+        for (key, val) in self {
+            val.validate_at(&validate_pointer(pointer, &key.to_string()), errors);
+        }
+    }
+
+    fn describe(path: &str, spec: &mut ValidationSpec) {
+        V::describe(&crate::normalize_field_path(path, "*"), spec);
+    }
+}
+
+impl<K, V, S> Validate for HashMap<K, V, S>
+where
+    K: Eq + Hash + fmt::Display,
+    V: Validate,
+    S: BuildHasher,
+{
+    fn validate_at(&self, pointer: &str, errors: &mut ValidationErrors) {
+        for (key, val) in self {
+            val.validate_at(&validate_pointer(pointer, &key.to_string()), errors);
+        }
+    }
+
+    fn describe(path: &str, spec: &mut ValidationSpec) {
+        V::describe(&crate::normalize_field_path(path, "*"), spec);
+    }
+}
+
+/// Append field name to JSON pointer, escaping it as per RFC 6901.
+///
+/// Used in [`derive@Validate`] macro expansion.
+#[doc(hidden)]
+#[must_use]
+pub fn validate_pointer(prefix: &str, field: &str) -> String {
+    format!("{prefix}/{}", field.replace('~', "~0").replace('/', "~1"))
+}
+
+/// Values with length, used in `length` validation rule.
+pub trait HasLength {
+    /// Get length of a value, or [`None`] if value is missing.
+    fn validation_length(&self) -> Option<u64>;
+}
+
+impl HasLength for String {
+    fn validation_length(&self) -> Option<u64> {
+        Some(self.chars().count() as u64)
+    }
+}
+
+impl<T: HasLength> HasLength for Option<T> {
+    fn validation_length(&self) -> Option<u64> {
+        self.as_ref().and_then(HasLength::validation_length)
+    }
+}
+
+impl<T: HasLength> HasLength for Box<T> {
+    fn validation_length(&self) -> Option<u64> {
+        self.as_ref().validation_length()
+    }
+}
+
+/// Implement [`HasLength`] for collection types.
+macro_rules! impl_has_length {
+    ($($ty:ident < $($arg:ident),+ >),+) => {
+        $(
+            impl<$($arg),+> HasLength for $ty<$($arg),+> {
+                fn validation_length(&self) -> Option<u64> {
+                    Some(self.len() as u64)
+                }
+            }
+        )+
+    };
+}
+
+impl_has_length!(
+    Vec<T>,
+    VecDeque<T>,
+    BTreeSet<T>,
+    HashSet<T, S>,
+    BTreeMap<K, V>,
+    HashMap<K, V, S>
+);
+
+/// Numeric values, used in `range` validation rule.
+pub trait AsNumber {
+    /// Get value as a float, or [`None`] if value is missing.
+    fn validation_number(&self) -> Option<f64>;
+}
+
+impl<T: AsNumber> AsNumber for Option<T> {
+    fn validation_number(&self) -> Option<f64> {
+        self.as_ref().and_then(AsNumber::validation_number)
+    }
+}
+
+/// Implement [`AsNumber`] for primitive numeric types.
+macro_rules! impl_as_number {
+    ($($ty:ty),+) => {
+        $(
+            impl AsNumber for $ty {
+                #[allow(clippy::cast_lossless, clippy::cast_precision_loss)]
+                fn validation_number(&self) -> Option<f64> {
+                    Some(*self as f64)
+                }
+            }
+        )+
+    };
+}
+
+impl_as_number!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+
+/// String values, used in `regex` and `email` validation rules.
+pub trait AsText {
+    /// Get value as a string slice, or [`None`] if value is missing.
+    fn validation_text(&self) -> Option<&str>;
+}
+
+impl AsText for String {
+    fn validation_text(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl<T: AsText> AsText for Option<T> {
+    fn validation_text(&self) -> Option<&str> {
+        self.as_ref().and_then(AsText::validation_text)
+    }
+}
+
+/// Check `length` rule.
+///
+/// Used in [`derive@Validate`] macro expansion.
+#[doc(hidden)]
+pub fn check_length<T: HasLength + ?Sized>(
+    value: &T,
+    min: Option<u64>,
+    max: Option<u64>,
+) -> Result<(), ValidationError> {
+    let Some(len) = value.validation_length() else {
+        return Ok(());
+    };
+    match (min, max) {
+        (Some(min), _) if len < min => Err(ValidationError::new(
+            "length.min",
+            format!("length must be at least {min}"),
+        )
+        .with_param("min", min)),
+        (_, Some(max)) if len > max => Err(ValidationError::new(
+            "length.max",
+            format!("length must be at most {max}"),
+        )
+        .with_param("max", max)),
+        _ => Ok(()),
+    }
+}
+
+/// Check `range` rule.
+///
+/// Used in [`derive@Validate`] macro expansion.
+#[doc(hidden)]
+pub fn check_range<T: AsNumber + ?Sized>(
+    value: &T,
+    min: Option<f64>,
+    max: Option<f64>,
+) -> Result<(), ValidationError> {
+    let Some(num) = value.validation_number() else {
+        return Ok(());
+    };
+    match (min, max) {
+        (Some(min), _) if num < min => Err(ValidationError::new(
+            "range.min",
+            format!("value must be at least {min}"),
+        )
+        .with_param("min", min)),
+        (_, Some(max)) if num > max => Err(ValidationError::new(
+            "range.max",
+            format!("value must be at most {max}"),
+        )
+        .with_param("max", max)),
+        _ => Ok(()),
+    }
+}
+
+/// Compiled regular expressions, keyed by pattern.
+static PATTERNS: Lazy<RwLock<HashMap<&'static str, Regex>>> = Lazy::new(Default::default);
+
+/// Check `regex` rule.
+///
+/// Pattern syntax is checked by [`derive@Validate`] macro at compile time.
+///
+/// Used in [`derive@Validate`] macro expansion.
+#[doc(hidden)]
+pub fn check_pattern<T: AsText + ?Sized>(
+    value: &T,
+    pattern: &'static str,
+) -> Result<(), ValidationError> {
+    let Some(text) = value.validation_text() else {
+        return Ok(());
+    };
+    let cached = PATTERNS.read().get(pattern).map(|re| re.is_match(text));
+    let is_match = match cached {
+        Some(is_match) => is_match,
+        None => {
+            let re = Regex::new(pattern).unwrap_or_else(|err| panic!("invalid pattern: {err}"));
+            let is_match = re.is_match(text);
+            PATTERNS.write().insert(pattern, re);
+            is_match
+        }
+    };
+    match is_match {
+        true => Ok(()),
+        false => Err(ValidationError::new(
+            "pattern",
+            format!("value must match pattern {pattern}"),
+        )
+        .with_param("pattern", pattern)),
+    }
+}
+
+/// Check `email` rule.
+///
+/// Only basic structure of an address is checked.
+///
+/// Used in [`derive@Validate`] macro expansion.
+#[doc(hidden)]
+pub fn check_email<T: AsText + ?Sized>(value: &T) -> Result<(), ValidationError> {
+    let Some(text) = value.validation_text() else {
+        return Ok(());
+    };
+    let valid = text.len() <= 254
+        && !text.chars().any(char::is_whitespace)
+        && text.split_once('@').is_some_and(|(local, domain)| {
+            !local.is_empty()
+                && local.len() <= 64
+                && !domain.contains('@')
+                && domain.contains('.')
+                && domain
+                    .split('.')
+                    .all(|label| !label.is_empty() && !label.starts_with('-'))
+        });
+    match valid {
+        true => Ok(()),
+        false => Err(ValidationError::new(
+            "email",
+            "value must be a valid email address",
+        )),
+    }
+}
+
+/// Reflect validation rules of a type in its JSON schema.
+///
+/// Rules of referenced schemas are applied to their definitions in generator.
+///
+/// Used in [`crate::handler`] macro expansion.
+#[doc(hidden)]
+pub fn annotate_validate_schema<T: Validate>(gen: &mut SchemaGenerator, schema: &mut SchemaObject) {
+    let mut spec = ValidationSpec::new();
+    T::describe("", &mut spec);
+    for (path, rules) in spec {
+        let segments = parse_spec_path(&path);
+        for rule in rules {
+            annotate_path(gen, schema, &segments, &rule);
+        }
+    }
+}
+
+/// Single step in validation spec path.
+#[derive(Debug, PartialEq)]
+enum PathSegment<'a> {
+    /// Object property.
+    Field(&'a str),
+    /// Array items.
+    Items,
+    /// Map values.
+    Values,
+}
+
+/// Split validation spec path into segments.
+fn parse_spec_path(path: &str) -> Vec<PathSegment<'_>> {
+    let mut segments = Vec::new();
+    for part in path.split('.').filter(|part| !part.is_empty()) {
+        if part == "*" {
+            segments.push(PathSegment::Values);
+            continue;
+        }
+        let name = part.trim_end_matches("[]");
+        if !name.is_empty() {
+            segments.push(PathSegment::Field(name));
+        }
+        for _ in 0..(part.len() - name.len()) / 2 {
+            segments.push(PathSegment::Items);
+        }
+    }
+    segments
+}
+
+/// Get definition name of referenced schema.
+fn reference_name(schema: &SchemaObject) -> Option<String> {
+    let reference = schema.reference.as_deref()?;
+    reference.rsplit_once('/').map(|(_, name)| name.to_string())
+}
+
+/// Apply validation rule to a schema at path.
+fn annotate_path(
+    gen: &mut SchemaGenerator,
+    schema: &mut SchemaObject,
+    path: &[PathSegment<'_>],
+    rule: &ValidationRule,
+) {
+    if let Some(name) = reference_name(schema) {
+        let Some(Schema::Object(mut def)) = gen.definitions().get(&name).cloned() else {
+            return;
+        };
+        annotate_path(gen, &mut def, path, rule);
+        if let Some(slot) = gen.definitions_mut().get_mut(&name) {
+            *slot = Schema::Object(def);
+        }
+        return;
+    }
+    // Optional values may be wrapped in a single-item `allOf`.
+    if let Some(Schema::Object(inner)) = schema
+        .subschemas
+        .as_mut()
+        .and_then(|sub| sub.all_of.as_mut())
+        .filter(|all_of| all_of.len() == 1)
+        .and_then(|all_of| all_of.first_mut())
+    {
+        return annotate_path(gen, inner, path, rule);
+    }
+    let Some((segment, rest)) = path.split_first() else {
+        rule.annotate(schema);
+        return;
+    };
+    let next = match segment {
+        PathSegment::Field(name) => schema
+            .object
+            .as_mut()
+            .and_then(|obj| obj.properties.get_mut(*name)),
+        PathSegment::Items => match schema.array.as_mut().and_then(|arr| arr.items.as_mut()) {
+            Some(SingleOrVec::Single(items)) => Some(items.as_mut()),
+            _ => None,
+        },
+        PathSegment::Values => schema
+            .object
+            .as_mut()
+            .and_then(|obj| obj.additional_properties.as_deref_mut()),
+    };
+    if let Some(Schema::Object(next)) = next {
+        annotate_path(gen, next, rest, rule);
+    }
+}
+
+/// Handler-level marker disabling validation in [`Validated`] extractors.
+///
+/// Added as request extension for handlers with `no_validate` parameter of [`crate::handler`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SkipValidation;
+
+/// Extractors which can be wrapped in [`Validated`].
+pub trait ValidationTarget {
+    /// Extracted value.
+    type Value: Validate;
+
+    /// Get extracted value.
+    fn validation_target(&self) -> &Self::Value;
+}
+
+impl<T: Validate> ValidationTarget for Json<T> {
+    type Value = T;
+
+    fn validation_target(&self) -> &Self::Value {
+        &self.0
+    }
+}
+
+impl<T: Validate> ValidationTarget for Form<T> {
+    type Value = T;
+
+    fn validation_target(&self) -> &Self::Value {
+        &self.0
+    }
+}
+
+impl<E: ValidationTarget> ValidationTarget for Normalized<E> {
+    type Value = E::Value;
+
+    fn validation_target(&self) -> &Self::Value {
+        self.0.validation_target()
+    }
+}
+
+/// Rejection returned by [`Validated`] extractor.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ValidationRejection<R> {
+    /// Inner extractor failed.
+    Extract(R),
+    /// Extracted value violates validation rules.
+    Invalid(ValidationErrors),
+}
+
+impl<R: IntoResponse> IntoResponse for ValidationRejection<R> {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Extract(rej) => rej.into_response(),
+            Self::Invalid(errors) => errors.into_response(),
+        }
+    }
+}
+
+/// Extractor wrapper, validating extracted value.
+///
+/// Supports [`Json`] and [`Form`] extractors, optionally wrapped in [`Normalized`]. In that case
+/// validation is performed after normalization.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Validated<T>(pub T);
+
+impl<T> Validated<T> {
+    /// Consume wrapper, returning inner extractor.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Validated<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Validated<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[async_trait]
+impl<E, S> FromRequest<S> for Validated<E>
+where
+    E: FromRequest<S> + ValidationTarget,
+    S: Send + Sync,
+{
+    type Rejection = ValidationRejection<E::Rejection>;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let skip = req.extensions().get::<SkipValidation>().is_some();
+        let inner = E::from_request(req, state)
+            .await
+            .map_err(ValidationRejection::Extract)?;
+        if !skip {
+            inner
+                .validation_target()
+                .validate()
+                .map_err(ValidationRejection::Invalid)?;
+        }
+        Ok(Self(inner))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::header};
+    use schemars::{gen::SchemaSettings, JsonSchema};
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::Normalize;
+
+    fn check_even(value: &u32) -> Result<(), ValidationError> {
+        match value % 2 {
+            0 => Ok(()),
+            _ => Err(ValidationError::new("even", "value must be even")),
+        }
+    }
+
+    fn check_period(period: &Period) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if period.end < period.start {
+            errors.add_field(
+                "end",
+                ValidationError::new("period.order", "end must not precede start"),
+            );
+        }
+        errors.into_result()
+    }
+
+    #[derive(Debug, Deserialize, JsonSchema, Normalize, Validate)]
+    #[validate(custom = "check_period")]
+    struct Period {
+        start: u32,
+        #[validate(custom = "check_even")]
+        end: u32,
+    }
+
+    #[derive(Debug, Deserialize, JsonSchema, Normalize, Validate)]
+    #[serde(rename_all = "camelCase")]
+    struct Address {
+        #[normalize(trim, uppercase)]
+        #[validate(length(equal = 2), regex(pattern = "^[A-Z]+$"))]
+        country_code: String,
+        #[validate(length(min = 1))]
+        city: String,
+    }
+
+    #[derive(Debug, Deserialize, JsonSchema, Normalize, Validate)]
+    struct Signup {
+        #[normalize(trim, lowercase)]
+        #[validate(email)]
+        email: String,
+        #[validate(range(min = 18, max = 150))]
+        age: u32,
+        #[validate(length(max = 8))]
+        nickname: Option<String>,
+        #[validate(length(min = 1, max = 3), nested)]
+        addresses: Vec<Address>,
+        #[validate(nested)]
+        periods: BTreeMap<String, Period>,
+    }
+
+    async fn extract(body: &str, skip: bool) -> Result<Signup, serde_json::Value> {
+        let mut req = Request::post("/")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        if skip {
+            req.extensions_mut().insert(SkipValidation);
+        }
+        match Validated::<Normalized<Json<Signup>>>::from_request(req, &()).await {
+            Ok(Validated(Normalized(Json(signup)))) => Ok(signup),
+            Err(rej) => {
+                let resp = rej.into_response();
+                assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+                let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                Err(serde_json::from_slice(&body).unwrap())
+            }
+        }
+    }
+
+    /// Rules are checked after normalization.
+    #[tokio::test]
+    async fn valid_input() {
+        let body = r#"{
+            "email": " John@Example.COM ",
+            "age": 42,
+            "addresses": [{"countryCode": " de ", "city": "Berlin"}],
+            "periods": {"q1": {"start": 1, "end": 4}}
+        }"#;
+        let signup = extract(body, false).await.unwrap();
+        assert_eq!(signup.email, "john@example.com");
+        assert_eq!(signup.addresses[0].country_code, "DE");
+    }
+
+    /// Violations in nested structs, collections and custom validators are all reported.
+    #[tokio::test]
+    async fn nested_violations() {
+        let body = r#"{
+            "email": "john.example.com",
+            "age": 12,
+            "nickname": "far too long",
+            "addresses": [
+                {"countryCode": "DE", "city": "Berlin"},
+                {"countryCode": "d1", "city": ""}
+            ],
+            "periods": {"a/b": {"start": 5, "end": 3}}
+        }"#;
+        let problem = extract(body, false).await.unwrap_err();
+        assert_eq!(problem["code"], "validation.failed");
+        let errors: Vec<_> = problem["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|err| {
+                (
+                    err["pointer"].as_str().unwrap(),
+                    err["code"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("/email", "email"),
+                ("/age", "range.min"),
+                ("/nickname", "length.max"),
+                ("/addresses/1/countryCode", "pattern"),
+                ("/addresses/1/city", "length.min"),
+                ("/periods/a~1b/end", "even"),
+                ("/periods/a~1b/end", "period.order"),
+            ]
+        );
+        assert_eq!(problem["errors"][1]["params"]["min"], 18.0);
+    }
+
+    /// Validation can be disabled per handler.
+    #[tokio::test]
+    async fn skip_validation() {
+        let body = r#"{"email": "x", "age": 1, "addresses": [], "periods": {}}"#;
+        assert!(extract(body, false).await.is_err());
+        assert_eq!(extract(body, true).await.unwrap().age, 1);
+    }
+
+    /// Built-in rule checks.
+    #[test]
+    fn rule_checks() {
+        assert!(check_email(&"a@b.co".to_string()).is_ok());
+        assert!(check_email(&Some("a b@c.d".to_string())).is_err());
+        assert!(check_email(&"a@b@c.d".to_string()).is_err());
+        assert!(check_email(&"a@localhost".to_string()).is_err());
+        assert!(check_email(&None::<String>).is_ok());
+        assert!(check_length(&"äöü".to_string(), Some(3), Some(3)).is_ok());
+        assert!(check_length(&vec![1, 2], None, Some(1)).is_err());
+        assert!(check_range(&-1.5_f64, Some(-1.0), None).is_err());
+        assert!(check_range(&None::<i32>, Some(0.0), None).is_ok());
+    }
+
+    /// Schemas of the same shape as test types, without validation rules.
+    mod plain {
+        use std::collections::BTreeMap;
+
+        use schemars::JsonSchema;
+
+        #[allow(dead_code)]
+        #[derive(JsonSchema)]
+        #[serde(rename_all = "camelCase")]
+        pub(super) struct Address {
+            country_code: String,
+            city: String,
+        }
+
+        #[allow(dead_code)]
+        #[derive(JsonSchema)]
+        pub(super) struct Signup {
+            email: String,
+            age: u32,
+            nickname: Option<String>,
+            addresses: Vec<Address>,
+            periods: BTreeMap<String, u32>,
+        }
+    }
+
+    /// Declared rules are reflected in JSON schema, including referenced definitions.
+    #[test]
+    fn schema_reflection() {
+        let mut gen = SchemaSettings::openapi3().into_generator();
+        let mut schema = gen.subschema_for::<plain::Signup>().into_object();
+        let defs = serde_json::to_value(gen.definitions()).unwrap();
+        assert!(defs["Signup"]["properties"]["email"]
+            .get("format")
+            .is_none());
+        annotate_validate_schema::<Signup>(&mut gen, &mut schema);
+        let defs = serde_json::to_value(gen.definitions()).unwrap();
+        let signup = &defs["Signup"]["properties"];
+        assert_eq!(signup["email"]["format"], "email");
+        assert_eq!(signup["age"]["minimum"], 18.0);
+        assert_eq!(signup["age"]["maximum"], 150.0);
+        assert_eq!(signup["nickname"]["maxLength"], 8);
+        assert_eq!(signup["addresses"]["minItems"], 1);
+        assert_eq!(signup["addresses"]["maxItems"], 3);
+        let address = &defs["Address"]["properties"];
+        assert_eq!(address["countryCode"]["minLength"], 2);
+        assert_eq!(address["countryCode"]["maxLength"], 2);
+        assert_eq!(address["countryCode"]["pattern"], "^[A-Z]+$");
+        assert_eq!(address["city"]["minLength"], 1);
+    }
+
+    /// Spec paths are split into segments.
+    #[test]
+    fn spec_paths() {
+        assert_eq!(
+            parse_spec_path("a[].b.*.c[][]"),
+            [
+                PathSegment::Field("a"),
+                PathSegment::Items,
+                PathSegment::Field("b"),
+                PathSegment::Values,
+                PathSegment::Field("c"),
+                PathSegment::Items,
+                PathSegment::Items,
+            ]
+        );
+    }
+}
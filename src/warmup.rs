@@ -0,0 +1,370 @@
+//! Handler warmup at startup.
+//!
+//! Warmup issues synthetic requests through the in-process router before readiness probe reports
+//! the service as ready. Application-defined warmup hooks are run beforehand, as part of startup
+//! dependency graph.
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    http::{header, HeaderName, HeaderValue, Method, Request},
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use tower::ServiceExt;
+use tracing::{info, info_span, warn, Instrument};
+
+use crate::probes::ProbeState;
+
+/// Marker extension for synthetic warmup requests.
+///
+/// Handlers can extract it as `Option<Extension<Warmup>>` to skip business side effects.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Warmup;
+
+/// Warmup configuration.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct WarmupConfig {
+    /// Overall warmup timeout.
+    ///
+    /// After this timeout readiness proceeds anyway.
+    #[serde(default = "WarmupConfig::default_timeout", with = "humantime_serde")]
+    timeout: Duration,
+    /// Timeout for a single warmup hook or request.
+    #[serde(
+        default = "WarmupConfig::default_target_timeout",
+        with = "humantime_serde"
+    )]
+    target_timeout: Duration,
+    /// Issue warmup requests to all GET handlers without path parameters.
+    #[serde(default)]
+    all_get_handlers: bool,
+    /// Synthetic requests to issue.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    requests: Vec<WarmupRequest>,
+}
+
+impl Default for WarmupConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Self::default_timeout(),
+            target_timeout: Self::default_target_timeout(),
+            all_get_handlers: false,
+            requests: Vec::new(),
+        }
+    }
+}
+
+impl WarmupConfig {
+    /// Default value for [`Self::timeout`].
+    #[must_use]
+    #[inline]
+    fn default_timeout() -> Duration {
+        Duration::from_secs(30)
+    }
+
+    /// Default value for [`Self::target_timeout`].
+    #[must_use]
+    #[inline]
+    fn default_target_timeout() -> Duration {
+        Duration::from_secs(5)
+    }
+
+    /// Set overall warmup timeout.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set timeout for a single warmup hook or request.
+    #[must_use]
+    pub fn with_target_timeout(mut self, timeout: Duration) -> Self {
+        self.target_timeout = timeout;
+        self
+    }
+
+    /// Timeout for a single warmup hook or request.
+    #[must_use]
+    pub(crate) fn target_timeout(&self) -> Duration {
+        self.target_timeout
+    }
+
+    /// Issue warmup requests to all GET handlers without path parameters.
+    #[must_use]
+    pub fn with_all_get_handlers(mut self, enabled: bool) -> Self {
+        self.all_get_handlers = enabled;
+        self
+    }
+
+    /// Add synthetic warmup request.
+    #[must_use]
+    pub fn with_request(mut self, request: WarmupRequest) -> Self {
+        self.requests.push(request);
+        self
+    }
+}
+
+/// Synthetic warmup request.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct WarmupRequest {
+    /// HTTP method.
+    #[serde(default = "WarmupRequest::default_method")]
+    method: String,
+    /// URL path, including optional query string.
+    path: String,
+    /// HTTP request headers.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<String, String>,
+    /// JSON request body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<serde_json::Value>,
+}
+
+impl WarmupRequest {
+    /// Default value for [`Self::method`].
+    #[must_use]
+    #[inline]
+    fn default_method() -> String {
+        Method::GET.to_string()
+    }
+
+    /// Create new warmup request.
+    #[must_use]
+    pub fn new(method: Method, path: impl ToString) -> Self {
+        Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            headers: BTreeMap::new(),
+            body: None,
+        }
+    }
+
+    /// Add HTTP request header.
+    #[must_use]
+    pub fn with_header(mut self, name: impl ToString, value: impl ToString) -> Self {
+        self.headers.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Set JSON request body.
+    #[must_use]
+    pub fn with_body(mut self, body: serde_json::Value) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    /// Build HTTP request, marked with [`Warmup`] extension.
+    fn to_request(&self) -> Option<Request<Body>> {
+        let body = match self.body {
+            Some(ref value) => Body::from(value.to_string()),
+            None => Body::empty(),
+        };
+        let mut req = Request::builder()
+            .method(Method::from_bytes(self.method.to_ascii_uppercase().as_bytes()).ok()?)
+            .uri(&self.path)
+            .extension(Warmup);
+        for (name, value) in &self.headers {
+            req = req.header(
+                HeaderName::try_from(name.as_str()).ok()?,
+                HeaderValue::try_from(value.as_str()).ok()?,
+            );
+        }
+        if self.body.is_some() && !self.headers.contains_key(header::CONTENT_TYPE.as_str()) {
+            req = req.header(header::CONTENT_TYPE, "application/json");
+        }
+        req.body(body).ok()
+    }
+}
+
+/// Warmup executor.
+pub(crate) struct WarmupRunner {
+    /// Warmup configuration.
+    config: WarmupConfig,
+    /// Synthetic requests to issue.
+    requests: Vec<WarmupRequest>,
+}
+
+impl WarmupRunner {
+    /// Create new warmup executor.
+    ///
+    /// `get_paths` are URL paths of GET handlers without path parameters, used if
+    /// [`WarmupConfig::all_get_handlers`] is enabled.
+    pub(crate) fn new<'a>(
+        config: WarmupConfig,
+        get_paths: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        let mut requests = config.requests.clone();
+        if config.all_get_handlers {
+            requests.extend(
+                get_paths
+                    .into_iter()
+                    .map(|path| WarmupRequest::new(Method::GET, path)),
+            );
+        }
+        Self { config, requests }
+    }
+
+    /// Check if there is anything to do.
+    #[must_use]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// Run warmup, marking it as finished in `probes` afterwards.
+    pub(crate) async fn run(self, app: Router, probes: ProbeState) {
+        let start = Instant::now();
+        let timeout = self.config.timeout;
+        let target_timeout = self.config.target_timeout;
+        let all = async move {
+            for wreq in self.requests {
+                let span = info_span!("warmup_request", method = wreq.method, path = wreq.path);
+                let Some(req) = wreq.to_request() else {
+                    span.in_scope(|| warn!("invalid warmup request"));
+                    continue;
+                };
+                let req_start = Instant::now();
+                let fut = async {
+                    let resp = app.clone().oneshot(req).await?;
+                    let status = resp.status();
+                    let _ = axum::body::to_bytes(resp.into_body(), usize::MAX).await;
+                    Ok::<_, std::convert::Infallible>(status)
+                };
+                match tokio::time::timeout(target_timeout, fut.instrument(span.clone())).await {
+                    Ok(Ok(status)) => span.in_scope(|| {
+                        info!(%status, elapsed = ?req_start.elapsed(), "warmup request finished");
+                    }),
+                    Ok(Err(never)) => match never {},
+                    Err(_) => span.in_scope(|| warn!("warmup request timed out")),
+                }
+            }
+        };
+        match tokio::time::timeout(timeout, all).await {
+            Ok(()) => info!(elapsed = ?start.elapsed(), "warmup finished"),
+            Err(_) => warn!(elapsed = ?start.elapsed(), "warmup timed out, proceeding anyway"),
+        }
+        probes.end_warmup();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use axum::{
+        extract::Extension,
+        http::{Method, StatusCode},
+        routing,
+    };
+
+    use super::*;
+    use crate::{auth::NoOpAuthExtractor, auth::NoOpAuthProvider, probes::Lifecycle, ProbeConfig};
+
+    async fn status(app: &Router, method: Method, path: &str) -> StatusCode {
+        let req = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::empty())
+            .unwrap();
+        app.clone().oneshot(req).await.unwrap().status()
+    }
+
+    async fn probe_app() -> (ProbeState, Router) {
+        let probes = ProbeConfig::default().build_state(&Default::default());
+        probes.set_lifecycle(Lifecycle::Ready);
+        let app = ProbeConfig::default().build_router(
+            probes.clone(),
+            NoOpAuthProvider,
+            NoOpAuthExtractor,
+        );
+        assert_eq!(
+            status(&app, Method::POST, "/maintenance/off").await,
+            StatusCode::OK
+        );
+        (probes, app)
+    }
+
+    /// Readiness is held until warmup is finished.
+    #[tokio::test]
+    async fn readiness_ordering() {
+        let (probes, app) = probe_app().await;
+        assert_eq!(
+            status(&app, Method::GET, "/healthz/ready").await,
+            StatusCode::OK
+        );
+
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let rx = Arc::new(tokio::sync::Mutex::new(Some(rx)));
+        let app = app.route(
+            "/wait",
+            routing::get(move || async move {
+                if let Some(rx) = rx.lock().await.take() {
+                    let _ = rx.await;
+                }
+            }),
+        );
+        let config = WarmupConfig::default().with_request(WarmupRequest::new(Method::GET, "/wait"));
+        let runner = WarmupRunner::new(config, []);
+        probes.begin_warmup();
+        let task = tokio::spawn(runner.run(app.clone(), probes.clone()));
+        assert_eq!(
+            status(&app, Method::GET, "/healthz/ready").await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        tx.send(()).unwrap();
+        task.await.unwrap();
+        assert_eq!(
+            status(&app, Method::GET, "/healthz/ready").await,
+            StatusCode::OK
+        );
+    }
+
+    /// Readiness proceeds after overall timeout.
+    #[tokio::test]
+    async fn timeout() {
+        let (probes, app) = probe_app().await;
+        let app = app.route("/stuck", routing::get(futures::future::pending::<()>));
+        let config = WarmupConfig::default()
+            .with_timeout(Duration::from_millis(50))
+            .with_request(WarmupRequest::new(Method::GET, "/stuck"));
+        probes.begin_warmup();
+        WarmupRunner::new(config, [])
+            .run(app.clone(), probes.clone())
+            .await;
+        assert!(probes.is_ready());
+    }
+
+    /// Handlers see warmup extension on synthetic requests.
+    #[tokio::test]
+    async fn extension_visible() {
+        let seen = Arc::new(AtomicBool::new(false));
+        let seen_hdl = seen.clone();
+        let app = Router::new().route(
+            "/hello",
+            routing::get(move |warmup: Option<Extension<Warmup>>| async move {
+                seen_hdl.store(warmup.is_some(), Ordering::Relaxed);
+                "hello"
+            }),
+        );
+        let probes = ProbeState::default();
+        WarmupRunner::new(
+            WarmupConfig::default().with_all_get_handlers(true),
+            ["/hello"],
+        )
+        .run(app.clone(), probes)
+        .await;
+        assert!(seen.load(Ordering::Relaxed));
+        assert_eq!(status(&app, Method::GET, "/hello").await, StatusCode::OK);
+        assert!(!seen.load(Ordering::Relaxed));
+    }
+}
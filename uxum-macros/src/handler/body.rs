@@ -15,6 +15,20 @@ pub(crate) enum RequestBody {
     Form,
     /// Some type serialized as JSON.
     Json(Path),
+    /// Some type serialized as JSON, normalized after deserialization.
+    NormalizedJson {
+        /// Deserialized type.
+        path: Path,
+        /// Handler-level normalization rules.
+        defaults: TokenStream,
+    },
+    /// Some type serialized as JSON, validated after deserialization.
+    ValidatedJson {
+        /// Deserialized type.
+        path: Path,
+        /// Handler-level normalization rules, if also normalized.
+        defaults: Option<TokenStream>,
+    },
 }
 
 impl ToTokens for RequestBody {
@@ -25,6 +39,26 @@ impl ToTokens for RequestBody {
             Self::Bytes => quote! { gen.subschema_for::<bytes::Bytes>().into_object() },
             Self::Form => return, // TODO: write this.
             Self::Json(path) => quote! { gen.subschema_for::<#path>().into_object() },
+            Self::NormalizedJson { path, defaults } => quote! {
+                {
+                    let mut schema = gen.subschema_for::<#path>().into_object();
+                    ::uxum::annotate_normalize_schema::<#path>(&mut schema, #defaults);
+                    schema
+                }
+            },
+            Self::ValidatedJson { path, defaults } => {
+                let normalize = defaults.as_ref().map(|defaults| {
+                    quote! { ::uxum::annotate_normalize_schema::<#path>(&mut schema, #defaults); }
+                });
+                quote! {
+                    {
+                        let mut schema = gen.subschema_for::<#path>().into_object();
+                        #normalize
+                        ::uxum::annotate_validate_schema::<#path>(gen, &mut schema);
+                        schema
+                    }
+                }
+            }
         };
         tokens.append_all(quote! {
             openapi3::RequestBody {
@@ -53,7 +87,21 @@ impl RequestBody {
             Self::String => mime::TEXT_PLAIN_UTF_8.as_ref(),
             Self::Bytes => mime::APPLICATION_OCTET_STREAM.as_ref(),
             Self::Form => mime::APPLICATION_WWW_FORM_URLENCODED.as_ref(),
-            Self::Json(_) => mime::APPLICATION_JSON.as_ref(),
+            Self::Json(_) | Self::NormalizedJson { .. } | Self::ValidatedJson { .. } => {
+                mime::APPLICATION_JSON.as_ref()
+            }
+        }
+    }
+
+    /// Set handler-level normalization rules, used in OpenAPI specification.
+    pub(crate) fn set_normalize_defaults(&mut self, rules: TokenStream) {
+        match self {
+            Self::NormalizedJson { defaults, .. } => *defaults = rules,
+            Self::ValidatedJson {
+                defaults: Some(defaults),
+                ..
+            } => *defaults = rules,
+            _ => {}
         }
     }
 }
@@ -73,18 +121,33 @@ pub(crate) fn detect_request_body(handler: &ItemFn) -> Option<RequestBody> {
                         "Bytes" => Some(RequestBody::Bytes),
                         // TODO: type inside Form.
                         "Form" => Some(RequestBody::Form),
-                        "Json" => match &seg.arguments {
-                            PathArguments::AngleBracketed(AngleBracketedGenericArguments {
-                                args,
-                                ..
-                            }) if args.len() == 1 => match &args[0] {
-                                GenericArgument::Type(Type::Path(TypePath { path, .. })) => {
-                                    Some(RequestBody::Json(path.clone()))
-                                }
+                        "Json" => single_type_arg(&seg.arguments)
+                            .map(|path| RequestBody::Json(path.clone())),
+                        // Identity of authenticated caller, extracted from request parts.
+                        "CurrentUser" => None,
+                        "Normalized" => detect_normalized(&seg.arguments),
+                        "Validated" => {
+                            let inner = single_type_arg(&seg.arguments)?.segments.last()?;
+                            match inner.ident.to_string().as_str() {
+                                "Form" => Some(RequestBody::Form),
+                                "Json" => single_type_arg(&inner.arguments).map(|path| {
+                                    RequestBody::ValidatedJson {
+                                        path: path.clone(),
+                                        defaults: None,
+                                    }
+                                }),
+                                "Normalized" => match detect_normalized(&inner.arguments)? {
+                                    RequestBody::NormalizedJson { path, defaults } => {
+                                        Some(RequestBody::ValidatedJson {
+                                            path,
+                                            defaults: Some(defaults),
+                                        })
+                                    }
+                                    other => Some(other),
+                                },
                                 _ => None,
-                            },
-                            _ => None,
-                        },
+                            }
+                        }
                         _ => None,
                     })
             }
@@ -94,3 +157,31 @@ pub(crate) fn detect_request_body(handler: &ItemFn) -> Option<RequestBody> {
         FnArg::Receiver(_) => None,
     })
 }
+
+/// Detect request body inside `Normalized` extractor wrapper, given its generic arguments.
+fn detect_normalized(args: &PathArguments) -> Option<RequestBody> {
+    let inner = single_type_arg(args)?.segments.last()?;
+    match inner.ident.to_string().as_str() {
+        "Form" => Some(RequestBody::Form),
+        "Json" => single_type_arg(&inner.arguments).map(|path| RequestBody::NormalizedJson {
+            path: path.clone(),
+            defaults: quote! { ::uxum::NormalizeRules::NONE },
+        }),
+        _ => None,
+    }
+}
+
+/// Get path of the only generic type argument, if any.
+fn single_type_arg(args: &PathArguments) -> Option<&Path> {
+    match args {
+        PathArguments::AngleBracketed(AngleBracketedGenericArguments { args, .. })
+            if args.len() == 1 =>
+        {
+            match &args[0] {
+                GenericArgument::Type(Type::Path(TypePath { path, .. })) => Some(path),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
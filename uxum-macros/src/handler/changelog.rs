@@ -0,0 +1,29 @@
+use darling::FromMeta;
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens, TokenStreamExt};
+
+/// Single entry of API changelog.
+#[derive(Debug, FromMeta)]
+pub(crate) struct HandlerChange {
+    /// Version in which the change was introduced.
+    pub(crate) version: syn::LitStr,
+    /// Human-readable description of the change.
+    note: String,
+}
+
+impl ToTokens for HandlerChange {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let version = &self.version;
+        let note = &self.note;
+        tokens.append_all(quote! {
+            ::uxum::ApiChange::new(#version, #note)
+        });
+    }
+}
+
+/// Check that version string conforms to semantic versioning.
+pub(crate) fn validate_version(version: &syn::LitStr) -> Result<(), String> {
+    semver::Version::parse(&version.value())
+        .map(|_| ())
+        .map_err(|err| format!("Invalid version \"{}\": {}", version.value(), err))
+}
@@ -2,7 +2,7 @@ use darling::FromMeta;
 use proc_macro2::TokenStream;
 use quote::{quote, ToTokens, TokenStreamExt};
 
-use crate::handler::spec::HandlerSpec;
+use crate::handler::{changelog::HandlerChange, spec::HandlerSpec};
 
 /// Top-level handler parameters object.
 #[derive(Debug, Default, FromMeta)]
@@ -17,6 +17,9 @@ pub(crate) struct HandlerData {
     /// HTTP method for handler.
     #[darling(default)]
     pub(crate) method: Option<HandlerMethod>,
+    /// Several HTTP methods for handler, mutually exclusive with `method`.
+    #[darling(default)]
+    pub(crate) methods: Vec<syn::LitStr>,
     /// Additional parameters for OpenAPI specification.
     #[darling(default, flatten)]
     pub(crate) spec: HandlerSpec,
@@ -26,11 +29,47 @@ pub(crate) struct HandlerData {
     /// Skip authentication for this method.
     #[darling(default)]
     pub(crate) no_auth: bool,
+    /// API version in which handler was introduced.
+    #[darling(default)]
+    pub(crate) since: Option<syn::LitStr>,
+    /// Changes to handler behavior, per API version.
+    #[darling(multiple)]
+    pub(crate) changes: Vec<HandlerChange>,
+    /// Default normalization rules for strings in normalized request bodies.
+    #[darling(default)]
+    pub(crate) normalize_strings: Option<syn::LitStr>,
+    /// Visibility of handler in API documentation.
+    #[darling(default)]
+    pub(crate) visibility: HandlerVisibility,
+    /// Watch handler for CPU-bound work which does not yield to async runtime.
+    #[darling(default)]
+    pub(crate) cpu_guard: bool,
+    /// Skip business rule validation of validated request bodies.
+    #[darling(default)]
+    pub(crate) no_validate: bool,
 }
 
-/// Supported HTTP methods.
+/// Handler visibility in API documentation.
 #[derive(Debug, Default, FromMeta)]
-#[darling(default, rename_all = "SCREAMING_SNAKE_CASE")]
+#[darling(default, rename_all = "lowercase")]
+pub(crate) enum HandlerVisibility {
+    Public,
+    #[default]
+    Internal,
+}
+
+impl ToTokens for HandlerVisibility {
+    fn to_tokens(&self, stream: &mut TokenStream) {
+        let new_tokens: TokenStream = match self {
+            Self::Public => quote! { ::uxum::ApiVisibility::Public },
+            Self::Internal => quote! { ::uxum::ApiVisibility::Internal },
+        };
+        stream.append_all(new_tokens);
+    }
+}
+
+/// Supported HTTP methods.
+#[derive(Debug, Default, PartialEq)]
 pub(crate) enum HandlerMethod {
     #[default]
     Get,
@@ -41,6 +80,54 @@ pub(crate) enum HandlerMethod {
     Options,
     Trace,
     Patch,
+    /// Non-standard method, like `QUERY` or WebDAV extension methods.
+    Custom(String),
+}
+
+impl FromMeta for HandlerMethod {
+    fn from_string(value: &str) -> darling::Result<Self> {
+        Ok(match value {
+            "GET" => Self::Get,
+            "HEAD" => Self::Head,
+            "POST" => Self::Post,
+            "PUT" => Self::Put,
+            "DELETE" => Self::Delete,
+            "OPTIONS" => Self::Options,
+            "TRACE" => Self::Trace,
+            "PATCH" => Self::Patch,
+            other if is_method_token(other) => Self::Custom(other.to_owned()),
+            other => {
+                return Err(darling::Error::custom(format!(
+                    "Invalid HTTP method: {other:?}"
+                )))
+            }
+        })
+    }
+}
+
+impl HandlerMethod {
+    /// HTTP method name.
+    pub(crate) fn as_str(&self) -> &str {
+        match self {
+            Self::Get => "GET",
+            Self::Head => "HEAD",
+            Self::Post => "POST",
+            Self::Put => "PUT",
+            Self::Delete => "DELETE",
+            Self::Options => "OPTIONS",
+            Self::Trace => "TRACE",
+            Self::Patch => "PATCH",
+            Self::Custom(method) => method,
+        }
+    }
+}
+
+/// Check if string is a valid HTTP method token, as defined in RFC 9110.
+fn is_method_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
 }
 
 impl ToTokens for HandlerMethod {
@@ -54,6 +141,10 @@ impl ToTokens for HandlerMethod {
             Self::Options => quote! { ::uxum::reexport::http::Method::OPTIONS },
             Self::Trace => quote! { ::uxum::reexport::http::Method::TRACE },
             Self::Patch => quote! { ::uxum::reexport::http::Method::PATCH },
+            Self::Custom(method) => quote! {
+                ::uxum::reexport::http::Method::from_bytes(#method.as_bytes())
+                    .expect("Method token validated at compile time")
+            },
         };
         stream.append_all(new_tokens);
     }
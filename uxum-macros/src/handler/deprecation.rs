@@ -0,0 +1,114 @@
+use darling::{ast::NestedMeta, FromMeta};
+use proc_macro_error::abort;
+use syn::LitStr;
+
+/// Handler deprecation attribute.
+///
+/// Accepts both a bare `deprecated` flag and `deprecated(since = "...", note = "...")`.
+#[derive(Debug, Default)]
+pub(crate) struct HandlerDeprecation {
+    /// Deprecation flag.
+    enabled: bool,
+    /// Date since which the handler is deprecated, in `YYYY-MM-DD` format.
+    since: Option<LitStr>,
+    /// Note for API users, i.e. which handler to use instead.
+    note: Option<String>,
+}
+
+/// Arguments of `deprecated(...)` attribute.
+#[derive(Debug, Default, FromMeta)]
+struct DeprecationArgs {
+    /// Date since which the handler is deprecated.
+    #[darling(default)]
+    since: Option<LitStr>,
+    /// Note for API users.
+    #[darling(default)]
+    note: Option<String>,
+}
+
+impl FromMeta for HandlerDeprecation {
+    fn from_word() -> darling::Result<Self> {
+        Self::from_bool(true)
+    }
+
+    fn from_bool(enabled: bool) -> darling::Result<Self> {
+        Ok(Self {
+            enabled,
+            ..Default::default()
+        })
+    }
+
+    fn from_list(items: &[NestedMeta]) -> darling::Result<Self> {
+        let args = DeprecationArgs::from_list(items)?;
+        Ok(Self {
+            enabled: true,
+            since: args.since,
+            note: args.note,
+        })
+    }
+}
+
+impl HandlerDeprecation {
+    /// Whether handler is marked as deprecated.
+    #[must_use]
+    pub(crate) fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Date since which the handler is deprecated, as written in attribute.
+    #[must_use]
+    pub(crate) fn since(&self) -> Option<String> {
+        self.since.as_ref().map(LitStr::value)
+    }
+
+    /// Note for API users.
+    #[must_use]
+    pub(crate) fn note(&self) -> Option<&str> {
+        self.note.as_deref()
+    }
+
+    /// Date since which the handler is deprecated, as seconds since UNIX epoch.
+    ///
+    /// Aborts compilation if date is not valid.
+    #[must_use]
+    pub(crate) fn since_timestamp(&self) -> Option<u64> {
+        let lit = self.since.as_ref()?;
+        match parse_date(&lit.value()) {
+            Some(days) => Some(days * 86_400),
+            None => abort!(
+                lit,
+                "Expected date in YYYY-MM-DD format, not before 1970-01-01"
+            ),
+        }
+    }
+}
+
+/// Parse `YYYY-MM-DD` date into number of days since UNIX epoch.
+fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date.splitn(3, '-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    let (year, month, day): (i64, i64, i64) =
+        (year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let month_days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return None,
+    };
+    if day < 1 || day > month_days {
+        return None;
+    }
+    // Days from civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    u64::try_from(era * 146_097 + doe - 719_468).ok()
+}
@@ -0,0 +1,124 @@
+use darling::FromMeta;
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens, TokenStreamExt};
+use syn::Path;
+
+use crate::util::quote_option;
+
+/// Parameters consumed outside of handler function, documented in OpenAPI specification only.
+#[derive(Debug, Default, FromMeta)]
+pub(crate) struct ExtraParams {
+    /// Query string parameters.
+    #[darling(multiple)]
+    query: Vec<ExtraParam>,
+    /// Request header parameters.
+    #[darling(multiple)]
+    header: Vec<ExtraParam>,
+    /// Cookie parameters.
+    #[darling(multiple)]
+    cookie: Vec<ExtraParam>,
+}
+
+impl ExtraParams {
+    /// Iterate over all declared parameters, along with their OpenAPI locations.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = ExtraParamWithLocation<'_>> {
+        self.query
+            .iter()
+            .map(|param| ExtraParamWithLocation("query", param))
+            .chain(
+                self.header
+                    .iter()
+                    .map(|param| ExtraParamWithLocation("header", param)),
+            )
+            .chain(
+                self.cookie
+                    .iter()
+                    .map(|param| ExtraParamWithLocation("cookie", param)),
+            )
+    }
+
+    /// Find first parameter declared more than once.
+    ///
+    /// Header names are compared case-insensitively.
+    #[must_use]
+    pub(crate) fn find_duplicate(&self) -> Option<ExtraParamWithLocation<'_>> {
+        let mut seen = Vec::new();
+        for param in self.iter() {
+            let key = param.key();
+            if seen.contains(&key) {
+                return Some(param);
+            }
+            seen.push(key);
+        }
+        None
+    }
+}
+
+/// Single parameter declaration.
+#[derive(Debug, FromMeta)]
+pub(crate) struct ExtraParam {
+    /// Parameter name.
+    pub(crate) name: String,
+    /// Type of parameter value.
+    ///
+    /// Defaults to [`String`].
+    #[darling(default)]
+    schema: Option<Path>,
+    /// Description.
+    #[darling(default)]
+    description: Option<String>,
+    /// Whether parameter is required.
+    #[darling(default)]
+    required: bool,
+    /// Deprecation flag.
+    #[darling(default)]
+    deprecated: bool,
+}
+
+/// Parameter declaration, along with its OpenAPI location.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ExtraParamWithLocation<'a>(pub(crate) &'static str, pub(crate) &'a ExtraParam);
+
+impl ExtraParamWithLocation<'_> {
+    /// Unique key of parameter within an operation.
+    #[must_use]
+    fn key(&self) -> (&'static str, String) {
+        match self.0 {
+            "header" => (self.0, self.1.name.to_ascii_lowercase()),
+            _ => (self.0, self.1.name.clone()),
+        }
+    }
+}
+
+impl ToTokens for ExtraParamWithLocation<'_> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let Self(location, param) = self;
+        let name = &param.name;
+        let description = quote_option(&param.description);
+        let required = param.required;
+        let deprecated = param.deprecated;
+        let value_type = match &param.schema {
+            Some(path) => quote! { #path },
+            None => quote! { String },
+        };
+        tokens.append_all(quote! {
+            openapi3::Parameter {
+                name: #name.into(),
+                location: #location.into(),
+                description: #description,
+                required: #required,
+                deprecated: #deprecated,
+                allow_empty_value: false,
+                value: openapi3::ParameterValue::Schema {
+                    style: None,
+                    explode: None,
+                    allow_reserved: false,
+                    schema: gen.subschema_for::<#value_type>().into_object(),
+                    example: None,
+                    examples: None,
+                },
+                extensions: Default::default(),
+            }
+        });
+    }
+}
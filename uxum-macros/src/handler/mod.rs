@@ -1,13 +1,18 @@
 //! Various options and extractors used in handler macro.
 
 pub(crate) mod body;
+pub(crate) mod changelog;
 pub(crate) mod data;
+pub(crate) mod deprecation;
 pub(crate) mod doc;
 pub(crate) mod external_doc;
+pub(crate) mod extra_param;
 pub(crate) mod path;
 pub(crate) mod path_param;
 pub(crate) mod query;
+pub(crate) mod query_param;
 pub(crate) mod response;
+pub(crate) mod response_header;
 pub(crate) mod server;
 pub(crate) mod spec;
 pub(crate) mod state;
@@ -0,0 +1,120 @@
+use darling::{ast::NestedMeta, FromMeta};
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::{Ident, Lit, Meta};
+
+use crate::handler::query::QueryType;
+
+/// Query parameter documentation.
+#[derive(Clone, Debug, Default, FromMeta)]
+pub(crate) struct OpenApiQueryParameter {
+    /// Description.
+    ///
+    /// Overrides description taken from field docstring.
+    #[darling(default)]
+    description: Option<String>,
+    /// Deprecation flag.
+    #[darling(default)]
+    deprecated: bool,
+    /// Example value.
+    #[darling(default)]
+    example: Option<Lit>,
+    /// Whether parameter is required.
+    ///
+    /// Detected from extractor schema if not set.
+    #[darling(default)]
+    required: Option<bool>,
+}
+
+/// Documentation for query parameters, keyed by extractor field name.
+#[derive(Debug, Default)]
+pub(crate) struct QueryParams(Vec<(Ident, OpenApiQueryParameter)>);
+
+impl FromMeta for QueryParams {
+    fn from_list(items: &[NestedMeta]) -> darling::Result<Self> {
+        let mut params = Vec::with_capacity(items.len());
+        let mut errors = darling::Error::accumulator();
+        for item in items {
+            let NestedMeta::Meta(meta) = item else {
+                errors.push(darling::Error::unsupported_format("literal").with_span(item));
+                continue;
+            };
+            let Some(ident) = meta.path().get_ident() else {
+                errors.push(darling::Error::custom("expected field name").with_span(meta));
+                continue;
+            };
+            let param = match meta {
+                Meta::Path(_) => Ok(OpenApiQueryParameter::default()),
+                _ => OpenApiQueryParameter::from_meta(meta),
+            };
+            if let Some(param) = errors.handle(param) {
+                params.push((ident.clone(), param));
+            }
+        }
+        errors.finish_with(Self(params))
+    }
+}
+
+impl QueryParams {
+    /// First documented field, used to report errors.
+    #[must_use]
+    pub(crate) fn first(&self) -> Option<&Ident> {
+        self.0.first().map(|(ident, _)| ident)
+    }
+
+    /// Generate code asserting that all documented fields exist in query extractor.
+    ///
+    /// Errors point to the field name inside handler attribute.
+    #[must_use]
+    pub(crate) fn generate_checks(&self, query_type: &QueryType) -> TokenStream {
+        let checks = self.0.iter().map(|(ident, _)| {
+            quote_spanned! { ident.span()=>
+                let _ = |query: &#query_type| {
+                    let _ = &query.#ident;
+                };
+            }
+        });
+        quote! { #(#checks)* }
+    }
+
+    /// Generate code applying documentation to parameter named `param`.
+    #[must_use]
+    pub(crate) fn generate_overrides(&self) -> TokenStream {
+        if self.0.is_empty() {
+            return TokenStream::new();
+        }
+        let arms = self.0.iter().map(|(ident, param)| {
+            let name = ident.to_string();
+            let description = param.description.as_ref().map(|descr| {
+                quote! { param.description = Some(#descr.into()); }
+            });
+            let deprecated = param.deprecated.then(|| {
+                quote! { param.deprecated = true; }
+            });
+            let required = param.required.map(|required| {
+                quote! { param.required = #required; }
+            });
+            let example = param.example.as_ref().map(|value| {
+                quote! {
+                    if let openapi3::ParameterValue::Schema { example, .. } = &mut param.value {
+                        *example = Some(schemars::_serde_json::json!(#value));
+                    }
+                }
+            });
+            quote! {
+                #name => {
+                    #description
+                    #deprecated
+                    #required
+                    #example
+                }
+            }
+        });
+        quote! {
+            match param.name.as_str() {
+                #(#arms)*
+                _ => {}
+            }
+        }
+    }
+}
@@ -7,7 +7,7 @@ pub(crate) enum ResponseTemplate {
     /// No response in function.
     Default,
     /// Some response type in function.
-    Typed(Type),
+    Typed(Box<Type>),
 }
 
 impl ToTokens for ResponseTemplate {
@@ -37,6 +37,6 @@ impl ToTokens for ResponseTemplate {
 pub(crate) fn detect_responses(handler: &ItemFn) -> ResponseTemplate {
     match &handler.sig.output {
         ReturnType::Default => ResponseTemplate::Default,
-        ReturnType::Type(_, t) => ResponseTemplate::Typed(*t.clone()),
+        ReturnType::Type(_, t) => ResponseTemplate::Typed(t.clone()),
     }
 }
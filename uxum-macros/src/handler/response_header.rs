@@ -0,0 +1,129 @@
+use convert_case::{Case, Casing};
+use darling::{ast::NestedMeta, FromMeta};
+use proc_macro2::TokenStream;
+use proc_macro_error::abort;
+use quote::{quote, ToTokens, TokenStreamExt};
+use syn::{Ident, LitStr, Meta};
+
+use crate::util::quote_option;
+
+/// Response header documentation.
+#[derive(Clone, Debug, Default, FromMeta)]
+pub(crate) struct OpenApiResponseHeader {
+    /// Header name.
+    ///
+    /// Derived from attribute name if not set, i.e. `retry_after` becomes `Retry-After`.
+    #[darling(default)]
+    name: Option<String>,
+    /// Description.
+    #[darling(default)]
+    description: Option<String>,
+    /// Type of header value: `string`, `integer`, `number` or `boolean`.
+    ///
+    /// Defaults to `string`.
+    #[darling(default)]
+    schema: Option<LitStr>,
+    /// Whether header is always present.
+    #[darling(default)]
+    required: bool,
+    /// Deprecation flag.
+    #[darling(default)]
+    deprecated: bool,
+    /// Response status codes this header applies to.
+    ///
+    /// Applies to all responses if empty.
+    #[darling(default)]
+    status: Vec<u16>,
+}
+
+/// Documentation for response headers.
+#[derive(Debug, Default)]
+pub(crate) struct ResponseHeaders(Vec<(Ident, OpenApiResponseHeader)>);
+
+impl FromMeta for ResponseHeaders {
+    fn from_list(items: &[NestedMeta]) -> darling::Result<Self> {
+        let mut headers = Vec::with_capacity(items.len());
+        let mut errors = darling::Error::accumulator();
+        for item in items {
+            let NestedMeta::Meta(meta) = item else {
+                errors.push(darling::Error::unsupported_format("literal").with_span(item));
+                continue;
+            };
+            let Some(ident) = meta.path().get_ident() else {
+                errors.push(darling::Error::custom("expected header name").with_span(meta));
+                continue;
+            };
+            let header = match meta {
+                Meta::Path(_) => Ok(OpenApiResponseHeader::default()),
+                _ => OpenApiResponseHeader::from_meta(meta),
+            };
+            if let Some(header) = errors.handle(header) {
+                headers.push((ident.clone(), header));
+            }
+        }
+        errors.finish_with(Self(headers))
+    }
+}
+
+impl ResponseHeaders {
+    /// Iterate over all declared headers.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = ResponseHeaderWithName<'_>> {
+        self.0
+            .iter()
+            .map(|(ident, header)| ResponseHeaderWithName(ident, header))
+    }
+}
+
+/// Response header declaration, along with attribute name.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ResponseHeaderWithName<'a>(&'a Ident, &'a OpenApiResponseHeader);
+
+impl ToTokens for ResponseHeaderWithName<'_> {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let Self(ident, header) = self;
+        let name = header
+            .name
+            .clone()
+            .unwrap_or_else(|| ident.to_string().to_case(Case::Train));
+        let description = quote_option(&header.description);
+        let required = header.required;
+        let deprecated = header.deprecated;
+        let instance_type = match header.schema.as_ref().map(LitStr::value).as_deref() {
+            None | Some("string") => quote! { String },
+            Some("integer") => quote! { Integer },
+            Some("number") => quote! { Number },
+            Some("boolean") => quote! { Boolean },
+            Some(other) => abort!(
+                header.schema,
+                "Unsupported header schema `{}`, expected one of: string, integer, number, boolean",
+                other
+            ),
+        };
+        let status = &header.status;
+        tokens.append_all(quote! {
+            ::uxum::add_response_header(
+                &mut responses,
+                #name,
+                openapi3::Header {
+                    description: #description,
+                    required: #required,
+                    deprecated: #deprecated,
+                    allow_empty_value: false,
+                    value: openapi3::ParameterValue::Schema {
+                        style: None,
+                        explode: None,
+                        allow_reserved: false,
+                        schema: schemars::schema::SchemaObject {
+                            instance_type: Some(schemars::schema::InstanceType::#instance_type.into()),
+                            ..Default::default()
+                        },
+                        example: None,
+                        examples: None,
+                    },
+                    extensions: Default::default(),
+                },
+                &[#(#status),*],
+            );
+        });
+    }
+}
@@ -2,14 +2,16 @@ use std::collections::HashMap;
 
 use darling::FromMeta;
 use proc_macro2::TokenStream;
+use proc_macro_error::abort;
 use quote::quote;
 use syn::ItemFn;
 
 use crate::{
     handler::{
-        body::RequestBody, data::HandlerMethod, doc::extract_docstring,
-        external_doc::OpenApiExternalDoc, path::extract_path_params,
-        path_param::OpenApiPathParameter, query::detect_query_strings, response::detect_responses,
+        body::RequestBody, data::HandlerMethod, deprecation::HandlerDeprecation,
+        doc::extract_docstring, external_doc::OpenApiExternalDoc, extra_param::ExtraParams,
+        path::extract_path_params, path_param::OpenApiPathParameter, query::detect_query_strings,
+        query_param::QueryParams, response::detect_responses, response_header::ResponseHeaders,
     },
     util::quote_option,
 };
@@ -36,12 +38,35 @@ pub(crate) struct HandlerSpec {
     /// Schema for path parameters.
     #[darling(default)]
     path_params: HashMap<String, OpenApiPathParameter>,
-    /// Deprecation flag.
+    /// Documentation for fields of query string extractor.
     #[darling(default)]
-    deprecated: bool,
+    query_params: QueryParams,
+    /// Documentation for response headers.
+    #[darling(default)]
+    response_headers: ResponseHeaders,
+    /// Deprecation flag, optionally with date and note.
+    #[darling(default)]
+    deprecated: HandlerDeprecation,
+    /// Parameters consumed by layers, rather than by handler function.
+    ///
+    /// These only affect OpenAPI specification.
+    #[darling(default)]
+    extra_params: ExtraParams,
 }
 
 impl HandlerSpec {
+    /// Handler deprecation attribute.
+    #[must_use]
+    pub(crate) fn deprecated(&self) -> &HandlerDeprecation {
+        &self.deprecated
+    }
+
+    /// Parameters consumed by layers, rather than by handler function.
+    #[must_use]
+    pub(crate) fn extra_params(&self) -> &ExtraParams {
+        &self.extra_params
+    }
+
     /// Generate OpenAPI operation schema code.
     #[must_use]
     pub(crate) fn generate_schema(
@@ -54,12 +79,22 @@ impl HandlerSpec {
     ) -> TokenStream {
         let tags = &self.tags;
         let docs = quote_option(&self.docs);
-        let deprecated = self.deprecated;
+        let deprecated = self.deprecated.enabled();
 
         let docstring = extract_docstring(&handler.attrs);
         let summary = quote_option(&self.summary.as_ref().or(docstring.title.as_ref()));
-        let description =
-            quote_option(&self.description.as_ref().or(docstring.description.as_ref()));
+        let description = self.description.as_ref().or(docstring.description.as_ref());
+        let description = quote_option(&match self.deprecated.note() {
+            Some(note) => Some(match description {
+                Some(descr) => format!("{descr}\n\n**Deprecated:** {note}"),
+                None => format!("**Deprecated:** {note}"),
+            }),
+            None => description.cloned(),
+        });
+        let extensions = match self.deprecated.since() {
+            Some(since) => quote! { okapi::map! { "x-deprecated-since".into() => #since.into() } },
+            None => quote! { Default::default() },
+        };
 
         let path_params = extract_path_params(path).map(|elem| {
             let param = self.path_params.get(elem).cloned().unwrap_or_default();
@@ -93,37 +128,50 @@ impl HandlerSpec {
             }
         });
 
-        let query_params = detect_query_strings(handler)
+        let query_type = detect_query_strings(handler);
+        if let (None, Some(ident)) = (&query_type, self.query_params.first()) {
+            abort!(
+                ident,
+                "`query_params` require a `Query<T>` extractor in handler arguments"
+            );
+        }
+        let query_overrides = self.query_params.generate_overrides();
+        let query_params = query_type
             .map(|qt| {
+                let query_checks = self.query_params.generate_checks(&qt);
                 quote! {
                     .into_iter()
                     .chain({
+                        #query_checks
                         let mut params = Vec::new();
                         let query_schema = schemars::schema_for!(#qt);
                         if let Some(query_object) = query_schema.schema.object {
-                            for (key, param) in query_object.properties.into_iter() {
-                                let obj = match &param {
+                            for (key, prop) in query_object.properties.into_iter() {
+                                let obj = match &prop {
                                     schemars::schema::Schema::Object(obj) => obj,
                                     _ => continue,
                                 };
                                 let meta = obj.metadata.as_ref();
-                                params.push(openapi3::Parameter {
-                                    name: key.into(),
+                                let required = query_object.required.contains(&key);
+                                let mut param = openapi3::Parameter {
+                                    name: key,
                                     location: "query".into(),
                                     description: meta.and_then(|m| m.description.clone()),
-                                    required: true, // FIXME: not always required.
+                                    required,
                                     deprecated: meta.map(|m| m.deprecated).unwrap_or_default(),
                                     allow_empty_value: false,
                                     value: openapi3::ParameterValue::Schema {
                                         style: None,
                                         explode: None,
                                         allow_reserved: false,
-                                        schema: param.into(),
+                                        schema: prop.into(),
                                         example: None, // TODO: maybe extract examples?
                                         examples: None,
                                     },
                                     extensions: Default::default(),
-                                }.into());
+                                };
+                                #query_overrides
+                                params.push(param.into());
                             }
                         }
                         params
@@ -133,8 +181,11 @@ impl HandlerSpec {
             })
             .unwrap_or_else(|| quote! {});
 
+        let extra_params = self.extra_params.iter();
+
         let request_body = quote_option(request_body);
         let responses = detect_responses(handler);
+        let response_headers = self.response_headers.iter();
 
         quote! {
             openapi3::Operation {
@@ -143,14 +194,23 @@ impl HandlerSpec {
                 description: #description,
                 external_docs: #docs,
                 operation_id: Some(#name.into()),
-                parameters: vec![#(#path_params.into()),*] #query_params,
+                parameters: {
+                    let mut params: Vec<openapi3::RefOr<openapi3::Parameter>> =
+                        vec![#(#path_params.into()),*] #query_params;
+                    ::uxum::merge_parameters(&mut params, [#(#extra_params),*]);
+                    params
+                },
                 request_body: #request_body,
-                responses: #responses,
+                responses: {
+                    let mut responses = #responses;
+                    #(#response_headers)*
+                    responses
+                },
                 callbacks: Default::default(), // TODO: fill?
                 deprecated: #deprecated,
                 security: None,
                 servers: None,
-                extensions: Default::default(),
+                extensions: #extensions,
             }
         }
     }
@@ -4,22 +4,27 @@
 
 mod case;
 mod handler;
+mod normalize;
 mod util;
+mod validate;
 
 use darling::{ast::NestedMeta, FromMeta};
 use proc_macro::TokenStream;
 use proc_macro_error::{abort, abort_call_site, proc_macro_error};
 use quote::{format_ident, quote};
-use syn::{parse_macro_input, ItemFn};
+use syn::{parse_macro_input, DeriveInput, ItemFn};
 
 use crate::{
     case::{ToCamelCase, ToSnakeCase},
     handler::{
         body::detect_request_body,
+        changelog::validate_version,
         data::{HandlerData, HandlerMethod},
         path::format_path_for_spec,
         state::detect_state,
     },
+    normalize::{derive_normalize, Rules},
+    validate::derive_validate,
 };
 
 /// Attribute macro for declaring service endpoints.
@@ -47,30 +52,98 @@ pub fn handler(args: TokenStream, input: TokenStream) -> TokenStream {
     let handler_name = data.name.unwrap_or_else(|| input.sig.ident.to_string());
     let handler_path = data.path.unwrap_or_else(|| format!("/{handler_name}"));
     let handler_spec_path = format_path_for_spec(&handler_path);
-    let request_body = detect_request_body(&input);
-    let handler_method = match data.method {
-        Some(method) => method,
-        None => {
-            if request_body.is_some() {
-                HandlerMethod::Post
-            } else {
-                HandlerMethod::Get
-            }
-        }
+    let normalize_strings = data.normalize_strings.as_ref().map(|list| {
+        let rules = Rules::parse_list(list);
+        quote! { #rules }
+    });
+    let mut request_body = detect_request_body(&input);
+    if let (Some(body), Some(rules)) = (request_body.as_mut(), &normalize_strings) {
+        body.set_normalize_defaults(rules.clone());
+    }
+    let normalize_strings = match normalize_strings {
+        Some(rules) => quote! { Some(#rules) },
+        None => quote! { None },
     };
+    if data.method.is_some() && !data.methods.is_empty() {
+        abort!(
+            input.sig.ident,
+            "Only one of `method` and `methods` attributes may be used"
+        );
+    }
+    let mut handler_methods: Vec<HandlerMethod> = Vec::with_capacity(data.methods.len());
+    for lit in &data.methods {
+        let method = match HandlerMethod::from_string(&lit.value()) {
+            Ok(method) => method,
+            Err(err) => abort!(lit, "{}", err),
+        };
+        if handler_methods.contains(&method) {
+            abort!(
+                lit,
+                "HTTP method {} is listed more than once",
+                method.as_str()
+            );
+        }
+        handler_methods.push(method);
+    }
+    if handler_methods.is_empty() {
+        handler_methods.push(match data.method {
+            Some(method) => method,
+            None => {
+                if request_body.is_some() {
+                    HandlerMethod::Post
+                } else {
+                    HandlerMethod::Get
+                }
+            }
+        });
+    }
+    let handler_method = &handler_methods[0];
     let no_auth = data.no_auth;
     let permissions = match no_auth {
         true => Vec::new(),
         false => data.permissions,
     };
+    let deprecated = data.spec.deprecated().enabled();
+    let deprecated_since = match data.spec.deprecated().since_timestamp() {
+        Some(secs) => quote! {
+            Some(::std::time::UNIX_EPOCH + ::std::time::Duration::from_secs(#secs))
+        },
+        None => quote! { None },
+    };
+    for version in data
+        .since
+        .iter()
+        .chain(data.changes.iter().map(|change| &change.version))
+    {
+        if let Err(err) = validate_version(version) {
+            abort!(version, "{}", err);
+        }
+    }
+    let since = match &data.since {
+        Some(version) => quote! { Some(#version) },
+        None => quote! { None },
+    };
+    let changes = &data.changes;
+    let visibility = &data.visibility;
+    let cpu_guard = data.cpu_guard;
+    let no_validate = data.no_validate;
     let handler_spec = data.spec.generate_schema(
         &handler_name,
         &handler_path,
-        &handler_method,
+        handler_method,
         &input,
         &request_body,
     );
 
+    if let Some(param) = data.spec.extra_params().find_duplicate() {
+        abort!(
+            input.sig.ident,
+            "Extra {} parameter {} is declared more than once",
+            param.0,
+            param.1.name
+        );
+    }
+
     let state = detect_state(&input);
     let into_service = match state {
         Some(s) => quote! { super::#fn_ident.with_state(::uxum::state::get::<#s>()) },
@@ -134,6 +207,26 @@ pub fn handler(args: TokenStream, input: TokenStream) -> TokenStream {
                     #handler_method
                 }
 
+                #[inline]
+                #[must_use]
+                fn methods(&self) -> Vec<http::Method> {
+                    vec![#(#handler_methods),*]
+                }
+
+                #[inline]
+                #[must_use]
+                fn module(&self) -> &'static str {
+                    // Strip private metadata module from path.
+                    let path = module_path!();
+                    path.rsplit_once("::").map_or(path, |(parent, _)| parent)
+                }
+
+                #[inline]
+                #[must_use]
+                fn handler_type_id(&self) -> ::std::any::TypeId {
+                    ::std::any::TypeId::of::<Self>()
+                }
+
                 #[inline]
                 #[must_use]
                 fn permissions(&self) -> &'static [&'static str] {
@@ -146,6 +239,55 @@ pub fn handler(args: TokenStream, input: TokenStream) -> TokenStream {
                     #no_auth
                 }
 
+                #[inline]
+                #[must_use]
+                fn deprecated(&self) -> bool {
+                    #deprecated
+                }
+
+                #[inline]
+                #[must_use]
+                fn deprecated_since(&self) -> Option<::std::time::SystemTime> {
+                    #deprecated_since
+                }
+
+                #[inline]
+                #[must_use]
+                fn since(&self) -> Option<&'static str> {
+                    #since
+                }
+
+                #[inline]
+                #[must_use]
+                fn changes(&self) -> &'static [::uxum::ApiChange] {
+                    const CHANGES: &[::uxum::ApiChange] = &[#(#changes),*];
+                    CHANGES
+                }
+
+                #[inline]
+                #[must_use]
+                fn visibility(&self) -> ::uxum::ApiVisibility {
+                    #visibility
+                }
+
+                #[inline]
+                #[must_use]
+                fn normalize_strings(&self) -> Option<::uxum::NormalizeRules> {
+                    #normalize_strings
+                }
+
+                #[inline]
+                #[must_use]
+                fn cpu_guard(&self) -> bool {
+                    #cpu_guard
+                }
+
+                #[inline]
+                #[must_use]
+                fn no_validate(&self) -> bool {
+                    #no_validate
+                }
+
                 #[inline]
                 #[must_use]
                 fn service(&self) -> BoxCloneService<Request<Body>, Response<Body>, Infallible> {
@@ -163,3 +305,45 @@ pub fn handler(args: TokenStream, input: TokenStream) -> TokenStream {
         }
     }.into()
 }
+
+/// Derive macro for declarative normalization of deserialized input.
+///
+/// Field attributes:
+/// * `#[normalize(trim, lowercase, uppercase, none_if_empty)]` sets rules for a field and its
+///   nested values.
+/// * `#[normalize(skip)]` opts field out of normalization, e.g. for passwords.
+///
+/// Fields without attributes use rules set on the struct, or inherited from enclosing value.
+#[proc_macro_error]
+#[proc_macro_derive(Normalize, attributes(normalize))]
+pub fn derive_normalize_impl(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    derive_normalize(&input).into()
+}
+
+/// Derive macro for declarative validation of deserialized input.
+///
+/// Field attributes:
+/// * `#[validate(length(min = 1, max = 64))]` or `#[validate(length(equal = 2))]` checks length
+///   of strings (in characters) or number of items in collections.
+/// * `#[validate(range(min = 0, max = 100))]` checks bounds of numeric values.
+/// * `#[validate(regex(pattern = "^[a-z]+$"))]` checks that string matches a regular expression.
+/// * `#[validate(email)]` checks that string looks like an email address.
+/// * `#[validate(custom = "path::to::func")]` calls a function with a reference to field value,
+///   returning `Result<(), ValidationError>`.
+/// * `#[validate(nested)]` validates nested value, which must implement `Validate` itself.
+///
+/// Struct attributes:
+/// * `#[validate(custom = "path::to::func")]` calls a function with a reference to the whole
+///   struct, returning `Result<(), ValidationErrors>`. Used for cross-field invariants.
+///
+/// Rules on optional fields are only checked if a value is present.
+///
+/// Attribute syntax is compatible with the one recognized by `schemars`, so types also deriving
+/// `JsonSchema` get the same rules in their schemas.
+#[proc_macro_error]
+#[proc_macro_derive(Validate, attributes(validate))]
+pub fn derive_validate_impl(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    derive_validate(&input).into()
+}
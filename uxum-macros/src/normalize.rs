@@ -0,0 +1,240 @@
+//! Derive macro for declarative input normalization.
+
+use convert_case::{Case, Casing};
+use proc_macro2::{Span, TokenStream};
+use proc_macro_error::abort;
+use quote::{quote, ToTokens, TokenStreamExt};
+use syn::{
+    meta::ParseNestedMeta, token::Paren, Attribute, Data, DeriveInput, Expr, Fields, Index, LitStr,
+    Token,
+};
+
+/// Normalization rules declared in attributes.
+#[derive(Debug, Default)]
+pub(crate) struct Rules {
+    /// Remove leading and trailing whitespace.
+    trim: bool,
+    /// Convert to lower case.
+    lowercase: bool,
+    /// Convert to upper case.
+    uppercase: bool,
+    /// Treat empty optional strings as missing.
+    none_if_empty: bool,
+}
+
+impl Rules {
+    /// Add rule by name.
+    fn add(&mut self, name: &str) -> Result<(), String> {
+        match name {
+            "trim" => self.trim = true,
+            "lowercase" => self.lowercase = true,
+            "uppercase" => self.uppercase = true,
+            "none_if_empty" => self.none_if_empty = true,
+            other => return Err(format!("Unknown normalization rule: {other}")),
+        }
+        if self.lowercase && self.uppercase {
+            return Err("Rules lowercase and uppercase are mutually exclusive".into());
+        }
+        Ok(())
+    }
+
+    /// Parse comma-separated list of rule names.
+    pub(crate) fn parse_list(list: &LitStr) -> Self {
+        let mut rules = Self::default();
+        for name in list.value().split(',').map(str::trim) {
+            if name.is_empty() {
+                continue;
+            }
+            if let Err(err) = rules.add(name) {
+                abort!(list, "{}", err);
+            }
+        }
+        rules
+    }
+}
+
+impl ToTokens for Rules {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let mut rules = quote! { ::uxum::NormalizeRules::NONE };
+        if self.trim {
+            rules.append_all(quote! { .trim() });
+        }
+        if self.lowercase {
+            rules.append_all(quote! { .lowercase() });
+        }
+        if self.uppercase {
+            rules.append_all(quote! { .uppercase() });
+        }
+        if self.none_if_empty {
+            rules.append_all(quote! { .none_if_empty() });
+        }
+        tokens.append_all(rules);
+    }
+}
+
+/// Field or container normalization attribute.
+enum Directive {
+    /// Explicit rules.
+    Rules(Rules),
+    /// Opt out of normalization.
+    Skip,
+}
+
+/// Parse `#[normalize(...)]` attributes.
+fn parse_directive(attrs: &[Attribute]) -> Option<Directive> {
+    let mut found = None;
+    for attr in attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("normalize"))
+    {
+        let mut rules = Rules::default();
+        let mut skip = false;
+        let res = attr.parse_nested_meta(|meta| {
+            let Some(ident) = meta.path.get_ident() else {
+                return Err(meta.error("Expected normalization rule name"));
+            };
+            match ident.to_string().as_str() {
+                "skip" => skip = true,
+                name => rules.add(name).map_err(|err| meta.error(err))?,
+            }
+            Ok(())
+        });
+        if let Err(err) = res {
+            abort!(err.span(), "{}", err);
+        }
+        found = Some(match skip {
+            true => Directive::Skip,
+            false => Directive::Rules(rules),
+        });
+    }
+    found
+}
+
+/// Skip value of an irrelevant serde attribute.
+fn skip_meta(meta: &ParseNestedMeta<'_>) -> syn::Result<()> {
+    if meta.input.peek(Paren) {
+        meta.parse_nested_meta(|inner| skip_meta(&inner))
+    } else if meta.input.peek(Token![=]) {
+        meta.value()?.parse::<Expr>().map(|_| ())
+    } else {
+        Ok(())
+    }
+}
+
+/// Get value of serde renaming attribute, preferring deserialization name if split.
+fn rename_value(meta: &ParseNestedMeta<'_>) -> syn::Result<Option<String>> {
+    if !meta.input.peek(Paren) {
+        return Ok(Some(meta.value()?.parse::<LitStr>()?.value()));
+    }
+    let mut value = None;
+    meta.parse_nested_meta(|inner| {
+        if inner.path.is_ident("deserialize") {
+            value = Some(inner.value()?.parse::<LitStr>()?.value());
+            Ok(())
+        } else {
+            skip_meta(&inner)
+        }
+    })?;
+    Ok(value)
+}
+
+/// Find serde attribute value, such as `rename` or `rename_all`.
+pub(crate) fn serde_attr(attrs: &[Attribute], key: &str) -> Option<String> {
+    let mut found = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        // Malformed serde attributes are reported by serde itself.
+        let _ = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(key) {
+                if let Some(value) = rename_value(&meta)? {
+                    found = Some(value);
+                }
+                Ok(())
+            } else {
+                skip_meta(&meta)
+            }
+        });
+    }
+    found
+}
+
+/// Apply serde `rename_all` convention to field name.
+pub(crate) fn rename_field(name: &str, rename_all: Option<&str>) -> String {
+    let case = match rename_all {
+        Some("lowercase") => return name.to_lowercase(),
+        Some("UPPERCASE") => return name.to_uppercase(),
+        Some("PascalCase") => Case::Pascal,
+        Some("camelCase") => Case::Camel,
+        Some("SCREAMING_SNAKE_CASE") => Case::ScreamingSnake,
+        Some("kebab-case") => Case::Kebab,
+        Some("SCREAMING-KEBAB-CASE") => Case::Cobol,
+        _ => return name.to_string(),
+    };
+    name.to_case(case)
+}
+
+/// Generate implementation of `Normalize` trait.
+pub(crate) fn derive_normalize(input: &DeriveInput) -> TokenStream {
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let Data::Struct(data) = &input.data else {
+        abort!(ident, "Normalize can only be derived for structs");
+    };
+    let container = match parse_directive(&input.attrs) {
+        Some(Directive::Rules(rules)) => Some(rules.into_token_stream()),
+        Some(Directive::Skip) => abort!(ident, "skip is only supported on fields"),
+        None => None,
+    };
+    let rename_all = serde_attr(&input.attrs, "rename_all");
+    let mut normalize = Vec::new();
+    let mut describe = Vec::new();
+    let fields: Vec<_> = match &data.fields {
+        Fields::Named(fields) => fields.named.iter().collect(),
+        Fields::Unnamed(fields) => fields.unnamed.iter().collect(),
+        Fields::Unit => Vec::new(),
+    };
+    for (idx, field) in fields.into_iter().enumerate() {
+        let (member, name) = match &field.ident {
+            Some(ident) => (
+                ident.to_token_stream(),
+                serde_attr(&field.attrs, "rename").unwrap_or_else(|| {
+                    rename_field(
+                        ident.to_string().trim_start_matches("r#"),
+                        rename_all.as_deref(),
+                    )
+                }),
+            ),
+            None => (Index::from(idx).to_token_stream(), idx.to_string()),
+        };
+        let rules = match parse_directive(&field.attrs) {
+            Some(Directive::Skip) => continue,
+            Some(Directive::Rules(rules)) => rules.into_token_stream(),
+            None => container.clone().unwrap_or_else(|| quote! { rules }),
+        };
+        let ty = &field.ty;
+        let name = LitStr::new(&name, Span::call_site());
+        normalize.push(quote! {
+            ::uxum::Normalize::normalize(&mut self.#member, #rules);
+        });
+        describe.push(quote! {
+            <#ty as ::uxum::Normalize>::describe(
+                &::uxum::normalize_field_path(path, #name),
+                #rules,
+                spec,
+            );
+        });
+    }
+    quote! {
+        #[automatically_derived]
+        impl #impl_generics ::uxum::Normalize for #ident #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn normalize(&mut self, rules: ::uxum::NormalizeRules) {
+                #(#normalize)*
+            }
+
+            #[allow(unused_variables)]
+            fn describe(path: &str, rules: ::uxum::NormalizeRules, spec: &mut ::uxum::NormalizeSpec) {
+                #(#describe)*
+            }
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Derive macro for declarative input validation.
+
+use proc_macro2::{Span, TokenStream};
+use proc_macro_error::abort;
+use quote::{quote, ToTokens};
+use syn::{
+    meta::ParseNestedMeta, Attribute, Data, DeriveInput, Expr, ExprPath, Fields, Index, LitStr,
+};
+
+use crate::normalize::{rename_field, serde_attr};
+
+/// Validation rules declared in field attributes.
+#[derive(Default)]
+struct FieldRules {
+    /// Length bounds.
+    length: Option<(Option<Expr>, Option<Expr>)>,
+    /// Numeric range bounds.
+    range: Option<(Option<Expr>, Option<Expr>)>,
+    /// Regular expression.
+    regex: Option<LitStr>,
+    /// Email address format.
+    email: bool,
+    /// Custom validation functions.
+    custom: Vec<ExprPath>,
+    /// Validate nested value.
+    nested: bool,
+}
+
+/// Parse `min`, `max` and `equal` bounds of a rule.
+fn parse_bounds(
+    meta: &ParseNestedMeta<'_>,
+    allow_equal: bool,
+) -> syn::Result<(Option<Expr>, Option<Expr>)> {
+    let (mut min, mut max) = (None, None);
+    meta.parse_nested_meta(|inner| {
+        if inner.path.is_ident("min") {
+            min = Some(inner.value()?.parse()?);
+        } else if inner.path.is_ident("max") {
+            max = Some(inner.value()?.parse()?);
+        } else if allow_equal && inner.path.is_ident("equal") {
+            let expr: Expr = inner.value()?.parse()?;
+            (min, max) = (Some(expr.clone()), Some(expr));
+        } else {
+            return Err(inner.error(match allow_equal {
+                true => "Expected min, max or equal",
+                false => "Expected min or max",
+            }));
+        }
+        Ok(())
+    })?;
+    if min.is_none() && max.is_none() {
+        return Err(meta.error("At least one bound is required"));
+    }
+    Ok((min, max))
+}
+
+/// Parse and check regular expression pattern.
+fn parse_pattern(meta: &ParseNestedMeta<'_>) -> syn::Result<LitStr> {
+    let mut found = None;
+    meta.parse_nested_meta(|inner| {
+        if !inner.path.is_ident("pattern") {
+            return Err(inner.error("Expected pattern"));
+        }
+        let pattern: LitStr = inner.value()?.parse()?;
+        if let Err(err) = regex_syntax::Parser::new().parse(&pattern.value()) {
+            return Err(syn::Error::new(
+                pattern.span(),
+                format!("Invalid regular expression: {err}"),
+            ));
+        }
+        found = Some(pattern);
+        Ok(())
+    })?;
+    found.ok_or_else(|| meta.error("Pattern is required"))
+}
+
+/// Parse custom validator function path.
+fn parse_custom(meta: &ParseNestedMeta<'_>) -> syn::Result<ExprPath> {
+    meta.value()?.parse::<LitStr>()?.parse()
+}
+
+/// Parse `#[validate(...)]` field attributes.
+fn parse_field_rules(attrs: &[Attribute]) -> FieldRules {
+    let mut rules = FieldRules::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("validate")) {
+        let res = attr.parse_nested_meta(|meta| {
+            let Some(ident) = meta.path.get_ident() else {
+                return Err(meta.error("Expected validation rule name"));
+            };
+            match ident.to_string().as_str() {
+                "length" => rules.length = Some(parse_bounds(&meta, true)?),
+                "range" => rules.range = Some(parse_bounds(&meta, false)?),
+                "regex" => rules.regex = Some(parse_pattern(&meta)?),
+                "email" => rules.email = true,
+                "custom" => rules.custom.push(parse_custom(&meta)?),
+                "nested" => rules.nested = true,
+                other => return Err(meta.error(format!("Unknown validation rule: {other}"))),
+            }
+            Ok(())
+        });
+        if let Err(err) = res {
+            abort!(err.span(), "{}", err);
+        }
+    }
+    rules
+}
+
+/// Parse `#[validate(custom = "...")]` container attributes.
+fn parse_container_rules(attrs: &[Attribute]) -> Vec<ExprPath> {
+    let mut custom = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("validate")) {
+        let res = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("custom") {
+                custom.push(parse_custom(&meta)?);
+                Ok(())
+            } else {
+                Err(meta.error("Only custom validators are supported on structs"))
+            }
+        });
+        if let Err(err) = res {
+            abort!(err.span(), "{}", err);
+        }
+    }
+    custom
+}
+
+/// Turn optional bound into tokens.
+fn bound(expr: &Option<Expr>, cast: TokenStream) -> TokenStream {
+    match expr {
+        Some(expr) => quote! { Some((#expr) as #cast) },
+        None => quote! { None },
+    }
+}
+
+/// Generate implementation of `Validate` trait.
+pub(crate) fn derive_validate(input: &DeriveInput) -> TokenStream {
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let Data::Struct(data) = &input.data else {
+        abort!(ident, "Validate can only be derived for structs");
+    };
+    let container_custom = parse_container_rules(&input.attrs);
+    let rename_all = serde_attr(&input.attrs, "rename_all");
+    let mut validate = Vec::new();
+    let mut describe = Vec::new();
+    let fields: Vec<_> = match &data.fields {
+        Fields::Named(fields) => fields.named.iter().collect(),
+        Fields::Unnamed(fields) => fields.unnamed.iter().collect(),
+        Fields::Unit => Vec::new(),
+    };
+    for (idx, field) in fields.into_iter().enumerate() {
+        let (member, name) = match &field.ident {
+            Some(ident) => (
+                ident.to_token_stream(),
+                serde_attr(&field.attrs, "rename").unwrap_or_else(|| {
+                    rename_field(
+                        ident.to_string().trim_start_matches("r#"),
+                        rename_all.as_deref(),
+                    )
+                }),
+            ),
+            None => (Index::from(idx).to_token_stream(), idx.to_string()),
+        };
+        let rules = parse_field_rules(&field.attrs);
+        let ty = &field.ty;
+        let name = LitStr::new(&name, Span::call_site());
+        let mut checks = Vec::new();
+        let mut spec_rules = Vec::new();
+        if let Some((min, max)) = &rules.length {
+            let (min, max) = (bound(min, quote! { u64 }), bound(max, quote! { u64 }));
+            checks.push(quote! { ::uxum::check_length(&self.#member, #min, #max) });
+            spec_rules.push(quote! { ::uxum::ValidationRule::Length { min: #min, max: #max } });
+        }
+        if let Some((min, max)) = &rules.range {
+            let (min, max) = (bound(min, quote! { f64 }), bound(max, quote! { f64 }));
+            checks.push(quote! { ::uxum::check_range(&self.#member, #min, #max) });
+            spec_rules.push(quote! { ::uxum::ValidationRule::Range { min: #min, max: #max } });
+        }
+        if let Some(pattern) = &rules.regex {
+            checks.push(quote! { ::uxum::check_pattern(&self.#member, #pattern) });
+            spec_rules.push(quote! { ::uxum::ValidationRule::Pattern(#pattern) });
+        }
+        if rules.email {
+            checks.push(quote! { ::uxum::check_email(&self.#member) });
+            spec_rules.push(quote! { ::uxum::ValidationRule::Email });
+        }
+        for custom in &rules.custom {
+            checks.push(quote! { #custom(&self.#member) });
+        }
+        if checks.is_empty() && !rules.nested {
+            continue;
+        }
+        let nested = rules.nested.then(|| {
+            quote! { ::uxum::Validate::validate_at(&self.#member, &pointer, errors); }
+        });
+        validate.push(quote! {
+            {
+                let pointer = ::uxum::validate_pointer(pointer, #name);
+                #(
+                    if let Err(err) = #checks {
+                        errors.add(&pointer, err);
+                    }
+                )*
+                #nested
+            }
+        });
+        let nested = rules.nested.then(|| {
+            quote! { <#ty as ::uxum::Validate>::describe(&path, spec); }
+        });
+        let spec_rules = (!spec_rules.is_empty()).then(|| {
+            quote! { spec.entry(path.clone()).or_default().extend([#(#spec_rules),*]); }
+        });
+        describe.push(quote! {
+            {
+                let path = ::uxum::normalize_field_path(path, #name);
+                #spec_rules
+                #nested
+            }
+        });
+    }
+    quote! {
+        #[automatically_derived]
+        impl #impl_generics ::uxum::Validate for #ident #ty_generics #where_clause {
+            #[allow(
+                unused_variables,
+                clippy::cast_lossless,
+                clippy::cast_precision_loss,
+                clippy::unnecessary_cast
+            )]
+            fn validate_at(&self, pointer: &str, errors: &mut ::uxum::ValidationErrors) {
+                #(#validate)*
+                #(
+                    if let Err(errs) = #container_custom(self) {
+                        errors.extend_at(pointer, errs);
+                    }
+                )*
+            }
+
+            #[allow(
+                unused_variables,
+                clippy::cast_lossless,
+                clippy::cast_precision_loss,
+                clippy::unnecessary_cast
+            )]
+            fn describe(path: &str, spec: &mut ::uxum::ValidationSpec) {
+                #(#describe)*
+            }
+        }
+    }
+}